@@ -9,6 +9,7 @@
 use crate::heapless::binary_heap::{BinaryHeap, Min};
 use crate::heapless::Vec;
 use crate::util::*;
+use core::cell::Cell;
 use core::cmp::Ordering;
 #[cfg(any(test, feature = "std"))]
 use derivative::Derivative;
@@ -31,6 +32,8 @@ pub struct BlossomTracker<const N: usize> {
     checkpoints: Vec<(CompactTimestamp, CompactWeight), N>,
     /// speed of the blossom
     grow_states: Vec<CompactGrowState, N>,
+    /// whether any dual variable or timestamp has overflowed since the last clear, see [`weight_add`]
+    overflow: Cell<bool>,
 }
 
 #[derive(Debug)]
@@ -63,6 +66,7 @@ impl<const N: usize> BlossomTracker<N> {
             },
             checkpoints: Vec::new(),
             grow_states: Vec::new(),
+            overflow: Cell::new(false),
         }
     }
 
@@ -70,11 +74,25 @@ impl<const N: usize> BlossomTracker<N> {
         self.hit_zero_events.clear();
         self.checkpoints.clear();
         self.grow_states.clear();
+        self.overflow.set(false);
+    }
+
+    pub fn overflowed(&self) -> bool {
+        self.overflow.get()
+    }
+
+    /// run the checked arithmetic with the overflow latch
+    #[inline(always)]
+    fn checked<T>(&self, arithmetic: impl FnOnce(&mut bool) -> T) -> T {
+        let mut overflow = self.overflow.get();
+        let result = arithmetic(&mut overflow);
+        self.overflow.set(overflow);
+        result
     }
 
     #[inline(always)]
     pub fn advance_time(&mut self, delta: CompactTimestamp) {
-        self.timestamp += delta;
+        self.timestamp = self.checked(|overflow| timestamp_add(self.timestamp, delta, overflow));
         debug_assert!(
             {
                 self.remove_outdated_events();
@@ -131,7 +149,9 @@ impl<const N: usize> BlossomTracker<N> {
         if grow_state == CompactGrowState::Shrink {
             self.hit_zero_events
                 .push(HitZeroEvent {
-                    timestamp: self.timestamp + dual_value as CompactTimestamp,
+                    timestamp: self.checked(|overflow| {
+                        timestamp_add(self.timestamp, timestamp_of_weight(dual_value, overflow), overflow)
+                    }),
                     node_index,
                 })
                 .ok()
@@ -141,12 +161,14 @@ impl<const N: usize> BlossomTracker<N> {
 
     fn local_get_dual_variable(&self, local_index: usize) -> CompactWeight {
         let (timestamp, dual_value) = *get!(self.checkpoints, local_index);
-        let delta = (self.timestamp - timestamp) as CompactWeight;
-        let dual_value = match *get!(self.grow_states, local_index) {
-            CompactGrowState::Grow => dual_value + delta,
-            CompactGrowState::Shrink => dual_value - delta,
-            CompactGrowState::Stay => dual_value,
-        };
+        let dual_value = self.checked(|overflow| {
+            let delta = weight_of_timestamp(self.timestamp - timestamp, overflow);
+            match *get!(self.grow_states, local_index) {
+                CompactGrowState::Grow => weight_add(dual_value, delta, overflow),
+                CompactGrowState::Shrink => weight_sub(dual_value, delta, overflow),
+                CompactGrowState::Stay => dual_value,
+            }
+        });
         debug_assert!(dual_value >= 0);
         dual_value
     }
@@ -155,12 +177,20 @@ impl<const N: usize> BlossomTracker<N> {
         self.local_get_dual_variable(self.local_index_of(node_index))
     }
 
+    /// whether the node is a blossom created since the last clear
+    pub fn contains(&self, node_index: CompactNodeIndex) -> bool {
+        !self.checkpoints.is_empty()
+            && node_index.get() >= self.first_index.get()
+            && ((node_index.get() - self.first_index.get()) as usize) < self.checkpoints.len()
+    }
+
     #[inline]
     fn is_valid_event(&self, first_event: &HitZeroEvent) -> bool {
         let local_index = self.local_index_of(first_event.node_index);
         if self.grow_states[local_index] == CompactGrowState::Shrink {
             let dual_value = self.local_get_dual_variable(local_index);
-            let actual_timestamp = self.timestamp + dual_value as CompactTimestamp;
+            let actual_timestamp =
+                self.checked(|overflow| timestamp_add(self.timestamp, timestamp_of_weight(dual_value, overflow), overflow));
             debug_assert!(
                 first_event.timestamp <= actual_timestamp,
                 "the first event should always capture growth"
@@ -187,7 +217,10 @@ impl<const N: usize> BlossomTracker<N> {
         self.remove_outdated_events();
         self.hit_zero_events.peek().map(|event| {
             debug_assert!(event.timestamp >= self.timestamp);
-            ((event.timestamp - self.timestamp) as CompactWeight, event.node_index)
+            (
+                self.checked(|overflow| weight_of_timestamp(event.timestamp - self.timestamp, overflow)),
+                event.node_index,
+            )
         })
     }
 }
@@ -279,5 +312,24 @@ mod tests {
         tracker.advance_time(30);
         tracker.set_speed(node_2, CompactGrowState::Shrink);
         assert_eq!(tracker.get_maximum_growth(), Some((60, node_2)));
+        assert!(!tracker.overflowed());
+    }
+
+    #[cfg(feature = "checked_weight")]
+    #[test]
+    fn blossom_tracker_checked_weight() {
+        // cargo test blossom_tracker_checked_weight --features checked_weight -- --nocapture
+        let mut tracker = BlossomTracker::<10>::new();
+        let node = ni!(0x1100);
+        tracker.create_blossom(node);
+        tracker.advance_time(CompactWeight::MAX as CompactTimestamp);
+        assert_eq!(tracker.get_dual_variable(node), CompactWeight::MAX);
+        assert!(!tracker.overflowed());
+        // the dual variable saturates instead of wrapping around to a negative value
+        tracker.advance_time(1);
+        assert_eq!(tracker.get_dual_variable(node), CompactWeight::MAX);
+        assert!(tracker.overflowed());
+        tracker.clear();
+        assert!(!tracker.overflowed());
     }
 }
@@ -0,0 +1,169 @@
+//! Conflict Queue
+//!
+//! The hardware can only report one (or a few) obstacles per round, while a single round may find many conflicts.
+//! This bounded FIFO sits between the dual and the primal module: it buffers up to `depth` conflicts reported in
+//! the same round, and drops (spills) the rest. Spilled conflicts are never lost because the dual module still holds
+//! them: they will be reported again when the queue runs empty and the hardware is queried again.
+//! Since the primal module may change the dual state when resolving a conflict, the queued conflicts may become
+//! outdated; the driver must validate a conflict before handing it to the primal module (see `pop_valid`).
+//!
+
+use crate::interface::*;
+
+pub struct ConflictQueue<const N: usize> {
+    /// runtime depth of the queue, no larger than `N`
+    depth: usize,
+    buffer: [CompactObstacle; N],
+    head: usize,
+    length: usize,
+    pub statistics: ConflictQueueStatistics,
+}
+
+#[cfg_attr(any(test, feature = "std"), derive(Debug))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Default)]
+pub struct ConflictQueueStatistics {
+    /// the number of conflicts pushed into the queue successfully
+    pub queued: usize,
+    /// the number of conflicts dropped because the queue is full; they will be re-queried later
+    pub spilled: usize,
+    /// the number of conflicts popped but found outdated
+    pub stale: usize,
+    /// the maximum number of conflicts in the queue at the same time
+    pub max_occupancy: usize,
+}
+
+const EMPTY_SLOT: CompactObstacle = CompactObstacle::None;
+
+impl<const N: usize> ConflictQueue<N> {
+    pub const fn new(depth: usize) -> Self {
+        assert!(depth >= 1 && depth <= N);
+        Self {
+            depth,
+            buffer: [EMPTY_SLOT; N],
+            head: 0,
+            length: 0,
+            statistics: ConflictQueueStatistics {
+                queued: 0,
+                spilled: 0,
+                stale: 0,
+                max_occupancy: 0,
+            },
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.length == self.depth
+    }
+
+    /// drop all the pending conflicts, e.g., when the dual module is reset
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.length = 0;
+    }
+
+    pub fn reset_statistics(&mut self) {
+        self.statistics = ConflictQueueStatistics::default();
+    }
+
+    /// push a conflict into the queue, returning false if it's spilled
+    pub fn push(&mut self, obstacle: CompactObstacle) -> bool {
+        debug_assert!(obstacle.is_obstacle(), "only obstacles should be queued");
+        if self.is_full() {
+            self.statistics.spilled += 1;
+            return false;
+        }
+        let index = (self.head + self.length) % self.depth;
+        self.buffer[index] = obstacle;
+        self.length += 1;
+        self.statistics.queued += 1;
+        if self.length > self.statistics.max_occupancy {
+            self.statistics.max_occupancy = self.length;
+        }
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<CompactObstacle> {
+        if self.is_empty() {
+            return None;
+        }
+        let obstacle = core::mem::replace(&mut self.buffer[self.head], CompactObstacle::None);
+        self.head = (self.head + 1) % self.depth;
+        self.length -= 1;
+        Some(obstacle)
+    }
+
+    /// pop the first conflict that is still valid according to `is_valid`, discarding the outdated ones
+    pub fn pop_valid(&mut self, mut is_valid: impl FnMut(&CompactObstacle) -> bool) -> Option<CompactObstacle> {
+        while let Some(obstacle) = self.pop() {
+            if is_valid(&obstacle) {
+                return Some(obstacle);
+            }
+            self.statistics.stale += 1;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn conflict(node: usize) -> CompactObstacle {
+        CompactObstacle::Conflict {
+            node_1: ni!(node).option(),
+            node_2: None.into(),
+            touch_1: ni!(node).option(),
+            touch_2: None.into(),
+            vertex_1: ni!(node),
+            vertex_2: ni!(100),
+        }
+    }
+
+    #[test]
+    fn conflict_queue_basic() {
+        // cargo test conflict_queue_basic -- --nocapture
+        let mut queue = ConflictQueue::<4>::new(2);
+        assert!(queue.push(conflict(0)));
+        assert!(queue.push(conflict(1)));
+        assert!(!queue.push(conflict(2)), "should spill");
+        assert_eq!(queue.pop(), Some(conflict(0)));
+        assert!(queue.push(conflict(3)));
+        assert_eq!(queue.pop(), Some(conflict(1)));
+        assert_eq!(queue.pop(), Some(conflict(3)));
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.statistics.queued, 3);
+        assert_eq!(queue.statistics.spilled, 1);
+        assert_eq!(queue.statistics.max_occupancy, 2);
+    }
+
+    #[test]
+    fn conflict_queue_pop_valid() {
+        // cargo test conflict_queue_pop_valid -- --nocapture
+        let mut queue = ConflictQueue::<4>::new(4);
+        for node in 0..4 {
+            queue.push(conflict(node));
+        }
+        let is_valid = |obstacle: &CompactObstacle| obstacle == &conflict(2) || obstacle == &conflict(3);
+        assert_eq!(queue.pop_valid(is_valid), Some(conflict(2)));
+        assert_eq!(queue.statistics.stale, 2);
+        assert_eq!(queue.pop_valid(is_valid), Some(conflict(3)));
+        assert_eq!(queue.pop_valid(is_valid), None);
+        queue.push(conflict(0));
+        queue.clear();
+        assert!(queue.is_empty());
+    }
+}
@@ -18,12 +18,18 @@ pub trait DualTrackedDriver {
 pub struct DualDriverTracked<D: DualStacklessDriver + DualTrackedDriver, const N: usize> {
     pub driver: D,
     pub blossom_tracker: BlossomTracker<N>,
+    /// the number of conflict queries to the driver since the last reset, each being a round trip to the hardware
+    pub round_trips: usize,
+    /// whether the accumulated growth has overflowed since the last reset, see [`weight_add`]
+    pub weight_overflow: bool,
 }
 
 impl<D: DualStacklessDriver + DualTrackedDriver, const N: usize> DualStacklessDriver for DualDriverTracked<D, N> {
     fn reset(&mut self) {
         self.driver.reset();
         self.blossom_tracker.clear();
+        self.round_trips = 0;
+        self.weight_overflow = false;
     }
 
     fn set_speed(&mut self, is_blossom: bool, node: CompactNodeIndex, speed: CompactGrowState) {
@@ -33,6 +39,25 @@ impl<D: DualStacklessDriver + DualTrackedDriver, const N: usize> DualStacklessDr
         }
     }
 
+    fn set_speed_with_magnitude(
+        &mut self,
+        is_blossom: bool,
+        node: CompactNodeIndex,
+        speed: CompactGrowState,
+        magnitude: CompactSpeed,
+    ) {
+        // the blossom tracker computes the time of hitting zero assuming unit speed
+        assert!(!is_blossom || magnitude == 1, "blossom speed magnitude is not supported");
+        self.driver.set_speed_with_magnitude(is_blossom, node, speed, magnitude);
+        if is_blossom {
+            self.blossom_tracker.set_speed(node, speed);
+        }
+    }
+
+    fn max_speed_magnitude(&self) -> CompactSpeed {
+        self.driver.max_speed_magnitude()
+    }
+
     fn on_blossom_created(&mut self, blossom: CompactNodeIndex) {
         self.blossom_tracker.create_blossom(blossom);
     }
@@ -62,8 +87,12 @@ impl<D: DualStacklessDriver + DualTrackedDriver, const N: usize> DualStacklessDr
                 CompactWeight::MAX
             };
             let (obstacle, local_grown) = self.driver.find_conflict(maximum_growth);
-            self.blossom_tracker.advance_time(local_grown as CompactTimestamp);
-            grown += local_grown;
+            self.round_trips += 1;
+            self.blossom_tracker
+                .advance_time(timestamp_of_weight(local_grown, &mut self.weight_overflow));
+            grown = weight_add(grown, local_grown, &mut self.weight_overflow);
+            // a zero-length growth either reaches the maximum growth of a blossom or propagates through
+            // zero-weight edges; in both cases the next iteration makes progress
             if !obstacle.is_finite_growth() {
                 return (obstacle, grown);
             }
@@ -73,6 +102,21 @@ impl<D: DualStacklessDriver + DualTrackedDriver, const N: usize> DualStacklessDr
     fn add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) {
         self.driver.add_defect(vertex, node);
     }
+
+    fn add_defects_bitmap(&mut self, base_vertex: CompactVertexIndex, base_node: CompactNodeIndex, bitmap: u64) {
+        self.driver.add_defects_bitmap(base_vertex, base_node, bitmap);
+    }
+
+    fn read_node_dual(&mut self, node: CompactNodeIndex) -> Option<CompactWeight> {
+        if self.blossom_tracker.contains(node) {
+            return Some(self.blossom_tracker.get_dual_variable(node));
+        }
+        self.driver.read_node_dual(node)
+    }
+
+    fn read_vertex_grown(&mut self, vertex: CompactVertexIndex) -> Option<CompactWeight> {
+        self.driver.read_vertex_grown(vertex)
+    }
 }
 
 impl<D: DualStacklessDriver + DualTrackedDriver, const N: usize> DualDriverTracked<D, N> {
@@ -80,6 +124,75 @@ impl<D: DualStacklessDriver + DualTrackedDriver, const N: usize> DualDriverTrack
         Self {
             driver,
             blossom_tracker: BlossomTracker::new(),
+            round_trips: 0,
+            weight_overflow: false,
+        }
+    }
+
+    /// whether any weight or growth has overflowed in this shot, which makes the result invalid; only detected with
+    /// the `checked_weight` feature
+    pub fn weight_overflowed(&self) -> bool {
+        self.weight_overflow || self.blossom_tracker.overflowed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a driver without conflicts that grows freely until `remaining` is used up, then reports no obstacle
+    struct FreeGrowthDriver {
+        remaining: CompactWeight,
+    }
+
+    impl DualStacklessDriver for FreeGrowthDriver {
+        fn reset(&mut self) {}
+        fn set_speed(&mut self, _is_blossom: bool, _node: CompactNodeIndex, _speed: CompactGrowState) {}
+        fn set_blossom(&mut self, _node: CompactNodeIndex, _blossom: CompactNodeIndex) {}
+        fn find_obstacle(&mut self) -> (CompactObstacle, CompactWeight) {
+            self.find_conflict(CompactWeight::MAX)
         }
+        fn add_defect(&mut self, _vertex: CompactVertexIndex, _node: CompactNodeIndex) {}
+    }
+
+    impl DualTrackedDriver for FreeGrowthDriver {
+        fn find_conflict(&mut self, maximum_growth: CompactWeight) -> (CompactObstacle, CompactWeight) {
+            if self.remaining > maximum_growth {
+                self.remaining -= maximum_growth;
+                (CompactObstacle::GrowLength { length: 0 }, maximum_growth)
+            } else {
+                let grown = core::mem::replace(&mut self.remaining, 0);
+                (CompactObstacle::None, grown)
+            }
+        }
+    }
+
+    /// the outer blossom hits zero first, and the inner one resumes shrinking only after the outer one is expanded
+    #[test]
+    fn dual_driver_tracked_nested_blossoms() {
+        // cargo test dual_driver_tracked_nested_blossoms -- --nocapture
+        let mut tracked = DualDriverTracked::<_, 10>::new(FreeGrowthDriver { remaining: 20 });
+        let (inner, outer) = (ni!(10), ni!(11));
+        tracked.on_blossom_created(inner);
+        assert_eq!(tracked.find_obstacle(), (CompactObstacle::None, 20));
+        // the outer blossom absorbs the inner one, whose dual variable is frozen
+        tracked.on_blossom_created(outer);
+        tracked.on_blossom_absorbed_into_blossom(inner);
+        tracked.driver.remaining = 30;
+        assert_eq!(tracked.find_obstacle(), (CompactObstacle::None, 30));
+        tracked.set_speed(true, outer, CompactGrowState::Shrink);
+        tracked.driver.remaining = 100;
+        let expected = CompactObstacle::BlossomNeedExpand { blossom: outer };
+        assert_eq!(tracked.find_obstacle(), (expected.clone(), 30));
+        assert_eq!(tracked.blossom_tracker.get_dual_variable(outer), 0);
+        assert_eq!(tracked.blossom_tracker.get_dual_variable(inner), 20);
+        // reported again without growth until the primal module expands it
+        assert_eq!(tracked.find_obstacle(), (expected, 0));
+        tracked.on_blossom_expanded(outer);
+        tracked.set_speed(true, inner, CompactGrowState::Shrink);
+        let expected = CompactObstacle::BlossomNeedExpand { blossom: inner };
+        assert_eq!(tracked.find_obstacle(), (expected, 20));
+        assert_eq!(tracked.driver.remaining, 50);
+        assert!(!tracked.weight_overflowed());
     }
 }
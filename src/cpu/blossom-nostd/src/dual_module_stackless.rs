@@ -9,9 +9,37 @@ use crate::util::*;
 pub trait DualStacklessDriver {
     fn reset(&mut self);
     fn set_speed(&mut self, is_blossom: bool, node: CompactNodeIndex, speed: CompactGrowState);
+    /// set a speed with magnitude other than 1, see [`CompactSpeed`]; drivers that only support unit speed
+    /// reject any magnitude other than 1, the caller should respect [`Self::max_speed_magnitude`]
+    fn set_speed_with_magnitude(
+        &mut self,
+        is_blossom: bool,
+        node: CompactNodeIndex,
+        speed: CompactGrowState,
+        magnitude: CompactSpeed,
+    ) {
+        assert!(magnitude == 1, "the driver only supports unit speed");
+        self.set_speed(is_blossom, node, speed);
+    }
+    /// the largest speed magnitude supported by the driver, see [`Self::set_speed_with_magnitude`]
+    fn max_speed_magnitude(&self) -> CompactSpeed {
+        1
+    }
     fn set_blossom(&mut self, node: CompactNodeIndex, blossom: CompactNodeIndex);
     fn find_obstacle(&mut self) -> (CompactObstacle, CompactWeight);
     fn add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex);
+    /// load multiple defects in one transaction; drivers without native support fall back to `add_defect`
+    fn add_defects_bitmap(&mut self, base_vertex: CompactVertexIndex, base_node: CompactNodeIndex, bitmap: u64) {
+        iterate_defects_bitmap(base_vertex, base_node, bitmap, |vertex, node| self.add_defect(vertex, node));
+    }
+    /// read back the dual variable of a node from the dual module, see [`DualInterface::read_node_dual`];
+    /// `None` if the driver does not support reading back dual variables
+    fn read_node_dual(&mut self, _node: CompactNodeIndex) -> Option<CompactWeight> {
+        None
+    }
+    fn read_vertex_grown(&mut self, _vertex: CompactVertexIndex) -> Option<CompactWeight> {
+        None
+    }
     /// just to inform a blossom has been created; no need to do anything
     fn on_blossom_created(&mut self, _blossom: CompactNodeIndex) {}
     fn on_blossom_expanded(&mut self, _blossom: CompactNodeIndex) {}
@@ -67,6 +95,28 @@ impl<D: DualStacklessDriver> DualInterface for DualModuleStackless<D> {
         self.driver.set_speed(is_blossom, node_index, grow_state);
     }
 
+    fn set_speed_with_magnitude(
+        &mut self,
+        is_blossom: bool,
+        node_index: CompactNodeIndex,
+        grow_state: CompactGrowState,
+        magnitude: CompactSpeed,
+    ) {
+        #[cfg(any(test, feature = "std"))]
+        if option_env!("PRINT_DUAL_CALLS").is_some() {
+            println!(
+                "set_speed_with_magnitude({node_index}({}), {grow_state:?}, {magnitude})",
+                if is_blossom { "blossom" } else { "defect" }
+            );
+        }
+        self.driver
+            .set_speed_with_magnitude(is_blossom, node_index, grow_state, magnitude);
+    }
+
+    fn max_speed_magnitude(&self) -> CompactSpeed {
+        self.driver.max_speed_magnitude()
+    }
+
     fn find_obstacle(&mut self) -> (CompactObstacle, CompactWeight) {
         self.driver.find_obstacle()
     }
@@ -74,6 +124,30 @@ impl<D: DualStacklessDriver> DualInterface for DualModuleStackless<D> {
     fn add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) {
         self.driver.add_defect(vertex, node);
     }
+
+    fn add_defects_bitmap(&mut self, base_vertex: CompactVertexIndex, base_node: CompactNodeIndex, bitmap: u64) {
+        #[cfg(any(test, feature = "std"))]
+        if option_env!("PRINT_DUAL_CALLS").is_some() {
+            println!("add_defects_bitmap({base_vertex}, {base_node}, {bitmap:#018X})");
+        }
+        self.driver.add_defects_bitmap(base_vertex, base_node, bitmap);
+    }
+
+    fn read_node_dual(&mut self, node: CompactNodeIndex) -> Option<CompactWeight> {
+        #[cfg(any(test, feature = "std"))]
+        if option_env!("PRINT_DUAL_CALLS").is_some() {
+            println!("read_node_dual({node})");
+        }
+        self.driver.read_node_dual(node)
+    }
+
+    fn read_vertex_grown(&mut self, vertex: CompactVertexIndex) -> Option<CompactWeight> {
+        #[cfg(any(test, feature = "std"))]
+        if option_env!("PRINT_DUAL_CALLS").is_some() {
+            println!("read_vertex_grown({vertex})");
+        }
+        self.driver.read_vertex_grown(vertex)
+    }
 }
 
 impl<D: DualStacklessDriver> DualModuleStackless<D> {
@@ -100,6 +174,7 @@ impl<D: DualStacklessDriver> DualModuleStackless<D> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::primal_module_embedded::*;
     use std::collections::{BTreeMap, VecDeque};
 
     #[test]
@@ -137,6 +212,62 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn dual_module_stackless_defects_bitmap() {
+        // cargo test dual_module_stackless_defects_bitmap -- --nocapture
+        let mut dual = DualModuleStackless::new(MockDualDriver::new());
+        dual.add_defects_bitmap(ni!(10), ni!(3), 0b1001 | (1 << 63));
+        dual.driver
+            .check(&["add_defect(10, 3)", "add_defect(13, 4)", "add_defect(73, 5)"]);
+        dual.add_defects_bitmap(ni!(0), ni!(0), 0);
+        dual.driver.check(&[]);
+    }
+
+    /// the lone defects grow faster until the primal module encounters them
+    #[test]
+    fn dual_module_stackless_lone_defect_magnitude() {
+        // cargo test dual_module_stackless_lone_defect_magnitude -- --nocapture
+        let mut primal: PrimalModuleEmbedded<100> = PrimalModuleEmbedded::new();
+        primal.nodes.blossom_begin = 50;
+        primal.lone_defect_magnitude = 2;
+        let mut dual = DualModuleStackless::new(MockDualDriver::new());
+        for node in [0, 1, 2] {
+            dual.add_defect(ni!(node), ni!(node));
+            primal.speed_up_lone_defect(&mut dual, ni!(node));
+        }
+        dual.driver.check(&[
+            "add_defect(0, 0)",
+            "set_speed_with_magnitude(false, 0, Grow, 2)",
+            "add_defect(1, 1)",
+            "set_speed_with_magnitude(false, 1, Grow, 2)",
+            "add_defect(2, 2)",
+            "set_speed_with_magnitude(false, 2, Grow, 2)",
+        ]);
+        let conflict = |node_1: usize, node_2: usize| CompactObstacle::Conflict {
+            node_1: ni!(node_1).option(),
+            node_2: ni!(node_2).option(),
+            touch_1: ni!(node_1).option(),
+            touch_2: ni!(node_2).option(),
+            vertex_1: ni!(node_1),
+            vertex_2: ni!(node_2),
+        };
+        primal.resolve(&mut dual, conflict(0, 1));
+        dual.driver.check(&[
+            "set_speed(false, 0, Grow)",
+            "set_speed(false, 1, Grow)",
+            "set_speed(false, 0, Stay)",
+            "set_speed(false, 1, Stay)",
+        ]);
+        // the encountered nodes are not slowed down again
+        primal.resolve(&mut dual, conflict(2, 1));
+        dual.driver.check(&[
+            "set_speed(false, 2, Grow)",
+            "set_speed(false, 2, Grow)",
+            "set_speed(false, 1, Shrink)",
+            "set_speed(false, 0, Grow)",
+        ]);
+    }
+
     pub struct MockPrimal {
         pub nodes: BTreeMap<CompactNodeIndex, MockPrimalNode>,
     }
@@ -239,6 +370,20 @@ mod tests {
         fn set_speed(&mut self, is_blossom: bool, node: CompactNodeIndex, speed: CompactGrowState) {
             self.log(format!("set_speed({is_blossom}, {node}, {speed:?})"));
         }
+        fn set_speed_with_magnitude(
+            &mut self,
+            is_blossom: bool,
+            node: CompactNodeIndex,
+            speed: CompactGrowState,
+            magnitude: CompactSpeed,
+        ) {
+            self.log(format!(
+                "set_speed_with_magnitude({is_blossom}, {node}, {speed:?}, {magnitude})"
+            ));
+        }
+        fn max_speed_magnitude(&self) -> CompactSpeed {
+            MAX_SPEED_MAGNITUDE
+        }
         fn set_blossom(&mut self, node: CompactNodeIndex, blossom: CompactNodeIndex) {
             self.log(format!("set_blossom({node}, {blossom})"));
         }
@@ -27,32 +27,79 @@ pub const EXTENDED_OP_CODE_LOAD_WEIGHTS_EXTERNAL: u32 = 0b011 << 3;
 pub const EXTENDED_OP_CODE_RESET: u32 = 0b100 << 3;
 pub const EXTENDED_OP_CODE_LOAD_DEFECTS_EXTERNAL: u32 = 0b101 << 3;
 pub const EXTENDED_OP_CODE_GROW: u32 = 0b110 << 3;
+pub const EXTENDED_OP_CODE_READ_DUAL: u32 = 0b111 << 3;
+
+/// the read dual instruction reads the grown value of a vertex; the flag must always be set because the dual module
+/// does not store the dual variables of the nodes, see [`crate::interface::DualInterface::read_node_dual`]
+pub const READ_DUAL_VERTEX_FLAG: u32 = 1 << 6;
+/// LoadDefectsBitmap shares the extended op code of ReadDual with the vertex flag cleared: the header carries the
+/// base node in bits [17, 32) and the base vertex divided by 64 in bits [7, 16), and is followed by two data words
+/// with the low and high 32 bits of the bitmap, see [`Instruction32::load_defects_bitmap`]
+pub const BITMAP_BLOCK_SHIFT: u32 = 7;
+pub const BITMAP_BLOCK_BITS: u32 = 9;
+pub const BITMAP_BLOCK_MASK: u32 = ((1 << BITMAP_BLOCK_BITS) - 1) << BITMAP_BLOCK_SHIFT;
+
+/// the speed magnitude of SetSpeed is stored in bits [12, 15); 0 is decoded as 1 for compatibility
+pub const SPEED_MAGNITUDE_SHIFT: u32 = 12;
+pub const SPEED_MAGNITUDE_MASK: u32 = 0b111 << SPEED_MAGNITUDE_SHIFT;
+
+/// the width of the vertex or node index fields, i.e., bits [17, 32) and [2, 17), regardless of the index type
+pub const INDEX_FIELD_BITS: u32 = 15;
+pub const INDEX_FIELD_MASK: u32 = (1 << INDEX_FIELD_BITS) - 1;
+/// the growth length of the Grow instruction is stored in bits [6, 32)
+pub const LENGTH_FIELD_SHIFT: u32 = 6;
+pub const LENGTH_FIELD_BITS: u32 = 32 - LENGTH_FIELD_SHIFT;
+
+fn index_field(index: CompactVertexIndex) -> u32 {
+    debug_assert!(
+        (index.get() as u32) <= INDEX_FIELD_MASK,
+        "index {} exceeds the {}-bit field",
+        index.get(),
+        INDEX_FIELD_BITS
+    );
+    index.get() as u32
+}
 
 impl Instruction32 {
     pub fn set_speed(node: CompactNodeIndex, speed: CompactGrowState) -> Self {
-        let field_node = (node.get() as u32) << 17;
+        let field_node = index_field(node) << 17;
         let field_speed = (speed as u32) << 15;
         Self(field_node | field_speed | OP_CODE_SET_SPEED)
     }
+    pub fn set_speed_with_magnitude(node: CompactNodeIndex, speed: CompactGrowState, magnitude: CompactSpeed) -> Self {
+        debug_assert!(
+            (1..=MAX_SPEED_MAGNITUDE).contains(&magnitude),
+            "speed magnitude {} out of range",
+            magnitude
+        );
+        let field_magnitude = (magnitude as u32) << SPEED_MAGNITUDE_SHIFT;
+        Self(Self::set_speed(node, speed).0 | field_magnitude)
+    }
     pub fn set_blossom(node: CompactNodeIndex, blossom: CompactNodeIndex) -> Self {
-        let field_node = (node.get() as u32) << 17;
-        let field_blossom = (blossom.get() as u32) << 2;
+        let field_node = index_field(node) << 17;
+        let field_blossom = index_field(blossom) << 2;
         Self(field_node | field_blossom | OP_CODE_SET_BLOSSOM)
     }
     pub fn grow(length: CompactWeight) -> Self {
-        let field_length = (length as u32) << 6;
+        debug_assert!(
+            length >= 0 && (length as u64) < (1 << LENGTH_FIELD_BITS),
+            "growth length {} does not fit the {}-bit field",
+            length,
+            LENGTH_FIELD_BITS
+        );
+        let field_length = (length as u32) << LENGTH_FIELD_SHIFT;
         Self(field_length | EXTENDED_OP_CODE_ENABLE | EXTENDED_OP_CODE_GROW)
     }
     pub fn reset() -> Self {
         Self(EXTENDED_OP_CODE_ENABLE | EXTENDED_OP_CODE_RESET)
     }
     pub fn add_defect_vertex(vertex: CompactVertexIndex, node: CompactNodeIndex) -> Self {
-        let field_vertex = (vertex.get() as u32) << 17;
-        let field_node = (node.get() as u32) << 2;
+        let field_vertex = index_field(vertex) << 17;
+        let field_node = index_field(node) << 2;
         Self(field_vertex | field_node | OP_CODE_ADD_DEFECT_VERTEX)
     }
     pub fn load_syndrome_external(time: CompactNodeIndex) -> Self {
-        let field_time = (time.get() as u32) << 17;
+        let field_time = index_field(time) << 17;
         Self(field_time | EXTENDED_OP_CODE_ENABLE | EXTENDED_OP_CODE_LOAD_DEFECTS_EXTERNAL)
     }
     pub fn load_weights_external() -> Self {
@@ -61,6 +108,23 @@ impl Instruction32 {
     pub fn find_obstacle() -> Self {
         Self(EXTENDED_OP_CODE_ENABLE | EXTENDED_OP_CODE_FIND_OBSTACLE)
     }
+    pub fn read_vertex_grown(vertex: CompactVertexIndex) -> Self {
+        let field_vertex = index_field(vertex) << 17;
+        Self(field_vertex | READ_DUAL_VERTEX_FLAG | EXTENDED_OP_CODE_ENABLE | EXTENDED_OP_CODE_READ_DUAL)
+    }
+    /// the header and the two data words of loading up to 64 defects, see [`crate::interface::iterate_defects_bitmap`];
+    /// the base vertex must be aligned to 64, see [`crate::interface::align_defects_bitmap`]
+    pub fn load_defects_bitmap(base_vertex: CompactVertexIndex, base_node: CompactNodeIndex, bitmap: u64) -> [Self; 3] {
+        debug_assert!(
+            base_vertex.get() % 64 == 0,
+            "base vertex {} is not aligned to 64",
+            base_vertex.get()
+        );
+        let field_node = index_field(base_node) << 17;
+        let field_block = (index_field(base_vertex) / 64) << BITMAP_BLOCK_SHIFT;
+        let header = field_node | field_block | EXTENDED_OP_CODE_ENABLE | EXTENDED_OP_CODE_READ_DUAL;
+        [Self(header), Self(bitmap as u32), Self((bitmap >> 32) as u32)]
+    }
 
     pub fn is_extended(self) -> bool {
         self.op_code() == OP_CODE_SET_SPEED && (self.0 & EXTENDED_OP_CODE_ENABLE) != 0
@@ -84,13 +148,44 @@ impl Instruction32 {
     pub fn is_grow(self) -> bool {
         self.is_extended() && self.extended_op_code() == EXTENDED_OP_CODE_GROW
     }
+    pub fn is_read_dual(self) -> bool {
+        self.is_extended() && self.extended_op_code() == EXTENDED_OP_CODE_READ_DUAL
+    }
+    pub fn is_read_vertex(self) -> bool {
+        self.is_read_dual() && (self.0 & READ_DUAL_VERTEX_FLAG) != 0
+    }
+    /// the header of LoadDefectsBitmap; the two data words that follow are not instructions
+    pub fn is_load_defects_bitmap(self) -> bool {
+        self.is_read_dual() && (self.0 & READ_DUAL_VERTEX_FLAG) == 0
+    }
 
     pub fn field1(self) -> u32 {
-        (self.0 >> 17) & ((1 << 15) - 1)
+        (self.0 >> 17) & INDEX_FIELD_MASK
+    }
+    pub fn field2(self) -> u32 {
+        (self.0 >> 2) & INDEX_FIELD_MASK
+    }
+    /// the growth length of the Grow instruction
+    pub fn get_length(self) -> CompactWeight {
+        (self.0 >> LENGTH_FIELD_SHIFT) as CompactWeight
+    }
+    /// the base vertex of the LoadDefectsBitmap header
+    pub fn get_bitmap_base_vertex(self) -> u32 {
+        ((self.0 & BITMAP_BLOCK_MASK) >> BITMAP_BLOCK_SHIFT) * 64
     }
     pub fn get_speed(self) -> CompactGrowState {
         FromPrimitive::from_u32((self.0 >> 15) & ((1 << 2) - 1)).unwrap()
     }
+    pub fn get_speed_magnitude(self) -> CompactSpeed {
+        match (self.0 & SPEED_MAGNITUDE_MASK) >> SPEED_MAGNITUDE_SHIFT {
+            0 => 1,
+            magnitude => magnitude as CompactSpeed,
+        }
+    }
+    /// the signed speed, i.e., the grow state multiplied by the magnitude
+    pub fn get_signed_speed(self) -> CompactSpeed {
+        self.get_speed().with_magnitude(self.get_speed_magnitude())
+    }
 
     #[cfg(any(test, feature = "std"))]
     pub fn string_detailed(self) -> String {
@@ -112,9 +207,18 @@ impl Into<u32> for Instruction32 {
 impl std::fmt::Debug for Instruction32 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.is_set_speed() {
-            f.debug_struct("SetSpeed")
-                .field("node", &self.field1())
-                .field("speed", &self.get_speed())
+            let mut debug_struct = f.debug_struct("SetSpeed");
+            debug_struct.field("node", &self.field1()).field("speed", &self.get_speed());
+            if self.get_speed_magnitude() != 1 {
+                debug_struct.field("magnitude", &self.get_speed_magnitude());
+            }
+            debug_struct.finish()
+        } else if self.is_read_vertex() {
+            f.debug_struct("ReadVertexGrown").field("vertex", &self.field1()).finish()
+        } else if self.is_load_defects_bitmap() {
+            f.debug_struct("LoadDefectsBitmap")
+                .field("base_vertex", &self.get_bitmap_base_vertex())
+                .field("base_node", &self.field1())
                 .finish()
         } else {
             unimplemented!("instruction {:#08X} = {:#032b}", self.0, self.0)
@@ -145,4 +249,178 @@ mod tests {
             "SetSpeed { node: 32766, speed: Stay }"
         );
     }
+
+    #[test]
+    fn instruction32_set_speed_magnitude() {
+        // cargo test instruction32_set_speed_magnitude -- --nocapture
+        let instruction = Instruction32::set_speed_with_magnitude(ni!(3), CompactGrowState::Shrink, 2);
+        instruction.print_detailed();
+        assert!(instruction.is_set_speed());
+        assert_eq!(instruction.field1(), 3);
+        assert_eq!(instruction.get_speed(), CompactGrowState::Shrink);
+        assert_eq!(instruction.get_speed_magnitude(), 2);
+        assert_eq!(instruction.get_signed_speed(), -2);
+        assert_eq!(
+            format!("{:?}", instruction),
+            "SetSpeed { node: 3, speed: Shrink, magnitude: 2 }"
+        );
+        // the original encoding is decoded as unit speed
+        let instruction = Instruction32::set_speed(ni!(3), CompactGrowState::Grow);
+        assert_eq!(instruction.get_speed_magnitude(), 1);
+        assert_eq!(instruction.get_signed_speed(), 1);
+        assert_eq!(
+            Instruction32::set_speed_with_magnitude(ni!(3), CompactGrowState::Grow, 1).get_signed_speed(),
+            1
+        );
+        assert_eq!(CompactGrowState::Stay.with_magnitude(MAX_SPEED_MAGNITUDE), 0);
+    }
+
+    #[test]
+    fn instruction32_read_dual() {
+        // cargo test instruction32_read_dual -- --nocapture
+        let instruction = Instruction32::read_vertex_grown(ni!(7));
+        instruction.print_detailed();
+        assert!(instruction.is_read_dual() && instruction.is_read_vertex());
+        assert!(!instruction.is_set_speed() && !instruction.is_grow());
+        assert_eq!(instruction.field1(), 7);
+        assert_eq!(format!("{:?}", instruction), "ReadVertexGrown { vertex: 7 }");
+    }
+
+    #[test]
+    fn instruction32_load_defects_bitmap() {
+        // cargo test instruction32_load_defects_bitmap -- --nocapture
+        let bitmap = 0x8000_0001_0000_0003;
+        let [header, low, high] = Instruction32::load_defects_bitmap(ni!(128), ni!(5), bitmap);
+        header.print_detailed();
+        assert!(header.is_load_defects_bitmap() && !header.is_read_vertex());
+        assert!(!Instruction32::read_vertex_grown(ni!(128)).is_load_defects_bitmap());
+        assert_eq!((header.get_bitmap_base_vertex(), header.field1()), (128, 5));
+        assert_eq!(((high.0 as u64) << 32) | low.0 as u64, bitmap);
+        assert_eq!(
+            format!("{:?}", header),
+            "LoadDefectsBitmap { base_vertex: 128, base_node: 5 }"
+        );
+    }
+
+    /// a deterministic xorshift generator, so that a failing case is reproducible from the printed seed
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+        fn below(&mut self, bound: u64) -> u64 {
+            self.next() % bound
+        }
+    }
+
+    /// the largest index that fits both the index type and the instruction field
+    fn max_index() -> u64 {
+        core::cmp::min(INDEX_FIELD_MASK as u64, CompactNodeNum::MAX as u64 - 1)
+    }
+
+    /// the largest growth length that fits both the weight type and the instruction field
+    fn max_length() -> u64 {
+        core::cmp::min((1u64 << LENGTH_FIELD_BITS) - 1, CompactWeight::MAX as u64)
+    }
+
+    /// the boundary values of a field, followed by random values
+    fn field_values(rng: &mut XorShift, max: u64, count: usize) -> std::vec::Vec<u64> {
+        let mut values = vec![0, 1, max / 2, max - 1, max];
+        values.extend((0..count).map(|_| rng.below(max + 1)));
+        values
+    }
+
+    const SPEEDS: [CompactGrowState; 3] = [CompactGrowState::Stay, CompactGrowState::Grow, CompactGrowState::Shrink];
+
+    #[test]
+    fn instruction32_round_trip_index_fields() {
+        // cargo test instruction32_round_trip_index_fields -- --nocapture
+        let mut rng = XorShift(0x2545F4914F6CDD1D);
+        let max = max_index();
+        println!("max index: {}", max);
+        let values = field_values(&mut rng, max, 1000);
+        for (i, &value) in values.iter().enumerate() {
+            let other = values[values.len() - 1 - i];
+            let speed = SPEEDS[i % SPEEDS.len()];
+            let instruction = Instruction32::set_speed(ni!(value), speed);
+            assert!(instruction.is_set_speed() && !instruction.is_extended());
+            assert_eq!((instruction.field1() as u64, instruction.get_speed()), (value, speed));
+            assert_eq!(instruction.get_speed_magnitude(), 1);
+            let magnitude = (i % MAX_SPEED_MAGNITUDE as usize) as CompactSpeed + 1;
+            let instruction = Instruction32::set_speed_with_magnitude(ni!(value), speed, magnitude);
+            assert!(instruction.is_set_speed());
+            assert_eq!((instruction.field1() as u64, instruction.get_speed()), (value, speed));
+            assert_eq!(instruction.get_speed_magnitude(), magnitude);
+            let instruction = Instruction32::set_blossom(ni!(value), ni!(other));
+            assert!(instruction.is_set_blossom() && !instruction.is_set_speed());
+            assert_eq!((instruction.field1() as u64, instruction.field2() as u64), (value, other));
+            let instruction = Instruction32::add_defect_vertex(ni!(value), ni!(other));
+            assert_eq!(instruction.op_code(), OP_CODE_ADD_DEFECT_VERTEX);
+            assert!(!instruction.is_set_blossom() && !instruction.is_match() && !instruction.is_extended());
+            assert_eq!((instruction.field1() as u64, instruction.field2() as u64), (value, other));
+            let instruction = Instruction32::load_syndrome_external(ni!(value));
+            assert!(instruction.is_extended());
+            assert_eq!(instruction.extended_op_code(), EXTENDED_OP_CODE_LOAD_DEFECTS_EXTERNAL);
+            assert_eq!(instruction.field1() as u64, value);
+            let instruction = Instruction32::read_vertex_grown(ni!(value));
+            assert!(instruction.is_read_vertex());
+            assert_eq!(instruction.field1() as u64, value);
+        }
+    }
+
+    #[test]
+    fn instruction32_round_trip_length_field() {
+        // cargo test instruction32_round_trip_length_field -- --nocapture
+        let mut rng = XorShift(0x9E3779B97F4A7C15);
+        let max = max_length();
+        println!("max length: {}", max);
+        for value in field_values(&mut rng, max, 1000) {
+            let instruction = Instruction32::grow(value as CompactWeight);
+            assert!(instruction.is_grow() && !instruction.is_set_speed() && !instruction.is_read_dual());
+            assert_eq!(instruction.get_length() as u64, value);
+        }
+    }
+
+    /// the op codes are mutually exclusive for any field values, including all-ones fields
+    #[test]
+    fn instruction32_op_code_exclusive() {
+        // cargo test instruction32_op_code_exclusive -- --nocapture
+        let (index, length) = (ni!(max_index()), max_length() as CompactWeight);
+        let instructions = [
+            Instruction32::set_speed_with_magnitude(index, CompactGrowState::Shrink, MAX_SPEED_MAGNITUDE),
+            Instruction32::set_blossom(index, index),
+            Instruction32::add_defect_vertex(index, index),
+            Instruction32::grow(length),
+            Instruction32::reset(),
+            Instruction32::load_syndrome_external(index),
+            Instruction32::load_weights_external(),
+            Instruction32::find_obstacle(),
+            Instruction32::read_vertex_grown(index),
+        ];
+        let kind = |instruction: Instruction32| {
+            if instruction.is_extended() {
+                4 + (instruction.extended_op_code() >> 3)
+            } else {
+                instruction.op_code()
+            }
+        };
+        for (i, &a) in instructions.iter().enumerate() {
+            for &b in instructions.iter().skip(i + 1) {
+                assert_ne!(kind(a), kind(b), "{:#034b} and {:#034b} decode to the same op code", a.0, b.0);
+            }
+        }
+    }
+
+    /// an index wider than the field must not silently alias a smaller index
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn instruction32_index_overflow() {
+        // cargo test instruction32_index_overflow -- --nocapture
+        Instruction32::set_blossom(ni!(1), ni!(INDEX_FIELD_MASK + 1));
+    }
 }
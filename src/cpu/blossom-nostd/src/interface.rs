@@ -17,7 +17,9 @@ pub enum CompactObstacle {
     None,
     /// a non-negative growth
     GrowLength {
-        /// when length is 0, one just need to call grow(0) for erasure errors to propagate
+        /// when length is 0, some nodes are propagating through zero-weight edges (e.g., from erasures or merged
+        /// edges) without growing; one just needs to find obstacle again, which propagates at least one more vertex.
+        /// the dual driver handles this internally and it is never passed to the primal module
         length: CompactWeight,
     },
     /// some conflict needs the primal module to resolve
@@ -36,27 +38,35 @@ pub enum CompactObstacle {
 }
 
 impl CompactObstacle {
+    /// merge two responses like a node of the arbitration tree in the hardware, with a deterministic priority:
+    /// Conflict > Conflict with a virtual vertex > BlossomNeedExpand > GrowLength (the minimum) > None, and ties are
+    /// broken by the lowest index; the result is thus independent of the order in which the responses are merged
     pub fn reduce(resp1: CompactObstacle, resp2: CompactObstacle) -> CompactObstacle {
-        if matches!(resp1, CompactObstacle::None) {
-            return resp2;
+        if resp2.arbitration_key() < resp1.arbitration_key() {
+            resp2
+        } else {
+            resp1
         }
-        if matches!(resp2, CompactObstacle::None) {
-            return resp1;
-        }
-        if !matches!(resp1, CompactObstacle::GrowLength { .. }) {
-            return resp1;
-        }
-        if !matches!(resp2, CompactObstacle::GrowLength { .. }) {
-            return resp2;
-        }
-        let CompactObstacle::GrowLength { length: length1 } = resp1 else {
-            unreachable!()
-        };
-        let CompactObstacle::GrowLength { length: length2 } = resp2 else {
-            unreachable!()
-        };
-        CompactObstacle::GrowLength {
-            length: core::cmp::min(length1, length2),
+    }
+
+    /// the arbitration order of [`Self::reduce`]: the smaller key wins; the conflict convergecast tree of the hardware
+    /// compares the same key at every tree node, see `ConvergecastConflict.arbitrationKey` in the Scala code
+    pub fn arbitration_key(&self) -> (u8, i64, i64) {
+        match self {
+            Self::Conflict {
+                node_1,
+                node_2,
+                vertex_1,
+                vertex_2,
+                ..
+            } => {
+                let rank = if node_1.is_some() && node_2.is_some() { 0 } else { 1 };
+                let (vertex_1, vertex_2) = (vertex_1.get() as i64, vertex_2.get() as i64);
+                (rank, core::cmp::min(vertex_1, vertex_2), core::cmp::max(vertex_1, vertex_2))
+            }
+            Self::BlossomNeedExpand { blossom } => (2, blossom.get() as i64, 0),
+            Self::GrowLength { length } => (3, *length as i64, 0),
+            Self::None => (4, 0, 0),
         }
     }
 
@@ -122,11 +132,86 @@ pub trait DualInterface {
     /// set the speed of a node
     fn set_speed(&mut self, is_blossom: bool, node_index: CompactNodeIndex, grow_state: CompactGrowState);
 
+    /// set the speed of a node with a magnitude other than 1, see [`CompactSpeed`]; the magnitude must not exceed
+    /// [`Self::max_speed_magnitude`]
+    fn set_speed_with_magnitude(
+        &mut self,
+        is_blossom: bool,
+        node_index: CompactNodeIndex,
+        grow_state: CompactGrowState,
+        magnitude: CompactSpeed,
+    ) {
+        assert!(magnitude == 1, "the dual module only supports unit speed");
+        self.set_speed(is_blossom, node_index, grow_state);
+    }
+
+    /// the largest speed magnitude supported by the dual module
+    fn max_speed_magnitude(&self) -> CompactSpeed {
+        1
+    }
+
     /// find an obstacle and return the amount of growth from last return
     fn find_obstacle(&mut self) -> (CompactObstacle, CompactWeight);
 
     /// add a defect at given vertex
     fn add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex);
+
+    /// add up to 64 defects in `[base_vertex, base_vertex + 64)` at once, see [`iterate_defects_bitmap`]
+    fn add_defects_bitmap(&mut self, base_vertex: CompactVertexIndex, base_node: CompactNodeIndex, bitmap: u64) {
+        iterate_defects_bitmap(base_vertex, base_node, bitmap, |vertex, node| self.add_defect(vertex, node));
+    }
+
+    /// read back the dual variable of a blossom, which is only tracked in software by [`crate::blossom_tracker`];
+    /// the dual module does not store the dual variable of any node but only the grown value of every vertex, so the
+    /// dual variable of a defect node is derived from [`Self::read_vertex_grown`] by the caller who knows the blossom
+    /// structure; `None` if not available
+    fn read_node_dual(&mut self, _node: CompactNodeIndex) -> Option<CompactWeight> {
+        None
+    }
+
+    /// read back the grown value of a vertex, i.e., the sum of the dual variables of all nodes covering it
+    fn read_vertex_grown(&mut self, _vertex: CompactVertexIndex) -> Option<CompactWeight> {
+        None
+    }
+}
+
+/// iterate the defects encoded in a bitmap: bit `i` set means vertex `base_vertex + i` is a defect;
+/// the defects are assigned consecutive node indices starting from `base_node`, in increasing vertex order
+#[inline]
+pub fn iterate_defects_bitmap(
+    base_vertex: CompactVertexIndex,
+    base_node: CompactNodeIndex,
+    mut bitmap: u64,
+    mut func: impl FnMut(CompactVertexIndex, CompactNodeIndex),
+) {
+    let mut node = base_node.get();
+    while bitmap != 0 {
+        let offset = bitmap.trailing_zeros();
+        func(ni!(base_vertex.get() + offset as CompactVertexNum), ni!(node));
+        node += 1;
+        bitmap &= bitmap - 1; // clear the lowest set bit
+    }
+}
+
+/// split a bitmap into at most two bitmaps whose base vertices are aligned to 64, as required by the wire encoding
+/// [`crate::instruction::Instruction32::load_defects_bitmap`]; every defect keeps its node index
+pub fn align_defects_bitmap(
+    base_vertex: CompactVertexIndex,
+    base_node: CompactNodeIndex,
+    bitmap: u64,
+    mut func: impl FnMut(CompactVertexIndex, CompactNodeIndex, u64),
+) {
+    let offset = base_vertex.get() % 64;
+    let aligned_vertex = base_vertex.get() - offset;
+    let low = bitmap << offset;
+    let high = if offset == 0 { 0 } else { bitmap >> (64 - offset) };
+    if low != 0 {
+        func(ni!(aligned_vertex), base_node, low);
+    }
+    if high != 0 {
+        let node = base_node.get() + low.count_ones() as CompactNodeNum;
+        func(ni!(aligned_vertex + 64), ni!(node), high);
+    }
 }
 
 impl CompactObstacle {
@@ -142,3 +227,69 @@ impl CompactObstacle {
         matches!(self, Self::GrowLength { .. })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conflict(node_1: usize, node_2: Option<usize>, vertex_1: usize, vertex_2: usize) -> CompactObstacle {
+        CompactObstacle::Conflict {
+            node_1: ni!(node_1).option(),
+            node_2: node_2.map(|node_2| ni!(node_2)).into(),
+            touch_1: ni!(node_1).option(),
+            touch_2: node_2.map(|node_2| ni!(node_2)).into(),
+            vertex_1: ni!(vertex_1),
+            vertex_2: ni!(vertex_2),
+        }
+    }
+
+    #[test]
+    fn interface_reduce_priority() {
+        // cargo test interface_reduce_priority -- --nocapture
+        let responses = [
+            CompactObstacle::None,
+            CompactObstacle::GrowLength { length: 4 },
+            CompactObstacle::GrowLength { length: 2 },
+            CompactObstacle::BlossomNeedExpand { blossom: ni!(20) },
+            CompactObstacle::BlossomNeedExpand { blossom: ni!(10) },
+            conflict(1, None, 3, 9),
+            conflict(2, Some(5), 8, 6),
+            conflict(3, Some(4), 7, 11),
+        ];
+        let reduce_in = |order: &[usize]| {
+            order
+                .iter()
+                .map(|&index| responses[index].clone())
+                .reduce(CompactObstacle::reduce)
+                .unwrap()
+        };
+        // every rotation of both directions yields the same winner: the peer conflict at the lowest vertex
+        let mut order: [usize; 8] = core::array::from_fn(|index| index);
+        for _ in 0..order.len() {
+            order.rotate_left(1);
+            assert_eq!(reduce_in(&order), responses[6]);
+            let mut reversed = order;
+            reversed.reverse();
+            assert_eq!(reduce_in(&reversed), responses[6]);
+        }
+        assert_eq!(reduce_in(&[0, 1, 5, 4, 3]), responses[5]);
+        assert_eq!(reduce_in(&[0, 3, 1, 4, 2]), responses[4]);
+        assert_eq!(reduce_in(&[1, 0, 2]), responses[2]);
+        assert_eq!(reduce_in(&[0, 0]), CompactObstacle::None);
+    }
+
+    #[test]
+    fn interface_align_defects_bitmap() {
+        // cargo test interface_align_defects_bitmap -- --nocapture
+        for (base_vertex, bitmap) in [(0, 0b1011), (70, 0b1011 | (1 << 63)), (127, u64::MAX), (64, 1 << 63)] {
+            let mut expected = std::vec::Vec::new();
+            iterate_defects_bitmap(ni!(base_vertex), ni!(3), bitmap, |vertex, node| expected.push((vertex, node)));
+            let mut aligned = std::vec::Vec::new();
+            align_defects_bitmap(ni!(base_vertex), ni!(3), bitmap, |base_vertex, base_node, bitmap| {
+                assert_eq!(base_vertex.get() % 64, 0);
+                iterate_defects_bitmap(base_vertex, base_node, bitmap, |vertex, node| aligned.push((vertex, node)));
+            });
+            assert_eq!(aligned, expected);
+        }
+    }
+}
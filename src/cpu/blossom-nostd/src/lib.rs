@@ -4,6 +4,7 @@
 
 pub mod benchmark;
 pub mod blossom_tracker;
+pub mod conflict_queue;
 pub mod dual_driver_tracked;
 pub mod dual_module_stackless;
 pub mod heapless;
@@ -11,7 +12,9 @@ pub mod instruction;
 pub mod interface;
 pub mod latency_benchmarker;
 pub mod layer_fusion;
+pub mod message_sequence;
 pub mod nonmax;
 pub mod primal_module_embedded;
 pub mod primal_nodes;
+pub mod primal_policy;
 pub mod util;
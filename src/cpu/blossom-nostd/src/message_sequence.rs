@@ -0,0 +1,184 @@
+//! Message Sequence
+//!
+//! On a real bus, an instruction may be dropped or delivered twice, and a dropped interrupt may let the CPU read a
+//! stale response. Without detection, the primal and dual modules silently diverge and produce a wrong correction.
+//! Every instruction is tagged with a wrapping sequence number, and the hardware echoes the sequence number of the
+//! last executed instruction along with each response.
+//! The hardware side uses [`SequenceFilter`] to execute each instruction only once, so that retransmitting an
+//! instruction with the same sequence number is always safe; the driver side uses [`MessageSequencer`] to check
+//! that every response answers the latest instruction.
+//!
+//! Scope: only the simulated bus of the combinatorial dual module (`DualModuleCombDriver` with `sequence_check`)
+//! implements this protocol, including the failure injection. `Instruction32` has no field for the sequence number
+//! and the Scala hardware does not echo one, so the looper and AXI4 drivers are not covered until the hardware
+//! carries the sequence number.
+//!
+
+#[cfg(feature = "serde")]
+use serde::*;
+
+pub type CompactSequence = u16;
+
+#[cfg_attr(any(test, feature = "std"), derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SequenceError {
+    /// the response is not updated since the last acknowledged instruction: either the instruction or
+    /// the response (interrupt) is lost
+    Lost {
+        expected: CompactSequence,
+        received: CompactSequence,
+    },
+    /// the response belongs to neither the latest instruction nor the last acknowledged one
+    Reordered {
+        expected: CompactSequence,
+        received: CompactSequence,
+    },
+}
+
+/// how the driver reacts to a desynchronized response; the registers of the hardware are never dumped to rebuild the
+/// state of the driver, so a shot that cannot be resynchronized by retransmission is reported as failed
+#[cfg_attr(any(test, feature = "std"), derive(Debug))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DesyncRecovery {
+    /// retransmit the instruction with the same sequence number; the hardware ignores it if already executed and
+    /// reports its latest state again
+    Resync,
+    /// give up the current shot at the first desynchronized response
+    Abort,
+}
+
+#[cfg_attr(any(test, feature = "std"), derive(Debug))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Default)]
+pub struct SequenceStatistics {
+    pub sent: usize,
+    pub lost: usize,
+    pub reordered: usize,
+}
+
+/// the driver side: assign sequence numbers and check the responses
+pub struct MessageSequencer {
+    next: CompactSequence,
+    acknowledged: Option<CompactSequence>,
+    pub statistics: SequenceStatistics,
+}
+
+impl MessageSequencer {
+    pub const fn new() -> Self {
+        Self {
+            next: 0,
+            acknowledged: None,
+            statistics: SequenceStatistics {
+                sent: 0,
+                lost: 0,
+                reordered: 0,
+            },
+        }
+    }
+
+    /// the sequence number of the next instruction
+    pub fn send(&mut self) -> CompactSequence {
+        let sequence = self.next;
+        self.next = self.next.wrapping_add(1);
+        self.statistics.sent += 1;
+        sequence
+    }
+
+    /// check the sequence number echoed by the hardware against the latest instruction
+    pub fn check(&mut self, received: CompactSequence) -> Result<(), SequenceError> {
+        let expected = self.next.wrapping_sub(1);
+        if received == expected {
+            self.acknowledged = Some(received);
+            Ok(())
+        } else if self.acknowledged == Some(received) {
+            self.statistics.lost += 1;
+            Err(SequenceError::Lost { expected, received })
+        } else {
+            self.statistics.reordered += 1;
+            Err(SequenceError::Reordered { expected, received })
+        }
+    }
+}
+
+impl Default for MessageSequencer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// the hardware side: execute every instruction only once
+pub struct SequenceFilter {
+    last: Option<CompactSequence>,
+    /// the number of instructions ignored because they have been executed
+    pub duplicated: usize,
+}
+
+impl SequenceFilter {
+    pub const fn new() -> Self {
+        Self {
+            last: None,
+            duplicated: 0,
+        }
+    }
+
+    /// returns whether the instruction should be executed
+    pub fn accept(&mut self, sequence: CompactSequence) -> bool {
+        if self.last == Some(sequence) {
+            self.duplicated += 1;
+            return false;
+        }
+        self.last = Some(sequence);
+        true
+    }
+}
+
+impl Default for SequenceFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_sequence_detection() {
+        // cargo test message_sequence_detection -- --nocapture
+        let mut sequencer = MessageSequencer::new();
+        let mut filter = SequenceFilter::new();
+        let first = sequencer.send();
+        assert!(filter.accept(first));
+        assert_eq!(sequencer.check(first), Ok(()));
+        // the second instruction is dropped and the stale response is read
+        let second = sequencer.send();
+        assert_eq!(
+            sequencer.check(first),
+            Err(SequenceError::Lost {
+                expected: second,
+                received: first
+            })
+        );
+        // retransmit, but this time the instruction is delivered twice
+        assert!(filter.accept(second));
+        assert!(!filter.accept(second));
+        assert_eq!(sequencer.check(second), Ok(()));
+        // a response from an older instruction
+        sequencer.send();
+        assert!(matches!(sequencer.check(first), Err(SequenceError::Reordered { .. })));
+        assert_eq!(sequencer.statistics.lost, 1);
+        assert_eq!(sequencer.statistics.reordered, 1);
+        assert_eq!(filter.duplicated, 1);
+    }
+
+    #[test]
+    fn message_sequence_wrapping() {
+        // cargo test message_sequence_wrapping -- --nocapture
+        let mut sequencer = MessageSequencer::new();
+        for _ in 0..(CompactSequence::MAX as usize + 10) {
+            let sequence = sequencer.send();
+            assert_eq!(sequencer.check(sequence), Ok(()));
+        }
+    }
+}
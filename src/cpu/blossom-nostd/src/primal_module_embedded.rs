@@ -19,6 +19,10 @@ pub struct PrimalModuleEmbedded<const N: usize, const VN: usize = 0> {
     pub nodes: PrimalNodes<N>,
     /// optionally, it can store the layer fusion table of the vertices
     pub layer_fusion: LayerFusionData<VN>,
+    /// the speed magnitude of the lone defects, i.e., those not yet encountered by the primal module, 1 by default.
+    /// A lone defect is an alternating tree of a single node without any tight edge to keep, so it can grow faster
+    /// toward the others; it grows at unit speed once encountered, like all the other nodes of the trees
+    pub lone_defect_magnitude: CompactSpeed,
 }
 
 impl<const N: usize, const VN: usize> PrimalModuleEmbedded<N, VN> {
@@ -26,6 +30,23 @@ impl<const N: usize, const VN: usize> PrimalModuleEmbedded<N, VN> {
         Self {
             nodes: PrimalNodes::new(),
             layer_fusion: LayerFusionData::new(),
+            lone_defect_magnitude: 1,
+        }
+    }
+
+    /// speed up a newly loaded defect according to [`Self::lone_defect_magnitude`], limited by the dual module
+    pub fn speed_up_lone_defect(&self, dual_module: &mut impl DualInterface, node: CompactNodeIndex) {
+        let magnitude = self.lone_defect_magnitude.min(dual_module.max_speed_magnitude());
+        if magnitude > 1 {
+            dual_module.set_speed_with_magnitude(false, node, CompactGrowState::Grow, magnitude);
+        }
+    }
+
+    /// a lone defect encountered for the first time grows at unit speed from now on, see
+    /// [`Self::lone_defect_magnitude`]
+    fn slow_down_lone_defect(&self, dual_module: &mut impl DualInterface, node: CompactNodeIndex) {
+        if self.lone_defect_magnitude > 1 && !self.nodes.is_blossom(node) && !self.nodes.maintains_defect_node(node) {
+            dual_module.set_speed(false, node, CompactGrowState::Grow);
         }
     }
 }
@@ -67,6 +88,10 @@ impl<const N: usize, const VN: usize> PrimalInterface for PrimalModuleEmbedded<N
                 debug_assert!(node_1.is_some() && touch_1.is_some());
                 let mut node_1 = usu!(node_1);
                 let touch_1 = usu!(touch_1);
+                self.slow_down_lone_defect(dual_module, node_1);
+                if let Some(node_2) = node_2.option() {
+                    self.slow_down_lone_defect(dual_module, node_2);
+                }
                 self.nodes.check_node_index(node_1);
                 self.nodes.check_node_index(touch_1);
                 cfg_if::cfg_if! {
@@ -844,6 +869,13 @@ impl<const N: usize, const VN: usize> PrimalModuleEmbedded<N, VN> {
     }
 
     /// fusing a layer will remove all existing virtual matchings with the layer
+    /// forget a finished defect node, see [`PrimalNodes::recycle_defect`]; its pending breaks are dropped as well, so
+    /// that a new defect reusing the index does not inherit them
+    pub fn recycle_defect(&mut self, node_index: CompactNodeIndex) {
+        self.layer_fusion.iterate_pending_breaks(|_, node| node == node_index);
+        self.nodes.recycle_defect(node_index);
+    }
+
     pub fn fuse_layer(&mut self, dual_module: &mut impl DualInterface, layer_id: CompactLayerId) {
         let (layer_fusion, nodes) = (&mut self.layer_fusion, &mut self.nodes);
         layer_fusion.iterate_pending_breaks(|layer_fusion, node| {
@@ -389,6 +389,18 @@ impl<const N: usize> PrimalNodes<N> {
         set!(self.first_blossom_child, blossom_index.get() as usize, None.into());
     }
 
+    /// forget a defect node whose matching has been finalized, so that its index can be reused by a new defect;
+    /// the caller must record the matching beforehand, and no other node should still refer to this node
+    pub fn recycle_defect(&mut self, node_index: CompactNodeIndex) {
+        debug_assert!(!self.is_blossom(node_index), "do not recycle a blossom");
+        debug_assert!(self.has_node(node_index), "do not recycle twice");
+        debug_assert!(
+            !self.get_node(node_index).in_alternating_tree(),
+            "cannot recycle a node in an alternating tree"
+        );
+        set!(self.buffer, node_index.get() as usize, None);
+    }
+
     /// create an iterator containing all the existing node indices
     pub fn index_iter(&self) -> Chain<Range<usize>, Range<usize>> {
         (0..self.count_defects).chain(self.blossom_begin..self.blossom_begin + self.count_blossoms)
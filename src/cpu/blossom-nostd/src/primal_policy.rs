@@ -0,0 +1,116 @@
+//! Primal Policy
+//!
+//! A single round of the dual module may find several obstacles at once, and the order in which the primal module
+//! resolves them decides which alternating tree grows or augments first. Every order leads to a minimum-weight
+//! perfect matching, but the number of iterations, and hence the latency, depends on it.
+//! A policy ranks the obstacles found in the same round: the one with the smallest priority is reported first and
+//! the others are queued in the order of priority (see [`crate::conflict_queue`]). Ties keep the order of the
+//! hardware, so that the policy only needs to rank what it cares about.
+//!
+
+use crate::interface::*;
+#[cfg(feature = "serde")]
+use serde::*;
+
+pub trait PrimalPolicy {
+    /// the obstacle with the smallest priority is resolved first
+    fn priority(&self, obstacle: &CompactObstacle) -> usize;
+}
+
+/// the node indices involved in an obstacle, `None` for a virtual vertex
+fn obstacle_nodes(obstacle: &CompactObstacle) -> [Option<usize>; 2] {
+    match obstacle {
+        CompactObstacle::Conflict { node_1, node_2, .. } => [
+            node_1.option().map(|node| node.get() as usize),
+            node_2.option().map(|node| node.get() as usize),
+        ],
+        CompactObstacle::BlossomNeedExpand { blossom } => [Some(blossom.get() as usize), None],
+        _ => [None, None],
+    }
+}
+
+/// the order of the hardware, i.e., the position of the vertex or edge reporting the obstacle
+pub struct HardwareOrder;
+
+impl PrimalPolicy for HardwareOrder {
+    fn priority(&self, _obstacle: &CompactObstacle) -> usize {
+        0
+    }
+}
+
+/// the defects are indexed in the order of loading and the blossoms after all the defects, so this policy resolves
+/// the obstacles of the earliest trees first, e.g., those of the older layers in stream decoding
+pub struct OldestNodeFirst;
+
+impl PrimalPolicy for OldestNodeFirst {
+    fn priority(&self, obstacle: &CompactObstacle) -> usize {
+        obstacle_nodes(obstacle).iter().flatten().copied().min().unwrap_or(usize::MAX)
+    }
+}
+
+/// resolve the obstacles of the latest nodes first, which are usually blossoms, keeping the work on the same tree
+/// until it settles
+pub struct NewestNodeFirst;
+
+impl PrimalPolicy for NewestNodeFirst {
+    fn priority(&self, obstacle: &CompactObstacle) -> usize {
+        usize::MAX - obstacle_nodes(obstacle).iter().flatten().copied().max().unwrap_or(0)
+    }
+}
+
+/// the built-in policies, e.g., to select one from a configuration
+#[cfg_attr(any(test, feature = "std"), derive(Debug))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrimalPolicyType {
+    #[default]
+    HardwareOrder,
+    OldestNodeFirst,
+    NewestNodeFirst,
+}
+
+impl PrimalPolicyType {
+    pub fn policy(&self) -> &'static dyn PrimalPolicy {
+        match self {
+            Self::HardwareOrder => &HardwareOrder,
+            Self::OldestNodeFirst => &OldestNodeFirst,
+            Self::NewestNodeFirst => &NewestNodeFirst,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn conflict(node_1: usize, node_2: Option<usize>) -> CompactObstacle {
+        CompactObstacle::Conflict {
+            node_1: ni!(node_1).option(),
+            node_2: node_2.map(|node_2| ni!(node_2)).into(),
+            touch_1: ni!(node_1).option(),
+            touch_2: node_2.map(|node_2| ni!(node_2)).into(),
+            vertex_1: ni!(node_1),
+            vertex_2: ni!(100),
+        }
+    }
+
+    #[test]
+    fn primal_policy_priority() {
+        // cargo test primal_policy_priority -- --nocapture
+        let obstacles = [
+            conflict(5, Some(3)),
+            conflict(1, None),
+            CompactObstacle::BlossomNeedExpand { blossom: ni!(50) },
+        ];
+        let rank = |policy_type: PrimalPolicyType| {
+            let policy = policy_type.policy();
+            let mut order = [0, 1, 2];
+            order.sort_by_key(|&index| policy.priority(&obstacles[index]));
+            order
+        };
+        assert_eq!(rank(PrimalPolicyType::HardwareOrder), [0, 1, 2]);
+        assert_eq!(rank(PrimalPolicyType::OldestNodeFirst), [1, 0, 2]);
+        assert_eq!(rank(PrimalPolicyType::NewestNodeFirst), [2, 0, 1]);
+    }
+}
@@ -78,6 +78,107 @@ impl From<CompactGrowState> for isize {
     }
 }
 
+/// a signed speed of growth, i.e., the grow state multiplied by a small magnitude;
+/// a magnitude larger than 1 allows two trees to grow toward each other faster, halving the number of rounds
+pub type CompactSpeed = i8;
+/// the speed magnitude is encoded in 3 bits in the instruction, see [`crate::instruction::Instruction32`]
+pub const MAX_SPEED_MAGNITUDE: CompactSpeed = 7;
+
+impl CompactGrowState {
+    pub fn with_magnitude(self, magnitude: CompactSpeed) -> CompactSpeed {
+        debug_assert!(
+            (1..=MAX_SPEED_MAGNITUDE).contains(&magnitude),
+            "speed magnitude {} out of range [1, {}]",
+            magnitude,
+            MAX_SPEED_MAGNITUDE
+        );
+        isize::from(self) as CompactSpeed * magnitude
+    }
+}
+
+/// The weight and growth arithmetic that may overflow with unusual weight scales, e.g., with `i16_weight`.
+/// By default it is the plain arithmetic, i.e., a silent wraparound in release builds; with the `checked_weight`
+/// feature, an overflow saturates instead and is latched in `overflow`, so that the shot is reported as a decoding
+/// failure rather than silently giving a wrong matching.
+#[inline(always)]
+pub fn weight_add(a: CompactWeight, b: CompactWeight, overflow: &mut bool) -> CompactWeight {
+    cfg_if::cfg_if! {
+        if #[cfg(feature="checked_weight")] {
+            a.checked_add(b).unwrap_or_else(|| {
+                *overflow = true;
+                a.saturating_add(b)
+            })
+        } else {
+            let _ = overflow;
+            a + b
+        }
+    }
+}
+
+#[inline(always)]
+pub fn weight_sub(a: CompactWeight, b: CompactWeight, overflow: &mut bool) -> CompactWeight {
+    cfg_if::cfg_if! {
+        if #[cfg(feature="checked_weight")] {
+            a.checked_sub(b).unwrap_or_else(|| {
+                *overflow = true;
+                a.saturating_sub(b)
+            })
+        } else {
+            let _ = overflow;
+            a - b
+        }
+    }
+}
+
+/// a non-negative weight as a timestamp, see [`weight_add`]
+#[inline(always)]
+pub fn timestamp_of_weight(weight: CompactWeight, overflow: &mut bool) -> CompactTimestamp {
+    cfg_if::cfg_if! {
+        if #[cfg(feature="checked_weight")] {
+            if weight < 0 {
+                *overflow = true;
+                return 0;
+            }
+            weight as CompactTimestamp
+        } else {
+            let _ = overflow;
+            weight as CompactTimestamp
+        }
+    }
+}
+
+/// a timestamp difference as a weight, see [`weight_add`]
+#[inline(always)]
+pub fn weight_of_timestamp(timestamp: CompactTimestamp, overflow: &mut bool) -> CompactWeight {
+    cfg_if::cfg_if! {
+        if #[cfg(feature="checked_weight")] {
+            use core::convert::TryFrom;
+            CompactWeight::try_from(timestamp).unwrap_or_else(|_| {
+                *overflow = true;
+                CompactWeight::MAX
+            })
+        } else {
+            let _ = overflow;
+            timestamp as CompactWeight
+        }
+    }
+}
+
+#[inline(always)]
+pub fn timestamp_add(a: CompactTimestamp, b: CompactTimestamp, overflow: &mut bool) -> CompactTimestamp {
+    cfg_if::cfg_if! {
+        if #[cfg(feature="checked_weight")] {
+            a.checked_add(b).unwrap_or_else(|| {
+                *overflow = true;
+                CompactTimestamp::MAX
+            })
+        } else {
+            let _ = overflow;
+            a + b
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum CompactMatchTarget {
     Peer(CompactNodeIndex),
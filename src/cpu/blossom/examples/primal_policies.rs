@@ -0,0 +1,55 @@
+//! # Primal Policies
+//!
+//! Compare the number of iterations, i.e., resolved obstacles, of the primal tie-breaking policies on the same
+//! shots. Every policy reaches the same matching weight, so only the iteration count is reported, both with a single
+//! conflict per round and with the conflict queue.
+//!
+//! ```sh
+//! cargo run --release --example primal_policies
+//! D=7 P=0.005 SAMPLES=10000 QUEUE_DEPTH=16 cargo run --release --example primal_policies
+//! ```
+//!
+
+use fusion_blossom::example_codes::*;
+use fusion_blossom::mwpm_solver::*;
+use micro_blossom::mwpm_solver::*;
+use micro_blossom::resources::MicroBlossomSingle;
+use micro_blossom::util::*;
+use serde_json::json;
+
+fn main() {
+    let d = env_usize("D", 5);
+    let p = env_f64("P", 0.01);
+    let samples = env_usize("SAMPLES", 1000);
+    let queue_depth = env_usize("QUEUE_DEPTH", 8);
+    println!("d = {d}, rounds = {d}, p = {p}, samples = {samples}");
+
+    let mut code = PhenomenologicalRotatedCode::new(d, d, p, 500);
+    let graph = MicroBlossomSingle::new_code(&code);
+    let syndrome_patterns: Vec<_> = (0..samples as u64).map(|seed| code.generate_random_errors(seed)).collect();
+    let defects: usize = syndrome_patterns.iter().map(|pattern| pattern.defect_vertices.len()).sum();
+    println!("average defects per shot: {}", defects as f64 / samples as f64);
+
+    for conflict_queue_depth in [1, queue_depth] {
+        for primal_policy in ["HardwareOrder", "OldestNodeFirst", "NewestNodeFirst"] {
+            let mut solver = SolverEmbeddedComb::new(
+                graph.clone(),
+                json!({ "dual": { "primal_policy": primal_policy, "conflict_queue_depth": conflict_queue_depth } }),
+            );
+            let mut iterations: Vec<usize> = Vec::with_capacity(samples);
+            for syndrome_pattern in syndrome_patterns.iter() {
+                solver.solve(syndrome_pattern);
+                iterations.push(solver.iterations());
+                solver.clear();
+            }
+            iterations.sort();
+            let average = iterations.iter().sum::<usize>() as f64 / samples as f64;
+            println!(
+                "{primal_policy:>16} (queue depth {conflict_queue_depth:>2}): iterations average {average:.3}, \
+                p99 {}, max {}",
+                iterations[((samples - 1) as f64 * 0.99).round() as usize],
+                iterations.last().unwrap()
+            );
+        }
+    }
+}
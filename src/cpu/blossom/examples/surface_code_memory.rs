@@ -0,0 +1,110 @@
+//! # Surface Code Memory Experiment
+//!
+//! End-to-end usage of the decoder: a rotated surface code with circuit-level noise is decoded round by round
+//! using layer fusion on the combinatorial dual module, and the logical error rate and decoding latency are reported.
+//! The defects of every measurement round are streamed to the decoder as the round arrives, so the latency of a round
+//! is the time to decode it, and the latency of a shot is the time from the arrival of the last round to the final
+//! correction.
+//!
+//! ```sh
+//! cargo run --release --example surface_code_memory
+//! D=5 ROUNDS=5 P=0.001 SAMPLES=10000 cargo run --release --example surface_code_memory
+//! ```
+//!
+
+use fusion_blossom::example_codes::*;
+use fusion_blossom::mwpm_solver::*;
+use micro_blossom::example_codes::QECPlaygroundCode;
+use micro_blossom::mwpm_solver::*;
+use micro_blossom::resources::MicroBlossomSingle;
+use micro_blossom::util::*;
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+fn main() {
+    let d = env_usize("D", 3);
+    let rounds = env_usize("ROUNDS", d);
+    let p = env_f64("P", 0.001);
+    let samples = env_usize("SAMPLES", 1000);
+    println!("d = {d}, rounds = {rounds}, p = {p}, samples = {samples}");
+
+    // rotated surface code with `rounds` noisy measurement rounds and a final perfect measurement round
+    let mut code = QECPlaygroundCode::new(
+        d,
+        p,
+        json!({
+            "nm": rounds,
+            "code_type": qecp::code_builder::CodeType::RotatedPlanarCode,
+            "noise_model": qecp::noise_model_builder::NoiseModelBuilder::StimNoiseModel,
+            "max_half_weight": 7,
+        }),
+    );
+    let mut graph = MicroBlossomSingle::new_code(&code);
+    graph.assign_layers_from_rounds(&code.get_vertex_rounds());
+    let num_layers = graph.layer_assignment.as_ref().unwrap().num_layers;
+    let layer_fusion = graph.layer_fusion.clone().unwrap();
+    assert_eq!(num_layers, layer_fusion.num_layers);
+    println!(
+        "vertices = {}, edges = {}, layers = {num_layers}",
+        graph.vertex_num,
+        graph.weighted_edges.len()
+    );
+
+    // each measurement round is fused into the decoder as a separate layer
+    let mut solver = SolverEmbeddedComb::new(
+        graph,
+        json!({
+            "dual": {
+                "sim_config": {
+                    "support_offloading": true,
+                    "support_layer_fusion": true,
+                }
+            }
+        }),
+    );
+
+    let mut logical_errors = 0;
+    let mut round_latencies: Vec<Duration> = Vec::with_capacity(samples * num_layers);
+    let mut latencies: Vec<Duration> = Vec::with_capacity(samples);
+    for seed in 0..samples as u64 {
+        let syndrome = code.generate_random_errors(seed);
+        let mut rounds = vec![vec![]; num_layers];
+        for &vertex_index in syndrome.defect_vertices.iter() {
+            rounds[layer_fusion.vertex_layer_id[&vertex_index]].push(vertex_index);
+        }
+        for (round, defect_vertices) in rounds.iter().enumerate() {
+            let begin = Instant::now();
+            solver.stream_round(defect_vertices);
+            if round + 1 == num_layers {
+                // the correction is only final once the last round is decoded
+                solver.stream_finish();
+                latencies.push(begin.elapsed());
+            }
+            round_latencies.push(begin.elapsed());
+        }
+        let subgraph = solver.subgraph();
+        if code.is_logical_error(&subgraph) {
+            logical_errors += 1;
+        }
+        solver.clear();
+    }
+
+    println!(
+        "logical error rate: {} ({logical_errors}/{samples})",
+        logical_errors as f64 / samples as f64
+    );
+    print_latency("round latency", round_latencies);
+    print_latency("shot latency (after the last round)", latencies);
+}
+
+fn print_latency(name: &str, mut latencies: Vec<Duration>) {
+    latencies.sort();
+    let percentile = |ratio: f64| latencies[((latencies.len() - 1) as f64 * ratio).round() as usize];
+    let average = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+    println!(
+        "{name}: average {average:?}, p50 {:?}, p99 {:?}, max {:?}",
+        percentile(0.5),
+        percentile(0.99),
+        latencies.last().unwrap()
+    );
+}
@@ -0,0 +1,278 @@
+//! Animation
+//!
+//! Export the decoding of a single shot as a self-contained animation description, e.g., for teaching and demos
+//! without the visualizer of fusion-blossom. Every frame is a full snapshot of the solver after one step, with a
+//! caption listing the instructions sent to the dual module in this step and an interpolation hint: the dual
+//! variables grow continuously and can be tweened linearly, while the structural changes (e.g., creating a
+//! blossom) happen at once.
+//!
+
+use crate::cli::CodeParameters;
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use clap::Parser;
+use fusion_blossom::example_codes::ExampleCode;
+use fusion_blossom::mwpm_solver::PrimalDualSolver;
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// the snapshot fields that change continuously as the dual variables grow
+pub const GROWTH_KEYS: &[&str] = &["left_growth", "right_growth", "grown", "dual_variable"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    /// nothing changes, e.g., an instruction without visible effect
+    Hold,
+    /// only the dual variables grow, tween the growth from the previous frame
+    Linear,
+    /// only the structure changes, jump to this frame
+    Step,
+    /// tween the growth first and then jump to the new structure
+    LinearThenStep,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AnimationFrame {
+    /// the start time of the frame in seconds
+    pub time: f64,
+    pub duration: f64,
+    pub caption: String,
+    pub interpolation: Interpolation,
+    pub snapshot: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Animation {
+    pub positions: Vec<VisualizePosition>,
+    pub defect_vertices: Vec<VertexIndex>,
+    pub frames: Vec<AnimationFrame>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnimationConfig {
+    /// the duration of a frame with a structural change, in seconds
+    #[serde(default = "animation_config_default::step_duration")]
+    pub step_duration: f64,
+    /// the duration of growing by one unit of weight, in seconds; a growth frame takes at least `step_duration`
+    #[serde(default = "animation_config_default::growth_duration")]
+    pub growth_duration: f64,
+    /// the duration of the last frame, in seconds
+    #[serde(default = "animation_config_default::final_duration")]
+    pub final_duration: f64,
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        serde_json::from_value(json!({})).unwrap()
+    }
+}
+
+pub mod animation_config_default {
+    pub fn step_duration() -> f64 {
+        0.5
+    }
+    pub fn growth_duration() -> f64 {
+        0.01
+    }
+    pub fn final_duration() -> f64 {
+        2.
+    }
+}
+
+/// replace every growth field by null, returning the largest change of the growth fields
+fn split_growth(value: &serde_json::Value, previous: Option<&serde_json::Value>) -> (serde_json::Value, f64) {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut structure = serde_json::Map::new();
+            let mut max_growth: f64 = 0.;
+            for (key, field) in map.iter() {
+                let previous_field = previous.and_then(|previous| previous.get(key));
+                if GROWTH_KEYS.contains(&key.as_str()) {
+                    let delta = field.as_f64().unwrap_or(0.) - previous_field.and_then(|field| field.as_f64()).unwrap_or(0.);
+                    max_growth = max_growth.max(delta.abs());
+                    structure.insert(key.clone(), serde_json::Value::Null);
+                } else {
+                    let (field, growth) = split_growth(field, previous_field);
+                    max_growth = max_growth.max(growth);
+                    structure.insert(key.clone(), field);
+                }
+            }
+            (serde_json::Value::Object(structure), max_growth)
+        }
+        serde_json::Value::Array(array) => {
+            let mut max_growth: f64 = 0.;
+            let structure = array
+                .iter()
+                .enumerate()
+                .map(|(index, field)| {
+                    let (field, growth) = split_growth(field, previous.and_then(|previous| previous.get(index)));
+                    max_growth = max_growth.max(growth);
+                    field
+                })
+                .collect();
+            (serde_json::Value::Array(structure), max_growth)
+        }
+        value => (value.clone(), 0.),
+    }
+}
+
+/// the instructions executed since `previous`, e.g., "grow, set_speed x2"
+fn caption_of(previous: &BTreeMap<String, usize>, current: &BTreeMap<String, usize>) -> String {
+    current
+        .iter()
+        .filter_map(|(name, &count)| {
+            let count = count - previous.get(name).cloned().unwrap_or(0);
+            match count {
+                0 => None,
+                1 => Some(name.clone()),
+                _ => Some(format!("{name} x{count}")),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Animation {
+    /// decode a single shot step by step; the solver is left finished
+    pub fn record<Dual: SolverTrackedDual>(
+        solver: &mut SolverEmbeddedBoxed<Dual>,
+        syndrome_pattern: &SyndromePattern,
+        positions: &[VisualizePosition],
+        config: &AnimationConfig,
+    ) -> Self {
+        let mut animation = Self {
+            positions: positions.to_vec(),
+            defect_vertices: syndrome_pattern.defect_vertices.clone(),
+            frames: vec![],
+        };
+        let instruction_counts =
+            |solver: &SolverEmbeddedBoxed<Dual>| solver.dual_module.driver.driver.instruction_counts().unwrap_or_default();
+        let mut previous_counts = instruction_counts(solver);
+        solver.load_syndrome(syndrome_pattern);
+        let mut has_next = true;
+        while has_next {
+            let counts = instruction_counts(solver);
+            let caption = if animation.frames.is_empty() {
+                format!("load {} defects", syndrome_pattern.defect_vertices.len())
+            } else {
+                caption_of(&previous_counts, &counts)
+            };
+            animation.push_frame(caption, solver.snapshot(false), config);
+            previous_counts = counts;
+            has_next = solver.step();
+        }
+        solver.finish();
+        animation.push_frame("solved".to_string(), solver.snapshot(false), config);
+        let subgraph = solver.subgraph();
+        let mut snapshot = solver.snapshot(false);
+        snapshot_combine_values(&mut snapshot, VisualizeSubgraph::new(&subgraph).snapshot(false), false);
+        animation.push_frame("subgraph".to_string(), snapshot, config);
+        animation.frames.last_mut().unwrap().duration = config.final_duration;
+        animation
+    }
+
+    fn push_frame(&mut self, caption: String, snapshot: serde_json::Value, config: &AnimationConfig) {
+        let previous = self.frames.last();
+        let (structure, growth) = split_growth(&snapshot, previous.map(|frame| &frame.snapshot));
+        let structure_changed = previous.map_or(true, |frame| split_growth(&frame.snapshot, None).0 != structure);
+        let interpolation = match (previous.is_some() && growth > 0., structure_changed) {
+            (false, false) => Interpolation::Hold,
+            (true, false) => Interpolation::Linear,
+            (false, true) => Interpolation::Step,
+            (true, true) => Interpolation::LinearThenStep,
+        };
+        let duration = match interpolation {
+            Interpolation::Linear | Interpolation::LinearThenStep => {
+                config.step_duration.max(growth * config.growth_duration)
+            }
+            Interpolation::Hold | Interpolation::Step => config.step_duration,
+        };
+        let time = previous.map_or(0., |frame| frame.time + frame.duration);
+        self.frames.push(AnimationFrame {
+            time,
+            duration,
+            caption,
+            interpolation,
+            snapshot,
+        });
+    }
+
+    pub fn total_duration(&self) -> f64 {
+        self.frames.last().map_or(0., |frame| frame.time + frame.duration)
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct AnimationParameters {
+    #[clap(flatten)]
+    code: CodeParameters,
+    /// the seed of the shot
+    #[clap(short = 's', long, default_value_t = 0)]
+    seed: u64,
+    /// the configuration of the embedded solver with the combinatorial dual module
+    #[clap(long, default_value_t = ("{}").to_string())]
+    primal_dual_config: String,
+    /// the timing of the frames, see [`AnimationConfig`]
+    #[clap(long, default_value_t = ("{}").to_string())]
+    animation_config: String,
+    /// the output animation file at visualize/data/<filename>
+    #[clap(long, default_value_t = format!("animation.json"))]
+    filename: String,
+}
+
+impl AnimationParameters {
+    pub fn run(&self) -> Animation {
+        let primal_dual_config: serde_json::Value = serde_json::from_str(&self.primal_dual_config).unwrap();
+        let config: AnimationConfig = serde_json::from_str(&self.animation_config).unwrap();
+        let mut code = self.code.build();
+        let mut solver = SolverEmbeddedComb::new(MicroBlossomSingle::new_code(code.as_ref()), primal_dual_config);
+        let syndrome_pattern = code.generate_random_errors(self.seed);
+        let animation = Animation::record(&mut solver, &syndrome_pattern, &code.get_positions(), &config);
+        let filename = visualize_data_folder() + self.filename.as_str();
+        std::fs::write(&filename, serde_json::to_string(&animation).unwrap()).unwrap();
+        println!(
+            "{} frames, {:.1}s in total, written to {filename}",
+            animation.frames.len(),
+            animation.total_duration()
+        );
+        animation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn animation_record() {
+        // cargo test animation_record -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        code.set_defect_vertices(&[16, 17, 18, 26, 34, 39]);
+        let mut solver = SolverEmbeddedComb::new(MicroBlossomSingle::new_code(&code), json!({}));
+        let config = AnimationConfig::default();
+        let animation = Animation::record(&mut solver, &code.get_syndrome(), &code.get_positions(), &config);
+        for frame in animation.frames.iter() {
+            println!("{:.2}s {:?} {}", frame.time, frame.interpolation, frame.caption);
+        }
+        assert_eq!(animation.frames[0].interpolation, Interpolation::Step);
+        assert!(animation
+            .frames
+            .iter()
+            .any(|frame| frame.interpolation != Interpolation::Step));
+        assert!(animation
+            .frames
+            .iter()
+            .skip(1)
+            .any(|frame| frame.caption.contains("set_speed")));
+        for pair in animation.frames.windows(2) {
+            assert_eq!(pair[1].time, pair[0].time + pair[0].duration);
+        }
+        assert_eq!(animation.frames.last().unwrap().caption, "subgraph");
+        serde_json::to_string(&animation).unwrap();
+    }
+}
@@ -30,7 +30,16 @@ fn generate_example(name: String, code: impl ExampleCode + FusionVisualizer) {
     }
 
     println!("generating {name}...");
-    let micro_blossom = MicroBlossomSingle::new_code(&code);
+    let mut micro_blossom = MicroBlossomSingle::new_code(&code);
+    micro_blossom.detect_symmetry();
+    if let Some(sharing_hints) = micro_blossom.sharing_hints.as_ref() {
+        println!(
+            "    {} replicated tiles, weight ROM: {} words for {} edges",
+            sharing_hints.tiles.len(),
+            sharing_hints.weight_rom_size(&micro_blossom),
+            micro_blossom.weighted_edges.len()
+        );
+    }
 
     let json_str = serde_json::to_string(&micro_blossom).unwrap();
     fs::write(filename, json_str).unwrap();
@@ -0,0 +1,112 @@
+//! # HLS Compare
+//!
+//! Check that the HLS code path of the embedded primal module makes the same decisions as the normal one.
+//! The same syndrome corpus is recorded by two builds of this binary, with and without the `hls` feature,
+//! and the traces are compared step by step; see [`micro_blossom::decision_trace`].
+//!
+//! ```sh
+//! make hls-compare
+//! # or manually
+//! cargo run --release --bin hls_compare -- record 7 0.05 target/reference.json
+//! cargo +nightly run --release --features hls --bin hls_compare -- record 7 0.05 target/hls.json
+//! cargo run --release --bin hls_compare -- compare target/reference.json target/hls.json
+//! ```
+//!
+
+use clap::{Parser, Subcommand};
+use fusion_blossom::cli::ExampleCodeType;
+use fusion_blossom::util::*;
+use micro_blossom::decision_trace::*;
+use std::fs;
+
+#[derive(Parser, Clone)]
+#[clap(author = clap::crate_authors!(", "))]
+#[clap(version = env!("CARGO_PKG_VERSION"))]
+#[clap(about = "Compare the decisions of the HLS and the normal primal module")]
+#[clap(color = clap::ColorChoice::Auto)]
+#[clap(propagate_version = true)]
+#[clap(arg_required_else_help = true)]
+pub struct HlsCompare {
+    #[clap(subcommand)]
+    command: HlsCompareCommands,
+}
+
+#[derive(Subcommand, Clone)]
+enum HlsCompareCommands {
+    /// record the decision trace of this build
+    Record {
+        /// code distance
+        #[clap(value_parser)]
+        d: VertexNum,
+        /// physical error rate
+        #[clap(value_parser)]
+        p: f64,
+        /// output trace file
+        #[clap(value_parser)]
+        output: String,
+        /// rounds of noisy measurement, valid only when multiple rounds
+        #[clap(short = 'n', long, default_value_t = 0)]
+        noisy_measurements: VertexNum,
+        /// maximum half weight of edges
+        #[clap(long, default_value_t = 500)]
+        max_half_weight: Weight,
+        /// example code type
+        #[clap(short = 'c', long, value_enum, default_value_t = ExampleCodeType::CodeCapacityPlanarCode)]
+        code_type: ExampleCodeType,
+        /// the number of shots; the seed of each shot is its index
+        #[clap(short = 'r', long, default_value_t = 1000)]
+        total_rounds: u64,
+        /// the maximum number of recorded steps per shot
+        #[clap(long, default_value_t = 100000)]
+        max_steps: usize,
+    },
+    /// compare a trace of the normal build against a trace of the HLS build; exits with error on any mismatch
+    Compare {
+        #[clap(value_parser)]
+        reference: String,
+        #[clap(value_parser)]
+        hls: String,
+    },
+}
+
+fn read_trace(filename: &str) -> DecisionTrace {
+    serde_json::from_str(&fs::read_to_string(filename).unwrap()).unwrap()
+}
+
+fn main() {
+    match HlsCompare::parse().command {
+        HlsCompareCommands::Record {
+            d,
+            p,
+            output,
+            noisy_measurements,
+            max_half_weight,
+            code_type,
+            total_rounds,
+            max_steps,
+        } => {
+            let mut code = code_type.build(d, p, noisy_measurements, max_half_weight, serde_json::json!({}));
+            let trace = record_decisions(code.as_mut(), 0..total_rounds, max_steps);
+            fs::write(output, serde_json::to_string(&trace).unwrap()).unwrap();
+        }
+        HlsCompareCommands::Compare { reference, hls } => {
+            let reference = read_trace(&reference);
+            let hls = read_trace(&hls);
+            assert!(!reference.hls, "the reference trace is recorded with the `hls` feature");
+            assert!(hls.hls, "the hls trace is recorded without the `hls` feature");
+            let comparison = compare_decisions(&reference, &hls);
+            println!(
+                "{} shots, {} identical steps, {} shots stopped at unhandled obstacles",
+                comparison.shots, comparison.compared_steps, comparison.unsupported_shots
+            );
+            for mismatch in comparison.mismatches.iter() {
+                println!("{}", serde_json::to_string(mismatch).unwrap());
+            }
+            assert!(
+                comparison.mismatches.is_empty(),
+                "{} shots mismatch",
+                comparison.mismatches.len()
+            );
+        }
+    }
+}
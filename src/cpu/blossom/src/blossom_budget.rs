@@ -0,0 +1,342 @@
+//! Blossom Budget
+//!
+//! The hardware reserves a fixed number of blossom indices per shot, which is usually chosen from a worst-case bound.
+//! To choose it from data instead, this module emulates a smaller budget in software: once a shot needs more
+//! blossoms than the budget, the hardware would have no index for the new blossom, so the primal-dual solve stops
+//! there. The matchings that are already settled are kept, and the remaining defects are matched greedily by their
+//! shortest-path distances without creating any blossom. The result is still a valid correction but no longer
+//! minimum-weight.
+//!
+//! The profiler reports the histogram of the blossoms per shot and how often the budget overflows; the accuracy
+//! impact shows up as the increase of the logical error rate in the benchmark.
+//!
+//! Similarly, [`BlossomSizeCap`] emulates a hardware that tracks at most a given number of children (the length of
+//! the odd cycle) per blossom. Refusing to create a larger blossom and growing on is not an option: the odd cycle is
+//! already tight, so any further growth of its nodes violates the edge constraints. The capped shot therefore takes
+//! the same fallback as an overflowed budget. The profiler reports the histogram of the largest blossom per shot, from
+//! which the overflow rate of every smaller cap can be read at once.
+//!
+
+use crate::pinned_matching::*;
+use crate::resources::*;
+use fusion_blossom::util::*;
+use micro_blossom_nostd::primal_nodes::*;
+use micro_blossom_nostd::util::*;
+use serde::Serialize;
+use serde_json::json;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BlossomBudgetStatistics {
+    pub shots: usize,
+    /// the number of shots that need more blossoms than the budget
+    pub overflowed_shots: usize,
+    /// the number of defects matched by the greedy fallback
+    pub fallback_defects: usize,
+    /// the number of shots that create a given number of blossoms; an overflowed shot stops at `budget + 1`
+    pub blossom_histogram: BTreeMap<usize, usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BlossomBudget {
+    /// the maximum number of blossoms in a shot
+    pub budget: usize,
+    /// whether the current shot has run out of blossoms
+    pub overflowed: bool,
+    pub statistics: BlossomBudgetStatistics,
+}
+
+impl BlossomBudget {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            overflowed: false,
+            statistics: BlossomBudgetStatistics::default(),
+        }
+    }
+
+    /// prepare for the next shot
+    pub fn clear(&mut self) {
+        self.overflowed = false;
+    }
+
+    /// called after every resolved obstacle; returns whether the shot has run out of blossoms
+    pub fn check(&mut self, count_blossoms: usize) -> bool {
+        self.overflowed |= count_blossoms > self.budget;
+        self.overflowed
+    }
+
+    /// record the statistics of the shot, where `fallback_defects` are matched greedily after an overflow
+    pub fn finish(&mut self, count_blossoms: usize, fallback_defects: usize) {
+        if self.overflowed {
+            self.statistics.overflowed_shots += 1;
+            self.statistics.fallback_defects += fallback_defects;
+        }
+        self.statistics.shots += 1;
+        *self.statistics.blossom_histogram.entry(count_blossoms).or_default() += 1;
+    }
+
+    pub fn generate_report(&self) -> serde_json::Value {
+        json!({
+            "budget": self.budget,
+            "shots": self.statistics.shots,
+            "overflowed_shots": self.statistics.overflowed_shots,
+            "overflow_rate": self.statistics.overflowed_shots as f64 / self.statistics.shots.max(1) as f64,
+            "fallback_defects": self.statistics.fallback_defects,
+            "blossom_histogram": self.statistics.blossom_histogram,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BlossomSizeCapStatistics {
+    pub shots: usize,
+    /// the number of shots that create a blossom with more children than the cap
+    pub overflowed_shots: usize,
+    /// the number of defects matched by the greedy fallback
+    pub fallback_defects: usize,
+    /// the number of shots whose largest blossom has a given number of children, 0 if no blossom is created;
+    /// an overflowed shot stops at its first blossom beyond the cap
+    pub max_children_histogram: BTreeMap<usize, usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BlossomSizeCap {
+    /// the maximum number of children of a blossom
+    pub cap: usize,
+    /// whether the current shot has created a blossom beyond the cap
+    pub overflowed: bool,
+    /// the number of children of the largest blossom in the current shot
+    pub max_children: usize,
+    /// the blossoms before this index have been checked in the current shot
+    checked_blossoms: usize,
+    pub statistics: BlossomSizeCapStatistics,
+}
+
+impl BlossomSizeCap {
+    pub fn new(cap: usize) -> Self {
+        assert!(cap >= 3, "a blossom has at least 3 children");
+        Self {
+            cap,
+            overflowed: false,
+            max_children: 0,
+            checked_blossoms: 0,
+            statistics: BlossomSizeCapStatistics::default(),
+        }
+    }
+
+    /// prepare for the next shot
+    pub fn clear(&mut self) {
+        self.overflowed = false;
+        self.max_children = 0;
+        self.checked_blossoms = 0;
+    }
+
+    /// called after every resolved obstacle to check the newly created blossoms; returns whether the shot has
+    /// created a blossom beyond the cap
+    pub fn check<const N: usize>(&mut self, nodes: &PrimalNodes<N>) -> bool {
+        for blossom_index in nodes.blossom_begin + self.checked_blossoms..nodes.blossom_begin + nodes.count_blossoms {
+            let blossom_index = ni!(blossom_index);
+            if !nodes.has_node(blossom_index) {
+                continue; // already expanded
+            }
+            let mut children = 0;
+            nodes.iterate_blossom_children(blossom_index, |_, _| children += 1);
+            self.max_children = self.max_children.max(children);
+        }
+        self.checked_blossoms = nodes.count_blossoms;
+        self.overflowed |= self.max_children > self.cap;
+        self.overflowed
+    }
+
+    /// record the statistics of the shot, where `fallback_defects` are matched greedily after an overflow
+    pub fn finish(&mut self, fallback_defects: usize) {
+        if self.overflowed {
+            self.statistics.overflowed_shots += 1;
+            self.statistics.fallback_defects += fallback_defects;
+        }
+        self.statistics.shots += 1;
+        *self.statistics.max_children_histogram.entry(self.max_children).or_default() += 1;
+    }
+
+    pub fn generate_report(&self) -> serde_json::Value {
+        json!({
+            "cap": self.cap,
+            "shots": self.statistics.shots,
+            "overflowed_shots": self.statistics.overflowed_shots,
+            "overflow_rate": self.statistics.overflowed_shots as f64 / self.statistics.shots.max(1) as f64,
+            "fallback_defects": self.statistics.fallback_defects,
+            "max_children_histogram": self.statistics.max_children_histogram,
+        })
+    }
+}
+
+/// the shortest-path distance from `source` to every vertex
+fn distances_from(graph: &MicroBlossomSingle, neighbors: &[Vec<(VertexIndex, Weight)>], source: VertexIndex) -> Vec<Weight> {
+    let mut distance = vec![Weight::MAX; graph.vertex_num];
+    let mut queue = BinaryHeap::new();
+    distance[source] = 0;
+    queue.push(Reverse((0, source)));
+    while let Some(Reverse((vertex_distance, vertex))) = queue.pop() {
+        if vertex_distance > distance[vertex] {
+            continue; // outdated entry
+        }
+        for &(peer, weight) in neighbors[vertex].iter() {
+            if vertex_distance + weight < distance[peer] {
+                distance[peer] = vertex_distance + weight;
+                queue.push(Reverse((distance[peer], peer)));
+            }
+        }
+    }
+    distance
+}
+
+/// match the defects greedily in the order of increasing distance, either in pairs or to the nearest virtual vertex;
+/// this is the fallback of a truncated solve, e.g., when the blossom budget or the blossom size cap overflows
+pub fn greedy_matching(graph: &MicroBlossomSingle, defect_vertices: &[VertexIndex]) -> PinnedMatching {
+    let mut neighbors = vec![vec![]; graph.vertex_num];
+    for edge in graph.weighted_edges.iter() {
+        neighbors[edge.l].push((edge.r, edge.w as Weight));
+        neighbors[edge.r].push((edge.l, edge.w as Weight));
+    }
+    // candidates of `(distance, vertex, target, is_virtual)`
+    let mut candidates = vec![];
+    for (index, &vertex_index) in defect_vertices.iter().enumerate() {
+        let distance = distances_from(graph, &neighbors, vertex_index);
+        for &peer in defect_vertices[index + 1..].iter() {
+            if distance[peer] != Weight::MAX {
+                candidates.push((distance[peer], vertex_index, peer, false));
+            }
+        }
+        if let Some(&virtual_vertex) = graph
+            .virtual_vertices
+            .iter()
+            .filter(|&&virtual_vertex| distance[virtual_vertex] != Weight::MAX)
+            .min_by_key(|&&virtual_vertex| distance[virtual_vertex])
+        {
+            candidates.push((distance[virtual_vertex], vertex_index, virtual_vertex, true));
+        }
+    }
+    candidates.sort();
+    let mut matched = BTreeSet::new();
+    let mut peer_matchings = vec![];
+    let mut virtual_matchings = vec![];
+    for (_, vertex_index, target, is_virtual) in candidates {
+        if matched.contains(&vertex_index) || (!is_virtual && matched.contains(&target)) {
+            continue;
+        }
+        matched.insert(vertex_index);
+        if is_virtual {
+            virtual_matchings.push((vertex_index, target));
+        } else {
+            matched.insert(target);
+            peer_matchings.push((vertex_index, target));
+        }
+    }
+    assert_eq!(matched.len(), defect_vertices.len(), "some defects cannot be matched");
+    PinnedMatching {
+        peer_matchings,
+        virtual_matchings,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwpm_solver::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::*;
+
+    #[test]
+    fn blossom_budget_greedy_matching() {
+        // cargo test blossom_budget_greedy_matching -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        for seed in 0..20 {
+            let defect_vertices = code.generate_random_errors(seed).defect_vertices;
+            let matching = greedy_matching(&graph, &defect_vertices);
+            let mut matched: Vec<VertexIndex> = matching.defect_vertices().collect();
+            matched.sort();
+            let mut expected = defect_vertices.clone();
+            expected.sort();
+            assert_eq!(matched, expected, "every defect is matched exactly once");
+            for (_, virtual_vertex) in matching.virtual_matchings.iter() {
+                assert!(graph.virtual_vertices.contains(virtual_vertex));
+            }
+        }
+    }
+
+    /// a smaller budget never decreases the weight, and a budget of 0 forbids any blossom
+    #[test]
+    fn blossom_budget_fallback() {
+        // cargo test blossom_budget_fallback -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(11, 0.1, 500);
+        let initializer = code.get_initializer();
+        let graph = MicroBlossomSingle::new_code(&code);
+        let mut solver = SolverSerial::new(&initializer);
+        let mut budget_solver = SolverEmbeddedComb::new(graph, json!({ "blossom_budget": 0 }));
+        let mut overflowed = 0;
+        for seed in 0..100 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            solver.solve(&syndrome_pattern);
+            budget_solver.solve(&syndrome_pattern);
+            let subgraph = budget_solver.subgraph();
+            let defects: BTreeSet<VertexIndex> = syndrome_pattern.defect_vertices.iter().cloned().collect();
+            assert_eq!(initializer.syndrome_of(&subgraph), defects);
+            assert!(budget_solver.sum_dual_variables() >= solver.sum_dual_variables());
+            let budget = budget_solver.blossom_budget.as_ref().unwrap();
+            if budget.overflowed {
+                overflowed += 1;
+            } else {
+                assert_eq!(budget_solver.sum_dual_variables(), solver.sum_dual_variables());
+            }
+            solver.clear();
+            budget_solver.clear();
+        }
+        let budget = budget_solver.blossom_budget.as_ref().unwrap();
+        println!("{}", budget.generate_report());
+        assert!(overflowed > 0, "the test should create at least one blossom");
+        assert_eq!(budget.statistics.overflowed_shots, overflowed);
+        assert_eq!(budget.statistics.shots, 100);
+        assert!(budget.statistics.blossom_histogram.keys().all(|&count| count <= 1));
+    }
+
+    /// a capped shot overflows exactly when the uncapped solve creates a blossom beyond the cap
+    #[test]
+    fn blossom_budget_size_cap() {
+        // cargo test blossom_budget_size_cap -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(11, 0.1, 500);
+        let initializer = code.get_initializer();
+        let graph = MicroBlossomSingle::new_code(&code);
+        let mut uncapped_solver = SolverEmbeddedComb::new(graph.clone(), json!({ "max_blossom_children": usize::MAX }));
+        let mut capped_solver = SolverEmbeddedComb::new(graph, json!({ "max_blossom_children": 3 }));
+        for seed in 0..200 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            uncapped_solver.solve(&syndrome_pattern);
+            capped_solver.solve(&syndrome_pattern);
+            let subgraph = capped_solver.subgraph();
+            let defects: BTreeSet<VertexIndex> = syndrome_pattern.defect_vertices.iter().cloned().collect();
+            assert_eq!(initializer.syndrome_of(&subgraph), defects);
+            uncapped_solver.subgraph();
+            let uncapped = uncapped_solver.blossom_size_cap.as_ref().unwrap();
+            let capped = capped_solver.blossom_size_cap.as_ref().unwrap();
+            assert!(!uncapped.overflowed);
+            assert_eq!(capped.overflowed, uncapped.max_children > 3);
+            uncapped_solver.clear();
+            capped_solver.clear();
+        }
+        let uncapped = uncapped_solver.blossom_size_cap.as_ref().unwrap();
+        let capped = capped_solver.blossom_size_cap.as_ref().unwrap();
+        println!("{}", uncapped.generate_report());
+        println!("{}", capped.generate_report());
+        let beyond_cap: usize = uncapped
+            .statistics
+            .max_children_histogram
+            .range(4..)
+            .map(|(_, count)| count)
+            .sum();
+        assert_eq!(capped.statistics.overflowed_shots, beyond_cap);
+        assert!(uncapped.statistics.max_children_histogram.contains_key(&3));
+    }
+}
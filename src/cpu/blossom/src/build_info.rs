@@ -0,0 +1,235 @@
+//! Build Info
+//!
+//! Report what this binary supports: the features it is compiled with, the primal-dual types it can build and
+//! the external tools found on this machine. The Scala based dual modules (`embedded-scala`, `embedded-looper` and
+//! `embedded-axi4`) are always compiled but only work when a Java runtime and the assembled jar are available,
+//! which is the most common cause of failures when running them on a new machine.
+//!
+
+use crate::cli::PrimalDualType;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// the jar package built by `sbt assembly`, relative to the repository root; see [`crate::util::ScalaMicroBlossomRunner`]
+pub const SCALA_JAR_PATH: &str = "target/scala-2.12/microblossom.jar";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FeatureInfo {
+    pub name: &'static str,
+    pub enabled: bool,
+    pub description: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PrimalDualInfo {
+    pub name: String,
+    /// whether it accepts a non-empty `--primal-dual-config`
+    pub configurable: bool,
+    /// whether it supports fusing layers round by round, i.e. `SolverTrackedDual::fuse_layer`
+    pub layer_fusion: bool,
+    /// whether the dual module runs in a Scala simulator, which requires Java and the assembled jar
+    pub requires_scala: bool,
+    /// whether it is usable on this machine
+    pub available: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolInfo {
+    pub name: &'static str,
+    pub detected: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub features: Vec<FeatureInfo>,
+    pub primal_dual_types: Vec<PrimalDualInfo>,
+    pub tools: Vec<ToolInfo>,
+}
+
+impl PrimalDualType {
+    pub fn configurable(&self) -> bool {
+        !matches!(self, Self::PrimalEmbedded | Self::DualComb)
+    }
+
+    pub fn layer_fusion(&self) -> bool {
+        matches!(
+            self,
+            Self::EmbeddedComb
+                | Self::EmbeddedScala
+                | Self::EmbeddedLooper
+                | Self::EmbeddedAxi4
+                | Self::EmbeddedCombJitter
+                | Self::EmbeddedCombTrace
+        )
+    }
+
+    pub fn requires_scala(&self) -> bool {
+        matches!(self, Self::EmbeddedScala | Self::EmbeddedLooper | Self::EmbeddedAxi4)
+    }
+}
+
+impl BuildInfo {
+    /// the features are detected at compile time, while the tools are probed when calling this function
+    pub fn detect() -> Self {
+        let tools = detect_tools();
+        let scala_available = tools
+            .iter()
+            .filter(|tool| tool.name == "java" || tool.name == "microblossom.jar")
+            .all(|tool| tool.detected);
+        let primal_dual_types = PrimalDualType::value_variants()
+            .iter()
+            .map(|primal_dual_type| PrimalDualInfo {
+                name: primal_dual_type.to_possible_value().unwrap().get_name().to_string(),
+                configurable: primal_dual_type.configurable(),
+                layer_fusion: primal_dual_type.layer_fusion(),
+                requires_scala: primal_dual_type.requires_scala(),
+                available: !primal_dual_type.requires_scala() || scala_available,
+            })
+            .collect();
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            features: compiled_features(),
+            primal_dual_types,
+            tools,
+        }
+    }
+
+    pub fn print(&self) {
+        println!("micro-blossom v{}", self.version);
+        println!("features:");
+        for feature in self.features.iter() {
+            let mark = if feature.enabled { "+" } else { "-" };
+            println!("    {mark} {:<16} {}", feature.name, feature.description);
+        }
+        println!("primal-dual types:");
+        for info in self.primal_dual_types.iter() {
+            let mut capabilities = vec![];
+            if info.configurable {
+                capabilities.push("config");
+            }
+            if info.layer_fusion {
+                capabilities.push("layer-fusion");
+            }
+            if info.requires_scala {
+                capabilities.push("scala");
+            }
+            let status = if info.available { "available" } else { "unavailable" };
+            println!("    {:<22} {:<12} [{}]", info.name, status, capabilities.join(", "));
+        }
+        println!("tools:");
+        for tool in self.tools.iter() {
+            let mark = if tool.detected { "+" } else { "-" };
+            println!("    {mark} {:<16} {}", tool.name, tool.detail);
+        }
+    }
+}
+
+fn compiled_features() -> Vec<FeatureInfo> {
+    // every feature of this crate in Cargo.toml, as seen by `cfg!`
+    vec![
+        FeatureInfo {
+            name: "compact",
+            enabled: cfg!(feature = "compact"),
+            description: "compact data types in the embedded crate",
+        },
+        FeatureInfo {
+            name: "hls",
+            enabled: cfg!(feature = "hls"),
+            description: "the HLS code path of the primal module",
+        },
+        FeatureInfo {
+            name: "checked_weight",
+            enabled: cfg!(feature = "checked_weight"),
+            description: "saturate on weight overflow and report the shot as a decoding failure",
+        },
+        FeatureInfo {
+            name: "u16_interface_index",
+            enabled: cfg!(feature = "u16_interface_index"),
+            description: "16 bit vertex and node indices between the primal and dual modules, not in the comb registers",
+        },
+        FeatureInfo {
+            name: "async_driver",
+            enabled: cfg!(feature = "async_driver"),
+            description: "pipeline the requests of several contexts of a remote backend",
+        },
+    ]
+}
+
+/// probe an executable by running it with the given arguments; returns the first non-empty line of its output
+fn probe_command(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).stdin(Stdio::null()).output().ok()?;
+    // java prints the version to stderr
+    let text = [output.stdout, output.stderr].concat();
+    let text = String::from_utf8_lossy(&text);
+    Some(
+        text.lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or_default()
+            .to_string(),
+    )
+}
+
+fn detect_tools() -> Vec<ToolInfo> {
+    let mut tools = vec![];
+    for (name, args) in [
+        ("java", ["-version"].as_slice()),
+        ("sbt", ["--script-version"].as_slice()),
+        ("verilator", ["--version"].as_slice()),
+    ] {
+        let version = probe_command(name, args);
+        tools.push(ToolInfo {
+            name,
+            detected: version.is_some(),
+            detail: version.unwrap_or_else(|| "not found in PATH".to_string()),
+        });
+    }
+    let jar_path = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../../../")).join(SCALA_JAR_PATH);
+    let jar_detected = jar_path.exists();
+    tools.push(ToolInfo {
+        name: "microblossom.jar",
+        detected: jar_detected,
+        detail: if jar_detected {
+            jar_path.display().to_string()
+        } else {
+            "not assembled, run `sbt assembly` in the repository root".to_string()
+        },
+    });
+    tools
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_info_detect() {
+        // cargo test build_info_detect -- --nocapture
+        let info = BuildInfo::detect();
+        info.print();
+        assert_eq!(info.primal_dual_types.len(), PrimalDualType::value_variants().len());
+        assert!(info
+            .primal_dual_types
+            .iter()
+            .filter(|info| !info.requires_scala)
+            .all(|info| info.available));
+        let hls = info.features.iter().find(|feature| feature.name == "hls").unwrap();
+        assert_eq!(hls.enabled, cfg!(feature = "hls"));
+        // exactly the features declared in Cargo.toml
+        let manifest = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")).unwrap();
+        let declared: Vec<&str> = (manifest.lines())
+            .skip_while(|line| line.trim() != "[features]")
+            .skip(1)
+            .take_while(|line| !line.starts_with('['))
+            .filter_map(|line| line.split_once(" = ").map(|(name, _)| name.trim()))
+            .filter(|&name| name != "default" && !name.starts_with('#'))
+            .collect();
+        let reported: Vec<&str> = info.features.iter().map(|feature| feature.name).collect();
+        assert_eq!(reported, declared);
+        serde_json::to_string(&info).unwrap();
+    }
+}
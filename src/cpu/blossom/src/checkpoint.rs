@@ -0,0 +1,170 @@
+//! Checkpoint
+//!
+//! Save the state of a solver in the middle of decoding a shot and restore it into a fresh solver, e.g., to recover
+//! from a crash in a long hardware-in-the-loop session, or to fork the state and compare two strategies from the
+//! same point.
+//!
+//! Neither the hardware nor the simulators can write the dual registers directly, so a checkpoint cannot simply be
+//! loaded back. Instead, it records how the state was reached: the loaded defects and the number of steps. The
+//! solver is deterministic, so replaying them into a fresh solver with the same graph and configuration reproduces
+//! the same primal nodes and dual registers. The checkpoint also keeps the full snapshot of the primal nodes and the
+//! dual registers, and the restore fails loudly if the replayed state differs, e.g., when the configuration does not
+//! match or the hardware behaves differently.
+//!
+
+use crate::mwpm_solver::*;
+use crate::pinned_matching::*;
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SolverCheckpoint {
+    pub vertex_num: VertexNum,
+    pub edge_num: usize,
+    /// the defects in the order of loading, which determines the node indices
+    pub defect_vertices: Vec<VertexIndex>,
+    /// the matches pinned before loading the defects
+    pub pinned: PinnedMatching,
+    /// the number of resolved obstacles
+    pub iteration: usize,
+    /// the number of fused layers
+    pub layer_id: usize,
+    /// the snapshot of the solver, including the primal nodes and the dual registers
+    pub snapshot: serde_json::Value,
+}
+
+impl SolverCheckpoint {
+    /// the number of steps to replay after loading the defects, see [`SolverEmbeddedBoxed::step`]
+    pub fn steps(&self) -> usize {
+        self.iteration + self.layer_id
+    }
+
+    pub fn save(&self, filename: &str) -> std::io::Result<()> {
+        std::fs::write(filename, serde_json::to_string(self)?)
+    }
+
+    pub fn load(filename: &str) -> std::io::Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(filename)?)?)
+    }
+}
+
+impl<Dual: SolverTrackedDual> SolverEmbeddedBoxed<Dual> {
+    /// the checkpoint of the current shot; the defects must be loaded by [`Self::load_syndrome`]
+    pub fn checkpoint(&self) -> SolverCheckpoint {
+        SolverCheckpoint {
+            vertex_num: self.graph.vertex_num,
+            edge_num: self.graph.weighted_edges.len(),
+            defect_vertices: self.loaded_defects.clone(),
+            pinned: self.pinned.clone(),
+            iteration: self.iteration,
+            layer_id: self.layer_id,
+            snapshot: self.snapshot(false),
+        }
+    }
+
+    /// clear the solver and replay the checkpoint; panics if the replayed state differs from the checkpoint
+    pub fn restore(&mut self, checkpoint: &SolverCheckpoint) {
+        assert_eq!(
+            checkpoint.vertex_num, self.graph.vertex_num,
+            "checkpoint of a different graph"
+        );
+        assert_eq!(
+            checkpoint.edge_num,
+            self.graph.weighted_edges.len(),
+            "checkpoint of a different graph"
+        );
+        self.clear();
+        self.pin_matching(checkpoint.pinned.clone());
+        self.load_ordered_defects(checkpoint.defect_vertices.clone());
+        for step in 0..checkpoint.steps() {
+            assert!(
+                self.step(),
+                "the solver finished at step {step} before reaching the checkpoint"
+            );
+        }
+        assert_eq!(
+            (self.iteration, self.layer_id),
+            (checkpoint.iteration, checkpoint.layer_id),
+            "the steps are interleaved differently from the checkpoint"
+        );
+        assert!(
+            self.snapshot(false) == checkpoint.snapshot,
+            "the restored state differs from the checkpoint, is the configuration the same?"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::PrimalDualSolver;
+    use serde_json::json;
+
+    /// restore the middle of every shot into a fresh solver, which must then finish with the same subgraph
+    #[test]
+    fn checkpoint_restore() {
+        // cargo test checkpoint_restore -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 4, 0.03, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        for primal_dual_config in [
+            json!({}),
+            json!({ "dual": { "sim_config": { "support_offloading": true, "support_layer_fusion": true } } }),
+        ] {
+            let mut solver = SolverEmbeddedComb::new(graph.clone(), primal_dual_config.clone());
+            let mut restored_solver = SolverEmbeddedComb::new(graph.clone(), primal_dual_config.clone());
+            for seed in 0..20 {
+                let syndrome_pattern = code.generate_random_errors(seed);
+                solver.load_syndrome(&syndrome_pattern);
+                let mut steps = 0;
+                while steps < 5 && solver.step() {
+                    steps += 1;
+                }
+                let checkpoint = solver.checkpoint();
+                assert_eq!(checkpoint.steps(), steps);
+                let checkpoint: SolverCheckpoint =
+                    serde_json::from_str(&serde_json::to_string(&checkpoint).unwrap()).unwrap();
+                restored_solver.restore(&checkpoint);
+                while solver.step() {}
+                while restored_solver.step() {}
+                assert_eq!(solver.subgraph(), restored_solver.subgraph());
+                solver.clear();
+                restored_solver.clear();
+            }
+        }
+    }
+
+    /// fork the state twice from the same checkpoint
+    #[test]
+    fn checkpoint_fork() {
+        // cargo test checkpoint_fork -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), json!({}));
+        solver.load_syndrome(&code.generate_random_errors(1));
+        solver.step();
+        let checkpoint = solver.checkpoint();
+        let mut fork = SolverEmbeddedComb::new(graph, json!({}));
+        for _ in 0..2 {
+            fork.restore(&checkpoint);
+            assert_eq!(fork.checkpoint(), checkpoint);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "the restored state differs from the checkpoint")]
+    fn checkpoint_restore_tampered() {
+        // cargo test checkpoint_restore_tampered -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        code.set_defect_vertices(&[16, 17]);
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), json!({}));
+        solver.load_syndrome(&code.get_syndrome());
+        let mut checkpoint = solver.checkpoint();
+        checkpoint.defect_vertices = vec![16, 18];
+        let mut other_solver = SolverEmbeddedComb::new(graph, json!({}));
+        other_solver.restore(&checkpoint);
+    }
+}
@@ -1,10 +1,31 @@
+use crate::animation::*;
+use crate::build_info::*;
+use crate::dual_module_comb_fan_in::*;
+use crate::edge_heatmap::*;
+use crate::feature_export::*;
+use crate::firmware_graph::*;
+use crate::gallery::*;
+use crate::graph_scaling::*;
+use crate::hardware_config_check::*;
+use crate::instruction_trace::*;
+use crate::latency_calibration::*;
 use crate::mwpm_solver::*;
 use crate::resources::*;
+use crate::result_diff::*;
+use crate::round_decimation::*;
+use crate::soak::*;
+use crate::stim_samples::*;
+use crate::throughput::*;
 use crate::transform_syndromes::*;
 use crate::util::*;
+use crate::vertex_reordering::*;
+use crate::virtual_merging::*;
+use crate::warm_start::*;
+use crate::weight_quantum::*;
 use byteorder::{LittleEndian, WriteBytesExt};
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use fusion_blossom::cli::{ExampleCodeType, RunnableBenchmarkParameters, Verifier};
+use fusion_blossom::example_codes::ExampleCode;
 use fusion_blossom::mwpm_solver::*;
 use fusion_blossom::util::*;
 use fusion_blossom::visualize::VisualizePosition;
@@ -38,14 +59,54 @@ pub struct Cli {
 #[derive(Subcommand, Clone)]
 #[allow(clippy::large_enum_variant)]
 enum Commands {
+    /// export the decoding of a single shot as an animation with captions and interpolation hints
+    Animate(AnimationParameters),
     /// benchmark the speed (and also correctness if enabled)
     Benchmark(BenchmarkParameters),
+    /// fit the latency model to the latencies measured on the board, see [`crate::latency_calibration`]
+    Calibrate(CalibrateParameters),
+    /// check the graph configuration consumed by the hardware generator against the graph, see
+    /// [`crate::hardware_config_check`]
+    CheckHardwareConfig(CheckHardwareConfigParameters),
+    /// approximately decode only every k-th measurement round for fast threshold scans, see
+    /// [`crate::round_decimation`]
+    Decimate(DecimateParameters),
+    /// decode the detection events sampled by Stim in the `b8` or `dets` format, see [`crate::stim_samples`]
+    DecodeStim(DecodeStimParameters),
+    /// compare two benchmark profiles of the same configuration and report the significant changes, see
+    /// [`crate::result_diff`]
+    DiffResults(DiffResultsParameters),
+    /// convert a graph configuration into a Rust file of const tables for the no_std firmware
+    EmitRustGraph(EmitRustGraphParameters),
+    /// export per-shot obstacle features as CSV for training a pre-decoder, see [`crate::feature_export`]
+    ExportFeatures(ExportFeaturesParameters),
+    /// report the fan-in of the signals of the comb model on a graph, see [`crate::dual_module_comb_fan_in`]
+    FanIn(FanInParameters),
+    /// visualize every shot matching a filter, e.g. verification failures, and link them in an index file
+    Gallery(GalleryParameters),
+    /// print the compiled features, the supported primal-dual types and the detected external tools
+    Info {
+        /// print in JSON format
+        #[clap(long, action)]
+        json: bool,
+    },
     Test {
         #[clap(subcommand)]
         command: TestCommands,
     },
     /// parse syndrome file to prepare for Micro Blossom
     Parser(MicroBlossomParserParameters),
+    /// extrapolate a calibrated graph to larger distances, see [`crate::graph_scaling`]
+    ScaleGraph(ScaleGraphParameters),
+    /// decode continuously for hours and report the latency, accuracy and memory drift, see [`crate::soak`]
+    Soak(SoakParameters),
+    /// process instruction traces, see [`crate::instruction_trace`]
+    Trace {
+        #[clap(subcommand)]
+        command: TraceCommands,
+    },
+    /// measure the aggregate throughput of independent shots pipelined through the contexts of one comb driver
+    Throughput(ThroughputParameters),
     /// transform syndrome file to another syndrome file that is more suitable for hardware implementation
     TransformSyndromes {
         #[clap(value_parser)]
@@ -117,6 +178,39 @@ pub struct BenchmarkParameters {
     /// and the u32 array binary syndrome defects for embedding into the memory {name}.defects
     #[clap(long, action)]
     parse_micro_blossom_files: bool,
+    /// decode with every weight divided by this unit, see [`crate::weight_quantum`]
+    #[clap(long)]
+    weight_quantum: Option<Weight>,
+}
+
+/// the example code shared by the commands that sample their own shots
+#[derive(Args, Clone, Debug)]
+pub struct CodeParameters {
+    /// code distance
+    #[clap(value_parser)]
+    pub d: VertexNum,
+    /// physical error rate: the probability of each edge to flip
+    #[clap(value_parser)]
+    pub p: f64,
+    /// rounds of noisy measurement, valid only when multiple rounds
+    #[clap(short = 'n', long, default_value_t = 0)]
+    pub noisy_measurements: VertexNum,
+    /// maximum half weight of edges
+    #[clap(long, default_value_t = 500)]
+    pub max_half_weight: Weight,
+    /// example code type
+    #[clap(short = 'c', long, value_enum, default_value_t = ExampleCodeType::CodeCapacityPlanarCode)]
+    pub code_type: ExampleCodeType,
+    /// the configuration of the code builder
+    #[clap(long, default_value_t = ("{}").to_string())]
+    pub code_config: String,
+}
+
+impl CodeParameters {
+    pub fn build(&self) -> Box<dyn ExampleCode> {
+        let code_config: serde_json::Value = serde_json::from_str(&self.code_config).unwrap();
+        (self.code_type).build(self.d, self.p, self.noisy_measurements, self.max_half_weight, code_config)
+    }
 }
 
 #[derive(Parser, Clone)]
@@ -130,6 +224,18 @@ pub struct MicroBlossomParserParameters {
     /// the u32 array binary syndrome defects for embedding into the memory
     #[clap(long)]
     defects_file: Option<String>,
+    /// partition the offloading units into this many rows of hardware tiles, see [`crate::offloading_regions`]
+    #[clap(long)]
+    offloading_tile_rows: Option<usize>,
+    /// partition the offloading units into this many columns of hardware tiles
+    #[clap(long)]
+    offloading_tile_columns: Option<usize>,
+    /// further split the offloading regions by the layer of the vertices
+    #[clap(long, action)]
+    offloading_split_layers: bool,
+    /// divide every weight by this unit and record it in the graph configuration, see [`crate::weight_quantum`]
+    #[clap(long)]
+    weight_quantum: Option<Weight>,
     /// for some known code, transform can modify the generated graph
     #[clap(subcommand)]
     transform_type: Option<TransformSyndromesType>,
@@ -149,6 +255,10 @@ pub enum PrimalDualType {
     EmbeddedLooper,
     /// embedded primal + Axi4 simulated dual
     EmbeddedAxi4,
+    /// embedded primal + combinatorial dual with random response delays, see [`crate::dual_module_jitter`]
+    EmbeddedCombJitter,
+    /// embedded primal + combinatorial dual recording an instruction trace, see [`crate::dual_module_trace`]
+    EmbeddedCombTrace,
     /// serial primal and dual, standard solution
     Serial,
     /// log error into a file for later fetch
@@ -194,6 +304,8 @@ enum TestCommands {
     EmbeddedCombLayerFusion(StandardTestParameters),
     EmbeddedCombPreMatchingLayerFusion(StandardTestParameters),
     PaperSection7(StandardTestParameters), // alias for `EmbeddedCombPreMatchingLayerFusion`
+    /// load the defects in a random order to catch bugs depending on the order of the node indices
+    EmbeddedCombShuffledDefects(StandardTestParameters),
     EmbeddedScala(StandardTestParameters),
     EmbeddedLooper(StandardTestParameters),
     EmbeddedAxi4(StandardTestParameters),
@@ -254,6 +366,75 @@ impl From<BenchmarkParameters> for fusion_blossom::cli::BenchmarkParameters {
     }
 }
 
+/// the benchmark of fusion-blossom only sees a [`PrimalDualSolver`], so the [`SolverResult`] of every shot is taken
+/// when the solver is cleared, outside of the measured decoding time, and reported as `result` in the solver profile
+/// of `--benchmark-profiler-output`
+pub struct SolverBenchmarked {
+    pub solver: Box<dyn MicroBlossomSolver>,
+    solved: bool,
+    last_result: Option<SolverResult>,
+}
+
+impl SolverBenchmarked {
+    pub fn new(solver: Box<dyn MicroBlossomSolver>) -> Self {
+        Self {
+            solver,
+            solved: false,
+            last_result: None,
+        }
+    }
+}
+
+impl PrimalDualSolver for SolverBenchmarked {
+    fn clear(&mut self) {
+        if self.solved {
+            self.last_result = Some(self.solver.result());
+            self.solved = false;
+        }
+        self.solver.clear();
+    }
+    fn reset_profiler(&mut self) {
+        self.solver.reset_profiler();
+    }
+    fn solve_visualizer(
+        &mut self,
+        syndrome_pattern: &SyndromePattern,
+        visualizer: Option<&mut fusion_blossom::visualize::Visualizer>,
+    ) {
+        self.solver.solve_visualizer(syndrome_pattern, visualizer);
+        self.solved = true;
+    }
+    fn perfect_matching_visualizer(
+        &mut self,
+        visualizer: Option<&mut fusion_blossom::visualize::Visualizer>,
+    ) -> fusion_blossom::primal_module::PerfectMatching {
+        self.solver.perfect_matching_visualizer(visualizer)
+    }
+    fn subgraph_visualizer(&mut self, visualizer: Option<&mut fusion_blossom::visualize::Visualizer>) -> Vec<EdgeIndex> {
+        self.solver.subgraph_visualizer(visualizer)
+    }
+    fn sum_dual_variables(&self) -> Weight {
+        self.solver.sum_dual_variables()
+    }
+    fn generate_profiler_report(&self) -> serde_json::Value {
+        let mut report = self.solver.generate_profiler_report();
+        if let Some(result) = self.last_result.as_ref() {
+            // the subgraph and the correction paths are left out, as they scale with the number of defects
+            report["result"] = json!({
+                "matching_weight": result.matching_weight,
+                "dual_objective": result.dual_objective,
+                "certified": result.certified,
+                "instruction_counts": result.instruction_counts,
+                "clock_cycles": result.clock_cycles,
+                "offloaded": result.offloaded,
+                "weight_overflow": result.weight_overflow,
+                "desynchronized": result.desynchronized,
+            });
+        }
+        report
+    }
+}
+
 impl From<BenchmarkParameters> for RunnableBenchmarkParameters {
     fn from(parameters: BenchmarkParameters) -> Self {
         let mut runnable =
@@ -271,14 +452,22 @@ impl From<BenchmarkParameters> for RunnableBenchmarkParameters {
                     code_config,
                     primal_dual_type,
                     primal_dual_config,
+                    weight_quantum,
                     ..
                 } = parameters;
                 let code_config: serde_json::Value = serde_json::from_str(&code_config).unwrap();
-                let primal_dual_config: serde_json::Value = serde_json::from_str(&primal_dual_config).unwrap();
+                let mut primal_dual_config: serde_json::Value = serde_json::from_str(&primal_dual_config).unwrap();
+                if let Some(weight_quantum) = weight_quantum {
+                    primal_dual_config
+                        .as_object_mut()
+                        .unwrap()
+                        .insert("weight_quantum".to_string(), json!(weight_quantum));
+                }
                 let code = code_type.build(d, p, noisy_measurements, max_half_weight, code_config);
                 let initializer = code.get_initializer();
                 let positions = code.get_positions();
-                runnable.primal_dual_solver = primal_dual_type.build(&initializer, &positions, primal_dual_config);
+                let solver = primal_dual_type.build(&initializer, &positions, primal_dual_config);
+                runnable.primal_dual_solver = Box::new(SolverBenchmarked::new(solver));
             }
         }
         runnable
@@ -381,6 +570,11 @@ impl TestCommands {
                 parameters,
                 json!({"dual":{"sim_config":{"support_offloading":true,"support_layer_fusion":true}}}),
             ),
+            TestCommands::EmbeddedCombShuffledDefects(parameters) => (
+                "embedded-comb",
+                parameters,
+                json!({"dual":{"sim_config":{"support_offloading":true,"support_layer_fusion":true}},"defect_order_seed":0}),
+            ),
             TestCommands::EmbeddedScala(parameters) => ("embedded-scala", parameters, json!({})),
             TestCommands::EmbeddedLooper(parameters) => ("embedded-looper", parameters, json!({})),
             TestCommands::EmbeddedAxi4(parameters) => ("embedded-axi4", parameters, json!({})),
@@ -456,7 +650,43 @@ impl Cli {
                     );
                 }
             }
+            Commands::Calibrate(parameters) => {
+                parameters.run();
+            }
+            Commands::CheckHardwareConfig(parameters) => parameters.run(),
+            Commands::Decimate(parameters) => {
+                parameters.run();
+            }
+            Commands::EmitRustGraph(parameters) => parameters.run(),
+            Commands::ExportFeatures(parameters) => parameters.run(),
+            Commands::DecodeStim(parameters) => parameters.run(),
+            Commands::DiffResults(parameters) => {
+                parameters.run();
+            }
+            Commands::FanIn(parameters) => parameters.run(),
+            Commands::Gallery(parameters) => {
+                parameters.run();
+            }
+            Commands::Animate(parameters) => {
+                parameters.run();
+            }
+            Commands::Throughput(parameters) => {
+                parameters.run();
+            }
+            Commands::Info { json } => {
+                let info = BuildInfo::detect();
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&info).unwrap());
+                } else {
+                    info.print();
+                }
+            }
             Commands::Test { command } => command.run(),
+            Commands::ScaleGraph(parameters) => parameters.run(),
+            Commands::Soak(parameters) => {
+                parameters.run();
+            }
+            Commands::Trace { command } => command.run(),
             Commands::Parser(parameters) => {
                 let code = fusion_blossom::example_codes::ErrorPatternReader::new(json!({
                     "filename": parameters.syndromes_file,
@@ -473,6 +703,18 @@ impl Cli {
                         assert_eq!(original.weighted_edges, micro_blossom.weighted_edges);
                         assert_eq!(original.virtual_vertices, micro_blossom.virtual_vertices);
                     }
+                    if let Some(weight_quantum) = parameters.weight_quantum {
+                        micro_blossom
+                            .quantize_weights(weight_quantum)
+                            .unwrap_or_else(|error| panic!("cannot quantize the weights: {error}"));
+                    }
+                    if parameters.offloading_tile_rows.is_some() || parameters.offloading_tile_columns.is_some() {
+                        micro_blossom.assign_offloading_regions(
+                            parameters.offloading_tile_rows.unwrap_or(1),
+                            parameters.offloading_tile_columns.unwrap_or(1),
+                            parameters.offloading_split_layers,
+                        );
+                    }
                     let json_str = serde_json::to_string(&micro_blossom).unwrap();
                     std::fs::write(graph_file, json_str).unwrap();
                 }
@@ -524,8 +766,72 @@ impl PrimalDualType {
         &self,
         initializer: &SolverInitializer,
         positions: &Vec<VisualizePosition>,
-        primal_dual_config: serde_json::Value,
-    ) -> Box<dyn PrimalDualSolver> {
+        mut primal_dual_config: serde_json::Value,
+    ) -> Box<dyn MicroBlossomSolver> {
+        // optionally accumulate the matched frequency of every edge across the run, e.g.
+        // `{"edge_heatmap":{"csv":"heatmap.csv"}}`, see [`SolverEdgeHeatmap`]
+        if let Some(edge_heatmap) = primal_dual_config
+            .as_object_mut()
+            .and_then(|config| config.remove("edge_heatmap"))
+        {
+            let config: EdgeHeatmapConfig = serde_json::from_value(edge_heatmap).unwrap();
+            let solver = self.build(initializer, positions, primal_dual_config);
+            return Box::new(SolverEdgeHeatmap::new(initializer, positions, solver, config));
+        }
+        // optionally reuse the unchanged components of the previous shot, e.g. `{"warm_start":true}` or
+        // `{"warm_start":"validate"}` to also compare against a full solve, see [`SolverWarmStart`]
+        if let Some(warm_start) = primal_dual_config
+            .as_object_mut()
+            .and_then(|config| config.remove("warm_start"))
+        {
+            let validate = match warm_start {
+                serde_json::Value::Bool(false) => return self.build(initializer, positions, primal_dual_config),
+                serde_json::Value::Bool(true) => false,
+                serde_json::Value::String(mode) if mode == "validate" => true,
+                _ => panic!("warm_start should be a boolean or \"validate\", found {warm_start}"),
+            };
+            let solver = self.build(initializer, positions, primal_dual_config);
+            return Box::new(SolverWarmStart::new(initializer, solver, validate));
+        }
+        // optionally merge the virtual vertices, e.g. `{"merge_virtual":"side"}`, see [`VirtualMerging`]
+        if let Some(policy) = primal_dual_config
+            .as_object_mut()
+            .and_then(|config| config.remove("merge_virtual"))
+        {
+            let policy: VirtualMergePolicy = serde_json::from_value(policy).unwrap();
+            let merging = VirtualMerging::new(policy, initializer, positions);
+            let solver = self.build(
+                &merging.initializer(initializer),
+                &merging.positions(positions),
+                primal_dual_config,
+            );
+            return Box::new(SolverVirtualMerged::new(solver, merging, initializer));
+        }
+        // optionally renumber the vertices for locality, e.g. `{"vertex_order":"bfs"}`, see [`VertexReordering`]
+        if let Some(vertex_order) = primal_dual_config
+            .as_object_mut()
+            .and_then(|config| config.remove("vertex_order"))
+        {
+            let vertex_order: VertexOrder = serde_json::from_value(vertex_order).unwrap();
+            let reordering = VertexReordering::new(vertex_order, initializer, positions);
+            let solver = self.build(
+                &reordering.initializer(initializer),
+                &reordering.positions(positions),
+                primal_dual_config,
+            );
+            return Box::new(SolverReordered::new(solver, reordering));
+        }
+        // optionally divide every weight by a common unit, e.g. `{"weight_quantum":50}`, see [`SolverQuantized`]
+        if let Some(weight_quantum) = primal_dual_config
+            .as_object_mut()
+            .and_then(|config| config.remove("weight_quantum"))
+        {
+            let weight_quantum: Weight = serde_json::from_value(weight_quantum).unwrap();
+            let quantized = quantize_initializer(initializer, weight_quantum)
+                .unwrap_or_else(|error| panic!("cannot quantize the weights: {error}"));
+            let solver = self.build(&quantized, positions, primal_dual_config);
+            return Box::new(SolverQuantized::new(solver, weight_quantum));
+        }
         // create micro blossom single graph configuration
         let graph = MicroBlossomSingle::new(initializer, positions);
         match self {
@@ -533,14 +839,13 @@ impl PrimalDualType {
                 assert_eq!(primal_dual_config, json!({}));
                 Box::new(SolverPrimalEmbedded::new(initializer))
             }
-            Self::DualComb => {
-                assert_eq!(primal_dual_config, json!({}));
-                Box::new(SolverDualComb::new(initializer))
-            }
+            Self::DualComb => Box::new(SolverDualComb::new_with_config(graph, primal_dual_config)),
             Self::EmbeddedComb => Box::new(SolverEmbeddedComb::new(graph, primal_dual_config)),
             Self::EmbeddedScala => Box::new(SolverEmbeddedScala::new(graph, primal_dual_config)),
             Self::EmbeddedLooper => Box::new(SolverEmbeddedLooper::new(graph, primal_dual_config)),
             Self::EmbeddedAxi4 => Box::new(SolverEmbeddedAxi4::new(graph, primal_dual_config)),
+            Self::EmbeddedCombJitter => Box::new(SolverEmbeddedCombJitter::new(graph, primal_dual_config)),
+            Self::EmbeddedCombTrace => Box::new(SolverEmbeddedCombTrace::new(graph, primal_dual_config)),
             Self::Serial | Self::ErrorPatternLogger => {
                 unreachable!()
             }
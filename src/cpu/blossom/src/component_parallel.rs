@@ -0,0 +1,490 @@
+//! Component Parallel
+//!
+//! The host-side coordinator of several Micro Blossom devices (different FPGA boards) serving a decoding graph made
+//! of independent connected components, e.g., many logical qubits decoded together.
+//! The graph is partitioned into groups of whole connected components and each device gets its own subgraph and
+//! primal module. The devices decode in lockstep round by round: every device fuses its own layers of a measurement
+//! round before any device moves on to the next round, so that none of them runs ahead in streaming decoding.
+//! Finally, the subgraphs of all the devices are aggregated back into the global edge indices.
+//!
+//! Different components never interact in a minimum-weight perfect matching, so the aggregated result is exactly the
+//! one of a single large device, and the devices never exchange any message. This is not a multi-FPGA client for a
+//! single code that is too large for one device: cutting a connected component across devices requires exchanging the
+//! vertex states along the cut in every clock cycle, which is only emulated in the RTL model by
+//! [`crate::dual_module_comb_mirror`], where the sync requests across the cut are counted in
+//! [`crate::dual_module_comb_mirror::MirrorStatistics`].
+//!
+//! The virtual vertices on the boundary between components are mirrored, i.e., copied into every partition that
+//! uses them. Each vertex has exactly one owner in [`BoundaryOwnership`], and a defect is only routed to the owner
+//! of its vertex, so that a defect on a mirrored vertex is neither counted twice nor dropped silently: the owner
+//! handles it exactly as a single device would, e.g., by its defect sanitizer.
+//!
+
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use fusion_blossom::mwpm_solver::PrimalDualSolver;
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone)]
+pub struct GraphPartition {
+    pub graph: MicroBlossomSingle,
+    /// the global index of every local vertex, in increasing order
+    pub vertices: Vec<VertexIndex>,
+    /// the global index of every local edge
+    pub edges: Vec<EdgeIndex>,
+}
+
+/// the single device that a defect on each vertex is routed to
+#[derive(Debug, Clone)]
+pub struct BoundaryOwnership {
+    /// the owner device and the local index of every vertex, `None` if the vertex is in no partition
+    pub owners: Vec<Option<(usize, VertexIndex)>>,
+    /// the devices holding a copy of every mirrored vertex, i.e., a vertex in more than one partition
+    pub mirrors: BTreeMap<VertexIndex, Vec<usize>>,
+}
+
+impl BoundaryOwnership {
+    /// a mirrored vertex is owned by the device with the most edges incident to it, and the lowest device id among
+    /// ties; the ownership only depends on the graph, so the same boundary defect always goes to the same device
+    pub fn new(graph: &MicroBlossomSingle, partitions: &[GraphPartition]) -> Self {
+        let mut holders = vec![vec![]; graph.vertex_num];
+        for (device_id, partition) in partitions.iter().enumerate() {
+            let mut incident_edges = BTreeMap::<VertexIndex, usize>::new();
+            for edge in partition.graph.weighted_edges.iter() {
+                *incident_edges.entry(edge.l).or_default() += 1;
+                *incident_edges.entry(edge.r).or_default() += 1;
+            }
+            for (local_index, &vertex_index) in partition.vertices.iter().enumerate() {
+                let incident_edges = incident_edges.get(&local_index).cloned().unwrap_or(0);
+                holders[vertex_index].push((device_id, local_index, incident_edges));
+            }
+        }
+        let owners = holders
+            .iter()
+            .map(|holders| {
+                (holders.iter())
+                    .min_by_key(|&&(device_id, _, incident_edges)| (std::cmp::Reverse(incident_edges), device_id))
+                    .map(|&(device_id, local_index, _)| (device_id, local_index))
+            })
+            .collect();
+        let mirrors = holders
+            .iter()
+            .enumerate()
+            .filter(|(_, holders)| holders.len() > 1)
+            .map(|(vertex_index, holders)| (vertex_index, holders.iter().map(|&(device_id, ..)| device_id).collect()))
+            .collect();
+        Self { owners, mirrors }
+    }
+
+    /// the local defect vertices of every device
+    pub fn route(&self, defect_vertices: &[VertexIndex], num_devices: usize) -> Vec<Vec<VertexIndex>> {
+        let mut routed = vec![vec![]; num_devices];
+        for &vertex_index in defect_vertices.iter() {
+            let (device_id, local_index) =
+                self.owners[vertex_index].unwrap_or_else(|| panic!("defect {vertex_index} is not in any partition"));
+            routed[device_id].push(local_index);
+        }
+        routed
+    }
+
+    pub fn is_mirrored(&self, vertex_index: VertexIndex) -> bool {
+        self.mirrors.contains_key(&vertex_index)
+    }
+}
+
+/// group the connected components of the regular vertices into `num_devices` partitions of balanced sizes;
+/// a component is never cut, so there must be at least `num_devices` of them;
+/// a virtual vertex does not connect components and is copied into every partition that uses it
+pub fn partition_components(graph: &MicroBlossomSingle, num_devices: usize) -> Vec<GraphPartition> {
+    assert!(num_devices > 0);
+    let mut is_virtual = vec![false; graph.vertex_num];
+    for &vertex_index in graph.virtual_vertices.iter() {
+        is_virtual[vertex_index] = true;
+    }
+    // union-find over the edges between regular vertices
+    let mut parent: Vec<usize> = (0..graph.vertex_num).collect();
+    fn find(parent: &mut [usize], mut vertex_index: usize) -> usize {
+        while parent[vertex_index] != vertex_index {
+            parent[vertex_index] = parent[parent[vertex_index]];
+            vertex_index = parent[vertex_index];
+        }
+        vertex_index
+    }
+    for edge in graph.weighted_edges.iter() {
+        if !is_virtual[edge.l] && !is_virtual[edge.r] {
+            let (left, right) = (find(&mut parent, edge.l), find(&mut parent, edge.r));
+            parent[left] = right;
+        }
+    }
+    let mut components = BTreeMap::<usize, Vec<usize>>::new();
+    for vertex_index in (0..graph.vertex_num).filter(|&index| !is_virtual[index]) {
+        let root = find(&mut parent, vertex_index);
+        components.entry(root).or_default().push(vertex_index);
+    }
+    assert!(
+        components.len() >= num_devices,
+        "only {} connected components for {num_devices} devices",
+        components.len()
+    );
+    // assign the largest component to the least loaded device
+    let mut components: Vec<Vec<usize>> = components.into_values().collect();
+    components.sort_by_key(|component| std::cmp::Reverse(component.len()));
+    let mut device_of = vec![usize::MAX; graph.vertex_num];
+    let mut loads = vec![0; num_devices];
+    for component in components.iter() {
+        let device_id = (0..num_devices).min_by_key(|&device_id| loads[device_id]).unwrap();
+        loads[device_id] += component.len();
+        for &vertex_index in component.iter() {
+            device_of[vertex_index] = device_id;
+        }
+    }
+    (0..num_devices)
+        .map(|device_id| {
+            let edges: Vec<EdgeIndex> = (0..graph.weighted_edges.len())
+                .filter(|&edge_index| {
+                    let edge = &graph.weighted_edges[edge_index];
+                    device_of[edge.l] == device_id || device_of[edge.r] == device_id
+                })
+                .collect();
+            let mut is_local = vec![false; graph.vertex_num];
+            for &edge_index in edges.iter() {
+                let edge = &graph.weighted_edges[edge_index];
+                is_local[edge.l] = true;
+                is_local[edge.r] = true;
+            }
+            for vertex_index in 0..graph.vertex_num {
+                is_local[vertex_index] |= device_of[vertex_index] == device_id; // isolated vertices
+            }
+            let vertices: Vec<VertexIndex> = (0..graph.vertex_num).filter(|&index| is_local[index]).collect();
+            let local_index: BTreeMap<usize, usize> = vertices
+                .iter()
+                .enumerate()
+                .map(|(local_index, &vertex_index)| (vertex_index, local_index))
+                .collect();
+            let initializer = SolverInitializer::new(
+                vertices.len(),
+                edges
+                    .iter()
+                    .map(|&edge_index| {
+                        let edge = &graph.weighted_edges[edge_index];
+                        (local_index[&edge.l], local_index[&edge.r], edge.w)
+                    })
+                    .collect(),
+                vertices
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &vertex_index)| is_virtual[vertex_index])
+                    .map(|(local_index, _)| local_index)
+                    .collect(),
+            );
+            let positions: Vec<VisualizePosition> = vertices
+                .iter()
+                .map(|&vertex_index| {
+                    let position = &graph.positions[vertex_index];
+                    VisualizePosition::new(position.i, position.j, position.t)
+                })
+                .collect();
+            GraphPartition {
+                graph: MicroBlossomSingle::new(&initializer, &positions),
+                vertices,
+                edges,
+            }
+        })
+        .collect()
+}
+
+/// decode independent connected components on several devices, see the module documentation for the scope
+pub struct SolverComponentParallel<Dual: SolverTrackedDual> {
+    pub devices: Vec<SolverEmbeddedBoxed<Dual>>,
+    pub partitions: Vec<GraphPartition>,
+    pub ownership: BoundaryOwnership,
+    /// `layer_times[device][layer]`: the time of every fusion layer of the device, used to synchronize the devices
+    layer_times: Vec<Vec<f64>>,
+    /// `round_latencies[round][device]` in seconds
+    pub round_latencies: Vec<Vec<f64>>,
+    /// the number of layers fused on each device; it is local to the device and involves no message between devices
+    pub fused_layers: Vec<usize>,
+    /// the number of defects on mirrored vertices, each routed to its owner only
+    pub boundary_defects: usize,
+}
+
+impl<Dual: SolverTrackedDual> SolverComponentParallel<Dual> {
+    /// every device shares the same `primal_dual_config`
+    pub fn new(graph: &MicroBlossomSingle, num_devices: usize, primal_dual_config: serde_json::Value) -> Self {
+        let partitions = partition_components(graph, num_devices);
+        let ownership = BoundaryOwnership::new(graph, &partitions);
+        let mut layer_times = vec![];
+        for partition in partitions.iter() {
+            let layer_fusion = partition.graph.layer_fusion.as_ref().unwrap();
+            layer_times.push(
+                layer_fusion
+                    .layers
+                    .iter()
+                    .map(|vertices| {
+                        vertices
+                            .iter()
+                            .map(|&vertex_index| partition.graph.positions[vertex_index].t)
+                            .fold(f64::INFINITY, f64::min)
+                    })
+                    .collect(),
+            );
+        }
+        let devices = partitions
+            .iter()
+            .map(|partition| SolverEmbeddedBoxed::<Dual>::new(partition.graph.clone(), primal_dual_config.clone()))
+            .collect();
+        Self {
+            devices,
+            partitions,
+            ownership,
+            layer_times,
+            round_latencies: vec![],
+            fused_layers: vec![0; num_devices],
+            boundary_defects: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for device in self.devices.iter_mut() {
+            device.clear();
+        }
+        self.round_latencies.clear();
+    }
+
+    pub fn solve(&mut self, syndrome_pattern: &SyndromePattern) {
+        let defect_vertices = (self.ownership).route(&syndrome_pattern.defect_vertices, self.devices.len());
+        self.boundary_defects += (syndrome_pattern.defect_vertices.iter())
+            .filter(|&&vertex_index| self.ownership.is_mirrored(vertex_index))
+            .count();
+        for (device, defect_vertices) in self.devices.iter_mut().zip(defect_vertices) {
+            device.load_syndrome(&SyndromePattern::new_vertices(defect_vertices));
+        }
+        // every round is fused on all the devices that own a layer at this time
+        let mut round_times: Vec<f64> = self.layer_times.iter().flatten().cloned().collect();
+        round_times.sort_by(f64::total_cmp);
+        round_times.dedup();
+        let mut next_layers = vec![0; self.devices.len()];
+        for round_time in round_times {
+            let mut latencies = vec![0.; self.devices.len()];
+            for (device_id, device) in self.devices.iter_mut().enumerate() {
+                let begin = std::time::Instant::now();
+                let layer_times = &self.layer_times[device_id];
+                while next_layers[device_id] < layer_times.len() && layer_times[next_layers[device_id]] <= round_time {
+                    if device.step_round() {
+                        self.fused_layers[device_id] += 1;
+                    }
+                    next_layers[device_id] += 1;
+                }
+                latencies[device_id] = begin.elapsed().as_secs_f64();
+            }
+            self.round_latencies.push(latencies);
+        }
+        // resolve the remaining obstacles, or all of them if layer fusion is disabled
+        let mut latencies = vec![0.; self.devices.len()];
+        for (device_id, device) in self.devices.iter_mut().enumerate() {
+            let begin = std::time::Instant::now();
+            while device.step() {}
+            device.finish();
+            latencies[device_id] = begin.elapsed().as_secs_f64();
+        }
+        self.round_latencies.push(latencies);
+    }
+
+    /// the aggregated subgraph in the global edge indices
+    pub fn subgraph(&mut self) -> Vec<EdgeIndex> {
+        let mut subgraph: Vec<EdgeIndex> = self
+            .devices
+            .iter_mut()
+            .zip(self.partitions.iter())
+            .flat_map(|(device, partition)| {
+                device
+                    .subgraph()
+                    .into_iter()
+                    .map(|edge_index| partition.edges[edge_index])
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        subgraph.sort();
+        subgraph
+    }
+
+    pub fn generate_profiler_report(&self) -> serde_json::Value {
+        // the devices run in parallel, so a round takes as long as the slowest device
+        let round_latency: Vec<f64> = self
+            .round_latencies
+            .iter()
+            .map(|latencies| latencies.iter().cloned().fold(0., f64::max))
+            .collect();
+        json!({
+            "round_latency": round_latency,
+            "device_round_latency": self.round_latencies,
+            "fused_layers": self.fused_layers,
+            "boundary_defects": self.boundary_defects,
+            "devices": self.devices.iter().map(|device| device.generate_profiler_report()).collect::<Vec<_>>(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dual_module_comb::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::SolverSerial;
+    use fusion_blossom::primal_module::SubGraphBuilder;
+
+    /// place several copies of the code side by side as a single graph, e.g., multiple logical qubits
+    fn multiple_patches(code: &dyn ExampleCode, copies: usize) -> (SolverInitializer, Vec<VisualizePosition>) {
+        let initializer = code.get_initializer();
+        let positions = code.get_positions();
+        let width = positions.iter().map(|position| position.j).fold(0., f64::max) + 2.;
+        let vertex_num = initializer.vertex_num;
+        let mut weighted_edges = vec![];
+        let mut virtual_vertices = vec![];
+        let mut all_positions = vec![];
+        for copy in 0..copies {
+            let offset = copy * vertex_num;
+            weighted_edges.extend(
+                initializer
+                    .weighted_edges
+                    .iter()
+                    .map(|&(l, r, w)| (l + offset, r + offset, w)),
+            );
+            virtual_vertices.extend(initializer.virtual_vertices.iter().map(|&vertex_index| vertex_index + offset));
+            all_positions.extend(
+                positions
+                    .iter()
+                    .map(|position| VisualizePosition::new(position.i, position.j + copy as f64 * width, position.t)),
+            );
+        }
+        let initializer = SolverInitializer::new(vertex_num * copies, weighted_edges, virtual_vertices);
+        (initializer, all_positions)
+    }
+
+    #[test]
+    fn component_parallel_partition() {
+        // cargo test component_parallel_partition -- --nocapture
+        let code = CodeCapacityPlanarCode::new(5, 0.1, 500);
+        let (initializer, positions) = multiple_patches(&code, 3);
+        let graph = MicroBlossomSingle::new(&initializer, &positions);
+        let partitions = partition_components(&graph, 2);
+        // two patches on one device and one patch on the other
+        let vertex_num = code.get_initializer().vertex_num;
+        let mut sizes: Vec<usize> = partitions.iter().map(|partition| partition.vertices.len()).collect();
+        sizes.sort();
+        assert_eq!(sizes, vec![vertex_num, 2 * vertex_num]);
+        let mut edges: Vec<EdgeIndex> = partitions.iter().flat_map(|partition| partition.edges.clone()).collect();
+        edges.sort();
+        assert_eq!(edges, (0..graph.weighted_edges.len()).collect::<Vec<_>>());
+        for partition in partitions.iter() {
+            for (local_index, edge) in partition.graph.weighted_edges.iter().enumerate() {
+                let global = &graph.weighted_edges[partition.edges[local_index]];
+                assert_eq!((partition.vertices[edge.l], partition.vertices[edge.r]), (global.l, global.r));
+            }
+        }
+    }
+
+    #[test]
+    fn component_parallel_decode() {
+        // cargo test component_parallel_decode -- --nocapture
+        for (mut code, config) in [
+            (
+                Box::new(CodeCapacityPlanarCode::new(5, 0.1, 500)) as Box<dyn ExampleCode>,
+                json!({}),
+            ),
+            (
+                Box::new(PhenomenologicalRotatedCode::new(3, 3, 0.03, 500)),
+                json!({ "dual": { "sim_config": { "support_layer_fusion": true } } }),
+            ),
+        ] {
+            let copies = 4;
+            let (initializer, positions) = multiple_patches(code.as_ref(), copies);
+            let vertex_num = code.get_initializer().vertex_num;
+            let graph = MicroBlossomSingle::new(&initializer, &positions);
+            let mut solver = SolverComponentParallel::<DualModuleCombDriver>::new(&graph, 2, config);
+            let mut standard_solver = SolverSerial::new(&initializer);
+            let mut subgraph_builder = SubGraphBuilder::new(&initializer);
+            for seed in 0..20 {
+                let mut defect_vertices = vec![];
+                for copy in 0..copies {
+                    let syndrome_pattern = code.generate_random_errors((seed * copies + copy) as u64);
+                    defect_vertices.extend(syndrome_pattern.defect_vertices.iter().map(|&v| v + copy * vertex_num));
+                }
+                let syndrome_pattern = SyndromePattern::new_vertices(defect_vertices);
+                solver.solve(&syndrome_pattern);
+                let subgraph = solver.subgraph();
+                standard_solver.solve(&syndrome_pattern);
+                subgraph_builder.load_subgraph(&standard_solver.subgraph());
+                let standard_total_weight = subgraph_builder.total_weight();
+                subgraph_builder.load_subgraph(&subgraph);
+                assert_eq!(subgraph_builder.total_weight(), standard_total_weight);
+                let original_defects = syndrome_pattern.defect_vertices.iter().cloned().collect();
+                assert_eq!(initializer.syndrome_of(&subgraph), original_defects);
+                solver.clear();
+                standard_solver.clear();
+            }
+            println!("{}", solver.generate_profiler_report()["fused_layers"]);
+        }
+    }
+
+    /// two chains joined by the virtual vertex `length` in the middle, which is mirrored into both partitions
+    fn chains_with_shared_boundary(length: usize) -> MicroBlossomSingle {
+        let vertex_num = 2 * length + 1;
+        let weighted_edges = (0..vertex_num - 1)
+            .map(|vertex_index| (vertex_index, vertex_index + 1, 100))
+            .collect();
+        let initializer = SolverInitializer::new(vertex_num, weighted_edges, vec![0, length, vertex_num - 1]);
+        let positions: Vec<VisualizePosition> = (0..vertex_num)
+            .map(|vertex_index| VisualizePosition::new(0., vertex_index as f64, 0.))
+            .collect();
+        MicroBlossomSingle::new(&initializer, &positions)
+    }
+
+    #[test]
+    fn component_parallel_boundary_ownership() {
+        // cargo test component_parallel_boundary_ownership -- --nocapture
+        let graph = chains_with_shared_boundary(5);
+        let partitions = partition_components(&graph, 2);
+        let ownership = BoundaryOwnership::new(&graph, &partitions);
+        assert_eq!(ownership.mirrors, BTreeMap::from([(5, vec![0, 1])]));
+        for (vertex_index, owner) in ownership.owners.iter().enumerate() {
+            let (device_id, local_index) = owner.unwrap();
+            assert_eq!(partitions[device_id].vertices[local_index], vertex_index);
+        }
+        // a tie of incident edges goes to the lowest device id
+        assert_eq!(ownership.owners[5].unwrap().0, 0);
+        let routed = ownership.route(&[3, 5, 7], 2);
+        assert_eq!(routed.iter().map(|defects| defects.len()).sum::<usize>(), 3);
+        assert!(routed[0].contains(&ownership.owners[5].unwrap().1));
+    }
+
+    /// a defect on the mirrored vertex is sanitized once by its owner, the same as a single device
+    #[test]
+    fn component_parallel_boundary_defects() {
+        // cargo test component_parallel_boundary_defects -- --nocapture
+        let graph = chains_with_shared_boundary(5);
+        let config = json!({ "sanitize": "Dedupe" });
+        let mut solver = SolverComponentParallel::<DualModuleCombDriver>::new(&graph, 2, config.clone());
+        let mut single_solver = SolverEmbeddedComb::new(graph.clone(), config);
+        let initializer = graph.get_initializer();
+        let mut subgraph_builder = SubGraphBuilder::new(&initializer);
+        for defect_vertices in [vec![2, 5, 7], vec![4, 5, 6], vec![5]] {
+            let syndrome_pattern = SyndromePattern::new_vertices(defect_vertices);
+            solver.solve(&syndrome_pattern);
+            single_solver.solve(&syndrome_pattern);
+            subgraph_builder.load_subgraph(&single_solver.subgraph());
+            let single_total_weight = subgraph_builder.total_weight();
+            subgraph_builder.load_subgraph(&solver.subgraph());
+            assert_eq!(subgraph_builder.total_weight(), single_total_weight);
+            solver.clear();
+            single_solver.clear();
+        }
+        let virtual_defects: usize = (solver.devices.iter())
+            .map(|device| device.sanitizer.as_ref().unwrap().total.virtual_defects)
+            .sum();
+        assert_eq!(virtual_defects, 3, "every boundary defect is counted exactly once");
+        assert_eq!(solver.boundary_defects, 3);
+    }
+}
@@ -0,0 +1,493 @@
+//! Conformance
+//!
+//! Cross-implementation checks for dual modules. A new backend (e.g., a Verilator simulation, an AXI4 bus or a GPU)
+//! is conformant if it behaves exactly like the combinatorial reference [`DualModuleCombDriver`]. Both dual modules
+//! are driven by their own embedded primal module in lockstep, so they receive the same instructions as long as they
+//! report the same obstacles. Three properties are checked on a few hand-picked scenarios and on random shots:
+//!
+//! - instruction semantics: the same instructions lead to the same kind of obstacle and the same growth
+//! - arbitration order: among multiple simultaneous obstacles, the same one is reported
+//! - obstacle correctness: resolving the reported obstacles leads to a minimum-weight perfect matching
+//!
+//! A backend proves equivalence by calling `run_conformance::<D>()` in its tests. Layer fusion is not covered,
+//! because the lockstep loop resolves obstacles without fusing layers.
+//!
+//! Similarly, [`run_primal_conformance`] checks the embedded primal module against the serial primal module of
+//! fusion-blossom, so that refactoring the primal module (e.g., recycling node indices) cannot silently change its
+//! decisions.
+//!
+
+use crate::decision_trace::*;
+use crate::dual_module_comb::*;
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use crate::util::*;
+use fusion_blossom::dual_module::*;
+use fusion_blossom::example_codes::*;
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::pointers::*;
+use fusion_blossom::primal_module::*;
+use fusion_blossom::primal_module_serial::*;
+use fusion_blossom::util::*;
+use micro_blossom_nostd::interface::*;
+use micro_blossom_nostd::util::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// any dual driver that the embedded solver runs on, see [`SolverEmbeddedBoxed`]
+pub trait DualModuleLike: SolverTrackedDual {}
+
+impl<D: SolverTrackedDual> DualModuleLike for D {}
+
+/// the code of all the scenarios; the hand-picked defects are the vertex indices of this code
+pub const CONFORMANCE_CODE_DISTANCE: VertexNum = 7;
+
+/// hand-picked scenarios covering a single defect, matching to the boundary, peers and nested blossoms
+pub const CONFORMANCE_SCENARIOS: &[(&str, &[VertexIndex])] = &[
+    ("empty", &[]),
+    ("single defect", &[16]),
+    ("defect pair", &[16, 26]),
+    ("blossom", &[18, 26, 34]),
+    ("nested blossoms", &[16, 17, 18, 26, 34, 39]),
+    ("multiple conflicts", &[20, 27, 28, 36, 43, 44, 45, 53]),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConformanceConfig {
+    /// the physical error rate of the random shots
+    #[serde(default = "conformance_config_default::p")]
+    pub p: f64,
+    /// the number of random shots, using seeds from 0
+    #[serde(default = "conformance_config_default::shots")]
+    pub shots: usize,
+    /// the configuration of the backend under test; only `dual.sim_config` is shared with the reference
+    #[serde(default = "Default::default")]
+    pub primal_dual_config: serde_json::Value,
+}
+
+impl Default for ConformanceConfig {
+    fn default() -> Self {
+        serde_json::from_value(json!({})).unwrap()
+    }
+}
+
+pub mod conformance_config_default {
+    pub fn p() -> f64 {
+        0.05
+    }
+    pub fn shots() -> usize {
+        20
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConformanceCheck {
+    InstructionSemantics,
+    ArbitrationOrder,
+    ObstacleCorrectness,
+    /// the primal module leaves the dual module in a different state after resolving the same obstacle
+    GrowState,
+    /// the primal module finds a different matching of the same weight
+    Matching,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConformanceFailure {
+    pub check: ConformanceCheck,
+    pub scenario: String,
+    pub defect_vertices: Vec<VertexIndex>,
+    /// the index of the obstacle where the backend diverges, `None` for the final result
+    pub step: Option<usize>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConformanceReport {
+    pub scenarios: usize,
+    /// the number of obstacles found identical in both dual modules
+    pub compared_steps: usize,
+    pub failures: Vec<ConformanceFailure>,
+}
+
+impl ConformanceReport {
+    pub fn is_conformant(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    pub fn assert_conformant(&self) {
+        assert!(
+            self.is_conformant(),
+            "dual module is not conformant: {}",
+            serde_json::to_string_pretty(self).unwrap()
+        );
+    }
+}
+
+/// run all the conformance checks with the default configuration and panic on any failure
+pub fn run_conformance<D: DualModuleLike>() -> ConformanceReport {
+    let report = check_conformance::<D>(&ConformanceConfig::default());
+    report.assert_conformant();
+    report
+}
+
+/// run all the conformance checks and report the failures without panicking
+pub fn check_conformance<D: DualModuleLike>(config: &ConformanceConfig) -> ConformanceReport {
+    let sim_config = config
+        .primal_dual_config
+        .pointer("/dual/sim_config")
+        .cloned()
+        .unwrap_or(json!({}));
+    assert_ne!(
+        sim_config.get("support_layer_fusion"),
+        Some(&json!(true)),
+        "conformance checks do not fuse layers"
+    );
+    let mut code = CodeCapacityPlanarCode::new(CONFORMANCE_CODE_DISTANCE, config.p, 500);
+    let graph = MicroBlossomSingle::new_code(&code);
+    let mut solver = SolverEmbeddedBoxed::<D>::new(graph.clone(), config.primal_dual_config.clone());
+    let mut reference = SolverEmbeddedComb::new(graph, json!({ "dual": { "sim_config": sim_config } }));
+    let mut standard_solver = SolverSerial::new(&code.get_initializer());
+    let mut report = ConformanceReport::default();
+    let scenarios = CONFORMANCE_SCENARIOS
+        .iter()
+        .map(|&(name, defect_vertices)| (name.to_string(), defect_vertices.to_vec()))
+        .chain((0..config.shots as u64).map(|seed| {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            (format!("random shot {seed}"), syndrome_pattern.defect_vertices)
+        }))
+        .collect::<Vec<_>>();
+    for (name, defect_vertices) in scenarios {
+        let syndrome_pattern = SyndromePattern::new_vertices(defect_vertices.clone());
+        let mut failures = vec![];
+        solver.load_syndrome(&syndrome_pattern);
+        reference.load_syndrome(&syndrome_pattern);
+        // a correct dual module never needs this many obstacles
+        let max_steps = 1000 * (defect_vertices.len() + 1);
+        let mut lockstep = true;
+        let mut compared_steps = 0;
+        let mut step = 0;
+        loop {
+            let (obstacle, grown) = solver.dual_module.find_obstacle();
+            if lockstep {
+                let (reference_obstacle, reference_grown) = reference.dual_module.find_obstacle();
+                if obstacle == reference_obstacle && grown == reference_grown {
+                    compared_steps += 1;
+                    if !reference_obstacle.is_none() {
+                        reference
+                            .primal_module
+                            .resolve(reference.dual_module.as_mut(), reference_obstacle);
+                    }
+                } else {
+                    // the instructions of the two primal modules differ from now on
+                    lockstep = false;
+                    let same_kind = std::mem::discriminant(&obstacle) == std::mem::discriminant(&reference_obstacle)
+                        && !matches!(obstacle, CompactObstacle::GrowLength { .. });
+                    let check = if same_kind && grown == reference_grown {
+                        ConformanceCheck::ArbitrationOrder
+                    } else {
+                        ConformanceCheck::InstructionSemantics
+                    };
+                    failures.push((
+                        check,
+                        Some(step),
+                        format!(
+                            "found {obstacle:?} after growing {grown}, \
+                            expected {reference_obstacle:?} after growing {reference_grown}"
+                        ),
+                    ));
+                }
+            }
+            if obstacle.is_none() {
+                break;
+            }
+            if step >= max_steps {
+                failures.push((
+                    ConformanceCheck::ObstacleCorrectness,
+                    Some(step),
+                    "the solver does not terminate".to_string(),
+                ));
+                break;
+            }
+            solver.primal_module.resolve(solver.dual_module.as_mut(), obstacle);
+            step += 1;
+        }
+        if step < max_steps {
+            solver.finish();
+            let subgraph = solver.subgraph();
+            standard_solver.solve(&syndrome_pattern);
+            if code.get_initializer().syndrome_of(&subgraph) != defect_vertices.iter().cloned().collect() {
+                failures.push((
+                    ConformanceCheck::ObstacleCorrectness,
+                    None,
+                    "the subgraph does not correct the defects".to_string(),
+                ));
+            } else if solver.sum_dual_variables() != standard_solver.sum_dual_variables() {
+                failures.push((
+                    ConformanceCheck::ObstacleCorrectness,
+                    None,
+                    format!(
+                        "the matching weight is {}, expected {}",
+                        solver.sum_dual_variables(),
+                        standard_solver.sum_dual_variables()
+                    ),
+                ));
+            }
+            standard_solver.clear();
+        }
+        solver.clear();
+        reference.clear();
+        report.scenarios += 1;
+        report.compared_steps += compared_steps;
+        for (check, step, message) in failures {
+            report.failures.push(ConformanceFailure {
+                check,
+                scenario: name.clone(),
+                defect_vertices: defect_vertices.clone(),
+                step,
+                message,
+            });
+        }
+    }
+    report
+}
+
+/// the state of the dual module before resolving an obstacle, with the node indices replaced by stable names
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PrimalStep {
+    obstacle: &'static str,
+    /// the `(vertex, node, root, speed, grown)` of every vertex covered by a node
+    vertices: Vec<(VertexIndex, String, String, CompactGrowState, Weight)>,
+}
+
+/// the defect nodes are named by their order in the syndrome and the blossoms by their order of creation
+fn node_name(node_index: NodeIndex, blossom_begin: NodeIndex, blossom_names: &BTreeMap<NodeIndex, usize>) -> String {
+    if node_index < blossom_begin {
+        format!("defect {node_index}")
+    } else {
+        format!("blossom {}", blossom_names[&node_index])
+    }
+}
+
+fn primal_step(obstacle: &'static str, driver: &DualModuleCombDriver, name: impl Fn(NodeIndex) -> String) -> PrimalStep {
+    let vertices = driver
+        .vertices
+        .iter()
+        .enumerate()
+        .filter_map(|(vertex_index, vertex)| {
+            let registers = &vertex.registers;
+            Some((
+                vertex_index,
+                name(registers.node_index?),
+                registers.root_index.map_or(String::new(), &name),
+                registers.speed,
+                registers.grown,
+            ))
+        })
+        .collect();
+    PrimalStep { obstacle, vertices }
+}
+
+/// the matched pairs of defect vertices, with the virtual vertex marked by `true`
+fn matched_pairs(perfect_matching: &PerfectMatching) -> BTreeSet<(VertexIndex, VertexIndex, bool)> {
+    let defect_vertex = |node_ptr: &DualNodePtr| match node_ptr.read_recursive().class {
+        DualNodeClass::DefectVertex { defect_index } => defect_index,
+        DualNodeClass::Blossom { .. } => unreachable!("perfect matching only contains defect vertices"),
+    };
+    let mut pairs = BTreeSet::new();
+    for (node_1, node_2) in perfect_matching.peer_matchings.iter() {
+        let (vertex_1, vertex_2) = (defect_vertex(node_1), defect_vertex(node_2));
+        pairs.insert((vertex_1.min(vertex_2), vertex_1.max(vertex_2), false));
+    }
+    for (node, virtual_vertex) in perfect_matching.virtual_matchings.iter() {
+        pairs.insert((defect_vertex(node), *virtual_vertex, true));
+    }
+    pairs
+}
+
+/// run the primal conformance checks with the default configuration and panic on any failure
+pub fn run_primal_conformance() -> ConformanceReport {
+    let report = check_primal_conformance(&ConformanceConfig::default());
+    report.assert_conformant();
+    report
+}
+
+/// Feed the same obstacles to the embedded primal module and the serial primal module of fusion-blossom, both on the
+/// combinatorial dual module, and compare the dual module state after every decision as well as the final matching.
+/// The dual module state reflects the effect of the grow-state and blossom instructions rather than the instructions
+/// themselves, since the two primal modules may send them in a different order or repeat them. The
+/// `primal_dual_config` applies to the embedded solver and must not enable offloading or layer fusion.
+pub fn check_primal_conformance(config: &ConformanceConfig) -> ConformanceReport {
+    for key in ["support_offloading", "support_layer_fusion"] {
+        assert_ne!(
+            config.primal_dual_config.pointer(&format!("/dual/sim_config/{key}")),
+            Some(&json!(true)),
+            "primal conformance checks require every defect to be handled by the primal module"
+        );
+    }
+    let mut code = CodeCapacityPlanarCode::new(CONFORMANCE_CODE_DISTANCE, config.p, 500);
+    let initializer = code.get_initializer();
+    let mut solver = SolverEmbeddedComb::new(MicroBlossomSingle::new_code(&code), config.primal_dual_config.clone());
+    let mut serial_primal = PrimalModuleSerialPtr::new_empty(&initializer);
+    let mut serial_dual = stacker::grow(MAX_NODE_NUM * 256, || {
+        Box::new(DualModuleCombAdaptor::new_empty(&initializer))
+    });
+    let interface_ptr = DualModuleInterfacePtr::new_empty();
+    let mut report = ConformanceReport::default();
+    let scenarios = CONFORMANCE_SCENARIOS
+        .iter()
+        .map(|&(name, defect_vertices)| (name.to_string(), defect_vertices.to_vec()))
+        .chain((0..config.shots as u64).map(|seed| {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            (format!("random shot {seed}"), syndrome_pattern.defect_vertices)
+        }))
+        .collect::<Vec<_>>();
+    for (name, defect_vertices) in scenarios {
+        let syndrome_pattern = SyndromePattern::new_vertices(defect_vertices.clone());
+        let defect_num = defect_vertices.len();
+        // the serial primal module decides through the callback before resolving each obstacle
+        let mut serial_steps = vec![];
+        serial_primal.solve_step_callback(
+            &interface_ptr,
+            &syndrome_pattern,
+            serial_dual.as_mut(),
+            |interface, dual_module, _primal_module, group_max_update_length| {
+                if dual_module.grown > 0 {
+                    interface.notify_grown(dual_module.grown);
+                }
+                dual_module.grown = 0;
+                let obstacle = match group_max_update_length.peek().unwrap() {
+                    MaxUpdateLength::Conflicting(..) => "conflict",
+                    MaxUpdateLength::TouchingVirtual(..) => "touching virtual",
+                    MaxUpdateLength::BlossomNeedExpand(..) => "blossom need expand",
+                    _ => unreachable!("dual RTL never reports this"),
+                };
+                // the interface creates the blossoms in order after the defects
+                let name = |node_index: NodeIndex| {
+                    if node_index < defect_num {
+                        format!("defect {node_index}")
+                    } else {
+                        format!("blossom {}", node_index - defect_num)
+                    }
+                };
+                serial_steps.push(primal_step(obstacle, &dual_module.dual_module.driver.driver, name));
+            },
+        );
+        let serial_pairs = matched_pairs(&serial_primal.perfect_matching(&interface_ptr, serial_dual.as_mut()));
+        let serial_weight = interface_ptr.sum_dual_variables();
+        // the embedded primal module, whose blossom indices may be reused after expanding
+        let blossom_begin = solver.primal_module.nodes.blossom_begin;
+        let mut blossom_names = BTreeMap::new();
+        let mut blossom_count = 0;
+        let mut embedded_steps = vec![];
+        solver.load_syndrome(&syndrome_pattern);
+        loop {
+            let (obstacle, _) = solver.dual_module.find_obstacle();
+            if obstacle.is_none() || embedded_steps.len() > serial_steps.len() {
+                break;
+            }
+            let obstacle_kind = match &obstacle {
+                CompactObstacle::Conflict { node_2, .. } if node_2.option().is_some() => "conflict",
+                CompactObstacle::Conflict { .. } => "touching virtual",
+                CompactObstacle::BlossomNeedExpand { .. } => "blossom need expand",
+                _ => unreachable!("the dual module spontaneously processes all finite growth"),
+            };
+            embedded_steps.push(primal_step(obstacle_kind, &solver.dual_module.driver.driver, |node_index| {
+                node_name(node_index, blossom_begin, &blossom_names)
+            }));
+            let mut recorder = DualRecorder::new(solver.dual_module.as_mut());
+            solver.primal_module.resolve(&mut recorder, obstacle);
+            for call in recorder.calls {
+                if let DualCall::CreateBlossom { blossom } = call {
+                    blossom_names.insert(blossom as NodeIndex, blossom_count);
+                    blossom_count += 1;
+                }
+            }
+        }
+        solver.finish();
+        let embedded_pairs = matched_pairs(&solver.perfect_matching());
+        let embedded_weight = solver.sum_dual_variables();
+        // identical dual module states lead to identical obstacles, so the first difference is the decision before
+        let mut failures = vec![];
+        let steps = embedded_steps.len().max(serial_steps.len());
+        for step in 0..steps {
+            match (embedded_steps.get(step), serial_steps.get(step)) {
+                (Some(embedded), Some(serial)) if embedded == serial => {
+                    report.compared_steps += 1;
+                }
+                (embedded, serial) => {
+                    failures.push((
+                        ConformanceCheck::GrowState,
+                        Some(step),
+                        format!("the dual module state differs: {embedded:?}, expected {serial:?}"),
+                    ));
+                    break;
+                }
+            }
+        }
+        if embedded_weight != serial_weight {
+            failures.push((
+                ConformanceCheck::ObstacleCorrectness,
+                None,
+                format!("the matching weight is {embedded_weight}, expected {serial_weight}"),
+            ));
+        } else if embedded_pairs != serial_pairs {
+            failures.push((
+                ConformanceCheck::Matching,
+                None,
+                format!("matched {embedded_pairs:?}, expected {serial_pairs:?}"),
+            ));
+        }
+        solver.clear();
+        serial_primal.clear();
+        serial_dual.clear();
+        interface_ptr.clear();
+        report.scenarios += 1;
+        for (check, step, message) in failures {
+            report.failures.push(ConformanceFailure {
+                check,
+                scenario: name.clone(),
+                defect_vertices: defect_vertices.clone(),
+                step,
+                message,
+            });
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conformance_comb() {
+        // cargo test conformance_comb -- --nocapture
+        let report = run_conformance::<DualModuleCombDriver>();
+        println!("{report:?}");
+        assert_eq!(
+            report.scenarios,
+            CONFORMANCE_SCENARIOS.len() + ConformanceConfig::default().shots
+        );
+        // the reference is also conformant with offloading, as long as both use the same simulation config
+        let config = ConformanceConfig {
+            primal_dual_config: json!({ "dual": { "sim_config": { "support_offloading": true } } }),
+            ..Default::default()
+        };
+        check_conformance::<DualModuleCombDriver>(&config).assert_conformant();
+    }
+
+    #[test]
+    fn conformance_primal() {
+        // cargo test conformance_primal -- --nocapture
+        // any decision different from the serial primal module fails, not only a suboptimal matching
+        let report = run_primal_conformance();
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        assert_eq!(
+            report.scenarios,
+            CONFORMANCE_SCENARIOS.len() + ConformanceConfig::default().shots
+        );
+        assert!(report.compared_steps > 0);
+    }
+}
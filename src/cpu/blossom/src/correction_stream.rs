@@ -0,0 +1,708 @@
+//! Correction Stream
+//!
+//! In streaming mode, an external Pauli-frame tracker or feed-forward controller needs the corrections as soon as they
+//! are decided rather than at the end of the shot. After every measurement round, the matched pairs whose defects
+//! are all at least `commit_delay` rounds old are committed, and the parity of every logical observable flipped by
+//! their corrections is streamed as `(observable, parity, round)` tuples whenever it changes. The parity is the
+//! cumulative parity of the observable up to that round, so that a later round can revise an earlier commit: the
+//! consumer simply keeps the latest parity of each observable. The last round `num_layers` is the final solve, after
+//! which the streamed parities equal those of the final correction.
+//!
+//! Optionally, the committed prefix of the rounds is frozen in the dual module (see
+//! [`SolverTrackedDual::freeze_vertex_range`]) once every defect in it is committed, so that the committed matchings
+//! stay as streamed and the frozen vertices never resurface any obstacle. If the driver does not support freezing, a
+//! warning is printed and the stream continues without freezing.
+//!
+//! A commit is only safe if the later rounds do not change the committed matchings. With `monitor` enabled, every
+//! round checks that the defects committed earlier are still matched to the same peers, and the final solve checks
+//! that no loaded defect is left unmatched; each violation is reported as a [`CommitWarning`] with the shot and the
+//! rounds involved, which means that `commit_delay` is too short for the noise.
+//!
+//! The consumer may fall behind the decoder. The [`Backpressure`] policy decides whether the decoder waits for the
+//! consumer, or buffers the corrections and retries at the next round so that the decoding is never stalled.
+//!
+
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use fusion_blossom::dual_module::*;
+use fusion_blossom::mwpm_solver::PrimalDualSolver;
+use fusion_blossom::primal_module::*;
+use fusion_blossom::util::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{LineWriter, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommittedCorrection {
+    pub observable: usize,
+    /// the cumulative parity of the observable after this round
+    pub parity: bool,
+    /// the measurement round at the end of which the correction is committed; `num_layers` means the final solve
+    pub round: usize,
+}
+
+/// the output channel of the committed corrections
+pub trait CorrectionSink {
+    /// deliver the correction if the consumer is ready, otherwise give it back
+    fn try_send(&mut self, correction: CommittedCorrection) -> Result<(), CommittedCorrection>;
+    /// deliver the correction, waiting for the consumer if necessary
+    fn send(&mut self, correction: CommittedCorrection);
+}
+
+/// a bounded channel, e.g., to a consumer thread; the capacity of the channel limits the corrections in flight
+impl CorrectionSink for SyncSender<CommittedCorrection> {
+    fn try_send(&mut self, correction: CommittedCorrection) -> Result<(), CommittedCorrection> {
+        match SyncSender::try_send(self, correction) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(correction)) => Err(correction),
+            Err(TrySendError::Disconnected(_)) => panic!("the correction consumer is disconnected"),
+        }
+    }
+    fn send(&mut self, correction: CommittedCorrection) {
+        SyncSender::send(self, correction).expect("the correction consumer is disconnected");
+    }
+}
+
+/// a callback that returns false when the consumer is not ready to take the correction
+pub struct CallbackSink<F: FnMut(CommittedCorrection) -> bool>(pub F);
+
+impl<F: FnMut(CommittedCorrection) -> bool> CorrectionSink for CallbackSink<F> {
+    fn try_send(&mut self, correction: CommittedCorrection) -> Result<(), CommittedCorrection> {
+        if (self.0)(correction) {
+            Ok(())
+        } else {
+            Err(correction)
+        }
+    }
+    fn send(&mut self, correction: CommittedCorrection) {
+        while !(self.0)(correction) {
+            std::thread::yield_now();
+        }
+    }
+}
+
+/// stream the corrections to a TCP socket as JSON lines; a writer thread drains a channel of `capacity` corrections
+/// so that a slow socket exerts backpressure through the channel
+pub fn socket_sink(address: impl ToSocketAddrs, capacity: usize) -> std::io::Result<SyncSender<CommittedCorrection>> {
+    let mut writer = LineWriter::new(TcpStream::connect(address)?);
+    let (sender, receiver) = sync_channel::<CommittedCorrection>(capacity);
+    std::thread::spawn(move || {
+        for correction in receiver.iter() {
+            if writeln!(writer, "{}", serde_json::to_string(&correction).unwrap()).is_err() {
+                break; // dropping the receiver disconnects the decoder
+            }
+        }
+    });
+    Ok(sender)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Backpressure {
+    /// wait for the consumer, which stalls the decoding
+    #[default]
+    Block,
+    /// keep the corrections not accepted by the consumer and retry after the next round; the remaining ones are
+    /// delivered blocking at the end of the shot
+    Buffer,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CorrectionStreamStatistics {
+    pub shots: usize,
+    /// the number of corrections delivered to the consumer
+    pub streamed: usize,
+    /// the number of corrections that revise an earlier commit of the same observable in the same shot
+    pub revised: usize,
+    /// the number of times the consumer is not ready to take a correction
+    pub not_ready: usize,
+    /// the maximum number of corrections buffered by [`Backpressure::Buffer`]
+    pub max_pending: usize,
+    /// the number of vertices frozen in all the shots
+    pub frozen_vertices: usize,
+    /// the number of inconsistent commits found by the monitor
+    pub warnings: usize,
+}
+
+/// the peer of a defect in a matching
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum MatchedPeer {
+    Defect(VertexIndex),
+    Virtual(VertexIndex),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum CommitInconsistency {
+    /// a committed defect is matched to a different peer, or not at all, in a later round
+    Rematched {
+        defect: VertexIndex,
+        committed: MatchedPeer,
+        current: Option<MatchedPeer>,
+    },
+    /// a loaded defect is left unmatched by the final solve
+    Unmatched { defect: VertexIndex },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommitWarning {
+    /// the number of shots decoded before
+    pub shot: usize,
+    /// the round of the inconsistent commit; the final solve `num_layers` for an unmatched defect
+    pub commit_round: usize,
+    /// the round in which the inconsistency is found
+    pub round: usize,
+    pub commit_delay: usize,
+    pub inconsistency: CommitInconsistency,
+}
+
+impl std::fmt::Display for CommitWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            shot,
+            commit_round,
+            round,
+            commit_delay,
+            ..
+        } = self;
+        match &self.inconsistency {
+            CommitInconsistency::Rematched {
+                defect,
+                committed,
+                current,
+            } => write!(
+                f,
+                "shot {shot}: defect {defect} committed to {committed:?} at round {commit_round} is matched to \
+                {current:?} at round {round}, commit delay {commit_delay} is too short"
+            ),
+            CommitInconsistency::Unmatched { defect } => {
+                write!(f, "shot {shot}: defect {defect} is left unmatched at round {round}")
+            }
+        }
+    }
+}
+
+fn defect_vertex(node_ptr: &DualNodePtr) -> VertexIndex {
+    match node_ptr.read_recursive().class {
+        DualNodeClass::DefectVertex { defect_index } => defect_index,
+        DualNodeClass::Blossom { .. } => unreachable!("perfect matching only contains defect vertices"),
+    }
+}
+
+/// the peer of every matched defect
+pub fn matched_peers(perfect_matching: &PerfectMatching) -> BTreeMap<VertexIndex, MatchedPeer> {
+    let mut peers = BTreeMap::new();
+    for (node_1, node_2) in perfect_matching.peer_matchings.iter() {
+        let (defect_1, defect_2) = (defect_vertex(node_1), defect_vertex(node_2));
+        peers.insert(defect_1, MatchedPeer::Defect(defect_2));
+        peers.insert(defect_2, MatchedPeer::Defect(defect_1));
+    }
+    for (node, virtual_vertex) in perfect_matching.virtual_matchings.iter() {
+        peers.insert(defect_vertex(node), MatchedPeer::Virtual(*virtual_vertex));
+    }
+    peers
+}
+
+pub struct CorrectionStream<Sink: CorrectionSink> {
+    pub sink: Sink,
+    pub backpressure: Backpressure,
+    /// commit a matched pair only when all its defects are at least this many rounds old
+    pub commit_delay: usize,
+    /// freeze the vertices of the committed rounds
+    pub freeze: bool,
+    /// check that the committed matchings survive the later rounds
+    pub monitor: bool,
+    pub warnings: Vec<CommitWarning>,
+    /// the peer and the round of every committed defect in the current shot
+    committed_peers: BTreeMap<VertexIndex, (MatchedPeer, usize)>,
+    /// the observables flipped by every edge, one bit per observable
+    edge_observables: Vec<u64>,
+    observable_num: usize,
+    vertex_layer_id: BTreeMap<VertexIndex, usize>,
+    num_layers: usize,
+    subgraph_builder: SubGraphBuilder,
+    pending: VecDeque<CommittedCorrection>,
+    pub statistics: CorrectionStreamStatistics,
+}
+
+impl<Sink: CorrectionSink> CorrectionStream<Sink> {
+    pub fn new(graph: &MicroBlossomSingle, edge_observables: Vec<u64>, observable_num: usize, sink: Sink) -> Self {
+        assert_eq!(
+            edge_observables.len(),
+            graph.weighted_edges.len(),
+            "one observable mask per edge"
+        );
+        assert!(observable_num <= 64, "at most 64 observables are supported");
+        let layer_fusion = graph
+            .layer_fusion
+            .as_ref()
+            .expect("streaming mode requires the layer fusion information");
+        Self {
+            sink,
+            backpressure: Backpressure::default(),
+            commit_delay: 0,
+            freeze: false,
+            monitor: false,
+            warnings: vec![],
+            committed_peers: BTreeMap::new(),
+            edge_observables,
+            observable_num,
+            vertex_layer_id: layer_fusion.vertex_layer_id.clone(),
+            num_layers: layer_fusion.num_layers,
+            subgraph_builder: SubGraphBuilder::new(&graph.get_initializer()),
+            pending: VecDeque::new(),
+            statistics: CorrectionStreamStatistics::default(),
+        }
+    }
+
+    fn layer_of(&self, vertex_index: VertexIndex) -> usize {
+        self.vertex_layer_id.get(&vertex_index).cloned().unwrap_or(0)
+    }
+
+    /// the observable mask of the matched pairs whose defects are all no later than `horizon`, together with the
+    /// committed defects
+    fn committed_observables(&mut self, perfect_matching: &PerfectMatching, horizon: usize) -> (u64, BTreeSet<VertexIndex>) {
+        let settled = |node_ptr: &DualNodePtr| self.layer_of(defect_vertex(node_ptr)) <= horizon;
+        let mut committed = PerfectMatching::default();
+        let mut committed_defects = BTreeSet::new();
+        for (node_1, node_2) in perfect_matching.peer_matchings.iter() {
+            if settled(node_1) && settled(node_2) {
+                committed.peer_matchings.push((node_1.clone(), node_2.clone()));
+                committed_defects.extend([defect_vertex(node_1), defect_vertex(node_2)]);
+            }
+        }
+        for (node, virtual_vertex) in perfect_matching.virtual_matchings.iter() {
+            if settled(node) {
+                committed.virtual_matchings.push((node.clone(), *virtual_vertex));
+                committed_defects.insert(defect_vertex(node));
+            }
+        }
+        self.subgraph_builder.clear();
+        self.subgraph_builder.load_perfect_matching(&committed);
+        let mask = self
+            .subgraph_builder
+            .get_subgraph()
+            .iter()
+            .fold(0, |mask, &edge_index| mask ^ self.edge_observables[edge_index as usize]);
+        (mask, committed_defects)
+    }
+
+    /// freeze the prefix of the vertices no later than `horizon` if all the loaded defects in it are committed;
+    /// returns the end of the frozen prefix
+    fn freeze_committed<Dual: SolverTrackedDual>(
+        &mut self,
+        solver: &mut SolverEmbeddedBoxed<Dual>,
+        horizon: usize,
+        committed_defects: &BTreeSet<VertexIndex>,
+        frozen_end: VertexIndex,
+    ) -> VertexIndex {
+        let all_committed = solver
+            .loaded_defects
+            .iter()
+            .filter(|vertex_index| !solver.removed_defects.contains(vertex_index))
+            .all(|&vertex_index| self.layer_of(vertex_index) > horizon || committed_defects.contains(&vertex_index));
+        if !all_committed {
+            return frozen_end;
+        }
+        let vertex_num = solver.graph.vertex_num;
+        let end = (0..vertex_num)
+            .find(|&vertex_index| self.layer_of(vertex_index) > horizon)
+            .unwrap_or(vertex_num);
+        if end > frozen_end {
+            if let Err(error) = solver.dual_module.driver.driver.freeze_vertex_range(frozen_end, end) {
+                // the committed matchings are still streamed, only without the guarantee of the frozen region
+                eprintln!("[warning] {error}, the committed rounds are not frozen");
+                self.freeze = false;
+                return frozen_end;
+            }
+            self.statistics.frozen_vertices += end - frozen_end;
+            return end;
+        }
+        frozen_end
+    }
+
+    fn warn(&mut self, commit_round: usize, round: usize, inconsistency: CommitInconsistency) {
+        let warning = CommitWarning {
+            shot: self.statistics.shots,
+            commit_round,
+            round,
+            commit_delay: self.commit_delay,
+            inconsistency,
+        };
+        eprintln!("[warning] {warning}");
+        self.statistics.warnings += 1;
+        self.warnings.push(warning);
+    }
+
+    /// check the earlier commits against the current matching and remember the new ones
+    fn monitor_commits(
+        &mut self,
+        peers: &BTreeMap<VertexIndex, MatchedPeer>,
+        committed_defects: &BTreeSet<VertexIndex>,
+        round: usize,
+    ) {
+        let rematched: Vec<VertexIndex> = (self.committed_peers.iter())
+            .filter(|(defect, (committed, _))| peers.get(defect) != Some(committed))
+            .map(|(&defect, _)| defect)
+            .collect();
+        for defect in rematched {
+            let (committed, commit_round) = self.committed_peers.remove(&defect).unwrap();
+            let current = peers.get(&defect).cloned();
+            self.warn(
+                commit_round,
+                round,
+                CommitInconsistency::Rematched {
+                    defect,
+                    committed,
+                    current,
+                },
+            );
+        }
+        for &defect in committed_defects.iter() {
+            self.committed_peers.entry(defect).or_insert((peers[&defect], round));
+        }
+    }
+
+    fn deliver(&mut self, correction: CommittedCorrection) {
+        match self.backpressure {
+            Backpressure::Block => {
+                if let Err(correction) = self.sink.try_send(correction) {
+                    self.statistics.not_ready += 1;
+                    self.sink.send(correction);
+                }
+                self.statistics.streamed += 1;
+            }
+            Backpressure::Buffer => {
+                self.pending.push_back(correction);
+                self.statistics.max_pending = self.statistics.max_pending.max(self.pending.len());
+            }
+        }
+    }
+
+    /// retry the buffered corrections in order, stopping at the first one the consumer does not take
+    fn flush(&mut self, blocking: bool) {
+        while let Some(correction) = self.pending.pop_front() {
+            match self.sink.try_send(correction) {
+                Ok(()) => self.statistics.streamed += 1,
+                Err(correction) => {
+                    self.statistics.not_ready += 1;
+                    if !blocking {
+                        self.pending.push_front(correction);
+                        return;
+                    }
+                    self.sink.send(correction);
+                    self.statistics.streamed += 1;
+                }
+            }
+        }
+    }
+
+    /// decode the syndrome round by round and stream the committed corrections; the defects of every round are only
+    /// loaded when the round arrives, so that the finished nodes of the frozen rounds can be recycled for a limited
+    /// pool of hardware node indices (see [`SolverEmbeddedBoxed::recycle_finished_nodes`]); the solver is left finished
+    pub fn solve<Dual: SolverTrackedDual>(
+        &mut self,
+        solver: &mut SolverEmbeddedBoxed<Dual>,
+        syndrome_pattern: &SyndromePattern,
+    ) {
+        let mut rounds = vec![vec![]; self.num_layers];
+        for &vertex_index in syndrome_pattern.defect_vertices.iter() {
+            rounds[self.layer_of(vertex_index).min(self.num_layers - 1)].push(vertex_index);
+        }
+        let mut parities: u64 = 0;
+        let mut changed: u64 = 0;
+        let mut round = 0;
+        let mut frozen_end = 0;
+        loop {
+            let fused = solver.stream_round(rounds.get(round).map_or(&[][..], Vec::as_slice));
+            if !fused {
+                // a truncated solve stops fusing, and the greedy fallback matches the defects of the later rounds
+                for defect_vertices in rounds.iter().skip(round + 1) {
+                    solver.stream_round(defect_vertices);
+                }
+                while solver.step() {}
+                solver.finish();
+            }
+            let perfect_matching = solver.perfect_matching();
+            let (observables, committed_defects) = if fused {
+                match (round + 1).checked_sub(self.commit_delay) {
+                    Some(settled_rounds) if settled_rounds > 0 => {
+                        let horizon = settled_rounds - 1;
+                        let (observables, committed_defects) = self.committed_observables(&perfect_matching, horizon);
+                        if self.freeze {
+                            frozen_end = self.freeze_committed(solver, horizon, &committed_defects, frozen_end);
+                        }
+                        (observables, committed_defects)
+                    }
+                    _ => (parities, BTreeSet::new()),
+                }
+            } else {
+                self.committed_observables(&perfect_matching, usize::MAX)
+            };
+            let round_id = if fused { round } else { self.num_layers };
+            if self.monitor {
+                let peers = matched_peers(&perfect_matching);
+                self.monitor_commits(&peers, &committed_defects, round_id);
+                if !fused {
+                    let unmatched: Vec<VertexIndex> = (solver.loaded_defects.iter())
+                        .filter(|defect| !peers.contains_key(defect) && !solver.removed_defects.contains(defect))
+                        .cloned()
+                        .collect();
+                    for defect in unmatched {
+                        self.warn(round_id, round_id, CommitInconsistency::Unmatched { defect });
+                    }
+                }
+            }
+            for observable in 0..self.observable_num {
+                let bit = 1 << observable;
+                if (observables ^ parities) & bit != 0 {
+                    if changed & bit != 0 {
+                        self.statistics.revised += 1;
+                    }
+                    changed |= bit;
+                    self.deliver(CommittedCorrection {
+                        observable,
+                        parity: observables & bit != 0,
+                        round: round_id,
+                    });
+                }
+            }
+            parities = observables;
+            if !fused {
+                break;
+            }
+            self.flush(false);
+            round += 1;
+        }
+        self.flush(true);
+        self.committed_peers.clear();
+        self.statistics.shots += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+    use serde_json::json;
+
+    /// observable `o` is flipped by the edges between a regular vertex and the virtual vertices of boundary `o`
+    fn boundary_observables(graph: &MicroBlossomSingle) -> Vec<u64> {
+        let min_j = graph
+            .positions
+            .iter()
+            .map(|position| position.j)
+            .fold(f64::INFINITY, f64::min);
+        graph
+            .weighted_edges
+            .iter()
+            .map(|edge| {
+                let mut mask = 0;
+                for vertex_index in [edge.l, edge.r] {
+                    if graph.virtual_vertices.contains(&vertex_index) {
+                        mask ^= if graph.positions[vertex_index].j == min_j {
+                            0b01
+                        } else {
+                            0b10
+                        };
+                    }
+                }
+                mask
+            })
+            .collect()
+    }
+
+    /// apply the streamed corrections of a shot to a frame
+    fn replay(corrections: &[CommittedCorrection]) -> u64 {
+        let mut frame = 0;
+        for pair in corrections.windows(2) {
+            assert!(pair[0].round <= pair[1].round, "the rounds are delivered in order");
+        }
+        for correction in corrections.iter() {
+            frame &= !(1 << correction.observable);
+            frame |= (correction.parity as u64) << correction.observable;
+        }
+        frame
+    }
+
+    /// the streamed parities end up equal to those of the final correction
+    #[test]
+    fn correction_stream_final_parity() {
+        // cargo test correction_stream_final_parity -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 6, 0.03, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let edge_observables = boundary_observables(&graph);
+        let config = json!({ "dual": { "sim_config": { "support_layer_fusion": true } } });
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), config);
+        let (sender, receiver) = sync_channel(1000);
+        let mut stream = CorrectionStream::new(&graph, edge_observables.clone(), 2, sender);
+        stream.commit_delay = 1;
+        let mut early_commits = 0;
+        for seed in 0..30 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            stream.solve(&mut solver, &syndrome_pattern);
+            let corrections: Vec<CommittedCorrection> = receiver.try_iter().collect();
+            early_commits += corrections
+                .iter()
+                .filter(|correction| correction.round < stream.num_layers)
+                .count();
+            let expected = solver
+                .subgraph()
+                .iter()
+                .fold(0, |mask, &edge_index| mask ^ edge_observables[edge_index as usize]);
+            assert_eq!(replay(&corrections), expected);
+            solver.clear();
+        }
+        println!("{:?}", stream.statistics);
+        assert!(
+            early_commits > 0,
+            "some corrections should be committed before the final solve"
+        );
+    }
+
+    /// the frozen committed rounds never resurface an obstacle (checked by the comb model in debug builds), and the
+    /// correction remains valid
+    #[test]
+    fn correction_stream_freeze_committed() {
+        // cargo test correction_stream_freeze_committed -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 6, 0.02, 500);
+        let initializer = code.get_initializer();
+        let graph = MicroBlossomSingle::new_code(&code);
+        let edge_observables = boundary_observables(&graph);
+        let config = json!({ "dual": { "sim_config": { "support_layer_fusion": true } } });
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), config);
+        let (sender, receiver) = sync_channel(1000);
+        let mut stream = CorrectionStream::new(&graph, edge_observables.clone(), 2, sender);
+        stream.commit_delay = 2;
+        stream.freeze = true;
+        for seed in 0..30 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            stream.solve(&mut solver, &syndrome_pattern);
+            let corrections: Vec<CommittedCorrection> = receiver.try_iter().collect();
+            let subgraph = solver.subgraph();
+            let defects: BTreeSet<VertexIndex> = syndrome_pattern.defect_vertices.iter().cloned().collect();
+            assert_eq!(initializer.syndrome_of(&subgraph), defects);
+            let expected = subgraph
+                .iter()
+                .fold(0, |mask, &edge_index| mask ^ edge_observables[edge_index as usize]);
+            assert_eq!(replay(&corrections), expected);
+            solver.clear();
+        }
+        println!("{:?}", stream.statistics);
+        assert!(stream.statistics.frozen_vertices > 0);
+        let vertices = &solver.dual_module.driver.driver.vertices;
+        assert!(
+            vertices.iter().all(|vertex| !vertex.registers.is_frozen),
+            "the reset releases the frozen vertices"
+        );
+    }
+
+    /// with the frozen rounds, a small pool of hardware node indices decodes shots with more defects than the pool
+    /// by recycling the finished nodes, and the result is still the minimum-weight perfect matching
+    #[test]
+    fn correction_stream_recycle_nodes() {
+        // cargo test correction_stream_recycle_nodes -- --nocapture
+        let capacity = 8;
+        let mut code = PhenomenologicalRotatedCode::new(3, 40, 0.01, 500);
+        let initializer = code.get_initializer();
+        let graph = MicroBlossomSingle::new_code(&code);
+        let edge_observables = boundary_observables(&graph);
+        let config = json!({
+            "dual": { "sim_config": { "support_layer_fusion": true } },
+            "hardware_node_capacity": capacity,
+        });
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), config);
+        let mut serial = SolverSerial::new(&initializer);
+        let (sender, _receiver) = sync_channel(100000);
+        let mut stream = CorrectionStream::new(&graph, edge_observables, 2, sender);
+        stream.commit_delay = 3;
+        stream.freeze = true;
+        let weight_of = |subgraph: &[EdgeIndex]| -> Weight {
+            (subgraph.iter())
+                .map(|&edge_index| initializer.weighted_edges[edge_index as usize].2)
+                .sum()
+        };
+        let mut oversized_shots = 0;
+        for seed in 0..30 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            if syndrome_pattern.defect_vertices.len() > capacity {
+                oversized_shots += 1;
+            }
+            stream.solve(&mut solver, &syndrome_pattern);
+            serial.solve(&syndrome_pattern);
+            assert_eq!(weight_of(&solver.subgraph()), weight_of(&serial.subgraph()));
+            solver.clear();
+            serial.clear();
+        }
+        let statistics = &solver.node_virtualizer.statistics;
+        println!("{oversized_shots} oversized shots, {statistics:?}");
+        assert!(oversized_shots > 0);
+        assert!(statistics.recycled > 0);
+        assert!(statistics.max_active <= capacity);
+    }
+
+    /// a slow consumer neither loses corrections nor stalls the rounds with the buffering policy
+    #[test]
+    fn correction_stream_callback_backpressure() {
+        // cargo test correction_stream_callback_backpressure -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 6, 0.05, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let edge_observables = boundary_observables(&graph);
+        let config = json!({ "dual": { "sim_config": { "support_layer_fusion": true } } });
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), config);
+        // the consumer only takes every other offer
+        let received = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        let consumer = received.clone();
+        let mut ready = false;
+        let sink = CallbackSink(move |correction| {
+            ready = !ready;
+            if ready {
+                consumer.borrow_mut().push(correction);
+            }
+            ready
+        });
+        let mut stream = CorrectionStream::new(&graph, edge_observables.clone(), 2, sink);
+        stream.backpressure = Backpressure::Buffer;
+        for seed in 0..20 {
+            stream.solve(&mut solver, &code.generate_random_errors(seed));
+            let corrections: Vec<CommittedCorrection> = received.borrow_mut().drain(..).collect();
+            let expected = solver
+                .subgraph()
+                .iter()
+                .fold(0, |mask, &edge_index| mask ^ edge_observables[edge_index as usize]);
+            assert_eq!(replay(&corrections), expected);
+            solver.clear();
+        }
+        let statistics = &stream.statistics;
+        println!("{statistics:?}");
+        assert!(statistics.streamed > 0 && statistics.not_ready > 0);
+        assert!(statistics.max_pending > 0);
+    }
+
+    /// a commit without delay is revised by the later rounds, while committing only at the final solve is always safe
+    #[test]
+    fn correction_stream_monitor() {
+        // cargo test correction_stream_monitor -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 6, 0.05, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let edge_observables = boundary_observables(&graph);
+        let config = json!({ "dual": { "sim_config": { "support_layer_fusion": true } } });
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), config);
+        let (sender, receiver) = sync_channel(1000);
+        let mut stream = CorrectionStream::new(&graph, edge_observables, 2, sender);
+        stream.monitor = true;
+        for commit_delay in [0, stream.num_layers + 1] {
+            stream.commit_delay = commit_delay;
+            stream.warnings.clear();
+            for seed in 0..30 {
+                stream.solve(&mut solver, &code.generate_random_errors(seed));
+                receiver.try_iter().for_each(drop);
+                solver.clear();
+            }
+            println!("commit delay {commit_delay}: {} warnings", stream.warnings.len());
+            assert!((stream.warnings.iter())
+                .all(|warning| matches!(warning.inconsistency, CommitInconsistency::Rematched { .. })));
+            assert_eq!(stream.warnings.is_empty(), commit_delay > 0);
+        }
+    }
+}
@@ -0,0 +1,396 @@
+//! Critical Path
+//!
+//! The end-to-end latency of a shot is a chain of dependencies between three parties: the interface (writing an
+//! instruction over the bus, and reading back a response), the hardware (the cycles of the accelerator executing the
+//! instruction, plus the broadcast and convergecast delays of reading an obstacle) and the firmware (the primal
+//! module handling a response before issuing the next instruction). The host does not wait for an instruction that
+//! does not read back anything, so the hardware may run in parallel with the bus writes, but every response blocks
+//! the host until the accelerator has caught up.
+//!
+//! [`CriticalPathAnalysis`] replays the events of an instruction trace (see [`crate::instruction_trace`]) on this
+//! timeline and reconstructs, for every shot, the chain of segments that determined its latency: whenever the host
+//! waits for the accelerator or the accelerator waits for an instruction, the chain continues from the party that
+//! finishes last. The segments are attributed to `interface.write`, `interface.read`, `hardware.<instruction>` and
+//! `firmware.<response>`, and aggregated over the run to show whether the optimization effort should go to the
+//! hardware, the firmware or the interface. The costs are given by a [`CriticalPathConfig`], e.g.,
+//!
+//! ```bash
+//! micro-blossom trace critical-path shots.trace --config '{"clock_period":5e-9,"bus_read":4e-7}'
+//! ```
+//!
+
+use crate::dual_module_comb_cycles::*;
+use crate::instruction_trace::*;
+use micro_blossom_nostd::interface::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::{Read, Result, Write};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CriticalPathConfig {
+    /// the clock period of the accelerator in seconds
+    #[serde(default = "critical_path_config_default::clock_period")]
+    pub clock_period: f64,
+    /// the cycles of the instructions; without the graph, the fixed broadcast and convergecast delays are used even
+    /// if a reduction or broadcast tree is configured
+    #[serde(default = "critical_path_config_default::cycles")]
+    pub cycles: CycleConfig,
+    /// the bus cost of writing an instruction or a register in seconds
+    #[serde(default = "critical_path_config_default::bus_write")]
+    pub bus_write: f64,
+    /// the bus cost of reading back a response in seconds, after the accelerator has produced it
+    #[serde(default = "critical_path_config_default::bus_read")]
+    pub bus_read: f64,
+    /// the firmware cost of handling each type of response in seconds, e.g., `{"conflict": 5e-7}`
+    #[serde(default = "Default::default")]
+    pub firmware: BTreeMap<String, f64>,
+    /// the firmware cost of handling a response of a type not listed in `firmware`
+    #[serde(default = "critical_path_config_default::firmware_default")]
+    pub firmware_default: f64,
+}
+
+pub mod critical_path_config_default {
+    use super::*;
+    pub fn clock_period() -> f64 {
+        5e-9 // 200MHz
+    }
+    pub fn cycles() -> CycleConfig {
+        serde_json::from_value(json!({})).unwrap()
+    }
+    pub fn bus_write() -> f64 {
+        4e-8
+    }
+    pub fn bus_read() -> f64 {
+        4e-7
+    }
+    pub fn firmware_default() -> f64 {
+        1e-7
+    }
+}
+
+impl Default for CriticalPathConfig {
+    fn default() -> Self {
+        serde_json::from_value(json!({})).unwrap()
+    }
+}
+
+/// the three parties that a segment of the critical path belongs to
+pub const CRITICAL_PATH_CATEGORIES: [&str; 3] = ["interface", "hardware", "firmware"];
+
+/// the latest point of a timeline together with the segments leading to it
+#[derive(Debug, Clone, Default)]
+struct Chain {
+    time: f64,
+    segments: BTreeMap<(&'static str, &'static str), f64>,
+}
+
+impl Chain {
+    fn extend(&mut self, category: &'static str, name: &'static str, duration: f64) {
+        self.time += duration;
+        *self.segments.entry((category, name)).or_default() += duration;
+    }
+
+    /// continue from the other timeline if it finishes later
+    fn wait(&mut self, other: &Chain) {
+        if other.time > self.time {
+            self.clone_from(other);
+        }
+    }
+}
+
+/// the critical path of a single shot
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ShotCriticalPath {
+    /// the end-to-end latency in seconds
+    pub latency: f64,
+    /// the time spent in every segment of the critical path, e.g., `hardware.find_obstacle`; they sum to the latency
+    pub segments: BTreeMap<String, f64>,
+}
+
+impl ShotCriticalPath {
+    /// the time spent in a category of segments
+    pub fn category(&self, category: &str) -> f64 {
+        (self.segments.iter())
+            .filter(|(name, _)| name.split('.').next() == Some(category))
+            .map(|(_, duration)| duration)
+            .sum()
+    }
+
+    /// the category that contributes the most to the latency
+    pub fn bottleneck(&self) -> &'static str {
+        CRITICAL_PATH_CATEGORIES
+            .into_iter()
+            .max_by(|a, b| self.category(a).total_cmp(&self.category(b)))
+            .unwrap()
+    }
+}
+
+/// the critical paths of the shots in a trace, accumulated one event at a time
+#[derive(Debug, Clone)]
+pub struct CriticalPathAnalysis {
+    pub config: CriticalPathConfig,
+    pub shots: usize,
+    pub total_latency: f64,
+    pub max_latency: f64,
+    /// the time spent in every segment summed over the shots
+    pub segments: BTreeMap<String, f64>,
+    /// the number of shots whose latency is dominated by each category
+    pub bottlenecks: BTreeMap<&'static str, usize>,
+    /// the host, i.e., the interface and the firmware
+    host: Chain,
+    accelerator: Chain,
+    /// the instruction that the next response belongs to
+    last_instruction: &'static str,
+}
+
+impl CriticalPathAnalysis {
+    pub fn new(config: CriticalPathConfig) -> Self {
+        Self {
+            config,
+            shots: 0,
+            total_latency: 0.,
+            max_latency: 0.,
+            segments: BTreeMap::new(),
+            bottlenecks: BTreeMap::new(),
+            host: Chain::default(),
+            accelerator: Chain::default(),
+            last_instruction: "unknown",
+        }
+    }
+
+    /// analyze every shot of the trace, optionally writing the critical path of every shot as a line of JSON
+    pub fn from_reader<R: Read>(
+        config: CriticalPathConfig,
+        reader: TraceReader<R>,
+        mut shots_output: Option<&mut dyn Write>,
+    ) -> Result<Self> {
+        let mut analysis = Self::new(config);
+        for event in reader {
+            if let Some(shot) = analysis.record(&event?) {
+                if let Some(output) = shots_output.as_mut() {
+                    writeln!(output, "{}", serde_json::to_string(&shot).unwrap())?;
+                }
+            }
+        }
+        Ok(analysis)
+    }
+
+    fn firmware_cost(&self, obstacle: &CompactObstacle) -> f64 {
+        let kind = response_kind(obstacle);
+        self.config
+            .firmware
+            .get(kind)
+            .copied()
+            .unwrap_or(self.config.firmware_default)
+    }
+
+    /// returns the critical path of the shot when the event ends it
+    pub fn record(&mut self, event: &TraceEvent) -> Option<ShotCriticalPath> {
+        let cycles = &self.config.cycles;
+        let clock_period = self.config.clock_period;
+        match event {
+            TraceEvent::Instruction(instruction) => {
+                let kind = instruction_kind(*instruction);
+                self.host.extend("interface", "write", self.config.bus_write);
+                self.accelerator.wait(&self.host);
+                let execute = 1 + cycles.execute_latency();
+                self.accelerator.extend("hardware", kind, execute as f64 * clock_period);
+                self.last_instruction = kind;
+            }
+            TraceEvent::MaximumGrowth(_) => self.host.extend("interface", "write", self.config.bus_write),
+            TraceEvent::Response { obstacle, .. } => {
+                let read = cycles.broadcast_delay + cycles.convergecast_delay;
+                (self.accelerator).extend("hardware", self.last_instruction, read as f64 * clock_period);
+                self.host.wait(&self.accelerator);
+                self.host.extend("interface", "read", self.config.bus_read);
+                let firmware_cost = self.firmware_cost(obstacle);
+                self.host.extend("firmware", response_kind(obstacle), firmware_cost);
+            }
+            TraceEvent::ShotEnd => return Some(self.finish_shot()),
+        }
+        None
+    }
+
+    fn finish_shot(&mut self) -> ShotCriticalPath {
+        self.host.wait(&self.accelerator);
+        let chain = std::mem::take(&mut self.host);
+        self.accelerator = Chain::default();
+        self.last_instruction = "unknown";
+        let shot = ShotCriticalPath {
+            latency: chain.time,
+            segments: (chain.segments.into_iter())
+                .map(|((category, name), duration)| (format!("{category}.{name}"), duration))
+                .collect(),
+        };
+        self.shots += 1;
+        self.total_latency += shot.latency;
+        self.max_latency = self.max_latency.max(shot.latency);
+        for (name, duration) in shot.segments.iter() {
+            *self.segments.entry(name.clone()).or_default() += duration;
+        }
+        *self.bottlenecks.entry(shot.bottleneck()).or_default() += 1;
+        shot
+    }
+
+    /// the time spent in a category summed over the shots
+    pub fn category(&self, category: &str) -> f64 {
+        (self.segments.iter())
+            .filter(|(name, _)| name.split('.').next() == Some(category))
+            .map(|(_, duration)| duration)
+            .sum()
+    }
+
+    /// the segments with the largest total time, in decreasing order
+    pub fn top_contributors(&self, top: usize) -> Vec<(&str, f64)> {
+        let mut contributors: Vec<(&str, f64)> = (self.segments.iter())
+            .map(|(name, &duration)| (name.as_str(), duration))
+            .collect();
+        contributors.sort_by(|a, b| b.1.total_cmp(&a.1));
+        contributors.truncate(top);
+        contributors
+    }
+
+    pub fn generate_report(&self, top: usize) -> serde_json::Value {
+        let share = |duration: f64| duration / self.total_latency.max(f64::MIN_POSITIVE);
+        let per_shot = |duration: f64| duration / self.shots.max(1) as f64;
+        let categories: serde_json::Map<String, serde_json::Value> = CRITICAL_PATH_CATEGORIES
+            .into_iter()
+            .map(|category| {
+                let duration = self.category(category);
+                let value = json!({ "average": per_shot(duration), "share": share(duration) });
+                (category.to_string(), value)
+            })
+            .collect();
+        let top_contributors: Vec<serde_json::Value> = (self.top_contributors(top).into_iter())
+            .map(|(name, duration)| json!({ "name": name, "average": per_shot(duration), "share": share(duration) }))
+            .collect();
+        json!({
+            "shots": self.shots,
+            "average_latency": per_shot(self.total_latency),
+            "max_latency": self.max_latency,
+            "categories": categories,
+            "top_contributors": top_contributors,
+            "bottlenecks": self.bottlenecks,
+        })
+    }
+
+    pub fn print(&self, top: usize) {
+        println!(
+            "shots: {}, average latency: {:.3}us, max latency: {:.3}us",
+            self.shots,
+            1e6 * self.total_latency / self.shots.max(1) as f64,
+            1e6 * self.max_latency
+        );
+        let total = self.total_latency.max(f64::MIN_POSITIVE);
+        for category in CRITICAL_PATH_CATEGORIES {
+            println!(
+                "    {category:>10}: {:>6.2}% of the latency, the bottleneck of {} shots",
+                100. * self.category(category) / total,
+                self.bottlenecks.get(category).unwrap_or(&0)
+            );
+        }
+        println!("top contributors:");
+        for (name, duration) in self.top_contributors(top) {
+            println!(
+                "    {name:>32}: {:>10.3}us per shot ({:>6.2}%)",
+                1e6 * duration / self.shots.max(1) as f64,
+                100. * duration / total
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use micro_blossom_nostd::instruction::*;
+    use micro_blossom_nostd::util::*;
+
+    fn config() -> CriticalPathConfig {
+        serde_json::from_value(json!({
+            "clock_period": 1e-8,
+            "cycles": { "broadcast_delay": 2, "convergecast_delay": 3 },
+            "bus_write": 1e-8,
+            "bus_read": 1e-7,
+            "firmware": { "conflict": 1e-6 },
+            "firmware_default": 0.,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn critical_path_single_shot() {
+        // cargo test critical_path_single_shot -- --nocapture
+        let mut analysis = CriticalPathAnalysis::new(config());
+        let events = [
+            TraceEvent::Instruction(Instruction32::reset()),
+            TraceEvent::Instruction(Instruction32::add_defect_vertex(ni!(1), ni!(0))),
+            TraceEvent::Instruction(Instruction32::find_obstacle()),
+            TraceEvent::Response {
+                obstacle: CompactObstacle::Conflict {
+                    node_1: ni!(0).option(),
+                    node_2: None.into(),
+                    touch_1: ni!(0).option(),
+                    touch_2: None.into(),
+                    vertex_1: ni!(1),
+                    vertex_2: ni!(2),
+                },
+                grown: 1,
+            },
+            TraceEvent::Instruction(Instruction32::set_speed(ni!(0), CompactGrowState::Stay)),
+        ];
+        for event in events.iter() {
+            assert_eq!(analysis.record(event), None);
+        }
+        let shot = analysis.record(&TraceEvent::ShotEnd).unwrap();
+        println!("{}", serde_json::to_string_pretty(&shot).unwrap());
+        // the accelerator executes every instruction right after its bus write, until the host waits for the obstacle
+        // of 1 + 5 cycles; the bus writes of `add_defect_vertex` and `find_obstacle` are hidden behind the accelerator
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+        assert!(close(shot.segments["interface.write"], 2e-8));
+        assert!(close(shot.segments["hardware.reset"], 1e-8));
+        assert!(close(shot.segments["hardware.add_defect_vertex"], 1e-8));
+        assert!(close(shot.segments["hardware.find_obstacle"], 6e-8));
+        assert!(close(shot.segments["interface.read"], 1e-7));
+        assert!(close(shot.segments["firmware.conflict"], 1e-6));
+        assert!(close(shot.segments["hardware.set_speed"], 1e-8));
+        let sum: f64 = shot.segments.values().sum();
+        assert!(close(sum, shot.latency));
+        assert!(close(shot.latency, 1.21e-6));
+        assert_eq!(shot.bottleneck(), "firmware");
+        assert_eq!(analysis.shots, 1);
+        assert_eq!(
+            analysis.top_contributors(1),
+            vec![("firmware.conflict", shot.segments["firmware.conflict"])]
+        );
+    }
+
+    /// a slow accelerator moves the critical path from the host to the hardware
+    #[test]
+    fn critical_path_hardware_bound() {
+        // cargo test critical_path_hardware_bound -- --nocapture
+        let mut config = config();
+        config.clock_period = 1e-6;
+        let mut analysis = CriticalPathAnalysis::new(config);
+        for _ in 0..3 {
+            analysis.record(&TraceEvent::Instruction(Instruction32::grow(2)));
+        }
+        analysis.record(&TraceEvent::Instruction(Instruction32::find_obstacle()));
+        analysis.record(&TraceEvent::Response {
+            obstacle: CompactObstacle::None,
+            grown: 0,
+        });
+        let shot = analysis.record(&TraceEvent::ShotEnd).unwrap();
+        // the instructions queue up in the accelerator, so only the first bus write is on the critical path
+        assert!((shot.segments["interface.write"] - 1e-8).abs() < 1e-12);
+        assert!((shot.category("hardware") - 9e-6).abs() < 1e-12);
+        assert_eq!(shot.bottleneck(), "hardware");
+        let report = analysis.generate_report(3);
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        assert_eq!(report["bottlenecks"]["hardware"], json!(1));
+        assert_eq!(report["top_contributors"][0]["name"], json!("hardware.find_obstacle"));
+        assert_eq!(report["top_contributors"][1]["name"], json!("hardware.grow"));
+    }
+}
@@ -0,0 +1,261 @@
+//! Decision Trace
+//!
+//! Compare the HLS and the normal code paths of the embedded primal module. The same syndrome corpus is decoded
+//! by the crate built with and without the `hls` feature, recording every obstacle together with the instructions
+//! that the primal module sends to the dual module when resolving it. Since the dual module is identical in both
+//! builds, the two traces must agree step by step until the HLS primal module reports an obstacle as unhandled
+//! (e.g., the recursive cases that Bambu HLS cannot synthesize), after which the rest of the shot is not compared.
+//!
+//! The HLS primal module cannot create defect nodes on the fly, so the recorder prepares a primal node for every
+//! loaded defect before solving; this is done in both builds so that the traces stay comparable.
+//!
+
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use fusion_blossom::example_codes::ExampleCode;
+use fusion_blossom::mwpm_solver::PrimalDualSolver;
+use fusion_blossom::util::*;
+use micro_blossom_nostd::interface::*;
+use micro_blossom_nostd::primal_nodes::PrimalNode;
+use micro_blossom_nostd::util::*;
+use serde::{Deserialize, Serialize};
+
+/// an instruction from the primal module to the dual module
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DualCall {
+    SetSpeed {
+        is_blossom: bool,
+        node: usize,
+        grow_state: String,
+    },
+    CreateBlossom {
+        blossom: usize,
+    },
+    ExpandBlossom {
+        blossom: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecisionStep {
+    pub obstacle: String,
+    /// whether the primal module reports the obstacle as resolved
+    pub handled: bool,
+    pub calls: Vec<DualCall>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShotTrace {
+    pub seed: u64,
+    pub defect_vertices: Vec<VertexIndex>,
+    pub steps: Vec<DecisionStep>,
+    /// false if the recording stops early because of an unhandled obstacle or `max_steps`
+    pub complete: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DecisionTrace {
+    /// whether the trace is recorded with the `hls` feature
+    pub hls: bool,
+    pub shots: Vec<ShotTrace>,
+}
+
+/// forward every call to the inner dual module while logging the instructions from the primal module
+pub struct DualRecorder<'a, D: DualInterface> {
+    pub inner: &'a mut D,
+    pub calls: Vec<DualCall>,
+}
+
+impl<'a, D: DualInterface> DualRecorder<'a, D> {
+    pub fn new(inner: &'a mut D) -> Self {
+        Self { inner, calls: vec![] }
+    }
+}
+
+impl<'a, D: DualInterface> DualInterface for DualRecorder<'a, D> {
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+    fn create_blossom(&mut self, primal_module: &impl PrimalInterface, blossom_index: CompactNodeIndex) {
+        self.calls.push(DualCall::CreateBlossom {
+            blossom: blossom_index.get() as usize,
+        });
+        self.inner.create_blossom(primal_module, blossom_index);
+    }
+    fn expand_blossom(&mut self, primal_module: &impl PrimalInterface, blossom_index: CompactNodeIndex) {
+        self.calls.push(DualCall::ExpandBlossom {
+            blossom: blossom_index.get() as usize,
+        });
+        self.inner.expand_blossom(primal_module, blossom_index);
+    }
+    fn set_speed(&mut self, is_blossom: bool, node_index: CompactNodeIndex, grow_state: CompactGrowState) {
+        self.calls.push(DualCall::SetSpeed {
+            is_blossom,
+            node: node_index.get() as usize,
+            grow_state: format!("{grow_state:?}"),
+        });
+        self.inner.set_speed(is_blossom, node_index, grow_state);
+    }
+    fn find_obstacle(&mut self) -> (CompactObstacle, CompactWeight) {
+        self.inner.find_obstacle()
+    }
+    fn add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) {
+        self.inner.add_defect(vertex, node);
+    }
+    fn read_node_dual(&mut self, node: CompactNodeIndex) -> Option<CompactWeight> {
+        self.inner.read_node_dual(node)
+    }
+    fn read_vertex_grown(&mut self, vertex: CompactVertexIndex) -> Option<CompactWeight> {
+        self.inner.read_vertex_grown(vertex)
+    }
+}
+
+/// decode `seeds` of the code and record the decisions of the primal module
+pub fn record_decisions(
+    code: &mut dyn ExampleCode,
+    seeds: impl IntoIterator<Item = u64>,
+    max_steps: usize,
+) -> DecisionTrace {
+    // layer fusion and offloading are disabled so that every defect is handled by the primal module
+    let mut solver = SolverEmbeddedComb::new(MicroBlossomSingle::new_code(code), json!({}));
+    let mut shots = vec![];
+    for seed in seeds {
+        let syndrome_pattern = code.generate_random_errors(seed);
+        solver.load_syndrome(&syndrome_pattern);
+        for node_index in 0..syndrome_pattern.defect_vertices.len() {
+            solver.primal_module.nodes.buffer[node_index] = Some(PrimalNode::new());
+        }
+        solver.primal_module.nodes.count_defects = syndrome_pattern.defect_vertices.len();
+        let mut steps = vec![];
+        let mut complete = false;
+        while steps.len() < max_steps {
+            let (obstacle, _) = solver.dual_module.find_obstacle();
+            if obstacle.is_none() {
+                complete = true;
+                break;
+            }
+            let mut recorder = DualRecorder::new(solver.dual_module.as_mut());
+            let handled = solver.primal_module.resolve(&mut recorder, obstacle.clone());
+            steps.push(DecisionStep {
+                obstacle: format!("{obstacle:?}"),
+                handled,
+                calls: recorder.calls,
+            });
+            if !handled {
+                break;
+            }
+        }
+        solver.clear();
+        shots.push(ShotTrace {
+            seed,
+            defect_vertices: syndrome_pattern.defect_vertices,
+            steps,
+            complete,
+        });
+    }
+    DecisionTrace {
+        hls: cfg!(feature = "hls"),
+        shots,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DecisionMismatch {
+    pub seed: u64,
+    /// the index of the first differing step, or the length of the shorter trace
+    pub step: usize,
+    pub reference: Option<DecisionStep>,
+    pub hls: Option<DecisionStep>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DecisionComparison {
+    pub shots: usize,
+    /// the number of steps that are handled by both and found identical
+    pub compared_steps: usize,
+    /// the shots where the HLS primal module stops at an unhandled obstacle
+    pub unsupported_shots: usize,
+    pub mismatches: Vec<DecisionMismatch>,
+}
+
+/// compare the trace of the normal build against the one of the HLS build, shot by shot
+pub fn compare_decisions(reference: &DecisionTrace, hls: &DecisionTrace) -> DecisionComparison {
+    assert_eq!(reference.shots.len(), hls.shots.len(), "the traces must use the same corpus");
+    let mut comparison = DecisionComparison {
+        shots: reference.shots.len(),
+        ..Default::default()
+    };
+    for (reference_shot, hls_shot) in reference.shots.iter().zip(hls.shots.iter()) {
+        assert_eq!(reference_shot.seed, hls_shot.seed, "the traces must use the same corpus");
+        assert_eq!(reference_shot.defect_vertices, hls_shot.defect_vertices);
+        let mut step = 0;
+        let mismatch = loop {
+            match (reference_shot.steps.get(step), hls_shot.steps.get(step)) {
+                (Some(_), Some(hls_step)) if !hls_step.handled => {
+                    comparison.unsupported_shots += 1;
+                    break false;
+                }
+                (Some(reference_step), Some(hls_step)) => {
+                    if reference_step != hls_step {
+                        break true;
+                    }
+                    comparison.compared_steps += 1;
+                }
+                (None, None) => break false,
+                // one of them has more steps, which is a mismatch unless both are truncated by `max_steps`
+                _ => break reference_shot.complete || hls_shot.complete,
+            }
+            step += 1;
+        };
+        if mismatch {
+            comparison.mismatches.push(DecisionMismatch {
+                seed: reference_shot.seed,
+                step,
+                reference: reference_shot.steps.get(step).cloned(),
+                hls: hls_shot.steps.get(step).cloned(),
+            });
+        }
+    }
+    comparison
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn decision_trace_compare() {
+        // cargo test decision_trace_compare -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let reference = record_decisions(&mut code, 0..100, usize::MAX);
+        assert!(reference.shots.iter().all(|shot| shot.complete || cfg!(feature = "hls")));
+        // a trace always agrees with itself
+        let comparison = compare_decisions(&reference, &reference);
+        println!("compared {} steps", comparison.compared_steps);
+        assert!(comparison.mismatches.is_empty());
+        assert!(comparison.compared_steps > 0);
+        // the traces round-trip through JSON, which is how the two builds exchange them
+        let loaded: DecisionTrace = serde_json::from_str(&serde_json::to_string(&reference).unwrap()).unwrap();
+        assert_eq!(loaded, reference);
+        // a modified decision is caught at the exact step
+        let mut modified = reference.clone();
+        let (shot_index, step_index) = modified
+            .shots
+            .iter()
+            .enumerate()
+            .find_map(|(shot_index, shot)| (!shot.steps.is_empty()).then_some((shot_index, shot.steps.len() - 1)))
+            .unwrap();
+        modified.shots[shot_index].steps[step_index]
+            .calls
+            .push(DualCall::ExpandBlossom { blossom: 0 });
+        let comparison = compare_decisions(&reference, &modified);
+        assert_eq!(comparison.mismatches.len(), 1);
+        assert_eq!(comparison.mismatches[0].step, step_index);
+        // an unhandled obstacle is not a mismatch
+        modified.shots[shot_index].steps[step_index].handled = false;
+        let comparison = compare_decisions(&reference, &modified);
+        assert!(comparison.mismatches.is_empty());
+        assert_eq!(comparison.unsupported_shots, 1);
+    }
+}
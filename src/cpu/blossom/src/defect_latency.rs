@@ -0,0 +1,196 @@
+//! Defect Latency
+//!
+//! Attribute the decoding latency to every single defect in streaming mode. A defect arrives when its measurement
+//! round is finished, i.e., layer `l` arrives at `l * round_interval`, and the decoder processes the rounds one by
+//! one as soon as they arrive and the previous round is done. The matching of a defect is committed at the end of
+//! the earliest round (no earlier than its arrival) after which its match target never changes again; this is the
+//! moment a conditional feed-forward operation could act on it. The last "round" is the final solve after all the
+//! layers are fused, where every remaining defect is committed.
+//!
+//! The processing time of each round is the wall time of [`SolverEmbeddedBoxed::step_round`], which is only
+//! meaningful relative to other runs of the same dual module.
+//!
+
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use fusion_blossom::dual_module::*;
+use fusion_blossom::mwpm_solver::PrimalDualSolver;
+use fusion_blossom::util::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DefectLatency {
+    pub vertex: VertexIndex,
+    /// the measurement round (fusion layer) of the defect, 0 if the vertex does not belong to any layer
+    pub round: usize,
+    /// the round at the end of which the matching is committed; `num_layers` means the final solve
+    pub commit_round: usize,
+    /// the time from the arrival of the round to the commit, in seconds
+    pub latency: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MatchTarget {
+    Peer(VertexIndex),
+    VirtualVertex(VertexIndex),
+}
+
+#[derive(Debug, Clone)]
+pub struct DefectLatencyTracker {
+    /// the time between the arrival of two consecutive measurement rounds, in seconds
+    pub round_interval: f64,
+    vertex_layer_id: BTreeMap<VertexIndex, usize>,
+    /// the latency of every defect in all the decoded shots
+    pub latencies: Vec<DefectLatency>,
+}
+
+impl DefectLatencyTracker {
+    pub fn new(graph: &MicroBlossomSingle, round_interval: f64) -> Self {
+        assert!(round_interval >= 0., "round interval must be non-negative");
+        Self {
+            round_interval,
+            vertex_layer_id: graph
+                .layer_fusion
+                .as_ref()
+                .map(|layer_fusion| layer_fusion.vertex_layer_id.clone())
+                .unwrap_or_default(),
+            latencies: vec![],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.latencies.clear();
+    }
+
+    /// the match target of every defect vertex that is matched at this moment
+    fn match_targets<Dual: SolverTrackedDual>(solver: &mut SolverEmbeddedBoxed<Dual>) -> BTreeMap<VertexIndex, MatchTarget> {
+        let defect_vertex = |node_ptr: &DualNodePtr| match node_ptr.read_recursive().class {
+            DualNodeClass::DefectVertex { defect_index } => defect_index,
+            DualNodeClass::Blossom { .. } => unreachable!("perfect matching only contains defect vertices"),
+        };
+        let perfect_matching = solver.perfect_matching();
+        let mut targets = BTreeMap::new();
+        for (node_1, node_2) in perfect_matching.peer_matchings.iter() {
+            let (vertex_1, vertex_2) = (defect_vertex(node_1), defect_vertex(node_2));
+            targets.insert(vertex_1, MatchTarget::Peer(vertex_2));
+            targets.insert(vertex_2, MatchTarget::Peer(vertex_1));
+        }
+        for (node, virtual_vertex) in perfect_matching.virtual_matchings.iter() {
+            targets.insert(defect_vertex(node), MatchTarget::VirtualVertex(*virtual_vertex));
+        }
+        targets
+    }
+
+    /// decode the syndrome round by round and record the latency of every defect; the solver is left finished
+    pub fn solve<Dual: SolverTrackedDual>(
+        &mut self,
+        solver: &mut SolverEmbeddedBoxed<Dual>,
+        syndrome_pattern: &SyndromePattern,
+    ) {
+        solver.load_syndrome(syndrome_pattern);
+        let mut clock = 0.;
+        let mut round_ends = vec![];
+        let mut snapshots = vec![];
+        loop {
+            // wait for the round to arrive
+            clock = f64::max(clock, round_ends.len() as f64 * self.round_interval);
+            let begin = Instant::now();
+            let fused = solver.step_round();
+            if !fused {
+                while solver.step() {}
+            }
+            clock += begin.elapsed().as_secs_f64();
+            round_ends.push(clock);
+            snapshots.push(Self::match_targets(solver));
+            if !fused {
+                break;
+            }
+        }
+        solver.finish();
+        let final_targets = snapshots.last().unwrap();
+        for &vertex in syndrome_pattern.defect_vertices.iter() {
+            let round = self.vertex_layer_id.get(&vertex).cloned().unwrap_or(0);
+            assert!(round < snapshots.len(), "streaming mode requires `support_layer_fusion`");
+            let target = final_targets.get(&vertex);
+            assert!(target.is_some(), "defect {vertex} is not matched after solving");
+            let mut commit_round = snapshots.len() - 1;
+            while commit_round > round && snapshots[commit_round - 1].get(&vertex) == target {
+                commit_round -= 1;
+            }
+            self.latencies.push(DefectLatency {
+                vertex,
+                round,
+                commit_round,
+                latency: round_ends[commit_round] - round as f64 * self.round_interval,
+            });
+        }
+    }
+
+    /// the distribution of the latencies, together with how many rounds the defects wait before being committed
+    pub fn generate_report(&self) -> serde_json::Value {
+        let mut latencies: Vec<f64> = self.latencies.iter().map(|defect| defect.latency).collect();
+        latencies.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let percentile = |ratio: f64| -> Option<f64> {
+            if latencies.is_empty() {
+                return None;
+            }
+            let index = ((latencies.len() - 1) as f64 * ratio).round() as usize;
+            Some(latencies[index])
+        };
+        let mut waited_rounds = BTreeMap::<usize, usize>::new();
+        for defect in self.latencies.iter() {
+            *waited_rounds.entry(defect.commit_round - defect.round).or_default() += 1;
+        }
+        json!({
+            "count": latencies.len(),
+            "mean": (!latencies.is_empty()).then(|| latencies.iter().sum::<f64>() / latencies.len() as f64),
+            "p50": percentile(0.5),
+            "p90": percentile(0.9),
+            "p99": percentile(0.99),
+            "p999": percentile(0.999),
+            "max": latencies.last(),
+            "waited_rounds": waited_rounds,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::*;
+
+    #[test]
+    fn defect_latency_streaming() {
+        // cargo test defect_latency_streaming -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 4, 0.03, 500);
+        let initializer = code.get_initializer();
+        let graph = MicroBlossomSingle::new_code(&code);
+        let num_layers = graph.layer_fusion.as_ref().unwrap().num_layers;
+        let config = json!({ "dual": { "sim_config": { "support_layer_fusion": true } } });
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), config);
+        let mut tracker = DefectLatencyTracker::new(&graph, 1e-6);
+        let mut serial = SolverSerial::new(&initializer);
+        let mut defect_count = 0;
+        for seed in 0..50 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            defect_count += syndrome_pattern.defect_vertices.len();
+            tracker.solve(&mut solver, &syndrome_pattern);
+            // streaming does not change the result
+            serial.solve(&syndrome_pattern);
+            assert_eq!(solver.sum_dual_variables(), serial.sum_dual_variables());
+            solver.clear();
+            serial.clear();
+        }
+        assert_eq!(tracker.latencies.len(), defect_count);
+        for defect in tracker.latencies.iter() {
+            assert!(defect.round <= defect.commit_round && defect.commit_round <= num_layers);
+            assert!(defect.latency >= 0.);
+        }
+        let report = tracker.generate_report();
+        println!("{report}");
+        assert_eq!(report["count"], json!(defect_count));
+    }
+}
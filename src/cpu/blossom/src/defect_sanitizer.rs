@@ -0,0 +1,231 @@
+//! Defect Sanitizer
+//!
+//! A glitch in the readout may report the same defect twice, a vertex index out of range, or a virtual vertex as a
+//! defect. None of them is a valid input of the dual module: a duplicated defect overwrites the node of the vertex
+//! and an invalid vertex indexes out of bounds. The sanitizer checks the defects of every shot before they are
+//! loaded and handles the invalid ones according to the policy, counting them per shot and in total. In streaming
+//! mode, the rounds of a shot are checked one by one as they arrive, see [`DefectSanitizer::sanitize_round`].
+//!
+//! The sanitizer is enabled by the `sanitize` field of the solver configuration, e.g., `{"sanitize":"Dedupe"}`.
+//!
+
+use crate::resources::*;
+use fusion_blossom::util::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SanitizePolicy {
+    /// drop the duplicated and invalid defects, keeping the first report of each vertex
+    #[default]
+    Dedupe,
+    /// do not decode a shot with any duplicated or invalid defect, i.e., load no defect at all
+    RejectShot,
+    /// same as [`SanitizePolicy::Dedupe`], but also print the dropped defects to the standard error
+    Log,
+}
+
+/// the invalid defects found in a shot, or accumulated over shots
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SanitizeCounts {
+    /// the repeated reports of the same vertex
+    pub duplicates: usize,
+    /// the vertex indices not in the graph
+    pub out_of_range: usize,
+    /// the virtual vertices reported as defects
+    pub virtual_defects: usize,
+    /// the number of rejected shots, at most 1 for a single shot
+    pub rejected_shots: usize,
+}
+
+impl SanitizeCounts {
+    pub fn is_clean(&self) -> bool {
+        self.duplicates == 0 && self.out_of_range == 0 && self.virtual_defects == 0
+    }
+
+    fn accumulate(&mut self, other: &Self) {
+        self.duplicates += other.duplicates;
+        self.out_of_range += other.out_of_range;
+        self.virtual_defects += other.virtual_defects;
+        self.rejected_shots += other.rejected_shots;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DefectSanitizer {
+    pub policy: SanitizePolicy,
+    /// the counts of the current shot
+    pub shot: SanitizeCounts,
+    pub total: SanitizeCounts,
+    vertex_num: usize,
+    virtual_vertices: BTreeSet<VertexIndex>,
+    /// the defects reported in the rounds of the current shot so far
+    visited: BTreeSet<VertexIndex>,
+}
+
+impl DefectSanitizer {
+    pub fn new(graph: &MicroBlossomSingle, policy: SanitizePolicy) -> Self {
+        Self {
+            policy,
+            shot: SanitizeCounts::default(),
+            total: SanitizeCounts::default(),
+            vertex_num: graph.vertex_num,
+            virtual_vertices: graph.virtual_vertices.iter().cloned().collect(),
+            visited: BTreeSet::new(),
+        }
+    }
+
+    pub fn reset_statistics(&mut self) {
+        self.total = SanitizeCounts::default();
+    }
+
+    /// the valid defects to load, in the original order
+    pub fn sanitize(&mut self, defect_vertices: &[VertexIndex]) -> Vec<VertexIndex> {
+        self.clear_shot();
+        self.sanitize_round(defect_vertices)
+    }
+
+    /// start a new shot, forgetting the counts and the defects of the rounds of the current one
+    pub fn clear_shot(&mut self) {
+        self.shot = SanitizeCounts::default();
+        self.visited.clear();
+    }
+
+    /// the valid defects of the next measurement round in streaming mode, in the original order; a defect reported
+    /// in an earlier round of the same shot counts as a duplicate. The rounds already decoded cannot be taken back, so
+    /// [`SanitizePolicy::RejectShot`] drops this round and all the later rounds of the shot
+    pub fn sanitize_round(&mut self, defect_vertices: &[VertexIndex]) -> Vec<VertexIndex> {
+        let mut counts = SanitizeCounts::default();
+        let mut sanitized = Vec::with_capacity(defect_vertices.len());
+        for &vertex_index in defect_vertices.iter() {
+            if vertex_index >= self.vertex_num {
+                counts.out_of_range += 1;
+            } else if self.virtual_vertices.contains(&vertex_index) {
+                counts.virtual_defects += 1;
+            } else if !self.visited.insert(vertex_index) {
+                counts.duplicates += 1;
+            } else {
+                sanitized.push(vertex_index);
+            }
+        }
+        if !counts.is_clean() {
+            match self.policy {
+                SanitizePolicy::Dedupe => {}
+                SanitizePolicy::RejectShot => {
+                    counts.rejected_shots = usize::from(self.shot.rejected_shots == 0);
+                }
+                SanitizePolicy::Log => eprintln!("[warning] invalid defects {counts:?} in {defect_vertices:?}"),
+            }
+        }
+        self.total.accumulate(&counts);
+        self.shot.accumulate(&counts);
+        if self.shot.rejected_shots > 0 {
+            sanitized.clear();
+        }
+        sanitized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwpm_solver::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::*;
+    use serde_json::json;
+
+    #[test]
+    fn defect_sanitizer_policies() {
+        // cargo test defect_sanitizer_policies -- --nocapture
+        let code = CodeCapacityPlanarCode::new(5, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let virtual_vertex = graph.virtual_vertices[0];
+        let defects = vec![7, 3, 7, 1000, virtual_vertex, 3, 12];
+        let mut sanitizer = DefectSanitizer::new(&graph, SanitizePolicy::Dedupe);
+        assert_eq!(sanitizer.sanitize(&defects), vec![7, 3, 12]);
+        let expected = SanitizeCounts {
+            duplicates: 2,
+            out_of_range: 1,
+            virtual_defects: 1,
+            rejected_shots: 0,
+        };
+        assert_eq!(sanitizer.shot, expected);
+        assert_eq!(sanitizer.sanitize(&[12, 7]), vec![12, 7]);
+        assert!(sanitizer.shot.is_clean());
+        assert_eq!(sanitizer.total, expected);
+        let mut sanitizer = DefectSanitizer::new(&graph, SanitizePolicy::RejectShot);
+        assert_eq!(sanitizer.sanitize(&defects), Vec::<VertexIndex>::new());
+        assert_eq!(sanitizer.shot.rejected_shots, 1);
+    }
+
+    /// the sanitized shot decodes the same as the clean one, with the counters in the result
+    #[test]
+    fn defect_sanitizer_solver() {
+        // cargo test defect_sanitizer_solver -- --nocapture
+        let code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let clean = SyndromePattern::new_vertices(vec![10, 11, 25, 30]);
+        let glitched = SyndromePattern::new_vertices(vec![10, 11, 11, 25, 30, graph.vertex_num + 5, 30]);
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), json!({}));
+        solver.solve(&clean);
+        let expected = solver.subgraph();
+        solver.clear();
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), json!({ "sanitize": "Dedupe" }));
+        solver.solve(&glitched);
+        let result = solver.result();
+        assert_eq!(result.subgraph, expected);
+        let counts = result.sanitized.unwrap();
+        assert_eq!((counts.duplicates, counts.out_of_range, counts.rejected_shots), (2, 1, 0));
+        solver.clear();
+        let mut solver = SolverEmbeddedComb::new(graph, json!({ "sanitize": "RejectShot" }));
+        solver.solve(&glitched);
+        let result = solver.result();
+        assert!(result.subgraph.is_empty());
+        assert_eq!(result.sanitized.unwrap().rejected_shots, 1);
+        println!("{}", solver.generate_profiler_report());
+    }
+
+    /// the streamed rounds are sanitized and shuffled like a whole shot, and a defect repeated in a later round counts
+    /// as a duplicate
+    #[test]
+    fn defect_sanitizer_stream_rounds() {
+        // cargo test defect_sanitizer_stream_rounds -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 4, 0.05, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let layer_fusion = graph.layer_fusion.clone().unwrap();
+        let config = json!({
+            "dual": { "sim_config": { "support_layer_fusion": true } },
+            "sanitize": "Dedupe",
+            "defect_order_seed": 7,
+        });
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), config);
+        let mut reference = SolverEmbeddedComb::new(graph.clone(), json!({}));
+        for seed in 0..20 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            let mut rounds = vec![vec![]; layer_fusion.num_layers];
+            for &vertex_index in syndrome_pattern.defect_vertices.iter() {
+                rounds[layer_fusion.vertex_layer_id[&vertex_index]].push(vertex_index);
+            }
+            // every round repeats the defects of the previous round and reports an invalid vertex
+            let mut previous: Vec<VertexIndex> = vec![];
+            for defect_vertices in rounds.iter() {
+                let mut glitched = defect_vertices.clone();
+                glitched.extend(previous.iter().cloned());
+                glitched.push(graph.vertex_num + 1);
+                solver.stream_round(&glitched);
+                previous = defect_vertices.clone();
+            }
+            solver.stream_finish();
+            reference.solve(&syndrome_pattern);
+            assert_eq!(solver.sum_dual_variables(), reference.sum_dual_variables());
+            let counts = solver.result().sanitized.unwrap();
+            let repeated = rounds[..layer_fusion.num_layers - 1]
+                .iter()
+                .map(|round| round.len())
+                .sum::<usize>();
+            assert_eq!((counts.duplicates, counts.out_of_range), (repeated, layer_fusion.num_layers));
+            solver.clear();
+            reference.clear();
+        }
+    }
+}
@@ -0,0 +1,301 @@
+//! Device Lock
+//!
+//! A Micro Blossom device has a single set of registers: two CLI invocations driving the same device would interleave
+//! their instructions and corrupt each other's decoding without any error. A [`DeviceLock`] is an advisory `flock` on
+//! a lock file per device in the lock directory (`MICRO_BLOSSOM_LOCK_DIR`, by default `micro-blossom-locks` in the
+//! temporary directory). The kernel decides the owner atomically and releases the lock when the owner exits for any
+//! reason, so a lock is never left behind by a crash. The file also records the PID of the owner together with the
+//! contexts it owns, but only as information: a second process is refused with a "device busy, held by PID" error.
+//!
+//! With `--force-take`, the lock is taken from a running owner anyway: a new lock file, already locked, atomically
+//! replaces the old one, whose `flock` is still held by the previous owner but no longer guards the device. The
+//! previous owner may have left its contexts in the middle of a shot, so the new owner resets them before use, and the
+//! previous owner finds out that it lost the device at its next shot instead of scribbling over the registers of the
+//! new owner.
+//!
+
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceLockError {
+    /// the device is locked by another running process
+    Busy {
+        device: String,
+        /// `None` if the owner has not recorded its PID yet
+        pid: Option<u32>,
+    },
+    /// the lock was taken by another process with `--force-take`
+    Lost {
+        device: String,
+        pid: Option<u32>,
+    },
+    Io {
+        device: String,
+        message: String,
+    },
+}
+
+impl std::fmt::Display for DeviceLockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Busy { device, pid: Some(pid) } => {
+                write!(f, "device {device} busy, held by PID {pid}; use --force-take to reset it")
+            }
+            Self::Busy { device, pid: None } => write!(f, "device {device} busy; use --force-take to reset it"),
+            Self::Lost { device, pid: Some(pid) } => write!(f, "device {device} was taken by PID {pid}"),
+            Self::Lost { device, pid: None } => write!(f, "device {device} is no longer locked"),
+            Self::Io { device, message } => write!(f, "cannot lock device {device}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for DeviceLockError {}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceLockConfig {
+    /// the name of the device, e.g., the address of the board; by default the name of the driver
+    #[serde(default = "Default::default")]
+    pub device: Option<String>,
+    /// take the device even if another running process holds it, and reset its state
+    #[serde(default = "Default::default")]
+    pub force_take: bool,
+}
+
+/// the content of the lock file, only for information: the ownership is decided by the `flock` alone
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceLockHolder {
+    pub pid: u32,
+    /// distinguishes the sessions of the same process
+    pub session: u64,
+    /// the contexts owned by the session
+    pub contexts: Vec<u16>,
+}
+
+pub struct DeviceLock {
+    pub device: String,
+    pub path: PathBuf,
+    /// the locked file, whose `flock` is released when it is closed
+    file: File,
+    pub holder: DeviceLockHolder,
+    /// the holder that the lock is forcibly taken from, whose contexts must be reset
+    pub taken_from: Option<DeviceLockHolder>,
+}
+
+/// try to `flock` the file without blocking, `Ok(false)` if another open file holds a conflicting lock
+fn try_flock(file: &File, exclusive: bool) -> std::io::Result<bool> {
+    let operation = if exclusive { libc::LOCK_EX } else { libc::LOCK_SH };
+    if unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let error = std::io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(libc::EWOULDBLOCK) => Ok(false),
+        _ => Err(error),
+    }
+}
+
+/// whether the open file is still the one at `path`, i.e., it has not been replaced by `--force-take`
+fn is_same_file(file: &File, path: &Path) -> std::io::Result<bool> {
+    let (opened, current) = match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(opened), Ok(current)) => (opened, current),
+        (_, Err(error)) if error.kind() == ErrorKind::NotFound => return Ok(false),
+        (Err(error), _) | (_, Err(error)) => return Err(error),
+    };
+    Ok(opened.dev() == current.dev() && opened.ino() == current.ino())
+}
+
+pub fn lock_directory() -> PathBuf {
+    match std::env::var_os("MICRO_BLOSSOM_LOCK_DIR") {
+        Some(directory) => PathBuf::from(directory),
+        None => std::env::temp_dir().join("micro-blossom-locks"),
+    }
+}
+
+impl DeviceLock {
+    pub fn lock_path(device: &str) -> PathBuf {
+        let name: String = (device.chars())
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .collect();
+        lock_directory().join(format!("{name}.lock"))
+    }
+
+    /// the recorded holder of the device, `None` if the device is not locked or the holder is not recorded yet
+    pub fn holder_of(device: &str) -> Option<DeviceLockHolder> {
+        let path = Self::lock_path(device);
+        let file = File::open(&path).ok()?;
+        if try_flock(&file, false).unwrap_or(true) {
+            return None; // nobody holds the lock, and the shared lock is released when the file is closed
+        }
+        let content = std::fs::read_to_string(&path).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// lock the device for the contexts, see the module documentation for `force_take`
+    pub fn acquire(device: &str, contexts: Vec<u16>, force_take: bool) -> Result<Self, DeviceLockError> {
+        let io_error = |error: std::io::Error| DeviceLockError::Io {
+            device: device.to_string(),
+            message: error.to_string(),
+        };
+        let path = Self::lock_path(device);
+        std::fs::create_dir_all(lock_directory()).map_err(io_error)?;
+        let holder = DeviceLockHolder {
+            pid: std::process::id(),
+            session: rand::random(),
+            contexts,
+        };
+        let content = serde_json::to_string(&holder).unwrap();
+        let locked = |file: File, taken_from: Option<DeviceLockHolder>| Self {
+            device: device.to_string(),
+            path: path.clone(),
+            file,
+            holder: holder.clone(),
+            taken_from,
+        };
+        loop {
+            // never truncate before locking, which would wipe the record of a running owner
+            let mut file = (OpenOptions::new().read(true).write(true).create(true).truncate(false))
+                .open(&path)
+                .map_err(io_error)?;
+            if try_flock(&file, true).map_err(io_error)? {
+                // the file may be replaced by `--force-take` between opening and locking it
+                if !is_same_file(&file, &path).map_err(io_error)? {
+                    continue;
+                }
+                file.set_len(0).map_err(io_error)?;
+                file.write_all(content.as_bytes()).map_err(io_error)?;
+                return Ok(locked(file, None));
+            }
+            let previous = Self::holder_of(device);
+            if !force_take {
+                return Err(DeviceLockError::Busy {
+                    device: device.to_string(),
+                    pid: previous.map(|previous| previous.pid),
+                });
+            }
+            // lock and fill a new file before it replaces the old one, so that it is never seen unlocked
+            let new_path = path.with_extension(format!("{}.{}", holder.pid, holder.session));
+            let mut file = (OpenOptions::new().read(true).write(true).create_new(true))
+                .open(&new_path)
+                .map_err(io_error)?;
+            assert!(try_flock(&file, true).map_err(io_error)?, "a new file is never locked");
+            file.write_all(content.as_bytes()).map_err(io_error)?;
+            std::fs::rename(&new_path, &path).map_err(io_error)?;
+            return Ok(locked(file, previous));
+        }
+    }
+
+    /// check that the lock is not taken by another session, e.g., before every shot
+    pub fn check(&self) -> Result<(), DeviceLockError> {
+        match is_same_file(&self.file, &self.path) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(DeviceLockError::Lost {
+                device: self.device.clone(),
+                pid: Self::holder_of(&self.device).map(|holder| holder.pid),
+            }),
+            Err(error) => Err(DeviceLockError::Io {
+                device: self.device.clone(),
+                message: error.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_device(name: &str) -> String {
+        format!("device_lock_{name}_{}", std::process::id())
+    }
+
+    #[test]
+    fn device_lock_busy() {
+        // cargo test device_lock_busy -- --nocapture
+        let device = test_device("busy");
+        let lock = DeviceLock::acquire(&device, vec![0, 1], false).unwrap();
+        assert!(lock.taken_from.is_none());
+        assert_eq!(DeviceLock::holder_of(&device), Some(lock.holder.clone()));
+        let error = DeviceLock::acquire(&device, vec![0], false).err().unwrap();
+        println!("{error}");
+        assert_eq!(
+            error,
+            DeviceLockError::Busy {
+                device: device.clone(),
+                pid: Some(std::process::id())
+            }
+        );
+        drop(lock);
+        assert_eq!(DeviceLock::holder_of(&device), None);
+        DeviceLock::acquire(&device, vec![0], false).unwrap();
+    }
+
+    #[test]
+    fn device_lock_force_take() {
+        // cargo test device_lock_force_take -- --nocapture
+        let device = test_device("force_take");
+        let lock = DeviceLock::acquire(&device, vec![2], false).unwrap();
+        let taken = DeviceLock::acquire(&device, vec![0], true).unwrap();
+        assert_eq!(taken.taken_from.as_ref(), Some(&lock.holder));
+        assert!(taken.check().is_ok());
+        let error = lock.check().unwrap_err();
+        println!("{error}");
+        assert!(matches!(error, DeviceLockError::Lost { pid: Some(_), .. }));
+        // closing the previous session does not release the lock of the new owner
+        drop(lock);
+        assert_eq!(DeviceLock::holder_of(&device), Some(taken.holder.clone()));
+        let error = DeviceLock::acquire(&device, vec![0], false).err().unwrap();
+        assert_eq!(
+            error,
+            DeviceLockError::Busy {
+                device: device.clone(),
+                pid: Some(std::process::id())
+            }
+        );
+    }
+
+    /// exactly one of the processes racing for the same device gets it
+    #[test]
+    fn device_lock_race() {
+        // cargo test device_lock_race -- --nocapture
+        let device = test_device("race");
+        let contenders = 16;
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(contenders));
+        let handles: Vec<_> = (0..contenders)
+            .map(|_| {
+                let (device, barrier) = (device.clone(), barrier.clone());
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    let result = DeviceLock::acquire(&device, vec![0], false);
+                    barrier.wait(); // keep the lock until every contender has tried
+                    result.is_ok()
+                })
+            })
+            .collect();
+        let winners = handles.into_iter().map(|handle| handle.join().unwrap());
+        assert_eq!(winners.filter(|&won| won).count(), 1);
+    }
+
+    /// the file left by a process that no longer exists is not locked, so it is taken over without `--force-take`
+    #[test]
+    fn device_lock_stale() {
+        // cargo test device_lock_stale -- --nocapture
+        let device = test_device("stale");
+        std::fs::create_dir_all(lock_directory()).unwrap();
+        let stale = DeviceLockHolder {
+            pid: i32::MAX as u32,
+            session: 0,
+            contexts: vec![0],
+        };
+        std::fs::write(DeviceLock::lock_path(&device), serde_json::to_string(&stale).unwrap()).unwrap();
+        assert_eq!(DeviceLock::holder_of(&device), None);
+        let lock = DeviceLock::acquire(&device, vec![0], false).unwrap();
+        assert!(lock.taken_from.is_none());
+        assert!(lock.check().is_ok());
+    }
+}
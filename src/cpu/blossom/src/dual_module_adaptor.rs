@@ -7,6 +7,11 @@ use micro_blossom_nostd::util::*;
 
 pub trait DualInterfaceWithInitializer {
     fn new_with_initializer(initializer: &SolverInitializer) -> Self;
+    /// the other conflicts found together with the last obstacle, which the primal module resolves in the same round;
+    /// a dual module without batch reporting always returns an empty list
+    fn take_obstacle_batch(&mut self) -> Vec<CompactObstacle> {
+        vec![]
+    }
 }
 
 /// dual module with native initializer from a MicroBlossomSingle graph and configurations
@@ -83,7 +88,42 @@ impl<D: DualInterface + DualInterfaceWithInitializer> DualModuleImpl for DualMod
         );
         let (obstacle, grown) = self.dual_module.find_obstacle();
         self.grown = grown as Weight;
-        let max_update_length = match obstacle {
+        let batch = self.dual_module.take_obstacle_batch();
+        let is_conflict = matches!(obstacle, CompactObstacle::Conflict { .. });
+        let mut group_max_update_length = GroupMaxUpdateLength::new();
+        group_max_update_length.add(self.max_update_length_of(obstacle));
+        if is_conflict {
+            for obstacle in batch.into_iter() {
+                group_max_update_length.add(self.max_update_length_of(obstacle));
+            }
+        }
+        group_max_update_length
+    }
+
+    fn grow(&mut self, _length: Weight) {
+        unimplemented!("RTL dual module doesn't allow explicit grow command")
+    }
+
+    fn prepare_nodes_shrink(&mut self, _nodes_circle: &[DualNodePtr]) -> &mut Vec<SyncRequest> {
+        self.sync_requests.clear();
+        &mut self.sync_requests
+    }
+}
+
+impl<D: DualInterface + DualInterfaceWithInitializer> DualModuleAdaptor<D> {
+    /// wrap a dual module constructed with its own configuration
+    pub fn new_with_dual_module(dual_module: D) -> Self {
+        Self {
+            dual_module,
+            nodes: vec![],
+            sync_requests: vec![],
+            grown: 0,
+        }
+    }
+
+    /// convert an obstacle into the dual nodes of the interface
+    fn max_update_length_of(&self, obstacle: CompactObstacle) -> MaxUpdateLength {
+        match obstacle {
             CompactObstacle::GrowLength { length } => MaxUpdateLength::NonZeroGrow((length as Weight, false)),
             CompactObstacle::Conflict {
                 node_1,
@@ -118,19 +158,7 @@ impl<D: DualInterface + DualInterfaceWithInitializer> DualModuleImpl for DualMod
                 MaxUpdateLength::BlossomNeedExpand(self.nodes[blossom.get() as usize].clone())
             }
             CompactObstacle::None => MaxUpdateLength::NonZeroGrow((Weight::MAX, false)),
-        };
-        let mut group_max_update_length = GroupMaxUpdateLength::new();
-        group_max_update_length.add(max_update_length);
-        group_max_update_length
-    }
-
-    fn grow(&mut self, _length: Weight) {
-        unimplemented!("RTL dual module doesn't allow explicit grow command")
-    }
-
-    fn prepare_nodes_shrink(&mut self, _nodes_circle: &[DualNodePtr]) -> &mut Vec<SyncRequest> {
-        self.sync_requests.clear();
-        &mut self.sync_requests
+        }
     }
 }
 
@@ -199,9 +227,19 @@ pub mod tests {
         defect_vertices: Vec<VertexIndex>,
         constructor: impl FnOnce(&SolverInitializer, &Vec<VisualizePosition>) -> Solver,
     ) -> Solver {
-        println!("{defect_vertices:?}");
         let half_weight = 500;
         let mut code = CodeCapacityPlanarCode::new(d, 0.1, half_weight);
+        dual_module_code_optional_viz(&mut code, visualize_filename, defect_vertices, constructor)
+    }
+
+    /// same as [`dual_module_standard_optional_viz`] but on a given code, e.g., with modified weights
+    pub fn dual_module_code_optional_viz<Solver: PrimalDualSolver + Sized>(
+        code: &mut dyn ExampleCode,
+        visualize_filename: Option<String>,
+        defect_vertices: Vec<VertexIndex>,
+        constructor: impl FnOnce(&SolverInitializer, &Vec<VisualizePosition>) -> Solver,
+    ) -> Solver {
+        println!("{defect_vertices:?}");
         let mut visualizer = match visualize_filename.as_ref() {
             Some(visualize_filename) => {
                 let visualizer = Visualizer::new(
@@ -238,4 +276,47 @@ pub mod tests {
         assert_eq!(total_weight, standard_total_weight);
         solver
     }
+
+    /// find a path of `length` edges between regular vertices, returning the edges and the vertices along the path;
+    /// useful for constructing zero-weight scenarios without hard-coding the vertex indices of a code
+    #[allow(clippy::unnecessary_cast)]
+    pub fn find_regular_path(code: &dyn ExampleCode, length: usize) -> (Vec<EdgeIndex>, Vec<VertexIndex>) {
+        let (vertices, edges) = code.immutable_vertices_edges();
+        fn extend(
+            vertices: &[CodeVertex],
+            edges: &[CodeEdge],
+            path: &mut (Vec<EdgeIndex>, Vec<VertexIndex>),
+            length: usize,
+        ) -> bool {
+            if path.0.len() == length {
+                return true;
+            }
+            let last = *path.1.last().unwrap();
+            for &edge_index in vertices[last as usize].neighbor_edges.iter() {
+                let (left, right) = edges[edge_index as usize].vertices;
+                let peer = if left == last { right } else { left };
+                if vertices[peer as usize].is_virtual || path.1.contains(&peer) {
+                    continue;
+                }
+                path.0.push(edge_index);
+                path.1.push(peer);
+                if extend(vertices, edges, path, length) {
+                    return true;
+                }
+                path.0.pop();
+                path.1.pop();
+            }
+            false
+        }
+        for (vertex_index, vertex) in vertices.iter().enumerate() {
+            if vertex.is_virtual {
+                continue;
+            }
+            let mut path = (vec![], vec![vertex_index as VertexIndex]);
+            if extend(vertices, edges, &mut path, length) {
+                return path;
+            }
+        }
+        panic!("no path of {length} edges between regular vertices")
+    }
 }
@@ -0,0 +1,309 @@
+//! Asynchronous Dual Driver
+//!
+//! The drivers of the remote backends, e.g., [`crate::dual_module_looper`] over the TCP link to the Scala simulation,
+//! wait for a full round trip on every instruction, so a sweep over many shots is dominated by the network latency
+//! rather than the simulation. The host answers the requests in the order it receives them, which allows the
+//! requests of several contexts to be pipelined over the same connection: [`AsyncLink`] writes every request as soon
+//! as it is issued and matches the responses in order.
+//!
+//! [`AsyncLooperDriver`] issues the instructions of one context of the looper host and awaits the responses, and
+//! [`BlockingLooperDriver`] adapts it to the synchronous solver loop by blocking the calling thread on the runtime.
+//! Running the solver of every context on its own thread overlaps their round trips, e.g.,
+//!
+//! ```ignore
+//! let runtime = tokio::runtime::Runtime::new()?;
+//! let host = AsyncLooperHost::new(graph, config, runtime.handle().clone())?; // `context_depth` in the sim_config
+//! let dual_module = DualModuleStackless::new(DualDriverTracked::<_, MAX_NODE_NUM>::new(host.context(1)));
+//! ```
+//!
+//! Only available with the `async_driver` feature.
+//!
+
+use crate::dual_module_looper::*;
+use crate::resources::*;
+use crate::simulation_tcp_client::*;
+use micro_blossom_nostd::dual_driver_tracked::*;
+use micro_blossom_nostd::dual_module_stackless::*;
+use micro_blossom_nostd::instruction::*;
+use micro_blossom_nostd::interface::*;
+use micro_blossom_nostd::util::*;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, oneshot};
+
+type Responder = oneshot::Sender<Result<String>>;
+
+fn link_closed() -> Error {
+    Error::new(ErrorKind::BrokenPipe, "the asynchronous link is closed")
+}
+
+/// a line-based connection whose requests are pipelined: a request is written without waiting for the responses of
+/// the previous ones, and the responses are delivered in the order of the requests
+pub struct AsyncLink {
+    requests: mpsc::UnboundedSender<(String, Responder)>,
+    /// the requests written but not yet answered
+    in_flight: Arc<AtomicUsize>,
+    /// the maximum number of requests in flight at the same time, i.e., how much the round trips overlap
+    max_in_flight: Arc<AtomicUsize>,
+}
+
+impl AsyncLink {
+    /// take over a connected stream, which must not be read or written elsewhere afterwards; must be called within
+    /// the context of a tokio runtime, e.g., after [`Handle::enter`]
+    pub fn new(stream: std::net::TcpStream) -> Result<Self> {
+        stream.set_nonblocking(true)?;
+        let (read_half, mut write_half) = tokio::net::TcpStream::from_std(stream)?.into_split();
+        let (requests, mut request_receiver) = mpsc::unbounded_channel::<(String, Responder)>();
+        let (pending, mut pending_receiver) = mpsc::unbounded_channel::<Responder>();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let (writer_in_flight, writer_max_in_flight) = (in_flight.clone(), max_in_flight.clone());
+        tokio::spawn(async move {
+            while let Some((line, responder)) = request_receiver.recv().await {
+                match write_half.write_all(format!("{line}\n").as_bytes()).await {
+                    Ok(()) => {
+                        let count = writer_in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        writer_max_in_flight.fetch_max(count, Ordering::SeqCst);
+                        // the reader stops when the connection is lost, and the responder is dropped with the error
+                        let _ = pending.send(responder);
+                    }
+                    Err(error) => {
+                        let _ = responder.send(Err(error));
+                    }
+                }
+            }
+        });
+        let reader_in_flight = in_flight.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(read_half).lines();
+            while let Some(responder) = pending_receiver.recv().await {
+                let response = match lines.next_line().await {
+                    Ok(Some(line)) => Ok(line),
+                    Ok(None) => Err(Error::new(ErrorKind::UnexpectedEof, "the host closed the connection")),
+                    Err(error) => Err(error),
+                };
+                reader_in_flight.fetch_sub(1, Ordering::SeqCst);
+                let _ = responder.send(response);
+            }
+        });
+        Ok(Self {
+            requests,
+            in_flight,
+            max_in_flight,
+        })
+    }
+
+    /// send a line and wait for the line of response
+    pub async fn request(&self, line: String) -> Result<String> {
+        let (responder, response) = oneshot::channel();
+        self.requests.send((line, responder)).map_err(|_| link_closed())?;
+        response.await.map_err(|_| link_closed())?
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight.load(Ordering::SeqCst)
+    }
+}
+
+/// the instructions of one context of the looper host, see [`DualModuleLooperDriver`] for the synchronous version
+pub struct AsyncLooperDriver {
+    pub link: Arc<AsyncLink>,
+    pub context_id: u16,
+    pub instruction_count: usize,
+}
+
+impl AsyncLooperDriver {
+    pub fn new(link: Arc<AsyncLink>, context_id: u16) -> Self {
+        Self {
+            link,
+            context_id,
+            instruction_count: 0,
+        }
+    }
+
+    async fn execute(&mut self, instruction: Instruction32, maximum_growth: u16) -> Result<OutputData> {
+        let input = InputData {
+            instruction: instruction.into(),
+            context_id: self.context_id,
+            maximum_growth,
+        };
+        let line = self
+            .link
+            .request(format!("execute: {}", serde_json::to_string(&input)?))
+            .await?;
+        self.instruction_count += 1;
+        let output: OutputData = serde_json::from_str(line.as_str())?;
+        debug_assert_eq!(output.context_id, self.context_id, "the response belongs to another context");
+        Ok(output)
+    }
+
+    pub async fn execute_instruction(&mut self, instruction: Instruction32) -> Result<()> {
+        self.execute(instruction, 0).await.map(|_| ())
+    }
+
+    pub async fn execute_find_obstacle(&mut self, maximum_growth: u16) -> Result<(CompactObstacle, CompactWeight)> {
+        let output = self.execute(Instruction32::find_obstacle(), maximum_growth).await?;
+        Ok(output.obstacle())
+    }
+}
+
+/// adapt [`AsyncLooperDriver`] to the synchronous solver loop; must not be called from within the runtime
+pub struct BlockingLooperDriver {
+    pub driver: AsyncLooperDriver,
+    runtime: Handle,
+}
+
+impl BlockingLooperDriver {
+    pub fn new(driver: AsyncLooperDriver, runtime: Handle) -> Self {
+        Self { driver, runtime }
+    }
+
+    fn execute_instruction(&mut self, instruction: Instruction32) {
+        self.runtime.block_on(self.driver.execute_instruction(instruction)).unwrap();
+    }
+
+    fn execute_find_obstacle(&mut self, maximum_growth: u16) -> (CompactObstacle, CompactWeight) {
+        self.runtime
+            .block_on(self.driver.execute_find_obstacle(maximum_growth))
+            .unwrap()
+    }
+}
+
+impl DualStacklessDriver for BlockingLooperDriver {
+    fn reset(&mut self) {
+        self.execute_instruction(Instruction32::reset());
+        self.driver.instruction_count = 0;
+    }
+    fn set_speed(&mut self, _is_blossom: bool, node: CompactNodeIndex, speed: CompactGrowState) {
+        self.execute_instruction(Instruction32::set_speed(node, speed));
+    }
+    fn set_blossom(&mut self, node: CompactNodeIndex, blossom: CompactNodeIndex) {
+        self.execute_instruction(Instruction32::set_blossom(node, blossom));
+    }
+    fn find_obstacle(&mut self) -> (CompactObstacle, CompactWeight) {
+        self.execute_find_obstacle(u16::MAX)
+    }
+    fn add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) {
+        self.execute_instruction(Instruction32::add_defect_vertex(vertex, node));
+    }
+}
+
+impl DualTrackedDriver for BlockingLooperDriver {
+    fn find_conflict(&mut self, maximum_growth: CompactWeight) -> (CompactObstacle, CompactWeight) {
+        self.execute_find_obstacle(maximum_growth as u16)
+    }
+}
+
+/// a looper host shared by the drivers of all its contexts
+pub struct AsyncLooperHost {
+    /// keeps the simulation running until the host is dropped
+    pub client: SimulationTcpClient,
+    pub link: Arc<AsyncLink>,
+    runtime: Handle,
+}
+
+impl AsyncLooperHost {
+    pub fn new(micro_blossom: MicroBlossomSingle, config: DualLooperConfig, runtime: Handle) -> Result<Self> {
+        let client = SimulationTcpClient::new("LooperHost", micro_blossom, config.name, config.sim_config)?;
+        let stream = client.try_clone_stream()?;
+        let link = {
+            let _guard = runtime.enter();
+            Arc::new(AsyncLink::new(stream)?)
+        };
+        Ok(Self { client, link, runtime })
+    }
+
+    pub fn context_depth(&self) -> usize {
+        self.client.sim_config.context_depth
+    }
+
+    /// the driver of a context, which is reset before returning
+    pub fn context(&self, context_id: usize) -> BlockingLooperDriver {
+        assert!(context_id < self.context_depth(), "context {context_id} does not exist");
+        let driver = AsyncLooperDriver::new(self.link.clone(), context_id as u16);
+        let mut driver = BlockingLooperDriver::new(driver, self.runtime.clone());
+        driver.reset();
+        driver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::{BufRead, Write};
+    use std::net::TcpListener;
+
+    /// a host that replies to every request after reading `batch` of them, echoing the context and the instruction
+    fn batched_echo_host(batch: usize, total: usize) -> (std::net::TcpStream, std::thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            let mut reader = std::io::BufReader::new(socket.try_clone().unwrap());
+            let mut writer = socket;
+            let mut requests = vec![];
+            for _ in 0..total {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let input: InputData = serde_json::from_str(line.trim().strip_prefix("execute: ").unwrap()).unwrap();
+                requests.push(input);
+                if requests.len() < batch {
+                    continue;
+                }
+                for input in requests.drain(..) {
+                    let output = json!({
+                        "context_id": input.context_id,
+                        "max_growable": input.instruction & 0xFF,
+                        "conflict": { "node1": 0, "node2": null, "touch1": 0, "touch2": null, "vertex1": 0,
+                            "vertex2": 0, "valid": false },
+                        "grown": 0,
+                    });
+                    writeln!(writer, "{output}").unwrap();
+                }
+            }
+        });
+        (std::net::TcpStream::connect(address).unwrap(), handle)
+    }
+
+    /// the host only answers once both contexts have sent their request, which deadlocks without pipelining
+    #[test]
+    fn dual_module_async_pipelined_contexts() {
+        // cargo test dual_module_async_pipelined_contexts -- --nocapture
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (stream, host) = batched_echo_host(2, 8);
+        let link = {
+            let _guard = runtime.enter();
+            Arc::new(AsyncLink::new(stream).unwrap())
+        };
+        let threads: Vec<_> = (0..2)
+            .map(|context_id| {
+                let driver = AsyncLooperDriver::new(link.clone(), context_id);
+                let mut driver = BlockingLooperDriver::new(driver, runtime.handle().clone());
+                std::thread::spawn(move || {
+                    (0..4)
+                        .map(|_| driver.execute_find_obstacle(100))
+                        .map(|(obstacle, _)| obstacle)
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let instruction: u32 = Instruction32::find_obstacle().into();
+        let expected = CompactObstacle::GrowLength {
+            length: (instruction & 0xFF) as CompactWeight,
+        };
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), vec![expected.clone(); 4]);
+        }
+        host.join().unwrap();
+        assert_eq!(link.in_flight(), 0);
+        assert_eq!(link.max_in_flight(), 2);
+    }
+}
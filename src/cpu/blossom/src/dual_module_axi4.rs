@@ -4,6 +4,7 @@
 //! It simulates the complete MicroBlossom module, which provides a AXI4 memory-mapped interface.
 //!
 
+use crate::device_lock::*;
 use crate::mwpm_solver::*;
 use crate::resources::*;
 use crate::simulation_tcp_client::*;
@@ -23,6 +24,7 @@ use serde::*;
 pub struct DualModuleAxi4Driver {
     pub client: SimulationTcpClient,
     pub context_id: u16,
+    pub device_lock: Option<DeviceLock>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,6 +34,17 @@ pub struct DualAxi4Config {
     pub sim_config: SimulationConfig,
     #[serde(default = "random_name_16")]
     pub name: String,
+    /// own the device exclusively among the processes on this machine, see [`DeviceLock`];
+    /// enabled by default, and disabled only by an explicit `null`
+    #[serde(default = "dual_axi4_config_default::device_lock")]
+    pub device_lock: Option<DeviceLockConfig>,
+}
+
+pub mod dual_axi4_config_default {
+    use crate::device_lock::DeviceLockConfig;
+    pub fn device_lock() -> Option<DeviceLockConfig> {
+        Some(DeviceLockConfig::default())
+    }
 }
 
 pub type DualModuleAxi4 = DualModuleStackless<DualDriverTracked<DualModuleAxi4Driver, MAX_NODE_NUM>>;
@@ -47,14 +60,42 @@ impl SolverTrackedDual for DualModuleAxi4Driver {
     fn get_pre_matchings(&self, belonging: DualModuleInterfaceWeak) -> PerfectMatching {
         self.client.get_pre_matchings(belonging)
     }
+    fn reset_profiler(&mut self) {
+        self.clear_hardware_counters().unwrap();
+    }
+    fn hardware_counters(&self) -> Option<HardwareCounters> {
+        Some(self.get_hardware_counters().unwrap())
+    }
+    fn supports_dual_readback(&self) -> bool {
+        true
+    }
 }
 
 impl DualModuleAxi4Driver {
     pub fn new(micro_blossom: MicroBlossomSingle, config: DualAxi4Config) -> std::io::Result<Self> {
+        let context_depth = config.sim_config.context_depth as u16;
+        // lock the device before touching any register
+        let device_lock = match config.device_lock.as_ref() {
+            Some(lock_config) => {
+                let device = lock_config.device.as_ref().unwrap_or(&config.name);
+                let lock = DeviceLock::acquire(device, (0..context_depth).collect(), lock_config.force_take)
+                    .map_err(|error| std::io::Error::new(std::io::ErrorKind::Other, error))?;
+                Some(lock)
+            }
+            None => None,
+        };
         let mut value = Self {
             client: SimulationTcpClient::new("MicroBlossomHost", micro_blossom, config.name, config.sim_config)?,
             context_id: 0,
+            device_lock,
         };
+        if let Some(taken_from) = value.device_lock.as_ref().and_then(|lock| lock.taken_from.as_ref()) {
+            eprintln!(
+                "[warning] device taken from PID {}, resetting all the contexts",
+                taken_from.pid
+            );
+            value.reset_all(context_depth)?;
+        }
         value.reset();
         Ok(value)
     }
@@ -161,6 +202,16 @@ impl DualModuleAxi4Driver {
         self.memory_read_32(48)
     }
 
+    pub fn get_hardware_counters(&self) -> std::io::Result<HardwareCounters> {
+        HardwareCounters::read(|address| self.memory_read_32(address))
+    }
+    pub fn clear_hardware_counters(&self) -> std::io::Result<()> {
+        for address in HardwareCounters::ADDRESSES {
+            self.memory_write_32(address, 0)?;
+        }
+        Ok(())
+    }
+
     pub fn sanity_check(&mut self) -> std::io::Result<()> {
         let error_counter = self.get_error_counter()?;
         if error_counter > 0 {
@@ -207,6 +258,9 @@ impl DualModuleAxi4Driver {
 
 impl DualStacklessDriver for DualModuleAxi4Driver {
     fn reset(&mut self) {
+        if let Some(device_lock) = self.device_lock.as_ref() {
+            device_lock.check().unwrap_or_else(|error| panic!("{error}"));
+        }
         self.execute_instruction(Instruction32::reset()).unwrap();
         // find obstacle to make sure the reset instruction is flushed
         self.get_single_readout().unwrap();
@@ -259,6 +313,15 @@ impl DualStacklessDriver for DualModuleAxi4Driver {
         self.execute_instruction(Instruction32::add_defect_vertex(vertex, node))
             .unwrap();
     }
+    fn read_vertex_grown(&mut self, vertex: CompactVertexIndex) -> Option<CompactWeight> {
+        self.execute_instruction(Instruction32::read_vertex_grown(vertex)).unwrap();
+        // reading the load time waits for all the instructions of this context; the selected vertex then reports its
+        // grown value as the 16 bits growable value, i.e., the upper half of the maximum growth field
+        let base = self.context_base_address();
+        self.memory_read_64(base).unwrap();
+        let grown = (self.memory_read_32(base + 16).unwrap() >> 16) as u16;
+        (grown != u16::MAX).then_some(grown as CompactWeight)
+    }
 }
 
 impl DualTrackedDriver for DualModuleAxi4Driver {
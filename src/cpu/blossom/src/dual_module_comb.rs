@@ -8,25 +8,39 @@
 //!
 
 use crate::dual_module_adaptor::*;
+use crate::dual_module_comb_assertion::*;
+use crate::dual_module_comb_context::*;
+use crate::dual_module_comb_cycles::*;
 use crate::dual_module_comb_edge::*;
+use crate::dual_module_comb_fault_injection::*;
+use crate::dual_module_comb_mirror::*;
+use crate::dual_module_comb_multi_beat::*;
 use crate::dual_module_comb_offloading::*;
+use crate::dual_module_comb_schedule::*;
+use crate::dual_module_comb_sequence::*;
+use crate::dual_module_comb_vcd::*;
 use crate::dual_module_comb_vertex::*;
 use crate::mwpm_solver::*;
 use crate::resources::*;
 use crate::simulation_tcp_client::*;
+use crate::snapshot_delta::*;
 use crate::util::*;
 use fusion_blossom::dual_module::*;
 use fusion_blossom::pointers::*;
 use fusion_blossom::primal_module::*;
 use fusion_blossom::util::*;
 use fusion_blossom::visualize::*;
+use micro_blossom_nostd::conflict_queue::*;
 use micro_blossom_nostd::dual_driver_tracked::*;
 use micro_blossom_nostd::dual_module_stackless::*;
 use micro_blossom_nostd::interface::*;
+use micro_blossom_nostd::primal_policy::*;
 use micro_blossom_nostd::util::*;
 use serde::*;
 use serde_json::json;
-use std::collections::BTreeSet;
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
 
 pub struct DualModuleCombDriver {
     pub initializer: SolverInitializer,
@@ -41,6 +55,131 @@ pub struct DualModuleCombDriver {
     /// only enabled when `config.log_instructions` is true
     pub profiler_instruction_history: Vec<Instruction>,
     pub profiler_response_history: Vec<(CompactObstacle, CompactWeight)>,
+    /// buffers the other conflicts found in the same round as the reported one
+    pub conflict_queue: ConflictQueue<MAX_CONFLICT_QUEUE_DEPTH>,
+    /// the other non-overlapping conflicts found by the last `FindObstacle`, only maintained when
+    /// `config.batch_obstacles` is set; see [`DualModuleCombDriver::take_obstacle_batch`]
+    pub obstacle_batch: Vec<CompactObstacle>,
+    /// the number of conflicts reported in a batch besides the first one
+    pub batched_obstacles: usize,
+    /// the defects resolved by the offloading units versus escalated to the primal module
+    pub pre_matching_statistics: PreMatchingStatistics,
+    /// only enabled when `config.sequence_check` is set
+    pub sequenced_bus: Option<SequencedBus>,
+    /// the number of executed instructions of each type since the last `clear`
+    pub instruction_counts: BTreeMap<&'static str, usize>,
+    /// latched when a grow length does not fit in [`CompactWeight`], only with the `checked_weight` feature
+    pub weight_overflow: Cell<bool>,
+    /// latched when the interface desynchronizes and cannot be recovered, only with `config.sequence_check`; the
+    /// remaining instructions of the shot are dropped
+    pub desynchronized: bool,
+    /// only enabled when `config.schedule` is set
+    pub scheduler: Option<InstructionScheduler>,
+    /// the enabled observers in the order of calling, see [`CombObserver`]
+    pub observers: Vec<Box<dyn CombObserver>>,
+    /// only enabled when `config.context_depth` is larger than 1
+    pub context_bank: Option<ContextBank>,
+    /// the region whose obstacles are reported first by the following `FindObstacle`, see
+    /// [`DualCombConfig::region_size`]
+    pub region_preference: Option<usize>,
+    /// the vertices occupied by a node, only maintained when `config.sparse` is set; the other vertices are idle
+    pub active_vertices: BTreeSet<VertexIndex>,
+    /// the vertices and edges whose signals may be cached by the last instruction in sparse evaluation, see
+    /// [`DualModuleCombDriver::signal_region`]; the signals of all the others are invalidated
+    pub cached_region: (Vec<VertexIndex>, Vec<EdgeIndex>),
+    /// set when a snapshot evaluates the signals of every vertex and edge, so that the next instruction in sparse
+    /// evaluation invalidates all of them
+    pub all_signals_cached: Cell<bool>,
+}
+
+pub const MAX_CONFLICT_QUEUE_DEPTH: usize = 64;
+
+/// an optional subsystem attached to the execution of [`DualModuleCombDriver`], e.g., the assertion hooks, the cycle
+/// counter or the VCD dumper; it lives in its own module and is enabled by its own entry of [`DualCombConfig`], and
+/// the dual module only calls the hooks below
+pub trait CombObserver: ObserverAny {
+    /// the key of its report in the profiler
+    fn name(&self) -> &'static str;
+    /// before an instruction changes the registers
+    fn before_execute(&mut self, _driver: &DualModuleCombDriver) {}
+    /// after the signals of an instruction are evaluated, before the registers are updated
+    fn signals_evaluated(&mut self, _driver: &DualModuleCombDriver) {}
+    /// after the registers are updated by an instruction; the observers are called in order, and the others can be
+    /// reached through [`DualModuleCombDriver::observer_mut`]
+    fn after_execute(&mut self, _driver: &mut DualModuleCombDriver) {}
+    /// the response of an instruction reading an obstacle as received by the driver, e.g., over a narrow bus
+    fn transfer_response(&mut self, response: CompactObstacle) -> CompactObstacle {
+        response
+    }
+    /// whether the signals of the vertices and edges outside the sparse evaluation are read
+    fn reads_all_signals(&self) -> bool {
+        false
+    }
+    /// the snapshot to emit instead of the complete one
+    fn emit_snapshot(&self, _abbrev: bool, snapshot: serde_json::Value) -> serde_json::Value {
+        snapshot
+    }
+    /// a full reset of the dual module, i.e., the end of a shot
+    fn clear(&mut self) {}
+    fn reset_profiler(&mut self) {}
+    fn generate_report(&self) -> Option<serde_json::Value> {
+        None
+    }
+}
+
+/// downcast an observer to its type, see [`DualModuleCombDriver::observer`]
+pub trait ObserverAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any> ObserverAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// an invalid [`DualCombConfig`], reported by [`DualModuleCombDriver::try_new`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CombConfigError {
+    /// the configuration is not valid JSON of a [`DualCombConfig`]
+    Format { message: String },
+    /// an option is out of its range
+    OutOfRange { option: &'static str, message: String },
+    /// two options cannot be enabled together
+    Incompatible {
+        option: &'static str,
+        other: &'static str,
+        reason: &'static str,
+    },
+    /// the resource of an option cannot be acquired, e.g., the VCD file
+    Unavailable { option: &'static str, message: String },
+}
+
+impl std::fmt::Display for CombConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Format { message } => write!(f, "invalid dual module configuration: {message}"),
+            Self::OutOfRange { option, message } => write!(f, "invalid `{option}`: {message}"),
+            Self::Incompatible { option, other, reason } => {
+                write!(f, "`{option}` is not compatible with `{other}`: {reason}")
+            }
+            Self::Unavailable { option, message } => write!(f, "cannot enable `{option}`: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for CombConfigError {}
+
+impl From<CombConfigError> for DualDriverError {
+    fn from(error: CombConfigError) -> Self {
+        Self::InvalidConfig {
+            message: error.to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,6 +192,62 @@ pub struct DualCombConfig {
     pub log_responses: bool,
     #[serde(default = "Default::default")]
     pub sim_config: SimulationConfig,
+    /// the number of conflicts reported to the primal module per round; 1 means only a single conflict is reported
+    /// and the rest are found again by re-querying
+    #[serde(default = "dual_comb_config_default::conflict_queue_depth")]
+    pub conflict_queue_depth: usize,
+    /// report all the conflicts found in the same round that share no node, so that the primal module resolves them
+    /// in a single round trip; unlike the conflict queue, they are handed over to the primal module at once
+    #[serde(default = "Default::default")]
+    pub batch_obstacles: bool,
+    /// the order of resolving the obstacles found in the same round, see [`PrimalPolicy`]
+    #[serde(default = "Default::default")]
+    pub primal_policy: PrimalPolicyType,
+    /// tag every instruction with a sequence number to detect lost or reordered messages on the simulated bus, see
+    /// [`SequencedBus`]; the hardware drivers do not support it yet
+    #[serde(default = "Default::default")]
+    pub sequence_check: Option<SequenceCheckConfig>,
+    /// evaluate the assertion hooks after every instruction, see [`AssertionHooks`]
+    #[serde(default = "Default::default")]
+    pub assertions: Option<AssertionConfig>,
+    /// count the clock cycles of the executed instructions with the given pipeline parameters, see [`CycleCounter`]
+    #[serde(default = "Default::default")]
+    pub cycles: Option<CycleConfig>,
+    /// reorder and fuse the instructions of the primal-dual loop to issue fewer of them, see [`InstructionScheduler`]
+    #[serde(default = "Default::default")]
+    pub schedule: Option<ScheduleConfig>,
+    /// partition the vertices into regions of this many consecutive indices, e.g., the vertices of a hardware context;
+    /// `FindObstacle` reports the obstacles touching its preferred region before the others
+    #[serde(default = "Default::default")]
+    pub region_size: Option<usize>,
+    /// only evaluate the vertices and edges whose state may change in an instruction, i.e., around the active
+    /// vertices and those addressed by the instruction; an idle vertex or edge keeps its registers and reports an
+    /// unbounded growth, so the result is the same as scanning all of them
+    #[serde(default = "Default::default")]
+    pub sparse: bool,
+    /// dump the registers after every instruction into a VCD waveform, see [`VcdDumper`]
+    #[serde(default = "Default::default")]
+    pub vcd: Option<VcdConfig>,
+    /// flip register bits after the chosen instructions to model single-event upsets, see [`FaultInjector`]
+    #[serde(default = "Default::default")]
+    pub faults: Option<FaultInjectionConfig>,
+    /// only emit the vertices and edges changed since the last snapshot, see [`DeltaSnapshotter`]
+    #[serde(default = "Default::default")]
+    pub delta_snapshot: bool,
+    /// read the obstacles over several bus beats with latency, see [`MultiBeatReadout`]
+    #[serde(default = "Default::default")]
+    pub multi_beat: Option<MultiBeatConfig>,
+    /// split the graph into partitions with mirrored boundary vertices, see [`BoundaryMirror`]
+    #[serde(default = "Default::default")]
+    pub mirror: Option<MirrorConfig>,
+    /// the number of independent decoding problems interleaved on this dual module, see [`ContextBank`]
+    #[serde(default = "dual_comb_config_default::context_depth")]
+    pub context_depth: usize,
+    /// with offloading, also pre-match a defect to the boundary through a non-defect vertex in the middle, see
+    /// [`OffloadingFinder::find_chain_match`]; the units are not in the graph configuration because the hardware
+    /// does not implement them yet
+    #[serde(default = "Default::default")]
+    pub chain_offloading: bool,
 }
 
 impl Default for DualCombConfig {
@@ -61,6 +256,94 @@ impl Default for DualCombConfig {
     }
 }
 
+impl DualCombConfig {
+    /// check the ranges of the options and the combinations of the optional subsystems that cannot work together
+    pub fn validate(&self) -> Result<(), CombConfigError> {
+        let incompatible = |conflicting: bool, option, other, reason| match conflicting {
+            true => Err(CombConfigError::Incompatible { option, other, reason }),
+            false => Ok(()),
+        };
+        if !(1..=MAX_CONFLICT_QUEUE_DEPTH).contains(&self.conflict_queue_depth) {
+            return Err(CombConfigError::OutOfRange {
+                option: "conflict_queue_depth",
+                message: format!("{} is not in [1, {MAX_CONFLICT_QUEUE_DEPTH}]", self.conflict_queue_depth),
+            });
+        }
+        if self.context_depth == 0 {
+            return Err(CombConfigError::OutOfRange {
+                option: "context_depth",
+                message: "at least one context is required".to_string(),
+            });
+        }
+        if let Some(multi_beat) = self.multi_beat.as_ref() {
+            if ![4, 8].contains(&multi_beat.beat_bytes) {
+                return Err(CombConfigError::OutOfRange {
+                    option: "multi_beat",
+                    message: format!("the bus is either 32 or 64 bits wide, not {} bytes", multi_beat.beat_bytes),
+                });
+            }
+        }
+        let (queued, sim_config) = (self.conflict_queue_depth > 1, &self.sim_config);
+        let queue_reason = "conflict queue validation is based on vertex registers only";
+        incompatible(
+            queued && sim_config.support_offloading,
+            "conflict_queue_depth",
+            "support_offloading",
+            queue_reason,
+        )?;
+        incompatible(
+            queued && sim_config.support_layer_fusion,
+            "conflict_queue_depth",
+            "support_layer_fusion",
+            queue_reason,
+        )?;
+        incompatible(
+            queued && self.batch_obstacles,
+            "batch_obstacles",
+            "conflict_queue_depth",
+            "the batched conflicts are reported to the primal module directly and should not be queued",
+        )?;
+        let sparse_reason = "the idle vertices are not evaluated";
+        incompatible(
+            self.sparse && sim_config.support_offloading,
+            "sparse",
+            "support_offloading",
+            sparse_reason,
+        )?;
+        incompatible(
+            self.sparse && sim_config.support_layer_fusion,
+            "sparse",
+            "support_layer_fusion",
+            sparse_reason,
+        )?;
+        incompatible(
+            self.sparse && (self.cycles.as_ref()).is_some_and(|cycles| cycles.reduction.is_some()),
+            "sparse",
+            "cycles.reduction",
+            "the reduction tree reduces the responses of every vertex and edge",
+        )?;
+        incompatible(
+            self.sparse && self.faults.is_some(),
+            "sparse",
+            "faults",
+            "sparse evaluation does not see the faults injected into the idle vertices",
+        )?;
+        let context_reason = "the queued or batched conflicts are not saved per context";
+        incompatible(
+            self.context_depth > 1 && queued,
+            "context_depth",
+            "conflict_queue_depth",
+            context_reason,
+        )?;
+        incompatible(
+            self.context_depth > 1 && self.batch_obstacles,
+            "context_depth",
+            "batch_obstacles",
+            context_reason,
+        )
+    }
+}
+
 pub mod dual_comb_config_default {
     pub fn log_instructions() -> bool {
         false
@@ -68,6 +351,12 @@ pub mod dual_comb_config_default {
     pub fn log_responses() -> bool {
         false
     }
+    pub fn conflict_queue_depth() -> usize {
+        1
+    }
+    pub fn context_depth() -> usize {
+        1
+    }
 }
 
 pub type DualModuleComb = DualModuleStackless<DualDriverTracked<DualModuleCombDriver, MAX_NODE_NUM>>;
@@ -77,21 +366,135 @@ impl DualInterfaceWithInitializer for DualModuleComb {
     fn new_with_initializer(initializer: &SolverInitializer) -> Self {
         DualModuleStackless::new(DualDriverTracked::new(DualModuleCombDriver::new_empty(initializer)))
     }
+    fn take_obstacle_batch(&mut self) -> Vec<CompactObstacle> {
+        self.driver.driver.take_obstacle_batch()
+    }
 }
 
 impl SolverTrackedDual for DualModuleCombDriver {
     fn new_from_graph_config(graph: MicroBlossomSingle, config: serde_json::Value) -> Self {
         Self::new(graph, serde_json::from_value(config).unwrap())
     }
+    fn try_new_from_graph_config(graph: MicroBlossomSingle, config: serde_json::Value) -> Result<Self, DualDriverError> {
+        let config = serde_json::from_value(config).map_err(|error| CombConfigError::Format {
+            message: error.to_string(),
+        })?;
+        Ok(Self::try_new(graph, config)?)
+    }
     fn reset_profiler(&mut self) {
         self.profiler_instruction_history.clear();
         self.profiler_response_history.clear();
+        self.conflict_queue.reset_statistics();
+        self.batched_obstacles = 0;
+        self.pre_matching_statistics = PreMatchingStatistics::default();
+        if let Some(sequenced_bus) = self.sequenced_bus.as_mut() {
+            sequenced_bus.reset_profiler();
+        }
+        if let Some(scheduler) = self.scheduler.as_mut() {
+            scheduler.statistics = ScheduleStatistics::default();
+        }
+        if let Some(context_bank) = self.context_bank.as_mut() {
+            context_bank.statistics = ContextStatistics::default();
+        }
+        for observer in self.observers.iter_mut() {
+            observer.reset_profiler();
+        }
     }
     fn generate_profiler_report(&self) -> serde_json::Value {
-        json!({
+        let sequenced_bus = self.sequenced_bus.as_ref();
+        let mut report = json!({
             "history": self.profiler_instruction_history,
+            "instruction_counts": self.instruction_counts,
             "conflicts": self.profiler_response_history,
-        })
+            "conflict_queue": self.conflict_queue.statistics,
+            "batched_obstacles": self.batched_obstacles,
+            "pre_matching": self.pre_matching_statistics.generate_report(),
+            "sequence": sequenced_bus.map(|sequenced_bus| &sequenced_bus.sequencer.statistics),
+            "duplicated_instructions": sequenced_bus.map(|sequenced_bus| sequenced_bus.sequence_filter.duplicated),
+            "schedule": self.scheduler.as_ref().map(|scheduler| &scheduler.statistics),
+            "contexts": self.context_bank.as_ref().map(|context_bank| &context_bank.statistics),
+        });
+        for observer in self.observers.iter() {
+            if let Some(observer_report) = observer.generate_report() {
+                report[observer.name()] = observer_report;
+            }
+        }
+        report
+    }
+    fn instruction_counts(&self) -> Option<BTreeMap<String, usize>> {
+        Some(
+            self.instruction_counts
+                .iter()
+                .map(|(&name, &count)| (name.to_string(), count))
+                .collect(),
+        )
+    }
+    fn clock_cycles(&self) -> Option<u64> {
+        self.observer::<CycleCounter>()
+            .map(|cycle_counter| cycle_counter.shot_cycles())
+    }
+    fn supports_dual_readback(&self) -> bool {
+        true
+    }
+    fn weight_overflowed(&self) -> bool {
+        self.weight_overflow.get()
+    }
+    fn desynchronized(&self) -> bool {
+        self.desynchronized
+    }
+    fn freeze_vertex_range(&mut self, begin: VertexIndex, end: VertexIndex) -> Result<(), DualDriverError> {
+        self.execute_instruction(Instruction::FreezeVertexRange { begin, end });
+        Ok(())
+    }
+    fn set_edge_weight(&mut self, edge_index: EdgeIndex, weight: Weight) -> Result<(), DualDriverError> {
+        let edge = &self.edges[edge_index];
+        let covered = self.vertices[edge.left_index].registers.grown + self.vertices[edge.right_index].registers.grown;
+        if weight < 0 || weight < covered {
+            return Err(DualDriverError::InvalidArgument {
+                operation: "set_edge_weight",
+                message: format!("weight {weight} of edge {edge_index} is negative or below its covered growth {covered}"),
+            });
+        }
+        self.execute_instruction(Instruction::SetEdgeWeight {
+            edge: edge_index,
+            weight,
+        });
+        Ok(())
+    }
+    fn remove_defect(&mut self, vertex_index: VertexIndex) -> Result<(), DualDriverError> {
+        let registers = &self.vertices[vertex_index].registers;
+        let invalid = |message: String| DualDriverError::InvalidArgument {
+            operation: "remove_defect",
+            message,
+        };
+        if !registers.is_defect {
+            return Err(invalid(format!("vertex {vertex_index} is not a defect")));
+        }
+        let node = registers.root_index.unwrap();
+        if registers.node_index != Some(node) {
+            return Err(invalid(format!("defect {vertex_index} is inside a blossom")));
+        }
+        self.execute_instruction(Instruction::RemoveDefectVertex {
+            vertex: vertex_index,
+            node,
+        });
+        Ok(())
+    }
+    fn release_nodes(&mut self, nodes: &[NodeIndex]) -> Result<bool, DualDriverError> {
+        let occupies_live_vertex = self.vertices.iter().any(|vertex| {
+            let registers = &vertex.registers;
+            !registers.is_frozen && nodes.iter().any(|&node| registers.root_index == Some(node))
+        });
+        if occupies_live_vertex {
+            return Ok(false);
+        }
+        for &node in nodes.iter() {
+            self.execute_instruction(Instruction::ReleaseNode { node });
+        }
+        Ok(true)
+    }
+    fn finish_shot(&mut self) {
+        self.record_pre_matching_statistics();
     }
     fn fuse_layer(&mut self, layer_id: usize) {
         self.execute_instruction(Instruction::LoadDefectsExternal {
@@ -100,36 +503,39 @@ impl SolverTrackedDual for DualModuleCombDriver {
         });
     }
     fn get_pre_matchings(&self, belonging: DualModuleInterfaceWeak) -> PerfectMatching {
-        let edges = self.pre_matching_edges();
+        let defect_node = |vertex: &Vertex| {
+            DualNodePtr::new_value(DualNode {
+                index: vertex.registers.node_index.unwrap(),
+                class: DualNodeClass::DefectVertex {
+                    defect_index: vertex.vertex_index,
+                },
+                grow_state: DualNodeGrowState::Stay,
+                defect_size: nonzero::nonzero!(1usize),
+                parent_blossom: None,
+                dual_variable_cache: (0, 0),
+                belonging: belonging.clone(),
+            })
+        };
         let mut perfect_matching = PerfectMatching::default();
-        for edge_index in edges.into_iter() {
+        // a chain matches the defect at one end with the virtual vertex at the other end, not along each of its edges
+        let mut chain_edges = BTreeSet::new();
+        for (defect_index, virtual_index, edges) in self.pre_matching_chains() {
+            chain_edges.extend(edges);
+            perfect_matching
+                .virtual_matchings
+                .push((defect_node(&self.vertices[defect_index]), virtual_index));
+        }
+        for edge_index in self.pre_matching_edges().into_iter() {
+            if chain_edges.contains(&edge_index) {
+                continue;
+            }
             let edge = &self.edges[edge_index];
             let left_vertex = &self.vertices[edge.left_index];
             let right_vertex = &self.vertices[edge.right_index];
             if !left_vertex.registers.is_virtual && !right_vertex.registers.is_virtual {
-                let left_node = DualNodePtr::new_value(DualNode {
-                    index: left_vertex.registers.node_index.unwrap(),
-                    class: DualNodeClass::DefectVertex {
-                        defect_index: left_vertex.vertex_index,
-                    },
-                    grow_state: DualNodeGrowState::Stay,
-                    defect_size: nonzero::nonzero!(1usize),
-                    parent_blossom: None,
-                    dual_variable_cache: (0, 0),
-                    belonging: belonging.clone(),
-                });
-                let right_node = DualNodePtr::new_value(DualNode {
-                    index: right_vertex.registers.node_index.unwrap(),
-                    class: DualNodeClass::DefectVertex {
-                        defect_index: right_vertex.vertex_index,
-                    },
-                    grow_state: DualNodeGrowState::Stay,
-                    defect_size: nonzero::nonzero!(1usize),
-                    parent_blossom: None,
-                    dual_variable_cache: (0, 0),
-                    belonging: belonging.clone(),
-                });
-                perfect_matching.peer_matchings.push((left_node, right_node));
+                perfect_matching
+                    .peer_matchings
+                    .push((defect_node(left_vertex), defect_node(right_vertex)));
             } else {
                 assert!(
                     !left_vertex.registers.is_virtual || !right_vertex.registers.is_virtual,
@@ -140,20 +546,9 @@ impl SolverTrackedDual for DualModuleCombDriver {
                 } else {
                     (left_vertex, right_vertex)
                 };
-                let regular_node = DualNodePtr::new_value(DualNode {
-                    index: regular_vertex.registers.node_index.unwrap(),
-                    class: DualNodeClass::DefectVertex {
-                        defect_index: regular_vertex.vertex_index,
-                    },
-                    grow_state: DualNodeGrowState::Stay,
-                    defect_size: nonzero::nonzero!(1usize),
-                    parent_blossom: None,
-                    dual_variable_cache: (0, 0),
-                    belonging: belonging.clone(),
-                });
                 perfect_matching
                     .virtual_matchings
-                    .push((regular_node, virtual_vertex.vertex_index));
+                    .push((defect_node(regular_vertex), virtual_vertex.vertex_index));
             }
         }
         perfect_matching
@@ -162,6 +557,11 @@ impl SolverTrackedDual for DualModuleCombDriver {
 
 impl DualModuleCombDriver {
     pub fn new(graph: MicroBlossomSingle, config: DualCombConfig) -> Self {
+        Self::try_new(graph, config).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    pub fn try_new(graph: MicroBlossomSingle, config: DualCombConfig) -> Result<Self, CombConfigError> {
+        config.validate()?;
         let virtual_vertices: BTreeSet<VertexIndex> = graph.virtual_vertices.iter().cloned().collect();
         let mut all_incident_edges: Vec<Vec<EdgeIndex>> = vec![vec![]; graph.vertex_num];
         for (edge_index, &WeightedEdge { l, r, .. }) in graph.weighted_edges.iter().enumerate() {
@@ -170,6 +570,44 @@ impl DualModuleCombDriver {
             }
         }
         let initializer = graph.get_initializer();
+        // the inert vertices do not report any response
+        let response_count =
+            all_incident_edges.iter().filter(|edges| !edges.is_empty()).count() + graph.weighted_edges.len();
+        // a node index is below twice the number of vertices and must fit in `compact_index`
+        assert!(
+            graph.vertex_num * 2 <= CompactVertexNum::MAX as usize,
+            "the {} vertices do not fit in the {}-bit indices, disable the `u16_interface_index` feature",
+            graph.vertex_num,
+            CompactVertexNum::BITS
+        );
+        // the order matters: the faults are injected before the other observers see the registers, and the assertion
+        // hooks attribute their violations to the injected faults
+        let mut observers: Vec<Box<dyn CombObserver>> = vec![];
+        if let Some(faults) = config.faults.clone() {
+            observers.push(Box::new(FaultInjector::new(faults, &graph)?));
+        }
+        if let Some(vcd) = config.vcd.as_ref() {
+            let vcd_dumper = VcdDumper::new(vcd, &graph).map_err(|error| CombConfigError::Unavailable {
+                option: "vcd",
+                message: error.to_string(),
+            })?;
+            observers.push(Box::new(vcd_dumper));
+        }
+        if let Some(cycles) = config.cycles.clone() {
+            observers.push(Box::new(CycleCounter::new(cycles, response_count)));
+        }
+        if let Some(assertions) = config.assertions.clone() {
+            observers.push(Box::new(AssertionHooks::new(assertions)));
+        }
+        if let Some(mirror) = config.mirror.clone() {
+            observers.push(Box::new(BoundaryMirror::new(mirror, &graph)?));
+        }
+        if let Some(multi_beat) = config.multi_beat.clone() {
+            observers.push(Box::new(MultiBeatReadout::new(multi_beat, &graph)));
+        }
+        if config.delta_snapshot {
+            observers.push(Box::new(DeltaSnapshotter::new()));
+        }
         let mut comb_driver = Self {
             initializer: initializer.clone(),
             vertices: all_incident_edges
@@ -188,8 +626,23 @@ impl DualModuleCombDriver {
                 .collect(),
             maximum_growth: CompactWeight::MAX,
             offloading_units: vec![],
-            instruction: Instruction::FindObstacle,
+            instruction: Instruction::FindObstacle { region_preference: None },
             graph: graph.clone(),
+            conflict_queue: ConflictQueue::new(config.conflict_queue_depth),
+            obstacle_batch: vec![],
+            batched_obstacles: 0,
+            pre_matching_statistics: PreMatchingStatistics::default(),
+            sequenced_bus: config.sequence_check.clone().map(SequencedBus::new),
+            instruction_counts: BTreeMap::new(),
+            weight_overflow: Cell::new(false),
+            desynchronized: false,
+            scheduler: config.schedule.clone().map(InstructionScheduler::new),
+            observers,
+            context_bank: (config.context_depth > 1).then(|| ContextBank::new(config.context_depth)),
+            region_preference: None,
+            active_vertices: BTreeSet::new(),
+            cached_region: (vec![], vec![]),
+            all_signals_cached: Cell::new(false),
             config,
             profiler_instruction_history: vec![],
             profiler_response_history: vec![],
@@ -208,11 +661,16 @@ impl DualModuleCombDriver {
                 comb_driver.vertices[*vertex_index].layer_id = Some(*layer_id);
             }
         }
+        if comb_driver.config.chain_offloading {
+            let mut chain_offloading = OffloadingFinder::new();
+            chain_offloading.find_chain_match(&initializer);
+            offloading_vec.extend(chain_offloading.0);
+        }
         if comb_driver.config.sim_config.support_offloading {
             comb_driver.set_offloading_units(&initializer, offloading_vec);
         }
         comb_driver.clear();
-        comb_driver
+        Ok(comb_driver)
     }
 
     pub fn set_offloading_units(&mut self, initializer: &SolverInitializer, offloading_types: Vec<OffloadingType>) {
@@ -253,8 +711,42 @@ impl DualModuleCombDriver {
         for offloading_unit in self.offloading_units.iter_mut() {
             offloading_unit.clear();
         }
+        self.conflict_queue.clear();
+        self.obstacle_batch.clear();
+        self.active_vertices.clear();
+        self.instruction_counts.clear();
+        self.weight_overflow.set(false);
+        self.desynchronized = false;
+        if let Some(scheduler) = self.scheduler.as_mut() {
+            scheduler.clear();
+        }
+        for observer in self.observers.iter_mut() {
+            observer.clear();
+        }
+        // a full reset clears every context
+        if self.context_bank.is_some() {
+            let checkpoint = self.checkpoint();
+            self.context_bank.as_mut().unwrap().reset(checkpoint);
+        }
     }
 
+    /// narrow a grow length to the width of the hardware response
+    pub fn compact_length(&self, length: Weight) -> CompactWeight {
+        #[cfg(feature = "checked_weight")]
+        {
+            CompactWeight::try_from(length).unwrap_or_else(|_| {
+                self.weight_overflow.set(true);
+                CompactWeight::MAX
+            })
+        }
+        #[cfg(not(feature = "checked_weight"))]
+        {
+            length.try_into().unwrap()
+        }
+    }
+
+    /// invalidate the signals of all the vertices and edges, including the idle ones in sparse evaluation: their
+    /// cached signals depend on the instruction and may be read later, e.g., by the mirror
     pub fn register_updated(&mut self) {
         for vertex in self.vertices.iter_mut() {
             vertex.register_updated()
@@ -265,6 +757,8 @@ impl DualModuleCombDriver {
         for offloading_unit in self.offloading_units.iter_mut() {
             offloading_unit.register_updated()
         }
+        self.cached_region = (vec![], vec![]);
+        self.all_signals_cached.set(false);
     }
 
     pub fn propagate_signals(&mut self, instruction: Instruction) {
@@ -272,8 +766,32 @@ impl DualModuleCombDriver {
         self.register_updated();
     }
 
+    /// in sparse evaluation, only invalidate the signals that may be cached: those of the previous instruction and
+    /// those that the scanned vertices and edges depend on, see [`Self::signal_region`]
+    fn propagate_sparse_signals(
+        &mut self,
+        instruction: Instruction,
+        vertex_indices: &[VertexIndex],
+        edge_indices: &[EdgeIndex],
+    ) {
+        self.instruction = instruction;
+        let region = self.signal_region(vertex_indices, edge_indices);
+        let previous = std::mem::replace(&mut self.cached_region, region);
+        for (region_vertices, region_edges) in [&previous, &self.cached_region] {
+            for &vertex_index in region_vertices.iter() {
+                self.vertices[vertex_index].register_updated();
+            }
+            for &edge_index in region_edges.iter() {
+                self.edges[edge_index].register_updated();
+            }
+        }
+    }
+
     pub fn update_registers(&mut self) {
         for vertex_index in 0..self.vertices.len() {
+            if self.vertices[vertex_index].is_inert {
+                continue;
+            }
             let registers = self.vertices[vertex_index].get_write_signals(self).clone();
             self.vertices[vertex_index].registers = registers;
         }
@@ -283,20 +801,504 @@ impl DualModuleCombDriver {
         }
     }
 
-    fn execute_instruction(&mut self, instruction: Instruction) -> CompactObstacle {
+    /// the vertices and edges to evaluate in sparse evaluation: the active vertices and those addressed by the
+    /// instruction, their neighbors that may be propagated to, and all the incident edges
+    pub fn sparse_scan(&self, instruction: &Instruction) -> (Vec<VertexIndex>, Vec<EdgeIndex>) {
+        let vertex_num = self.vertices.len();
+        let mut seeds = vec![];
+        let mut edge_indices = BTreeSet::new();
+        match *instruction {
+            Instruction::AddDefectVertex { vertex, .. } | Instruction::RemoveDefectVertex { vertex, .. } => {
+                seeds.push(vertex);
+            }
+            Instruction::LoadDefectsBitmap { base_vertex, bitmap, .. } => {
+                seeds.extend(
+                    (0..64)
+                        .filter(|offset| (bitmap >> offset) & 1 == 1)
+                        .map(|offset| base_vertex + offset)
+                        .filter(|&vertex_index| vertex_index < vertex_num),
+                );
+            }
+            Instruction::FreezeVertexRange { begin, end } => {
+                seeds.extend(begin.min(vertex_num)..end.min(vertex_num));
+            }
+            Instruction::SetEdgeWeight { edge, .. } => {
+                edge_indices.insert(edge);
+            }
+            Instruction::LoadDefectsExternal { .. } => unreachable!("layer fusion is not supported in sparse evaluation"),
+            _ => {}
+        }
+        let mut vertex_indices = BTreeSet::new();
+        for &vertex_index in self.active_vertices.iter().chain(seeds.iter()) {
+            vertex_indices.insert(vertex_index);
+            for &edge_index in self.vertices[vertex_index].edge_indices.iter() {
+                vertex_indices.insert(self.edges[edge_index].get_peer(vertex_index));
+            }
+        }
+        vertex_indices.retain(|&vertex_index| !self.vertices[vertex_index].is_inert);
+        for &vertex_index in vertex_indices.iter() {
+            edge_indices.extend(self.vertices[vertex_index].edge_indices.iter().cloned());
+        }
+        (vertex_indices.into_iter().collect(), edge_indices.into_iter().collect())
+    }
+
+    /// the vertices and edges whose signals may be evaluated when the scanned ones respond: an edge reads the
+    /// propagation of both its endpoints, which in turn reads their incident edges and neighbors
+    pub fn signal_region(
+        &self,
+        vertex_indices: &[VertexIndex],
+        edge_indices: &[EdgeIndex],
+    ) -> (Vec<VertexIndex>, Vec<EdgeIndex>) {
+        let mut endpoints: BTreeSet<VertexIndex> = vertex_indices.iter().cloned().collect();
+        for &edge_index in edge_indices.iter() {
+            endpoints.insert(self.edges[edge_index].left_index);
+            endpoints.insert(self.edges[edge_index].right_index);
+        }
+        let mut region_vertices = endpoints.clone();
+        let mut region_edges = BTreeSet::new();
+        for &vertex_index in endpoints.iter() {
+            for &edge_index in self.vertices[vertex_index].edge_indices.iter() {
+                region_edges.insert(edge_index);
+                region_vertices.insert(self.edges[edge_index].get_peer(vertex_index));
+            }
+        }
+        (region_vertices.into_iter().collect(), region_edges.into_iter().collect())
+    }
+
+    /// update the registers of the evaluated vertices and edges only, tracking the active vertices
+    fn update_sparse_registers(&mut self, vertex_indices: &[VertexIndex], edge_indices: &[EdgeIndex]) {
+        for &vertex_index in vertex_indices.iter() {
+            let registers = self.vertices[vertex_index].get_write_signals(self).clone();
+            if registers.node_index.is_some() && !registers.is_virtual {
+                self.active_vertices.insert(vertex_index);
+            } else {
+                self.active_vertices.remove(&vertex_index);
+            }
+            self.vertices[vertex_index].registers = registers;
+        }
+        for &edge_index in edge_indices.iter() {
+            let registers = self.edges[edge_index].get_write_signals(self).clone();
+            self.edges[edge_index].registers = registers;
+        }
+    }
+
+    pub(crate) fn execute_instruction(&mut self, instruction: Instruction) -> CompactObstacle {
+        if self.desynchronized {
+            // the shot has failed: report an idle module so that the primal module stops
+            return CompactObstacle::GrowLength {
+                length: CompactWeight::MAX,
+            };
+        }
+        if !instruction.is_set_speed() {
+            self.flush_speeds();
+        }
+        if let Some(scheduler) = self.scheduler.as_mut() {
+            scheduler.executed(&instruction);
+        }
+        let Some(mut sequenced_bus) = self.sequenced_bus.take() else {
+            return self.execute_on_hardware(instruction);
+        };
+        let response = sequenced_bus.execute(instruction, |instruction| self.execute_on_hardware(instruction));
+        self.sequenced_bus = Some(sequenced_bus);
+        response.unwrap_or_else(|| {
+            // abort the shot, or the retransmissions are exhausted
+            self.desynchronized = true;
+            CompactObstacle::GrowLength {
+                length: CompactWeight::MAX,
+            }
+        })
+    }
+
+    /// issue a speed update through the scheduler, if any
+    fn schedule_speed(&mut self, instruction: Instruction) {
+        let instruction = match self.scheduler.as_mut() {
+            Some(scheduler) => scheduler.schedule_speed(instruction),
+            None => Some(instruction),
+        };
+        if let Some(instruction) = instruction {
+            self.execute_instruction(instruction);
+        }
+    }
+
+    /// issue the buffered speed updates before an instruction that depends on them
+    pub(crate) fn flush_speeds(&mut self) {
+        let pending = (self.scheduler.as_mut())
+            .map(|scheduler| scheduler.flush())
+            .unwrap_or_default();
+        for instruction in pending {
+            self.execute_instruction(instruction);
+        }
+    }
+
+    /// the enabled observer of the given type, e.g., `driver.observer::<CycleCounter>()`
+    pub fn observer<T: CombObserver + 'static>(&self) -> Option<&T> {
+        (self.observers.iter()).find_map(|observer| (**observer).as_any().downcast_ref::<T>())
+    }
+
+    pub fn observer_mut<T: CombObserver + 'static>(&mut self) -> Option<&mut T> {
+        (self.observers.iter_mut()).find_map(|observer| (**observer).as_any_mut().downcast_mut::<T>())
+    }
+
+    /// call the observers in order, each with the dual module holding the others
+    fn notify_observers(&mut self, mut notify: impl FnMut(&mut dyn CombObserver, &mut Self)) {
+        for index in 0..self.observers.len() {
+            let mut observer = self.observers.remove(index);
+            notify(&mut *observer, self);
+            self.observers.insert(index, observer);
+        }
+    }
+
+    fn execute_on_hardware(&mut self, instruction: Instruction) -> CompactObstacle {
         if self.config.log_instructions {
             self.profiler_instruction_history.push(instruction.clone());
         }
-        self.propagate_signals(instruction);
-        let response = self
+        *self.instruction_counts.entry(instruction.name()).or_default() += 1;
+        self.notify_observers(|observer, driver| observer.before_execute(driver));
+        let sparse_scan = self.config.sparse.then(|| self.sparse_scan(&instruction));
+        // an observer may read the signals outside the scanned ones, e.g., the mirror reads the boundary vertices
+        let invalidate_all =
+            self.observers.iter().any(|observer| observer.reads_all_signals()) || self.all_signals_cached.get();
+        match sparse_scan.as_ref() {
+            Some((vertex_indices, edge_indices)) if !invalidate_all => {
+                self.propagate_sparse_signals(instruction, vertex_indices, edge_indices)
+            }
+            _ => self.propagate_signals(instruction),
+        }
+        let responses: Vec<CompactObstacle> = match sparse_scan.as_ref() {
+            Some((vertex_indices, edge_indices)) => (vertex_indices.iter())
+                .map(|&vertex_index| self.vertices[vertex_index].get_response(self).clone())
+                .chain(
+                    edge_indices
+                        .iter()
+                        .map(|&edge_index| self.edges[edge_index].get_response(self).clone()),
+                )
+                .collect(),
+            None => self
+                .vertices
+                .iter()
+                .filter(|vertex| !vertex.is_inert)
+                .map(|vertex| vertex.get_response(self).clone())
+                .chain(self.edges.iter().map(|edge| edge.get_response(self).clone()))
+                .collect(),
+        };
+        let mut obstacles: Vec<CompactObstacle> = vec![];
+        if self.instruction.reads_obstacle() {
+            // the obstacles of the same priority follow the arbitration tree of the hardware, see `CompactObstacle::reduce`
+            let policy = self.config.primal_policy.policy();
+            let region_preference = self.instruction.region_preference();
+            obstacles = responses.iter().filter(|obstacle| obstacle.is_obstacle()).cloned().collect();
+            obstacles.sort_by_key(|obstacle| {
+                let is_preferred = region_preference.is_some_and(|region| self.touches_region(obstacle, region));
+                (!is_preferred, policy.priority(obstacle), obstacle.arbitration_key())
+            });
+            debug_assert!(
+                obstacles.iter().all(|obstacle| match obstacle {
+                    CompactObstacle::Conflict { vertex_1, vertex_2, .. } => [vertex_1, vertex_2].iter().all(|vertex| {
+                        !self.vertices[vertex.get() as VertexIndex]
+                            .get_post_execute_state(self)
+                            .is_frozen
+                    }),
+                    _ => true,
+                }),
+                "a frozen region resurfaced an obstacle"
+            );
+        }
+        if self.conflict_queue.depth() > 1 {
+            // the first obstacle is reported directly; queue the others of this round
+            for obstacle in obstacles.iter().skip(1) {
+                let mut obstacle = obstacle.clone();
+                obstacle.fix_conflict_order();
+                self.conflict_queue.push(obstacle);
+            }
+        }
+        if self.config.batch_obstacles && self.instruction.reads_obstacle() {
+            self.obstacle_batch = Self::non_overlapping_conflicts(&obstacles);
+        }
+        let reduction = (self.observer::<CycleCounter>()).and_then(|cycle_counter| cycle_counter.reduction.as_ref());
+        let response = match (obstacles.into_iter().next(), reduction) {
+            (Some(obstacle), _) => obstacle,
+            (None, Some(reduction)) => reduction.reduce(responses, CompactObstacle::reduce),
+            (None, None) => Self::reduce_responses(responses),
+        };
+        let response = match self.instruction.reads_obstacle() {
+            true => (self.observers.iter_mut()).fold(response, |response, observer| observer.transfer_response(response)),
+            false => response,
+        };
+        self.notify_observers(|observer, driver| observer.signals_evaluated(driver));
+        match sparse_scan {
+            Some((vertex_indices, edge_indices)) => self.update_sparse_registers(&vertex_indices, &edge_indices),
+            None => self.update_registers(),
+        }
+        self.notify_observers(|observer, driver| observer.after_execute(driver));
+        response
+    }
+
+    /// whether a conflict has a vertex in the region, see [`DualCombConfig::region_size`]
+    fn touches_region(&self, obstacle: &CompactObstacle, region: usize) -> bool {
+        let region_size =
+            (self.config.region_size).expect("a region preference requires `region_size` in the configuration");
+        match obstacle {
+            CompactObstacle::Conflict { vertex_1, vertex_2, .. } => [vertex_1, vertex_2]
+                .iter()
+                .any(|vertex| vertex.get() as usize / region_size == region),
+            _ => false,
+        }
+    }
+
+    /// greedily select the conflicts that share no node with the reported obstacle and the previously selected ones,
+    /// in the order of reporting; the reported obstacle itself is not included
+    fn non_overlapping_conflicts(obstacles: &[CompactObstacle]) -> Vec<CompactObstacle> {
+        let mut occupied = BTreeSet::new();
+        let mut batch = vec![];
+        for (index, obstacle) in obstacles.iter().enumerate() {
+            let CompactObstacle::Conflict { node_1, node_2, .. } = obstacle else {
+                continue;
+            };
+            let nodes: Vec<_> = [node_1.option(), node_2.option()]
+                .into_iter()
+                .flatten()
+                .map(|node| node.get())
+                .collect();
+            if nodes.iter().any(|node| occupied.contains(node)) {
+                continue;
+            }
+            occupied.extend(nodes);
+            if index > 0 {
+                let mut obstacle = obstacle.clone();
+                obstacle.fix_conflict_order();
+                batch.push(obstacle);
+            }
+        }
+        batch
+    }
+
+    /// take the conflicts found together with the last reported one, see [`DualCombConfig::batch_obstacles`]
+    pub fn take_obstacle_batch(&mut self) -> Vec<CompactObstacle> {
+        self.batched_obstacles += self.obstacle_batch.len();
+        std::mem::take(&mut self.obstacle_batch)
+    }
+
+    /// the arbitration tree over all the responses, which does not depend on the order of merging; there is no
+    /// response at all when every vertex and edge is idle in sparse evaluation
+    fn reduce_responses(responses: Vec<CompactObstacle>) -> CompactObstacle {
+        let unbounded = CompactObstacle::GrowLength {
+            length: CompactWeight::MAX,
+        };
+        responses.into_iter().reduce(CompactObstacle::reduce).unwrap_or(unbounded)
+    }
+
+    /// check whether a queued conflict still holds in the current registers; the primal module may have changed
+    /// the speed or the blossom structure when resolving the previous conflicts
+    fn is_conflict_valid(vertices: &[Vertex], edges: &[Edge], obstacle: &CompactObstacle) -> bool {
+        let CompactObstacle::Conflict {
+            node_1,
+            node_2,
+            touch_1,
+            touch_2,
+            vertex_1,
+            vertex_2,
+        } = obstacle
+        else {
+            return false;
+        };
+        let matches_node = |vertex: &CompactVertexIndex, node: &OptionCompactNodeIndex, touch: &OptionCompactNodeIndex| {
+            let registers = &vertices[vertex.get() as VertexIndex].registers;
+            match node.option() {
+                Some(node) => {
+                    !registers.is_virtual
+                        && registers.node_index == Some(node.get() as NodeIndex)
+                        && registers.root_index == touch.option().map(|touch| touch.get() as NodeIndex)
+                }
+                None => registers.is_virtual,
+            }
+        };
+        if node_1 == node_2 || !matches_node(vertex_1, node_1, touch_1) || !matches_node(vertex_2, node_2, touch_2) {
+            return false;
+        }
+        let left = &vertices[vertex_1.get() as VertexIndex];
+        let right = &vertices[vertex_2.get() as VertexIndex];
+        if left.registers.is_frozen || right.registers.is_frozen {
+            return false;
+        }
+        if !CompactGrowState::is_conflicting(left.registers.speed, right.registers.speed) {
+            return false;
+        }
+        // the same condition as the edge reporting the conflict, see [`Edge::get_response`]
+        left.edge_indices.iter().any(|&edge_index| {
+            let edge = &edges[edge_index];
+            edge.get_peer(left.vertex_index) == right.vertex_index
+                && edge.registers.weight - left.registers.grown - right.registers.grown == 0
+        })
+    }
+
+    /// the node occupying a vertex, if any
+    fn occupying_node(registers: &VertexRegisters) -> Option<NodeIndex> {
+        if registers.is_virtual {
+            return None;
+        }
+        registers.node_index.filter(|&node_index| node_index != VIRTUAL_NODE_INDEX)
+    }
+
+    /// the follow-up of a growth that rounds down to zero: the remaining weight of an edge is less than the joint
+    /// speed of its two sides, or a shrinking vertex has grown less than its speed magnitude. A single unit of growth
+    /// for every node would overshoot, so a single side grows alone: the node of the edge or vertex together with the
+    /// nodes tightly connected to it, i.e., its alternating tree, which keeps the tight edges within the tree.
+    /// The side must not overshoot any other edge and must not contain a blossom, whose dual variable is tracked
+    /// with the global growth in [`DualDriverTracked`]. When no side can grow, the optimum may need half-integral
+    /// dual variables, e.g., a cycle of odd weight among the nodes of the same tree, and the edge is reported as a
+    /// conflict although it is not tight; only even weights with unit speeds avoid this
+    fn fractional_step(&self) -> Option<FractionalStep> {
+        let mut seeds = vec![];
+        let mut conflicts = vec![];
+        for edge in self.edges.iter() {
+            let left = &self.vertices[edge.left_index].registers;
+            let right = &self.vertices[edge.right_index].registers;
+            let (left_node, right_node) = (Self::occupying_node(left), Self::occupying_node(right));
+            if left.is_frozen || right.is_frozen || left_node == right_node {
+                continue;
+            }
+            let joint_speed = left.signed_speed() + right.signed_speed();
+            let remaining = edge.registers.weight - left.grown - right.grown;
+            if remaining <= 0 || remaining >= joint_speed {
+                continue;
+            }
+            for (registers, node) in [(left, left_node), (right, right_node)] {
+                if registers.signed_speed() > 0 {
+                    seeds.extend(node);
+                }
+            }
+            if (left_node.is_some() || left.is_virtual) && (right_node.is_some() || right.is_virtual) {
+                let touch = |registers: &VertexRegisters| registers.root_index.filter(|_| !registers.is_virtual);
+                conflicts.push(CompactObstacle::Conflict {
+                    node_1: left_node.map(compact_index).into(),
+                    touch_1: touch(left).map(compact_index).into(),
+                    vertex_1: compact_index(edge.left_index),
+                    node_2: right_node.map(compact_index).into(),
+                    touch_2: touch(right).map(compact_index).into(),
+                    vertex_2: compact_index(edge.right_index),
+                });
+            }
+        }
+        for vertex in self.vertices.iter() {
+            let registers = &vertex.registers;
+            if registers.speed == CompactGrowState::Shrink
+                && !registers.is_frozen
+                && registers.grown > 0
+                && registers.grown < Weight::from(registers.speed_magnitude)
+            {
+                seeds.extend(Self::occupying_node(registers));
+            }
+        }
+        if seeds.is_empty() {
+            return None;
+        }
+        let components: Vec<BTreeSet<NodeIndex>> = seeds.into_iter().map(|seed| self.tight_component(seed)).collect();
+        let valid = |component: &&BTreeSet<NodeIndex>| self.is_fractional_step_valid(component);
+        if let Some(component) = (components.iter().filter(valid)).find(|component| !self.contains_blossom(component)) {
+            return Some(FractionalStep::Grow(component.clone()));
+        }
+        if let Some(conflict) = conflicts.into_iter().next() {
+            return Some(FractionalStep::Conflict(conflict));
+        }
+        // a shrinking side with a blossom is still better than a module that never reports an obstacle
+        (components.iter().find(valid)).map(|component| FractionalStep::Grow(component.clone()))
+    }
+
+    /// the moving nodes connected to the seed through tight edges
+    fn tight_component(&self, seed: NodeIndex) -> BTreeSet<NodeIndex> {
+        let mut component = BTreeSet::from([seed]);
+        loop {
+            let mut is_extended = false;
+            for edge in self.edges.iter() {
+                let left = &self.vertices[edge.left_index].registers;
+                let right = &self.vertices[edge.right_index].registers;
+                let (Some(left_node), Some(right_node)) = (Self::occupying_node(left), Self::occupying_node(right)) else {
+                    continue;
+                };
+                if left_node == right_node
+                    || left.is_frozen
+                    || right.is_frozen
+                    || left.speed == CompactGrowState::Stay
+                    || right.speed == CompactGrowState::Stay
+                    || edge.registers.weight - left.grown - right.grown != 0
+                    || component.contains(&left_node) == component.contains(&right_node)
+                {
+                    continue;
+                }
+                component.insert(left_node);
+                component.insert(right_node);
+                is_extended = true;
+            }
+            if !is_extended {
+                return component;
+            }
+        }
+    }
+
+    /// the change of the grown value of a vertex when only the nodes in the component grow by a single unit
+    fn fractional_delta(registers: &VertexRegisters, component: &BTreeSet<NodeIndex>) -> Weight {
+        match Self::occupying_node(registers) {
+            Some(node) if component.contains(&node) && !registers.is_frozen => {
+                Weight::from(registers.speed.with_magnitude(1))
+            }
+            _ => 0,
+        }
+    }
+
+    /// whether the component can grow alone by a single unit without overshooting an edge or a shrinking vertex
+    fn is_fractional_step_valid(&self, component: &BTreeSet<NodeIndex>) -> bool {
+        let vertices_valid = self
             .vertices
             .iter()
-            .map(|vertex| vertex.get_response(self).clone())
-            .chain(self.edges.iter().map(|edge| edge.get_response(self).clone()))
-            .reduce(CompactObstacle::reduce)
-            .unwrap();
-        self.update_registers();
-        response
+            .all(|vertex| vertex.registers.grown + Self::fractional_delta(&vertex.registers, component) >= 0);
+        vertices_valid
+            && self.edges.iter().all(|edge| {
+                let left = &self.vertices[edge.left_index].registers;
+                let right = &self.vertices[edge.right_index].registers;
+                if Self::occupying_node(left).is_some() && Self::occupying_node(left) == Self::occupying_node(right) {
+                    return true;
+                }
+                let delta = Self::fractional_delta(left, component) + Self::fractional_delta(right, component);
+                delta <= 0 || edge.registers.weight - left.grown - right.grown - delta >= 0
+            })
+    }
+
+    /// whether any node in the component is a blossom, whose vertices have a different root node
+    fn contains_blossom(&self, component: &BTreeSet<NodeIndex>) -> bool {
+        self.vertices.iter().any(|vertex| {
+            Self::occupying_node(&vertex.registers)
+                .is_some_and(|node| component.contains(&node) && vertex.registers.root_index != Some(node))
+        })
+    }
+
+    /// grow the nodes in the component alone by a single unit: the other moving nodes stay and the magnitude of the
+    /// growing nodes is 1 during the growth, and their speeds are restored afterwards
+    fn grow_fractional_step(&mut self, component: &BTreeSet<NodeIndex>) {
+        let mut speeds = BTreeMap::<NodeIndex, (CompactGrowState, CompactSpeed)>::new();
+        for vertex in self.vertices.iter() {
+            if let Some(node) = Self::occupying_node(&vertex.registers) {
+                if vertex.registers.speed != CompactGrowState::Stay {
+                    speeds.insert(node, (vertex.registers.speed, vertex.registers.speed_magnitude));
+                }
+            }
+        }
+        speeds.retain(|node, (_, magnitude)| !component.contains(node) || *magnitude != 1);
+        for (&node, &(speed, _)) in speeds.iter() {
+            let speed = if component.contains(&node) {
+                speed
+            } else {
+                CompactGrowState::Stay
+            };
+            self.execute_instruction(Instruction::SetSpeed { node, speed });
+        }
+        self.execute_instruction(Instruction::Grow { length: 1 });
+        for (node, (speed, magnitude)) in speeds.into_iter() {
+            self.execute_instruction(if magnitude == 1 {
+                Instruction::SetSpeed { node, speed }
+            } else {
+                Instruction::SetSpeedWithMagnitude { node, speed, magnitude }
+            });
+        }
     }
 
     /// get all the edges that are pre-matched in the graph
@@ -307,6 +1309,58 @@ impl DualModuleCombDriver {
             .map(|edge| edge.edge_index)
             .collect()
     }
+
+    /// the defects pre-matched to a virtual vertex through a chain of two edges, as `(defect, virtual, edges)`
+    pub fn pre_matching_chains(&self) -> Vec<(VertexIndex, VertexIndex, [EdgeIndex; 2])> {
+        let mut chains = vec![];
+        for offloading in self.offloading_units.iter() {
+            if let OffloadingType::ChainMatch {
+                edge_index,
+                boundary_edge,
+                virtual_vertex,
+            } = offloading.offloading_type
+            {
+                if offloading.get_signals(self).condition {
+                    let middle_index = self.edges[boundary_edge].get_peer(virtual_vertex);
+                    let defect_index = self.edges[edge_index].get_peer(middle_index);
+                    chains.push((defect_index, virtual_vertex, [edge_index, boundary_edge]));
+                }
+            }
+        }
+        chains
+    }
+
+    /// count how the defects of the finished shot are resolved, see [`PreMatchingStatistics`]
+    fn record_pre_matching_statistics(&mut self) {
+        let defects = self.vertices.iter().filter(|vertex| vertex.registers.is_defect).count();
+        if defects == 0 {
+            return;
+        }
+        let mut shot = PreMatchingStatistics {
+            shots: 1,
+            defects,
+            ..Default::default()
+        };
+        if !self.offloading_units.is_empty() {
+            let chains = self.pre_matching_chains();
+            shot.chain_matched = chains.len();
+            let chain_edges: BTreeSet<EdgeIndex> = chains.into_iter().flat_map(|(_, _, edges)| edges).collect();
+            for edge_index in self.pre_matching_edges() {
+                if chain_edges.contains(&edge_index) {
+                    continue;
+                }
+                let edge = &self.edges[edge_index];
+                if self.vertices[edge.left_index].registers.is_virtual
+                    || self.vertices[edge.right_index].registers.is_virtual
+                {
+                    shot.virtual_matched += 1;
+                } else {
+                    shot.peer_matched += 2;
+                }
+            }
+        }
+        self.pre_matching_statistics.add(&shot);
+    }
 }
 
 impl DualStacklessDriver for DualModuleCombDriver {
@@ -314,11 +1368,27 @@ impl DualStacklessDriver for DualModuleCombDriver {
         self.clear();
     }
     fn set_speed(&mut self, _is_blossom: bool, node: CompactNodeIndex, speed: CompactGrowState) {
-        self.execute_instruction(Instruction::SetSpeed {
+        self.schedule_speed(Instruction::SetSpeed {
+            node: node.get() as NodeIndex,
+            speed,
+        });
+    }
+    fn set_speed_with_magnitude(
+        &mut self,
+        _is_blossom: bool,
+        node: CompactNodeIndex,
+        speed: CompactGrowState,
+        magnitude: CompactSpeed,
+    ) {
+        self.schedule_speed(Instruction::SetSpeedWithMagnitude {
             node: node.get() as NodeIndex,
             speed,
+            magnitude,
         });
     }
+    fn max_speed_magnitude(&self) -> CompactSpeed {
+        MAX_SPEED_MAGNITUDE
+    }
     fn set_blossom(&mut self, node: CompactNodeIndex, blossom: CompactNodeIndex) {
         self.execute_instruction(Instruction::SetBlossom {
             node: node.get() as NodeIndex,
@@ -326,9 +1396,33 @@ impl DualStacklessDriver for DualModuleCombDriver {
         });
     }
     fn find_obstacle(&mut self) -> (CompactObstacle, CompactWeight) {
+        if self.desynchronized {
+            return (CompactObstacle::None, 0);
+        }
+        self.flush_speeds();
+        self.obstacle_batch.clear();
+        let (vertices, edges) = (&self.vertices, &self.edges);
+        let queued = self
+            .conflict_queue
+            .pop_valid(|obstacle| Self::is_conflict_valid(vertices, edges, obstacle));
+        if let Some(obstacle) = queued {
+            return (obstacle, 0);
+        }
+        let fuse_grow = (self.scheduler.as_ref()).is_some_and(|scheduler| scheduler.config.fuse_grow);
         let mut grown: CompactWeight = 0;
+        let mut fused_obstacle = None;
         loop {
-            let mut obstacle = self.execute_instruction(Instruction::FindObstacle);
+            let mut obstacle = match fused_obstacle.take() {
+                // the grown state may have vertices to propagate, which only a separate `FindObstacle` sees
+                Some(CompactObstacle::GrowLength { length: 0 }) | None => {
+                    let region_preference = self.region_preference;
+                    self.execute_instruction(Instruction::FindObstacle { region_preference })
+                }
+                Some(obstacle) => {
+                    self.scheduler.as_mut().unwrap().statistics.fused_grow += 1;
+                    obstacle
+                }
+            };
             obstacle.fix_conflict_order();
             match obstacle {
                 CompactObstacle::None => unreachable!(),
@@ -336,14 +1430,29 @@ impl DualStacklessDriver for DualModuleCombDriver {
                     assert!(length >= 0, "report negative grow length");
                     if length == CompactWeight::MAX {
                         return (CompactObstacle::None, grown);
+                    } else if let Some(step) = (length == 0).then(|| self.fractional_step()).flatten() {
+                        match step {
+                            FractionalStep::Grow(component) => self.grow_fractional_step(&component),
+                            FractionalStep::Conflict(mut conflict) => {
+                                conflict.fix_conflict_order();
+                                return (conflict, grown);
+                            }
+                        }
                     } else {
                         let length = std::cmp::min(length, self.maximum_growth);
                         if length == 0 {
                             return (CompactObstacle::GrowLength { length: 0 }, grown as CompactWeight);
                         } else {
-                            self.execute_instruction(Instruction::Grow {
-                                length: length as Weight,
-                            });
+                            let grow_length = length as Weight;
+                            if fuse_grow {
+                                let instruction = Instruction::GrowFindObstacle {
+                                    length: grow_length,
+                                    region_preference: self.region_preference,
+                                };
+                                fused_obstacle = Some(self.execute_instruction(instruction));
+                            } else {
+                                self.execute_instruction(Instruction::Grow { length: grow_length });
+                            }
                             self.maximum_growth -= length;
                             grown += length;
                         }
@@ -355,11 +1464,32 @@ impl DualStacklessDriver for DualModuleCombDriver {
         }
     }
     fn add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) {
+        assert!(
+            !self.vertices[vertex.get() as VertexIndex].is_inert,
+            "defect vertex {vertex:?} has no incident edge and can never be matched"
+        );
         self.execute_instruction(Instruction::AddDefectVertex {
             vertex: vertex.get() as VertexIndex,
             node: node.get() as NodeIndex,
         });
     }
+    fn add_defects_bitmap(&mut self, base_vertex: CompactVertexIndex, base_node: CompactNodeIndex, bitmap: u64) {
+        for offset in (0..64).filter(|offset| (bitmap >> offset) & 1 == 1) {
+            let vertex_index = base_vertex.get() as VertexIndex + offset;
+            assert!(
+                !self.vertices[vertex_index].is_inert,
+                "defect vertex {vertex_index} has no incident edge and can never be matched"
+            );
+        }
+        self.execute_instruction(Instruction::LoadDefectsBitmap {
+            base_vertex: base_vertex.get() as VertexIndex,
+            base_node: base_node.get() as NodeIndex,
+            bitmap,
+        });
+    }
+    fn read_vertex_grown(&mut self, vertex: CompactVertexIndex) -> Option<CompactWeight> {
+        Some(self.vertices[vertex.get() as VertexIndex].registers.grown as CompactWeight)
+    }
 }
 
 impl DualTrackedDriver for DualModuleCombDriver {
@@ -446,15 +1576,19 @@ impl FusionVisualizer for DualModuleCombDriver {
                 value
             })
             .collect();
+        if self.config.sparse {
+            self.all_signals_cached.set(true);
+        }
         let vertices_comb: Vec<serde_json::Value> =
             self.vertices.iter().map(|vertex| vertex.snapshot(abbrev, self)).collect();
         let edges_comb: Vec<serde_json::Value> = self.edges.iter().map(|edge| edge.snapshot(abbrev, self)).collect();
-        json!({
+        let snapshot = json!({
             "vertices": vertices,
             "edges": edges,
             "vertices_comb": vertices_comb,
             "edges_comb": edges_comb,
-        })
+        });
+        (self.observers.iter()).fold(snapshot, |snapshot, observer| observer.emit_snapshot(abbrev, snapshot))
     }
 }
 
@@ -464,18 +1598,87 @@ impl FusionVisualizer for DualModuleCombAdaptor {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// the follow-up of a growth that rounds down to zero, see [`DualModuleCombDriver::fractional_step`]
+enum FractionalStep {
+    /// grow these nodes alone by a single unit
+    Grow(BTreeSet<NodeIndex>),
+    /// no side can grow alone, report the edge as a conflict
+    Conflict(CompactObstacle),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Instruction {
     SetSpeed { node: NodeIndex, speed: CompactGrowState },
+    SetSpeedWithMagnitude { node: NodeIndex, speed: CompactGrowState, magnitude: CompactSpeed },
     SetBlossom { node: NodeIndex, blossom: NodeIndex },
     AddDefectVertex { vertex: VertexIndex, node: NodeIndex },
-    FindObstacle,
+    /// clear the defect vertex and the region of its node, see [`SolverTrackedDual::remove_defect`]
+    RemoveDefectVertex { vertex: VertexIndex, node: NodeIndex },
+    /// load up to 64 defects in `[base_vertex, base_vertex + 64)`, assigning node indices from `base_node`; see
+    /// [`micro_blossom_nostd::instruction::Instruction32::load_defects_bitmap`] for the wire encoding
+    LoadDefectsBitmap { base_vertex: VertexIndex, base_node: NodeIndex, bitmap: u64 },
+    /// report the obstacles touching the preferred region first, if any, see [`DualCombConfig::region_size`]
+    FindObstacle { region_preference: Option<usize> },
     Grow { length: Weight },
+    /// grow and report the obstacle of the grown state, equivalent to `Grow` followed by `FindObstacle`
+    GrowFindObstacle { length: Weight, region_preference: Option<usize> },
     LoadDefectsExternal { time: usize, channel: usize },
+    /// freeze the vertices in `begin..end`, see [`SolverTrackedDual::freeze_vertex_range`]
+    FreezeVertexRange { begin: VertexIndex, end: VertexIndex },
+    /// set the weight of an edge until the next reset, see [`SolverTrackedDual::set_edge_weight`]
+    SetEdgeWeight { edge: EdgeIndex, weight: Weight },
+    /// release the index of a finished node in the frozen region, see [`SolverTrackedDual::release_nodes`]
+    ReleaseNode { node: NodeIndex },
+}
+
+impl Instruction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SetSpeed { .. } => "set_speed",
+            Self::SetSpeedWithMagnitude { .. } => "set_speed_with_magnitude",
+            Self::SetBlossom { .. } => "set_blossom",
+            Self::AddDefectVertex { .. } => "add_defect_vertex",
+            Self::RemoveDefectVertex { .. } => "remove_defect_vertex",
+            Self::LoadDefectsBitmap { .. } => "load_defects_bitmap",
+            Self::FindObstacle { .. } => "find_obstacle",
+            Self::Grow { .. } => "grow",
+            Self::GrowFindObstacle { .. } => "grow_find_obstacle",
+            Self::LoadDefectsExternal { .. } => "load_defects_external",
+            Self::FreezeVertexRange { .. } => "freeze_vertex_range",
+            Self::SetEdgeWeight { .. } => "set_edge_weight",
+            Self::ReleaseNode { .. } => "release_node",
+        }
+    }
+
+    pub fn is_set_speed(&self) -> bool {
+        matches!(self, Self::SetSpeed { .. } | Self::SetSpeedWithMagnitude { .. })
+    }
+
+    /// whether the response of the instruction is the obstacle, see [`CompactObstacle`]
+    pub fn reads_obstacle(&self) -> bool {
+        matches!(self, Self::FindObstacle { .. } | Self::GrowFindObstacle { .. })
+    }
+
+    pub fn region_preference(&self) -> Option<usize> {
+        match *self {
+            Self::FindObstacle { region_preference } | Self::GrowFindObstacle { region_preference, .. } => region_preference,
+            _ => None,
+        }
+    }
 }
 
 pub const VIRTUAL_NODE_INDEX: NodeIndex = NodeIndex::MAX;
 
+/// narrow a vertex or node index to the width of the interface indices, i.e., [`CompactVertexNum`] which is 16 bits
+/// with the `u16_interface_index` feature; the largest value is reserved for `None`. Making the register width follow
+/// the feature is out of scope: the vertex registers keep their full width [`NodeIndex`], so this is the only place
+/// where the narrowing happens
+pub fn compact_index(index: usize) -> CompactVertexIndex {
+    (CompactVertexNum::try_from(index).ok())
+        .and_then(CompactVertexIndex::new)
+        .unwrap_or_else(|| panic!("index {index} overflows the {}-bit hardware index", CompactVertexNum::BITS))
+}
+
 #[macro_export]
 macro_rules! referenced_signal {
     ($signal:expr, $function:expr) => {
@@ -495,6 +1698,9 @@ pub mod tests {
     use super::*;
     use crate::dual_module_adaptor::tests::*;
     use fusion_blossom::example_codes::*;
+    use rand::Rng;
+    use rand_xoshiro::rand_core::SeedableRng;
+    use rand_xoshiro::Xoroshiro128StarStar;
 
     // to use visualization, we need the folder of fusion-blossom repo
     // e.g. export FUSION_DIR=/Users/wuyue/Documents/GitHub/fusion-blossom
@@ -525,6 +1731,595 @@ pub mod tests {
         dual_module_comb_basic_standard_syndrome(7, visualize_filename, defect_vertices, false, false);
     }
 
+    /// test loading dense defects using a single bitmap instruction
+    #[test]
+    fn dual_module_comb_defects_bitmap_1() {
+        // cargo test dual_module_comb_defects_bitmap_1 -- --nocapture
+        let visualize_filename = "dual_module_comb_defects_bitmap_1.json".to_string();
+        let defect_vertices = vec![16, 17, 18, 26, 34, 39];
+        dual_module_comb_basic_standard_syndrome(7, visualize_filename, defect_vertices, false, false);
+    }
+
+    /// two trees growing toward each other at speed 2 meet in half of the time
+    #[test]
+    fn dual_module_comb_speed_magnitude_1() {
+        // cargo test dual_module_comb_speed_magnitude_1 -- --nocapture
+        let code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let initializer = code.get_initializer();
+        let virtual_vertices: BTreeSet<_> = initializer.virtual_vertices.iter().cloned().collect();
+        let &(left, right, weight) = initializer
+            .weighted_edges
+            .iter()
+            .find(|(left, right, _)| !virtual_vertices.contains(left) && !virtual_vertices.contains(right))
+            .unwrap();
+        let mut driver = DualModuleCombDriver::new_empty(&initializer);
+        driver.add_defect(ni!(left), ni!(0));
+        driver.add_defect(ni!(right), ni!(1));
+        driver.set_speed_with_magnitude(false, ni!(0), CompactGrowState::Grow, 2);
+        driver.set_speed_with_magnitude(false, ni!(1), CompactGrowState::Grow, 2);
+        let (obstacle, grown) = driver.find_obstacle();
+        assert!(matches!(obstacle, CompactObstacle::Conflict { .. }), "{obstacle:?}");
+        assert_eq!(grown as Weight * 4, weight);
+    }
+
+    /// the lone defects grow at speed 2 until the primal module encounters them, which still certifies the
+    /// minimum-weight perfect matching
+    #[test]
+    fn dual_module_comb_speed_magnitude_2() {
+        // cargo test dual_module_comb_speed_magnitude_2 -- --nocapture
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let mut solver = SolverEmbeddedComb::new(MicroBlossomSingle::new_code(&code), json!({}));
+        let config = json!({ "lone_defect_magnitude": 2 });
+        let mut fast_solver = SolverEmbeddedComb::new(MicroBlossomSingle::new_code(&code), config);
+        for seed in 0..20 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            solver.solve(&syndrome_pattern);
+            fast_solver.solve(&syndrome_pattern);
+            let (result, fast_result) = (solver.result(), fast_solver.result());
+            assert_eq!(fast_result.matching_weight, result.matching_weight);
+            assert_eq!(fast_result.dual_objective, Some(fast_result.matching_weight));
+            let instruction_counts = fast_result.instruction_counts.unwrap();
+            let magnitude_count = instruction_counts.get("set_speed_with_magnitude").cloned().unwrap_or(0);
+            assert!(magnitude_count >= syndrome_pattern.defect_vertices.len());
+            solver.clear();
+            fast_solver.clear();
+        }
+    }
+
+    /// an erased edge is tight immediately and a reweighted edge keeps its weight until the next reset
+    #[test]
+    fn dual_module_comb_set_edge_weight_1() {
+        // cargo test dual_module_comb_set_edge_weight_1 -- --nocapture
+        let code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let initializer = code.get_initializer();
+        let virtual_vertices: BTreeSet<_> = initializer.virtual_vertices.iter().cloned().collect();
+        let edge_index = initializer
+            .weighted_edges
+            .iter()
+            .position(|(left, right, _)| !virtual_vertices.contains(left) && !virtual_vertices.contains(right))
+            .unwrap();
+        let (left, right, weight) = initializer.weighted_edges[edge_index];
+        let mut driver = DualModuleCombDriver::new_empty(&initializer);
+        for (new_weight, expected_grown) in [(0, 0), (weight * 2, weight)] {
+            driver.set_edge_weight(edge_index, new_weight).unwrap();
+            driver.add_defect(ni!(left), ni!(0));
+            driver.add_defect(ni!(right), ni!(1));
+            let (obstacle, grown) = driver.find_obstacle();
+            assert!(matches!(obstacle, CompactObstacle::Conflict { .. }), "{obstacle:?}");
+            assert_eq!(grown as Weight, expected_grown);
+            assert_eq!(driver.instruction_counts["set_edge_weight"], 1);
+            driver.reset();
+            assert_eq!(driver.edges[edge_index].registers.weight, weight);
+        }
+        // a weight below the growth covering the edge is rejected without any change
+        driver.add_defect(ni!(left), ni!(0));
+        driver.find_conflict(2);
+        for invalid_weight in [-2, 0] {
+            assert!(matches!(
+                driver.set_edge_weight(edge_index, invalid_weight),
+                Err(DualDriverError::InvalidArgument { .. })
+            ));
+        }
+        assert_eq!(driver.edges[edge_index].registers.weight, weight);
+        driver.set_edge_weight(edge_index, 3).unwrap();
+    }
+
+    /// a removed defect leaves the dual module as if it were never added
+    #[test]
+    fn dual_module_comb_remove_defect_1() {
+        // cargo test dual_module_comb_remove_defect_1 -- --nocapture
+        let code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let initializer = code.get_initializer();
+        let mut driver = DualModuleCombDriver::new_empty(&initializer);
+        let mut reference = DualModuleCombDriver::new_empty(&initializer);
+        driver.add_defect(ni!(16), ni!(0));
+        driver.add_defect(ni!(26), ni!(1));
+        reference.add_defect(ni!(26), ni!(1));
+        assert_eq!(driver.find_conflict(100), reference.find_conflict(100));
+        driver.remove_defect(16).unwrap();
+        assert_eq!(driver.instruction_counts["remove_defect_vertex"], 1);
+        assert_eq!(driver.read_vertex_grown(ni!(16)), Some(0));
+        let registers = |driver: &DualModuleCombDriver| -> Vec<VertexRegisters> {
+            driver.vertices.iter().map(|vertex| vertex.registers.clone()).collect()
+        };
+        assert_eq!(registers(&driver), registers(&reference));
+        assert_eq!(
+            driver.find_conflict(CompactWeight::MAX),
+            reference.find_conflict(CompactWeight::MAX)
+        );
+    }
+
+    /// an odd weight is not divisible by the joint speed of two growing defects: the growth rounds down and a single
+    /// side grows the rest, so that the edge is tight when it reports the conflict
+    #[test]
+    fn dual_module_comb_odd_weight_1() {
+        // cargo test dual_module_comb_odd_weight_1 -- --nocapture
+        let initializer = SolverInitializer::new(3, vec![(0, 1, 3), (1, 2, 4)], vec![2]);
+        let mut driver = DualModuleCombDriver::new_empty(&initializer);
+        driver.add_defect(ni!(0), ni!(0));
+        driver.add_defect(ni!(1), ni!(1));
+        let (obstacle, grown) = driver.find_conflict(CompactWeight::MAX);
+        assert!(matches!(obstacle, CompactObstacle::Conflict { .. }), "{obstacle:?}");
+        assert_eq!(grown, 1);
+        let duals = [
+            driver.read_vertex_grown(ni!(0)).unwrap(),
+            driver.read_vertex_grown(ni!(1)).unwrap(),
+        ];
+        assert_eq!(duals.iter().sum::<CompactWeight>(), 3);
+        assert!(duals.iter().all(|&dual| dual >= 1), "{duals:?}");
+        // the speeds are restored after the single side grows alone
+        assert!(driver.vertices[..2]
+            .iter()
+            .all(|vertex| vertex.registers.speed == CompactGrowState::Grow && vertex.registers.speed_magnitude == 1));
+    }
+
+    /// a solver with odd weights certifies the matching with a dual objective equal to its weight
+    #[test]
+    fn dual_module_comb_odd_weight_2() {
+        // cargo test dual_module_comb_odd_weight_2 -- --nocapture
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        let initializer = SolverInitializer::new(4, vec![(0, 1, 3), (1, 2, 5), (2, 3, 3)], vec![3]);
+        let positions = vec![VisualizePosition::new(0., 0., 0.); 4];
+        let mut solver = SolverEmbeddedComb::new(MicroBlossomSingle::new(&initializer, &positions), json!({}));
+        solver.solve(&SyndromePattern::new_vertices(vec![0, 1, 2]));
+        let matching_weight = solver
+            .subgraph()
+            .iter()
+            .map(|&edge_index| initializer.weighted_edges[edge_index].2)
+            .sum::<Weight>();
+        assert_eq!(solver.read_dual_objective(), Some(matching_weight));
+    }
+
+    /// report multiple conflicts found in the same round through the conflict queue
+    #[test]
+    fn dual_module_comb_conflict_queue_1() {
+        // cargo test dual_module_comb_conflict_queue_1 -- --nocapture
+        let visualize_filename = "dual_module_comb_conflict_queue_1.json".to_string();
+        let defect_vertices = vec![16, 17, 18, 26, 34, 39];
+        let solver =
+            dual_module_standard_optional_viz(7, Some(visualize_filename), defect_vertices, |initializer, positions| {
+                SolverEmbeddedComb::new(
+                    MicroBlossomSingle::new(initializer, positions),
+                    json!({ "dual": { "conflict_queue_depth": 4 } }),
+                )
+            });
+        let statistics = &solver.dual_module.driver.driver.conflict_queue.statistics;
+        println!("{statistics:?}");
+        assert!(statistics.queued > 0, "multiple conflicts should be found in the same round");
+    }
+
+    /// the conflicts handed out of the queue are the ones a fresh scan would report, also after the primal module
+    /// changes the speeds, and the decoding result is the same as the serial solver at any queue depth
+    #[test]
+    fn dual_module_comb_conflict_queue_sequence() {
+        // cargo test dual_module_comb_conflict_queue_sequence -- --nocapture
+        use fusion_blossom::mwpm_solver::{PrimalDualSolver, SolverSerial};
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let initializer = code.get_initializer();
+        let graph = MicroBlossomSingle::new_code(&code);
+        let mut serial = SolverSerial::new(&initializer);
+        let mut solvers = [1, 8].map(|conflict_queue_depth| {
+            SolverEmbeddedComb::new(
+                graph.clone(),
+                json!({ "dual": { "conflict_queue_depth": conflict_queue_depth } }),
+            )
+        });
+        for seed in 0..50 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            serial.solve(&syndrome_pattern);
+            let mut sequences = vec![];
+            for solver in solvers.iter_mut() {
+                let mut sequence = vec![];
+                solver.load_syndrome(&syndrome_pattern);
+                loop {
+                    let (obstacle, _) = solver.dual_module.find_obstacle();
+                    if obstacle.is_none() {
+                        break;
+                    }
+                    if matches!(obstacle, CompactObstacle::Conflict { .. }) {
+                        let driver = &solver.dual_module.driver.driver;
+                        assert!(
+                            DualModuleCombDriver::is_conflict_valid(&driver.vertices, &driver.edges, &obstacle),
+                            "outdated conflict {obstacle:?} after {sequence:?}"
+                        );
+                    }
+                    sequence.push(obstacle.clone());
+                    solver.primal_module.resolve(solver.dual_module.as_mut(), obstacle);
+                }
+                solver.finish();
+                assert_eq!(solver.sum_dual_variables(), serial.sum_dual_variables());
+                sequences.push(sequence);
+            }
+            // the first conflict is found before any decision of the primal module; the others may differ, because a
+            // speed change of the primal module may create a conflict reported before the queued ones
+            assert_eq!(sequences[0].first(), sequences[1].first());
+            serial.clear();
+            for solver in solvers.iter_mut() {
+                solver.clear();
+            }
+        }
+        let [unqueued, queued] = solvers.map(|solver| solver.dual_module.driver.driver.conflict_queue.statistics.clone());
+        println!("depth 1: {unqueued:?}, depth 8: {queued:?}");
+        assert_eq!(unqueued.queued + unqueued.spilled, 0);
+        assert!(queued.queued > 0);
+        // the primal module invalidates some of the queued conflicts, which are then queried again
+        assert!(queued.stale > 0);
+    }
+
+    /// the standard primal module resolves all the non-overlapping conflicts of a round together
+    #[test]
+    fn dual_module_comb_batch_obstacles_1() {
+        // cargo test dual_module_comb_batch_obstacles_1 -- --nocapture
+        let defect_vertices = vec![16, 17, 18, 26, 34, 39, 43, 44];
+        let mut round_trips = vec![];
+        for batch_obstacles in [false, true] {
+            let solver = dual_module_standard_optional_viz(7, None, defect_vertices.clone(), |initializer, positions| {
+                SolverDualComb::new_with_config(
+                    MicroBlossomSingle::new(initializer, positions),
+                    json!({ "dual": { "batch_obstacles": batch_obstacles } }),
+                )
+            });
+            let driver = &solver.dual_module.dual_module.driver.driver;
+            assert_eq!(driver.batched_obstacles > 0, batch_obstacles);
+            round_trips.push(driver.profiler_response_history.len());
+        }
+        println!("round trips: {round_trips:?}");
+        assert!(round_trips[1] < round_trips[0]);
+    }
+
+    /// the dual objective read back from the hardware equals the weight of the minimum-weight perfect matching
+    #[test]
+    fn dual_module_comb_read_dual_1() {
+        // cargo test dual_module_comb_read_dual_1 -- --nocapture
+        let visualize_filename = "dual_module_comb_read_dual_1.json".to_string();
+        let defect_vertices = vec![16, 17, 18, 26, 34, 39];
+        let mut solver =
+            dual_module_standard_optional_viz(7, Some(visualize_filename), defect_vertices, |initializer, positions| {
+                SolverEmbeddedComb::new(MicroBlossomSingle::new(initializer, positions), json!({}))
+            });
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        let dual_objective = solver.read_dual_objective();
+        assert_eq!(dual_objective, Some(solver.sum_dual_variables()));
+        // a defect vertex is covered by its own node and all the blossoms containing it
+        let grown = solver.dual_module.read_vertex_grown(ni!(16)).unwrap() as Weight;
+        assert!(grown >= solver.read_node_dual(0).unwrap());
+    }
+
+    /// the solver result is certified by the dual objective and counts the instructions of this shot only
+    #[test]
+    fn dual_module_comb_solver_result_1() {
+        // cargo test dual_module_comb_solver_result_1 -- --nocapture
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let mut solver = SolverEmbeddedComb::new(MicroBlossomSingle::new_code(&code), json!({}));
+        for seed in 0..20 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            solver.solve(&syndrome_pattern);
+            let result = solver.result();
+            println!("{}", serde_json::to_string(&result).unwrap());
+            assert!(result.certified);
+            assert_eq!(result.dual_objective, Some(result.matching_weight));
+            assert_eq!(result.offloaded, None);
+            let instruction_counts = result.instruction_counts.unwrap();
+            let loaded = instruction_counts.get("add_defect_vertex").cloned().unwrap_or(0)
+                + instruction_counts.get("load_defects_bitmap").cloned().unwrap_or(0);
+            assert!(loaded <= syndrome_pattern.defect_vertices.len());
+            solver.clear();
+        }
+        let config = json!({ "dual": { "sim_config": { "support_offloading": true } } });
+        let mut solver = SolverEmbeddedComb::new(MicroBlossomSingle::new_code(&code), config);
+        solver.solve(&code.generate_random_errors(0));
+        let result = solver.result();
+        // the vertex registers also count the growth of the offloaded defects
+        assert_eq!(result.dual_objective, Some(result.matching_weight));
+        assert!(result.certified && result.offloaded.is_some());
+    }
+
+    /// the hardware may report the defects in any order, which changes the node indices but not the matching weight
+    #[test]
+    fn dual_module_comb_defect_order_independent() {
+        // cargo test dual_module_comb_defect_order_independent -- --nocapture
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        let mut code = PhenomenologicalRotatedCode::new(5, 4, 0.03, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        for sim_config in [json!({}), json!({ "support_offloading": true, "support_layer_fusion": true })] {
+            let mut solver = SolverEmbeddedComb::new(graph.clone(), json!({ "dual": { "sim_config": sim_config } }));
+            let mut shuffled_solver = SolverEmbeddedComb::new(
+                graph.clone(),
+                json!({ "dual": { "sim_config": sim_config }, "defect_order_seed": 1 }),
+            );
+            let mut reordered = 0;
+            for seed in 0..50 {
+                let syndrome_pattern = code.generate_random_errors(seed);
+                solver.solve(&syndrome_pattern);
+                shuffled_solver.solve(&syndrome_pattern);
+                if shuffled_solver.checkpoint().defect_vertices != syndrome_pattern.defect_vertices {
+                    reordered += 1;
+                }
+                assert_eq!(solver.sum_dual_variables(), shuffled_solver.sum_dual_variables());
+                solver.clear();
+                shuffled_solver.clear();
+            }
+            assert!(reordered > 0);
+        }
+    }
+
+    /// sparse evaluation skips the idle vertices and edges but reaches the same dual variables with the same
+    /// instructions
+    #[test]
+    fn dual_module_comb_sparse_evaluation() {
+        // cargo test dual_module_comb_sparse_evaluation -- --nocapture
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        let mut code = ExampleCodeType::CircuitLevelPlanarCode.build(7, 0.005, 7, 500, json!({}));
+        let graph = MicroBlossomSingle::new_code(code.as_ref());
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), json!({}));
+        let mut sparse_solver = SolverEmbeddedComb::new(graph.clone(), json!({ "dual": { "sparse": true } }));
+        for seed in 0..50 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            solver.solve(&syndrome_pattern);
+            sparse_solver.solve(&syndrome_pattern);
+            assert_eq!(solver.sum_dual_variables(), sparse_solver.sum_dual_variables(), "seed {seed}");
+            assert_eq!(solver.result().instruction_counts, sparse_solver.result().instruction_counts);
+            solver.clear();
+            sparse_solver.clear();
+        }
+        // only the neighborhood of a single defect is evaluated
+        let vertex_index = (graph.vertex_num / 2..)
+            .find(|vertex_index| !graph.virtual_vertices.contains(vertex_index))
+            .unwrap();
+        let config = serde_json::from_value(json!({ "sparse": true })).unwrap();
+        let mut driver = DualModuleCombDriver::new(graph.clone(), config);
+        driver.add_defect(ni!(vertex_index), ni!(0));
+        assert_eq!(driver.active_vertices, BTreeSet::from([vertex_index]));
+        let (vertex_indices, edge_indices) = driver.sparse_scan(&Instruction::FindObstacle { region_preference: None });
+        assert!(vertex_indices.len() <= driver.vertices[vertex_index].edge_indices.len() + 1);
+        assert!(edge_indices.len() < graph.weighted_edges.len() / 2);
+        // only the signals around the scanned neighborhood are invalidated
+        driver.find_obstacle();
+        assert!(driver.cached_region.0.len() < graph.vertex_num / 2);
+        assert!(driver.cached_region.1.len() < graph.weighted_edges.len() / 2);
+    }
+
+    /// the time of decoding a large circuit-level graph with and without sparse evaluation
+    #[test]
+    fn dual_module_comb_sparse_timing() {
+        // cargo test --release dual_module_comb_sparse_timing -- --nocapture
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        let d = 15;
+        let mut code = ExampleCodeType::CircuitLevelPlanarCode.build(d, 0.001, d, 500, json!({}));
+        let graph = MicroBlossomSingle::new_code(code.as_ref());
+        let syndrome_patterns: Vec<_> = (0..5).map(|seed| code.generate_random_errors(seed)).collect();
+        let mut elapsed = vec![];
+        let mut sum_dual_variables = vec![];
+        for sparse in [false, true] {
+            let mut solver = SolverEmbeddedComb::new(graph.clone(), json!({ "dual": { "sparse": sparse } }));
+            let begin = std::time::Instant::now();
+            for syndrome_pattern in syndrome_patterns.iter() {
+                solver.solve(syndrome_pattern);
+                sum_dual_variables.push(solver.sum_dual_variables());
+                solver.clear();
+            }
+            elapsed.push(begin.elapsed().as_secs_f64());
+        }
+        println!(
+            "d = {d}, vertices: {}, edges: {}, dense: {:.3}s, sparse: {:.3}s",
+            graph.vertex_num,
+            graph.weighted_edges.len(),
+            elapsed[0],
+            elapsed[1]
+        );
+        let shots = syndrome_patterns.len();
+        assert_eq!(sum_dual_variables[..shots], sum_dual_variables[shots..]);
+        assert!(elapsed[1] < elapsed[0]);
+    }
+
+    /// every primal policy resolves the obstacles in a different order but reaches the same matching weight
+    #[test]
+    fn dual_module_comb_primal_policy() {
+        // cargo test dual_module_comb_primal_policy -- --nocapture
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        let mut code = PhenomenologicalRotatedCode::new(5, 4, 0.05, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), json!({}));
+        for primal_policy in ["HardwareOrder", "OldestNodeFirst", "NewestNodeFirst"] {
+            for conflict_queue_depth in [1, 8] {
+                let mut policy_solver = SolverEmbeddedComb::new(
+                    graph.clone(),
+                    json!({ "dual": { "primal_policy": primal_policy, "conflict_queue_depth": conflict_queue_depth } }),
+                );
+                let mut iterations = 0;
+                for seed in 0..50 {
+                    let syndrome_pattern = code.generate_random_errors(seed);
+                    solver.solve(&syndrome_pattern);
+                    policy_solver.solve(&syndrome_pattern);
+                    iterations += policy_solver.iterations();
+                    assert_eq!(solver.sum_dual_variables(), policy_solver.sum_dual_variables());
+                    solver.clear();
+                    policy_solver.clear();
+                }
+                println!("{primal_policy} (queue depth {conflict_queue_depth}): {iterations} iterations");
+            }
+        }
+    }
+
+    /// the intermediate matching is available between steps and settles once the solver finishes
+    #[test]
+    fn dual_module_comb_intermediate_matching_1() {
+        // cargo test dual_module_comb_intermediate_matching_1 -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        code.set_defect_vertices(&[16, 17, 18, 26, 34, 39]);
+        let mut solver = SolverEmbeddedComb::new(MicroBlossomSingle::new_code(&code), json!({}));
+        solver.load_syndrome(&code.get_syndrome());
+        let mut steps = 0;
+        while solver.step() {
+            steps += 1;
+            let intermediate_matching = solver.intermediate_matching();
+            println!("step {steps}: {intermediate_matching:?}");
+            for &(node_1, node_2) in intermediate_matching.peer_matchings.iter() {
+                assert!(!intermediate_matching.unsettled.contains(&node_1));
+                assert!(!intermediate_matching.unsettled.contains(&node_2));
+            }
+        }
+        assert!(steps > 0);
+        let intermediate_matching = solver.intermediate_matching();
+        assert!(intermediate_matching.unsettled.is_empty());
+        assert!(!intermediate_matching.peer_matchings.is_empty() || !intermediate_matching.virtual_matchings.is_empty());
+    }
+
+    /// X and Z decoding graphs interleaved per round on the same dual core give the same result as decoding alone
+    #[test]
+    fn dual_module_comb_interleaved_xz_1() {
+        // cargo test dual_module_comb_interleaved_xz_1 -- --nocapture
+        use crate::example_codes::set_boundary_half_weights;
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        // the X and Z graphs of a rotated code share the topology; the Z graph has cheaper boundaries, e.g., because
+        // of a different noise on the boundary qubits
+        let mut code_x = PhenomenologicalRotatedCode::new(5, 4, 0.03, 500);
+        let mut code_z = PhenomenologicalRotatedCode::new(5, 4, 0.03, 500);
+        let half_weights = (0..code_z.immutable_vertices_edges().0.len())
+            .map(|vertex_index| (vertex_index, 300))
+            .collect();
+        set_boundary_half_weights(&mut code_z, &half_weights);
+        let graphs = vec![MicroBlossomSingle::new_code(&code_x), MicroBlossomSingle::new_code(&code_z)];
+        assert_ne!(graphs[0].weighted_edges, graphs[1].weighted_edges);
+        let num_layers = graphs[0].layer_fusion.as_ref().unwrap().num_layers;
+        let config = json!({ "dual": { "sim_config": { "support_layer_fusion": true } } });
+        let mut solver = SolverInterleaved::new(graphs, config);
+        let initializers = [code_x.get_initializer(), code_z.get_initializer()];
+        for seed in 0..20 {
+            let syndrome_patterns = vec![
+                code_x.generate_random_errors(seed),
+                code_z.generate_random_errors(seed + 1000),
+            ];
+            solver.solve(&syndrome_patterns);
+            assert_eq!(solver.round_latencies.len(), num_layers);
+            let subgraphs = solver.subgraphs();
+            for ((initializer, syndrome_pattern), subgraph) in
+                (initializers.iter().zip(syndrome_patterns.iter())).zip(subgraphs.iter())
+            {
+                let mut standard_solver = fusion_blossom::mwpm_solver::SolverSerial::new(initializer);
+                let mut subgraph_builder = fusion_blossom::primal_module::SubGraphBuilder::new(initializer);
+                standard_solver.solve(syndrome_pattern);
+                subgraph_builder.load_subgraph(&standard_solver.subgraph());
+                let standard_total_weight = subgraph_builder.total_weight();
+                subgraph_builder.load_subgraph(subgraph);
+                assert_eq!(subgraph_builder.total_weight(), standard_total_weight);
+                let original_defects = syndrome_pattern.defect_vertices.iter().cloned().collect();
+                assert_eq!(initializer.syndrome_of(subgraph), original_defects);
+            }
+            solver.clear();
+        }
+    }
+
+    /// lost and duplicated messages are recovered by retransmission, without affecting the result
+    #[test]
+    fn dual_module_comb_sequence_resync_1() {
+        // cargo test dual_module_comb_sequence_resync_1 -- --nocapture
+        let visualize_filename = "dual_module_comb_sequence_resync_1.json".to_string();
+        let defect_vertices = vec![16, 17, 18, 26, 34, 39];
+        let solver =
+            dual_module_standard_optional_viz(7, Some(visualize_filename), defect_vertices, |initializer, positions| {
+                SolverEmbeddedComb::new(
+                    MicroBlossomSingle::new(initializer, positions),
+                    json!({ "dual": { "sequence_check": {
+                        "drop_instruction": 0.1,
+                        "drop_response": 0.1,
+                        "duplicate_instruction": 0.1,
+                        "seed": 123,
+                    } } }),
+                )
+            });
+        let sequenced_bus = solver.dual_module.driver.driver.sequenced_bus.as_ref().unwrap();
+        println!(
+            "{:?}, duplicated: {}",
+            sequenced_bus.sequencer.statistics, sequenced_bus.sequence_filter.duplicated
+        );
+        assert!(sequenced_bus.sequencer.statistics.lost > 0);
+        assert!(sequenced_bus.sequence_filter.duplicated > 0);
+    }
+
+    /// an unrecoverable desynchronization fails the shot instead of panicking, and the correction stays valid
+    #[test]
+    fn dual_module_comb_sequence_failed_shot() {
+        // cargo test dual_module_comb_sequence_failed_shot -- --nocapture
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let initializer = code.get_initializer();
+        let graph = MicroBlossomSingle::new_code(&code);
+        for (recovery, max_retries) in [("Abort", 10), ("Resync", 0)] {
+            let config = json!({ "dual": { "sequence_check": {
+                "recovery": recovery,
+                "max_retries": max_retries,
+                "drop_instruction": 0.05,
+                "seed": 123,
+            } } });
+            let mut solver = SolverEmbeddedComb::new(graph.clone(), config);
+            let mut failed = 0;
+            for seed in 0..50 {
+                let syndrome_pattern = code.generate_random_errors(seed);
+                solver.solve(&syndrome_pattern);
+                let result = solver.result();
+                if result.desynchronized {
+                    failed += 1;
+                    assert!(!result.certified);
+                }
+                let original_defects = syndrome_pattern.defect_vertices.iter().cloned().collect();
+                assert_eq!(initializer.syndrome_of(&result.subgraph), original_defects);
+                solver.clear();
+            }
+            println!("{recovery}: {failed} failed shots");
+            assert!(failed > 0);
+            assert_eq!(solver.desynchronized_shots, failed);
+        }
+    }
+
+    /// an invalid combination of optional subsystems is reported as an error instead of panicking
+    #[test]
+    fn dual_module_comb_config_error() {
+        // cargo test dual_module_comb_config_error -- --nocapture
+        let code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let config: DualCombConfig = serde_json::from_value(json!({ "sparse": true, "faults": {} })).unwrap();
+        assert!(matches!(
+            DualModuleCombDriver::try_new(graph.clone(), config),
+            Err(CombConfigError::Incompatible {
+                option: "sparse",
+                other: "faults",
+                ..
+            })
+        ));
+        let config = json!({ "mirror": { "partitions": [[0, 4]] } });
+        let error = DualModuleCombDriver::try_new_from_graph_config(graph.clone(), config)
+            .err()
+            .unwrap();
+        assert!(matches!(error, DualDriverError::InvalidConfig { .. }), "{error}");
+        let config = json!({ "dual": { "conflict_queue_depth": 4, "batch_obstacles": true } });
+        let error = SolverEmbeddedComb::try_new(graph, config).err().unwrap();
+        println!("{error}");
+        assert!(matches!(error, DualDriverError::InvalidConfig { .. }));
+    }
+
     /// evaluate a new feature of pre matching without compromises global optimal result
     #[test]
     fn dual_module_comb_pre_matching_basic_1() {
@@ -588,6 +2383,104 @@ pub mod tests {
         }
     }
 
+    /// a defect two edges away from the boundary is pre-matched through the vertex in the middle
+    #[test]
+    fn dual_module_comb_pre_matching_chain() {
+        // cargo test dual_module_comb_pre_matching_chain -- --nocapture
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        let initializer = SolverInitializer::new(6, vec![(0, 1, 2), (1, 2, 8), (2, 3, 8), (3, 4, 8), (4, 5, 8)], vec![0, 5]);
+        let mut chain_offloading = OffloadingFinder::new();
+        chain_offloading.find_chain_match(&initializer);
+        assert_eq!(
+            chain_offloading.0,
+            vec![
+                OffloadingType::ChainMatch {
+                    edge_index: 1,
+                    boundary_edge: 0,
+                    virtual_vertex: 0
+                },
+                OffloadingType::ChainMatch {
+                    edge_index: 3,
+                    boundary_edge: 4,
+                    virtual_vertex: 5
+                },
+            ]
+        );
+        let graph = MicroBlossomSingle::new_initializer_only(&initializer);
+        let syndrome_pattern = SyndromePattern::new_vertices(vec![2]);
+        for (chain_offloading, offloaded) in [(false, 0), (true, 1)] {
+            let config = json!({
+                "dual": { "sim_config": { "support_offloading": true }, "chain_offloading": chain_offloading }
+            });
+            let mut solver = SolverEmbeddedComb::new(graph.clone(), config);
+            solver.solve(&syndrome_pattern);
+            assert_eq!(solver.offloaded, offloaded);
+            assert_eq!(solver.sum_dual_variables(), 10);
+            let perfect_matching = solver.perfect_matching();
+            assert_eq!(perfect_matching.virtual_matchings.len(), 1);
+            assert_eq!(perfect_matching.virtual_matchings[0].1, 0);
+        }
+    }
+
+    /// the chain offloading units only take over decisions of the primal module, so the matching weight is the same
+    #[test]
+    fn dual_module_comb_pre_matching_chain_random() {
+        // cargo test dual_module_comb_pre_matching_chain_random -- --nocapture
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        let mut code = ExampleCodeType::CircuitLevelPlanarCode.build(5, 0.01, 5, 500, json!({}));
+        let graph = MicroBlossomSingle::new_code(code.as_ref());
+        let new_solver = |chain_offloading: bool| {
+            let config = json!({
+                "dual": { "sim_config": { "support_offloading": true }, "chain_offloading": chain_offloading }
+            });
+            SolverEmbeddedComb::new(graph.clone(), config)
+        };
+        let (mut solver, mut chain_solver) = (new_solver(false), new_solver(true));
+        let (mut offloaded, mut chain_offloaded) = (0, 0);
+        for seed in 0..100 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            solver.solve(&syndrome_pattern);
+            chain_solver.solve(&syndrome_pattern);
+            assert_eq!(solver.sum_dual_variables(), chain_solver.sum_dual_variables());
+            offloaded += solver.offloaded;
+            chain_offloaded += chain_solver.offloaded;
+            solver.clear();
+            chain_solver.clear();
+        }
+        println!("offloaded: {offloaded}, with chains: {chain_offloaded}");
+        assert!(chain_offloaded >= offloaded);
+    }
+
+    #[test]
+    fn dual_module_comb_pre_matching_statistics() {
+        // cargo test dual_module_comb_pre_matching_statistics -- --nocapture
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        let mut code = ExampleCodeType::CircuitLevelPlanarCode.build(5, 0.01, 5, 500, json!({}));
+        let graph = MicroBlossomSingle::new_code(code.as_ref());
+        for support_offloading in [false, true] {
+            let config = json!({
+                "dual": { "sim_config": { "support_offloading": support_offloading }, "chain_offloading": true }
+            });
+            let mut solver = SolverEmbeddedComb::new(graph.clone(), config);
+            let (mut shots, mut defects, mut offloaded) = (0, 0, 0);
+            for seed in 0..100 {
+                // the last shot is counted without being cleared
+                solver.clear();
+                let syndrome_pattern = code.generate_random_errors(seed);
+                solver.solve(&syndrome_pattern);
+                shots += usize::from(!syndrome_pattern.defect_vertices.is_empty());
+                defects += syndrome_pattern.defect_vertices.len();
+                offloaded += solver.offloaded;
+            }
+            let statistics = &solver.dual_module.driver.driver.pre_matching_statistics;
+            println!("{}", statistics.generate_report());
+            assert_eq!((statistics.shots, statistics.defects), (shots, defects));
+            assert!(statistics.pre_matched() >= offloaded);
+            assert_eq!(statistics.pre_matched() > 0, support_offloading);
+            assert_eq!(statistics.escalated() + statistics.pre_matched(), defects);
+        }
+    }
+
     /// test layer fusion without any offloading
     #[test]
     fn dual_module_comb_layer_fusion_1() {
@@ -747,6 +2640,182 @@ pub mod tests {
         }
     }
 
+    /// asymmetric boundaries: the boundary edges on the left side are cheaper than those on the right side
+    #[test]
+    fn dual_module_comb_asymmetric_boundary() {
+        // cargo test dual_module_comb_asymmetric_boundary -- --nocapture
+        let d = 5;
+        let mut code = CodeCapacityPlanarCode::new(d, 0.1, 500);
+        let original = code.get_initializer();
+        let virtual_vertices: BTreeSet<_> = original.virtual_vertices.iter().cloned().collect();
+        let mut half_weights = BTreeMap::new();
+        for &(left, right, _) in original.weighted_edges.iter() {
+            if virtual_vertices.contains(&left) != virtual_vertices.contains(&right) {
+                // the virtual vertex on the left of each row has a smaller index
+                let regular = std::cmp::max(left, right);
+                let half_weight = if virtual_vertices.contains(&std::cmp::min(left, right)) {
+                    300
+                } else {
+                    700
+                };
+                half_weights.insert(regular, half_weight);
+            }
+        }
+        crate::example_codes::set_boundary_half_weights(&mut code, &half_weights);
+        let initializer = code.get_initializer();
+        let mut standard_solver = fusion_blossom::mwpm_solver::SolverSerial::new(&initializer);
+        let mut subgraph_builder = fusion_blossom::primal_module::SubGraphBuilder::new(&initializer);
+        let mut solver = SolverEmbeddedComb::new(
+            MicroBlossomSingle::new_code(&code),
+            json!({ "dual": { "sim_config": { "support_offloading": true } } }),
+        );
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        let mut syndrome_patterns = vec![];
+        for (left, right, _) in initializer.weighted_edges.iter() {
+            let defect_vertices: Vec<_> = [left, right]
+                .into_iter()
+                .filter(|vertex_index| !virtual_vertices.contains(vertex_index))
+                .cloned()
+                .collect();
+            syndrome_patterns.push(SyndromePattern::new_vertices(defect_vertices));
+        }
+        for seed in 0..50 {
+            syndrome_patterns.push(code.generate_random_errors(seed));
+        }
+        for syndrome_pattern in syndrome_patterns.iter() {
+            solver.solve(syndrome_pattern);
+            let subgraph = solver.subgraph();
+            let original_defects = syndrome_pattern.defect_vertices.iter().cloned().collect();
+            assert_eq!(initializer.syndrome_of(&subgraph), original_defects);
+            standard_solver.solve(syndrome_pattern);
+            subgraph_builder.load_subgraph(&standard_solver.subgraph());
+            let standard_total_weight = subgraph_builder.total_weight();
+            subgraph_builder.load_subgraph(&subgraph);
+            assert_eq!(subgraph_builder.total_weight(), standard_total_weight);
+            solver.clear();
+            standard_solver.clear();
+        }
+    }
+
+    /// isolated vertices, e.g., from an imported graph, are inert and do not affect the decoding result
+    #[test]
+    fn dual_module_comb_isolated_vertices() {
+        // cargo test dual_module_comb_isolated_vertices -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(5, 0.1, 500);
+        let mut initializer = code.get_initializer();
+        let mut positions = code.get_positions();
+        // append an isolated virtual vertex and an isolated regular vertex
+        let isolated_virtual = initializer.vertex_num;
+        initializer.vertex_num += 2;
+        initializer.virtual_vertices.push(isolated_virtual);
+        positions.push(VisualizePosition::new(-1., -1., 0.));
+        positions.push(VisualizePosition::new(-1., -2., 0.));
+        let mut solver = SolverEmbeddedComb::new(MicroBlossomSingle::new(&initializer, &positions), json!({}));
+        assert!(solver.dual_module.driver.driver.vertices[isolated_virtual].is_inert);
+        assert!(solver.dual_module.driver.driver.vertices[isolated_virtual + 1].is_inert);
+        let mut standard_solver = fusion_blossom::mwpm_solver::SolverSerial::new(&initializer);
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        for seed in 0..50 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            solver.solve(&syndrome_pattern);
+            standard_solver.solve(&syndrome_pattern);
+            assert_eq!(solver.sum_dual_variables(), standard_solver.sum_dual_variables());
+            solver.clear();
+            standard_solver.clear();
+        }
+    }
+
+    /// zero-weight edges, e.g., from erasures or merging, are always tight: two defects connected by a zero-weight
+    /// edge conflict immediately after loading, before any growth
+    #[test]
+    fn dual_module_comb_zero_weight_conflict() {
+        // cargo test dual_module_comb_zero_weight_conflict -- --nocapture
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        for support_offloading in [false, true] {
+            let visualize_filename = format!("dual_module_comb_zero_weight_conflict_{support_offloading}.json");
+            let solver = dual_module_comb_zero_weight_syndrome(7, visualize_filename, 1, &[0, 1], support_offloading);
+            if !support_offloading {
+                assert_eq!(solver.sum_dual_variables(), 0);
+            }
+        }
+    }
+
+    /// a node propagates through a chain of zero-weight edges without growing, one vertex per instruction
+    #[test]
+    fn dual_module_comb_zero_weight_propagate() {
+        // cargo test dual_module_comb_zero_weight_propagate -- --nocapture
+        use fusion_blossom::mwpm_solver::PrimalDualSolver;
+        for support_offloading in [false, true] {
+            let visualize_filename = format!("dual_module_comb_zero_weight_propagate_{support_offloading}.json");
+            let solver = dual_module_comb_zero_weight_syndrome(7, visualize_filename, 3, &[0, 3], support_offloading);
+            if !support_offloading {
+                assert_eq!(solver.sum_dual_variables(), 0);
+            }
+        }
+    }
+
+    /// a temporarily matched pair with zero dual variables is touched by a third node, so that the shrinking node
+    /// has zero dual variable already; the growing nodes must conflict through it instead of stalling forever
+    #[test]
+    fn dual_module_comb_zero_weight_shrink() {
+        // cargo test dual_module_comb_zero_weight_shrink -- --nocapture
+        for support_offloading in [false, true] {
+            let visualize_filename = format!("dual_module_comb_zero_weight_shrink_{support_offloading}.json");
+            dual_module_comb_zero_weight_syndrome(7, visualize_filename, 2, &[0, 1, 2], support_offloading);
+            let visualize_filename = format!("dual_module_comb_zero_weight_shrink_tail_{support_offloading}.json");
+            dual_module_comb_zero_weight_syndrome(7, visualize_filename, 3, &[0, 1, 2, 3], support_offloading);
+        }
+    }
+
+    /// random zero-weight edges and random defects compared with the serial solver, with and without pre-matching
+    #[test]
+    fn dual_module_comb_zero_weight_random() {
+        // cargo test dual_module_comb_zero_weight_random -- --nocapture
+        let d = 7;
+        let mut rng = Xoroshiro128StarStar::seed_from_u64(0);
+        for support_offloading in [false, true] {
+            for seed in 0..50 {
+                let mut code = CodeCapacityPlanarCode::new(d, 0.1, 500);
+                let edge_num = code.immutable_vertices_edges().1.len();
+                let zero_weight_edges: Vec<EdgeIndex> = (0..edge_num).filter(|_| rng.gen::<f64>() < 0.1).collect();
+                crate::example_codes::set_zero_weight_edges(&mut code, &zero_weight_edges);
+                let defect_vertices = code.generate_random_errors(seed).defect_vertices;
+                dual_module_code_optional_viz(&mut code, None, defect_vertices, |initializer, positions| {
+                    SolverEmbeddedComb::new(
+                        MicroBlossomSingle::new(initializer, positions),
+                        json!({ "dual": { "sim_config": { "support_offloading": support_offloading } } }),
+                    )
+                });
+            }
+        }
+    }
+
+    /// set the edges along a path of `path_length` regular vertices to zero weight and put defects at the given
+    /// positions of the path
+    pub fn dual_module_comb_zero_weight_syndrome(
+        d: VertexNum,
+        visualize_filename: String,
+        path_length: usize,
+        defect_positions: &[usize],
+        support_offloading: bool,
+    ) -> SolverEmbeddedComb {
+        let mut code = CodeCapacityPlanarCode::new(d, 0.1, 500);
+        let (path_edges, path_vertices) = find_regular_path(&code, path_length);
+        crate::example_codes::set_zero_weight_edges(&mut code, &path_edges);
+        let defect_vertices = defect_positions.iter().map(|&position| path_vertices[position]).collect();
+        dual_module_code_optional_viz(
+            &mut code,
+            Some(visualize_filename),
+            defect_vertices,
+            |initializer, positions| {
+                SolverEmbeddedComb::new(
+                    MicroBlossomSingle::new(initializer, positions),
+                    json!({ "dual": { "sim_config": { "support_offloading": support_offloading } } }),
+                )
+            },
+        )
+    }
+
     pub fn dual_module_comb_basic_standard_syndrome(
         d: VertexNum,
         visualize_filename: String,
@@ -773,4 +2842,53 @@ pub mod tests {
             },
         )
     }
+
+    /// two conflicts show up in the same round, and the one in the preferred region is reported first
+    #[test]
+    fn dual_module_comb_region_preference() {
+        // cargo test dual_module_comb_region_preference -- --nocapture
+        let code = CodeCapacityRepetitionCode::new(11, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let config = json!({ "region_size": 6 });
+        for region in [0, 1] {
+            let mut driver = DualModuleCombDriver::new(graph.clone(), serde_json::from_value(config.clone()).unwrap());
+            driver.region_preference = Some(region);
+            for (node, vertex) in [2, 3, 7, 8].into_iter().enumerate() {
+                driver.add_defect(ni!(vertex), ni!(node));
+            }
+            let (obstacle, _) = driver.find_obstacle();
+            println!("region {region}: {obstacle:?}");
+            let CompactObstacle::Conflict { vertex_1, vertex_2, .. } = obstacle else {
+                panic!("expect a conflict, got {obstacle:?}");
+            };
+            assert!([vertex_1, vertex_2].iter().all(|vertex| vertex.get() as usize / 6 == region));
+        }
+    }
+
+    #[test]
+    fn dual_module_comb_compact_index() {
+        // cargo test dual_module_comb_compact_index -- --nocapture
+        // cargo test --features u16_interface_index dual_module_comb_compact_index -- --nocapture
+        let largest = CompactVertexNum::MAX as usize - 1;
+        assert_eq!(compact_index(largest).get() as usize, largest);
+        assert!(std::panic::catch_unwind(|| compact_index(largest + 1)).is_err());
+        assert!(std::panic::catch_unwind(|| compact_index(VIRTUAL_NODE_INDEX)).is_err());
+    }
+
+    #[cfg(feature = "checked_weight")]
+    #[test]
+    fn dual_module_comb_checked_weight() {
+        // cargo test --features checked_weight dual_module_comb_checked_weight -- --nocapture
+        let code = CodeCapacityPlanarCode::new(5, 0.1, 500);
+        let mut driver = DualModuleCombDriver::new(
+            MicroBlossomSingle::new_code(&code),
+            serde_json::from_value(json!({})).unwrap(),
+        );
+        assert_eq!(driver.compact_length(100), 100);
+        assert!(!driver.weight_overflowed());
+        assert_eq!(driver.compact_length(Weight::MAX), CompactWeight::MAX);
+        assert!(driver.weight_overflowed());
+        driver.clear();
+        assert!(!driver.weight_overflowed());
+    }
 }
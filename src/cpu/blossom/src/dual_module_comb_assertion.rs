@@ -0,0 +1,430 @@
+//! Assertion Hooks of the Combinatorial Dual Module
+//!
+//! When the hardware disagrees with the behavioral model, the first question is which instruction broke which
+//! invariant. The hooks are predicates over (instruction, pre-state, post-state) that are evaluated after every
+//! instruction executed by [`DualModuleCombDriver`]; a violation is recorded together with a reproduction, i.e., the
+//! graph and the instructions since the last reset, which can be replayed against the RTL simulation.
+//!
+//! Enable the default hooks with `{"dual": {"assertions": {}}}`; more hooks can be registered with
+//! [`AssertionHooks::register`]. The default hooks encode the key invariants of Micro Blossom:
+//! - `non_negative_grown`: the growth of every vertex is non-negative
+//! - `tightness`: an edge between two different nodes is never grown beyond its weight
+//! - `blossom_parity`: a blossom contains an odd number of defect vertices when searching for obstacles
+//!
+//! The paranoid mode `{"dual": {"assertions": {"paranoid": true}}}` adds the more expensive hooks below and dumps
+//! the instructions together with the registers before and after the violating instruction in the panic message, so
+//! that a divergence of the hardware model is caught at the very instruction instead of at the end of the shot:
+//! - `edge_growth`: an edge is never grown beyond its weight, also when one end is a virtual vertex
+//! - `node_consistency`: the node of every vertex agrees with its flags, i.e., a virtual vertex always keeps the
+//!   virtual node, a defect vertex always has its own node, and a vertex without a node neither grows nor has a root
+//!
+
+use crate::dual_module_comb::*;
+use crate::dual_module_comb_fault_injection::*;
+use crate::dual_module_comb_vertex::*;
+use crate::resources::*;
+use fusion_blossom::util::*;
+use micro_blossom_nostd::util::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssertionConfig {
+    /// register the default hooks that encode the invariants of Micro Blossom
+    #[serde(default = "assertion_config_default::default_hooks")]
+    pub default_hooks: bool,
+    /// dump the reproduction of the first violation to this file
+    #[serde(default = "Default::default")]
+    pub dump_file: Option<String>,
+    /// panic on a violation; otherwise the violations are only recorded and reported in the profile
+    #[serde(default = "assertion_config_default::panic")]
+    pub panic: bool,
+    /// register the paranoid hooks as well, and dump the registers in the panic message
+    #[serde(default = "Default::default")]
+    pub paranoid: bool,
+}
+
+pub mod assertion_config_default {
+    pub fn default_hooks() -> bool {
+        true
+    }
+    pub fn panic() -> bool {
+        true
+    }
+}
+
+/// the registers of the behavioral model before or after an instruction
+#[derive(Debug, Clone)]
+pub struct CombState {
+    pub vertices: Vec<VertexRegisters>,
+    /// the effective weight of every edge, i.e., halved when the conditioned vertex is still virtual
+    pub edge_weights: Vec<Weight>,
+}
+
+impl CombState {
+    pub fn capture(driver: &DualModuleCombDriver) -> Self {
+        Self {
+            vertices: driver.vertices.iter().map(|vertex| vertex.registers.clone()).collect(),
+            edge_weights: driver
+                .edges
+                .iter()
+                .map(|edge| match edge.conditioned_vertex {
+                    Some(vertex_index) if driver.vertices[vertex_index].registers.is_virtual => {
+                        (edge.registers.weight / 4) * 2
+                    }
+                    _ => edge.registers.weight,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        json!({
+            "vertices": self.vertices.iter().map(|registers| registers.snapshot()).collect::<Vec<_>>(),
+            "edge_weights": self.edge_weights,
+        })
+    }
+}
+
+pub struct AssertionContext<'a> {
+    pub instruction: &'a Instruction,
+    pub pre: &'a CombState,
+    pub post: &'a CombState,
+    pub graph: &'a MicroBlossomSingle,
+}
+
+/// returns the reason of the violation
+pub type AssertionPredicate = Box<dyn Fn(&AssertionContext) -> Result<(), String>>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AssertionViolation {
+    pub hook: String,
+    pub message: String,
+    /// the index of the violating instruction since the last reset
+    pub instruction_index: usize,
+}
+
+pub struct AssertionHooks {
+    pub config: AssertionConfig,
+    hooks: Vec<(String, AssertionPredicate)>,
+    /// the instructions since the last reset, for the reproduction
+    history: Vec<Instruction>,
+    pub violations: Vec<AssertionViolation>,
+    /// the registers before the executing instruction
+    pre_state: Option<CombState>,
+}
+
+impl AssertionHooks {
+    pub fn new(config: AssertionConfig) -> Self {
+        let mut hooks = Self {
+            hooks: vec![],
+            history: vec![],
+            violations: vec![],
+            pre_state: None,
+            config,
+        };
+        if hooks.config.default_hooks {
+            hooks.register_default_hooks();
+        }
+        if hooks.config.paranoid {
+            hooks.register_paranoid_hooks();
+        }
+        hooks
+    }
+
+    pub fn register(&mut self, name: &str, predicate: AssertionPredicate) {
+        self.hooks.push((name.to_string(), predicate));
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    fn register_default_hooks(&mut self) {
+        self.register(
+            "non_negative_grown",
+            Box::new(
+                |context| match context.post.vertices.iter().position(|vertex| vertex.grown < 0) {
+                    Some(vertex_index) => Err(format!("vertex {vertex_index} has negative growth")),
+                    None => Ok(()),
+                },
+            ),
+        );
+        self.register(
+            "tightness",
+            Box::new(|context| {
+                let vertices = &context.post.vertices;
+                for (edge_index, edge) in context.graph.weighted_edges.iter().enumerate() {
+                    let (left, right) = (&vertices[edge.l], &vertices[edge.r]);
+                    let is_live = |vertex: &VertexRegisters| !vertex.is_virtual && !vertex.is_frozen;
+                    if !is_live(left) || !is_live(right) || left.node_index.is_none() || right.node_index.is_none() {
+                        continue;
+                    }
+                    let weight = context.post.edge_weights[edge_index];
+                    if left.node_index != right.node_index && left.grown + right.grown > weight {
+                        return Err(format!(
+                            "edge {edge_index} between nodes {:?} and {:?} is over-grown: {} + {} > {weight}",
+                            left.node_index, right.node_index, left.grown, right.grown
+                        ));
+                    }
+                }
+                Ok(())
+            }),
+        );
+        self.register(
+            "blossom_parity",
+            Box::new(|context| {
+                // a blossom is only complete after all its children are set, i.e., when searching for obstacles
+                if !context.instruction.reads_obstacle() {
+                    return Ok(());
+                }
+                // outer node -> (number of defect vertices, whether it is a blossom)
+                let mut outer_nodes = BTreeMap::<NodeIndex, (usize, bool)>::new();
+                for vertex in context.pre.vertices.iter().filter(|vertex| vertex.is_defect) {
+                    if let Some(node_index) = vertex.node_index {
+                        let entry = outer_nodes.entry(node_index).or_default();
+                        entry.0 += 1;
+                        entry.1 |= vertex.root_index != Some(node_index);
+                    }
+                }
+                match outer_nodes
+                    .into_iter()
+                    .find(|(_, (defects, is_blossom))| *is_blossom && defects % 2 == 0)
+                {
+                    Some((node_index, (defects, _))) => Err(format!("blossom {node_index} has {defects} defect vertices")),
+                    None => Ok(()),
+                }
+            }),
+        );
+    }
+
+    fn register_paranoid_hooks(&mut self) {
+        self.register(
+            "edge_growth",
+            Box::new(|context| {
+                let vertices = &context.post.vertices;
+                for (edge_index, edge) in context.graph.weighted_edges.iter().enumerate() {
+                    let (left, right) = (&vertices[edge.l], &vertices[edge.r]);
+                    if left.is_frozen || right.is_frozen || left.node_index == right.node_index {
+                        continue;
+                    }
+                    let weight = context.post.edge_weights[edge_index];
+                    if left.grown + right.grown > weight {
+                        return Err(format!(
+                            "edge {edge_index} ({} - {}) is over-grown: {} + {} > {weight}",
+                            edge.l, edge.r, left.grown, right.grown
+                        ));
+                    }
+                }
+                Ok(())
+            }),
+        );
+        self.register(
+            "node_consistency",
+            Box::new(|context| {
+                for (vertex_index, vertex) in context.post.vertices.iter().enumerate() {
+                    let is_virtual_node = vertex.node_index == Some(VIRTUAL_NODE_INDEX);
+                    if vertex.node_index.is_some() != vertex.root_index.is_some() {
+                        return Err(format!(
+                            "vertex {vertex_index} has node {:?} but root {:?}",
+                            vertex.node_index, vertex.root_index
+                        ));
+                    }
+                    if vertex.is_virtual && (!is_virtual_node || vertex.root_index != Some(VIRTUAL_NODE_INDEX)) {
+                        return Err(format!(
+                            "virtual vertex {vertex_index} is propagated to node {:?} of root {:?}",
+                            vertex.node_index, vertex.root_index
+                        ));
+                    }
+                    // a defect vertex of a released node stays in the frozen region without a node
+                    if vertex.is_defect && ((vertex.node_index.is_none() && !vertex.is_frozen) || is_virtual_node) {
+                        return Err(format!("defect vertex {vertex_index} has node {:?}", vertex.node_index));
+                    }
+                    if vertex.node_index.is_none()
+                        && !vertex.is_frozen
+                        && (vertex.grown != 0 || vertex.speed != CompactGrowState::Stay)
+                    {
+                        return Err(format!(
+                            "vertex {vertex_index} without node has growth {} and speed {:?}",
+                            vertex.grown, vertex.speed
+                        ));
+                    }
+                }
+                Ok(())
+            }),
+        );
+    }
+
+    /// evaluate all the hooks after an instruction
+    pub fn check(&mut self, instruction: &Instruction, pre: &CombState, post: &CombState, graph: &MicroBlossomSingle) {
+        self.history.push(instruction.clone());
+        let context = AssertionContext {
+            instruction,
+            pre,
+            post,
+            graph,
+        };
+        for (name, predicate) in self.hooks.iter() {
+            let Err(message) = predicate(&context) else {
+                continue;
+            };
+            let violation = AssertionViolation {
+                hook: name.clone(),
+                message,
+                instruction_index: self.history.len() - 1,
+            };
+            if self.violations.is_empty() {
+                if let Some(dump_file) = self.config.dump_file.as_ref() {
+                    let reproduction = json!({
+                        "violation": violation,
+                        "instructions": self.history,
+                        "pre": pre.snapshot(),
+                        "post": post.snapshot(),
+                        "graph": graph,
+                    });
+                    std::fs::write(dump_file, serde_json::to_string(&reproduction).unwrap()).unwrap();
+                }
+            }
+            if self.config.panic {
+                let dump = if self.config.paranoid {
+                    let dump = json!({
+                        "instructions": self.history,
+                        "pre": pre.snapshot(),
+                        "post": post.snapshot(),
+                    });
+                    format!("\n{}", serde_json::to_string_pretty(&dump).unwrap())
+                } else {
+                    String::new()
+                };
+                panic!(
+                    "assertion `{}` violated by {instruction:?}: {}{dump}",
+                    violation.hook, violation.message
+                );
+            }
+            self.violations.push(violation);
+        }
+    }
+}
+
+impl CombObserver for AssertionHooks {
+    fn name(&self) -> &'static str {
+        "assertion_violations"
+    }
+    fn before_execute(&mut self, driver: &DualModuleCombDriver) {
+        self.pre_state = Some(CombState::capture(driver));
+    }
+    fn after_execute(&mut self, driver: &mut DualModuleCombDriver) {
+        let pre_state = self.pre_state.take().unwrap();
+        let post_state = CombState::capture(driver);
+        self.check(&driver.instruction, &pre_state, &post_state, &driver.graph);
+        if let Some(fault_injector) = driver.observer_mut::<FaultInjector>() {
+            fault_injector.observe(&self.violations);
+        }
+    }
+    fn clear(&mut self) {
+        AssertionHooks::clear(self);
+    }
+    fn reset_profiler(&mut self) {
+        self.violations.clear();
+    }
+    fn generate_report(&self) -> Option<serde_json::Value> {
+        Some(json!(self.violations))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwpm_solver::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::*;
+    use micro_blossom_nostd::dual_driver_tracked::*;
+    use micro_blossom_nostd::dual_module_stackless::*;
+
+    /// the default invariants hold for random shots, with and without layer fusion
+    #[test]
+    fn dual_module_comb_assertion_default_hooks() {
+        // cargo test dual_module_comb_assertion_default_hooks -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 4, 0.05, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        for support_layer_fusion in [false, true] {
+            let config = json!({ "dual": {
+                "assertions": {},
+                "sim_config": { "support_layer_fusion": support_layer_fusion },
+            } });
+            let mut solver = SolverEmbeddedComb::new(graph.clone(), config);
+            for seed in 0..50 {
+                solver.solve(&code.generate_random_errors(seed));
+                solver.clear();
+            }
+        }
+    }
+
+    /// the paranoid hooks hold for random shots, and a corrupted register panics with the dump of the registers
+    #[test]
+    fn dual_module_comb_assertion_paranoid() {
+        // cargo test dual_module_comb_assertion_paranoid -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 4, 0.05, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        for support_layer_fusion in [false, true] {
+            let config = json!({ "dual": {
+                "assertions": { "paranoid": true },
+                "sim_config": { "support_layer_fusion": support_layer_fusion },
+            } });
+            let mut solver = SolverEmbeddedComb::new(graph.clone(), config);
+            for seed in 0..50 {
+                solver.solve(&code.generate_random_errors(seed));
+                solver.clear();
+            }
+        }
+        let graph = MicroBlossomSingle::new_code(&CodeCapacityPlanarCode::new(7, 0.1, 500));
+        let config: DualCombConfig = serde_json::from_value(json!({ "assertions": { "paranoid": true } })).unwrap();
+        let mut driver = DualModuleCombDriver::new(graph, config);
+        driver.vertices[16].registers.grown = 4;
+        driver.register_updated();
+        let error =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| driver.add_defect(ni!(26), ni!(0)))).unwrap_err();
+        let message = error.downcast_ref::<String>().unwrap();
+        println!("{message}");
+        assert!(message.contains("node_consistency"));
+        assert!(message.contains("\"post\""));
+    }
+
+    /// a violated user hook is recorded with a reproduction
+    #[test]
+    fn dual_module_comb_assertion_reproduction() {
+        // cargo test dual_module_comb_assertion_reproduction -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let dump_file = std::env::temp_dir().join("dual_module_comb_assertion_reproduction.json");
+        let config = json!({ "dual": { "assertions": {
+            "default_hooks": false,
+            "panic": false,
+            "dump_file": dump_file,
+        } } });
+        let mut solver = SolverEmbeddedComb::new(graph, config);
+        let hooks = solver.dual_module.driver.driver.observer_mut::<AssertionHooks>().unwrap();
+        hooks.register(
+            "no_growth_beyond_100",
+            Box::new(
+                |context| match context.post.vertices.iter().any(|vertex| vertex.grown > 100) {
+                    true => Err("grown beyond 100".to_string()),
+                    false => Ok(()),
+                },
+            ),
+        );
+        solver.solve(&code.generate_random_errors(0));
+        let hooks = solver.dual_module.driver.driver.observer::<AssertionHooks>().unwrap();
+        assert!(!hooks.violations.is_empty());
+        assert!(hooks
+            .violations
+            .iter()
+            .all(|violation| violation.hook == "no_growth_beyond_100"));
+        let reproduction: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&dump_file).unwrap()).unwrap();
+        let instruction_index = hooks.violations[0].instruction_index;
+        assert_eq!(reproduction["instructions"].as_array().unwrap().len(), instruction_index + 1);
+        assert_eq!(reproduction["violation"]["hook"], json!("no_growth_beyond_100"));
+    }
+}
@@ -0,0 +1,150 @@
+//! Checkpoint of the Combinatorial Dual Module
+//!
+//! A failing instruction deep into a long benchmark is tedious to reproduce by replaying the whole shot. A
+//! [`CombCheckpoint`] saves the registers of the vertices and edges together with the node duals in the middle of a
+//! decoding, so that a fresh dual module of the same graph can resume right before the instruction in question. The
+//! combinational signals are not saved: they are evaluated again from the registers and the last instruction.
+//!
+//! The pending conflicts of the conflict queue and the obstacle batch belong to the primal module's view of the last
+//! round and are dropped on restore, so take the checkpoint right after a `FindObstacle` has been consumed.
+//!
+
+use crate::dual_module_comb::*;
+use crate::dual_module_comb_edge::*;
+use crate::dual_module_comb_vertex::*;
+use fusion_blossom::util::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckpointError {
+    /// the checkpoint is not valid JSON of a [`CombCheckpoint`]
+    Format { message: String },
+    /// the checkpoint is taken on a different graph
+    GraphMismatch {
+        vertex_num: usize,
+        edge_num: usize,
+        expected_vertex_num: usize,
+        expected_edge_num: usize,
+    },
+}
+
+impl std::fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Format { message } => write!(f, "invalid checkpoint: {message}"),
+            Self::GraphMismatch {
+                vertex_num,
+                edge_num,
+                expected_vertex_num,
+                expected_edge_num,
+            } => write!(
+                f,
+                "checkpoint of {vertex_num} vertices and {edge_num} edges, \
+                but the graph has {expected_vertex_num} vertices and {expected_edge_num} edges"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CombCheckpoint {
+    pub vertices: Vec<VertexRegisters>,
+    pub edges: Vec<EdgeRegisters>,
+    /// the last instruction, which the combinational signals depend on
+    pub instruction: Instruction,
+    /// only maintained when `config.sparse` is set
+    pub active_vertices: BTreeSet<VertexIndex>,
+}
+
+impl DualModuleCombDriver {
+    pub fn checkpoint(&self) -> CombCheckpoint {
+        CombCheckpoint {
+            vertices: self.vertices.iter().map(|vertex| vertex.registers.clone()).collect(),
+            edges: self.edges.iter().map(|edge| edge.registers.clone()).collect(),
+            instruction: self.instruction.clone(),
+            active_vertices: self.active_vertices.clone(),
+        }
+    }
+
+    pub fn serialize_state(&self) -> serde_json::Value {
+        serde_json::to_value(self.checkpoint()).unwrap()
+    }
+
+    /// the state is left unchanged if the checkpoint is rejected
+    pub fn restore_state(&mut self, state: &serde_json::Value) -> Result<(), CheckpointError> {
+        let checkpoint: CombCheckpoint = serde_json::from_value(state.clone()).map_err(|error| CheckpointError::Format {
+            message: error.to_string(),
+        })?;
+        if checkpoint.vertices.len() != self.vertices.len() || checkpoint.edges.len() != self.edges.len() {
+            return Err(CheckpointError::GraphMismatch {
+                vertex_num: checkpoint.vertices.len(),
+                edge_num: checkpoint.edges.len(),
+                expected_vertex_num: self.vertices.len(),
+                expected_edge_num: self.edges.len(),
+            });
+        }
+        for (vertex, registers) in self.vertices.iter_mut().zip(checkpoint.vertices) {
+            vertex.registers = registers;
+        }
+        for (edge, registers) in self.edges.iter_mut().zip(checkpoint.edges) {
+            edge.registers = registers;
+        }
+        self.instruction = checkpoint.instruction;
+        self.active_vertices = checkpoint.active_vertices;
+        self.conflict_queue.clear();
+        self.obstacle_batch.clear();
+        self.register_updated();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::visualize::*;
+    use micro_blossom_nostd::dual_driver_tracked::*;
+    use micro_blossom_nostd::dual_module_stackless::*;
+    use micro_blossom_nostd::util::*;
+    use serde_json::json;
+
+    /// a fresh dual module resumes from the checkpoint exactly as the original one continues
+    #[test]
+    fn dual_module_comb_checkpoint_resume() {
+        // cargo test dual_module_comb_checkpoint_resume -- --nocapture
+        let code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let mut driver = DualModuleCombDriver::new(graph.clone(), DualCombConfig::default());
+        driver.add_defect(ni!(16), ni!(0));
+        driver.add_defect(ni!(26), ni!(1));
+        driver.find_conflict(2);
+        let checkpoint = serde_json::to_string(&driver.serialize_state()).unwrap();
+        let mut restored = DualModuleCombDriver::new(graph, DualCombConfig::default());
+        restored.restore_state(&serde_json::from_str(&checkpoint).unwrap()).unwrap();
+        assert_eq!(restored.snapshot(true), driver.snapshot(true));
+        assert_eq!(restored.read_vertex_grown(ni!(16)), driver.read_vertex_grown(ni!(16)));
+        assert_eq!(
+            restored.find_conflict(CompactWeight::MAX),
+            driver.find_conflict(CompactWeight::MAX)
+        );
+        assert_eq!(restored.snapshot(true), driver.snapshot(true));
+    }
+
+    #[test]
+    fn dual_module_comb_checkpoint_reject() {
+        // cargo test dual_module_comb_checkpoint_reject -- --nocapture
+        let small = MicroBlossomSingle::new_code(&CodeCapacityPlanarCode::new(5, 0.1, 500));
+        let large = MicroBlossomSingle::new_code(&CodeCapacityPlanarCode::new(7, 0.1, 500));
+        let checkpoint = DualModuleCombDriver::new(small, DualCombConfig::default()).serialize_state();
+        let mut driver = DualModuleCombDriver::new(large, DualCombConfig::default());
+        let error = driver.restore_state(&checkpoint).unwrap_err();
+        println!("{error}");
+        assert!(matches!(error, CheckpointError::GraphMismatch { .. }));
+        let error = driver.restore_state(&json!({ "vertices": 1 })).unwrap_err();
+        assert!(matches!(error, CheckpointError::Format { .. }));
+    }
+}
@@ -0,0 +1,284 @@
+//! Multiple Contexts of the Combinatorial Dual Module
+//!
+//! The hardware keeps the registers of every vertex and edge in a small memory addressed by the context id that comes
+//! with every instruction, so that a single instance interleaves several independent decoding problems on the same
+//! graph, e.g., the X and Z graphs of a surface code: while the CPU resolves an obstacle of one context, the
+//! instructions of another context keep the dual module busy. [`ContextBank`] models this memory: the driver works on
+//! the registers of the active context in place and the bank holds those of the others, so that switching the
+//! context swaps the registers instead of resetting them. The per-context state is exactly what a [`CombCheckpoint`]
+//! saves; the statistics are shared by all the contexts and only cleared by a full reset.
+//!
+//! Similar to the `context_id` of the embedded driver, a [`ContextDriver`] is a handle to a shared dual module that
+//! switches to its own context before every instruction, so that every context runs its own primal module. Enable it
+//! with `{ "dual": { "context_depth": 2 } }`.
+//!
+
+use crate::dual_module_comb::*;
+use crate::dual_module_comb_checkpoint::*;
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use fusion_blossom::dual_module::*;
+use fusion_blossom::primal_module::*;
+use fusion_blossom::visualize::*;
+use micro_blossom_nostd::dual_driver_tracked::*;
+use micro_blossom_nostd::dual_module_stackless::*;
+use micro_blossom_nostd::interface::*;
+use micro_blossom_nostd::util::*;
+use serde::*;
+use std::cell::{RefCell, RefMut};
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ContextStatistics {
+    /// the number of instructions addressing a different context than the previous one
+    pub switches: usize,
+}
+
+pub struct ContextBank {
+    pub depth: usize,
+    pub active: usize,
+    /// the registers of every context, where the entry of the active context is stale
+    pub contexts: Vec<CombCheckpoint>,
+    pub statistics: ContextStatistics,
+}
+
+impl ContextBank {
+    pub fn new(depth: usize) -> Self {
+        assert!(depth >= 1, "at least one context is required");
+        Self {
+            depth,
+            active: 0,
+            contexts: vec![],
+            statistics: ContextStatistics::default(),
+        }
+    }
+
+    /// reset every context to the given state, i.e., the registers after a full reset
+    pub fn reset(&mut self, checkpoint: CombCheckpoint) {
+        self.contexts = vec![checkpoint; self.depth];
+        self.active = 0;
+    }
+}
+
+impl DualModuleCombDriver {
+    /// exchange the registers of the active context with the saved ones
+    fn swap_context(&mut self, context: &mut CombCheckpoint) {
+        for (vertex, registers) in self.vertices.iter_mut().zip(context.vertices.iter_mut()) {
+            std::mem::swap(&mut vertex.registers, registers);
+        }
+        for (edge, registers) in self.edges.iter_mut().zip(context.edges.iter_mut()) {
+            std::mem::swap(&mut edge.registers, registers);
+        }
+        std::mem::swap(&mut self.instruction, &mut context.instruction);
+        std::mem::swap(&mut self.active_vertices, &mut context.active_vertices);
+    }
+
+    /// address the following instructions to another context; a driver without contexts only has context 0
+    pub fn switch_context(&mut self, context_id: usize) {
+        let Some(mut context_bank) = self.context_bank.take() else {
+            assert_eq!(context_id, 0, "context switching requires `context_depth` > 1");
+            return;
+        };
+        assert!(
+            context_id < context_bank.depth,
+            "context {context_id} out of the depth {}",
+            context_bank.depth
+        );
+        if context_id != context_bank.active {
+            // the buffered speed updates belong to the previous context
+            self.flush_speeds();
+            let active = context_bank.active;
+            self.swap_context(&mut context_bank.contexts[active]);
+            self.swap_context(&mut context_bank.contexts[context_id]);
+            context_bank.active = context_id;
+            context_bank.statistics.switches += 1;
+            self.register_updated();
+        }
+        self.context_bank = Some(context_bank);
+    }
+
+    /// reset the registers of the active context, keeping the other contexts and the statistics
+    pub fn reset_context(&mut self) {
+        self.flush_speeds();
+        for vertex in self.vertices.iter_mut() {
+            vertex.clear();
+        }
+        for edge in self.edges.iter_mut() {
+            edge.clear();
+        }
+        self.conflict_queue.clear();
+        self.obstacle_batch.clear();
+        self.active_vertices.clear();
+        self.desynchronized = false;
+    }
+}
+
+/// a handle to a single context of a shared dual module
+pub struct ContextDriver {
+    pub driver: Rc<RefCell<DualModuleCombDriver>>,
+    pub context_id: usize,
+}
+
+impl ContextDriver {
+    pub fn new(driver: DualModuleCombDriver) -> Self {
+        Self {
+            driver: Rc::new(RefCell::new(driver)),
+            context_id: 0,
+        }
+    }
+
+    /// another context of the same dual module
+    pub fn share(&self, context_id: usize) -> Self {
+        Self {
+            driver: self.driver.clone(),
+            context_id,
+        }
+    }
+
+    /// the shared dual module addressing this context
+    fn active(&self) -> RefMut<DualModuleCombDriver> {
+        let mut driver = self.driver.borrow_mut();
+        driver.switch_context(self.context_id);
+        driver
+    }
+}
+
+impl SolverTrackedDual for ContextDriver {
+    fn new_from_graph_config(graph: MicroBlossomSingle, config: serde_json::Value) -> Self {
+        Self::new(DualModuleCombDriver::new(graph, serde_json::from_value(config).unwrap()))
+    }
+    fn try_new_from_graph_config(graph: MicroBlossomSingle, config: serde_json::Value) -> Result<Self, DualDriverError> {
+        Ok(Self::new(DualModuleCombDriver::try_new_from_graph_config(graph, config)?))
+    }
+    fn reset_profiler(&mut self) {
+        self.driver.borrow_mut().reset_profiler();
+    }
+    fn get_pre_matchings(&self, belonging: DualModuleInterfaceWeak) -> PerfectMatching {
+        self.active().get_pre_matchings(belonging)
+    }
+    // the statistics are shared by the contexts, while the instructions address the active one
+    forward_solver_tracked_dual!(self, self.driver.borrow(), self.active();
+        generate_profiler_report, fuse_layer, instruction_counts, clock_cycles, hardware_counters,
+        supports_dual_readback, weight_overflowed, desynchronized, freeze_vertex_range, set_edge_weight, remove_defect,
+        release_nodes, finish_shot,
+    );
+}
+
+impl DualStacklessDriver for ContextDriver {
+    fn reset(&mut self) {
+        self.active().reset_context();
+    }
+    fn set_speed(&mut self, is_blossom: bool, node: CompactNodeIndex, speed: CompactGrowState) {
+        self.active().set_speed(is_blossom, node, speed);
+    }
+    fn set_speed_with_magnitude(
+        &mut self,
+        is_blossom: bool,
+        node: CompactNodeIndex,
+        speed: CompactGrowState,
+        magnitude: CompactSpeed,
+    ) {
+        self.active().set_speed_with_magnitude(is_blossom, node, speed, magnitude);
+    }
+    fn max_speed_magnitude(&self) -> CompactSpeed {
+        MAX_SPEED_MAGNITUDE
+    }
+    fn set_blossom(&mut self, node: CompactNodeIndex, blossom: CompactNodeIndex) {
+        self.active().set_blossom(node, blossom);
+    }
+    fn find_obstacle(&mut self) -> (CompactObstacle, CompactWeight) {
+        self.active().find_obstacle()
+    }
+    fn add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) {
+        self.active().add_defect(vertex, node);
+    }
+    fn add_defects_bitmap(&mut self, base_vertex: CompactVertexIndex, base_node: CompactNodeIndex, bitmap: u64) {
+        self.active().add_defects_bitmap(base_vertex, base_node, bitmap);
+    }
+    fn read_vertex_grown(&mut self, vertex: CompactVertexIndex) -> Option<CompactWeight> {
+        self.active().read_vertex_grown(vertex)
+    }
+}
+
+impl DualTrackedDriver for ContextDriver {
+    fn find_conflict(&mut self, maximum_growth: CompactWeight) -> (CompactObstacle, CompactWeight) {
+        self.active().find_conflict(maximum_growth)
+    }
+}
+
+impl FusionVisualizer for ContextDriver {
+    fn snapshot(&self, abbrev: bool) -> serde_json::Value {
+        self.active().snapshot(abbrev)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::PrimalDualSolver;
+    use serde_json::json;
+
+    /// the registers of a context are not touched by the instructions of another context
+    #[test]
+    fn dual_module_comb_context_switch() {
+        // cargo test dual_module_comb_context_switch -- --nocapture
+        let code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let config: DualCombConfig = serde_json::from_value(json!({ "context_depth": 2 })).unwrap();
+        let mut context_0 = ContextDriver::new(DualModuleCombDriver::new(graph.clone(), config));
+        let mut context_1 = context_0.share(1);
+        let mut reference_0 = DualModuleCombDriver::new(graph.clone(), DualCombConfig::default());
+        let mut reference_1 = DualModuleCombDriver::new(graph, DualCombConfig::default());
+        for (context, reference, vertex) in [(&mut context_0, &mut reference_0, 16), (&mut context_1, &mut reference_1, 26)]
+        {
+            context.add_defect(ni!(vertex), ni!(0));
+            reference.add_defect(ni!(vertex), ni!(0));
+        }
+        assert_eq!(context_0.find_conflict(2), reference_0.find_conflict(2));
+        assert_eq!(context_1.find_conflict(4), reference_1.find_conflict(4));
+        assert_eq!(context_0.snapshot(true), reference_0.snapshot(true));
+        assert_eq!(context_0.read_vertex_grown(ni!(16)), reference_0.read_vertex_grown(ni!(16)));
+        assert_eq!(context_1.read_vertex_grown(ni!(26)), reference_1.read_vertex_grown(ni!(26)));
+        // resetting a context keeps the others
+        context_0.reset();
+        reference_0.reset();
+        assert_eq!(context_1.snapshot(true), reference_1.snapshot(true));
+        assert_eq!(context_0.snapshot(true), reference_0.snapshot(true));
+        let statistics = context_0.driver.borrow().context_bank.as_ref().unwrap().statistics.clone();
+        println!("{statistics:?}");
+        assert!(statistics.switches >= 6);
+    }
+
+    /// two primal modules interleave their instructions on the same dual module
+    #[test]
+    fn dual_module_comb_context_interleave() {
+        // cargo test dual_module_comb_context_interleave -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let config = json!({ "dual": { "context_depth": 2 } });
+        let mut solver_x = SolverEmbeddedBoxed::<ContextDriver>::new(graph.clone(), config.clone());
+        let mut solver_z = SolverEmbeddedBoxed::<ContextDriver>::new(graph.clone(), config);
+        solver_z.dual_module.driver.driver = solver_x.dual_module.driver.driver.share(1);
+        let mut reference = SolverEmbeddedComb::new(graph, json!({}));
+        for seed in 0..20 {
+            let (syndrome_x, syndrome_z) = (code.generate_random_errors(seed), code.generate_random_errors(seed + 1000));
+            solver_x.load_syndrome(&syndrome_x);
+            solver_z.load_syndrome(&syndrome_z);
+            let (mut running_x, mut running_z) = (true, true);
+            while running_x || running_z {
+                running_x = running_x && solver_x.step();
+                running_z = running_z && solver_z.step();
+            }
+            solver_x.finish();
+            solver_z.finish();
+            for (solver, syndrome_pattern) in [(&mut solver_x, &syndrome_x), (&mut solver_z, &syndrome_z)] {
+                reference.solve(syndrome_pattern);
+                assert_eq!(solver.result().matching_weight, reference.result().matching_weight);
+                assert_eq!(solver.subgraph(), reference.subgraph());
+                reference.clear();
+                solver.clear();
+            }
+        }
+    }
+}
@@ -0,0 +1,700 @@
+//! Cycle Accounting of the Combinatorial Dual Module
+//!
+//! The behavioral model executes an instruction in a single step, while the accelerator spends several clock cycles
+//! on it. This module counts the cycles of every executed instruction following the pipeline parameters of
+//! `DualConfig` in the Scala implementation: an instruction is issued in one cycle and the next instruction to the
+//! same context waits for the execute latency, i.e., the injected registers plus 2 cycles of memory fetch and write
+//! when context switching is enabled; a `FindObstacle` further waits for the broadcast and convergecast delays
+//! before the obstacle can be read.
+//!
+//! The bus between the CPU and the accelerator is not counted here, see [`crate::round_trips`]. Comparing the
+//! projected cycles with the latency measured on the FPGA isolates the cost of the bus and the primal module.
+//!
+//! With a [`PipelineConfig`], the instructions are further modeled as flowing through a pipeline of several stages
+//! (e.g., fetch, execute, update and write), one instruction entering per cycle. An instruction touching a vertex
+//! written by an instruction still in flight is a hazard and stalls; `FindObstacle` and `Grow` touch every vertex, and
+//! the host waits for the obstacle of a `FindObstacle` before issuing the next instruction. This predicts the
+//! throughput of the pipelined hardware instead of the serialized execution above.
+//!
+//! With a [`ReductionConfig`], the convergecast delay is derived from the shape of the tree reducing the responses of
+//! the vertices and edges instead of being a fixed parameter: every node of the tree reduces a group of responses
+//! (e.g., 8 edges per node) and every level of the tree costs the configured latency. The responses of the
+//! behavioral model are then reduced group by group in the same way, see [`ReductionTree`]. Likewise, with a
+//! [`BroadcastConfig`], the broadcast delay is derived from the tree distributing the instructions to the vertices
+//! and edges, given the fan-out of every node and the latency of every stage, see [`BroadcastTree`]. Together they
+//! give the network delay of reading an obstacle on a graph of realistic size instead of the delays of a toy graph.
+//!
+//! The reduction tree also arbitrates between the conflicts reported at the same time. A larger fan-in takes fewer
+//! levels but longer combinational paths; instead of a register after every level, `pipeline_stages` cuts the tree
+//! into a given number of stages, trading the latency against the critical path. The extra latency of every
+//! `FindObstacle` over any other instruction is reported as `find_obstacle_extra_latency`.
+//!
+
+use crate::dual_module_comb::*;
+use crate::dual_module_comb_vertex::*;
+use fusion_blossom::util::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CycleConfig {
+    #[serde(default = "Default::default")]
+    pub broadcast_delay: u64,
+    #[serde(default = "cycle_config_default::convergecast_delay")]
+    pub convergecast_delay: u64,
+    /// the number of pipeline registers injected into the execute stage
+    #[serde(default = "Default::default")]
+    pub inject_registers: u64,
+    /// the number of contexts; more than one context costs 2 more cycles per instruction
+    #[serde(default = "cycle_config_default::context_depth")]
+    pub context_depth: usize,
+    /// model the pipelined execution, see [`PipelineModel`]
+    #[serde(default = "Default::default")]
+    pub pipeline: Option<PipelineConfig>,
+    /// derive the convergecast delay from the grouping of the responses, overriding `convergecast_delay`
+    #[serde(default = "Default::default")]
+    pub reduction: Option<ReductionConfig>,
+    /// derive the broadcast delay from the fan-out of the instruction distribution, overriding `broadcast_delay`
+    #[serde(default = "Default::default")]
+    pub broadcast: Option<BroadcastConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BroadcastConfig {
+    /// the number of children driven by every node of the broadcast tree
+    #[serde(default = "broadcast_config_default::fan_out")]
+    pub fan_out: usize,
+    /// the cycles spent in every stage of the tree, i.e., 1 with a register after every node
+    #[serde(default = "broadcast_config_default::stage_latency")]
+    pub stage_latency: u64,
+}
+
+pub mod broadcast_config_default {
+    pub fn fan_out() -> usize {
+        8
+    }
+    pub fn stage_latency() -> u64 {
+        1
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReductionConfig {
+    /// the number of responses reduced by every node of the convergecast tree, e.g., 2 for a binary tree
+    #[serde(default = "reduction_config_default::group_size")]
+    pub group_size: usize,
+    /// the cycles spent in every level of the tree, i.e., 1 with a register after every group and 0 for a purely
+    /// combinational tree
+    #[serde(default = "reduction_config_default::group_latency")]
+    pub group_latency: u64,
+    /// the number of pipeline stages the levels are evenly cut into, one cycle each, overriding `group_latency`;
+    /// more stages than levels are not possible
+    #[serde(default = "Default::default")]
+    pub pipeline_stages: Option<usize>,
+}
+
+pub mod reduction_config_default {
+    pub fn group_size() -> usize {
+        8
+    }
+    pub fn group_latency() -> u64 {
+        1
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+    /// the number of pipeline stages, e.g., 4 for fetch, execute, update and write
+    #[serde(default = "pipeline_config_default::stages")]
+    pub stages: u64,
+    /// the cycles an instruction is held back after a conflicting instruction is issued; by default the conflicting
+    /// instruction has to reach the end of the pipeline, i.e., `stages - 1` without any forwarding
+    #[serde(default = "Default::default")]
+    pub hazard_stall: Option<u64>,
+}
+
+pub mod pipeline_config_default {
+    pub fn stages() -> u64 {
+        4
+    }
+}
+
+pub mod cycle_config_default {
+    pub fn convergecast_delay() -> u64 {
+        1
+    }
+    pub fn context_depth() -> usize {
+        1
+    }
+}
+
+impl CycleConfig {
+    /// from issuing an instruction to the time it's safe to issue another one to the same context
+    pub fn execute_latency(&self) -> u64 {
+        let context_delay = if self.context_depth != 1 { 2 } else { 0 };
+        self.inject_registers + context_delay
+    }
+
+    /// from issuing an instruction to receiving the obstacle, given the delays of the broadcast and convergecast trees
+    pub fn read_latency(&self, broadcast_delay: u64, convergecast_delay: u64) -> u64 {
+        broadcast_delay + convergecast_delay + self.execute_latency()
+    }
+
+    pub fn instruction_cycles(&self, instruction: &Instruction, broadcast_delay: u64, convergecast_delay: u64) -> u64 {
+        if instruction.reads_obstacle() {
+            1 + self.read_latency(broadcast_delay, convergecast_delay)
+        } else {
+            1 + self.execute_latency()
+        }
+    }
+}
+
+/// the convergecast tree over a number of responses, each node reducing a group of the level below
+#[derive(Debug, Clone, Serialize)]
+pub struct ReductionTree {
+    pub config: ReductionConfig,
+    pub leaves: usize,
+    /// the number of groups in every level, from the leaves to the root
+    pub levels: Vec<usize>,
+}
+
+/// the number of nodes in every level of a tree over `leaves`, each node covering `group_size` nodes of the level
+/// below, from the leaves to the root
+pub fn tree_levels(leaves: usize, group_size: usize) -> Vec<usize> {
+    let mut levels = vec![];
+    let mut width = leaves;
+    loop {
+        width = width.div_ceil(group_size);
+        levels.push(width);
+        if width <= 1 {
+            break;
+        }
+    }
+    levels
+}
+
+impl ReductionTree {
+    pub fn new(config: ReductionConfig, leaves: usize) -> Self {
+        assert!(config.group_size >= 2, "a group reduces at least 2 responses");
+        let levels = tree_levels(leaves, config.group_size);
+        Self { config, leaves, levels }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// the number of pipeline stages, `None` if every level costs `group_latency`
+    pub fn stages(&self) -> Option<usize> {
+        (self.config.pipeline_stages).map(|pipeline_stages| pipeline_stages.clamp(1, self.depth()))
+    }
+
+    /// the most levels of combinational logic in a single cycle, which bounds the clock frequency
+    pub fn critical_levels(&self) -> usize {
+        match self.stages() {
+            Some(stages) => self.depth().div_ceil(stages),
+            None if self.config.group_latency == 0 => self.depth(),
+            None => 1,
+        }
+    }
+
+    /// the cycles from the responses of the leaves to the root
+    pub fn delay(&self) -> u64 {
+        match self.stages() {
+            Some(stages) => stages as u64,
+            None => self.depth() as u64 * self.config.group_latency,
+        }
+    }
+
+    /// reduce every group of the level below, level by level up to the root
+    pub fn reduce<T>(&self, mut values: Vec<T>, reduce: impl Fn(T, T) -> T) -> T {
+        debug_assert_eq!(
+            values.len(),
+            self.leaves,
+            "the tree is built for a different number of responses"
+        );
+        for _ in self.levels.iter() {
+            let mut groups = vec![];
+            let mut values_iter = values.into_iter().peekable();
+            while values_iter.peek().is_some() {
+                let group = values_iter.by_ref().take(self.config.group_size);
+                groups.push(group.reduce(&reduce).unwrap());
+            }
+            values = groups;
+        }
+        values.pop().expect("no response to reduce")
+    }
+}
+
+/// the broadcast tree distributing every instruction from the root to the vertices and edges
+#[derive(Debug, Clone, Serialize)]
+pub struct BroadcastTree {
+    pub config: BroadcastConfig,
+    pub leaves: usize,
+    /// the number of nodes in every level, from the leaves to the root
+    pub levels: Vec<usize>,
+}
+
+impl BroadcastTree {
+    pub fn new(config: BroadcastConfig, leaves: usize) -> Self {
+        assert!(config.fan_out >= 2, "a node drives at least 2 children");
+        let levels = tree_levels(leaves, config.fan_out);
+        Self { config, leaves, levels }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// the cycles from the root to the leaves
+    pub fn delay(&self) -> u64 {
+        self.depth() as u64 * self.config.stage_latency
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InstructionCycles {
+    pub count: usize,
+    pub cycles: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CycleStatistics {
+    pub shots: usize,
+    pub total_cycles: u64,
+    pub max_cycles: u64,
+    pub instructions: BTreeMap<&'static str, InstructionCycles>,
+    pub pipeline: PipelineStatistics,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PipelineStatistics {
+    pub total_cycles: u64,
+    pub max_cycles: u64,
+    /// the number of instructions stalled by a hazard
+    pub hazards: usize,
+    pub stall_cycles: u64,
+}
+
+/// the timing of a single shot in the pipeline
+#[derive(Debug, Clone)]
+pub struct PipelineModel {
+    pub config: PipelineConfig,
+    /// the cycle at which the last instruction is issued, `None` at the beginning of a shot
+    issued: Option<u64>,
+    /// the host cannot issue before this cycle because it waits for an obstacle
+    host_ready: u64,
+    /// the issue cycle of the last instruction writing each vertex
+    vertex_written: Vec<Option<u64>>,
+    /// the issue cycle of the last instruction writing any vertex
+    any_written: Option<u64>,
+    /// the cycle at which all the issued instructions finish
+    pub completion: u64,
+    pub hazards: usize,
+    pub stall_cycles: u64,
+}
+
+impl PipelineModel {
+    pub fn new(config: PipelineConfig) -> Self {
+        assert!(config.stages >= 1, "a pipeline has at least one stage");
+        Self {
+            config,
+            issued: None,
+            host_ready: 0,
+            vertex_written: vec![],
+            any_written: None,
+            completion: 0,
+            hazards: 0,
+            stall_cycles: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.issued = None;
+        self.host_ready = 0;
+        self.vertex_written.clear();
+        self.any_written = None;
+        self.completion = 0;
+        self.hazards = 0;
+        self.stall_cycles = 0;
+    }
+
+    /// the cycles of the current shot, including the wait for the last obstacle
+    pub fn cycles(&self) -> u64 {
+        self.completion.max(self.host_ready)
+    }
+
+    /// issue an instruction that writes `written` vertices; `read_latency` is the delay of reading the obstacle
+    pub fn issue(&mut self, instruction: &Instruction, written: &[VertexIndex], read_latency: u64) {
+        let stages = self.config.stages;
+        let hazard_stall = self.config.hazard_stall.unwrap_or(stages - 1);
+        let earliest = self.issued.map(|issued| issued + 1).unwrap_or(0).max(self.host_ready);
+        // the last conflicting instruction: any writer for an instruction touching every vertex
+        let touches_all = instruction.reads_obstacle() || matches!(instruction, Instruction::Grow { .. });
+        let conflicting = if touches_all {
+            self.any_written
+        } else {
+            written
+                .iter()
+                .filter_map(|&vertex_index| self.vertex_written.get(vertex_index).copied().flatten())
+                .max()
+        };
+        let issue = match conflicting {
+            Some(conflicting) if conflicting + stages > earliest => earliest.max(conflicting + 1 + hazard_stall),
+            _ => earliest,
+        };
+        if issue > earliest {
+            self.hazards += 1;
+            self.stall_cycles += issue - earliest;
+        }
+        for &vertex_index in written.iter() {
+            if self.vertex_written.len() <= vertex_index {
+                self.vertex_written.resize(vertex_index + 1, None);
+            }
+            self.vertex_written[vertex_index] = Some(issue);
+        }
+        if !written.is_empty() {
+            self.any_written = Some(issue);
+        }
+        self.issued = Some(issue);
+        self.completion = self.completion.max(issue + stages);
+        if instruction.reads_obstacle() {
+            self.host_ready = issue + stages + read_latency;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CycleCounter {
+    pub config: CycleConfig,
+    /// the cycles of the current shot
+    pub cycles: u64,
+    /// the cycles of the current shot spent on each type of instruction
+    pub instructions: BTreeMap<&'static str, InstructionCycles>,
+    /// the finished shots, i.e., those cleared since the last reset of the profiler
+    pub statistics: CycleStatistics,
+    pub pipeline: Option<PipelineModel>,
+    pub reduction: Option<ReductionTree>,
+    pub broadcast: Option<BroadcastTree>,
+    /// the vertex registers before the executing instruction, only needed by the pipeline model
+    pre_registers: Option<Vec<VertexRegisters>>,
+}
+
+impl CycleCounter {
+    /// `responses` is the number of responses reduced by the convergecast tree, i.e., of the vertices and edges,
+    /// which are also the receivers of the broadcast tree
+    pub fn new(config: CycleConfig, responses: usize) -> Self {
+        Self {
+            pipeline: config.pipeline.clone().map(PipelineModel::new),
+            reduction: (config.reduction.clone()).map(|reduction| ReductionTree::new(reduction, responses)),
+            broadcast: (config.broadcast.clone()).map(|broadcast| BroadcastTree::new(broadcast, responses)),
+            config,
+            cycles: 0,
+            instructions: BTreeMap::new(),
+            statistics: CycleStatistics::default(),
+            pre_registers: None,
+        }
+    }
+
+    pub fn convergecast_delay(&self) -> u64 {
+        match self.reduction.as_ref() {
+            Some(reduction) => reduction.delay(),
+            None => self.config.convergecast_delay,
+        }
+    }
+
+    pub fn broadcast_delay(&self) -> u64 {
+        match self.broadcast.as_ref() {
+            Some(broadcast) => broadcast.delay(),
+            None => self.config.broadcast_delay,
+        }
+    }
+
+    /// the cycles that a `FindObstacle` waits for its obstacle in addition to the latency of any instruction
+    pub fn find_obstacle_extra_latency(&self) -> u64 {
+        self.broadcast_delay() + self.convergecast_delay()
+    }
+
+    /// the cycles of the current shot, pipelined if a pipeline is configured
+    pub fn shot_cycles(&self) -> u64 {
+        match self.pipeline.as_ref() {
+            Some(pipeline) => pipeline.cycles(),
+            None => self.cycles,
+        }
+    }
+
+    /// record an executed instruction and the vertices whose registers it has changed, which are only needed by the
+    /// pipeline model
+    pub fn record(&mut self, instruction: &Instruction, written: &[VertexIndex]) {
+        if let Some(pipeline) = self.pipeline.as_mut() {
+            pipeline.issue(instruction, written, self.find_obstacle_extra_latency());
+        }
+        let cycles = (self.config).instruction_cycles(instruction, self.broadcast_delay(), self.convergecast_delay());
+        self.cycles += cycles;
+        let entry = self.instructions.entry(instruction.name()).or_default();
+        entry.count += 1;
+        entry.cycles += cycles;
+    }
+
+    /// finish the current shot, if any instruction has been executed
+    pub fn clear(&mut self) {
+        if self.cycles == 0 {
+            return;
+        }
+        self.statistics.shots += 1;
+        self.statistics.total_cycles += self.cycles;
+        self.statistics.max_cycles = self.statistics.max_cycles.max(self.cycles);
+        for (name, instruction) in std::mem::take(&mut self.instructions) {
+            let entry = self.statistics.instructions.entry(name).or_default();
+            entry.count += instruction.count;
+            entry.cycles += instruction.cycles;
+        }
+        self.cycles = 0;
+        if let Some(pipeline) = self.pipeline.as_mut() {
+            let statistics = &mut self.statistics.pipeline;
+            statistics.total_cycles += pipeline.cycles();
+            statistics.max_cycles = statistics.max_cycles.max(pipeline.cycles());
+            statistics.hazards += pipeline.hazards;
+            statistics.stall_cycles += pipeline.stall_cycles;
+            pipeline.clear();
+        }
+    }
+
+    pub fn generate_report(&self) -> serde_json::Value {
+        let shots = self.statistics.shots.max(1) as f64;
+        let instructions: BTreeMap<_, _> = self
+            .statistics
+            .instructions
+            .iter()
+            .map(|(name, instruction)| {
+                (
+                    name,
+                    json!({
+                        "count": instruction.count,
+                        "cycles": instruction.cycles,
+                        "cycles_per_shot": instruction.cycles as f64 / shots,
+                    }),
+                )
+            })
+            .collect();
+        let instruction_count: usize = self
+            .statistics
+            .instructions
+            .values()
+            .map(|instruction| instruction.count)
+            .sum();
+        let pipeline = &self.statistics.pipeline;
+        json!({
+            "shots": self.statistics.shots,
+            "total_cycles": self.statistics.total_cycles,
+            "average_cycles": self.statistics.total_cycles as f64 / shots,
+            "max_cycles": self.statistics.max_cycles,
+            "instructions": instructions,
+            "broadcast_delay": self.broadcast_delay(),
+            "convergecast_delay": self.convergecast_delay(),
+            "find_obstacle_extra_latency": self.find_obstacle_extra_latency(),
+            "broadcast": self.broadcast.as_ref().map(|broadcast| json!({
+                "fan_out": broadcast.config.fan_out,
+                "levels": broadcast.levels,
+            })),
+            "reduction": self.reduction.as_ref().map(|reduction| json!({
+                "group_size": reduction.config.group_size,
+                "levels": reduction.levels,
+                "stages": reduction.stages(),
+                "critical_levels": reduction.critical_levels(),
+            })),
+            "pipeline": self.pipeline.as_ref().map(|_| json!({
+                "total_cycles": pipeline.total_cycles,
+                "average_cycles": pipeline.total_cycles as f64 / shots,
+                "max_cycles": pipeline.max_cycles,
+                "hazards": pipeline.hazards,
+                "stall_cycles": pipeline.stall_cycles,
+                "instructions_per_cycle": instruction_count as f64 / pipeline.total_cycles.max(1) as f64,
+            })),
+        })
+    }
+}
+
+impl CombObserver for CycleCounter {
+    fn name(&self) -> &'static str {
+        "cycles"
+    }
+    fn before_execute(&mut self, driver: &DualModuleCombDriver) {
+        // the pipeline model needs the vertices written by the instruction
+        if self.pipeline.is_some() {
+            self.pre_registers = Some(driver.vertices.iter().map(|vertex| vertex.registers.clone()).collect());
+        }
+    }
+    fn after_execute(&mut self, driver: &mut DualModuleCombDriver) {
+        let written: Vec<VertexIndex> = match self.pre_registers.take() {
+            Some(pre_registers) => (0..driver.vertices.len())
+                .filter(|&vertex_index| pre_registers[vertex_index] != driver.vertices[vertex_index].registers)
+                .collect(),
+            None => vec![],
+        };
+        self.record(&driver.instruction, &written);
+    }
+    fn clear(&mut self) {
+        CycleCounter::clear(self);
+    }
+    fn reset_profiler(&mut self) {
+        self.statistics = CycleStatistics::default();
+    }
+    fn generate_report(&self) -> Option<serde_json::Value> {
+        Some(CycleCounter::generate_report(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwpm_solver::*;
+    use crate::resources::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::*;
+
+    /// the cycles of a shot add up from the instruction counts
+    #[test]
+    fn dual_module_comb_cycles_per_shot() {
+        // cargo test dual_module_comb_cycles_per_shot -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let config = json!({ "dual": { "cycles": { "inject_registers": 2, "context_depth": 2 } } });
+        let mut solver = SolverEmbeddedComb::new(graph, config);
+        let mut total_cycles = 0;
+        for seed in 0..20 {
+            solver.solve(&code.generate_random_errors(seed));
+            let result = solver.result();
+            let instruction_counts = result.instruction_counts.unwrap();
+            let find_obstacle = *instruction_counts.get("find_obstacle").unwrap_or(&0) as u64;
+            let others = instruction_counts.values().sum::<usize>() as u64 - find_obstacle;
+            // execute latency 2 + 2 and read latency 1 + 4
+            assert_eq!(result.clock_cycles, Some(find_obstacle * 6 + others * 5));
+            total_cycles += result.clock_cycles.unwrap();
+            solver.clear();
+        }
+        let counter = solver.dual_module.driver.driver.observer::<CycleCounter>().unwrap();
+        println!("{}", counter.generate_report());
+        assert_eq!(counter.statistics.total_cycles, total_cycles);
+        assert!(counter.statistics.instructions["grow"].cycles > 0);
+    }
+
+    /// the convergecast delay follows the depth of the tree, which reduces to the same obstacle as a linear scan
+    #[test]
+    fn dual_module_comb_cycles_reduction_tree() {
+        // cargo test dual_module_comb_cycles_reduction_tree -- --nocapture
+        let config = |group_size| serde_json::from_value(json!({ "group_size": group_size })).unwrap();
+        assert_eq!(ReductionTree::new(config(8), 100).levels, vec![13, 2, 1]);
+        assert_eq!(ReductionTree::new(config(2), 8).levels, vec![4, 2, 1]);
+        assert_eq!(ReductionTree::new(config(8), 8).delay(), 1);
+        let tree = ReductionTree::new(config(3), 20);
+        assert_eq!(tree.reduce((0..20).collect(), |a, b| a.max(b)), 19);
+        // the cycles of a shot follow the tree over all the vertices and edges of the graph
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let config = json!({ "dual": { "cycles": { "reduction": { "group_size": 4, "group_latency": 2 } } } });
+        let mut solver = SolverEmbeddedComb::new(graph, config);
+        let counter = solver.dual_module.driver.driver.observer::<CycleCounter>().unwrap();
+        let delay = counter.convergecast_delay();
+        println!("{:?}", counter.reduction.as_ref().unwrap().levels);
+        assert_eq!(delay, 2 * counter.reduction.as_ref().unwrap().depth() as u64);
+        for seed in 0..20 {
+            solver.solve(&code.generate_random_errors(seed));
+            let result = solver.result();
+            let instruction_counts = result.instruction_counts.unwrap();
+            let find_obstacle = *instruction_counts.get("find_obstacle").unwrap_or(&0) as u64;
+            let others = instruction_counts.values().sum::<usize>() as u64 - find_obstacle;
+            assert_eq!(result.clock_cycles, Some(find_obstacle * (1 + delay) + others));
+            solver.clear();
+        }
+    }
+
+    /// pipeline stages trade the latency of every `FindObstacle` against the levels of logic in a single cycle
+    #[test]
+    fn dual_module_comb_cycles_reduction_stages() {
+        // cargo test dual_module_comb_cycles_reduction_stages -- --nocapture
+        let config = |value| serde_json::from_value(value).unwrap();
+        let tree = ReductionTree::new(config(json!({ "group_size": 2, "pipeline_stages": 3 })), 100);
+        assert_eq!(tree.depth(), 7);
+        assert_eq!((tree.delay(), tree.critical_levels()), (3, 3));
+        let tree = ReductionTree::new(config(json!({ "group_size": 2, "pipeline_stages": 10 })), 100);
+        assert_eq!((tree.delay(), tree.critical_levels()), (7, 1));
+        let tree = ReductionTree::new(config(json!({ "group_size": 16, "group_latency": 0 })), 100);
+        assert_eq!((tree.delay(), tree.critical_levels()), (0, 2));
+        let code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let mut critical_levels = vec![];
+        for (group_size, pipeline_stages) in [(2, 1), (2, 4), (8, 1)] {
+            let graph = MicroBlossomSingle::new_code(&code);
+            let config = json!({ "dual": { "cycles": {
+                "reduction": { "group_size": group_size, "pipeline_stages": pipeline_stages },
+            } } });
+            let solver = SolverEmbeddedComb::new(graph, config);
+            let counter = solver.dual_module.driver.driver.observer::<CycleCounter>().unwrap();
+            assert_eq!(counter.find_obstacle_extra_latency(), pipeline_stages as u64);
+            let report = counter.generate_report();
+            assert_eq!(report["find_obstacle_extra_latency"], json!(pipeline_stages));
+            critical_levels.push(report["reduction"]["critical_levels"].as_u64().unwrap());
+        }
+        println!("critical levels: {critical_levels:?}");
+        assert!(critical_levels[1] < critical_levels[0] && critical_levels[2] < critical_levels[0]);
+    }
+
+    /// the network delay of reading an obstacle grows with the depth of both trees, i.e., logarithmically in the size
+    /// of the graph
+    #[test]
+    fn dual_module_comb_cycles_broadcast_tree() {
+        // cargo test dual_module_comb_cycles_broadcast_tree -- --nocapture
+        let config = |fan_out| serde_json::from_value(json!({ "fan_out": fan_out })).unwrap();
+        assert_eq!(BroadcastTree::new(config(8), 100).levels, vec![13, 2, 1]);
+        assert_eq!(BroadcastTree::new(config(4), 1000).depth(), 5);
+        let mut network_delays = vec![];
+        for d in [5, 11] {
+            let mut code = CodeCapacityPlanarCode::new(d, 0.1, 500);
+            let graph = MicroBlossomSingle::new_code(&code);
+            let config = json!({ "dual": { "cycles": {
+                "broadcast": { "fan_out": 4, "stage_latency": 1 },
+                "reduction": { "group_size": 4, "group_latency": 1 },
+            } } });
+            let mut solver = SolverEmbeddedComb::new(graph, config);
+            let counter = solver.dual_module.driver.driver.observer::<CycleCounter>().unwrap();
+            let network_delay = counter.broadcast_delay() + counter.convergecast_delay();
+            assert_eq!(counter.broadcast_delay(), counter.convergecast_delay());
+            network_delays.push(network_delay);
+            for seed in 0..10 {
+                solver.solve(&code.generate_random_errors(seed));
+                let result = solver.result();
+                let instruction_counts = result.instruction_counts.unwrap();
+                let find_obstacle = *instruction_counts.get("find_obstacle").unwrap_or(&0) as u64;
+                let others = instruction_counts.values().sum::<usize>() as u64 - find_obstacle;
+                assert_eq!(result.clock_cycles, Some(find_obstacle * (1 + network_delay) + others));
+                solver.clear();
+            }
+        }
+        println!("network delays: {network_delays:?}");
+        assert!(network_delays[0] < network_delays[1]);
+    }
+
+    /// back-to-back instructions writing the same vertex stall until the first one leaves the pipeline
+    #[test]
+    fn dual_module_comb_cycles_pipeline_hazard() {
+        // cargo test dual_module_comb_cycles_pipeline_hazard -- --nocapture
+        let mut pipeline = PipelineModel::new(serde_json::from_value(json!({ "stages": 4 })).unwrap());
+        let write = Instruction::SetBlossom { node: 0, blossom: 1 };
+        pipeline.issue(&write, &[1], 2);
+        pipeline.issue(&write, &[2], 2);
+        assert_eq!((pipeline.hazards, pipeline.cycles()), (0, 5));
+        pipeline.issue(&write, &[1], 2); // issued at cycle 4 instead of 2
+        assert_eq!((pipeline.hazards, pipeline.stall_cycles, pipeline.cycles()), (1, 2, 8));
+        pipeline.issue(&Instruction::FindObstacle { region_preference: None }, &[], 2); // issued at cycle 8 instead of 5
+        assert_eq!((pipeline.hazards, pipeline.stall_cycles, pipeline.cycles()), (2, 5, 14));
+        pipeline.issue(&write, &[3], 2); // waits for the obstacle until cycle 14
+        assert_eq!((pipeline.hazards, pipeline.cycles()), (2, 18));
+        pipeline.clear();
+        assert_eq!(pipeline.cycles(), 0);
+    }
+}
@@ -2,6 +2,7 @@ use crate::dual_module_comb::*;
 use fusion_blossom::util::*;
 use micro_blossom_nostd::interface::*;
 use micro_blossom_nostd::util::*;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::cell::{Ref, RefCell};
 
@@ -25,7 +26,7 @@ pub struct VirtualMatchingEdgeProfile {
     pub required_permit_vertices: Vec<VertexIndex>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct EdgeRegisters {
     pub weight: Weight,
 }
@@ -140,10 +141,14 @@ impl Edge {
         .clone()
     }
 
-    pub fn get_post_execute_state(&self, _dual_module: &DualModuleCombDriver) -> Ref<'_, EdgeRegisters> {
+    pub fn get_post_execute_state(&self, dual_module: &DualModuleCombDriver) -> Ref<'_, EdgeRegisters> {
         referenced_signal!(self.signals.post_execute_state, || {
-            let state = self.registers.clone();
-            // TODO: dynamically update edge weights
+            let mut state = self.registers.clone();
+            if let Instruction::SetEdgeWeight { edge, weight } = &dual_module.instruction {
+                if edge == &self.edge_index {
+                    state.weight = *weight;
+                }
+            }
             state
         })
     }
@@ -168,17 +173,23 @@ impl Edge {
         referenced_signal!(self.signals.response, || {
             let left_shadow = dual_module.vertices[self.left_index].get_shadow_node(dual_module);
             let right_shadow = dual_module.vertices[self.right_index].get_shadow_node(dual_module);
-            if left_shadow.node_index == right_shadow.node_index {
+            // the edges touching a committed region never resurface an obstacle
+            let touches_frozen = [self.left_index, self.right_index].iter().any(|&vertex_index| {
+                dual_module.vertices[vertex_index]
+                    .get_post_execute_state(dual_module)
+                    .is_frozen
+            });
+            if left_shadow.node_index == right_shadow.node_index || touches_frozen {
                 return CompactObstacle::GrowLength {
                     length: CompactWeight::MAX,
                 };
             }
-            let joint_speed = Weight::from(left_shadow.speed) + Weight::from(right_shadow.speed);
+            let joint_speed = left_shadow.signed_speed() + right_shadow.signed_speed();
             if joint_speed > 0 {
                 let remaining = self.get_remaining(dual_module);
                 let node_mapper = |node_index: NodeIndex| -> Option<CompactNodeIndex> {
                     if node_index != VIRTUAL_NODE_INDEX {
-                        Some(ni!(node_index))
+                        Some(compact_index(node_index))
                     } else {
                         None
                     }
@@ -189,19 +200,17 @@ impl Edge {
                     return CompactObstacle::Conflict {
                         node_1: left_shadow.node_index.and_then(node_mapper).into(),
                         touch_1: left_shadow.root_index.and_then(node_mapper).into(),
-                        vertex_1: ni!(self.left_index),
+                        vertex_1: compact_index(self.left_index),
                         node_2: right_shadow.node_index.and_then(node_mapper).into(),
                         touch_2: right_shadow.root_index.and_then(node_mapper).into(),
-                        vertex_2: ni!(self.right_index),
+                        vertex_2: compact_index(self.right_index),
                     };
                 }
-                assert!(
-                    remaining % joint_speed == 0,
-                    "found a case where the reported maxGrowth is rounding down, edge {}",
-                    self.edge_index
-                );
+                // with odd weights or speed magnitude larger than 1, the remaining weight may not be divisible by the
+                // joint speed; the growth rounds down and the driver grows a single side for the rest, see
+                // [`DualModuleCombDriver::fractional_step`]
                 return CompactObstacle::GrowLength {
-                    length: (remaining / joint_speed).try_into().unwrap(),
+                    length: dual_module.compact_length(remaining / joint_speed),
                 };
             }
             CompactObstacle::GrowLength {
@@ -0,0 +1,406 @@
+//! Fan-in Report of the Combinatorial Dual Module
+//!
+//! Every signal of the behavioral model corresponds to a combinational logic in the hardware. A signal that reads
+//! many other signals, or whose logic cone reaches the registers of many vertices and edges, is a likely critical
+//! path; the global response reduction, for example, reads every vertex and edge. This module walks the signal
+//! dependencies of [`DualModuleCombDriver`] on a given graph and reports, for every type of signal, the fan-in (the
+//! number of signals read directly) and the number of distinct vertices and edges whose registers are in its cone.
+//! The signals with a fan-in above the threshold would need pipelining or a tree restructuring in hardware.
+//!
+//! Generate the report with `micro-blossom fan-in <d> -c <code-type> --threshold 8`.
+//!
+
+use crate::dual_module_comb::*;
+use crate::resources::*;
+use clap::Parser;
+use fusion_blossom::cli::ExampleCodeType;
+use fusion_blossom::util::*;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+/// a signal instance of the behavioral model, see the `get_*` functions of the vertex, edge and offloading unit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Signal {
+    VertexRegisters(VertexIndex),
+    VertexTightCount(VertexIndex),
+    VertexOffloadingStalled(VertexIndex),
+    VertexPostExecuteState(VertexIndex),
+    VertexPropagatingPeer(VertexIndex),
+    VertexPostUpdateState(VertexIndex),
+    VertexShadowNode(VertexIndex),
+    VertexResponse(VertexIndex),
+    EdgeRegisters(EdgeIndex),
+    EdgePostFetchWeight(EdgeIndex),
+    EdgePostFetchIsTight(EdgeIndex),
+    EdgePostFetchCountTight(EdgeIndex),
+    EdgeOffloadingStalled(EdgeIndex),
+    EdgePostExecuteState(EdgeIndex),
+    EdgePostExecuteIsTight(EdgeIndex),
+    EdgeResponse(EdgeIndex),
+    OffloadingSignals(usize),
+    /// the reduction of the responses of all the vertices and edges
+    Response,
+}
+
+impl Signal {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::VertexRegisters(_) => "vertex.registers",
+            Self::VertexTightCount(_) => "vertex.tight_count",
+            Self::VertexOffloadingStalled(_) => "vertex.offloading_stalled",
+            Self::VertexPostExecuteState(_) => "vertex.post_execute_state",
+            Self::VertexPropagatingPeer(_) => "vertex.propagating_peer",
+            Self::VertexPostUpdateState(_) => "vertex.post_update_state",
+            Self::VertexShadowNode(_) => "vertex.shadow_node",
+            Self::VertexResponse(_) => "vertex.response",
+            Self::EdgeRegisters(_) => "edge.registers",
+            Self::EdgePostFetchWeight(_) => "edge.post_fetch_weight",
+            Self::EdgePostFetchIsTight(_) => "edge.post_fetch_is_tight",
+            Self::EdgePostFetchCountTight(_) => "edge.post_fetch_count_tight",
+            Self::EdgeOffloadingStalled(_) => "edge.offloading_stalled",
+            Self::EdgePostExecuteState(_) => "edge.post_execute_state",
+            Self::EdgePostExecuteIsTight(_) => "edge.post_execute_is_tight",
+            Self::EdgeResponse(_) => "edge.response",
+            Self::OffloadingSignals(_) => "offloading.signals",
+            Self::Response => "response",
+        }
+    }
+
+    /// the signals read directly by this signal
+    pub fn inputs(&self, driver: &DualModuleCombDriver) -> BTreeSet<Signal> {
+        let mut inputs = BTreeSet::new();
+        match *self {
+            Self::VertexRegisters(_) | Self::EdgeRegisters(_) => {}
+            Self::VertexTightCount(vertex_index) => {
+                inputs.extend(
+                    driver.vertices[vertex_index]
+                        .edge_indices
+                        .iter()
+                        .map(|&edge_index| Self::EdgePostFetchCountTight(edge_index)),
+                );
+            }
+            Self::VertexOffloadingStalled(vertex_index) => {
+                let offloading_indices = driver.vertices[vertex_index].offloading_indices.iter();
+                inputs.extend(offloading_indices.map(|&index| Self::OffloadingSignals(index)));
+            }
+            Self::VertexPostExecuteState(vertex_index) => {
+                inputs.insert(Self::VertexRegisters(vertex_index));
+            }
+            Self::VertexPropagatingPeer(vertex_index) => {
+                inputs.insert(Self::VertexPostExecuteState(vertex_index));
+                for &edge_index in driver.vertices[vertex_index].edge_indices.iter() {
+                    inputs.insert(Self::EdgePostExecuteIsTight(edge_index));
+                    inputs.insert(Self::VertexPostExecuteState(driver.edges[edge_index].get_peer(vertex_index)));
+                }
+            }
+            Self::VertexPostUpdateState(vertex_index) => {
+                inputs.insert(Self::VertexPostExecuteState(vertex_index));
+                inputs.insert(Self::VertexPropagatingPeer(vertex_index));
+            }
+            Self::VertexShadowNode(vertex_index) => {
+                inputs.insert(Self::VertexPostUpdateState(vertex_index));
+                inputs.insert(Self::VertexPropagatingPeer(vertex_index));
+                inputs.insert(Self::VertexOffloadingStalled(vertex_index));
+            }
+            Self::VertexResponse(vertex_index) => {
+                inputs.insert(Self::VertexPostUpdateState(vertex_index));
+            }
+            Self::EdgePostFetchWeight(edge_index) => {
+                inputs.insert(Self::EdgeRegisters(edge_index));
+                if let Some(conditioned_vertex) = driver.edges[edge_index].conditioned_vertex {
+                    inputs.insert(Self::VertexRegisters(conditioned_vertex));
+                }
+            }
+            Self::EdgePostFetchIsTight(edge_index) => {
+                let edge = &driver.edges[edge_index];
+                inputs.insert(Self::VertexRegisters(edge.left_index));
+                inputs.insert(Self::VertexRegisters(edge.right_index));
+                inputs.insert(Self::EdgePostFetchWeight(edge_index));
+            }
+            Self::EdgePostFetchCountTight(edge_index) => {
+                inputs.insert(Self::EdgePostFetchIsTight(edge_index));
+                if let Some(conditioned_vertex) = driver.edges[edge_index].conditioned_vertex {
+                    inputs.insert(Self::VertexRegisters(conditioned_vertex));
+                }
+            }
+            Self::EdgeOffloadingStalled(edge_index) => {
+                let offloading_indices = driver.edges[edge_index].offloading_indices.iter();
+                inputs.extend(offloading_indices.map(|&index| Self::OffloadingSignals(index)));
+            }
+            Self::EdgePostExecuteState(edge_index) => {
+                inputs.insert(Self::EdgeRegisters(edge_index));
+            }
+            Self::EdgePostExecuteIsTight(edge_index) => {
+                let edge = &driver.edges[edge_index];
+                inputs.insert(Self::VertexPostExecuteState(edge.left_index));
+                inputs.insert(Self::VertexPostExecuteState(edge.right_index));
+                inputs.insert(Self::EdgePostFetchWeight(edge_index));
+            }
+            Self::EdgeResponse(edge_index) => {
+                let edge = &driver.edges[edge_index];
+                for vertex_index in [edge.left_index, edge.right_index] {
+                    inputs.insert(Self::VertexShadowNode(vertex_index));
+                    inputs.insert(Self::VertexPostExecuteState(vertex_index));
+                }
+                inputs.insert(Self::EdgePostFetchWeight(edge_index));
+            }
+            Self::OffloadingSignals(offloading_index) => {
+                let edge_index = match driver.offloading_units[offloading_index].offloading_type {
+                    OffloadingType::DefectMatch { edge_index }
+                    | OffloadingType::VirtualMatch { edge_index, .. }
+                    | OffloadingType::FusionMatch { edge_index, .. }
+                    | OffloadingType::ChainMatch { edge_index, .. } => edge_index,
+                };
+                inputs.insert(Self::EdgePostFetchIsTight(edge_index));
+                let edge = &driver.edges[edge_index];
+                for vertex_index in [edge.left_index, edge.right_index] {
+                    inputs.insert(Self::VertexRegisters(vertex_index));
+                    inputs.insert(Self::VertexTightCount(vertex_index));
+                }
+                if let OffloadingType::VirtualMatch { virtual_vertex, .. } =
+                    driver.offloading_units[offloading_index].offloading_type
+                {
+                    let regular_index = edge.get_peer(virtual_vertex);
+                    for &neighbor_edge_index in driver.vertices[regular_index].edge_indices.iter() {
+                        let neighbor_index = driver.edges[neighbor_edge_index].get_peer(regular_index);
+                        inputs.insert(Self::EdgePostFetchIsTight(neighbor_edge_index));
+                        inputs.insert(Self::VertexRegisters(neighbor_index));
+                        inputs.insert(Self::VertexTightCount(neighbor_index));
+                    }
+                }
+                if let OffloadingType::ChainMatch { boundary_edge, .. } =
+                    driver.offloading_units[offloading_index].offloading_type
+                {
+                    inputs.insert(Self::EdgePostFetchIsTight(boundary_edge));
+                    let boundary = &driver.edges[boundary_edge];
+                    for regular_index in [edge.left_index, edge.right_index] {
+                        for &neighbor_edge_index in driver.vertices[regular_index].edge_indices.iter() {
+                            let neighbor_index = driver.edges[neighbor_edge_index].get_peer(regular_index);
+                            inputs.insert(Self::EdgePostFetchIsTight(neighbor_edge_index));
+                            inputs.insert(Self::VertexRegisters(neighbor_index));
+                            inputs.insert(Self::VertexTightCount(neighbor_index));
+                        }
+                    }
+                    inputs.insert(Self::VertexRegisters(boundary.left_index));
+                    inputs.insert(Self::VertexRegisters(boundary.right_index));
+                }
+            }
+            Self::Response => {
+                let vertices = driver.vertices.iter().filter(|vertex| !vertex.is_inert);
+                inputs.extend(vertices.map(|vertex| Self::VertexResponse(vertex.vertex_index)));
+                inputs.extend((0..driver.edges.len()).map(Self::EdgeResponse));
+            }
+        }
+        inputs
+    }
+
+    /// all the signals of a driver that this module analyzes
+    pub fn all(driver: &DualModuleCombDriver) -> Vec<Signal> {
+        let mut signals = vec![];
+        for vertex_index in 0..driver.vertices.len() {
+            signals.extend([
+                Self::VertexTightCount(vertex_index),
+                Self::VertexOffloadingStalled(vertex_index),
+                Self::VertexPostExecuteState(vertex_index),
+                Self::VertexPropagatingPeer(vertex_index),
+                Self::VertexPostUpdateState(vertex_index),
+                Self::VertexShadowNode(vertex_index),
+                Self::VertexResponse(vertex_index),
+            ]);
+        }
+        for edge_index in 0..driver.edges.len() {
+            signals.extend([
+                Self::EdgePostFetchWeight(edge_index),
+                Self::EdgePostFetchIsTight(edge_index),
+                Self::EdgePostFetchCountTight(edge_index),
+                Self::EdgeOffloadingStalled(edge_index),
+                Self::EdgePostExecuteState(edge_index),
+                Self::EdgePostExecuteIsTight(edge_index),
+                Self::EdgeResponse(edge_index),
+            ]);
+        }
+        signals.extend((0..driver.offloading_units.len()).map(Self::OffloadingSignals));
+        signals.push(Self::Response);
+        signals
+    }
+}
+
+/// the registers in the logic cone of a signal
+#[derive(Debug, Clone, Default)]
+pub struct SignalCone {
+    pub vertices: BTreeSet<VertexIndex>,
+    pub edges: BTreeSet<EdgeIndex>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SignalFanIn {
+    pub signal: String,
+    pub fan_in: usize,
+    pub vertices: usize,
+    pub edges: usize,
+}
+
+/// the summary of all the instances of a type of signal
+#[derive(Debug, Clone, Serialize)]
+pub struct SignalTypeFanIn {
+    pub instances: usize,
+    pub max_fan_in: usize,
+    pub max_vertices: usize,
+    pub max_edges: usize,
+    /// the instances with a fan-in above the threshold
+    pub flagged: usize,
+    /// the instance with the largest fan-in
+    pub worst: SignalFanIn,
+}
+
+pub struct FanInAnalyzer<'a> {
+    driver: &'a DualModuleCombDriver,
+    cones: BTreeMap<Signal, Rc<SignalCone>>,
+}
+
+impl<'a> FanInAnalyzer<'a> {
+    pub fn new(driver: &'a DualModuleCombDriver) -> Self {
+        Self {
+            driver,
+            cones: BTreeMap::new(),
+        }
+    }
+
+    pub fn cone(&mut self, signal: Signal) -> Rc<SignalCone> {
+        if let Some(cone) = self.cones.get(&signal) {
+            return cone.clone();
+        }
+        let mut cone = SignalCone::default();
+        match signal {
+            Signal::VertexRegisters(vertex_index) => {
+                cone.vertices.insert(vertex_index);
+            }
+            Signal::EdgeRegisters(edge_index) => {
+                cone.edges.insert(edge_index);
+            }
+            _ => {
+                for input in signal.inputs(self.driver) {
+                    let input_cone = self.cone(input);
+                    cone.vertices.extend(input_cone.vertices.iter());
+                    cone.edges.extend(input_cone.edges.iter());
+                }
+            }
+        }
+        let cone = Rc::new(cone);
+        self.cones.insert(signal, cone.clone());
+        cone
+    }
+
+    pub fn fan_in(&mut self, signal: Signal) -> SignalFanIn {
+        let cone = self.cone(signal);
+        SignalFanIn {
+            signal: format!("{signal:?}"),
+            fan_in: signal.inputs(self.driver).len(),
+            vertices: cone.vertices.len(),
+            edges: cone.edges.len(),
+        }
+    }
+
+    /// the fan-in of every type of signal, flagging the instances with a fan-in above `threshold`
+    pub fn report(&mut self, threshold: usize) -> BTreeMap<&'static str, SignalTypeFanIn> {
+        let mut report = BTreeMap::<&'static str, SignalTypeFanIn>::new();
+        for signal in Signal::all(self.driver) {
+            let fan_in = self.fan_in(signal);
+            let flagged = (fan_in.fan_in > threshold) as usize;
+            match report.get_mut(signal.name()) {
+                Some(summary) => {
+                    summary.instances += 1;
+                    summary.max_vertices = summary.max_vertices.max(fan_in.vertices);
+                    summary.max_edges = summary.max_edges.max(fan_in.edges);
+                    summary.flagged += flagged;
+                    if fan_in.fan_in > summary.max_fan_in {
+                        summary.max_fan_in = fan_in.fan_in;
+                        summary.worst = fan_in;
+                    }
+                }
+                None => {
+                    report.insert(
+                        signal.name(),
+                        SignalTypeFanIn {
+                            instances: 1,
+                            max_fan_in: fan_in.fan_in,
+                            max_vertices: fan_in.vertices,
+                            max_edges: fan_in.edges,
+                            flagged,
+                            worst: fan_in,
+                        },
+                    );
+                }
+            }
+        }
+        report
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct FanInParameters {
+    /// code distance
+    #[clap(value_parser)]
+    d: VertexNum,
+    /// rounds of noisy measurement, valid only when multiple rounds
+    #[clap(short = 'n', long, default_value_t = 0)]
+    noisy_measurements: VertexNum,
+    /// example code type
+    #[clap(short = 'c', long, value_enum, default_value_t = ExampleCodeType::CodeCapacityPlanarCode)]
+    code_type: ExampleCodeType,
+    /// the configuration of the code builder
+    #[clap(long, default_value_t = ("{}").to_string())]
+    code_config: String,
+    /// the configuration of the dual module, e.g., to enable offloading or layer fusion
+    #[clap(long, default_value_t = ("{}").to_string())]
+    dual_config: String,
+    /// flag the signals reading more than this many signals
+    #[clap(long, default_value_t = 8)]
+    threshold: usize,
+}
+
+impl FanInParameters {
+    pub fn run(&self) {
+        let code_config: serde_json::Value = serde_json::from_str(&self.code_config).unwrap();
+        let code = self.code_type.build(self.d, 0.01, self.noisy_measurements, 500, code_config);
+        let graph = MicroBlossomSingle::new_code(code.as_ref());
+        let driver = DualModuleCombDriver::new(graph, serde_json::from_str(&self.dual_config).unwrap());
+        let report = FanInAnalyzer::new(&driver).report(self.threshold);
+        println!("{}", json!({ "threshold": self.threshold, "signals": report }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn dual_module_comb_fan_in_report() {
+        // cargo test dual_module_comb_fan_in_report -- --nocapture
+        let code = CodeCapacityPlanarCode::new(5, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let config = json!({ "sim_config": { "support_offloading": true } });
+        let driver = DualModuleCombDriver::new(graph, serde_json::from_value(config).unwrap());
+        let mut analyzer = FanInAnalyzer::new(&driver);
+        let report = analyzer.report(4);
+        println!("{}", json!(report));
+        // the global reduction reads every vertex and edge
+        let response = &report["response"];
+        assert_eq!(response.instances, 1);
+        assert_eq!(response.flagged, 1);
+        assert_eq!(response.max_edges, driver.edges.len());
+        let active_vertices = driver.vertices.iter().filter(|vertex| !vertex.is_inert).count();
+        assert_eq!(response.max_fan_in, active_vertices + driver.edges.len());
+        // the local signals only read the neighborhood
+        let edge_response = &report["edge.response"];
+        assert!(edge_response.max_fan_in <= 5);
+        assert_eq!(edge_response.flagged, edge_response.instances);
+        assert_eq!(report["edge.post_execute_state"].max_fan_in, 1);
+        assert_eq!(report["edge.post_execute_state"].flagged, 0);
+        let cone = analyzer.cone(Signal::EdgeRegisters(0));
+        assert_eq!((cone.vertices.len(), cone.edges.len()), (0, 1));
+    }
+}
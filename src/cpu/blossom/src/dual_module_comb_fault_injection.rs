@@ -0,0 +1,272 @@
+//! Fault Injection of the Combinatorial Dual Module
+//!
+//! A single-event upset in the FPGA flips a bit of a register, silently corrupting the state of the dual module. To
+//! evaluate how such upsets corrupt the decoding, a [`RegisterFault`] flips one bit of a vertex or edge register right
+//! after the chosen instruction, counted from 0 since the last reset, so the same fault strikes every shot. The index
+//! registers are flipped in their hardware encoding, i.e., `vertex_bits` wide with all ones for `None`; the edge itself
+//! holds no growth, so its upset is modeled on the weight register.
+//!
+//! Every injection is recorded in a [`FaultRecord`]. With the assertion hooks enabled and `panic` disabled, e.g.,
+//! `{ "assertions": { "panic": false }, "faults": { "faults": [...] } }`, the first violation at or after the
+//! injection is attributed to the fault as its detection; a fault without detection is silent, and its effect can
+//! only be seen in the decoding result.
+//!
+
+use crate::dual_module_comb::*;
+use crate::dual_module_comb_assertion::*;
+use crate::dual_module_comb_edge::*;
+use crate::dual_module_comb_vertex::*;
+use crate::hardware_config_check::*;
+use crate::resources::*;
+use fusion_blossom::util::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FaultTarget {
+    VertexGrown(VertexIndex),
+    VertexNodeIndex(VertexIndex),
+    VertexRootIndex(VertexIndex),
+    EdgeWeight(EdgeIndex),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegisterFault {
+    /// flip the bit right after this instruction, counted from 0 since the last reset
+    pub instruction: usize,
+    pub target: FaultTarget,
+    pub bit: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FaultInjectionConfig {
+    #[serde(default = "Default::default")]
+    pub faults: Vec<RegisterFault>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FaultRecord {
+    /// the number of resets before the injection
+    pub shot: usize,
+    pub fault: RegisterFault,
+    /// the register in the hardware encoding before and after the flip
+    pub before: u64,
+    pub after: u64,
+    /// the first assertion violation at or after the injection in the same shot
+    pub detected_by: Option<AssertionViolation>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FaultReport {
+    pub injected: usize,
+    pub detected: usize,
+    pub records: Vec<FaultRecord>,
+}
+
+pub struct FaultInjector {
+    pub config: FaultInjectionConfig,
+    pub records: Vec<FaultRecord>,
+    vertex_bits: usize,
+    shot: usize,
+    /// the number of assertion violations already attributed
+    observed_violations: usize,
+}
+
+impl FaultInjector {
+    pub fn new(config: FaultInjectionConfig, graph: &MicroBlossomSingle) -> Result<Self, CombConfigError> {
+        let (vertex_bits, _) = generated_bits(graph);
+        for fault in config.faults.iter() {
+            let in_range = match fault.target {
+                FaultTarget::VertexGrown(vertex_index)
+                | FaultTarget::VertexNodeIndex(vertex_index)
+                | FaultTarget::VertexRootIndex(vertex_index) => vertex_index < graph.vertex_num,
+                FaultTarget::EdgeWeight(edge_index) => edge_index < graph.weighted_edges.len(),
+            };
+            if !in_range {
+                return Err(CombConfigError::OutOfRange {
+                    option: "faults",
+                    message: format!("the target {:?} is not in the graph", fault.target),
+                });
+            }
+        }
+        Ok(Self {
+            config,
+            records: vec![],
+            vertex_bits,
+            shot: 0,
+            observed_violations: 0,
+        })
+    }
+
+    /// add a fault that strikes from the next instruction on
+    pub fn schedule(&mut self, fault: RegisterFault) {
+        self.config.faults.push(fault);
+    }
+
+    pub fn clear(&mut self) {
+        self.shot += 1;
+    }
+
+    /// flip the registers of the faults scheduled after the instruction `instruction_index`
+    pub fn inject(&mut self, instruction_index: usize, vertices: &mut [Vertex], edges: &mut [Edge]) {
+        let index_none = (1u64 << self.vertex_bits) - 1;
+        let encode = |node_index: Option<NodeIndex>| match node_index {
+            Some(VIRTUAL_NODE_INDEX) | None => index_none,
+            Some(node_index) => node_index as u64,
+        };
+        let decode = |value: u64| (value != index_none).then_some(value as NodeIndex);
+        for fault in self
+            .config
+            .faults
+            .iter()
+            .filter(|fault| fault.instruction == instruction_index)
+        {
+            let mask = 1u64 << fault.bit;
+            let (before, after) = match fault.target {
+                FaultTarget::VertexGrown(vertex_index) => {
+                    let registers = &mut vertices[vertex_index].registers;
+                    let before = registers.grown as u64;
+                    registers.grown = (before ^ mask) as Weight;
+                    (before, before ^ mask)
+                }
+                FaultTarget::VertexNodeIndex(vertex_index) => {
+                    assert!(fault.bit < self.vertex_bits, "node index has only {} bits", self.vertex_bits);
+                    let registers = &mut vertices[vertex_index].registers;
+                    let before = encode(registers.node_index);
+                    registers.node_index = decode(before ^ mask);
+                    (before, before ^ mask)
+                }
+                FaultTarget::VertexRootIndex(vertex_index) => {
+                    assert!(fault.bit < self.vertex_bits, "root index has only {} bits", self.vertex_bits);
+                    let registers = &mut vertices[vertex_index].registers;
+                    let before = encode(registers.root_index);
+                    registers.root_index = decode(before ^ mask);
+                    (before, before ^ mask)
+                }
+                FaultTarget::EdgeWeight(edge_index) => {
+                    let registers = &mut edges[edge_index].registers;
+                    let before = registers.weight as u64;
+                    registers.weight = (before ^ mask) as Weight;
+                    (before, before ^ mask)
+                }
+            };
+            match fault.target {
+                FaultTarget::EdgeWeight(edge_index) => edges[edge_index].register_updated(),
+                FaultTarget::VertexGrown(vertex_index)
+                | FaultTarget::VertexNodeIndex(vertex_index)
+                | FaultTarget::VertexRootIndex(vertex_index) => vertices[vertex_index].register_updated(),
+            }
+            self.records.push(FaultRecord {
+                shot: self.shot,
+                fault: fault.clone(),
+                before,
+                after,
+                detected_by: None,
+            });
+        }
+    }
+
+    /// attribute the new assertion violations to the undetected faults of this shot
+    pub fn observe(&mut self, violations: &[AssertionViolation]) {
+        if violations.len() < self.observed_violations {
+            self.observed_violations = 0; // the violations are cleared with the profiler
+        }
+        let Some(violation) = violations.get(self.observed_violations) else {
+            return;
+        };
+        self.observed_violations = violations.len();
+        for record in self.records.iter_mut().rev() {
+            if record.shot != self.shot {
+                break;
+            }
+            if record.detected_by.is_none() && record.fault.instruction <= violation.instruction_index {
+                record.detected_by = Some(violation.clone());
+            }
+        }
+    }
+
+    pub fn generate_report(&self) -> FaultReport {
+        FaultReport {
+            injected: self.records.len(),
+            detected: self.records.iter().filter(|record| record.detected_by.is_some()).count(),
+            records: self.records.clone(),
+        }
+    }
+}
+
+impl CombObserver for FaultInjector {
+    fn name(&self) -> &'static str {
+        "faults"
+    }
+    fn after_execute(&mut self, driver: &mut DualModuleCombDriver) {
+        let instruction_index = driver.instruction_counts.values().sum::<usize>() - 1;
+        self.inject(instruction_index, &mut driver.vertices, &mut driver.edges);
+    }
+    fn clear(&mut self) {
+        FaultInjector::clear(self);
+    }
+    fn reset_profiler(&mut self) {
+        self.records.clear();
+    }
+    fn generate_report(&self) -> Option<serde_json::Value> {
+        Some(json!(FaultInjector::generate_report(self)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwpm_solver::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::PrimalDualSolver;
+    use micro_blossom_nostd::dual_module_stackless::*;
+    use micro_blossom_nostd::util::*;
+    use serde_json::json;
+
+    /// a flipped sign bit of the growth is caught by `non_negative_grown` in the same instruction
+    #[test]
+    fn dual_module_comb_fault_injection_detected() {
+        // cargo test dual_module_comb_fault_injection_detected -- --nocapture
+        let code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let config: DualCombConfig = serde_json::from_value(json!({
+            "assertions": { "panic": false },
+            "faults": { "faults": [{ "instruction": 1, "target": { "vertex_grown": 16 }, "bit": 63 }] },
+        }))
+        .unwrap();
+        let mut driver = DualModuleCombDriver::new(MicroBlossomSingle::new_code(&code), config);
+        driver.add_defect(ni!(16), ni!(0));
+        driver.add_defect(ni!(17), ni!(1));
+        let report = driver.observer::<FaultInjector>().unwrap().generate_report();
+        assert_eq!((report.injected, report.detected), (1, 1));
+        assert_eq!(report.records[0].after, 1 << 63);
+        let violation = report.records[0].detected_by.as_ref().unwrap();
+        assert_eq!(
+            (violation.hook.as_str(), violation.instruction_index),
+            ("non_negative_grown", 1)
+        );
+    }
+
+    /// a fault on a register that is never used afterwards is silent and does not corrupt the decoding
+    #[test]
+    fn dual_module_comb_fault_injection_silent() {
+        // cargo test dual_module_comb_fault_injection_silent -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        code.set_defect_vertices(&[16, 17]);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let config = json!({ "dual": {
+            "assertions": { "panic": false },
+            "faults": { "faults": [{ "instruction": 0, "target": { "vertex_root_index": 42 }, "bit": 0 }] },
+        } });
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), config);
+        let mut reference = SolverEmbeddedComb::new(graph, json!({}));
+        solver.solve(&code.get_syndrome());
+        reference.solve(&code.get_syndrome());
+        assert_eq!(solver.result().matching_weight, reference.result().matching_weight);
+        let driver = &solver.dual_module.driver.driver;
+        let report = driver.observer::<FaultInjector>().unwrap().generate_report();
+        assert_eq!((report.injected, report.detected), (1, 0));
+        assert_ne!(report.records[0].before, report.records[0].after);
+    }
+}
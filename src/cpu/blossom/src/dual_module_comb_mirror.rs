@@ -0,0 +1,227 @@
+//! Mirrored Vertices of the Combinatorial Dual Module
+//!
+//! The planned fusion of several Micro Blossom chips splits a single connected graph into partitions of consecutive
+//! vertices. Every edge belongs to exactly one partition, the one owning its smaller endpoint, and an endpoint owned
+//! by another partition is mirrored, i.e., every partition holding an edge incident to the vertex keeps a copy of its
+//! registers. All the chips execute the same broadcast instruction, so the copies agree on whatever the instruction
+//! itself changes, e.g., the growth or a new defect. Only the propagation breaks the symmetry: a mirrored vertex adopts
+//! the node of a propagating peer over a tight edge, and that edge lives in a single partition. The deciding partition
+//! then sends a [`MirrorSyncRequest`] with the new node to every other copy; when no peer is found in any partition,
+//! the owner decides that the vertex is released.
+//!
+//! The dual module still evaluates the whole graph at once, so the decoding is unchanged; [`BoundaryMirror`] derives
+//! the sync requests that the split hardware would exchange across the boundary after every instruction. An
+//! instruction that needs any sync request costs an extra round trip between the chips.
+//!
+//! Enable it with `{ "dual": { "mirror": { "partitions": [[0, 30], [30, 60]] } } }`.
+//!
+
+use crate::dual_module_comb::*;
+use crate::resources::*;
+use fusion_blossom::util::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MirrorConfig {
+    /// the vertex range `[begin, end)` owned by every partition, covering all the vertices exactly once
+    pub partitions: Vec<(VertexIndex, VertexIndex)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MirrorSyncRequest {
+    pub vertex: VertexIndex,
+    pub from: usize,
+    pub to: usize,
+    pub node_index: Option<NodeIndex>,
+    pub root_index: Option<NodeIndex>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MirrorStatistics {
+    pub sync_requests: usize,
+    /// the instructions with at least one sync request, each taking an extra round trip between the chips
+    pub sync_instructions: usize,
+}
+
+pub struct BoundaryMirror {
+    pub config: MirrorConfig,
+    pub vertex_partitions: Vec<usize>,
+    pub edge_partitions: Vec<usize>,
+    /// the partitions holding a copy of every mirrored vertex, the owner first
+    pub holders: BTreeMap<VertexIndex, Vec<usize>>,
+    /// the sync requests of the last instruction
+    pub requests: Vec<MirrorSyncRequest>,
+    pub statistics: MirrorStatistics,
+}
+
+impl BoundaryMirror {
+    pub fn new(config: MirrorConfig, graph: &MicroBlossomSingle) -> Result<Self, CombConfigError> {
+        let invalid = |message: String| CombConfigError::OutOfRange {
+            option: "mirror",
+            message,
+        };
+        let mut vertex_partitions = vec![usize::MAX; graph.vertex_num];
+        for (partition, &(begin, end)) in config.partitions.iter().enumerate() {
+            if begin > end || end > graph.vertex_num {
+                return Err(invalid(format!("invalid vertex range [{begin}, {end})")));
+            }
+            for vertex_index in begin..end {
+                if vertex_partitions[vertex_index] != usize::MAX {
+                    return Err(invalid(format!("vertex {vertex_index} in two partitions")));
+                }
+                vertex_partitions[vertex_index] = partition;
+            }
+        }
+        if let Some(vertex_index) = vertex_partitions.iter().position(|&partition| partition == usize::MAX) {
+            return Err(invalid(format!("vertex {vertex_index} is not in any partition")));
+        }
+        let edge_partitions: Vec<usize> = (graph.weighted_edges.iter())
+            .map(|edge| vertex_partitions[edge.l.min(edge.r)])
+            .collect();
+        let mut holders = BTreeMap::<VertexIndex, Vec<usize>>::new();
+        for (edge, &partition) in graph.weighted_edges.iter().zip(edge_partitions.iter()) {
+            for vertex_index in [edge.l, edge.r] {
+                if vertex_partitions[vertex_index] != partition {
+                    let holders = holders
+                        .entry(vertex_index)
+                        .or_insert_with(|| vec![vertex_partitions[vertex_index]]);
+                    if !holders.contains(&partition) {
+                        holders.push(partition);
+                    }
+                }
+            }
+        }
+        Ok(Self {
+            config,
+            vertex_partitions,
+            edge_partitions,
+            holders,
+            requests: vec![],
+            statistics: MirrorStatistics::default(),
+        })
+    }
+
+    pub fn is_mirrored(&self, vertex_index: VertexIndex) -> bool {
+        self.holders.contains_key(&vertex_index)
+    }
+
+    /// derive the sync requests of the current instruction, before the registers are updated
+    pub fn record(&mut self, dual_module: &DualModuleCombDriver) {
+        self.requests.clear();
+        for (&vertex_index, holders) in self.holders.iter() {
+            let vertex = &dual_module.vertices[vertex_index];
+            let post_execute_state = vertex.get_post_execute_state(dual_module);
+            let post_update_state = vertex.get_post_update_state(dual_module);
+            if (post_execute_state.node_index, post_execute_state.root_index)
+                == (post_update_state.node_index, post_update_state.root_index)
+            {
+                continue; // every copy executes the same instruction
+            }
+            // the partition of the edge that the vertex propagates through, following `Vertex::get_propagating_peer`
+            let deciding_edge = (post_update_state.node_index.is_some())
+                .then(|| {
+                    vertex.edge_indices.iter().find(|&&edge_index| {
+                        let edge = &dual_module.edges[edge_index];
+                        edge.get_post_execute_is_tight(dual_module)
+                            && dual_module.vertices[edge.get_peer(vertex_index)].get_is_propagating(dual_module)
+                    })
+                })
+                .flatten();
+            let from = match deciding_edge {
+                Some(&edge_index) => self.edge_partitions[edge_index],
+                None => holders[0],
+            };
+            for &to in holders.iter().filter(|&&to| to != from) {
+                self.requests.push(MirrorSyncRequest {
+                    vertex: vertex_index,
+                    from,
+                    to,
+                    node_index: post_update_state.node_index,
+                    root_index: post_update_state.root_index,
+                });
+            }
+        }
+        self.statistics.sync_requests += self.requests.len();
+        if !self.requests.is_empty() {
+            self.statistics.sync_instructions += 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.requests.clear();
+    }
+}
+
+impl CombObserver for BoundaryMirror {
+    fn name(&self) -> &'static str {
+        "mirror"
+    }
+    fn signals_evaluated(&mut self, driver: &DualModuleCombDriver) {
+        self.record(driver);
+    }
+    fn reads_all_signals(&self) -> bool {
+        true // the signals of the boundary vertices outside the sparse evaluation
+    }
+    fn clear(&mut self) {
+        BoundaryMirror::clear(self);
+    }
+    fn reset_profiler(&mut self) {
+        self.statistics = MirrorStatistics::default();
+    }
+    fn generate_report(&self) -> Option<serde_json::Value> {
+        Some(json!(self.statistics))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwpm_solver::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::PrimalDualSolver;
+    use serde_json::json;
+
+    #[test]
+    fn dual_module_comb_mirror_partitions() {
+        // cargo test dual_module_comb_mirror_partitions -- --nocapture
+        let code = CodeCapacityRepetitionCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let vertex_num = graph.vertex_num;
+        let mirror = BoundaryMirror::new(
+            MirrorConfig {
+                partitions: vec![(0, 4), (4, vertex_num)],
+            },
+            &graph,
+        )
+        .unwrap();
+        // only the first vertex of the second partition is shared, through the edge (3, 4) of the first partition
+        assert_eq!(mirror.holders, BTreeMap::from([(4, vec![1, 0])]));
+        assert!(mirror.is_mirrored(4) && !mirror.is_mirrored(3));
+    }
+
+    /// the sync requests only travel between the holders of a mirrored vertex, and the decoding is unchanged
+    #[test]
+    fn dual_module_comb_mirror_sync_requests() {
+        // cargo test dual_module_comb_mirror_sync_requests -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let half = graph.vertex_num / 2;
+        let config = json!({ "dual": { "mirror": { "partitions": [[0, half], [half, graph.vertex_num]] } } });
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), config);
+        let mut reference = SolverEmbeddedComb::new(graph, json!({}));
+        for seed in 0..20 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            solver.solve(&syndrome_pattern);
+            reference.solve(&syndrome_pattern);
+            assert_eq!(solver.result().matching_weight, reference.result().matching_weight);
+            solver.clear();
+            reference.clear();
+        }
+        let mirror = solver.dual_module.driver.driver.observer::<BoundaryMirror>().unwrap();
+        println!("{:?}", mirror.statistics);
+        assert!(mirror.statistics.sync_requests > 0);
+        assert!(mirror.statistics.sync_instructions <= mirror.statistics.sync_requests);
+    }
+}
@@ -0,0 +1,377 @@
+//! Multi-Beat Readout of the Combinatorial Dual Module
+//!
+//! The hardware does not return an obstacle in a single transaction: the arbitration tree takes several cycles to
+//! settle, and the obstacle is wider than the bus. The readout is thus a sequence of beats of `beat_bytes` each, where
+//! the first beat carries a `found` flag that stays clear until the obstacle settles, and the fields of a conflict
+//! follow in the later beats, which may still hold the previous obstacle when read too early. Every beat is tagged
+//! with the low bits of the obstacle sequence number in its highest byte, so that the driver recognizes such a
+//! partial response and reads the beat again.
+//!
+//! The payload is bit-packed like the hardware: the flags, a 32-bit grow length and the six indices of a conflict
+//! with `vertex_bits` each, all ones for `None` (see [`generated_bits`]). [`BeatChannel`] exposes the beats of an
+//! obstacle with the configured latencies, counted in bus reads, and [`BeatAssembler`] is the polling logic of the
+//! driver; the reassembled obstacle replaces the combinatorial response, so an encoding bug shows up in the decoding.
+//!
+//! Enable it with `{ "dual": { "multi_beat": { "beat_bytes": 4, "header_latency": 3, "field_latency": 1 } } }`.
+//!
+
+use crate::dual_module_comb::*;
+use crate::hardware_config_check::*;
+use crate::resources::*;
+use micro_blossom_nostd::interface::*;
+use micro_blossom_nostd::util::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MultiBeatConfig {
+    /// the width of the bus, 4 or 8 bytes
+    #[serde(default = "multi_beat_config_default::beat_bytes")]
+    pub beat_bytes: usize,
+    /// the number of reads of the first beat before the `found` flag is set
+    #[serde(default = "Default::default")]
+    pub header_latency: usize,
+    /// the number of reads after the `found` flag before the other beats hold the new obstacle
+    #[serde(default = "Default::default")]
+    pub field_latency: usize,
+    /// give up polling after this many reads of a single obstacle
+    #[serde(default = "multi_beat_config_default::max_reads")]
+    pub max_reads: usize,
+}
+
+pub mod multi_beat_config_default {
+    pub fn beat_bytes() -> usize {
+        8
+    }
+    pub fn max_reads() -> usize {
+        1000
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MultiBeatStatistics {
+    pub responses: usize,
+    /// the total number of bus reads
+    pub reads: usize,
+    /// the reads of the first beat before the `found` flag is set
+    pub header_polls: usize,
+    /// the reads of the other beats that still hold the previous obstacle
+    pub stale_beats: usize,
+}
+
+const FLAG_FOUND: u64 = 1;
+const KIND_GROW: u64 = 0;
+const KIND_CONFLICT: u64 = 1;
+const KIND_BLOSSOM: u64 = 2;
+const KIND_NONE: u64 = 3;
+const FLAG_BITS: usize = 3;
+const LENGTH_BITS: usize = 32;
+
+/// the beats of an obstacle, each with the tag in its highest byte
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadoutBeats {
+    pub tag: u8,
+    pub beats: Vec<u64>,
+}
+
+impl ReadoutBeats {
+    fn payload_bits(beat_bytes: usize) -> usize {
+        8 * beat_bytes - 8
+    }
+
+    pub fn beat_num(beat_bytes: usize, vertex_bits: usize) -> usize {
+        (FLAG_BITS + LENGTH_BITS + 6 * vertex_bits).div_ceil(Self::payload_bits(beat_bytes))
+    }
+
+    pub fn encode(obstacle: &CompactObstacle, tag: u8, beat_bytes: usize, vertex_bits: usize) -> Self {
+        let index_none = (1u64 << vertex_bits) - 1;
+        let index = |index: OptionCompactNodeIndex| index.option().map_or(index_none, |index| index.get() as u64);
+        let (kind, length, indices) = match obstacle {
+            CompactObstacle::GrowLength { length } => (KIND_GROW, *length, [index_none; 6]),
+            CompactObstacle::Conflict {
+                node_1,
+                node_2,
+                touch_1,
+                touch_2,
+                vertex_1,
+                vertex_2,
+            } => (
+                KIND_CONFLICT,
+                0,
+                [
+                    index(*node_1),
+                    index(*node_2),
+                    index(*touch_1),
+                    index(*touch_2),
+                    vertex_1.get() as u64,
+                    vertex_2.get() as u64,
+                ],
+            ),
+            CompactObstacle::BlossomNeedExpand { blossom } => {
+                let mut indices = [index_none; 6];
+                indices[0] = blossom.get() as u64;
+                (KIND_BLOSSOM, 0, indices)
+            }
+            CompactObstacle::None => (KIND_NONE, 0, [index_none; 6]),
+        };
+        let mut fields = vec![
+            (FLAG_FOUND | (kind << 1), FLAG_BITS),
+            (length as i32 as u32 as u64, LENGTH_BITS),
+        ];
+        for value in indices {
+            debug_assert!(value <= index_none, "index {value} does not fit in {vertex_bits} bits");
+            fields.push((value, vertex_bits));
+        }
+        let payload_bits = Self::payload_bits(beat_bytes);
+        let mut beats = vec![0u64; Self::beat_num(beat_bytes, vertex_bits)];
+        let mut position = 0;
+        for (value, bits) in fields {
+            for bit in 0..bits {
+                let (beat, offset) = ((position + bit) / payload_bits, (position + bit) % payload_bits);
+                beats[beat] |= ((value >> bit) & 1) << offset;
+            }
+            position += bits;
+        }
+        for beat in beats.iter_mut() {
+            *beat |= (tag as u64) << payload_bits;
+        }
+        Self { tag, beats }
+    }
+
+    pub fn decode(&self, beat_bytes: usize, vertex_bits: usize) -> CompactObstacle {
+        let payload_bits = Self::payload_bits(beat_bytes);
+        let mut position = 0;
+        let mut read = |bits: usize| {
+            let mut value = 0;
+            for bit in 0..bits {
+                let (beat, offset) = ((position + bit) / payload_bits, (position + bit) % payload_bits);
+                value |= ((self.beats[beat] >> offset) & 1) << bit;
+            }
+            position += bits;
+            value
+        };
+        let flags = read(FLAG_BITS);
+        assert!(flags & FLAG_FOUND != 0, "decoding an obstacle that is not found");
+        let length = read(LENGTH_BITS) as u32 as i32 as CompactWeight;
+        let index_none = (1u64 << vertex_bits) - 1;
+        let indices: Vec<u64> = (0..6).map(|_| read(vertex_bits)).collect();
+        let node = |value: u64| {
+            if value == index_none {
+                OptionCompactNodeIndex::NONE
+            } else {
+                ni!(value as CompactNodeNum).option()
+            }
+        };
+        match flags >> 1 {
+            KIND_GROW => CompactObstacle::GrowLength { length },
+            KIND_CONFLICT => CompactObstacle::Conflict {
+                node_1: node(indices[0]),
+                node_2: node(indices[1]),
+                touch_1: node(indices[2]),
+                touch_2: node(indices[3]),
+                vertex_1: ni!(indices[4] as CompactVertexNum),
+                vertex_2: ni!(indices[5] as CompactVertexNum),
+            },
+            KIND_BLOSSOM => CompactObstacle::BlossomNeedExpand {
+                blossom: ni!(indices[0] as CompactNodeNum),
+            },
+            _ => CompactObstacle::None,
+        }
+    }
+}
+
+/// the readout registers of the hardware, exposing the beats of the latest obstacle with latency
+pub struct BeatChannel {
+    pub config: MultiBeatConfig,
+    pub vertex_bits: usize,
+    current: ReadoutBeats,
+    /// the beats of the previous obstacle, still visible before the new ones settle
+    previous: ReadoutBeats,
+    /// the number of reads since the latest obstacle is latched
+    reads: usize,
+}
+
+impl BeatChannel {
+    pub fn new(config: MultiBeatConfig, graph: &MicroBlossomSingle) -> Self {
+        assert!([4, 8].contains(&config.beat_bytes), "the bus is either 32 or 64 bits wide");
+        let (vertex_bits, _) = generated_bits(graph);
+        let empty = ReadoutBeats {
+            tag: 0,
+            beats: vec![0; ReadoutBeats::beat_num(config.beat_bytes, vertex_bits)],
+        };
+        Self {
+            config,
+            vertex_bits,
+            current: empty.clone(),
+            previous: empty,
+            reads: 0,
+        }
+    }
+
+    /// start reporting a new obstacle
+    pub fn latch(&mut self, obstacle: &CompactObstacle) {
+        let tag = self.current.tag.wrapping_add(1);
+        let beats = ReadoutBeats::encode(obstacle, tag, self.config.beat_bytes, self.vertex_bits);
+        self.previous = std::mem::replace(&mut self.current, beats);
+        self.reads = 0;
+    }
+
+    pub fn read(&mut self, beat: usize) -> u64 {
+        self.reads += 1;
+        let payload_bits = ReadoutBeats::payload_bits(self.config.beat_bytes);
+        if self.reads <= self.config.header_latency {
+            // the header is cleared as soon as the instruction is accepted, the other beats are not yet updated
+            return if beat == 0 {
+                (self.current.tag as u64) << payload_bits
+            } else {
+                self.previous.beats[beat]
+            };
+        }
+        if beat > 0 && self.reads <= self.config.header_latency + self.config.field_latency {
+            return self.previous.beats[beat];
+        }
+        self.current.beats[beat]
+    }
+}
+
+/// the polling logic of the driver
+#[derive(Debug, Clone, Default)]
+pub struct BeatAssembler {
+    /// the tag of the next obstacle
+    tag: u8,
+    pub statistics: MultiBeatStatistics,
+}
+
+impl BeatAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// read the beats of the obstacle just issued, re-reading every beat until it is found and up to date
+    pub fn receive(&mut self, channel: &mut BeatChannel) -> CompactObstacle {
+        self.tag = self.tag.wrapping_add(1);
+        let payload_bits = ReadoutBeats::payload_bits(channel.config.beat_bytes);
+        let beat_num = ReadoutBeats::beat_num(channel.config.beat_bytes, channel.vertex_bits);
+        let mut beats = Vec::with_capacity(beat_num);
+        let mut reads = 0;
+        for beat in 0..beat_num {
+            loop {
+                assert!(reads < channel.config.max_reads, "obstacle not ready after {reads} reads");
+                reads += 1;
+                let value = channel.read(beat);
+                let is_current = (value >> payload_bits) as u8 == self.tag;
+                if beat == 0 && !(is_current && value & FLAG_FOUND != 0) {
+                    self.statistics.header_polls += 1;
+                } else if beat > 0 && !is_current {
+                    self.statistics.stale_beats += 1;
+                } else {
+                    beats.push(value);
+                    break;
+                }
+            }
+        }
+        self.statistics.responses += 1;
+        self.statistics.reads += reads;
+        ReadoutBeats { tag: self.tag, beats }.decode(channel.config.beat_bytes, channel.vertex_bits)
+    }
+}
+
+/// the channel and the driver logic of the readout, one per dual module
+pub struct MultiBeatReadout {
+    pub channel: BeatChannel,
+    pub assembler: BeatAssembler,
+}
+
+impl MultiBeatReadout {
+    pub fn new(config: MultiBeatConfig, graph: &MicroBlossomSingle) -> Self {
+        Self {
+            channel: BeatChannel::new(config, graph),
+            assembler: BeatAssembler::new(),
+        }
+    }
+
+    /// pass the combinatorial response through the bus
+    pub fn transfer(&mut self, obstacle: &CompactObstacle) -> CompactObstacle {
+        self.channel.latch(obstacle);
+        self.assembler.receive(&mut self.channel)
+    }
+}
+
+impl CombObserver for MultiBeatReadout {
+    fn name(&self) -> &'static str {
+        "multi_beat"
+    }
+    fn transfer_response(&mut self, response: CompactObstacle) -> CompactObstacle {
+        self.transfer(&response)
+    }
+    fn reset_profiler(&mut self) {
+        self.assembler.statistics = MultiBeatStatistics::default();
+    }
+    fn generate_report(&self) -> Option<serde_json::Value> {
+        Some(json!(self.assembler.statistics))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwpm_solver::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::PrimalDualSolver;
+    use serde_json::json;
+
+    #[test]
+    fn dual_module_comb_multi_beat_round_trip() {
+        // cargo test dual_module_comb_multi_beat_round_trip -- --nocapture
+        let obstacles = [
+            CompactObstacle::GrowLength { length: 0 },
+            CompactObstacle::GrowLength {
+                length: CompactWeight::MAX,
+            },
+            CompactObstacle::Conflict {
+                node_1: ni!(3).option(),
+                node_2: OptionCompactNodeIndex::NONE,
+                touch_1: ni!(3).option(),
+                touch_2: OptionCompactNodeIndex::NONE,
+                vertex_1: ni!(17),
+                vertex_2: ni!(0),
+            },
+            CompactObstacle::BlossomNeedExpand { blossom: ni!(60) },
+            CompactObstacle::None,
+        ];
+        for beat_bytes in [4, 8] {
+            for obstacle in obstacles.iter() {
+                let beats = ReadoutBeats::encode(obstacle, 42, beat_bytes, 7);
+                assert_eq!(beats.beats.len(), if beat_bytes == 4 { 4 } else { 2 });
+                assert!(beats.beats.iter().all(|beat| beat >> (8 * beat_bytes - 8) == 42));
+                assert_eq!(&beats.decode(beat_bytes, 7), obstacle);
+            }
+        }
+    }
+
+    /// the driver tolerates the partial responses and decodes the same as without the bus
+    #[test]
+    fn dual_module_comb_multi_beat_solver() {
+        // cargo test dual_module_comb_multi_beat_solver -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let config = json!({ "dual": { "multi_beat": { "beat_bytes": 4, "header_latency": 3, "field_latency": 2 } } });
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), config);
+        let mut reference = SolverEmbeddedComb::new(graph, json!({}));
+        for seed in 0..20 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            solver.solve(&syndrome_pattern);
+            reference.solve(&syndrome_pattern);
+            assert_eq!(solver.result().matching_weight, reference.result().matching_weight);
+            solver.clear();
+            reference.clear();
+        }
+        let driver = &solver.dual_module.driver.driver;
+        let multi_beat = driver.observer::<MultiBeatReadout>().unwrap();
+        let statistics = &multi_beat.assembler.statistics;
+        println!("{statistics:?}");
+        assert_eq!(statistics.header_polls, 3 * statistics.responses);
+        assert!(statistics.stale_beats > 0);
+        assert!(statistics.reads > statistics.responses * ReadoutBeats::beat_num(4, multi_beat.channel.vertex_bits));
+    }
+}
@@ -2,6 +2,8 @@ use crate::dual_module_comb::*;
 use crate::resources::*;
 use fusion_blossom::util::*;
 use micro_blossom_nostd::util::*;
+use serde::Serialize;
+use serde_json::json;
 use std::cell::{Ref, RefCell};
 use std::collections::BTreeSet;
 
@@ -16,6 +18,60 @@ pub struct Offloading {
     pub signals: RefCell<Option<OffloadingSignals>>,
 }
 
+/// how the defects of the decoded shots are resolved, counted by the driver when a shot is finished: a defect is
+/// either pre-matched by an offloading unit or escalated to the primal module
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PreMatchingStatistics {
+    /// the shots with at least one defect
+    pub shots: usize,
+    pub defects: usize,
+    /// the defects matched with another defect by a defect match unit
+    pub peer_matched: usize,
+    /// the defects matched with a virtual vertex by a virtual match unit
+    pub virtual_matched: usize,
+    /// the defects matched with a virtual vertex through a chain match unit
+    pub chain_matched: usize,
+}
+
+impl PreMatchingStatistics {
+    pub fn pre_matched(&self) -> usize {
+        self.peer_matched + self.virtual_matched + self.chain_matched
+    }
+
+    pub fn escalated(&self) -> usize {
+        self.defects - self.pre_matched()
+    }
+
+    /// the fraction of the defects resolved by the offloading units
+    pub fn hit_rate(&self) -> f64 {
+        if self.defects == 0 {
+            return 0.;
+        }
+        self.pre_matched() as f64 / self.defects as f64
+    }
+
+    pub fn add(&mut self, other: &Self) {
+        self.shots += other.shots;
+        self.defects += other.defects;
+        self.peer_matched += other.peer_matched;
+        self.virtual_matched += other.virtual_matched;
+        self.chain_matched += other.chain_matched;
+    }
+
+    pub fn generate_report(&self) -> serde_json::Value {
+        json!({
+            "shots": self.shots,
+            "defects": self.defects,
+            "peer_matched": self.peer_matched,
+            "virtual_matched": self.virtual_matched,
+            "chain_matched": self.chain_matched,
+            "pre_matched": self.pre_matched(),
+            "escalated": self.escalated(),
+            "hit_rate": self.hit_rate(),
+        })
+    }
+}
+
 pub struct OffloadingSignals {
     /// when this offloading is taking effect
     pub condition: bool,
@@ -70,6 +126,24 @@ impl Offloading {
                 affecting_vertices.insert(left_index);
                 affecting_vertices.insert(right_index);
             }
+            OffloadingType::ChainMatch {
+                edge_index,
+                boundary_edge,
+                virtual_vertex: _,
+            } => {
+                affecting_edges.insert(edge_index);
+                affecting_edges.insert(boundary_edge);
+                let (left_index, right_index, _) = initializer.weighted_edges[edge_index];
+                // the defect, the vertex in the middle and all their neighbors
+                for &(neighbor_left, neighbor_right, _) in initializer.weighted_edges.iter() {
+                    for regular_index in [left_index, right_index] {
+                        if neighbor_left == regular_index || neighbor_right == regular_index {
+                            affecting_vertices.insert(neighbor_left);
+                            affecting_vertices.insert(neighbor_right);
+                        }
+                    }
+                }
+            }
         }
         Self {
             offloading_type,
@@ -166,6 +240,64 @@ impl Offloading {
                     }
                     condition
                 }
+                OffloadingType::ChainMatch {
+                    edge_index,
+                    boundary_edge,
+                    virtual_vertex: virtual_index,
+                } => {
+                    let boundary = &dual_module.edges[boundary_edge];
+                    let middle_index = boundary.get_peer(virtual_index);
+                    let chain_edge = &dual_module.edges[edge_index];
+                    let defect_index = chain_edge.get_peer(middle_index);
+                    let virtual_vertex = &dual_module.vertices[virtual_index];
+                    let middle_vertex = &dual_module.vertices[middle_index];
+                    let defect_vertex = &dual_module.vertices[defect_index];
+                    // the vertex in the middle is reached by the defect, and the defect reaches the virtual vertex
+                    let mut condition = boundary.get_post_fetch_is_tight(dual_module)
+                        && chain_edge.get_post_fetch_is_tight(dual_module)
+                        && virtual_vertex.registers.is_virtual
+                        && defect_vertex.registers.is_defect
+                        && defect_vertex.registers.speed == CompactGrowState::Grow
+                        && !middle_vertex.registers.is_defect
+                        && middle_vertex.registers.node_index.is_some()
+                        && middle_vertex.registers.node_index == defect_vertex.registers.node_index;
+                    // any other vertex reached by the chain must not be reached by anything else
+                    let chain = [
+                        (defect_index, edge_index, edge_index),
+                        (middle_index, edge_index, boundary_edge),
+                    ];
+                    for &(regular_index, chain_edge_1, chain_edge_2) in chain.iter() {
+                        for &neighbor_edge_index in dual_module.vertices[regular_index].edge_indices.iter() {
+                            if neighbor_edge_index == chain_edge_1 || neighbor_edge_index == chain_edge_2 {
+                                continue;
+                            }
+                            let neighbor_edge = &dual_module.edges[neighbor_edge_index];
+                            let neighbor_vertex = &dual_module.vertices[neighbor_edge.get_peer(regular_index)];
+                            condition &= !neighbor_edge.get_post_fetch_is_tight(dual_module)
+                                || (neighbor_vertex.get_is_unique_tight(dual_module)
+                                    && !neighbor_vertex.registers.is_defect
+                                    && !neighbor_vertex.registers.is_virtual);
+                        }
+                    }
+                    if condition {
+                        vertex_stalls.insert(defect_index);
+                        vertex_stalls.insert(middle_index);
+                        edge_stalls.insert(edge_index);
+                        edge_stalls.insert(boundary_edge);
+                        for &(regular_index, chain_edge_1, chain_edge_2) in chain.iter() {
+                            for &neighbor_edge_index in dual_module.vertices[regular_index].edge_indices.iter() {
+                                if neighbor_edge_index == chain_edge_1 || neighbor_edge_index == chain_edge_2 {
+                                    continue;
+                                }
+                                let neighbor_edge = &dual_module.edges[neighbor_edge_index];
+                                if neighbor_edge.get_post_fetch_is_tight(dual_module) {
+                                    vertex_stalls.insert(neighbor_edge.get_peer(regular_index));
+                                }
+                            }
+                        }
+                    }
+                    condition
+                }
             };
             OffloadingSignals {
                 condition,
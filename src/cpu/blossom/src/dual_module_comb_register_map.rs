@@ -0,0 +1,494 @@
+//! Register Map of the Combinatorial Dual Module
+//!
+//! The FPGA design is only accessible through the memory-mapped registers of `MicroBlossomBus`: the control block
+//! (timer, hardware information and counters), the instruction arrays and the per-context readouts. This module
+//! exposes the combinatorial dual module, one driver per context, through the same address map, so that the driver
+//! code (e.g. the memory accesses of `DualModuleAxi4Driver` or the firmware) can be tested against the software model
+//! before the hardware exists.
+//!
+//! Differences from the hardware: the timer counts the bus transactions instead of clock cycles, the parity reports
+//! are always 0 and the load stall emulator registers are stored but have no effect. Accessing an unknown address
+//! increments the error counter, as the hardware does.
+//!
+
+use crate::dual_module_comb::*;
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use embedded_blossom::extern_c::*;
+use fusion_blossom::util::*;
+use micro_blossom_nostd::dual_driver_tracked::*;
+use micro_blossom_nostd::dual_module_stackless::*;
+use micro_blossom_nostd::instruction::*;
+use micro_blossom_nostd::interface::*;
+use micro_blossom_nostd::util::*;
+
+/// `DualConfig.version` of the RTL generator that this register map mirrors
+pub const REGISTER_MAP_VERSION: u32 = 0x240123c0;
+pub const INSTRUCTION_64_BASE: usize = 4 * 1024;
+pub const INSTRUCTION_32_BASE: usize = 8 * 1024;
+pub const READOUT_BASE: usize = 128 * 1024;
+/// each context takes 128 bytes in the readout array
+pub const READOUT_CONTEXT_BYTES: usize = 128;
+pub const MAX_CONTEXT_DEPTH: usize = 1024;
+
+/// the registers of a context, i.e., an entry in the readout array
+pub struct ContextRegisters {
+    pub driver: DualModuleCombDriver,
+    pub maximum_growth: u16,
+    pub accumulated_grown: u16,
+    /// the growable length of the last readout
+    pub max_growable: u16,
+    /// the cached obstacle, invalidated by any instruction or by updating the maximum growth
+    pub obstacle: Option<CompactObstacle>,
+    pub load_time: u64,
+    pub finish_time: u64,
+    pub load_stall_start_time: u64,
+    pub load_stall_interval: u32,
+}
+
+pub struct CombRegisterFile {
+    pub contexts: Vec<ContextRegisters>,
+    pub info: MicroBlossomHardwareInfo,
+    pub timer: u64,
+    pub instruction_counter: u32,
+    pub readout_counter: u32,
+    pub transaction_counter: u32,
+    pub error_counter: u32,
+}
+
+/// replace `num_bytes` bytes of `word` at the byte `offset` with `data`
+fn merge_bytes(word: u64, offset: usize, num_bytes: usize, data: u64) -> u64 {
+    let mask = if num_bytes == 8 {
+        u64::MAX
+    } else {
+        (1u64 << (8 * num_bytes)) - 1
+    };
+    (word & !(mask << (8 * offset))) | ((data & mask) << (8 * offset))
+}
+
+impl CombRegisterFile {
+    /// the context depth, the bus width and the supported features follow `config.sim_config`
+    pub fn new(graph: MicroBlossomSingle, config: DualCombConfig) -> Self {
+        let sim_config = &config.sim_config;
+        assert!((1..=MAX_CONTEXT_DEPTH).contains(&sim_config.context_depth));
+        let mut flags = MicroBlossomHardwareFlags::empty();
+        flags.set(
+            MicroBlossomHardwareFlags::SUPPORT_ADD_DEFECT_VERTEX,
+            sim_config.support_add_defect_vertex,
+        );
+        flags.set(MicroBlossomHardwareFlags::SUPPORT_OFFLOADING, sim_config.support_offloading);
+        flags.set(
+            MicroBlossomHardwareFlags::SUPPORT_LAYER_FUSION,
+            sim_config.support_layer_fusion,
+        );
+        flags.set(MicroBlossomHardwareFlags::HARD_CODE_WEIGHTS, sim_config.hard_code_weights);
+        flags.set(
+            MicroBlossomHardwareFlags::SUPPORT_CONTEXT_SWITCHING,
+            sim_config.context_depth > 1,
+        );
+        flags.set(MicroBlossomHardwareFlags::IS_64_BUS, sim_config.use_64_bus);
+        flags.set(
+            MicroBlossomHardwareFlags::SUPPORT_LOAD_STALL_EMULATOR,
+            sim_config.support_load_stall_emulator,
+        );
+        let (vertex_bits, weight_bits) = crate::hardware_config_check::generated_bits(&graph);
+        let info = MicroBlossomHardwareInfo {
+            version: REGISTER_MAP_VERSION,
+            context_depth: sim_config.context_depth as u32,
+            conflict_channels: sim_config.conflict_channels as u8,
+            vertex_bits: vertex_bits as u8,
+            weight_bits: weight_bits as u8,
+            instruction_buffer_depth: 4,
+            flags,
+            num_layers: graph
+                .layer_fusion
+                .as_ref()
+                .map_or(0, |layer_fusion| layer_fusion.num_layers as u8),
+            reserved: 0,
+        };
+        let contexts = (0..sim_config.context_depth)
+            .map(|_| ContextRegisters {
+                driver: DualModuleCombDriver::new(graph.clone(), config.clone()),
+                maximum_growth: 0,
+                accumulated_grown: 0,
+                max_growable: 0,
+                obstacle: None,
+                load_time: 0,
+                finish_time: 0,
+                load_stall_start_time: 0,
+                load_stall_interval: 0,
+            })
+            .collect();
+        Self {
+            contexts,
+            info,
+            timer: 0,
+            instruction_counter: 0,
+            readout_counter: 0,
+            transaction_counter: 0,
+            error_counter: 0,
+        }
+    }
+
+    /// the counters of the control block, without counting the access
+    pub fn counters(&self) -> HardwareCounters {
+        HardwareCounters {
+            instruction_counter: self.instruction_counter,
+            readout_counter: self.readout_counter,
+            transaction_counter: self.transaction_counter,
+            error_counter: self.error_counter,
+        }
+    }
+
+    pub fn is_64_bus(&self) -> bool {
+        self.info.flags.contains(MicroBlossomHardwareFlags::IS_64_BUS)
+    }
+
+    /// the context id and the sub address of an address in the readout array
+    fn context_of(&self, address: usize) -> Option<(usize, usize)> {
+        let context_id = (address - READOUT_BASE) / READOUT_CONTEXT_BYTES;
+        if context_id >= self.contexts.len() {
+            return None;
+        }
+        Some((context_id, (address - READOUT_BASE) % READOUT_CONTEXT_BYTES))
+    }
+
+    /// read `num_bytes` (1, 2, 4 or 8) at `address`; the bytes outside the register are returned as 0
+    pub fn memory_read(&mut self, num_bytes: usize, address: usize) -> u64 {
+        assert!([1, 2, 4, 8].contains(&num_bytes) && address % num_bytes == 0);
+        self.timer += 1;
+        let (word_address, offset) = (address / 8 * 8, address % 8);
+        let word = if word_address < INSTRUCTION_64_BASE {
+            self.read_control(word_address)
+        } else if (READOUT_BASE..2 * READOUT_BASE).contains(&word_address) {
+            self.transaction_counter += 1;
+            self.read_readout(word_address)
+        } else {
+            None
+        };
+        let Some(word) = word else {
+            self.error_counter += 1;
+            return u64::MAX;
+        };
+        let word = word >> (8 * offset);
+        if num_bytes == 8 {
+            word
+        } else {
+            word & ((1u64 << (8 * num_bytes)) - 1)
+        }
+    }
+
+    fn read_control(&mut self, word_address: usize) -> Option<u64> {
+        let raw = unsafe { MicroBlossomHardwareInfoUnion { info: self.info }.raw };
+        Some(match word_address {
+            0 => self.timer,
+            8 => raw[0],
+            16 => raw[1],
+            24 => self.instruction_counter as u64,
+            32 => self.readout_counter as u64,
+            40 => self.transaction_counter as u64,
+            48 => self.error_counter as u64,
+            _ => return None,
+        })
+    }
+
+    fn read_readout(&mut self, word_address: usize) -> Option<u64> {
+        let (context_id, sub_address) = self.context_of(word_address)?;
+        if sub_address == 32 || sub_address == 40 {
+            self.readout_counter += 1;
+            let raw = self.single_readout(context_id);
+            return Some(raw[(sub_address - 32) / 8]);
+        }
+        let context = &self.contexts[context_id];
+        Some(match sub_address {
+            0 => context.load_time,
+            8 => context.finish_time,
+            16 => ((context.max_growable as u64) << 16) | context.maximum_growth as u64,
+            24 => 0,
+            112 => context.load_stall_start_time,
+            120 => context.load_stall_interval as u64,
+            _ => return None,
+        })
+    }
+
+    /// the obstacle readout, issuing a `FindObstacle` that grows up to the maximum growth if not cached
+    pub fn single_readout(&mut self, context_id: usize) -> [u64; 2] {
+        let timer = self.timer;
+        let context = &mut self.contexts[context_id];
+        let obstacle = match context.obstacle.clone() {
+            Some(obstacle) => obstacle,
+            None => {
+                let maximum_growth = context.maximum_growth.saturating_sub(context.accumulated_grown);
+                let (mut obstacle, grown) = if maximum_growth == 0 {
+                    // the offloaded primal is disabled, only report the growable length
+                    let obstacle = context
+                        .driver
+                        .execute_instruction(Instruction::FindObstacle { region_preference: None });
+                    (obstacle, 0)
+                } else {
+                    context.driver.find_conflict(maximum_growth as CompactWeight)
+                };
+                if matches!(obstacle, CompactObstacle::GrowLength { length } if length == CompactWeight::MAX) {
+                    obstacle = CompactObstacle::None;
+                }
+                context.accumulated_grown += grown as u16;
+                context.max_growable = match obstacle {
+                    CompactObstacle::None => u16::MAX,
+                    CompactObstacle::GrowLength { length } => length as u16,
+                    _ => 0,
+                };
+                context.finish_time = timer;
+                context.obstacle = Some(obstacle.clone());
+                obstacle
+            }
+        };
+        let index = |index: OptionCompactNodeIndex| index.option().map_or(u16::MAX, |index| index.get() as u16);
+        let mut readout = SingleReadout {
+            accumulated_grown: context.accumulated_grown,
+            ..Default::default()
+        };
+        match obstacle {
+            CompactObstacle::None => readout.max_growable = u8::MAX,
+            CompactObstacle::GrowLength { length } => readout.max_growable = length.min(u8::MAX as CompactWeight - 1) as u8,
+            CompactObstacle::Conflict {
+                node_1,
+                node_2,
+                touch_1,
+                touch_2,
+                vertex_1,
+                vertex_2,
+            } => {
+                readout.conflict_valid = 1;
+                readout.node_1 = index(node_1);
+                readout.node_2 = index(node_2);
+                readout.touch_1 = index(touch_1);
+                readout.touch_2 = index(touch_2);
+                readout.vertex_1 = vertex_1.get() as u16;
+                readout.vertex_2 = vertex_2.get() as u16;
+            }
+            _ => unreachable!(),
+        }
+        unsafe { SingleReadoutUnion { readout }.raw }
+    }
+
+    /// write the lowest `num_bytes` (1, 2, 4 or 8) of `data` at `address`
+    pub fn memory_write(&mut self, num_bytes: usize, address: usize, data: u64) {
+        assert!([1, 2, 4, 8].contains(&num_bytes) && address % num_bytes == 0);
+        self.timer += 1;
+        let (word_address, offset) = (address / 8 * 8, address % 8);
+        let accepted = if word_address < INSTRUCTION_64_BASE {
+            self.write_control(word_address, offset, num_bytes, data)
+        } else if (INSTRUCTION_64_BASE..INSTRUCTION_32_BASE).contains(&address) && self.is_64_bus() && num_bytes == 8 {
+            self.transaction_counter += 1;
+            self.write_instruction((data >> 32) as u16 as usize, Instruction32(data as u32))
+        } else if (INSTRUCTION_32_BASE..INSTRUCTION_32_BASE + 4 * MAX_CONTEXT_DEPTH).contains(&address)
+            && !self.is_64_bus()
+            && num_bytes == 4
+        {
+            self.transaction_counter += 1;
+            self.write_instruction((address - INSTRUCTION_32_BASE) / 4, Instruction32(data as u32))
+        } else if (READOUT_BASE..2 * READOUT_BASE).contains(&word_address) {
+            self.transaction_counter += 1;
+            self.write_readout(word_address, offset, num_bytes, data)
+        } else {
+            false
+        };
+        if !accepted {
+            self.error_counter += 1;
+        }
+    }
+
+    fn write_control(&mut self, word_address: usize, offset: usize, num_bytes: usize, data: u64) -> bool {
+        let counter = match word_address {
+            24 => &mut self.instruction_counter,
+            32 => &mut self.readout_counter,
+            40 => &mut self.transaction_counter,
+            48 => &mut self.error_counter,
+            _ => return false,
+        };
+        *counter = merge_bytes(*counter as u64, offset, num_bytes, data) as u32;
+        true
+    }
+
+    fn write_readout(&mut self, word_address: usize, offset: usize, num_bytes: usize, data: u64) -> bool {
+        let Some((context_id, sub_address)) = self.context_of(word_address) else {
+            return false;
+        };
+        let context = &mut self.contexts[context_id];
+        match sub_address {
+            0 => context.accumulated_grown = 0,
+            16 => {
+                // updating the maximum growth invalidates the readout and clears the accumulated grown value
+                context.maximum_growth = merge_bytes(context.maximum_growth as u64, offset, num_bytes, data) as u16;
+                context.accumulated_grown = 0;
+                context.obstacle = None;
+            }
+            112 => context.load_stall_start_time = merge_bytes(context.load_stall_start_time, offset, num_bytes, data),
+            120 => {
+                context.load_stall_interval = merge_bytes(context.load_stall_interval as u64, offset, num_bytes, data) as u32
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// decode and execute an instruction on a context; returns false if the instruction is not supported
+    fn write_instruction(&mut self, context_id: usize, instruction: Instruction32) -> bool {
+        if context_id >= self.contexts.len() {
+            return false;
+        }
+        self.instruction_counter += 1;
+        let timer = self.timer;
+        let context = &mut self.contexts[context_id];
+        context.load_time = timer;
+        context.obstacle = None;
+        let driver = &mut context.driver;
+        let field1 = instruction.field1() as VertexIndex;
+        let field2 = instruction.field2() as VertexIndex;
+        if instruction.is_set_speed() {
+            match instruction.get_speed_magnitude() {
+                1 => driver.set_speed(false, ni!(field1), instruction.get_speed()),
+                magnitude => driver.set_speed_with_magnitude(false, ni!(field1), instruction.get_speed(), magnitude),
+            }
+        } else if instruction.is_set_blossom() {
+            driver.set_blossom(ni!(field1), ni!(field2));
+        } else if instruction.op_code() == OP_CODE_ADD_DEFECT_VERTEX {
+            driver.add_defect(ni!(field1), ni!(field2));
+        } else if instruction.is_extended() {
+            match instruction.extended_op_code() {
+                EXTENDED_OP_CODE_FIND_OBSTACLE => {
+                    // pre-fetch the readout
+                    self.single_readout(context_id);
+                }
+                EXTENDED_OP_CODE_RESET => driver.reset(),
+                EXTENDED_OP_CODE_GROW => {
+                    driver.execute_instruction(Instruction::Grow {
+                        length: instruction.get_length() as Weight,
+                    });
+                }
+                EXTENDED_OP_CODE_LOAD_DEFECTS_EXTERNAL => driver.fuse_layer(field1),
+                _ => return false,
+            }
+        } else {
+            return false;
+        }
+        true
+    }
+
+    pub fn memory_read_64(&mut self, address: usize) -> u64 {
+        self.memory_read(8, address)
+    }
+    pub fn memory_read_32(&mut self, address: usize) -> u32 {
+        self.memory_read(4, address) as u32
+    }
+    pub fn memory_read_16(&mut self, address: usize) -> u16 {
+        self.memory_read(2, address) as u16
+    }
+    pub fn memory_write_64(&mut self, address: usize, data: u64) {
+        self.memory_write(8, address, data)
+    }
+    pub fn memory_write_32(&mut self, address: usize, data: u32) {
+        self.memory_write(4, address, data as u64)
+    }
+    pub fn memory_write_16(&mut self, address: usize, data: u16) {
+        self.memory_write(2, address, data as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+    use serde_json::json;
+
+    /// the accesses of `DualModuleAxi4Driver` on a 64-bit bus
+    fn execute(registers: &mut CombRegisterFile, context_id: u16, instruction: Instruction32) {
+        let data = (instruction.0 as u64) | ((context_id as u64) << 32);
+        registers.memory_write_64(INSTRUCTION_64_BASE, data);
+    }
+    fn find_conflict(
+        registers: &mut CombRegisterFile,
+        context_id: u16,
+        maximum_growth: u16,
+    ) -> (CompactObstacle, CompactWeight) {
+        let base = READOUT_BASE + READOUT_CONTEXT_BYTES * context_id as usize;
+        registers.memory_write_16(base + 16, maximum_growth);
+        let readout = unsafe {
+            let mut readout_union = SingleReadoutUnion { raw: [0, 0] };
+            readout_union.raw[0] = registers.memory_read_64(base + 32);
+            readout_union.raw[1] = registers.memory_read_64(base + 40);
+            readout_union.readout
+        };
+        registers.memory_write_16(base, 0);
+        readout.into_obstacle()
+    }
+
+    #[test]
+    fn dual_module_comb_register_map_hardware_info() {
+        // cargo test dual_module_comb_register_map_hardware_info -- --nocapture
+        let code = CodeCapacityPlanarCode::new(5, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let config: DualCombConfig =
+            serde_json::from_value(json!({ "sim_config": { "context_depth": 2, "use_64_bus": true } })).unwrap();
+        let mut registers = CombRegisterFile::new(graph, config);
+        let info = unsafe {
+            let mut info_union = MicroBlossomHardwareInfoUnion { raw: [0, 0] };
+            info_union.raw[0] = registers.memory_read_64(8);
+            info_union.raw[1] = registers.memory_read_64(16);
+            info_union.info
+        };
+        assert_eq!(info.version, REGISTER_MAP_VERSION);
+        assert_eq!(info.context_depth, 2);
+        assert_eq!((info.vertex_bits, info.weight_bits), (7, 10));
+        assert!(info.flags.contains(MicroBlossomHardwareFlags::SUPPORT_CONTEXT_SWITCHING));
+        assert_eq!(registers.memory_read_32(8), REGISTER_MAP_VERSION);
+        // unknown addresses and contexts are counted as errors, which can be cleared
+        assert_eq!(registers.memory_read_32(48), 0);
+        registers.memory_read_64(56);
+        registers.memory_write_16(READOUT_BASE + 2 * READOUT_CONTEXT_BYTES + 16, 100);
+        assert_eq!(registers.memory_read_32(48), 2);
+        registers.memory_write_32(48, 0);
+        assert_eq!(registers.memory_read_32(48), 0);
+        // the driver reads the same counters through the bus
+        registers.memory_read_64(56);
+        let counters = HardwareCounters::read(|address| Ok::<_, ()>(registers.memory_read_32(address))).unwrap();
+        assert_eq!(counters, registers.counters());
+        assert_eq!(counters.error_counter, 1);
+        assert!(registers.memory_read_64(0) > 0, "the timer is running");
+    }
+
+    /// the driver sequence through the register map reports the same obstacles as the driver itself
+    #[test]
+    fn dual_module_comb_register_map_driver_sequence() {
+        // cargo test dual_module_comb_register_map_driver_sequence -- --nocapture
+        let code = CodeCapacityRepetitionCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let config: DualCombConfig =
+            serde_json::from_value(json!({ "sim_config": { "context_depth": 2, "use_64_bus": true } })).unwrap();
+        let mut registers = CombRegisterFile::new(graph.clone(), config.clone());
+        let mut reference = DualModuleCombDriver::new(graph, config);
+        for context_id in 0..2 {
+            execute(&mut registers, context_id, Instruction32::reset());
+        }
+        // only context 1 is loaded, context 0 stays empty
+        execute(&mut registers, 1, Instruction32::add_defect_vertex(ni!(1), ni!(0)));
+        execute(&mut registers, 1, Instruction32::add_defect_vertex(ni!(4), ni!(1)));
+        reference.add_defect(ni!(1), ni!(0));
+        reference.add_defect(ni!(4), ni!(1));
+        assert_eq!(registers.memory_read_32(24), 4, "instruction counter");
+        let (obstacle, grown) = find_conflict(&mut registers, 1, 1000);
+        assert_eq!((obstacle.clone(), grown), reference.find_conflict(1000));
+        assert!(matches!(obstacle, CompactObstacle::Conflict { .. }));
+        assert_eq!(find_conflict(&mut registers, 0, 1000), (CompactObstacle::None, 0));
+        // a limited maximum growth reports a growable length of 0, and the grown value is cleared by the driver
+        execute(&mut registers, 1, Instruction32::set_speed(ni!(0), CompactGrowState::Shrink));
+        execute(&mut registers, 1, Instruction32::set_speed(ni!(1), CompactGrowState::Shrink));
+        reference.set_speed(false, ni!(0), CompactGrowState::Shrink);
+        reference.set_speed(false, ni!(1), CompactGrowState::Shrink);
+        assert_eq!(find_conflict(&mut registers, 1, 100), reference.find_conflict(100));
+        let base = READOUT_BASE + READOUT_CONTEXT_BYTES;
+        assert_eq!(registers.memory_read_16(base + 16), 100);
+        assert!(registers.memory_read_64(base + 8) >= registers.memory_read_64(base));
+        assert_eq!(registers.memory_read_32(48), 0);
+    }
+}
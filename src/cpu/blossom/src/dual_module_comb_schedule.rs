@@ -0,0 +1,245 @@
+//! Instruction Scheduling of the Combinatorial Dual Module
+//!
+//! The canonical loop of the driver issues `FindObstacle`, then `Grow` by the reported length, then `FindObstacle`
+//! again until a conflict shows up, and forwards every speed update of the primal module as it comes. Several of these
+//! transactions are avoidable:
+//!
+//! - the obstacle of the grown state is available in the same pass as `Grow`, so [`Instruction::GrowFindObstacle`]
+//!   saves the following `FindObstacle` whenever it reports a conflict or a positive length; a zero length means some
+//!   vertices are yet to be propagated in the grown state and `FindObstacle` is still needed
+//! - the primal module may set the speed of a node more than once when resolving an obstacle, and only the last one
+//!   matters to the next `FindObstacle`
+//! - a speed update that does not change the speed of the node changes nothing in the hardware
+//!
+//! The [`InstructionScheduler`] buffers the speed updates until an instruction depends on them, drops the redundant
+//! ones and fuses `Grow` with the following `FindObstacle`. The `instruction_counts` of the driver only include the
+//! issued instructions, so they compare directly with the canonical loop; the savings of every rule are reported in
+//! the profile.
+//!
+
+use crate::dual_module_comb::*;
+use fusion_blossom::util::*;
+use micro_blossom_nostd::util::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleConfig {
+    /// issue `GrowFindObstacle` instead of `Grow` followed by `FindObstacle`
+    #[serde(default = "schedule_config_default::fuse_grow")]
+    pub fuse_grow: bool,
+    /// buffer the speed updates and only issue the last one of every node before a dependent instruction
+    #[serde(default = "schedule_config_default::coalesce_speed")]
+    pub coalesce_speed: bool,
+    /// drop the speed updates to the current speed of the node
+    #[serde(default = "schedule_config_default::skip_redundant_speed")]
+    pub skip_redundant_speed: bool,
+}
+
+pub mod schedule_config_default {
+    pub fn fuse_grow() -> bool {
+        true
+    }
+    pub fn coalesce_speed() -> bool {
+        true
+    }
+    pub fn skip_redundant_speed() -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScheduleStatistics {
+    /// the `FindObstacle` instructions saved by fusing them into `Grow`, i.e., the fused obstacles that are used
+    pub fused_grow: usize,
+    /// the speed updates overwritten by a later update of the same node before being issued
+    pub coalesced_speed: usize,
+    /// the speed updates dropped because the node already has the speed
+    pub redundant_speed: usize,
+}
+
+/// the speed of a node together with its magnitude
+type NodeSpeed = (CompactGrowState, CompactSpeed);
+
+#[derive(Debug, Clone)]
+pub struct InstructionScheduler {
+    pub config: ScheduleConfig,
+    /// the speed of the unfrozen vertices of a node, only recorded when they are known to be the same
+    known_speeds: BTreeMap<NodeIndex, NodeSpeed>,
+    /// the nodes that have been assigned to any vertex since the last clear
+    assigned: BTreeSet<NodeIndex>,
+    /// the buffered speed updates, at most one per node
+    pending: Vec<Instruction>,
+    pub statistics: ScheduleStatistics,
+}
+
+impl InstructionScheduler {
+    pub fn new(config: ScheduleConfig) -> Self {
+        Self {
+            config,
+            known_speeds: BTreeMap::new(),
+            assigned: BTreeSet::new(),
+            pending: vec![],
+            statistics: ScheduleStatistics::default(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.known_speeds.clear();
+        self.assigned.clear();
+        self.pending.clear();
+    }
+
+    fn node_speed(instruction: &Instruction) -> (NodeIndex, NodeSpeed) {
+        match *instruction {
+            Instruction::SetSpeed { node, speed } => (node, (speed, 1)),
+            Instruction::SetSpeedWithMagnitude { node, speed, magnitude } => (node, (speed, magnitude)),
+            _ => unreachable!("not a speed update: {instruction:?}"),
+        }
+    }
+
+    /// schedule a speed update, returning the instruction to issue immediately, if any
+    pub fn schedule_speed(&mut self, instruction: Instruction) -> Option<Instruction> {
+        if !self.config.coalesce_speed {
+            return self.filter_redundant(instruction);
+        }
+        let (node, _) = Self::node_speed(&instruction);
+        match (self.pending.iter_mut()).find(|pending| Self::node_speed(pending).0 == node) {
+            Some(pending) => {
+                *pending = instruction;
+                self.statistics.coalesced_speed += 1;
+            }
+            None => self.pending.push(instruction),
+        }
+        None
+    }
+
+    /// take the buffered speed updates that need to be issued before any other instruction
+    pub fn flush(&mut self) -> Vec<Instruction> {
+        let pending = std::mem::take(&mut self.pending);
+        pending
+            .into_iter()
+            .filter_map(|instruction| self.filter_redundant(instruction))
+            .collect()
+    }
+
+    fn filter_redundant(&mut self, instruction: Instruction) -> Option<Instruction> {
+        let (node, speed) = Self::node_speed(&instruction);
+        if self.config.skip_redundant_speed && self.known_speeds.get(&node) == Some(&speed) {
+            self.statistics.redundant_speed += 1;
+            return None;
+        }
+        Some(instruction)
+    }
+
+    /// a node gains vertices at the default speed
+    fn assign(&mut self, node: NodeIndex) {
+        let default_speed = (CompactGrowState::Grow, 1);
+        if self.assigned.insert(node) || self.known_speeds.get(&node) == Some(&default_speed) {
+            self.known_speeds.insert(node, default_speed);
+        } else {
+            self.known_speeds.remove(&node);
+        }
+    }
+
+    /// track the speed of the nodes after an instruction is issued; the propagated vertices always take the speed of
+    /// a growing node and the shrinking vertices only leave their node, so neither breaks the recorded speeds
+    pub fn executed(&mut self, instruction: &Instruction) {
+        match *instruction {
+            Instruction::SetSpeed { .. } | Instruction::SetSpeedWithMagnitude { .. } => {
+                let (node, speed) = Self::node_speed(instruction);
+                self.known_speeds.insert(node, speed);
+            }
+            Instruction::SetBlossom { node, blossom } => {
+                self.known_speeds.remove(&node);
+                self.assign(blossom);
+            }
+            Instruction::AddDefectVertex { node, .. } => self.assign(node),
+            Instruction::RemoveDefectVertex { node, .. } | Instruction::ReleaseNode { node } => {
+                self.known_speeds.remove(&node);
+            }
+            Instruction::LoadDefectsBitmap { base_node, bitmap, .. } => {
+                for node in base_node..base_node + bitmap.count_ones() as NodeIndex {
+                    self.assign(node);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwpm_solver::*;
+    use crate::resources::*;
+    use fusion_blossom::cli::ExampleCodeType;
+    use fusion_blossom::mwpm_solver::*;
+    use serde_json::json;
+
+    #[test]
+    fn dual_module_comb_schedule_coalesce() {
+        // cargo test dual_module_comb_schedule_coalesce -- --nocapture
+        let mut scheduler = InstructionScheduler::new(serde_json::from_value(json!({})).unwrap());
+        scheduler.executed(&Instruction::AddDefectVertex { vertex: 3, node: 0 });
+        scheduler.executed(&Instruction::AddDefectVertex { vertex: 5, node: 1 });
+        let set_speed = |node, speed| Instruction::SetSpeed { node, speed };
+        assert_eq!(scheduler.schedule_speed(set_speed(0, CompactGrowState::Stay)), None);
+        assert_eq!(scheduler.schedule_speed(set_speed(1, CompactGrowState::Stay)), None);
+        assert_eq!(scheduler.schedule_speed(set_speed(0, CompactGrowState::Grow)), None);
+        // node 0 is back to its current speed
+        let issued = scheduler.flush();
+        assert_eq!(issued, vec![set_speed(1, CompactGrowState::Stay)]);
+        issued.iter().for_each(|instruction| scheduler.executed(instruction));
+        assert_eq!(scheduler.schedule_speed(set_speed(1, CompactGrowState::Stay)), None);
+        assert!(scheduler.flush().is_empty());
+        // a blossom absorbing node 1 makes its speed unknown when it is expanded
+        scheduler.executed(&Instruction::SetBlossom { node: 1, blossom: 8 });
+        scheduler.executed(&Instruction::SetBlossom { node: 1, blossom: 1 });
+        assert_eq!(scheduler.schedule_speed(set_speed(1, CompactGrowState::Grow)), None);
+        assert_eq!(scheduler.flush().len(), 1);
+        assert_eq!(
+            (scheduler.statistics.coalesced_speed, scheduler.statistics.redundant_speed),
+            (1, 2)
+        );
+    }
+
+    /// compare the instructions of the canonical loop and the scheduled one on circuit-level noise
+    #[test]
+    fn dual_module_comb_schedule_circuit_level() {
+        // cargo test dual_module_comb_schedule_circuit_level -- --nocapture
+        let mut code = ExampleCodeType::CircuitLevelPlanarCode.build(5, 0.005, 5, 500, json!({}));
+        let graph = MicroBlossomSingle::new_code(code.as_ref());
+        let mut canonical = SolverEmbeddedComb::new(graph.clone(), json!({}));
+        let mut scheduled = SolverEmbeddedComb::new(graph, json!({ "dual": { "schedule": {} } }));
+        let mut counts = [BTreeMap::<String, usize>::new(), BTreeMap::<String, usize>::new()];
+        for seed in 0..200 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            let mut matching_weights = vec![];
+            for (solver, counts) in [&mut canonical, &mut scheduled].into_iter().zip(counts.iter_mut()) {
+                solver.solve(&syndrome_pattern);
+                let result = solver.result();
+                for (name, count) in result.instruction_counts.unwrap() {
+                    *counts.entry(name).or_default() += count;
+                }
+                matching_weights.push(result.matching_weight);
+                solver.clear();
+            }
+            // the fused obstacles may resolve the conflicts in a different order, but the matching is still optimal
+            assert_eq!(matching_weights[0], matching_weights[1], "seed {seed}");
+        }
+        let names: BTreeSet<&String> = counts.iter().flat_map(|counts| counts.keys()).collect();
+        println!("{:<26}{:>10}{:>10}", "instruction", "canonical", "scheduled");
+        for name in names {
+            let [before, after] = [&counts[0], &counts[1]].map(|counts| counts.get(name).cloned().unwrap_or(0));
+            println!("{name:<26}{before:>10}{after:>10}");
+        }
+        let [before, after] = counts.map(|counts| counts.values().sum::<usize>());
+        println!("{:<26}{before:>10}{after:>10}", "total");
+        let statistics = &scheduled.dual_module.driver.driver.scheduler.as_ref().unwrap().statistics;
+        println!("{}", json!(statistics));
+        assert!(statistics.fused_grow > 0);
+        assert!(after < before);
+    }
+}
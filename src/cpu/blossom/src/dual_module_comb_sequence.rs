@@ -0,0 +1,124 @@
+//! Sequenced Bus of the Combinatorial Dual Module
+//!
+//! The bus between the driver and the hardware may lose or duplicate a message. With `sequence_check` set, every
+//! instruction to the combinatorial dual module goes through a [`SequencedBus`]: it carries a sequence number (see
+//! [`MessageSequencer`]), the hardware executes it at most once (see [`SequenceFilter`]), and the driver recognizes a
+//! stale readout by its sequence number and retransmits the instruction or aborts the shot, following
+//! [`DesyncRecovery`]. The failures are injected with the probabilities in [`SequenceCheckConfig`].
+//!
+//! Enable it with `{ "dual": { "sequence_check": { "drop_response": 0.1, "seed": 123 } } }`.
+//!
+
+use crate::dual_module_comb::*;
+use micro_blossom_nostd::interface::*;
+use micro_blossom_nostd::message_sequence::*;
+use rand::Rng;
+use rand_xoshiro::rand_core::SeedableRng;
+use rand_xoshiro::Xoroshiro128StarStar;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SequenceCheckConfig {
+    #[serde(default = "sequence_check_config_default::recovery")]
+    pub recovery: DesyncRecovery,
+    /// the maximum number of retransmissions of a single instruction when `recovery` is `Resync`
+    #[serde(default = "sequence_check_config_default::max_retries")]
+    pub max_retries: usize,
+    /// failure injection: the probability that an instruction never reaches the hardware
+    #[serde(default = "Default::default")]
+    pub drop_instruction: f64,
+    /// failure injection: the probability that the response (interrupt) is lost and the stale readout is read
+    #[serde(default = "Default::default")]
+    pub drop_response: f64,
+    /// failure injection: the probability that an instruction is delivered twice
+    #[serde(default = "Default::default")]
+    pub duplicate_instruction: f64,
+    #[serde(default = "Default::default")]
+    pub seed: u64,
+}
+
+pub mod sequence_check_config_default {
+    use micro_blossom_nostd::message_sequence::DesyncRecovery;
+    pub fn recovery() -> DesyncRecovery {
+        DesyncRecovery::Resync
+    }
+    pub fn max_retries() -> usize {
+        10
+    }
+}
+
+pub struct SequencedBus {
+    pub config: SequenceCheckConfig,
+    pub sequencer: MessageSequencer,
+    pub sequence_filter: SequenceFilter,
+    /// the readout of the hardware: the sequence number of the last executed instruction and its response
+    readout: (CompactSequence, CompactObstacle),
+    fault_rng: Xoroshiro128StarStar,
+}
+
+impl SequencedBus {
+    pub fn new(config: SequenceCheckConfig) -> Self {
+        Self {
+            sequencer: MessageSequencer::new(),
+            sequence_filter: SequenceFilter::new(),
+            readout: (CompactSequence::MAX, CompactObstacle::None),
+            fault_rng: Xoroshiro128StarStar::seed_from_u64(config.seed),
+            config,
+        }
+    }
+
+    /// send an instruction to the hardware, which executes it with `execute`, until its response is read; `None` if
+    /// the shot is aborted or the retransmissions are exhausted
+    pub fn execute(
+        &mut self,
+        instruction: Instruction,
+        mut execute: impl FnMut(Instruction) -> CompactObstacle,
+    ) -> Option<CompactObstacle> {
+        let sequence = self.sequencer.send();
+        for _ in 0..=self.config.max_retries {
+            let stale_readout = self.readout.clone();
+            let (received, response) = if self.transmit(&instruction, sequence, &mut execute) {
+                self.readout.clone()
+            } else {
+                stale_readout
+            };
+            match self.sequencer.check(received) {
+                Ok(()) => return Some(response),
+                Err(_) if self.config.recovery == DesyncRecovery::Abort => break,
+                Err(_) => {}
+            }
+        }
+        None
+    }
+
+    /// model an unreliable bus between the driver and the hardware according to the failure injection config,
+    /// returning whether the driver is notified of the updated readout
+    fn transmit(
+        &mut self,
+        instruction: &Instruction,
+        sequence: CompactSequence,
+        execute: &mut impl FnMut(Instruction) -> CompactObstacle,
+    ) -> bool {
+        if self.fault_rng.gen::<f64>() < self.config.drop_instruction {
+            return false;
+        }
+        let deliveries = if self.fault_rng.gen::<f64>() < self.config.duplicate_instruction {
+            2
+        } else {
+            1
+        };
+        for _ in 0..deliveries {
+            // a retransmitted or duplicated instruction is not executed again, the readout remains the same
+            if self.sequence_filter.accept(sequence) {
+                self.readout = (sequence, execute(instruction.clone()));
+            }
+        }
+        self.fault_rng.gen::<f64>() >= self.config.drop_response
+    }
+
+    pub fn reset_profiler(&mut self) {
+        self.sequencer.statistics = SequenceStatistics::default();
+        self.sequence_filter.duplicated = 0;
+    }
+}
@@ -0,0 +1,228 @@
+//! VCD Waveform of the Combinatorial Dual Module
+//!
+//! Dump the registers of every vertex and edge after every instruction into a Value Change Dump, so that the
+//! execution of the software model can be viewed in GTKWave next to the RTL simulation of the same shot. The time
+//! `t` holds the registers after the instruction `t` (starting from 0), and the `opcode` signal is the index of the
+//! instruction in [`INSTRUCTION_NAMES`]. The widths follow the hardware (see [`generated_bits`]), and a node index
+//! of `None` is all ones like `IndexNone` in the RTL.
+//!
+//! Enable it with `{ "dual": { "vcd": { "filename": "shot.vcd" } } }`.
+//!
+
+use crate::dual_module_comb::*;
+use crate::dual_module_comb_edge::*;
+use crate::dual_module_comb_vertex::*;
+use crate::hardware_config_check::*;
+use crate::resources::*;
+use fusion_blossom::util::*;
+use micro_blossom_nostd::util::*;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VcdConfig {
+    pub filename: String,
+}
+
+/// the `opcode` signal is the index in this list, in the order of [`Instruction`]
+pub const INSTRUCTION_NAMES: &[&str] = &[
+    "set_speed",
+    "set_speed_with_magnitude",
+    "set_blossom",
+    "add_defect_vertex",
+    "remove_defect_vertex",
+    "load_defects_bitmap",
+    "find_obstacle",
+    "grow",
+    "grow_find_obstacle",
+    "load_defects_external",
+    "freeze_vertex_range",
+    "set_edge_weight",
+    "release_node",
+];
+
+pub struct VcdDumper {
+    writer: BufWriter<File>,
+    /// the identifier, the width and the last dumped value of every variable, in the order of declaration
+    variables: Vec<(String, usize, Option<u64>)>,
+    vertex_bits: usize,
+    time: u64,
+}
+
+/// the printable identifier of the variable, in base 94 from `!`
+fn identifier(mut index: usize) -> String {
+    let mut identifier = String::new();
+    loop {
+        identifier.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return identifier;
+        }
+        index -= 1;
+    }
+}
+
+impl VcdDumper {
+    pub fn new(config: &VcdConfig, graph: &MicroBlossomSingle) -> Result<Self> {
+        let (vertex_bits, weight_bits) = generated_bits(graph);
+        let mut writer = BufWriter::new(File::create(&config.filename)?);
+        let mut variables = vec![];
+        let mut declare = |writer: &mut BufWriter<File>, name: &str, width: usize| -> Result<()> {
+            let id = identifier(variables.len());
+            writeln!(writer, "$var wire {width} {id} {name} $end")?;
+            variables.push((id, width, None));
+            Ok(())
+        };
+        writeln!(writer, "$version micro-blossom {} $end", env!("CARGO_PKG_VERSION"))?;
+        writeln!(writer, "$timescale 1ns $end")?;
+        writeln!(writer, "$scope module dual_module $end")?;
+        declare(&mut writer, "opcode", log2_up(INSTRUCTION_NAMES.len()))?;
+        for vertex_index in 0..graph.vertex_num {
+            let grown_bits = log2_up(graph.vertex_max_growth[vertex_index] as usize + 1).max(weight_bits);
+            writeln!(writer, "$scope module vertex_{vertex_index} $end")?;
+            declare(&mut writer, "speed", 2)?;
+            declare(&mut writer, "speed_magnitude", log2_up(MAX_SPEED_MAGNITUDE as usize + 1))?;
+            declare(&mut writer, "grown", grown_bits)?;
+            declare(&mut writer, "is_virtual", 1)?;
+            declare(&mut writer, "is_defect", 1)?;
+            declare(&mut writer, "node_index", vertex_bits)?;
+            declare(&mut writer, "root_index", vertex_bits)?;
+            declare(&mut writer, "is_frozen", 1)?;
+            writeln!(writer, "$upscope $end")?;
+        }
+        for edge_index in 0..graph.weighted_edges.len() {
+            writeln!(writer, "$scope module edge_{edge_index} $end")?;
+            declare(&mut writer, "weight", weight_bits)?;
+            writeln!(writer, "$upscope $end")?;
+        }
+        writeln!(writer, "$upscope $end")?;
+        writeln!(writer, "$enddefinitions $end")?;
+        Ok(Self {
+            writer,
+            variables,
+            vertex_bits,
+            time: 0,
+        })
+    }
+
+    fn values(&self, instruction: &Instruction, vertices: &[Vertex], edges: &[Edge]) -> Vec<u64> {
+        let index_none = (1u64 << self.vertex_bits) - 1;
+        let node_value = |node_index: Option<NodeIndex>| match node_index {
+            Some(VIRTUAL_NODE_INDEX) | None => index_none,
+            Some(node_index) => node_index as u64,
+        };
+        let opcode = INSTRUCTION_NAMES.iter().position(|&name| name == instruction.name()).unwrap();
+        let mut values = vec![opcode as u64];
+        for vertex in vertices.iter() {
+            let registers = &vertex.registers;
+            values.extend([
+                registers.speed as u64,
+                registers.speed_magnitude as u64,
+                registers.grown as u64,
+                registers.is_virtual as u64,
+                registers.is_defect as u64,
+                node_value(registers.node_index),
+                node_value(registers.root_index),
+                registers.is_frozen as u64,
+            ]);
+        }
+        values.extend(edges.iter().map(|edge| edge.registers.weight as u64));
+        values
+    }
+
+    /// dump the values that changed since the last instruction
+    pub fn dump(&mut self, instruction: &Instruction, vertices: &[Vertex], edges: &[Edge]) -> Result<()> {
+        let values = self.values(instruction, vertices, edges);
+        writeln!(self.writer, "#{}", self.time)?;
+        if self.time == 0 {
+            writeln!(self.writer, "$dumpvars")?;
+        }
+        for ((id, width, last_value), value) in self.variables.iter_mut().zip(values) {
+            if *last_value == Some(value) {
+                continue;
+            }
+            *last_value = Some(value);
+            if *width == 1 {
+                writeln!(self.writer, "{value}{id}")?;
+            } else {
+                writeln!(self.writer, "b{value:b} {id}")?;
+            }
+        }
+        if self.time == 0 {
+            writeln!(self.writer, "$end")?;
+        }
+        self.time += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl CombObserver for VcdDumper {
+    fn name(&self) -> &'static str {
+        "vcd"
+    }
+    fn after_execute(&mut self, driver: &mut DualModuleCombDriver) {
+        self.dump(&driver.instruction, &driver.vertices, &driver.edges).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwpm_solver::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::PrimalDualSolver;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    /// replaying the value changes recovers the final registers
+    #[test]
+    fn dual_module_comb_vcd_replay() {
+        // cargo test dual_module_comb_vcd_replay -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        code.set_defect_vertices(&[16, 17, 18, 26, 34, 39]);
+        let filename = std::env::temp_dir().join("dual_module_comb_vcd_replay.vcd");
+        let filename = filename.to_str().unwrap();
+        let config = json!({ "dual": { "vcd": { "filename": filename } } });
+        let mut solver = SolverEmbeddedComb::new(MicroBlossomSingle::new_code(&code), config);
+        solver.solve(&code.get_syndrome());
+        let driver = &mut solver.dual_module.driver.driver;
+        driver.observer_mut::<VcdDumper>().unwrap().flush().unwrap();
+        // parse the declarations and replay the value changes
+        let (mut scopes, mut names, mut values) = (vec![], BTreeMap::new(), BTreeMap::new());
+        let mut timestamps = 0;
+        for line in std::fs::read_to_string(filename).unwrap().lines() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens[0] {
+                "$scope" => scopes.push(tokens[2]),
+                "$upscope" => {
+                    scopes.pop();
+                }
+                "$var" => {
+                    names.insert(tokens[3].to_string(), format!("{}.{}", scopes.last().unwrap(), tokens[4]));
+                }
+                token if token.starts_with('#') => timestamps += 1,
+                token if token.starts_with('b') => {
+                    values.insert(names[tokens[1]].clone(), u64::from_str_radix(&token[1..], 2).unwrap());
+                }
+                token if token.starts_with(['0', '1']) => {
+                    values.insert(names[&token[1..]].clone(), token[..1].parse().unwrap());
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(timestamps, driver.instruction_counts.values().sum::<usize>());
+        for vertex in driver.vertices.iter() {
+            let value = |name: &str| values[&format!("vertex_{}.{name}", vertex.vertex_index)];
+            assert_eq!(value("grown"), vertex.registers.grown as u64);
+            assert_eq!(value("is_defect"), vertex.registers.is_defect as u64);
+            assert_eq!(value("speed"), vertex.registers.speed as u64);
+        }
+        assert_eq!(values["dual_module.opcode"], 5, "the last instruction is `find_obstacle`");
+    }
+}
@@ -2,6 +2,7 @@ use crate::dual_module_comb::*;
 use fusion_blossom::util::*;
 use micro_blossom_nostd::interface::*;
 use micro_blossom_nostd::util::*;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::cell::{Ref, RefCell};
 
@@ -14,6 +15,8 @@ pub struct Vertex {
     pub signals: VertexCombSignals,
     /// loading a layer of defects
     pub layer_id: Option<usize>,
+    /// a vertex without any incident edge never changes its state and is skipped when scanning the vertices
+    pub is_inert: bool,
 }
 
 pub struct VirtualMatchingVertexProfile {
@@ -21,14 +24,19 @@ pub struct VirtualMatchingVertexProfile {
 }
 
 /// the persistent state of the vertex
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VertexRegisters {
     pub speed: CompactGrowState,
+    /// the magnitude of the speed, 1 unless set by `SetSpeedWithMagnitude`
+    pub speed_magnitude: CompactSpeed,
     pub grown: Weight,
     pub is_virtual: bool,
     pub is_defect: bool,
+    /// always full width, also with the `u16_interface_index` feature, see [`crate::dual_module_comb::compact_index`]
     pub node_index: Option<NodeIndex>,
     pub root_index: Option<NodeIndex>,
+    /// a frozen vertex belongs to a committed region: it keeps its state and never reports any obstacle
+    pub is_frozen: bool,
 }
 
 /// combinatorial signals of the vertex, should be invalidated whenever the registers are updated
@@ -46,11 +54,13 @@ pub struct VertexCombSignals {
 pub struct PropagatingPeer {
     pub node_index: Option<NodeIndex>,
     pub root_index: Option<NodeIndex>,
+    pub speed_magnitude: CompactSpeed,
 }
 
 #[derive(Debug, Clone)]
 pub struct ShadowNode {
     pub speed: CompactGrowState,
+    pub speed_magnitude: CompactSpeed,
     pub node_index: Option<NodeIndex>,
     pub root_index: Option<NodeIndex>,
     pub is_virtual: bool,
@@ -60,13 +70,25 @@ impl VertexRegisters {
     pub fn new(is_virtual: bool) -> Self {
         Self {
             speed: CompactGrowState::Stay,
+            speed_magnitude: 1,
             grown: 0,
             is_virtual,
             is_defect: false,
             node_index: if is_virtual { Some(VIRTUAL_NODE_INDEX) } else { None },
             root_index: if is_virtual { Some(VIRTUAL_NODE_INDEX) } else { None },
+            is_frozen: false,
         }
     }
+
+    pub fn signed_speed(&self) -> Weight {
+        Weight::from(self.speed.with_magnitude(self.speed_magnitude))
+    }
+}
+
+impl ShadowNode {
+    pub fn signed_speed(&self) -> Weight {
+        Weight::from(self.speed.with_magnitude(self.speed_magnitude))
+    }
 }
 
 impl VertexCombSignals {
@@ -87,6 +109,7 @@ impl Vertex {
     pub fn new(vertex_index: VertexIndex, edge_indices: Vec<EdgeIndex>, is_virtual: bool) -> Self {
         Self {
             vertex_index,
+            is_inert: edge_indices.is_empty(),
             edge_indices,
             offloading_indices: vec![],
             default_is_virtual: is_virtual,
@@ -148,22 +171,35 @@ impl Vertex {
                 Instruction::SetSpeed { node, speed } => {
                     if self.registers.node_index == Some(*node) {
                         state.speed = *speed;
+                        state.speed_magnitude = 1;
+                    }
+                }
+                Instruction::SetSpeedWithMagnitude { node, speed, magnitude } => {
+                    if self.registers.node_index == Some(*node) {
+                        assert!(
+                            (1..=MAX_SPEED_MAGNITUDE).contains(magnitude),
+                            "speed magnitude {magnitude} out of range"
+                        );
+                        state.speed = *speed;
+                        state.speed_magnitude = *magnitude;
                     }
                 }
                 Instruction::SetBlossom { node, blossom } => {
                     if self.registers.node_index == Some(*node) || self.registers.root_index == Some(*node) {
                         state.node_index = Some(*blossom);
                         state.speed = CompactGrowState::Grow;
+                        state.speed_magnitude = 1;
                     }
                 }
-                Instruction::Grow { length } => {
+                Instruction::Grow { length } | Instruction::GrowFindObstacle { length, .. } => {
                     // growth may be disabled if it's pre-matched or it's virtual
                     let mut disable_growth = self.get_offloading_stalled(dual_module);
                     if self.layer_id.is_some() {
                         disable_growth |= state.is_virtual;
                     }
+                    disable_growth |= state.is_frozen;
                     if !disable_growth {
-                        state.grown = self.registers.grown + Weight::from(self.registers.speed) * length;
+                        state.grown = self.registers.grown + self.registers.signed_speed() * length;
                         assert!(
                             state.grown >= 0,
                             "vertex {} has negative grown value {}",
@@ -176,10 +212,38 @@ impl Vertex {
                     if self.vertex_index == *vertex {
                         state.is_defect = true;
                         state.speed = CompactGrowState::Grow;
+                        state.speed_magnitude = 1;
                         state.root_index = Some(*node);
                         state.node_index = Some(*node);
                     }
                 }
+                Instruction::RemoveDefectVertex { vertex, node } => {
+                    // the whole region of the node retracts together with the defect vertex
+                    if self.vertex_index == *vertex || self.registers.root_index == Some(*node) {
+                        state = VertexRegisters {
+                            is_frozen: state.is_frozen,
+                            ..VertexRegisters::new(state.is_virtual)
+                        };
+                    }
+                }
+                Instruction::LoadDefectsBitmap {
+                    base_vertex,
+                    base_node,
+                    bitmap,
+                } => {
+                    if self.vertex_index >= *base_vertex && self.vertex_index < *base_vertex + 64 {
+                        let offset = self.vertex_index - *base_vertex;
+                        if (bitmap >> offset) & 1 == 1 {
+                            // the node index is the number of defects before this vertex in the bitmap
+                            let node = *base_node + (bitmap & ((1u64 << offset) - 1)).count_ones() as NodeIndex;
+                            state.is_defect = true;
+                            state.speed = CompactGrowState::Grow;
+                            state.speed_magnitude = 1;
+                            state.root_index = Some(node);
+                            state.node_index = Some(node);
+                        }
+                    }
+                }
                 Instruction::LoadDefectsExternal { time, channel: _ } => {
                     if let Some(layer_id) = self.layer_id {
                         if &layer_id == time {
@@ -188,15 +252,30 @@ impl Vertex {
                         }
                     }
                 }
+                Instruction::FreezeVertexRange { begin, end } => {
+                    if (*begin..*end).contains(&self.vertex_index) {
+                        state.is_frozen = true;
+                    }
+                }
+                Instruction::ReleaseNode { node } => {
+                    // the frozen vertex keeps its growth, only the node index is given back for a new defect
+                    if state.is_frozen && self.registers.root_index == Some(*node) {
+                        state.node_index = None;
+                        state.root_index = None;
+                    }
+                }
                 _ => {}
             }
+            if state.is_frozen {
+                state.speed = CompactGrowState::Stay;
+            }
             state
         })
     }
 
     pub fn get_is_propagating(&self, dual_module: &DualModuleCombDriver) -> bool {
         let state = self.get_post_execute_state(dual_module);
-        !state.is_virtual && state.speed == CompactGrowState::Grow
+        !state.is_virtual && !state.is_frozen && state.speed == CompactGrowState::Grow
     }
 
     pub fn get_propagating_peer(&self, dual_module: &DualModuleCombDriver) -> Ref<'_, Option<PropagatingPeer>> {
@@ -214,6 +293,7 @@ impl Vertex {
                     return Some(PropagatingPeer {
                         node_index: peer_post_execute_state.node_index,
                         root_index: peer_post_execute_state.root_index,
+                        speed_magnitude: peer_post_execute_state.speed_magnitude,
                     });
                 }
             }
@@ -225,15 +305,17 @@ impl Vertex {
         referenced_signal!(self.signals.post_update_state, || {
             let mut state = self.get_post_execute_state(dual_module).clone();
             let propagating_peer = self.get_propagating_peer(dual_module);
-            if !state.is_defect && !state.is_virtual && state.grown == 0 {
+            if !state.is_defect && !state.is_virtual && !state.is_frozen && state.grown == 0 {
                 if let Some(peer) = propagating_peer.clone() {
                     state.node_index = peer.node_index;
                     state.root_index = peer.root_index;
                     state.speed = CompactGrowState::Grow;
+                    state.speed_magnitude = peer.speed_magnitude;
                 } else {
                     state.node_index = None;
                     state.root_index = None;
                     state.speed = CompactGrowState::Stay;
+                    state.speed_magnitude = 1;
                 }
             }
             state
@@ -248,6 +330,7 @@ impl Vertex {
                 node_index: state.node_index,
                 root_index: state.root_index,
                 speed: state.speed,
+                speed_magnitude: state.speed_magnitude,
                 is_virtual: state.is_virtual,
             };
             if state.speed == CompactGrowState::Shrink && state.grown == 0 {
@@ -255,6 +338,7 @@ impl Vertex {
                     shadow_node.node_index = peer.node_index;
                     shadow_node.root_index = peer.root_index;
                     shadow_node.speed = CompactGrowState::Grow;
+                    shadow_node.speed_magnitude = peer.speed_magnitude;
                 }
             }
             // compile-time condition
@@ -277,7 +361,10 @@ impl Vertex {
         referenced_signal!(self.signals.response, || {
             let post_update_state = self.get_post_update_state(dual_module);
             if post_update_state.speed == CompactGrowState::Shrink {
-                let length = post_update_state.grown.try_into().unwrap();
+                // the growth rounds down and the driver shrinks the rest alone, see
+                // [`DualModuleCombDriver::fractional_step`]
+                let magnitude = Weight::from(post_update_state.speed_magnitude);
+                let length = dual_module.compact_length(post_update_state.grown / magnitude);
                 debug_assert!(length >= 0, "vertex {} report negative grow length", self.vertex_index);
                 return CompactObstacle::GrowLength { length };
             }
@@ -296,11 +383,13 @@ impl VertexRegisters {
     pub fn snapshot(&self) -> serde_json::Value {
         json!({
             "speed": format!("{:?}", self.speed),
+            "speed_magnitude": self.speed_magnitude,
             "grown": self.grown,
             "is_virtual": self.is_virtual,
             "is_defect": self.is_defect,
             "node_index": self.node_index,
             "root_index": self.root_index,
+            "is_frozen": self.is_frozen,
         })
     }
 }
@@ -309,6 +398,7 @@ impl ShadowNode {
     pub fn snapshot(&self) -> serde_json::Value {
         json!({
             "speed": format!("{:?}", self.speed),
+            "speed_magnitude": self.speed_magnitude,
             "node_index": self.node_index,
             "root_index": self.root_index,
         })
@@ -320,6 +410,7 @@ impl PropagatingPeer {
         json!({
             "node_index": self.node_index,
             "root_index": self.root_index,
+            "speed_magnitude": self.speed_magnitude,
         })
     }
 }
@@ -0,0 +1,249 @@
+//! Dual Module Jitter
+//!
+//! A wrapper driver that delays every response of the inner dual driver by a random but bounded time, to emulate
+//! bus contention and clock-domain crossing jitter between the controller and the dual accelerator. Instructions
+//! without a response (e.g., `set_speed`) are posted writes and are not delayed. Since the delays never change the
+//! order of the responses, the decoding result must be exactly the same as without jitter; any difference reveals
+//! a hidden timing assumption in the controller or the primal module. The latency measured by the benchmark or
+//! [`crate::defect_latency`] then shows how sensitive the latency distribution is to the jitter.
+//!
+//! The jitter is configured by the `jitter` field of the dual configuration, e.g.,
+//! `{"dual":{"jitter":{"max_delay":2e-6,"spike_probability":0.01}}}`; the other fields are passed to the inner
+//! driver.
+//!
+
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use fusion_blossom::visualize::*;
+use micro_blossom_nostd::dual_driver_tracked::*;
+use micro_blossom_nostd::dual_module_stackless::*;
+use micro_blossom_nostd::interface::*;
+use micro_blossom_nostd::util::*;
+use rand::Rng;
+use rand_xoshiro::rand_core::SeedableRng;
+use rand_xoshiro::Xoroshiro128StarStar;
+use serde::*;
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JitterConfig {
+    /// the minimum delay of every response, in seconds
+    #[serde(default = "jitter_config_default::min_delay")]
+    pub min_delay: f64,
+    /// the maximum delay of a response without spike, in seconds; the delay is uniformly distributed
+    #[serde(default = "jitter_config_default::max_delay")]
+    pub max_delay: f64,
+    /// the probability that a response is further delayed by `spike_delay`, e.g., when the bus is occupied
+    #[serde(default = "jitter_config_default::spike_probability")]
+    pub spike_probability: f64,
+    #[serde(default = "jitter_config_default::spike_delay")]
+    pub spike_delay: f64,
+    #[serde(default = "jitter_config_default::seed")]
+    pub seed: u64,
+}
+
+pub mod jitter_config_default {
+    pub fn min_delay() -> f64 {
+        0.
+    }
+    pub fn max_delay() -> f64 {
+        1e-6
+    }
+    pub fn spike_probability() -> f64 {
+        0.
+    }
+    pub fn spike_delay() -> f64 {
+        1e-5
+    }
+    pub fn seed() -> u64 {
+        0
+    }
+}
+
+impl Default for JitterConfig {
+    fn default() -> Self {
+        serde_json::from_value(json!({})).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JitterStatistics {
+    /// the number of delayed responses
+    pub responses: usize,
+    pub spikes: usize,
+    /// the sum of the injected delays, in seconds
+    pub total_delay: f64,
+    pub max_delay: f64,
+}
+
+pub struct DualModuleJitterDriver<D: SolverTrackedDual> {
+    pub driver: D,
+    pub config: JitterConfig,
+    rng: Xoroshiro128StarStar,
+    pub statistics: JitterStatistics,
+}
+
+impl<D: SolverTrackedDual> DualModuleJitterDriver<D> {
+    pub fn new(driver: D, config: JitterConfig) -> Self {
+        assert!(
+            0. <= config.min_delay && config.min_delay <= config.max_delay,
+            "invalid delay range [{}, {}]",
+            config.min_delay,
+            config.max_delay
+        );
+        assert!((0. ..=1.).contains(&config.spike_probability));
+        assert!(config.spike_delay >= 0.);
+        Self {
+            driver,
+            rng: Xoroshiro128StarStar::seed_from_u64(config.seed),
+            config,
+            statistics: JitterStatistics::default(),
+        }
+    }
+
+    /// busy wait for a random delay, because sleeping is far too coarse for sub-microsecond jitter
+    fn delay_response(&mut self) {
+        let mut delay = self.rng.gen_range(self.config.min_delay..=self.config.max_delay);
+        if self.rng.gen::<f64>() < self.config.spike_probability {
+            delay += self.config.spike_delay;
+            self.statistics.spikes += 1;
+        }
+        self.statistics.responses += 1;
+        self.statistics.total_delay += delay;
+        self.statistics.max_delay = self.statistics.max_delay.max(delay);
+        let deadline = Instant::now() + Duration::from_secs_f64(delay);
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+impl<D: SolverTrackedDual> SolverTrackedDual for DualModuleJitterDriver<D> {
+    fn new_from_graph_config(graph: MicroBlossomSingle, config: serde_json::Value) -> Self {
+        Self::try_new_from_graph_config(graph, config).unwrap_or_else(|error| panic!("{error}"))
+    }
+    fn try_new_from_graph_config(graph: MicroBlossomSingle, mut config: serde_json::Value) -> Result<Self, DualDriverError> {
+        let jitter_config = match config.as_object_mut().and_then(|config| config.remove("jitter")) {
+            Some(jitter) => serde_json::from_value(jitter).map_err(|error| DualDriverError::InvalidConfig {
+                message: format!("jitter: {error}"),
+            })?,
+            None => JitterConfig::default(),
+        };
+        Ok(Self::new(D::try_new_from_graph_config(graph, config)?, jitter_config))
+    }
+    fn reset_profiler(&mut self) {
+        self.driver.reset_profiler();
+        self.statistics = JitterStatistics::default();
+    }
+    fn generate_profiler_report(&self) -> serde_json::Value {
+        let mut report = self.driver.generate_profiler_report();
+        report["jitter"] = json!(self.statistics);
+        report
+    }
+    forward_solver_tracked_dual!(self, self.driver, self.driver;
+        fuse_layer, get_pre_matchings, instruction_counts, clock_cycles, hardware_counters, supports_dual_readback,
+        weight_overflowed, desynchronized, freeze_vertex_range, set_edge_weight, remove_defect, release_nodes,
+        finish_shot,
+    );
+}
+
+impl<D: SolverTrackedDual> DualStacklessDriver for DualModuleJitterDriver<D> {
+    fn reset(&mut self) {
+        self.driver.reset();
+    }
+    fn set_speed(&mut self, is_blossom: bool, node: CompactNodeIndex, speed: CompactGrowState) {
+        self.driver.set_speed(is_blossom, node, speed);
+    }
+    fn set_speed_with_magnitude(
+        &mut self,
+        is_blossom: bool,
+        node: CompactNodeIndex,
+        speed: CompactGrowState,
+        magnitude: CompactSpeed,
+    ) {
+        self.driver.set_speed_with_magnitude(is_blossom, node, speed, magnitude);
+    }
+    fn max_speed_magnitude(&self) -> CompactSpeed {
+        self.driver.max_speed_magnitude()
+    }
+    fn set_blossom(&mut self, node: CompactNodeIndex, blossom: CompactNodeIndex) {
+        self.driver.set_blossom(node, blossom);
+    }
+    fn find_obstacle(&mut self) -> (CompactObstacle, CompactWeight) {
+        let response = self.driver.find_obstacle();
+        self.delay_response();
+        response
+    }
+    fn add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) {
+        self.driver.add_defect(vertex, node);
+    }
+    fn add_defects_bitmap(&mut self, base_vertex: CompactVertexIndex, base_node: CompactNodeIndex, bitmap: u64) {
+        self.driver.add_defects_bitmap(base_vertex, base_node, bitmap);
+    }
+    fn read_vertex_grown(&mut self, vertex: CompactVertexIndex) -> Option<CompactWeight> {
+        let response = self.driver.read_vertex_grown(vertex);
+        self.delay_response();
+        response
+    }
+    fn on_blossom_created(&mut self, blossom: CompactNodeIndex) {
+        self.driver.on_blossom_created(blossom);
+    }
+    fn on_blossom_expanded(&mut self, blossom: CompactNodeIndex) {
+        self.driver.on_blossom_expanded(blossom);
+    }
+    fn on_blossom_absorbed_into_blossom(&mut self, child: CompactNodeIndex) {
+        self.driver.on_blossom_absorbed_into_blossom(child);
+    }
+}
+
+impl<D: SolverTrackedDual> DualTrackedDriver for DualModuleJitterDriver<D> {
+    fn find_conflict(&mut self, maximum_growth: CompactWeight) -> (CompactObstacle, CompactWeight) {
+        let response = self.driver.find_conflict(maximum_growth);
+        self.delay_response();
+        response
+    }
+}
+
+impl<D: SolverTrackedDual> FusionVisualizer for DualModuleJitterDriver<D> {
+    fn snapshot(&self, abbrev: bool) -> serde_json::Value {
+        self.driver.snapshot(abbrev)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::PrimalDualSolver;
+
+    /// the jitter must not change any decoding result, including the pre-matching and the layer fusion
+    #[test]
+    fn dual_module_jitter_same_result() {
+        // cargo test dual_module_jitter_same_result -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 4, 0.03, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        for dual_config in [
+            json!({}),
+            json!({ "sim_config": { "support_offloading": true, "support_layer_fusion": true } }),
+        ] {
+            let mut jitter_dual_config = dual_config.clone();
+            jitter_dual_config["jitter"] = json!({ "max_delay": 1e-7, "spike_probability": 0.1, "spike_delay": 1e-6 });
+            let mut solver = SolverEmbeddedComb::new(graph.clone(), json!({ "dual": dual_config }));
+            let mut jitter_solver = SolverEmbeddedCombJitter::new(graph.clone(), json!({ "dual": jitter_dual_config }));
+            for seed in 0..20 {
+                let syndrome_pattern = code.generate_random_errors(seed);
+                solver.solve(&syndrome_pattern);
+                jitter_solver.solve(&syndrome_pattern);
+                assert_eq!(solver.subgraph(), jitter_solver.subgraph());
+                solver.clear();
+                jitter_solver.clear();
+            }
+            let statistics = &jitter_solver.dual_module.driver.driver.statistics;
+            println!("{statistics:?}");
+            assert!(statistics.responses > 0);
+            assert!(statistics.max_delay <= 1e-7 + 1e-6);
+        }
+    }
+}
@@ -47,6 +47,9 @@ impl SolverTrackedDual for DualModuleLooperDriver {
     fn get_pre_matchings(&self, belonging: DualModuleInterfaceWeak) -> PerfectMatching {
         self.client.get_pre_matchings(belonging)
     }
+    fn supports_dual_readback(&self) -> bool {
+        true
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -114,30 +117,37 @@ impl DualModuleLooperDriver {
             context_id,
             maximum_growth,
         })?;
-        let grown = CompactWeight::try_from(output.grown).unwrap();
-        if output.max_growable == u16::MAX {
-            assert!(!output.conflict.valid, "growable must be finite when conflict is detected");
-            return Ok((CompactObstacle::None, grown));
+        Ok(output.obstacle())
+    }
+}
+
+impl OutputData {
+    /// the obstacle and the growth reported by the host
+    pub fn obstacle(&self) -> (CompactObstacle, CompactWeight) {
+        let grown = CompactWeight::try_from(self.grown).unwrap();
+        if self.max_growable == u16::MAX {
+            assert!(!self.conflict.valid, "growable must be finite when conflict is detected");
+            return (CompactObstacle::None, grown);
         }
-        if output.conflict.valid {
-            return Ok((
+        if self.conflict.valid {
+            return (
                 CompactObstacle::Conflict {
-                    node_1: ni!(output.conflict.node1).option(),
-                    node_2: output.conflict.node2.map(|v| ni!(v)).into(),
-                    touch_1: ni!(output.conflict.touch1).option(),
-                    touch_2: output.conflict.touch2.map(|v| ni!(v)).into(),
-                    vertex_1: ni!(output.conflict.vertex1),
-                    vertex_2: ni!(output.conflict.vertex2),
+                    node_1: ni!(self.conflict.node1).option(),
+                    node_2: self.conflict.node2.map(|v| ni!(v)).into(),
+                    touch_1: ni!(self.conflict.touch1).option(),
+                    touch_2: self.conflict.touch2.map(|v| ni!(v)).into(),
+                    vertex_1: ni!(self.conflict.vertex1),
+                    vertex_2: ni!(self.conflict.vertex2),
                 },
                 grown,
-            ));
+            );
         }
-        return Ok((
+        (
             CompactObstacle::GrowLength {
-                length: CompactWeight::try_from(output.max_growable).unwrap(),
+                length: CompactWeight::try_from(self.max_growable).unwrap(),
             },
             grown,
-        ));
+        )
     }
 }
 
@@ -161,6 +171,21 @@ impl DualStacklessDriver for DualModuleLooperDriver {
         self.execute_instruction(Instruction32::add_defect_vertex(vertex, node), self.context_id)
             .unwrap();
     }
+    fn add_defects_bitmap(&mut self, base_vertex: CompactVertexIndex, base_node: CompactNodeIndex, bitmap: u64) {
+        // the host expands every bitmap into `AddDefectVertex` instructions, see `LooperHost.scala`
+        align_defects_bitmap(base_vertex, base_node, bitmap, |base_vertex, base_node, bitmap| {
+            for instruction in Instruction32::load_defects_bitmap(base_vertex, base_node, bitmap) {
+                self.execute_instruction(instruction, self.context_id).unwrap();
+            }
+        });
+    }
+    fn read_vertex_grown(&mut self, vertex: CompactVertexIndex) -> Option<CompactWeight> {
+        // the selected vertex reports its grown value through the convergecast of the growable length
+        let output = self
+            .execute_instruction(Instruction32::read_vertex_grown(vertex), self.context_id)
+            .unwrap();
+        (output.max_growable != u16::MAX).then(|| CompactWeight::try_from(output.max_growable).unwrap())
+    }
 }
 
 impl DualTrackedDriver for DualModuleLooperDriver {
@@ -178,6 +203,7 @@ impl FusionVisualizer for DualModuleLooperDriver {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::conformance::*;
     use crate::dual_module_adaptor::tests::*;
     use fusion_blossom::util::*;
     use serde_json::json;
@@ -237,6 +263,31 @@ mod tests {
         dual_module_looper_basic_standard_syndrome(3, visualize_filename, defect_vertices, config);
     }
 
+    /// the looper is conformant with the combinatorial reference, including the arbitration order
+    #[test]
+    fn dual_module_looper_conformance() {
+        // cargo test dual_module_looper_conformance -- --nocapture
+        let config = ConformanceConfig {
+            primal_dual_config: json!({ "dual": { "name": "dual_module_looper_conformance" } }),
+            ..Default::default()
+        };
+        check_conformance::<DualModuleLooperDriver>(&config).assert_conformant();
+    }
+
+    /// loading every window of defects as a bitmap, which the host expands into `AddDefectVertex` instructions
+    #[test]
+    fn dual_module_looper_conformance_bitmap() {
+        // cargo test dual_module_looper_conformance_bitmap -- --nocapture
+        let config = ConformanceConfig {
+            primal_dual_config: json!({
+                "bitmap_loading_threshold": 1,
+                "dual": { "name": "dual_module_looper_conformance_bitmap" },
+            }),
+            ..Default::default()
+        };
+        check_conformance::<DualModuleLooperDriver>(&config).assert_conformant();
+    }
+
     pub fn dual_module_looper_basic_standard_syndrome(
         d: VertexNum,
         visualize_filename: String,
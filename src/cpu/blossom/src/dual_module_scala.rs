@@ -59,6 +59,9 @@ impl SolverTrackedDual for DualModuleScalaDriver {
         // TODO: implement pre matching fetching
         PerfectMatching::default()
     }
+    fn supports_dual_readback(&self) -> bool {
+        true
+    }
 }
 
 pub type DualModuleScalaAdaptor = DualModuleAdaptor<DualModuleScala>;
@@ -209,6 +212,14 @@ impl DualStacklessDriver for DualModuleScalaDriver {
     fn add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) {
         write!(self.link.lock().unwrap().writer, "add_defect({vertex}, {node})\n").unwrap();
     }
+    fn read_vertex_grown(&mut self, vertex: CompactVertexIndex) -> Option<CompactWeight> {
+        write!(self.link.lock().unwrap().writer, "read_vertex_grown({vertex})\n").unwrap();
+        let mut line = String::new();
+        self.link.lock().unwrap().reader.read_line(&mut line).unwrap();
+        let grown = scan_fmt!(&line, "Grown({d})", Weight).unwrap();
+        // a grown value that does not fit in the weight bits is reported as infinity
+        (grown != i32::MAX as Weight).then_some(grown as CompactWeight)
+    }
 }
 
 impl DualTrackedDriver for DualModuleScalaDriver {
@@ -288,6 +299,30 @@ pub mod tests {
         dual_module_comb_basic_standard_syndrome(7, visualize_filename, defect_vertices, false, false);
     }
 
+    /// zero-weight edges are always tight: the defects along the path conflict and propagate without growing
+    #[test]
+    #[cfg(not(debug_assertions))]  // only in release mode
+    fn dual_module_scala_zero_weight_1() {
+        // cargo test --release dual_module_scala_zero_weight_1 -- --nocapture
+        use fusion_blossom::example_codes::*;
+        let visualize_filename = "dual_module_scala_zero_weight_1.json".to_string();
+        let mut code = CodeCapacityPlanarCode::new(3, 0.1, 500);
+        let (path_edges, path_vertices) = find_regular_path(&code, 3);
+        crate::example_codes::set_zero_weight_edges(&mut code, &path_edges);
+        let defect_vertices = vec![path_vertices[0], path_vertices[1], path_vertices[2]];
+        dual_module_code_optional_viz(
+            &mut code,
+            Some(visualize_filename.clone()),
+            defect_vertices,
+            |initializer, positions| {
+                SolverEmbeddedScala::new(
+                    MicroBlossomSingle::new(initializer, positions),
+                    json!({ "dual": { "name": visualize_filename.trim_end_matches(".json").to_string() } }),
+                )
+            },
+        );
+    }
+
     pub fn dual_module_scala_basic_standard_syndrome(
         d: VertexNum,
         visualize_filename: String,
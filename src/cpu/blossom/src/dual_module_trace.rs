@@ -0,0 +1,369 @@
+//! Dual Module Trace
+//!
+//! A wrapper driver that records every instruction issued to the inner dual driver, together with the responses read
+//! back, into an instruction trace (see [`crate::instruction_trace`]). The trace serves as the stimuli of a hardware
+//! testbench: [`replay_trace`] issues exactly the same instructions to a dual driver and compares every response with
+//! the recorded one, which also reproduces a rare failure without running the primal module again.
+//!
+//! The driver calls are recorded as the 32-bit instructions of the accelerator: a defect bitmap is expanded into
+//! `AddDefectVertex` instructions of consecutive nodes, the maximum growth of `find_conflict` is recorded as a write
+//! to the register of the primal offloading unit before `FindObstacle`, and the dual variable or the growth read
+//! back from the accelerator is recorded as a response without obstacle. The `is_blossom` hint of the speed updates,
+//! the blossom callbacks and the instructions of the comb model without a 32-bit encoding (`freeze_vertex_range` and
+//! `set_edge_weight`) are not recorded.
+//!
+//! The trace is configured by the `trace` field of the dual configuration, e.g.,
+//! `{"dual":{"trace":{"filename":"shots.trace"}}}`; the other fields are passed to the inner driver.
+//!
+
+use crate::instruction_trace::*;
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use fusion_blossom::visualize::*;
+use micro_blossom_nostd::dual_driver_tracked::*;
+use micro_blossom_nostd::dual_module_stackless::*;
+use micro_blossom_nostd::instruction::*;
+use micro_blossom_nostd::interface::*;
+use micro_blossom_nostd::util::*;
+use serde::*;
+use serde_json::json;
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Read, Result};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TraceConfig {
+    /// the trace file, overwritten if it exists
+    pub filename: String,
+}
+
+pub struct DualModuleTraceDriver<D: SolverTrackedDual> {
+    pub driver: D,
+    pub config: TraceConfig,
+    /// only taken when the driver is dropped
+    writer: Option<TraceWriter<BufWriter<File>>>,
+    /// the number of events recorded in the current shot
+    shot_events: usize,
+    /// a reset is recorded lazily before the first event of a shot, so that the trace does not end with an empty shot
+    pending_reset: bool,
+}
+
+impl<D: SolverTrackedDual> DualModuleTraceDriver<D> {
+    pub fn new(driver: D, config: TraceConfig) -> Self {
+        let writer = TraceWriter::create(&config.filename)
+            .unwrap_or_else(|error| panic!("cannot create trace file {}: {error}", config.filename));
+        Self {
+            driver,
+            config,
+            writer: Some(writer),
+            shot_events: 0,
+            pending_reset: true,
+        }
+    }
+
+    fn write(&mut self, event: &TraceEvent) {
+        self.writer.as_mut().unwrap().write(event).unwrap();
+    }
+
+    fn record(&mut self, event: TraceEvent) {
+        if self.pending_reset {
+            self.pending_reset = false;
+            self.record(TraceEvent::Instruction(Instruction32::reset()));
+        }
+        self.shot_events += 1;
+        self.write(&event);
+    }
+
+    fn record_instruction(&mut self, instruction: Instruction32) {
+        self.record(TraceEvent::Instruction(instruction));
+    }
+
+    fn record_response(&mut self, response: (CompactObstacle, CompactWeight)) -> (CompactObstacle, CompactWeight) {
+        let (obstacle, grown) = response.clone();
+        self.record(TraceEvent::Response { obstacle, grown });
+        response
+    }
+
+    fn record_readout(&mut self, value: CompactWeight) -> CompactWeight {
+        self.record_response((CompactObstacle::None, value)).1
+    }
+
+    /// the number of events written to the trace, including the shot ends
+    pub fn event_count(&self) -> usize {
+        self.writer.as_ref().map(|writer| writer.count).unwrap_or(0)
+    }
+}
+
+impl<D: SolverTrackedDual> Drop for DualModuleTraceDriver<D> {
+    fn drop(&mut self) {
+        if self.shot_events > 0 {
+            self.write(&TraceEvent::ShotEnd);
+        }
+        if let Err(error) = self.writer.take().unwrap().finish() {
+            eprintln!("failed to finish trace file {}: {error}", self.config.filename);
+        }
+    }
+}
+
+impl<D: SolverTrackedDual> SolverTrackedDual for DualModuleTraceDriver<D> {
+    fn new_from_graph_config(graph: MicroBlossomSingle, config: serde_json::Value) -> Self {
+        Self::try_new_from_graph_config(graph, config).unwrap_or_else(|error| panic!("{error}"))
+    }
+    fn try_new_from_graph_config(
+        graph: MicroBlossomSingle,
+        mut config: serde_json::Value,
+    ) -> std::result::Result<Self, DualDriverError> {
+        let invalid = |message: String| DualDriverError::InvalidConfig { message };
+        let trace_config = (config.as_object_mut())
+            .and_then(|config| config.remove("trace"))
+            .ok_or_else(|| invalid("the trace driver requires the `trace` field in the dual configuration".to_string()))?;
+        let trace_config = serde_json::from_value(trace_config).map_err(|error| invalid(format!("trace: {error}")))?;
+        Ok(Self::new(D::try_new_from_graph_config(graph, config)?, trace_config))
+    }
+    fn reset_profiler(&mut self) {
+        self.driver.reset_profiler();
+    }
+    fn generate_profiler_report(&self) -> serde_json::Value {
+        let mut report = self.driver.generate_profiler_report();
+        report["trace"] = json!({ "filename": self.config.filename, "events": self.event_count() });
+        report
+    }
+    fn fuse_layer(&mut self, layer_id: usize) {
+        self.record_instruction(Instruction32::load_syndrome_external(ni!(layer_id)));
+        self.driver.fuse_layer(layer_id);
+    }
+    forward_solver_tracked_dual!(self, self.driver, self.driver;
+        get_pre_matchings, instruction_counts, clock_cycles, hardware_counters, supports_dual_readback,
+        weight_overflowed, desynchronized, freeze_vertex_range, set_edge_weight, remove_defect, release_nodes,
+        finish_shot,
+    );
+}
+
+impl<D: SolverTrackedDual> DualStacklessDriver for DualModuleTraceDriver<D> {
+    fn reset(&mut self) {
+        if self.shot_events > 0 {
+            self.write(&TraceEvent::ShotEnd);
+            self.shot_events = 0;
+        }
+        self.pending_reset = true;
+        self.driver.reset();
+    }
+    fn set_speed(&mut self, is_blossom: bool, node: CompactNodeIndex, speed: CompactGrowState) {
+        self.record_instruction(Instruction32::set_speed(node, speed));
+        self.driver.set_speed(is_blossom, node, speed);
+    }
+    fn set_speed_with_magnitude(
+        &mut self,
+        is_blossom: bool,
+        node: CompactNodeIndex,
+        speed: CompactGrowState,
+        magnitude: CompactSpeed,
+    ) {
+        self.record_instruction(Instruction32::set_speed_with_magnitude(node, speed, magnitude));
+        self.driver.set_speed_with_magnitude(is_blossom, node, speed, magnitude);
+    }
+    fn max_speed_magnitude(&self) -> CompactSpeed {
+        self.driver.max_speed_magnitude()
+    }
+    fn set_blossom(&mut self, node: CompactNodeIndex, blossom: CompactNodeIndex) {
+        self.record_instruction(Instruction32::set_blossom(node, blossom));
+        self.driver.set_blossom(node, blossom);
+    }
+    fn find_obstacle(&mut self) -> (CompactObstacle, CompactWeight) {
+        self.record_instruction(Instruction32::find_obstacle());
+        let response = self.driver.find_obstacle();
+        self.record_response(response)
+    }
+    fn add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) {
+        self.record_instruction(Instruction32::add_defect_vertex(vertex, node));
+        self.driver.add_defect(vertex, node);
+    }
+    fn add_defects_bitmap(&mut self, base_vertex: CompactVertexIndex, base_node: CompactNodeIndex, bitmap: u64) {
+        let offsets = (0..64).filter(|offset| (bitmap >> offset) & 1 == 1);
+        for (rank, offset) in offsets.enumerate() {
+            let vertex = ni!(base_vertex.get() as usize + offset);
+            let node = ni!(base_node.get() as usize + rank);
+            self.record_instruction(Instruction32::add_defect_vertex(vertex, node));
+        }
+        self.driver.add_defects_bitmap(base_vertex, base_node, bitmap);
+    }
+    fn read_vertex_grown(&mut self, vertex: CompactVertexIndex) -> Option<CompactWeight> {
+        let value = self.driver.read_vertex_grown(vertex)?;
+        self.record_instruction(Instruction32::read_vertex_grown(vertex));
+        Some(self.record_readout(value))
+    }
+    fn on_blossom_created(&mut self, blossom: CompactNodeIndex) {
+        self.driver.on_blossom_created(blossom);
+    }
+    fn on_blossom_expanded(&mut self, blossom: CompactNodeIndex) {
+        self.driver.on_blossom_expanded(blossom);
+    }
+    fn on_blossom_absorbed_into_blossom(&mut self, child: CompactNodeIndex) {
+        self.driver.on_blossom_absorbed_into_blossom(child);
+    }
+}
+
+impl<D: SolverTrackedDual> DualTrackedDriver for DualModuleTraceDriver<D> {
+    fn find_conflict(&mut self, maximum_growth: CompactWeight) -> (CompactObstacle, CompactWeight) {
+        self.record(TraceEvent::MaximumGrowth(maximum_growth));
+        self.record_instruction(Instruction32::find_obstacle());
+        let response = self.driver.find_conflict(maximum_growth);
+        self.record_response(response)
+    }
+}
+
+impl<D: SolverTrackedDual> FusionVisualizer for DualModuleTraceDriver<D> {
+    fn snapshot(&self, abbrev: bool) -> serde_json::Value {
+        self.driver.snapshot(abbrev)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReplayReport {
+    pub events: usize,
+    pub shots: usize,
+    pub instructions: usize,
+    /// the responses that differ from the recorded ones
+    pub mismatches: usize,
+    /// the recorded and the replayed response of the first mismatch
+    pub first_mismatch: Option<String>,
+}
+
+fn invalid_trace(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// issue a recorded instruction to the driver, returning the response if the instruction has one
+fn replay_instruction<D: DualStacklessDriver + DualTrackedDriver>(
+    driver: &mut D,
+    instruction: Instruction32,
+    maximum_growth: &mut Option<CompactWeight>,
+) -> Result<Option<TraceEvent>> {
+    let response = |(obstacle, grown)| Some(TraceEvent::Response { obstacle, grown });
+    let readout = |value: Option<CompactWeight>| match value {
+        Some(value) => Ok(response((CompactObstacle::None, value))),
+        None => Err(Error::new(
+            ErrorKind::Unsupported,
+            "the driver does not support reading back dual variables",
+        )),
+    };
+    let (field1, field2) = (ni!(instruction.field1()), ni!(instruction.field2()));
+    if instruction.is_set_speed() {
+        if instruction.0 & SPEED_MAGNITUDE_MASK == 0 {
+            driver.set_speed(false, field1, instruction.get_speed());
+        } else {
+            driver.set_speed_with_magnitude(false, field1, instruction.get_speed(), instruction.get_speed_magnitude());
+        }
+        return Ok(None);
+    }
+    if !instruction.is_extended() {
+        match instruction.op_code() {
+            OP_CODE_SET_BLOSSOM => driver.set_blossom(field1, field2),
+            OP_CODE_ADD_DEFECT_VERTEX => driver.add_defect(field1, field2),
+            _ => return Err(invalid_trace("the match instruction cannot be replayed")),
+        }
+        return Ok(None);
+    }
+    Ok(match instruction.extended_op_code() {
+        EXTENDED_OP_CODE_RESET => {
+            driver.reset();
+            *maximum_growth = None;
+            None
+        }
+        EXTENDED_OP_CODE_FIND_OBSTACLE => match maximum_growth.take() {
+            Some(maximum_growth) => response(driver.find_conflict(maximum_growth)),
+            None => response(driver.find_obstacle()),
+        },
+        EXTENDED_OP_CODE_READ_DUAL if instruction.is_read_vertex() => readout(driver.read_vertex_grown(field1))?,
+        _ => {
+            let message = format!("instruction {} cannot be replayed", instruction_kind(instruction));
+            return Err(Error::new(ErrorKind::Unsupported, message));
+        }
+    })
+}
+
+/// feed the instructions of a trace to the driver and compare the responses with the recorded ones
+pub fn replay_trace<D: DualStacklessDriver + DualTrackedDriver, R: Read>(
+    driver: &mut D,
+    reader: TraceReader<R>,
+) -> Result<ReplayReport> {
+    let mut report = ReplayReport::default();
+    let mut maximum_growth = None;
+    // the replayed response waiting to be compared with the next event
+    let mut replayed: Option<TraceEvent> = None;
+    for (index, event) in reader.enumerate() {
+        let event = event?;
+        report.events += 1;
+        if let Some(replayed) = replayed.take() {
+            if !matches!(event, TraceEvent::Response { .. }) {
+                return Err(invalid_trace("an instruction is not followed by its response"));
+            }
+            if replayed != event {
+                report.mismatches += 1;
+                report
+                    .first_mismatch
+                    .get_or_insert_with(|| format!("event {index}: recorded {event:?}, replayed {replayed:?}"));
+            }
+            continue;
+        }
+        match event {
+            TraceEvent::Instruction(instruction) => {
+                report.instructions += 1;
+                replayed = replay_instruction(driver, instruction, &mut maximum_growth)?;
+            }
+            TraceEvent::MaximumGrowth(length) => maximum_growth = Some(length),
+            TraceEvent::Response { .. } => return Err(invalid_trace("a response without instruction")),
+            TraceEvent::ShotEnd => report.shots += 1,
+        }
+    }
+    if replayed.is_some() {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "the last response is missing"));
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dual_module_comb::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::PrimalDualSolver;
+
+    /// the recorded trace replays on a fresh driver with exactly the same responses
+    #[test]
+    fn dual_module_trace_replay() {
+        // cargo test dual_module_trace_replay -- --nocapture
+        let path = std::env::temp_dir().join(format!("dual_module_trace_replay_{}.trace", std::process::id()));
+        let filename = path.to_str().unwrap().to_string();
+        let mut code = PhenomenologicalRotatedCode::new(5, 4, 0.03, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let config = json!({ "dual": { "trace": { "filename": filename } } });
+        let mut solver = SolverEmbeddedCombTrace::new(graph.clone(), config);
+        for seed in 0..20 {
+            solver.solve(&code.generate_random_errors(seed));
+            solver.clear();
+        }
+        drop(solver); // finish the trace file
+        let mut driver = DualModuleCombDriver::new(graph, serde_json::from_value(json!({})).unwrap());
+        let report = replay_trace(&mut driver, TraceReader::open(&filename).unwrap()).unwrap();
+        println!("{}", json!(report));
+        assert_eq!(report.shots, 20);
+        assert_eq!(report.mismatches, 0);
+        // a response that differs from the recorded one is reported
+        let mut events: Vec<TraceEvent> = TraceReader::open(&filename).unwrap().map(Result::unwrap).collect();
+        std::fs::remove_file(&filename).unwrap();
+        let tampered = events.iter_mut().find_map(|event| match event {
+            TraceEvent::Response { grown, .. } if *grown > 0 => Some(grown),
+            _ => None,
+        });
+        *tampered.unwrap() += 2;
+        let mut writer = TraceWriter::new(vec![]).unwrap();
+        for event in events.iter() {
+            writer.write(event).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+        let report = replay_trace(&mut driver, TraceReader::new(bytes.as_slice()).unwrap()).unwrap();
+        println!("{:?}", report.first_mismatch);
+        assert_eq!(report.mismatches, 1);
+    }
+}
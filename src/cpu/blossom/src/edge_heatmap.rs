@@ -0,0 +1,243 @@
+//! Edge Heatmap
+//!
+//! Accumulate how often each edge appears in the final matchings across a benchmark run. An edge that is never
+//! selected although it should be often points to a weight bug, and the hot edges are where the hardware switches
+//! the most. [`SolverEdgeHeatmap`] records the subgraph of every shot around any solver and exports the counts when
+//! it is dropped, i.e., at the end of the benchmark: a CSV with one row per edge, and a visualizer file whose single
+//! snapshot fills every edge in proportion to its matched frequency.
+//!
+//! Enable it with `--primal-dual-config '{"edge_heatmap":{"csv":"heatmap.csv","overlay":"heatmap.json"}}'`, where
+//! the overlay is written to `visualize/data/`.
+//!
+
+use crate::mwpm_solver::*;
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::io::Write;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EdgeHeatmapConfig {
+    /// the CSV file of the per-edge counts
+    #[serde(default = "Default::default")]
+    pub csv: Option<String>,
+    /// the visualizer file at visualize/data/<overlay>
+    #[serde(default = "Default::default")]
+    pub overlay: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EdgeHeatmap {
+    pub weighted_edges: Vec<(VertexIndex, VertexIndex, Weight)>,
+    pub is_virtual: Vec<bool>,
+    /// the number of recorded shots
+    pub shots: usize,
+    /// the number of shots in which each edge is matched
+    pub counts: Vec<usize>,
+}
+
+impl EdgeHeatmap {
+    pub fn new(initializer: &SolverInitializer) -> Self {
+        let mut is_virtual = vec![false; initializer.vertex_num];
+        for &vertex_index in initializer.virtual_vertices.iter() {
+            is_virtual[vertex_index] = true;
+        }
+        Self {
+            weighted_edges: initializer.weighted_edges.clone(),
+            is_virtual,
+            shots: 0,
+            counts: vec![0; initializer.weighted_edges.len()],
+        }
+    }
+
+    pub fn record(&mut self, subgraph: &[EdgeIndex]) {
+        self.shots += 1;
+        for &edge_index in subgraph.iter() {
+            self.counts[edge_index] += 1;
+        }
+    }
+
+    /// the fraction of shots in which the edge is matched
+    pub fn frequency(&self, edge_index: EdgeIndex) -> f64 {
+        self.counts[edge_index] as f64 / self.shots.max(1) as f64
+    }
+
+    pub fn never_matched(&self) -> Vec<EdgeIndex> {
+        (0..self.counts.len())
+            .filter(|&edge_index| self.counts[edge_index] == 0)
+            .collect()
+    }
+
+    pub fn write_csv(&self, filename: &str) -> std::io::Result<()> {
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(filename)?);
+        writeln!(writer, "edge,left,right,weight,count,frequency")?;
+        for (edge_index, &(left, right, weight)) in self.weighted_edges.iter().enumerate() {
+            let (count, frequency) = (self.counts[edge_index], self.frequency(edge_index));
+            writeln!(writer, "{edge_index},{left},{right},{weight},{count},{frequency}")?;
+        }
+        writer.flush()
+    }
+
+    pub fn write_overlay(&self, filename: &str, positions: &[VisualizePosition]) -> std::io::Result<()> {
+        let mut visualizer = Visualizer::new(Some(visualize_data_folder() + filename), positions.to_vec(), true)?;
+        visualizer.snapshot(format!("edge heatmap of {} shots", self.shots), self)
+    }
+}
+
+/// every edge is grown from both sides in proportion to its frequency, so a fully matched edge looks tight
+impl FusionVisualizer for EdgeHeatmap {
+    fn snapshot(&self, abbrev: bool) -> serde_json::Value {
+        let vertices: Vec<serde_json::Value> = self
+            .is_virtual
+            .iter()
+            .map(|&is_virtual| {
+                json!({
+                    if abbrev { "v" } else { "is_virtual" }: i32::from(is_virtual),
+                    if abbrev { "s" } else { "is_defect" }: 0,
+                })
+            })
+            .collect();
+        let edges: Vec<serde_json::Value> = self
+            .weighted_edges
+            .iter()
+            .enumerate()
+            .map(|(edge_index, &(left, right, weight))| {
+                let growth = (weight as f64 * self.frequency(edge_index) / 2.).round() as Weight;
+                json!({
+                    if abbrev { "w" } else { "weight" }: weight,
+                    if abbrev { "l" } else { "left" }: left,
+                    if abbrev { "r" } else { "right" }: right,
+                    if abbrev { "lg" } else { "left_growth" }: growth,
+                    if abbrev { "rg" } else { "right_growth" }: growth,
+                    "count": self.counts[edge_index],
+                    "frequency": self.frequency(edge_index),
+                })
+            })
+            .collect();
+        json!({
+            "vertices": vertices,
+            "edges": edges,
+        })
+    }
+}
+
+pub struct SolverEdgeHeatmap {
+    pub solver: Box<dyn MicroBlossomSolver>,
+    pub heatmap: EdgeHeatmap,
+    pub config: EdgeHeatmapConfig,
+    positions: Vec<VisualizePosition>,
+}
+
+impl SolverEdgeHeatmap {
+    pub fn new(
+        initializer: &SolverInitializer,
+        positions: &[VisualizePosition],
+        solver: Box<dyn MicroBlossomSolver>,
+        config: EdgeHeatmapConfig,
+    ) -> Self {
+        Self {
+            solver,
+            heatmap: EdgeHeatmap::new(initializer),
+            config,
+            positions: positions.to_vec(),
+        }
+    }
+
+    pub fn export(&self) -> std::io::Result<()> {
+        if let Some(csv) = self.config.csv.as_ref() {
+            self.heatmap.write_csv(csv)?;
+        }
+        if let Some(overlay) = self.config.overlay.as_ref() {
+            self.heatmap.write_overlay(overlay, &self.positions)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SolverEdgeHeatmap {
+    fn drop(&mut self) {
+        self.export().unwrap();
+    }
+}
+
+impl PrimalDualSolver for SolverEdgeHeatmap {
+    fn clear(&mut self) {
+        self.solver.clear();
+    }
+    /// the heatmap accumulates over the whole run and is not reset with the profiler
+    fn reset_profiler(&mut self) {
+        self.solver.reset_profiler();
+    }
+    fn solve_visualizer(&mut self, syndrome_pattern: &SyndromePattern, visualizer: Option<&mut Visualizer>) {
+        self.solver.solve_visualizer(syndrome_pattern, visualizer);
+        let subgraph = self.solver.subgraph();
+        self.heatmap.record(&subgraph);
+    }
+    fn perfect_matching_visualizer(&mut self, visualizer: Option<&mut Visualizer>) -> PerfectMatching {
+        self.solver.perfect_matching_visualizer(visualizer)
+    }
+    fn subgraph_visualizer(&mut self, visualizer: Option<&mut Visualizer>) -> Vec<EdgeIndex> {
+        self.solver.subgraph_visualizer(visualizer)
+    }
+    fn sum_dual_variables(&self) -> Weight {
+        self.solver.sum_dual_variables()
+    }
+    fn generate_profiler_report(&self) -> serde_json::Value {
+        json!({
+            "solver": self.solver.generate_profiler_report(),
+            "edge_heatmap": {
+                "shots": self.heatmap.shots,
+                "counts": self.heatmap.counts,
+                "never_matched": self.heatmap.never_matched().len(),
+            },
+        })
+    }
+}
+
+impl MicroBlossomSolver for SolverEdgeHeatmap {
+    fn result(&mut self) -> SolverResult {
+        self.solver.result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn edge_heatmap_counts() {
+        // cargo test edge_heatmap_counts -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.05, 500);
+        let initializer = code.get_initializer();
+        let positions = code.get_positions();
+        let graph = MicroBlossomSingle::new(&initializer, &positions);
+        let csv = std::env::temp_dir().join("edge_heatmap_counts.csv");
+        let config = EdgeHeatmapConfig {
+            csv: Some(csv.to_str().unwrap().to_string()),
+            overlay: None,
+        };
+        let inner = Box::new(SolverEmbeddedComb::new(graph, json!({})));
+        let mut solver = SolverEdgeHeatmap::new(&initializer, &positions, inner, config);
+        let mut expected = vec![0; initializer.weighted_edges.len()];
+        for seed in 0..20 {
+            solver.solve(&code.generate_random_errors(seed));
+            for edge_index in solver.subgraph() {
+                expected[edge_index] += 1;
+            }
+            solver.clear();
+        }
+        assert_eq!(solver.heatmap.shots, 20);
+        assert_eq!(solver.heatmap.counts, expected);
+        let snapshot = solver.heatmap.snapshot(true);
+        assert_eq!(snapshot["edges"].as_array().unwrap().len(), expected.len());
+        drop(solver);
+        let content = std::fs::read_to_string(csv).unwrap();
+        assert_eq!(content.lines().count(), expected.len() + 1);
+        assert!(content.starts_with("edge,left,right,weight,count,frequency"));
+    }
+}
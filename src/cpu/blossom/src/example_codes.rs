@@ -3,7 +3,7 @@ use fusion_blossom::util::*;
 use fusion_blossom::visualize::*;
 use rand_xoshiro::rand_core::SeedableRng;
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 /// example code with QEC-Playground as simulator
 pub struct QECPlaygroundCode {
@@ -92,6 +92,9 @@ pub struct QECPlaygroundCodeConfig {
     pub max_half_weight: usize,
     #[serde(default = "qec_playground_default_configs::trim_isolated_vertices")]
     pub trim_isolated_vertices: bool,
+    /// merge the parallel edges between the same pair of vertices into a single edge, see [`merge_parallel_edges`]
+    #[serde(default = "qec_playground_default_configs::merge_parallel_edges")]
+    pub merge_parallel_edges: bool,
 }
 
 pub mod qec_playground_default_configs {
@@ -116,6 +119,9 @@ pub mod qec_playground_default_configs {
     pub fn trim_isolated_vertices() -> bool {
         true
     }
+    pub fn merge_parallel_edges() -> bool {
+        false
+    }
 }
 
 impl QECPlaygroundCode {
@@ -205,7 +211,6 @@ impl QECPlaygroundCode {
                 });
             }
         }
-        code.edge_index_map = std::sync::Arc::new(edge_index_map);
         // automatically create the vertices and nearest-neighbor connection
         code.fill_vertices(code.vertex_index_map.len() as VertexNum);
         // set virtual vertices and positions
@@ -219,6 +224,197 @@ impl QECPlaygroundCode {
                 code.vertices[*new_index as usize].is_virtual = true;
             }
         }
+        if config.merge_parallel_edges {
+            let merge = merge_parallel_edges(&mut code);
+            for new_index in edge_index_map.values_mut() {
+                *new_index = merge.edge_mapping[*new_index as usize];
+            }
+        }
+        code.edge_index_map = std::sync::Arc::new(edge_index_map);
         code
     }
+
+    /// the measurement round (time coordinate in the simulator) of every vertex, see [`crate::layer_assignment`]
+    pub fn get_vertex_rounds(&self) -> Vec<usize> {
+        let mut vertex_rounds = vec![0; self.vertices.len()];
+        for (vertex_index, new_index) in self.vertex_index_map.iter() {
+            vertex_rounds[*new_index as usize] = self.adaptor.vertex_to_position_mapping[*vertex_index].t;
+        }
+        vertex_rounds
+    }
+
+    /// check whether the correction given by the subgraph (in the trimmed edge indices) leads to a logical error
+    /// on the last randomly generated errors
+    pub fn is_logical_error(&mut self, subgraph: &[EdgeIndex]) -> bool {
+        use qecp::simulator::SimulatorGenerics;
+        // when parallel edges are merged, correct using the most probable one, i.e., with the smallest weight
+        let weighted_edges = &self.adaptor.initializer.weighted_edges;
+        let mut original_edge_index = HashMap::<EdgeIndex, usize>::new();
+        for (&original, &trimmed) in self.edge_index_map.iter() {
+            let kept = original_edge_index.entry(trimmed).or_insert(original);
+            if (weighted_edges[original].2, original) < (weighted_edges[*kept].2, *kept) {
+                *kept = original;
+            }
+        }
+        let original_subgraph: Vec<_> = subgraph
+            .iter()
+            .map(|edge_index| original_edge_index[edge_index] as EdgeIndex)
+            .collect();
+        let correction = self.adaptor.subgraph_to_correction(&original_subgraph);
+        let (logical_i, logical_j) = self.simulator.validate_correction(&correction);
+        logical_i || logical_j
+    }
+}
+
+/// set the weight of the boundary edges (between a regular vertex and a virtual vertex) of each regular vertex in
+/// `half_weights`, e.g., to model asymmetric boundaries during lattice surgery; the other edges are not changed.
+/// note that recomputing the weights from the error probabilities overwrites them
+#[allow(clippy::unnecessary_cast)]
+pub fn set_boundary_half_weights(code: &mut dyn ExampleCode, half_weights: &BTreeMap<VertexIndex, Weight>) {
+    let (vertices, edges) = code.vertices_edges();
+    for edge in edges.iter_mut() {
+        let (left, right) = edge.vertices;
+        let regular = match (vertices[left as usize].is_virtual, vertices[right as usize].is_virtual) {
+            (false, true) => left,
+            (true, false) => right,
+            _ => continue,
+        };
+        if let Some(&half_weight) = half_weights.get(&regular) {
+            assert!(half_weight >= 0, "boundary weight must be non-negative");
+            edge.half_weight = half_weight;
+        }
+    }
+}
+
+/// set the weight of each edge in `edge_indices` to zero, e.g., to model erased qubits or merged edges whose
+/// combined error probability is at least 0.5; a zero-weight edge is always tight
+#[allow(clippy::unnecessary_cast)]
+pub fn set_zero_weight_edges(code: &mut dyn ExampleCode, edge_indices: &[EdgeIndex]) {
+    let (_, edges) = code.vertices_edges();
+    for &edge_index in edge_indices.iter() {
+        edges[edge_index as usize].half_weight = 0;
+    }
+}
+
+/// the result of [`merge_parallel_edges`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ParallelEdgeMerge {
+    /// the number of edges before merging
+    pub original_edges: usize,
+    /// the number of vertex pairs connected by more than one edge
+    pub merged_pairs: usize,
+    /// the number of edges removed, i.e., merged into another edge
+    pub merged_edges: usize,
+    /// the pairs merged by their weights only, because some of the edges do not have an error probability
+    pub weight_only_pairs: usize,
+    /// the new index of every original edge
+    pub edge_mapping: Vec<EdgeIndex>,
+}
+
+/// merge the parallel edges between the same pair of vertices into a single edge, which is common in graphs
+/// imported from a detector error model. An independent error mechanism on each edge flips the pair when an odd
+/// number of them happens, so the probabilities combine as `p = p1 (1 - p2) + p2 (1 - p1)` and the half weight is
+/// recomputed with the same scale as the original edges. If any of them has no error probability (`p = 0`), e.g.,
+/// when the code is constructed from the weights only, the merged edge keeps the smallest weight instead.
+/// The remaining edges keep their relative order, so that the result is deterministic.
+#[allow(clippy::unnecessary_cast)]
+pub fn merge_parallel_edges(code: &mut dyn ExampleCode) -> ParallelEdgeMerge {
+    let (vertices, edges) = code.vertices_edges();
+    let mut merge = ParallelEdgeMerge {
+        original_edges: edges.len(),
+        ..Default::default()
+    };
+    let mut pair_edges = BTreeMap::<(VertexIndex, VertexIndex), EdgeIndex>::new();
+    let mut groups: Vec<Vec<CodeEdge>> = vec![];
+    for edge in edges.iter() {
+        let (left, right) = edge.vertices;
+        let pair = (std::cmp::min(left, right), std::cmp::max(left, right));
+        let new_index = *pair_edges.entry(pair).or_insert_with(|| {
+            groups.push(vec![]);
+            (groups.len() - 1) as EdgeIndex
+        });
+        groups[new_index as usize].push(edge.clone());
+        merge.edge_mapping.push(new_index);
+    }
+    let log_ratio = |p: f64| ((1. - p) / p).ln();
+    let mut merged_edges = Vec::with_capacity(groups.len());
+    for group in groups.into_iter() {
+        let mut merged = group[0].clone();
+        if group.len() > 1 {
+            merge.merged_pairs += 1;
+            merge.merged_edges += group.len() - 1;
+            merged.pe = 1. - group.iter().map(|edge| 1. - edge.pe).product::<f64>();
+            merged.is_erasure = group.iter().any(|edge| edge.is_erasure);
+            if group.iter().all(|edge| edge.p > 0. && edge.p < 0.5) {
+                merged.p = group
+                    .iter()
+                    .skip(1)
+                    .fold(group[0].p, |p, edge| p * (1. - edge.p) + edge.p * (1. - p));
+                let scale = group.iter().map(|edge| edge.half_weight as f64).sum::<f64>()
+                    / group.iter().map(|edge| log_ratio(edge.p)).sum::<f64>();
+                merged.half_weight = if merged.p < 0.5 {
+                    (scale * log_ratio(merged.p)).round() as Weight
+                } else {
+                    0
+                };
+            } else {
+                merge.weight_only_pairs += 1;
+                merged.half_weight = group.iter().map(|edge| edge.half_weight).min().unwrap();
+            }
+        }
+        merged_edges.push(merged);
+    }
+    *edges = merged_edges;
+    for vertex in vertices.iter_mut() {
+        vertex.neighbor_edges.clear();
+    }
+    for (edge_index, edge) in edges.iter().enumerate() {
+        let (left, right) = edge.vertices;
+        vertices[left as usize].neighbor_edges.push(edge_index as EdgeIndex);
+        vertices[right as usize].neighbor_edges.push(edge_index as EdgeIndex);
+    }
+    merge
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example_codes_merge_parallel_edges() {
+        // cargo test example_codes_merge_parallel_edges -- --nocapture
+        let mut code = CodeCapacityRepetitionCode::new(5, 0.1, 500);
+        let original = code.get_initializer();
+        let original_edges = code.immutable_vertices_edges().1.clone();
+        // duplicate every edge of the code with the same error probability
+        {
+            let (_, edges) = code.vertices_edges();
+            let duplicated: Vec<_> = edges.clone();
+            edges.extend(duplicated);
+        }
+        let merge = merge_parallel_edges(&mut code);
+        println!("{merge:?}");
+        assert_eq!(merge.original_edges, 2 * original_edges.len());
+        assert_eq!(merge.merged_pairs, original_edges.len());
+        assert_eq!(merge.merged_edges, original_edges.len());
+        assert_eq!(merge.weight_only_pairs, 0);
+        let initializer = code.get_initializer();
+        assert_eq!(initializer.weighted_edges.len(), original.weighted_edges.len());
+        for (edge_index, edge) in code.immutable_vertices_edges().1.iter().enumerate() {
+            assert_eq!(merge.edge_mapping[edge_index], edge_index as EdgeIndex);
+            assert_eq!(merge.edge_mapping[edge_index + original_edges.len()], edge_index as EdgeIndex);
+            // two independent mechanisms of probability 0.1 flip the pair with probability 0.18
+            assert!((edge.p - 0.18).abs() < 1e-9);
+            assert!(edge.half_weight < original_edges[edge_index].half_weight);
+        }
+        for (vertex_index, vertex) in code.immutable_vertices_edges().0.iter().enumerate() {
+            for &edge_index in vertex.neighbor_edges.iter() {
+                let (left, right) = code.immutable_vertices_edges().1[edge_index as usize].vertices;
+                assert!(left as usize == vertex_index || right as usize == vertex_index);
+            }
+        }
+        // merging again does nothing
+        let merge = merge_parallel_edges(&mut code);
+        assert_eq!(merge.merged_edges, 0);
+    }
 }
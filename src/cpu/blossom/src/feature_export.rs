@@ -0,0 +1,230 @@
+//! Feature Export
+//!
+//! A learned pre-decoder could become a future offloading unit if it predicts which shots (or regions) are easy. To
+//! train one, this module runs many shots and exports a feature vector per shot in a columnar CSV file, which loads
+//! directly into pandas or Arrow (e.g., `pyarrow.csv.read_csv`). The columns are:
+//!
+//! - `seed`, `defects`: the shot and its number of defects
+//! - `defects_r<row>_c<column>`: the defects in every region of a `rows` x `columns` grid over the (i, j) plane
+//! - `offloaded`: the defects matched inside the dual module
+//! - `iterations`, `round_trips`, `matching_weight`: the cost and the result of the solve
+//! - `conflicts`, `blossom_need_expand`: the number of obstacles of each type
+//! - `obstacle_sequence`: the obstacles in the order they are reported, `C` for a conflict and `E` for a blossom
+//!   that needs to expand
+//!
+
+use crate::cli::CodeParameters;
+use crate::dual_module_comb::*;
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use crate::syndrome_source::*;
+use clap::Parser;
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::util::*;
+use micro_blossom_nostd::interface::*;
+use serde_json::json;
+use std::io::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShotFeatures {
+    pub seed: u64,
+    pub defects: usize,
+    /// the defects in every region, row-major
+    pub region_defects: Vec<usize>,
+    pub offloaded: usize,
+    pub iterations: usize,
+    pub round_trips: usize,
+    pub matching_weight: Weight,
+    pub obstacle_sequence: String,
+}
+
+/// bins the regular vertices into a grid of regions by their positions
+#[derive(Debug, Clone)]
+pub struct FeatureExtractor {
+    pub rows: usize,
+    pub columns: usize,
+    /// the region of every vertex, `None` for virtual vertices
+    vertex_regions: Vec<Option<usize>>,
+}
+
+impl FeatureExtractor {
+    pub fn new(graph: &MicroBlossomSingle, rows: usize, columns: usize) -> Self {
+        assert!(rows > 0 && columns > 0, "at least one region is required");
+        let is_virtual = |vertex_index: usize| graph.virtual_vertices.contains(&vertex_index);
+        let regular: Vec<usize> = (0..graph.vertex_num).filter(|&index| !is_virtual(index)).collect();
+        let range = |coordinate: fn(&Position) -> f64| {
+            let values = regular.iter().map(|&index| coordinate(&graph.positions[index]));
+            let min = values.clone().fold(f64::INFINITY, f64::min);
+            let max = values.fold(f64::NEG_INFINITY, f64::max);
+            (min, max)
+        };
+        let bin = |value: f64, (min, max): (f64, f64), count: usize| {
+            if max <= min {
+                return 0;
+            }
+            (((value - min) / (max - min) * count as f64) as usize).min(count - 1)
+        };
+        let (range_i, range_j) = (range(|position| position.i), range(|position| position.j));
+        let vertex_regions = (0..graph.vertex_num)
+            .map(|index| {
+                (!is_virtual(index)).then(|| {
+                    let position = &graph.positions[index];
+                    bin(position.i, range_i, rows) * columns + bin(position.j, range_j, columns)
+                })
+            })
+            .collect();
+        Self {
+            rows,
+            columns,
+            vertex_regions,
+        }
+    }
+
+    /// solve the shot and extract its features; the solver is cleared afterwards
+    pub fn extract(&self, solver: &mut SolverEmbeddedComb, seed: u64, syndrome_pattern: &SyndromePattern) -> ShotFeatures {
+        let mut region_defects = vec![0; self.rows * self.columns];
+        for &vertex_index in syndrome_pattern.defect_vertices.iter() {
+            if let Some(region) = self.vertex_regions[vertex_index] {
+                region_defects[region] += 1;
+            }
+        }
+        solver.dual_module.driver.driver.profiler_response_history.clear();
+        solver.solve(syndrome_pattern);
+        let obstacle_sequence = solver
+            .dual_module
+            .driver
+            .driver
+            .profiler_response_history
+            .iter()
+            .filter_map(|(obstacle, _)| match obstacle {
+                CompactObstacle::Conflict { .. } => Some('C'),
+                CompactObstacle::BlossomNeedExpand { .. } => Some('E'),
+                _ => None,
+            })
+            .collect();
+        let result = solver.result();
+        let features = ShotFeatures {
+            seed,
+            defects: syndrome_pattern.defect_vertices.len(),
+            region_defects,
+            offloaded: solver.offloaded,
+            iterations: solver.iterations(),
+            round_trips: solver.dual_module.driver.round_trips,
+            matching_weight: result.matching_weight,
+            obstacle_sequence,
+        };
+        solver.clear();
+        features
+    }
+
+    pub fn csv_header(&self) -> String {
+        let mut columns = vec!["seed".to_string(), "defects".to_string()];
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                columns.push(format!("defects_r{row}_c{column}"));
+            }
+        }
+        for name in [
+            "offloaded",
+            "iterations",
+            "round_trips",
+            "matching_weight",
+            "conflicts",
+            "blossom_need_expand",
+            "obstacle_sequence",
+        ] {
+            columns.push(name.to_string());
+        }
+        columns.join(",")
+    }
+}
+
+impl ShotFeatures {
+    pub fn csv_row(&self) -> String {
+        let mut values = vec![self.seed.to_string(), self.defects.to_string()];
+        values.extend(self.region_defects.iter().map(|count| count.to_string()));
+        values.extend([
+            self.offloaded.to_string(),
+            self.iterations.to_string(),
+            self.round_trips.to_string(),
+            self.matching_weight.to_string(),
+            self.obstacle_sequence.matches('C').count().to_string(),
+            self.obstacle_sequence.matches('E').count().to_string(),
+            self.obstacle_sequence.clone(),
+        ]);
+        values.join(",")
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct ExportFeaturesParameters {
+    #[clap(flatten)]
+    code: CodeParameters,
+    /// the number of shots to run; the seed of each shot is its index
+    #[clap(short = 'r', long, default_value_t = 1000)]
+    total_rounds: usize,
+    /// the configuration of the embedded comb solver
+    #[clap(long, default_value_t = json!({ "dual": { "sim_config": { "support_offloading": true } } }).to_string())]
+    primal_dual_config: String,
+    /// the number of rows of the region grid
+    #[clap(long, default_value_t = 2)]
+    region_rows: usize,
+    /// the number of columns of the region grid
+    #[clap(long, default_value_t = 2)]
+    region_columns: usize,
+    #[clap(flatten)]
+    source: SyndromeSourceParameters,
+}
+
+impl ExportFeaturesParameters {
+    pub fn run(&self) {
+        let primal_dual_config: serde_json::Value = serde_json::from_str(&self.primal_dual_config).unwrap();
+        let code = self.code.build();
+        let graph = MicroBlossomSingle::new_code(code.as_ref());
+        let mut source = self.source.build(code);
+        let extractor = FeatureExtractor::new(&graph, self.region_rows, self.region_columns);
+        let mut solver = SolverEmbeddedComb::new(graph, primal_dual_config);
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&self.output_file).unwrap());
+        writeln!(writer, "{}", extractor.csv_header()).unwrap();
+        for seed in 0..self.total_rounds as u64 {
+            let Some(syndrome_pattern) = source.next_syndrome_pattern() else {
+                break;
+            };
+            let features = extractor.extract(&mut solver, seed, &syndrome_pattern);
+            writeln!(writer, "{}", features.csv_row()).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn feature_export_columns() {
+        // cargo test feature_export_columns -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(9, 0.05, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let extractor = FeatureExtractor::new(&graph, 3, 2);
+        let config = json!({ "dual": { "sim_config": { "support_offloading": true } } });
+        let mut solver = SolverEmbeddedComb::new(graph, config);
+        let header = extractor.csv_header();
+        println!("{header}");
+        let column_num = header.split(',').count();
+        assert_eq!(column_num, 2 + 6 + 7);
+        let mut offloaded = 0;
+        for seed in 0..50 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            let features = extractor.extract(&mut solver, seed, &syndrome_pattern);
+            assert_eq!(features.region_defects.iter().sum::<usize>(), features.defects);
+            assert_eq!(features.csv_row().split(',').count(), column_num);
+            // every defect is either offloaded or handled by the primal module through obstacles
+            if features.defects > features.offloaded {
+                assert!(!features.obstacle_sequence.is_empty());
+            }
+            offloaded += features.offloaded;
+        }
+        assert!(offloaded > 0);
+    }
+}
@@ -0,0 +1,166 @@
+//! Firmware Graph
+//!
+//! The microcontroller of the embedded build should not parse JSON at runtime, so the decoding graph is converted
+//! into a generated Rust file of const arrays and included in the no_std firmware, e.g.,
+//! `include!(concat!(env!("OUT_DIR"), "/graph.rs"))`. Only primitive types are used so that the generated file
+//! compiles without any dependency:
+//!
+//! - `EDGE_VERTICES[e] = [l, r]` and `EDGE_WEIGHTS[e]` of every edge, in the unit of `WEIGHT_QUANTUM`, see
+//!   [`crate::weight_quantum`]
+//! - `IS_VIRTUAL[v]` of every vertex
+//! - the adjacency in the compressed sparse row format: the edges incident to vertex `v` are
+//!   `VERTEX_EDGES[VERTEX_EDGE_OFFSETS[v]..VERTEX_EDGE_OFFSETS[v + 1]]`, in increasing edge index
+//!
+//! Generate it with `micro-blossom emit-rust-graph <graph.json> <graph.rs>`.
+//!
+
+use crate::resources::*;
+use clap::Parser;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareGraph {
+    pub vertex_num: usize,
+    pub edge_vertices: Vec<[u32; 2]>,
+    pub edge_weights: Vec<i32>,
+    pub weight_quantum: i32,
+    pub is_virtual: Vec<bool>,
+    pub vertex_edge_offsets: Vec<u32>,
+    pub vertex_edges: Vec<u32>,
+}
+
+impl FirmwareGraph {
+    pub fn new(graph: &MicroBlossomSingle) -> Self {
+        let index = |value: usize| u32::try_from(value).expect("index overflow");
+        let mut incident_edges = vec![vec![]; graph.vertex_num];
+        for (edge_index, edge) in graph.weighted_edges.iter().enumerate() {
+            incident_edges[edge.l].push(index(edge_index));
+            incident_edges[edge.r].push(index(edge_index));
+        }
+        let mut vertex_edge_offsets = vec![0];
+        let mut vertex_edges = vec![];
+        for edges in incident_edges.into_iter() {
+            vertex_edges.extend(edges);
+            vertex_edge_offsets.push(index(vertex_edges.len()));
+        }
+        let mut is_virtual = vec![false; graph.vertex_num];
+        for &vertex_index in graph.virtual_vertices.iter() {
+            is_virtual[vertex_index] = true;
+        }
+        Self {
+            vertex_num: graph.vertex_num,
+            edge_vertices: graph
+                .weighted_edges
+                .iter()
+                .map(|edge| [index(edge.l), index(edge.r)])
+                .collect(),
+            edge_weights: graph
+                .weighted_edges
+                .iter()
+                .map(|edge| i32::try_from(edge.w).expect("weight overflow"))
+                .collect(),
+            weight_quantum: i32::try_from(graph.weight_quantum()).expect("weight overflow"),
+            is_virtual,
+            vertex_edge_offsets,
+            vertex_edges,
+        }
+    }
+
+    /// the edges incident to the vertex
+    pub fn incident_edges(&self, vertex_index: usize) -> &[u32] {
+        let begin = self.vertex_edge_offsets[vertex_index] as usize;
+        let end = self.vertex_edge_offsets[vertex_index + 1] as usize;
+        &self.vertex_edges[begin..end]
+    }
+
+    /// the content of the generated Rust file
+    pub fn to_rust(&self) -> String {
+        fn array<T: std::fmt::Debug>(output: &mut String, name: &str, element_type: &str, length: &str, values: &[T]) {
+            writeln!(output, "pub const {name}: [{element_type}; {length}] = [").unwrap();
+            for chunk in values.chunks(16) {
+                let line: Vec<String> = chunk.iter().map(|value| format!("{value:?}")).collect();
+                writeln!(output, "    {},", line.join(", ")).unwrap();
+            }
+            writeln!(output, "];").unwrap();
+        }
+        let mut output = String::new();
+        writeln!(
+            output,
+            "// DO NOT MODIFY: automatically generated by `micro-blossom emit-rust-graph`"
+        )
+        .unwrap();
+        writeln!(output).unwrap();
+        writeln!(output, "pub const VERTEX_NUM: usize = {};", self.vertex_num).unwrap();
+        writeln!(output, "pub const EDGE_NUM: usize = {};", self.edge_vertices.len()).unwrap();
+        writeln!(output, "pub const VERTEX_EDGE_NUM: usize = {};", self.vertex_edges.len()).unwrap();
+        writeln!(output, "pub const WEIGHT_QUANTUM: i32 = {};", self.weight_quantum).unwrap();
+        array(&mut output, "EDGE_VERTICES", "[u32; 2]", "EDGE_NUM", &self.edge_vertices);
+        array(&mut output, "EDGE_WEIGHTS", "i32", "EDGE_NUM", &self.edge_weights);
+        array(&mut output, "IS_VIRTUAL", "bool", "VERTEX_NUM", &self.is_virtual);
+        array(
+            &mut output,
+            "VERTEX_EDGE_OFFSETS",
+            "u32",
+            "VERTEX_NUM + 1",
+            &self.vertex_edge_offsets,
+        );
+        array(&mut output, "VERTEX_EDGES", "u32", "VERTEX_EDGE_NUM", &self.vertex_edges);
+        output
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct EmitRustGraphParameters {
+    /// the graph configuration, e.g., generated by `parser --graph-file`
+    #[clap(value_parser)]
+    graph_file: String,
+    /// the generated Rust file
+    #[clap(value_parser)]
+    output_file: String,
+}
+
+impl EmitRustGraphParameters {
+    pub fn run(&self) {
+        let graph: MicroBlossomSingle = serde_json::from_str(&std::fs::read_to_string(&self.graph_file).unwrap()).unwrap();
+        std::fs::write(&self.output_file, FirmwareGraph::new(&graph).to_rust()).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn firmware_graph_tables() {
+        // cargo test firmware_graph_tables -- --nocapture
+        let code = CodeCapacityPlanarCode::new(5, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let tables = FirmwareGraph::new(&graph);
+        assert_eq!(tables.vertex_edges.len(), 2 * graph.weighted_edges.len());
+        for vertex_index in 0..graph.vertex_num {
+            let expected: Vec<u32> = (0..graph.weighted_edges.len())
+                .filter(|&edge_index| {
+                    let edge = &graph.weighted_edges[edge_index];
+                    edge.l == vertex_index || edge.r == vertex_index
+                })
+                .map(|edge_index| edge_index as u32)
+                .collect();
+            assert_eq!(tables.incident_edges(vertex_index), expected.as_slice());
+            assert_eq!(
+                tables.is_virtual[vertex_index],
+                graph.virtual_vertices.contains(&vertex_index)
+            );
+        }
+        let rust = tables.to_rust();
+        println!("{rust}");
+        assert!(rust.contains(&format!("pub const VERTEX_NUM: usize = {};", graph.vertex_num)));
+        assert!(rust.contains("pub const WEIGHT_QUANTUM: i32 = 1;"));
+        assert!(rust.contains("pub const VERTEX_EDGE_OFFSETS: [u32; VERTEX_NUM + 1] = [\n    0, "));
+        let edge = &graph.weighted_edges[0];
+        assert!(rust.contains(&format!(
+            "pub const EDGE_VERTICES: [[u32; 2]; EDGE_NUM] = [\n    [{}, {}], ",
+            edge.l, edge.r
+        )));
+    }
+}
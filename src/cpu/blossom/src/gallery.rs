@@ -0,0 +1,242 @@
+//! Gallery
+//!
+//! Post-mortem analysis of outliers without rerunning shots one at a time: run many shots, then write a visualizer
+//! file for every shot that matches a filter, together with an index JSON linking the files with the metadata.
+//! The shots are first run without the visualizer so that the latency is not affected by taking snapshots;
+//! only the selected shots are run again with the visualizer, using the same seed.
+//!
+
+use crate::cli::{CodeParameters, PrimalDualType};
+use crate::mwpm_solver::*;
+use crate::syndrome_source::*;
+use crate::verifier::*;
+use clap::{Parser, ValueEnum};
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+use serde::Serialize;
+use serde_json::json;
+use std::time::Instant;
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Serialize, Debug)]
+pub enum GalleryFilter {
+    /// shots whose correction is invalid or heavier than `--verifier-config` allows, compared with the serial solver
+    VerificationFailure,
+    /// shots whose latency is above `--latency-percentile`
+    SlowLatency,
+    /// every shot, useful for small runs
+    All,
+}
+
+#[derive(Parser, Clone)]
+pub struct GalleryParameters {
+    #[clap(flatten)]
+    code: CodeParameters,
+    /// the number of shots to run; the seed of each shot is its index
+    #[clap(short = 'r', long, default_value_t = 1000)]
+    total_rounds: usize,
+    /// select the combination of primal and dual module
+    #[clap(short = 'p', long, value_enum, default_value_t = PrimalDualType::EmbeddedComb)]
+    primal_dual_type: PrimalDualType,
+    /// the configuration of primal and dual module
+    #[clap(long, default_value_t = ("{}").to_string())]
+    primal_dual_config: String,
+    /// the tolerated suboptimality, see [`VerifierConfig`]; by default the correction must be minimum-weight
+    #[clap(long, default_value_t = ("{}").to_string())]
+    verifier_config: String,
+    /// which shots to visualize
+    #[clap(short = 'f', long, value_enum, default_value_t = GalleryFilter::VerificationFailure)]
+    filter: GalleryFilter,
+    /// the latency percentile used by `--filter slow-latency`
+    #[clap(long, default_value_t = 0.99)]
+    latency_percentile: f64,
+    /// the gallery folder at visualize/data/<folder>, containing index.json and a visualizer file per shot
+    #[clap(long, default_value_t = format!("gallery"))]
+    folder: String,
+    /// the maximum number of visualizer files to write
+    #[clap(long, default_value_t = 100)]
+    max_shots: usize,
+    #[clap(flatten)]
+    source: SyndromeSourceParameters,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShotRecord {
+    pub seed: u64,
+    /// decoding latency in seconds, including building the subgraph
+    pub latency: f64,
+    pub verified: bool,
+    pub verdict: Verdict,
+    /// the matching weight, the dual objective and the statistics reported by the solver
+    pub result: SolverResult,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GalleryEntry {
+    /// visualizer file relative to the gallery folder
+    pub filename: String,
+    #[serde(flatten)]
+    pub record: ShotRecord,
+    pub defect_vertices: Vec<VertexIndex>,
+}
+
+/// the indices of the records matching the filter, in the order of the shots
+pub fn select_shots(records: &[ShotRecord], filter: GalleryFilter, latency_percentile: f64) -> Vec<usize> {
+    match filter {
+        GalleryFilter::VerificationFailure => (0..records.len()).filter(|&index| !records[index].verified).collect(),
+        GalleryFilter::SlowLatency => {
+            if records.is_empty() {
+                return vec![];
+            }
+            let mut latencies: Vec<f64> = records.iter().map(|record| record.latency).collect();
+            latencies.sort_by(f64::total_cmp);
+            let threshold = latencies[((latencies.len() - 1) as f64 * latency_percentile).round() as usize];
+            (0..records.len())
+                .filter(|&index| records[index].latency > threshold)
+                .collect()
+        }
+        GalleryFilter::All => (0..records.len()).collect(),
+    }
+}
+
+impl GalleryParameters {
+    pub fn run(&self) -> Vec<GalleryEntry> {
+        assert!(
+            !matches!(
+                self.primal_dual_type,
+                PrimalDualType::Serial | PrimalDualType::ErrorPatternLogger
+            ),
+            "the gallery is for the solvers in this crate"
+        );
+        assert!((0. ..=1.).contains(&self.latency_percentile));
+        let primal_dual_config: serde_json::Value = serde_json::from_str(&self.primal_dual_config).unwrap();
+        let verifier_config: VerifierConfig = serde_json::from_str(&self.verifier_config).unwrap();
+        let code = self.code.build();
+        let initializer = code.get_initializer();
+        let positions = code.get_positions();
+        let mut solver = self.primal_dual_type.build(&initializer, &positions, primal_dual_config);
+        let mut verifier = BoundedVerifier::new(&initializer, verifier_config);
+        let mut source = self.source.build(code);
+        // first run every shot without visualizer
+        let mut records = Vec::with_capacity(self.total_rounds);
+        let mut syndrome_patterns = Vec::with_capacity(self.total_rounds);
+        for seed in 0..self.total_rounds as u64 {
+            let Some(syndrome_pattern) = source.next_syndrome_pattern() else {
+                break;
+            };
+            let begin = Instant::now();
+            solver.solve(&syndrome_pattern);
+            solver.subgraph();
+            let latency = begin.elapsed().as_secs_f64();
+            // reading back the dual variables is not part of the latency
+            let result = solver.result();
+            let verdict = verifier.verify(&syndrome_pattern, &result);
+            records.push(ShotRecord {
+                seed,
+                latency,
+                verified: verdict.is_accepted(),
+                verdict,
+                result,
+            });
+            syndrome_patterns.push(syndrome_pattern);
+            solver.clear();
+        }
+        println!("verdicts: {:?}", verifier.statistics.verdicts);
+        // then rerun the selected shots with visualizer
+        let mut selected = select_shots(&records, self.filter, self.latency_percentile);
+        println!("{} shots match filter {:?}", selected.len(), self.filter);
+        selected.truncate(self.max_shots);
+        let folder = visualize_data_folder() + self.folder.as_str();
+        std::fs::create_dir_all(&folder).unwrap();
+        let mut entries = Vec::with_capacity(selected.len());
+        for index in selected {
+            let record = records[index].clone();
+            let syndrome_pattern = syndrome_patterns[index].clone();
+            let filename = format!("shot_{}.json", record.seed);
+            let mut visualizer = Visualizer::new(Some(format!("{folder}/{filename}")), positions.clone(), true).unwrap();
+            solver.solve_visualizer(&syndrome_pattern, Some(&mut visualizer));
+            solver.subgraph_visualizer(Some(&mut visualizer));
+            solver.clear();
+            entries.push(GalleryEntry {
+                filename,
+                record,
+                defect_vertices: syndrome_pattern.defect_vertices,
+            });
+        }
+        let index = json!({
+            "code_type": self.code.code_type.to_possible_value().unwrap().get_name(),
+            "d": self.code.d,
+            "p": self.code.p,
+            "noisy_measurements": self.code.noisy_measurements,
+            "primal_dual_type": self.primal_dual_type,
+            "primal_dual_config": self.primal_dual_config,
+            "total_rounds": self.total_rounds,
+            "verifier_config": verifier.config,
+            "verifier_statistics": verifier.statistics,
+            "filter": self.filter,
+            "latency_percentile": self.latency_percentile,
+            "shots": entries,
+        });
+        std::fs::write(format!("{folder}/index.json"), serde_json::to_string_pretty(&index).unwrap()).unwrap();
+        print_visualize_link(format!("{}/index.json", self.folder));
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    fn records_of(latencies: &[f64], failures: &[usize]) -> Vec<ShotRecord> {
+        (0..latencies.len())
+            .map(|index| ShotRecord {
+                seed: index as u64,
+                latency: latencies[index],
+                verified: !failures.contains(&index),
+                verdict: if failures.contains(&index) {
+                    Verdict::InvalidCorrection
+                } else {
+                    Verdict::Optimal
+                },
+                result: SolverResult::default(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn gallery_select_shots() {
+        // cargo test gallery_select_shots -- --nocapture
+        let latencies: Vec<f64> = (0..100).map(|index| ((index * 37) % 100) as f64).collect();
+        let records = records_of(&latencies, &[3, 50]);
+        assert_eq!(select_shots(&records, GalleryFilter::VerificationFailure, 0.99), vec![3, 50]);
+        assert_eq!(select_shots(&records, GalleryFilter::All, 0.99).len(), 100);
+        // only the slowest shot (latency 99) is above the 99th percentile
+        let slow = select_shots(&records, GalleryFilter::SlowLatency, 0.99);
+        assert_eq!(slow.len(), 1);
+        assert_eq!(records[slow[0]].latency, 99.);
+        assert_eq!(select_shots(&records, GalleryFilter::SlowLatency, 0.9).len(), 10);
+        assert!(select_shots(&[], GalleryFilter::SlowLatency, 0.99).is_empty());
+    }
+
+    #[test]
+    fn gallery_code_parameters() {
+        // cargo test gallery_code_parameters -- --nocapture
+        let parameters = GalleryParameters::parse_from([
+            "gallery",
+            "3",
+            "0.05",
+            "-n",
+            "2",
+            "-c",
+            "phenomenological-planar-code",
+            "-r",
+            "10",
+        ]);
+        assert_eq!(parameters.total_rounds, 10);
+        let initializer = parameters.code.build().get_initializer();
+        let expected = PhenomenologicalPlanarCode::new(3, 2, 0.05, 500).get_initializer();
+        assert_eq!(initializer.vertex_num, expected.vertex_num);
+        assert_eq!(initializer.weighted_edges, expected.weighted_edges);
+    }
+}
@@ -0,0 +1,275 @@
+//! Graph Builder
+//!
+//! Construct a custom decoding graph vertex by vertex and edge by edge, instead of assembling a [`SolverInitializer`]
+//! together with the separate positions and layers by hand. Every step is validated when it is taken, so an error
+//! points to the offending vertex or edge rather than surfacing later in the solver or the hardware generator.
+//! [`GraphBuilder::finish`] derives the rest of [`MicroBlossomSingle`], e.g., the binary trees, the offloading units
+//! and the layer fusion, exactly as for the example codes.
+//!
+//! ```ignore
+//! let mut builder = GraphBuilder::new();
+//! let left = builder.add_vertex(Position { t: 0., i: 0., j: 0. }, true, None)?;
+//! let middle = builder.add_vertex(Position { t: 0., i: 0., j: 1. }, false, Some(0))?;
+//! let right = builder.add_vertex(Position { t: 0., i: 0., j: 2. }, true, None)?;
+//! builder.add_edge(left, middle, 2)?;
+//! builder.add_edge(middle, right, 2)?;
+//! let graph = builder.finish()?;
+//! ```
+//!
+
+use crate::resources::*;
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphBuilderError {
+    /// the coordinates of a vertex must be finite
+    InvalidPosition { vertex: VertexIndex },
+    /// virtual vertices do not belong to any layer
+    VirtualLayer { vertex: VertexIndex },
+    /// either every regular vertex has a layer or none of them has
+    InconsistentLayer { vertex: VertexIndex },
+    /// an edge refers to a vertex not added yet
+    VertexOutOfRange { vertex: VertexIndex, vertex_num: usize },
+    /// an edge connects a vertex to itself
+    SelfLoop { vertex: VertexIndex },
+    /// the weights must be non-negative even numbers so that the dual variables stay integers
+    InvalidWeight { edge: EdgeIndex, weight: Weight },
+    /// two edges connect the same pair of vertices
+    DuplicateEdge { edge: EdgeIndex, existing: EdgeIndex },
+    /// an edge between two virtual vertices can never be matched
+    VirtualEdge { edge: EdgeIndex },
+    /// the graph has no vertex at all
+    Empty,
+}
+
+impl std::fmt::Display for GraphBuilderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidPosition { vertex } => write!(f, "vertex {vertex} has a non-finite position"),
+            Self::VirtualLayer { vertex } => write!(f, "virtual vertex {vertex} should not have a layer"),
+            Self::InconsistentLayer { vertex } => {
+                write!(
+                    f,
+                    "vertex {vertex} disagrees with the other regular vertices on having a layer"
+                )
+            }
+            Self::VertexOutOfRange { vertex, vertex_num } => {
+                write!(f, "vertex {vertex} out of range, the graph has {vertex_num} vertices")
+            }
+            Self::SelfLoop { vertex } => write!(f, "edge connects vertex {vertex} to itself"),
+            Self::InvalidWeight { edge, weight } => {
+                write!(f, "edge {edge} has weight {weight}, which is not a non-negative even number")
+            }
+            Self::DuplicateEdge { edge, existing } => write!(f, "edge {edge} duplicates edge {existing}"),
+            Self::VirtualEdge { edge } => write!(f, "edge {edge} connects two virtual vertices"),
+            Self::Empty => write!(f, "the graph has no vertex"),
+        }
+    }
+}
+
+impl std::error::Error for GraphBuilderError {}
+
+#[derive(Debug, Clone, Default)]
+pub struct GraphBuilder {
+    positions: Vec<Position>,
+    is_virtual: Vec<bool>,
+    layers: Vec<Option<usize>>,
+    weighted_edges: Vec<WeightedEdge>,
+    /// the edge index of every pair of vertices `(min, max)`
+    edge_pairs: BTreeMap<(VertexIndex, VertexIndex), EdgeIndex>,
+    /// whether the regular vertices have layers, decided by the first regular vertex
+    has_layers: Option<bool>,
+}
+
+impl GraphBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn vertex_num(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn edge_num(&self) -> usize {
+        self.weighted_edges.len()
+    }
+
+    /// add a vertex and return its index; `layer` is the measurement round of a regular vertex, see
+    /// [`crate::layer_assignment`], and must be `None` for a virtual vertex
+    pub fn add_vertex(
+        &mut self,
+        position: Position,
+        is_virtual: bool,
+        layer: Option<usize>,
+    ) -> Result<VertexIndex, GraphBuilderError> {
+        let vertex = self.positions.len();
+        if !(position.i.is_finite() && position.j.is_finite() && position.t.is_finite()) {
+            return Err(GraphBuilderError::InvalidPosition { vertex });
+        }
+        if is_virtual {
+            if layer.is_some() {
+                return Err(GraphBuilderError::VirtualLayer { vertex });
+            }
+        } else if *self.has_layers.get_or_insert(layer.is_some()) != layer.is_some() {
+            return Err(GraphBuilderError::InconsistentLayer { vertex });
+        }
+        self.positions.push(position);
+        self.is_virtual.push(is_virtual);
+        self.layers.push(layer);
+        Ok(vertex)
+    }
+
+    /// add an edge between two existing vertices and return its index
+    pub fn add_edge(
+        &mut self,
+        left: VertexIndex,
+        right: VertexIndex,
+        weight: Weight,
+    ) -> Result<EdgeIndex, GraphBuilderError> {
+        let edge = self.weighted_edges.len();
+        let vertex_num = self.vertex_num();
+        for vertex in [left, right] {
+            if vertex >= vertex_num {
+                return Err(GraphBuilderError::VertexOutOfRange { vertex, vertex_num });
+            }
+        }
+        if left == right {
+            return Err(GraphBuilderError::SelfLoop { vertex: left });
+        }
+        if weight < 0 || weight % 2 != 0 {
+            return Err(GraphBuilderError::InvalidWeight { edge, weight });
+        }
+        if self.is_virtual[left] && self.is_virtual[right] {
+            return Err(GraphBuilderError::VirtualEdge { edge });
+        }
+        let pair = (left.min(right), left.max(right));
+        if let Some(&existing) = self.edge_pairs.get(&pair) {
+            return Err(GraphBuilderError::DuplicateEdge { edge, existing });
+        }
+        self.edge_pairs.insert(pair, edge);
+        self.weighted_edges.push(WeightedEdge {
+            l: left,
+            r: right,
+            w: weight,
+        });
+        Ok(edge)
+    }
+
+    /// the complete graph configuration, with the layer assignment if the vertices have layers
+    pub fn finish(self) -> Result<MicroBlossomSingle, GraphBuilderError> {
+        if self.positions.is_empty() {
+            return Err(GraphBuilderError::Empty);
+        }
+        let virtual_vertices: BTreeSet<VertexIndex> =
+            (0..self.vertex_num()).filter(|&vertex| self.is_virtual[vertex]).collect();
+        let initializer = SolverInitializer::new(
+            self.vertex_num(),
+            (self.weighted_edges.iter()).map(|edge| (edge.l, edge.r, edge.w)).collect(),
+            virtual_vertices.into_iter().collect(),
+        );
+        let positions: Vec<_> = (self.positions.iter())
+            .map(|position| VisualizePosition::new(position.i, position.j, position.t))
+            .collect();
+        let mut graph = MicroBlossomSingle::new(&initializer, &positions);
+        if self.has_layers == Some(true) {
+            let rounds: Vec<usize> = self.layers.iter().map(|layer| layer.unwrap_or(0)).collect();
+            graph.assign_layers_from_rounds(&rounds);
+        }
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwpm_solver::*;
+    use fusion_blossom::mwpm_solver::PrimalDualSolver;
+    use serde_json::json;
+
+    /// a repetition code over 2 rounds, with a virtual vertex at each end of every round
+    fn repetition_code(length: usize) -> Result<MicroBlossomSingle, GraphBuilderError> {
+        let mut builder = GraphBuilder::new();
+        for round in 0..2 {
+            let position = |j: usize| Position {
+                t: round as f64,
+                i: 0.,
+                j: j as f64,
+            };
+            let mut last = builder.add_vertex(position(0), true, None)?;
+            for j in 1..=length {
+                let is_virtual = j == length;
+                let vertex = builder.add_vertex(position(j), is_virtual, (!is_virtual).then_some(round))?;
+                builder.add_edge(last, vertex, 2)?;
+                if round > 0 && !is_virtual {
+                    builder.add_edge(vertex - length - 1, vertex, 4)?;
+                }
+                last = vertex;
+            }
+        }
+        builder.finish()
+    }
+
+    #[test]
+    fn graph_builder_repetition_code() {
+        // cargo test graph_builder_repetition_code -- --nocapture
+        let graph = repetition_code(5).unwrap();
+        assert_eq!(graph.vertex_num, 12);
+        assert_eq!(graph.virtual_vertices, vec![0, 5, 6, 11]);
+        assert_eq!(graph.weighted_edges.len(), 14);
+        let layer_assignment = graph.layer_assignment.as_ref().unwrap();
+        assert_eq!(layer_assignment.layers(), vec![vec![1, 2, 3, 4], vec![7, 8, 9, 10]]);
+        let mut solver = SolverEmbeddedComb::new(graph, json!({}));
+        solver.solve(&SyndromePattern::new_vertices(vec![2, 9]));
+        assert_eq!(solver.result().matching_weight, 6);
+    }
+
+    #[test]
+    fn graph_builder_validation() {
+        // cargo test graph_builder_validation -- --nocapture
+        let origin = || Position { t: 0., i: 0., j: 0. };
+        let mut builder = GraphBuilder::new();
+        assert_eq!(GraphBuilder::new().finish().err(), Some(GraphBuilderError::Empty));
+        let nan = Position { t: f64::NAN, ..origin() };
+        assert_eq!(
+            builder.add_vertex(nan, false, None),
+            Err(GraphBuilderError::InvalidPosition { vertex: 0 })
+        );
+        assert_eq!(
+            builder.add_vertex(origin(), true, Some(0)),
+            Err(GraphBuilderError::VirtualLayer { vertex: 0 })
+        );
+        let (a, b, c) = (
+            builder.add_vertex(origin(), false, Some(0)).unwrap(),
+            builder.add_vertex(origin(), true, None).unwrap(),
+            builder.add_vertex(origin(), true, None).unwrap(),
+        );
+        assert_eq!(
+            builder.add_vertex(origin(), false, None),
+            Err(GraphBuilderError::InconsistentLayer { vertex: 3 })
+        );
+        assert_eq!(builder.add_edge(a, a, 2), Err(GraphBuilderError::SelfLoop { vertex: a }));
+        assert_eq!(
+            builder.add_edge(a, 3, 2),
+            Err(GraphBuilderError::VertexOutOfRange {
+                vertex: 3,
+                vertex_num: 3
+            })
+        );
+        assert_eq!(
+            builder.add_edge(a, b, 3),
+            Err(GraphBuilderError::InvalidWeight { edge: 0, weight: 3 })
+        );
+        assert_eq!(builder.add_edge(b, c, 2), Err(GraphBuilderError::VirtualEdge { edge: 0 }));
+        assert_eq!(builder.add_edge(a, b, 2), Ok(0));
+        assert_eq!(
+            builder.add_edge(b, a, 4),
+            Err(GraphBuilderError::DuplicateEdge { edge: 1, existing: 0 })
+        );
+        println!("{}", builder.add_edge(b, a, 4).unwrap_err());
+        // the failed steps leave the builder unchanged
+        let graph = builder.finish().unwrap();
+        assert_eq!((graph.vertex_num, graph.weighted_edges.len()), (3, 1));
+    }
+}
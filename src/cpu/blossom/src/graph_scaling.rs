@@ -0,0 +1,195 @@
+//! Graph Scaling
+//!
+//! The weights of a decoding graph are calibrated from the experiment, which is only possible at the small distances
+//! of the current devices (e.g., d=3 or 5). To project the hardware requirements of larger devices, this module
+//! extrapolates a calibrated graph to larger distances: every edge is classified by its type, i.e., the displacement
+//! between its two vertices and whether it touches the boundary, and the edges of the same type in a larger code of
+//! the same family get the average calibrated weight of that type. The larger code provides the vertices, the
+//! positions and hence the layer structure, so the generated graph is a complete hardware configuration.
+//!
+//! Generate the graphs with `micro-blossom scale-graph <calibrated.json> --distances 7,9,11 -c <code-type>`.
+//!
+
+use crate::resources::*;
+use clap::Parser;
+use fusion_blossom::cli::ExampleCodeType;
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// the displacement is rounded to this resolution when comparing the types
+const POSITION_RESOLUTION: f64 = 1e-3;
+
+/// the type of an edge, invariant under the translation of the lattice
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct EdgeType {
+    /// the displacement in (t, i, j); a boundary edge points from the regular vertex to the virtual vertex, and the
+    /// other edges are oriented so that the displacement is lexicographically positive
+    pub displacement: [i64; 3],
+    pub is_boundary: bool,
+}
+
+impl EdgeType {
+    pub fn of(graph: &MicroBlossomSingle, edge: &WeightedEdge) -> Self {
+        let is_virtual = |vertex_index: usize| graph.virtual_vertices.contains(&vertex_index);
+        let (from, to) = if is_virtual(edge.l) && !is_virtual(edge.r) {
+            (edge.r, edge.l)
+        } else {
+            (edge.l, edge.r)
+        };
+        let round = |value: f64| (value / POSITION_RESOLUTION).round() as i64;
+        let (a, b) = (&graph.positions[from], &graph.positions[to]);
+        let mut displacement = [round(b.t - a.t), round(b.i - a.i), round(b.j - a.j)];
+        let is_boundary = is_virtual(edge.l) != is_virtual(edge.r);
+        if !is_boundary && displacement < [0; 3] {
+            displacement = displacement.map(|value| -value);
+        }
+        Self {
+            displacement,
+            is_boundary,
+        }
+    }
+}
+
+/// the calibrated weight of every edge type
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EdgeTypeWeights {
+    /// the average weight, rounded to an even number, and the number of calibrated edges of each type
+    pub weights: BTreeMap<EdgeType, (Weight, usize)>,
+}
+
+impl EdgeTypeWeights {
+    pub fn calibrate(graph: &MicroBlossomSingle) -> Self {
+        let mut sums = BTreeMap::<EdgeType, (f64, usize)>::new();
+        for edge in graph.weighted_edges.iter() {
+            let entry = sums.entry(EdgeType::of(graph, edge)).or_default();
+            entry.0 += edge.w as f64;
+            entry.1 += 1;
+        }
+        Self {
+            weights: sums
+                .into_iter()
+                .map(|(edge_type, (sum, count))| {
+                    let weight = (sum / count as f64 / 2.).round() as Weight * 2;
+                    (edge_type, (weight, count))
+                })
+                .collect(),
+        }
+    }
+
+    /// assign the calibrated weights to the template graph of a larger code; the edges of the types not calibrated
+    /// keep the weights of the template and are reported
+    pub fn scale(&self, template: &MicroBlossomSingle) -> (MicroBlossomSingle, BTreeSet<EdgeType>) {
+        let mut initializer = template.get_initializer();
+        let mut uncalibrated = BTreeSet::new();
+        for (edge, weighted_edge) in template.weighted_edges.iter().zip(initializer.weighted_edges.iter_mut()) {
+            let edge_type = EdgeType::of(template, edge);
+            match self.weights.get(&edge_type) {
+                Some(&(weight, _)) => weighted_edge.2 = weight,
+                None => {
+                    uncalibrated.insert(edge_type);
+                }
+            }
+        }
+        let positions: Vec<VisualizePosition> = template
+            .positions
+            .iter()
+            .map(|position| VisualizePosition::new(position.i, position.j, position.t))
+            .collect();
+        (MicroBlossomSingle::new(&initializer, &positions), uncalibrated)
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct ScaleGraphParameters {
+    /// the calibrated graph configuration at a small distance, e.g., generated by `parser --graph-file`
+    #[clap(value_parser)]
+    calibrated_graph_file: String,
+    /// the distances to extrapolate to, e.g., `--distances 7,9,11`
+    #[clap(long, value_delimiter = ',', required = true)]
+    distances: Vec<VertexNum>,
+    /// the code family of the calibrated graph
+    #[clap(short = 'c', long, value_enum, default_value_t = ExampleCodeType::CodeCapacityPlanarCode)]
+    code_type: ExampleCodeType,
+    /// the configuration of the code builder
+    #[clap(long, default_value_t = ("{}").to_string())]
+    code_config: String,
+    /// rounds of noisy measurement of the larger codes; by default as many as the distance
+    #[clap(short = 'n', long)]
+    noisy_measurements: Option<VertexNum>,
+    /// the generated graph of distance `d` is written to `<output_prefix>d<d>.json`
+    #[clap(long, default_value_t = ("scaled_").to_string())]
+    output_prefix: String,
+}
+
+impl ScaleGraphParameters {
+    pub fn run(&self) {
+        let calibrated: MicroBlossomSingle =
+            serde_json::from_str(&std::fs::read_to_string(&self.calibrated_graph_file).unwrap()).unwrap();
+        let weights = EdgeTypeWeights::calibrate(&calibrated);
+        let code_config: serde_json::Value = serde_json::from_str(&self.code_config).unwrap();
+        for &d in self.distances.iter() {
+            let noisy_measurements = self.noisy_measurements.unwrap_or(d);
+            // the error rate only affects the weights of the template, which are replaced
+            let code = self.code_type.build(d, 0.01, noisy_measurements, 500, code_config.clone());
+            let (graph, uncalibrated) = weights.scale(&MicroBlossomSingle::new_code(code.as_ref()));
+            let output_file = format!("{}d{d}.json", self.output_prefix);
+            std::fs::write(&output_file, serde_json::to_string(&graph).unwrap()).unwrap();
+            println!(
+                "{}",
+                json!({
+                    "d": d,
+                    "output_file": output_file,
+                    "vertex_num": graph.vertex_num,
+                    "edge_num": graph.weighted_edges.len(),
+                    "offloading_units": graph.offloading.0.len(),
+                    "num_layers": graph.layer_fusion.as_ref().map(|layer_fusion| layer_fusion.num_layers),
+                    "uncalibrated_edge_types": uncalibrated,
+                })
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    /// the time-like edges of a calibrated d=3 graph are heavier than the template, which carries over to d=7
+    #[test]
+    fn graph_scaling_extrapolate() {
+        // cargo test graph_scaling_extrapolate -- --nocapture
+        let is_time_like = |edge_type: &EdgeType| edge_type.displacement[0] != 0;
+        let mut calibrated = MicroBlossomSingle::new_code(&PhenomenologicalRotatedCode::new(3, 3, 0.01, 500));
+        for edge_index in 0..calibrated.weighted_edges.len() {
+            let edge_type = EdgeType::of(&calibrated, &calibrated.weighted_edges[edge_index]);
+            calibrated.weighted_edges[edge_index].w = if is_time_like(&edge_type) { 600 } else { 200 };
+        }
+        let weights = EdgeTypeWeights::calibrate(&calibrated);
+        // scaling to the same distance reproduces the calibrated weights
+        let (same, uncalibrated) = weights.scale(&MicroBlossomSingle::new_code(&PhenomenologicalRotatedCode::new(
+            3, 3, 0.1, 500,
+        )));
+        assert!(uncalibrated.is_empty());
+        assert_eq!(same.weighted_edges, calibrated.weighted_edges);
+        let template = MicroBlossomSingle::new_code(&PhenomenologicalRotatedCode::new(7, 7, 0.1, 500));
+        let (scaled, uncalibrated) = weights.scale(&template);
+        assert!(uncalibrated.is_empty(), "{uncalibrated:?}");
+        assert_eq!(scaled.vertex_num, template.vertex_num);
+        assert_eq!(
+            scaled.layer_fusion.as_ref().unwrap().num_layers,
+            template.layer_fusion.as_ref().unwrap().num_layers
+        );
+        for edge in scaled.weighted_edges.iter() {
+            let expected = if is_time_like(&EdgeType::of(&scaled, edge)) {
+                600
+            } else {
+                200
+            };
+            assert_eq!(edge.w, expected);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Graph Symmetry
+//!
+//! A decoding graph with many measurement rounds is mostly the same unit cell repeated along the time axis:
+//! the vertices of a single round together with the edges from them to the later rounds.
+//! This analysis pass detects such translational symmetry and emits hints to the hardware config generator.
+//! The edges at the same position of every copy can read their weights from a single shared ROM, and the copies can
+//! be instantiated as replicated tiles of one template, which reduces the configuration size and BRAM for large d.
+//!
+
+use crate::layer_assignment::*;
+use crate::resources::*;
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SharingHints {
+    /// maximal runs of consecutive layers that are translated copies of each other
+    pub tiles: Vec<ReplicatedTile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplicatedTile {
+    /// consecutive layer ids; the first layer serves as the template
+    pub layers: Vec<usize>,
+    /// the translation of the time coordinate between consecutive copies
+    pub period: f64,
+    /// `vertices[k][x]` is the vertex in the k-th copy corresponding to the x-th vertex of the template
+    pub vertices: Vec<Vec<usize>>,
+    /// `edges[k][x]` is the edge in the k-th copy corresponding to the x-th edge of the template;
+    /// an edge belongs to the earliest layer among its non-virtual endpoints
+    pub edges: Vec<Vec<usize>>,
+    /// the weight ROM shared by all the copies: `weights[x]` is the weight of the x-th edge of every copy
+    pub weights: Vec<isize>,
+}
+
+type Coordinate = (OrderedFloat<f64>, OrderedFloat<f64>, OrderedFloat<f64>);
+
+/// the unit cell of a single layer in canonical order, so that two layers are translated copies of each other
+/// if and only if their signatures are equal
+struct LayerCell {
+    t: f64,
+    /// (relative position, max growth) of every vertex, sorted
+    vertices: Vec<((Coordinate, isize), usize)>,
+    /// (relative position of both endpoints, weight) of every edge, sorted
+    edges: Vec<((Coordinate, Coordinate, isize), usize)>,
+}
+
+impl LayerCell {
+    fn same_shape(&self, other: &Self) -> bool {
+        self.vertices.len() == other.vertices.len()
+            && self.edges.len() == other.edges.len()
+            && self.vertices.iter().zip(other.vertices.iter()).all(|(a, b)| a.0 == b.0)
+            && self.edges.iter().zip(other.edges.iter()).all(|(a, b)| a.0 == b.0)
+    }
+}
+
+impl SharingHints {
+    /// the layers are taken from `graph.layer_assignment` if available, otherwise inferred from the positions
+    pub fn new(graph: &MicroBlossomSingle) -> Self {
+        let tolerance = layer_assignment_default::tolerance();
+        let assignment = graph
+            .layer_assignment
+            .clone()
+            .unwrap_or_else(|| LayerAssignment::from_positions(graph, tolerance));
+        let cells = Self::build_cells(graph, &assignment);
+        let mut tiles = vec![];
+        let mut begin = 0;
+        while begin < cells.len() {
+            let mut end = begin + 1;
+            while end < cells.len() && cells[begin].same_shape(&cells[end]) {
+                let period = cells[begin + 1].t - cells[begin].t;
+                if (cells[end].t - cells[end - 1].t - period).abs() > tolerance {
+                    break;
+                }
+                end += 1;
+            }
+            if end - begin >= 2 {
+                let template = &cells[begin];
+                tiles.push(ReplicatedTile {
+                    layers: (begin..end).collect(),
+                    period: cells[begin + 1].t - template.t,
+                    vertices: cells[begin..end]
+                        .iter()
+                        .map(|cell| cell.vertices.iter().map(|(_, vertex_index)| *vertex_index).collect())
+                        .collect(),
+                    edges: cells[begin..end]
+                        .iter()
+                        .map(|cell| cell.edges.iter().map(|(_, edge_index)| *edge_index).collect())
+                        .collect(),
+                    weights: template.edges.iter().map(|((_, _, weight), _)| *weight).collect(),
+                });
+            }
+            begin = end;
+        }
+        Self { tiles }
+    }
+
+    fn build_cells(graph: &MicroBlossomSingle, assignment: &LayerAssignment) -> Vec<LayerCell> {
+        let mut cells: Vec<LayerCell> = assignment
+            .layers()
+            .into_iter()
+            .map(|vertices| LayerCell {
+                t: vertices
+                    .iter()
+                    .map(|&vertex_index| graph.positions[vertex_index].t)
+                    .fold(f64::INFINITY, f64::min),
+                vertices: vec![],
+                edges: vec![],
+            })
+            .collect();
+        let relative = |vertex_index: usize, t: f64| -> Coordinate {
+            let position = &graph.positions[vertex_index];
+            (position.i.into(), position.j.into(), (position.t - t).into())
+        };
+        for (vertex_index, layer_id) in assignment.vertex_layer_id.iter().enumerate() {
+            if let Some(layer_id) = layer_id {
+                let cell = &mut cells[*layer_id];
+                let signature = (relative(vertex_index, cell.t), graph.vertex_max_growth[vertex_index]);
+                cell.vertices.push((signature, vertex_index));
+            }
+        }
+        for (edge_index, edge) in graph.weighted_edges.iter().enumerate() {
+            let layer_id = match (assignment.vertex_layer_id[edge.l], assignment.vertex_layer_id[edge.r]) {
+                (Some(left), Some(right)) => left.min(right),
+                (Some(layer_id), None) | (None, Some(layer_id)) => layer_id,
+                (None, None) => continue, // an edge between virtual vertices is never used
+            };
+            let cell = &mut cells[layer_id];
+            let (left, right) = (relative(edge.l, cell.t), relative(edge.r, cell.t));
+            let signature = if left <= right {
+                (left, right, edge.w)
+            } else {
+                (right, left, edge.w)
+            };
+            cell.edges.push((signature, edge_index));
+        }
+        for cell in cells.iter_mut() {
+            cell.vertices.sort();
+            cell.edges.sort();
+        }
+        cells
+    }
+
+    /// the number of weight ROM words when every tile shares a single copy of its weights
+    pub fn weight_rom_size(&self, graph: &MicroBlossomSingle) -> usize {
+        let shared_edges: usize = self
+            .tiles
+            .iter()
+            .map(|tile| tile.edges.iter().map(Vec::len).sum::<usize>())
+            .sum();
+        let template_edges: usize = self.tiles.iter().map(|tile| tile.weights.len()).sum();
+        graph.weighted_edges.len() - shared_edges + template_edges
+    }
+}
+
+impl MicroBlossomSingle {
+    /// detect the translational symmetry and record the sharing hints, or None if there is no replicated tile
+    pub fn detect_symmetry(&mut self) {
+        let sharing_hints = SharingHints::new(self);
+        self.sharing_hints = (!sharing_hints.tiles.is_empty()).then_some(sharing_hints);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn graph_symmetry_phenomenological() {
+        // cargo test graph_symmetry_phenomenological -- --nocapture
+        let code = PhenomenologicalRotatedCode::new(5, 5, 0.1, 500);
+        let mut graph = MicroBlossomSingle::new_code(&code);
+        graph.detect_symmetry();
+        let sharing_hints = graph.sharing_hints.as_ref().unwrap();
+        assert_eq!(sharing_hints.tiles.len(), 1);
+        let tile = &sharing_hints.tiles[0];
+        println!("layers: {:?}, period: {}", tile.layers, tile.period);
+        // the last layer has no edges to the next round
+        assert_eq!(tile.layers, (0..5).collect::<Vec<_>>());
+        for copy in 0..tile.layers.len() {
+            for (x, &vertex_index) in tile.vertices[copy].iter().enumerate() {
+                let template = &graph.positions[tile.vertices[0][x]];
+                let position = &graph.positions[vertex_index];
+                assert_eq!((position.i, position.j), (template.i, template.j));
+                assert_eq!(position.t, template.t + copy as f64 * tile.period);
+            }
+            for (x, &edge_index) in tile.edges[copy].iter().enumerate() {
+                assert_eq!(graph.weighted_edges[edge_index].w, tile.weights[x]);
+            }
+        }
+        let rom_size = sharing_hints.weight_rom_size(&graph);
+        println!("weight ROM: {rom_size} words for {} edges", graph.weighted_edges.len());
+        assert!(rom_size * 2 < graph.weighted_edges.len());
+    }
+
+    #[test]
+    fn graph_symmetry_none() {
+        // cargo test graph_symmetry_none -- --nocapture
+        let code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let mut graph = MicroBlossomSingle::new_code(&code);
+        graph.detect_symmetry();
+        assert_eq!(graph.sharing_hints, None);
+    }
+}
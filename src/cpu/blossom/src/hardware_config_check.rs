@@ -0,0 +1,242 @@
+//! Hardware Configuration Check
+//!
+//! The SpinalHDL generator consumes its own copy of the graph configuration (`DualConfig(filename = ...)`), which
+//! may drift from the graph used by the software, e.g., when the graph is regenerated with a different weight
+//! quantization or offloading finder but the bitstream is not. This module compares the configuration actually
+//! consumed by the generator against the graph entry by entry: the vertex and edge tables, the weights, the
+//! offloading units, the layer fusion and the binary trees.
+//!
+//! Optionally, the hardware information read back by the driver (`get_hardware_info()` in the firmware) is checked
+//! against the bit widths that the generator derives from the graph, see `DualConfig.fitGraph`.
+//!
+//! Run it with `micro-blossom check-hardware-config <graph.json> <generated.json> --readback <info.json>`.
+//!
+
+use crate::resources::*;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// an entry that differs between the graph and the hardware configuration
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConfigMismatch {
+    pub table: String,
+    /// the index in the table, `None` when the table itself (e.g., its length) differs
+    pub index: Option<usize>,
+    pub expected: serde_json::Value,
+    pub actual: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConfigCheckReport {
+    /// the number of compared entries
+    pub compared: usize,
+    pub mismatches: Vec<ConfigMismatch>,
+}
+
+impl ConfigCheckReport {
+    pub fn is_consistent(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    pub fn assert_consistent(&self) {
+        assert!(
+            self.is_consistent(),
+            "hardware configuration drifted from the graph: {}",
+            serde_json::to_string_pretty(self).unwrap()
+        );
+    }
+
+    fn compare<T: PartialEq + Serialize>(&mut self, table: &str, index: Option<usize>, expected: &T, actual: &T) {
+        self.compared += 1;
+        if expected != actual {
+            self.mismatches.push(ConfigMismatch {
+                table: table.to_string(),
+                index,
+                expected: json!(expected),
+                actual: json!(actual),
+            });
+        }
+    }
+
+    /// compare the common entries one by one, and the length if it differs
+    fn compare_table<T: PartialEq + Serialize>(&mut self, table: &str, expected: &[T], actual: &[T]) {
+        if expected.len() != actual.len() {
+            self.compare(&format!("{table}.len"), None, &expected.len(), &actual.len());
+        }
+        for (index, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+            self.compare(table, Some(index), expected, actual);
+        }
+    }
+}
+
+/// the hardware information read back by the driver; the other fields, e.g., the version, are ignored
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HardwareReadback {
+    pub vertex_bits: usize,
+    pub weight_bits: usize,
+    /// only checked when layer fusion is supported
+    #[serde(default = "Default::default")]
+    pub num_layers: usize,
+    #[serde(default = "Default::default")]
+    pub support_layer_fusion: bool,
+}
+
+/// `log2Up` of SpinalHDL: the number of bits to represent `value` different values
+pub fn log2_up(value: usize) -> usize {
+    assert!(value > 0);
+    (usize::BITS - (value - 1).leading_zeros()) as usize
+}
+
+/// the vertex bits and the weight bits that the generator derives from the graph, see `DualConfig.fitGraph`
+pub fn generated_bits(graph: &MicroBlossomSingle) -> (usize, usize) {
+    assert!(graph.vertex_num > 0);
+    let mut vertex_bits = log2_up(graph.vertex_num * 2);
+    let max_weight = graph.weighted_edges.iter().map(|edge| edge.w).max().unwrap();
+    assert!(max_weight > 0);
+    let weight_bits = log2_up(max_weight as usize + 1);
+    if weight_bits + 4 > vertex_bits * 2 {
+        vertex_bits = (weight_bits + 5) / 2;
+    }
+    (vertex_bits.max(5), weight_bits)
+}
+
+/// compare the configuration consumed by the generator against the graph
+pub fn check_hardware_config(graph: &MicroBlossomSingle, generated: &MicroBlossomSingle) -> ConfigCheckReport {
+    let mut report = ConfigCheckReport::default();
+    report.compare("vertex_num", None, &graph.vertex_num, &generated.vertex_num);
+    let is_virtual = |graph: &MicroBlossomSingle| {
+        let mut is_virtual = vec![false; graph.vertex_num];
+        for &vertex_index in graph.virtual_vertices.iter() {
+            is_virtual[vertex_index] = true;
+        }
+        is_virtual
+    };
+    report.compare_table("is_virtual", &is_virtual(graph), &is_virtual(generated));
+    report.compare_table("vertex_max_growth", &graph.vertex_max_growth, &generated.vertex_max_growth);
+    let edge_vertices = |graph: &MicroBlossomSingle| -> Vec<[usize; 2]> {
+        graph.weighted_edges.iter().map(|edge| [edge.l, edge.r]).collect()
+    };
+    report.compare_table("edge_vertices", &edge_vertices(graph), &edge_vertices(generated));
+    let edge_weights =
+        |graph: &MicroBlossomSingle| -> Vec<isize> { graph.weighted_edges.iter().map(|edge| edge.w).collect() };
+    report.compare_table("edge_weights", &edge_weights(graph), &edge_weights(generated));
+    report.compare_table("offloading", &graph.offloading.0, &generated.offloading.0);
+    report.compare("layer_fusion", None, &graph.layer_fusion, &generated.layer_fusion);
+    report.compare("parity_reporters", None, &graph.parity_reporters, &generated.parity_reporters);
+    report.compare(
+        "vertex_binary_tree",
+        None,
+        &graph.vertex_binary_tree,
+        &generated.vertex_binary_tree,
+    );
+    report.compare("edge_binary_tree", None, &graph.edge_binary_tree, &generated.edge_binary_tree);
+    report.compare(
+        "vertex_edge_binary_tree",
+        None,
+        &graph.vertex_edge_binary_tree,
+        &generated.vertex_edge_binary_tree,
+    );
+    report
+}
+
+/// compare the hardware information read back by the driver against the bits derived from the graph
+pub fn check_readback(graph: &MicroBlossomSingle, readback: &HardwareReadback, report: &mut ConfigCheckReport) {
+    let (vertex_bits, weight_bits) = generated_bits(graph);
+    report.compare("vertex_bits", None, &vertex_bits, &readback.vertex_bits);
+    report.compare("weight_bits", None, &weight_bits, &readback.weight_bits);
+    if readback.support_layer_fusion {
+        let num_layers = graph.layer_fusion.as_ref().map(|layer_fusion| layer_fusion.num_layers);
+        report.compare("num_layers", None, &num_layers, &Some(readback.num_layers));
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct CheckHardwareConfigParameters {
+    /// the graph used by the software, e.g., generated by `parser --graph-file`
+    #[clap(value_parser)]
+    graph_file: String,
+    /// the graph configuration consumed by the SpinalHDL generator
+    #[clap(value_parser)]
+    generated_file: String,
+    /// the hardware information read back by the driver in JSON, see [`HardwareReadback`]
+    #[clap(long)]
+    readback: Option<String>,
+}
+
+impl CheckHardwareConfigParameters {
+    pub fn run(&self) {
+        let load = |filename: &str| -> MicroBlossomSingle {
+            serde_json::from_str(&std::fs::read_to_string(filename).unwrap()).unwrap()
+        };
+        let graph = load(&self.graph_file);
+        let mut report = check_hardware_config(&graph, &load(&self.generated_file));
+        if let Some(readback) = self.readback.as_ref() {
+            let readback: HardwareReadback = serde_json::from_str(&std::fs::read_to_string(readback).unwrap()).unwrap();
+            check_readback(&graph, &readback, &mut report);
+        }
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        if !report.is_consistent() {
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn hardware_config_check_drift() {
+        // cargo test hardware_config_check_drift -- --nocapture
+        let code = CodeCapacityPlanarCode::new(5, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let report = check_hardware_config(&graph, &graph.clone());
+        report.assert_consistent();
+        assert!(report.compared > graph.weighted_edges.len() * 2);
+        // a bitstream generated before the graph is re-quantized and the offloading units are dropped
+        let mut generated = graph.clone();
+        generated.weighted_edges[3].w += 2;
+        generated.weighted_edges.swap(5, 6);
+        generated.offloading.0.pop();
+        let report = check_hardware_config(&graph, &generated);
+        let mismatches: Vec<(&str, Option<usize>)> = (report.mismatches.iter())
+            .map(|mismatch| (mismatch.table.as_str(), mismatch.index))
+            .collect();
+        assert_eq!(
+            mismatches,
+            vec![
+                ("edge_vertices", Some(5)),
+                ("edge_vertices", Some(6)),
+                ("edge_weights", Some(3)),
+                ("offloading.len", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn hardware_config_check_readback() {
+        // cargo test hardware_config_check_readback -- --nocapture
+        let code = CodeCapacityRepetitionCode::new(3, 0.1, 1);
+        let graph = MicroBlossomSingle::new_code(&code);
+        // at least 5 vertex bits to encode all the instructions
+        assert_eq!(generated_bits(&graph), (5, 2));
+        let code = CodeCapacityPlanarCode::new(5, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        // the vertex bits are expanded so that an instruction holds the maximum length
+        assert_eq!(generated_bits(&graph), (7, 10));
+        let readback: HardwareReadback =
+            serde_json::from_value(json!({ "version": 0x240123c0u32, "vertex_bits": 7, "weight_bits": 10 })).unwrap();
+        let mut report = ConfigCheckReport::default();
+        check_readback(&graph, &readback, &mut report);
+        report.assert_consistent();
+        let readback = HardwareReadback {
+            weight_bits: 9,
+            ..readback
+        };
+        check_readback(&graph, &readback, &mut report);
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].table, "weight_bits");
+    }
+}
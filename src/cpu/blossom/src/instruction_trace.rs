@@ -0,0 +1,867 @@
+//! Instruction Trace
+//!
+//! A compact storage format for the instructions sent to the dual accelerator and the responses read back from it.
+//! Stored as raw words, the trace of millions of shots quickly becomes too large to keep, so each record is encoded
+//! with the domain knowledge of the instruction set:
+//! node and vertex indices are delta-encoded against the previous ones (e.g., defects are loaded in increasing
+//! vertex order with consecutive node indices), all the integers are variable-length, and a repeated block of
+//! records is run-length encoded (typically the find-obstacle-then-grow loop when the defects are far apart).
+//! Any instruction that is not recognized is stored as a raw word, so the encoding is always lossless.
+//!
+//! The delta state is reset at every shot boundary; the reader decodes the records one by one without loading the
+//! whole file. Both the writer and the reader keep at most [`MAX_REPEAT_PERIOD`] recent events, and a repetition is
+//! replayed lazily regardless of its count, so a multi-gigabyte capture is processed with bounded memory, e.g., by
+//! `micro-blossom trace stats <file>` which summarizes the instruction mix in a single pass. A trace recorded from a
+//! dual driver (see [`crate::dual_module_trace`]) is fed back by `micro-blossom trace replay <file> <graph>`, and its
+//! latency is broken down by `micro-blossom trace critical-path <file>`, see [`crate::critical_path`].
+//!
+
+use crate::critical_path::*;
+use crate::dual_module_comb::*;
+use crate::dual_module_trace::*;
+use crate::resources::*;
+use clap::Subcommand;
+use micro_blossom_nostd::instruction::*;
+use micro_blossom_nostd::interface::*;
+use micro_blossom_nostd::util::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceEvent {
+    Instruction(Instruction32),
+    /// the obstacle and the accumulated growth read back from the accelerator
+    Response {
+        obstacle: CompactObstacle,
+        grown: CompactWeight,
+    },
+    /// the end of a shot
+    ShotEnd,
+    /// a write to the maximum growth register of the primal offloading unit, which bounds the growth of the
+    /// following `FindObstacle` instructions
+    MaximumGrowth(CompactWeight),
+}
+
+pub const TRACE_MAGIC: &[u8; 8] = b"MBTRACE\x01";
+
+/// the maximum length of a block of events that can be repeated, e.g., `FindObstacle`, `GrowLength` and `Grow`
+pub const MAX_REPEAT_PERIOD: usize = 8;
+
+const TAG_SHOT_END: u8 = 0x00;
+const TAG_REPEAT: u8 = 0x01;
+const TAG_SET_SPEED: u8 = 0x10; // the lower 2 bits are the grow state
+const TAG_SET_BLOSSOM: u8 = 0x20;
+const TAG_ADD_DEFECT_VERTEX: u8 = 0x21;
+const TAG_GROW: u8 = 0x22;
+const TAG_FIND_OBSTACLE: u8 = 0x23;
+const TAG_RESET: u8 = 0x24;
+const TAG_RAW_INSTRUCTION: u8 = 0x2F;
+const TAG_RESPONSE_NONE: u8 = 0x30;
+const TAG_RESPONSE_GROW_LENGTH: u8 = 0x31;
+const TAG_RESPONSE_CONFLICT: u8 = 0x32;
+const TAG_RESPONSE_BLOSSOM_NEED_EXPAND: u8 = 0x33;
+const TAG_MAXIMUM_GROWTH: u8 = 0x40;
+
+/// the references of delta encoding, shared by the writer and the reader
+#[derive(Default)]
+struct DeltaState {
+    node: i64,
+    blossom: i64,
+    vertex: i64,
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn write_varint(writer: &mut impl Write, mut value: u64) -> Result<()> {
+    let mut buffer = [0u8; 10];
+    let mut length = 0;
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            buffer[length] = byte;
+            length += 1;
+            break;
+        }
+        buffer[length] = byte | 0x80;
+        length += 1;
+    }
+    writer.write_all(&buffer[..length])
+}
+
+fn read_byte(reader: &mut impl Read) -> Result<Option<u8>> {
+    let mut byte = [0u8];
+    loop {
+        match reader.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+fn read_varint(reader: &mut impl Read) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = read_byte(reader)?.ok_or_else(|| Error::from(ErrorKind::UnexpectedEof))?;
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(Error::new(ErrorKind::InvalidData, "varint too long"))
+}
+
+fn write_delta(writer: &mut impl Write, value: i64, reference: i64) -> Result<()> {
+    write_varint(writer, zigzag(value - reference))
+}
+
+fn read_delta(reader: &mut impl Read, reference: i64) -> Result<i64> {
+    Ok(reference + unzigzag(read_varint(reader)?))
+}
+
+/// 0 for None, otherwise the zigzag delta plus one
+fn write_option_delta(writer: &mut impl Write, value: Option<i64>, reference: i64) -> Result<()> {
+    write_varint(writer, value.map_or(0, |value| zigzag(value - reference) + 1))
+}
+
+fn read_option_delta(reader: &mut impl Read, reference: i64) -> Result<Option<i64>> {
+    Ok(match read_varint(reader)? {
+        0 => None,
+        encoded => Some(reference + unzigzag(encoded - 1)),
+    })
+}
+
+fn node_of(value: i64) -> Result<CompactNodeIndex> {
+    CompactNodeNum::try_from(value)
+        .ok()
+        .and_then(|value| CompactNodeIndex::new(value).option())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid index"))
+}
+
+fn option_node_of(value: Option<i64>) -> Result<OptionCompactNodeIndex> {
+    Ok(match value {
+        Some(value) => node_of(value)?.option(),
+        None => None.into(),
+    })
+}
+
+fn option_value(value: OptionCompactNodeIndex) -> Option<i64> {
+    value.option().map(|index| index.get() as i64)
+}
+
+/// a compressed encoding of the instruction, if it recovers exactly the same word
+fn compress_instruction(instruction: Instruction32) -> Option<(u8, Vec<i64>)> {
+    let word = instruction.0;
+    let field1 = instruction.field1() as i64;
+    let field2 = instruction.field2() as i64;
+    let (tag, values) = if instruction.is_set_speed() {
+        (TAG_SET_SPEED | instruction.get_speed() as u8, vec![field1])
+    } else if instruction.is_set_blossom() {
+        (TAG_SET_BLOSSOM, vec![field1, field2])
+    } else if instruction.op_code() == OP_CODE_ADD_DEFECT_VERTEX {
+        (TAG_ADD_DEFECT_VERTEX, vec![field1, field2])
+    } else if instruction.is_grow() {
+        (TAG_GROW, vec![(word >> 6) as i64])
+    } else if instruction == Instruction32::find_obstacle() {
+        (TAG_FIND_OBSTACLE, vec![])
+    } else if instruction == Instruction32::reset() {
+        (TAG_RESET, vec![])
+    } else {
+        return None;
+    };
+    (decompress_instruction(tag, &values).ok()? == instruction).then_some((tag, values))
+}
+
+fn decompress_instruction(tag: u8, values: &[i64]) -> Result<Instruction32> {
+    Ok(match tag {
+        _ if tag & !0b11 == TAG_SET_SPEED => {
+            let speed = match tag & 0b11 {
+                0 => CompactGrowState::Stay,
+                1 => CompactGrowState::Grow,
+                2 => CompactGrowState::Shrink,
+                _ => return Err(Error::new(ErrorKind::InvalidData, "invalid speed")),
+            };
+            Instruction32::set_speed(node_of(values[0])?, speed)
+        }
+        TAG_SET_BLOSSOM => Instruction32::set_blossom(node_of(values[0])?, node_of(values[1])?),
+        TAG_ADD_DEFECT_VERTEX => Instruction32::add_defect_vertex(node_of(values[0])?, node_of(values[1])?),
+        TAG_GROW => Instruction32::grow(values[0] as CompactWeight),
+        TAG_FIND_OBSTACLE => Instruction32::find_obstacle(),
+        TAG_RESET => Instruction32::reset(),
+        _ => unreachable!(),
+    })
+}
+
+/// a pending repetition of the last `block.len()` events
+struct Repetition {
+    block: Vec<TraceEvent>,
+    repeats: u64,
+    /// the number of events in a partial repetition
+    partial: usize,
+}
+
+impl Repetition {
+    fn advance(&mut self) {
+        self.partial += 1;
+        if self.partial == self.block.len() {
+            self.repeats += 1;
+            self.partial = 0;
+        }
+    }
+}
+
+fn push_recent(recent: &mut VecDeque<TraceEvent>, event: &TraceEvent) {
+    if recent.len() == MAX_REPEAT_PERIOD {
+        recent.pop_front();
+    }
+    recent.push_back(event.clone());
+}
+
+pub struct TraceWriter<W: Write> {
+    writer: W,
+    state: DeltaState,
+    /// the last events of the current shot, to detect repetitions
+    recent: VecDeque<TraceEvent>,
+    repetition: Option<Repetition>,
+    /// the number of events written
+    pub count: usize,
+}
+
+impl TraceWriter<BufWriter<File>> {
+    pub fn create(filename: &str) -> Result<Self> {
+        Self::new(BufWriter::new(File::create(filename)?))
+    }
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(TRACE_MAGIC)?;
+        Ok(Self {
+            writer,
+            state: DeltaState::default(),
+            recent: VecDeque::with_capacity(MAX_REPEAT_PERIOD),
+            repetition: None,
+            count: 0,
+        })
+    }
+
+    pub fn write(&mut self, event: &TraceEvent) -> Result<()> {
+        self.count += 1;
+        if event == &TraceEvent::ShotEnd {
+            self.flush_repetition()?;
+            self.writer.write_all(&[TAG_SHOT_END])?;
+            self.state = DeltaState::default();
+            self.recent.clear();
+            return Ok(());
+        }
+        if let Some(repetition) = self.repetition.as_mut() {
+            if &repetition.block[repetition.partial] == event {
+                repetition.advance();
+                push_recent(&mut self.recent, event);
+                return Ok(());
+            }
+            self.flush_repetition()?;
+        }
+        let length = self.recent.len();
+        if let Some(period) = (1..=length).find(|&period| &self.recent[length - period] == event) {
+            let mut repetition = Repetition {
+                block: self.recent.range(length - period..).cloned().collect(),
+                repeats: 0,
+                partial: 0,
+            };
+            repetition.advance();
+            self.repetition = Some(repetition);
+        } else {
+            self.encode(event)?;
+        }
+        push_recent(&mut self.recent, event);
+        Ok(())
+    }
+
+    fn flush_repetition(&mut self) -> Result<()> {
+        if let Some(repetition) = self.repetition.take() {
+            if repetition.repeats > 0 {
+                self.writer.write_all(&[TAG_REPEAT])?;
+                write_varint(&mut self.writer, repetition.block.len() as u64)?;
+                write_varint(&mut self.writer, repetition.repeats)?;
+            }
+            for event in repetition.block[..repetition.partial].iter() {
+                self.encode(event)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn encode(&mut self, event: &TraceEvent) -> Result<()> {
+        let writer = &mut self.writer;
+        let state = &mut self.state;
+        match event {
+            TraceEvent::ShotEnd => unreachable!(),
+            TraceEvent::Instruction(instruction) => match compress_instruction(*instruction) {
+                Some((tag, values)) => {
+                    writer.write_all(&[tag])?;
+                    match tag {
+                        TAG_SET_BLOSSOM => {
+                            write_delta(writer, values[0], state.node)?;
+                            write_delta(writer, values[1], state.blossom)?;
+                            (state.node, state.blossom) = (values[0], values[1]);
+                        }
+                        TAG_ADD_DEFECT_VERTEX => {
+                            write_delta(writer, values[0], state.vertex)?;
+                            // node indices of defects are usually consecutive
+                            write_delta(writer, values[1], state.node + 1)?;
+                            (state.vertex, state.node) = (values[0], values[1]);
+                        }
+                        TAG_GROW => write_varint(writer, values[0] as u64)?,
+                        TAG_FIND_OBSTACLE | TAG_RESET => {}
+                        _ => {
+                            write_delta(writer, values[0], state.node)?;
+                            state.node = values[0];
+                        }
+                    }
+                }
+                None => {
+                    writer.write_all(&[TAG_RAW_INSTRUCTION])?;
+                    write_varint(writer, instruction.0 as u64)?;
+                }
+            },
+            TraceEvent::Response { obstacle, grown } => {
+                match obstacle {
+                    CompactObstacle::None => writer.write_all(&[TAG_RESPONSE_NONE])?,
+                    CompactObstacle::GrowLength { length } => {
+                        writer.write_all(&[TAG_RESPONSE_GROW_LENGTH])?;
+                        write_varint(writer, zigzag(*length as i64))?;
+                    }
+                    CompactObstacle::Conflict {
+                        node_1,
+                        node_2,
+                        touch_1,
+                        touch_2,
+                        vertex_1,
+                        vertex_2,
+                    } => {
+                        writer.write_all(&[TAG_RESPONSE_CONFLICT])?;
+                        let node_1 = option_value(*node_1);
+                        let node_2 = option_value(*node_2);
+                        let reference_1 = node_1.unwrap_or(state.node);
+                        write_option_delta(writer, node_1, state.node)?;
+                        write_option_delta(writer, node_2, reference_1)?;
+                        write_option_delta(writer, option_value(*touch_1), reference_1)?;
+                        write_option_delta(writer, option_value(*touch_2), node_2.unwrap_or(reference_1))?;
+                        let vertex_1 = vertex_1.get() as i64;
+                        write_delta(writer, vertex_1, state.vertex)?;
+                        write_delta(writer, vertex_2.get() as i64, vertex_1)?;
+                        (state.node, state.vertex) = (reference_1, vertex_1);
+                    }
+                    CompactObstacle::BlossomNeedExpand { blossom } => {
+                        writer.write_all(&[TAG_RESPONSE_BLOSSOM_NEED_EXPAND])?;
+                        write_delta(writer, blossom.get() as i64, state.blossom)?;
+                        state.blossom = blossom.get() as i64;
+                    }
+                }
+                write_varint(writer, zigzag(*grown as i64))?;
+            }
+            TraceEvent::MaximumGrowth(length) => {
+                writer.write_all(&[TAG_MAXIMUM_GROWTH])?;
+                write_varint(writer, zigzag(*length as i64))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// flush the pending repetition and return the inner writer
+    pub fn finish(mut self) -> Result<W> {
+        self.flush_repetition()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// streaming reader of the events, one at a time
+pub struct TraceReader<R: Read> {
+    reader: R,
+    state: DeltaState,
+    recent: VecDeque<TraceEvent>,
+    /// the block being replayed, the number of remaining events and the position in the block
+    replay: Option<(Vec<TraceEvent>, u64, usize)>,
+}
+
+impl TraceReader<BufReader<File>> {
+    pub fn open(filename: &str) -> Result<Self> {
+        Self::new(BufReader::new(File::open(filename)?))
+    }
+}
+
+impl<R: Read> TraceReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != TRACE_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not an instruction trace"));
+        }
+        Ok(Self {
+            reader,
+            state: DeltaState::default(),
+            recent: VecDeque::with_capacity(MAX_REPEAT_PERIOD),
+            replay: None,
+        })
+    }
+
+    /// returns None at the end of the trace
+    pub fn read(&mut self) -> Result<Option<TraceEvent>> {
+        if let Some((block, remaining, position)) = self.replay.as_mut() {
+            let event = block[*position].clone();
+            *position = (*position + 1) % block.len();
+            *remaining -= 1;
+            if *remaining == 0 {
+                self.replay = None;
+            }
+            push_recent(&mut self.recent, &event);
+            return Ok(Some(event));
+        }
+        let Some(tag) = read_byte(&mut self.reader)? else {
+            return Ok(None);
+        };
+        if tag == TAG_SHOT_END {
+            self.state = DeltaState::default();
+            self.recent.clear();
+            return Ok(Some(TraceEvent::ShotEnd));
+        }
+        if tag == TAG_REPEAT {
+            let period = read_varint(&mut self.reader)? as usize;
+            let repeats = read_varint(&mut self.reader)?;
+            if period == 0 || period > self.recent.len() || repeats == 0 {
+                return Err(Error::new(ErrorKind::InvalidData, "invalid repetition"));
+            }
+            let block = self.recent.range(self.recent.len() - period..).cloned().collect();
+            self.replay = Some((block, period as u64 * repeats, 0));
+            return self.read();
+        }
+        let event = self.decode(tag)?;
+        push_recent(&mut self.recent, &event);
+        Ok(Some(event))
+    }
+
+    fn decode(&mut self, tag: u8) -> Result<TraceEvent> {
+        let reader = &mut self.reader;
+        let state = &mut self.state;
+        Ok(match tag {
+            TAG_SET_BLOSSOM => {
+                state.node = read_delta(reader, state.node)?;
+                state.blossom = read_delta(reader, state.blossom)?;
+                TraceEvent::Instruction(decompress_instruction(tag, &[state.node, state.blossom])?)
+            }
+            TAG_ADD_DEFECT_VERTEX => {
+                state.vertex = read_delta(reader, state.vertex)?;
+                state.node = read_delta(reader, state.node + 1)?;
+                TraceEvent::Instruction(decompress_instruction(tag, &[state.vertex, state.node])?)
+            }
+            TAG_GROW => TraceEvent::Instruction(decompress_instruction(tag, &[read_varint(reader)? as i64])?),
+            TAG_FIND_OBSTACLE | TAG_RESET => TraceEvent::Instruction(decompress_instruction(tag, &[])?),
+            _ if tag & !0b11 == TAG_SET_SPEED => {
+                state.node = read_delta(reader, state.node)?;
+                TraceEvent::Instruction(decompress_instruction(tag, &[state.node])?)
+            }
+            TAG_RAW_INSTRUCTION => TraceEvent::Instruction(Instruction32(read_varint(reader)? as u32)),
+            TAG_RESPONSE_NONE..=TAG_RESPONSE_BLOSSOM_NEED_EXPAND => {
+                let obstacle = match tag {
+                    TAG_RESPONSE_NONE => CompactObstacle::None,
+                    TAG_RESPONSE_GROW_LENGTH => CompactObstacle::GrowLength {
+                        length: unzigzag(read_varint(reader)?) as CompactWeight,
+                    },
+                    TAG_RESPONSE_CONFLICT => {
+                        let node_1 = read_option_delta(reader, state.node)?;
+                        let reference_1 = node_1.unwrap_or(state.node);
+                        let node_2 = read_option_delta(reader, reference_1)?;
+                        let touch_1 = read_option_delta(reader, reference_1)?;
+                        let touch_2 = read_option_delta(reader, node_2.unwrap_or(reference_1))?;
+                        let vertex_1 = read_delta(reader, state.vertex)?;
+                        let vertex_2 = read_delta(reader, vertex_1)?;
+                        (state.node, state.vertex) = (reference_1, vertex_1);
+                        CompactObstacle::Conflict {
+                            node_1: option_node_of(node_1)?,
+                            node_2: option_node_of(node_2)?,
+                            touch_1: option_node_of(touch_1)?,
+                            touch_2: option_node_of(touch_2)?,
+                            vertex_1: node_of(vertex_1)?,
+                            vertex_2: node_of(vertex_2)?,
+                        }
+                    }
+                    _ => {
+                        state.blossom = read_delta(reader, state.blossom)?;
+                        CompactObstacle::BlossomNeedExpand {
+                            blossom: node_of(state.blossom)?,
+                        }
+                    }
+                };
+                let grown = unzigzag(read_varint(reader)?) as CompactWeight;
+                TraceEvent::Response { obstacle, grown }
+            }
+            TAG_MAXIMUM_GROWTH => TraceEvent::MaximumGrowth(unzigzag(read_varint(reader)?) as CompactWeight),
+            _ => return Err(Error::new(ErrorKind::InvalidData, format!("unknown tag {tag:#04X}"))),
+        })
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = Result<TraceEvent>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+/// the name of the instruction in the instruction mix
+pub fn instruction_kind(instruction: Instruction32) -> &'static str {
+    if instruction.is_set_speed() {
+        return "set_speed";
+    }
+    if !instruction.is_extended() {
+        return match instruction.op_code() {
+            OP_CODE_SET_BLOSSOM => "set_blossom",
+            OP_CODE_ADD_DEFECT_VERTEX => "add_defect_vertex",
+            _ => "match",
+        };
+    }
+    match instruction.extended_op_code() {
+        EXTENDED_OP_CODE_FIND_OBSTACLE => "find_obstacle",
+        EXTENDED_OP_CODE_CLEAR_ACCUMULATOR => "clear_accumulator",
+        EXTENDED_OP_CODE_ACCUMULATE_EDGE => "accumulate_edge",
+        EXTENDED_OP_CODE_LOAD_WEIGHTS_EXTERNAL => "load_weights_external",
+        EXTENDED_OP_CODE_RESET => "reset",
+        EXTENDED_OP_CODE_LOAD_DEFECTS_EXTERNAL => "load_defects_external",
+        EXTENDED_OP_CODE_GROW => "grow",
+        _ if instruction.is_read_vertex() => "read_vertex_grown",
+        _ => "read_dual",
+    }
+}
+
+/// the name of the obstacle in the response mix
+pub fn response_kind(obstacle: &CompactObstacle) -> &'static str {
+    match obstacle {
+        CompactObstacle::None => "none",
+        CompactObstacle::GrowLength { .. } => "grow_length",
+        CompactObstacle::Conflict { .. } => "conflict",
+        CompactObstacle::BlossomNeedExpand { .. } => "blossom_need_expand",
+    }
+}
+
+/// the summary of a trace, accumulated one event at a time so that its memory does not grow with the trace
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TraceStatistics {
+    pub events: usize,
+    /// the number of complete shots; the events after the last shot end are counted but do not form a shot
+    pub shots: usize,
+    pub instructions: BTreeMap<&'static str, usize>,
+    pub responses: BTreeMap<&'static str, usize>,
+    /// the maximum number of instructions in a shot
+    pub max_shot_instructions: usize,
+    /// the maximum number of defects added in a shot
+    pub max_shot_defects: usize,
+    pub maximum_growth_writes: usize,
+    /// the instructions and defects of the current shot
+    #[serde(skip)]
+    shot_instructions: usize,
+    #[serde(skip)]
+    shot_defects: usize,
+}
+
+impl TraceStatistics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_reader<R: Read>(reader: TraceReader<R>) -> Result<Self> {
+        let mut statistics = Self::new();
+        for event in reader {
+            statistics.record(&event?);
+        }
+        Ok(statistics)
+    }
+
+    pub fn record(&mut self, event: &TraceEvent) {
+        self.events += 1;
+        match event {
+            TraceEvent::Instruction(instruction) => {
+                let kind = instruction_kind(*instruction);
+                *self.instructions.entry(kind).or_default() += 1;
+                self.shot_instructions += 1;
+                if kind == "add_defect_vertex" {
+                    self.shot_defects += 1;
+                }
+            }
+            TraceEvent::Response { obstacle, .. } => *self.responses.entry(response_kind(obstacle)).or_default() += 1,
+            TraceEvent::MaximumGrowth(_) => self.maximum_growth_writes += 1,
+            TraceEvent::ShotEnd => {
+                self.shots += 1;
+                self.max_shot_instructions = self.max_shot_instructions.max(self.shot_instructions);
+                self.max_shot_defects = self.max_shot_defects.max(self.shot_defects);
+                self.shot_instructions = 0;
+                self.shot_defects = 0;
+            }
+        }
+    }
+
+    /// the total number of instructions
+    pub fn instruction_count(&self) -> usize {
+        self.instructions.values().sum()
+    }
+
+    pub fn print(&self) {
+        let instruction_count = self.instruction_count();
+        println!("events: {}, shots: {}", self.events, self.shots);
+        println!(
+            "instructions: {instruction_count} ({:.2} per shot, max {} per shot)",
+            instruction_count as f64 / self.shots.max(1) as f64,
+            self.max_shot_instructions
+        );
+        for (kind, count) in self.instructions.iter() {
+            println!(
+                "    {kind:>22}: {count:>12} ({:>6.2}%)",
+                100. * *count as f64 / instruction_count.max(1) as f64
+            );
+        }
+        println!("responses: {}", self.responses.values().sum::<usize>());
+        for (kind, count) in self.responses.iter() {
+            println!("    {kind:>22}: {count:>12}");
+        }
+        println!("max defects per shot: {}", self.max_shot_defects);
+        println!("maximum growth writes: {}", self.maximum_growth_writes);
+    }
+}
+
+#[derive(Subcommand, Clone)]
+pub enum TraceCommands {
+    /// summarize the instruction mix of a trace in a single streaming pass
+    Stats {
+        /// the trace file, or `-` to read from the standard input
+        #[clap(value_parser)]
+        trace_file: String,
+        /// print in JSON format
+        #[clap(long, action)]
+        json: bool,
+    },
+    /// reconstruct the critical path of every shot and report the top contributors to the latency, see
+    /// [`crate::critical_path`]
+    CriticalPath {
+        /// the trace file, or `-` to read from the standard input
+        #[clap(value_parser)]
+        trace_file: String,
+        /// the costs of the interface, the hardware and the firmware, see [`CriticalPathConfig`]
+        #[clap(long, default_value_t = ("{}").to_string())]
+        config: String,
+        /// the number of top contributors to report
+        #[clap(long, default_value_t = 10)]
+        top: usize,
+        /// write the critical path of every shot to this file, one line of JSON per shot
+        #[clap(long)]
+        shots_output: Option<String>,
+        /// print in JSON format
+        #[clap(long, action)]
+        json: bool,
+    },
+    /// replay the instructions of a trace on the combinatorial dual module and compare the responses
+    Replay {
+        #[clap(value_parser)]
+        trace_file: String,
+        /// the graph that the trace is recorded on, in the JSON format of [`MicroBlossomSingle`]
+        #[clap(value_parser)]
+        graph_file: String,
+        /// the configuration of the combinatorial dual module
+        #[clap(long, default_value_t = ("{}").to_string())]
+        dual_config: String,
+    },
+}
+
+impl TraceCommands {
+    pub fn run(&self) {
+        match self {
+            Self::Stats { trace_file, json } => {
+                let statistics = if trace_file == "-" {
+                    TraceStatistics::from_reader(TraceReader::new(std::io::stdin().lock()).unwrap())
+                } else {
+                    TraceStatistics::from_reader(TraceReader::open(trace_file).unwrap())
+                }
+                .unwrap();
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&statistics).unwrap());
+                } else {
+                    statistics.print();
+                }
+            }
+            Self::CriticalPath {
+                trace_file,
+                config,
+                top,
+                shots_output,
+                json,
+            } => {
+                let config: CriticalPathConfig = serde_json::from_str(config).unwrap();
+                let mut shots_output = shots_output
+                    .as_ref()
+                    .map(|filename| BufWriter::new(File::create(filename).unwrap()));
+                let shots_output = shots_output.as_mut().map(|output| output as &mut dyn Write);
+                let analysis = if trace_file == "-" {
+                    let reader = TraceReader::new(std::io::stdin().lock()).unwrap();
+                    CriticalPathAnalysis::from_reader(config, reader, shots_output)
+                } else {
+                    CriticalPathAnalysis::from_reader(config, TraceReader::open(trace_file).unwrap(), shots_output)
+                }
+                .unwrap();
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&analysis.generate_report(*top)).unwrap());
+                } else {
+                    analysis.print(*top);
+                }
+            }
+            Self::Replay {
+                trace_file,
+                graph_file,
+                dual_config,
+            } => {
+                let graph: MicroBlossomSingle = serde_json::from_str(&std::fs::read_to_string(graph_file).unwrap()).unwrap();
+                let mut driver = DualModuleCombDriver::new(graph, serde_json::from_str(dual_config).unwrap());
+                let report = replay_trace(&mut driver, TraceReader::open(trace_file).unwrap()).unwrap();
+                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+                if report.mismatches > 0 {
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_shot(shot: usize) -> Vec<TraceEvent> {
+        let mut events = vec![TraceEvent::Instruction(Instruction32::reset())];
+        let defects: Vec<usize> = (0..10).map(|index| 100 * shot + 3 * index + shot % 3).collect();
+        for (node, &vertex) in defects.iter().enumerate() {
+            events.push(TraceEvent::Instruction(Instruction32::add_defect_vertex(
+                ni!(vertex),
+                ni!(node),
+            )));
+        }
+        for node in 0..defects.len() {
+            events.push(TraceEvent::Instruction(Instruction32::set_speed(
+                ni!(node),
+                CompactGrowState::Grow,
+            )));
+        }
+        events.push(TraceEvent::MaximumGrowth(40));
+        for _ in 0..20 {
+            events.push(TraceEvent::Instruction(Instruction32::find_obstacle()));
+            events.push(TraceEvent::Response {
+                obstacle: CompactObstacle::GrowLength { length: 2 },
+                grown: 0,
+            });
+            events.push(TraceEvent::Instruction(Instruction32::grow(2)));
+        }
+        for node in (0..defects.len()).step_by(2) {
+            events.push(TraceEvent::Response {
+                obstacle: CompactObstacle::Conflict {
+                    node_1: ni!(node).option(),
+                    node_2: if node == 0 { None.into() } else { ni!(node + 1).option() },
+                    touch_1: ni!(node).option(),
+                    touch_2: ni!(node + 1).option(),
+                    vertex_1: ni!(defects[node] + 1),
+                    vertex_2: ni!(defects[node] + 2),
+                },
+                grown: 40,
+            });
+            events.push(TraceEvent::Instruction(Instruction32::set_speed(
+                ni!(node),
+                CompactGrowState::Stay,
+            )));
+        }
+        events.push(TraceEvent::Instruction(Instruction32::set_blossom(ni!(1), ni!(5000))));
+        events.push(TraceEvent::Instruction(Instruction32::set_speed_with_magnitude(
+            ni!(5000),
+            CompactGrowState::Grow,
+            3,
+        )));
+        events.push(TraceEvent::Instruction(Instruction32::load_weights_external()));
+        events.push(TraceEvent::Response {
+            obstacle: CompactObstacle::BlossomNeedExpand { blossom: ni!(5000) },
+            grown: -1,
+        });
+        events.push(TraceEvent::Response {
+            obstacle: CompactObstacle::None,
+            grown: 0,
+        });
+        events.push(TraceEvent::ShotEnd);
+        events
+    }
+
+    #[test]
+    fn instruction_trace_round_trip() {
+        // cargo test instruction_trace_round_trip -- --nocapture
+        let events: Vec<TraceEvent> = (0..100).flat_map(example_shot).collect();
+        let mut writer = TraceWriter::new(vec![]).unwrap();
+        for event in events.iter() {
+            writer.write(event).unwrap();
+        }
+        assert_eq!(writer.count, events.len());
+        let bytes = writer.finish().unwrap();
+        // a raw trace takes at least one 32-bit word per event
+        println!("{} events compressed into {} bytes", events.len(), bytes.len());
+        assert!(bytes.len() * 2 < events.len() * 4);
+        let decoded: Vec<TraceEvent> = TraceReader::new(bytes.as_slice()).unwrap().map(Result::unwrap).collect();
+        assert_eq!(decoded.len(), events.len());
+        assert!(decoded == events);
+    }
+
+    /// the statistics are computed while streaming a trace much longer than the memory held by the reader
+    #[test]
+    fn instruction_trace_streaming_stats() {
+        // cargo test instruction_trace_streaming_stats -- --nocapture
+        let shots = 2000;
+        let path = std::env::temp_dir().join(format!("instruction_trace_streaming_stats_{}.trace", std::process::id()));
+        let filename = path.to_str().unwrap();
+        let mut writer = TraceWriter::create(filename).unwrap();
+        let mut expected = TraceStatistics::new();
+        for shot in 0..shots {
+            // generate on the fly so that the whole trace is never held in memory
+            for event in example_shot(shot) {
+                expected.record(&event);
+                writer.write(&event).unwrap();
+            }
+        }
+        writer.finish().unwrap();
+        let statistics = TraceStatistics::from_reader(TraceReader::open(filename).unwrap()).unwrap();
+        std::fs::remove_file(filename).unwrap();
+        statistics.print();
+        assert_eq!(statistics, expected);
+        assert_eq!(statistics.shots, shots);
+        assert_eq!(statistics.instructions["find_obstacle"], 20 * shots);
+        assert_eq!(statistics.instructions["load_weights_external"], shots);
+        assert_eq!(statistics.responses["conflict"], 5 * shots);
+        assert_eq!(statistics.max_shot_defects, 10);
+    }
+
+    #[test]
+    fn instruction_trace_corrupted() {
+        // cargo test instruction_trace_corrupted -- --nocapture
+        assert!(TraceReader::new(b"NOTTRACE".as_slice()).is_err());
+        let mut writer = TraceWriter::new(vec![]).unwrap();
+        for event in example_shot(0) {
+            writer.write(&event).unwrap();
+        }
+        let mut bytes = writer.finish().unwrap();
+        // remove the shot end and a part of the last response
+        bytes.truncate(bytes.len() - 2);
+        let result: Result<Vec<TraceEvent>> = TraceReader::new(bytes.as_slice()).unwrap().collect();
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,305 @@
+//! Latency Calibration
+//!
+//! A software sweep over the design space cannot measure the latency of a hardware that is not built yet, so it
+//! predicts the latency of every shot with a linear model instead: a fixed cost per shot, a bus cost per round trip
+//! (see [`crate::round_trips`]) and a cost per instruction of each type, i.e., the cycles of each stage of the
+//! accelerator times the clock period.
+//!
+//! The model is calibrated against a real hardware: the same syndrome corpus is decoded on the board (e.g., by the
+//! `benchmark_decoding` main of the embedded firmware, which prints the hardware latency of every shot) and by the
+//! combinatorial dual module in software, which gives the instruction counts and the round trips of every shot. The
+//! parameters are then fitted by least squares and stored as JSON, to be used by
+//! `{"latency_model": <model>}` in the solver configuration.
+//!
+
+use crate::mwpm_solver::*;
+use clap::Parser;
+use fusion_blossom::example_codes::*;
+use fusion_blossom::mwpm_solver::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// the features of a single shot that the latency depends on
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatencyFeatures {
+    pub round_trips: usize,
+    pub instruction_counts: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LatencyModel {
+    /// the fixed cost of a shot in seconds
+    pub base: f64,
+    /// the bus cost of a round trip in seconds
+    pub round_trip: f64,
+    /// the cost of an instruction of each type in seconds; an instruction type not listed costs nothing
+    pub instructions: BTreeMap<String, f64>,
+    /// the quality of the fit, if the model is calibrated
+    #[serde(default = "Default::default")]
+    pub calibration: Option<CalibrationQuality>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationQuality {
+    pub samples: usize,
+    /// the root mean square of the residuals in seconds
+    pub residual_rms: f64,
+    /// the coefficient of determination, 1 means a perfect fit
+    pub r_squared: f64,
+}
+
+/// solve `matrix * x = vector` by Gaussian elimination with partial pivoting; `None` if singular
+fn solve_linear_system(mut matrix: Vec<Vec<f64>>, mut vector: Vec<f64>) -> Option<Vec<f64>> {
+    let size = vector.len();
+    for column in 0..size {
+        let pivot = (column..size).max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))?;
+        if matrix[pivot][column].abs() < f64::EPSILON {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        vector.swap(column, pivot);
+        for row in column + 1..size {
+            let factor = matrix[row][column] / matrix[column][column];
+            for k in column..size {
+                matrix[row][k] -= factor * matrix[column][k];
+            }
+            vector[row] -= factor * vector[column];
+        }
+    }
+    let mut solution = vec![0.; size];
+    for row in (0..size).rev() {
+        let sum: f64 = (row + 1..size).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (vector[row] - sum) / matrix[row][row];
+    }
+    Some(solution)
+}
+
+impl LatencyModel {
+    pub fn predict(&self, features: &LatencyFeatures) -> f64 {
+        self.base
+            + self.round_trip * features.round_trips as f64
+            + features
+                .instruction_counts
+                .iter()
+                .map(|(name, &count)| self.instructions.get(name).cloned().unwrap_or(0.) * count as f64)
+                .sum::<f64>()
+    }
+
+    /// fit the model to the measured `(features, latency)` samples by least squares. The instruction types that are
+    /// always issued together (e.g., one `find_obstacle` per round trip) cannot be told apart, so a tiny ridge
+    /// regularization splits their cost instead of failing; the predictions are not affected.
+    pub fn fit(samples: &[(LatencyFeatures, f64)]) -> Option<Self> {
+        let names: Vec<String> = samples
+            .iter()
+            .flat_map(|(features, _)| features.instruction_counts.keys().cloned())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let row_of = |features: &LatencyFeatures| -> Vec<f64> {
+            let mut row = vec![1., features.round_trips as f64];
+            row.extend(
+                names
+                    .iter()
+                    .map(|name| features.instruction_counts.get(name).cloned().unwrap_or(0) as f64),
+            );
+            row
+        };
+        let size = names.len() + 2;
+        let mut normal = vec![vec![0.; size]; size];
+        let mut rhs = vec![0.; size];
+        for (features, latency) in samples.iter() {
+            let row = row_of(features);
+            for i in 0..size {
+                rhs[i] += row[i] * latency;
+                for j in 0..size {
+                    normal[i][j] += row[i] * row[j];
+                }
+            }
+        }
+        // the intercept is not regularized
+        let trace: f64 = (1..size).map(|i| normal[i][i]).sum();
+        for (i, normal_row) in normal.iter_mut().enumerate().skip(1) {
+            normal_row[i] += 1e-9 * trace / size as f64;
+        }
+        let solution = solve_linear_system(normal, rhs)?;
+        let mut model = Self {
+            base: solution[0],
+            round_trip: solution[1],
+            instructions: names.into_iter().zip(solution[2..].iter().cloned()).collect(),
+            calibration: None,
+        };
+        let count = samples.len() as f64;
+        let mean = samples.iter().map(|(_, latency)| latency).sum::<f64>() / count;
+        let residual: f64 = samples
+            .iter()
+            .map(|(features, latency)| (model.predict(features) - latency).powi(2))
+            .sum();
+        let total: f64 = samples.iter().map(|(_, latency)| (latency - mean).powi(2)).sum();
+        model.calibration = Some(CalibrationQuality {
+            samples: samples.len(),
+            residual_rms: (residual / count).sqrt(),
+            r_squared: if total == 0. { 1. } else { 1. - residual / total },
+        });
+        Some(model)
+    }
+
+    pub fn load(filename: &str) -> std::io::Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(filename)?)?)
+    }
+
+    pub fn save(&self, filename: &str) -> std::io::Result<()> {
+        std::fs::write(filename, serde_json::to_string_pretty(self).unwrap())
+    }
+}
+
+/// the predicted latency of every shot decoded by the solver, see [`SolverEmbeddedBoxedConfig::latency_model`]
+#[derive(Debug, Clone)]
+pub struct LatencyPredictor {
+    pub model: LatencyModel,
+    pub latencies: Vec<f64>,
+}
+
+impl LatencyPredictor {
+    pub fn new(model: LatencyModel) -> Self {
+        Self {
+            model,
+            latencies: vec![],
+        }
+    }
+
+    pub fn generate_report(&self) -> serde_json::Value {
+        let mut sorted = self.latencies.clone();
+        sorted.sort_by(f64::total_cmp);
+        let quantile = |q: f64| (!sorted.is_empty()).then(|| sorted[((sorted.len() - 1) as f64 * q).round() as usize]);
+        json!({
+            "shots": sorted.len(),
+            "average": sorted.iter().sum::<f64>() / sorted.len().max(1) as f64,
+            "p50": quantile(0.5),
+            "p99": quantile(0.99),
+            "max": sorted.last(),
+        })
+    }
+}
+
+/// the per-shot latencies in seconds measured on the board, either a JSON array or the log of the
+/// `benchmark_decoding` main, whose lines look like `[3] time: 1.234us, counter: 56, wall: 7.890us`
+pub fn read_measured_latencies(filename: &str) -> std::io::Result<Vec<f64>> {
+    let content = std::fs::read_to_string(filename)?;
+    if let Ok(latencies) = serde_json::from_str::<Vec<f64>>(&content) {
+        return Ok(latencies);
+    }
+    Ok(content
+        .lines()
+        .filter(|line| line.starts_with('['))
+        .filter_map(|line| line.split_once("time: "))
+        .filter_map(|(_, rest)| rest.split_once("us"))
+        .filter_map(|(value, _)| value.trim().parse::<f64>().ok())
+        .map(|microseconds| microseconds * 1e-6)
+        .collect())
+}
+
+#[derive(Parser, Clone)]
+pub struct CalibrateParameters {
+    /// syndrome file of the corpus decoded on the board, see the `parser` subcommand
+    #[clap(value_parser)]
+    syndromes_file: String,
+    /// the measured latency of every shot in the order of the syndrome file, see [`read_measured_latencies`]
+    #[clap(value_parser)]
+    measured_file: String,
+    /// the calibrated model output file
+    #[clap(value_parser)]
+    output_file: String,
+    /// the graph configuration used by the board, otherwise inferred from the syndrome file
+    #[clap(long)]
+    graph_file: Option<String>,
+    /// the configuration of primal and dual module, which should match the features of the board
+    #[clap(long, default_value_t = ("{}").to_string())]
+    primal_dual_config: String,
+}
+
+impl CalibrateParameters {
+    pub fn run(&self) -> LatencyModel {
+        let code = ErrorPatternReader::new(json!({ "filename": self.syndromes_file }));
+        let graph = match self.graph_file.as_ref() {
+            Some(graph_file) => serde_json::from_str(&std::fs::read_to_string(graph_file).unwrap()).unwrap(),
+            None => crate::resources::MicroBlossomSingle::new_code(&code),
+        };
+        let measured = read_measured_latencies(&self.measured_file).unwrap();
+        assert!(
+            measured.len() <= code.syndrome_patterns.len(),
+            "more measured latencies than shots in the corpus"
+        );
+        let primal_dual_config: serde_json::Value = serde_json::from_str(&self.primal_dual_config).unwrap();
+        let mut solver = SolverEmbeddedComb::new(graph, primal_dual_config);
+        let mut samples = Vec::with_capacity(measured.len());
+        for (syndrome_pattern, &latency) in code.syndrome_patterns.iter().zip(measured.iter()) {
+            solver.solve(syndrome_pattern);
+            samples.push((solver.latency_features(), latency));
+            solver.clear();
+        }
+        let model = LatencyModel::fit(&samples).expect("not enough samples to calibrate");
+        println!("{}", serde_json::to_string_pretty(&model).unwrap());
+        model.save(&self.output_file).unwrap();
+        model
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::*;
+
+    #[test]
+    fn latency_calibration_fit() {
+        // cargo test latency_calibration_fit -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(9, 0.05, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let truth = LatencyModel {
+            base: 1e-6,
+            round_trip: 3e-7,
+            instructions: [("grow".to_string(), 2e-8), ("set_speed".to_string(), 5e-8)]
+                .into_iter()
+                .collect(),
+            calibration: None,
+        };
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), json!({}));
+        let mut samples = vec![];
+        for seed in 0..200 {
+            solver.solve(&code.generate_random_errors(seed));
+            let features = solver.latency_features();
+            let latency = truth.predict(&features);
+            samples.push((features, latency));
+            solver.clear();
+        }
+        let model = LatencyModel::fit(&samples).unwrap();
+        println!("{}", serde_json::to_string_pretty(&model).unwrap());
+        assert!(model.calibration.as_ref().unwrap().r_squared > 0.999);
+        for (features, latency) in samples.iter() {
+            assert!((model.predict(features) - latency).abs() < 1e-3 * latency);
+        }
+        // the calibrated model predicts the latency in a software sweep
+        let mut solver = SolverEmbeddedComb::new(graph, json!({ "latency_model": model }));
+        solver.solve(&code.generate_random_errors(1000));
+        let predictor = solver.latency_predictor.as_ref().unwrap();
+        assert_eq!(predictor.latencies.len(), 1);
+        println!("{}", predictor.generate_report());
+    }
+
+    #[test]
+    fn latency_calibration_read_log() {
+        // cargo test latency_calibration_read_log -- --nocapture
+        let filename = std::env::temp_dir().join(format!("latency_calibration_read_log_{}.txt", std::process::id()));
+        let filename = filename.to_str().unwrap();
+        std::fs::write(
+            filename,
+            "[info] have run 0 samples\n[0] time: 1.500us, counter: 12, wall: 3.000us\n[1] time: 0.250us, counter: 3, wall: 1.000us\n",
+        )
+        .unwrap();
+        let latencies = read_measured_latencies(filename).unwrap();
+        std::fs::remove_file(filename).unwrap();
+        assert_eq!(latencies.len(), 2);
+        assert!((latencies[0] - 1.5e-6).abs() < 1e-12 && (latencies[1] - 0.25e-6).abs() < 1e-12);
+    }
+}
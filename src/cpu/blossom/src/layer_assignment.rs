@@ -0,0 +1,157 @@
+//! Layer Assignment
+//!
+//! Streaming decoding and layer fusion need to know the measurement round (layer) of every vertex.
+//! The assignment is either read from the metadata of the example code (the round of each syndrome measurement),
+//! or inferred by clustering the time coordinate of the vertex positions when such metadata is not available.
+//! Virtual vertices do not belong to any layer.
+//!
+
+use crate::resources::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerAssignment {
+    pub num_layers: usize,
+    /// the layer id of each vertex, or None for virtual vertices
+    pub vertex_layer_id: Vec<Option<usize>>,
+}
+
+pub mod layer_assignment_default {
+    /// positions generated by the example codes use integer time coordinates, so any tolerance below 1 works
+    pub fn tolerance() -> f64 {
+        0.5
+    }
+}
+
+impl LayerAssignment {
+    /// assign layers from the measurement round of every vertex; rounds need not be consecutive,
+    /// e.g., QEC-Playground uses a time step of 6 between rounds
+    pub fn from_rounds(graph: &MicroBlossomSingle, vertex_rounds: &[usize]) -> Self {
+        assert_eq!(vertex_rounds.len(), graph.vertex_num, "every vertex should have a round");
+        let is_virtual = graph.is_virtual_vertices();
+        let mut layer_ids = BTreeMap::<usize, usize>::new(); // round: layer id
+        for (vertex_index, &round) in vertex_rounds.iter().enumerate() {
+            if !is_virtual[vertex_index] {
+                layer_ids.insert(round, 0);
+            }
+        }
+        for (layer_id, value) in layer_ids.values_mut().enumerate() {
+            *value = layer_id;
+        }
+        Self {
+            num_layers: layer_ids.len(),
+            vertex_layer_id: (0..graph.vertex_num)
+                .map(|vertex_index| (!is_virtual[vertex_index]).then(|| layer_ids[&vertex_rounds[vertex_index]]))
+                .collect(),
+        }
+    }
+
+    /// cluster the time coordinates of the positions: two sorted time values closer than `tolerance` belong to
+    /// the same layer, which tolerates the small offsets that some visualizations add to the positions
+    pub fn from_positions(graph: &MicroBlossomSingle, tolerance: f64) -> Self {
+        assert!(tolerance >= 0., "tolerance must be non-negative");
+        let is_virtual = graph.is_virtual_vertices();
+        let mut sorted_vertices: Vec<usize> = (0..graph.vertex_num).filter(|&index| !is_virtual[index]).collect();
+        sorted_vertices.sort_by(|&a, &b| graph.positions[a].t.total_cmp(&graph.positions[b].t));
+        let mut vertex_layer_id = vec![None; graph.vertex_num];
+        let mut num_layers = 0;
+        let mut last_t = f64::NEG_INFINITY;
+        for vertex_index in sorted_vertices {
+            let t = graph.positions[vertex_index].t;
+            if num_layers == 0 || t - last_t > tolerance {
+                num_layers += 1;
+            }
+            last_t = t;
+            vertex_layer_id[vertex_index] = Some(num_layers - 1);
+        }
+        Self {
+            num_layers,
+            vertex_layer_id,
+        }
+    }
+
+    /// the vertices in each layer, in increasing vertex index
+    pub fn layers(&self) -> Vec<Vec<usize>> {
+        let mut layers = vec![vec![]; self.num_layers];
+        for (vertex_index, layer_id) in self.vertex_layer_id.iter().enumerate() {
+            if let Some(layer_id) = layer_id {
+                layers[*layer_id].push(vertex_index);
+            }
+        }
+        layers
+    }
+}
+
+impl MicroBlossomSingle {
+    fn is_virtual_vertices(&self) -> Vec<bool> {
+        let mut is_virtual = vec![false; self.vertex_num];
+        for &vertex_index in self.virtual_vertices.iter() {
+            is_virtual[vertex_index] = true;
+        }
+        is_virtual
+    }
+
+    pub fn assign_layers_from_rounds(&mut self, vertex_rounds: &[usize]) {
+        self.layer_assignment = Some(LayerAssignment::from_rounds(self, vertex_rounds));
+    }
+
+    pub fn assign_layers_from_positions(&mut self) {
+        self.layer_assignment = Some(LayerAssignment::from_positions(self, layer_assignment_default::tolerance()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn layer_assignment_from_positions() {
+        // cargo test layer_assignment_from_positions -- --nocapture
+        let code = PhenomenologicalRotatedCode::new(3, 3, 0.1, 500);
+        let mut graph = MicroBlossomSingle::new_code(&code);
+        graph.assign_layers_from_positions();
+        let assignment = graph.layer_assignment.as_ref().unwrap();
+        assert_eq!(assignment.num_layers, 4);
+        // should agree with the layers used by layer fusion
+        let layer_fusion = graph.layer_fusion.as_ref().unwrap();
+        assert_eq!(assignment.layers(), layer_fusion.layers);
+        for vertex_index in graph.virtual_vertices.iter() {
+            assert_eq!(assignment.vertex_layer_id[*vertex_index], None);
+        }
+    }
+
+    #[test]
+    fn layer_assignment_clustering_tolerance() {
+        // cargo test layer_assignment_clustering_tolerance -- --nocapture
+        let code = PhenomenologicalRotatedCode::new(3, 3, 0.1, 500);
+        let mut graph = MicroBlossomSingle::new_code(&code);
+        let expected = LayerAssignment::from_positions(&graph, layer_assignment_default::tolerance());
+        // small jitter should not change the clustering
+        for (vertex_index, position) in graph.positions.iter_mut().enumerate() {
+            position.t += if vertex_index % 2 == 0 { 0.1 } else { -0.1 };
+        }
+        assert_eq!(LayerAssignment::from_positions(&graph, 0.3), expected);
+        // without tolerance the jittered time values split every layer into two
+        assert_eq!(
+            LayerAssignment::from_positions(&graph, 0.).num_layers,
+            2 * expected.num_layers
+        );
+    }
+
+    #[test]
+    fn layer_assignment_from_rounds() {
+        // cargo test layer_assignment_from_rounds -- --nocapture
+        let code = PhenomenologicalRotatedCode::new(3, 3, 0.1, 500);
+        let mut graph = MicroBlossomSingle::new_code(&code);
+        // QEC-Playground style rounds with a time step of 6; virtual vertices have arbitrary rounds
+        let layer_fusion = graph.layer_fusion.as_ref().unwrap();
+        let vertex_rounds: Vec<usize> = (0..graph.vertex_num)
+            .map(|vertex_index| 6 * layer_fusion.vertex_layer_id.get(&vertex_index).cloned().unwrap_or(100))
+            .collect();
+        graph.assign_layers_from_rounds(&vertex_rounds);
+        let expected = LayerAssignment::from_positions(&graph, layer_assignment_default::tolerance());
+        assert_eq!(graph.layer_assignment, Some(expected));
+    }
+}
@@ -1,22 +1,80 @@
 #[macro_use]
 extern crate serde_json;
 
+pub mod animation;
+pub mod blossom_budget;
+pub mod build_info;
+pub mod checkpoint;
 pub mod cli;
+pub mod component_parallel;
+pub mod conformance;
+pub mod correction_stream;
+pub mod critical_path;
+pub mod decision_trace;
+pub mod defect_latency;
+pub mod defect_sanitizer;
+pub mod device_lock;
 pub mod dual_module_adaptor;
+#[cfg(feature = "async_driver")]
+pub mod dual_module_async;
 pub mod dual_module_axi4;
 pub mod dual_module_comb;
+pub mod dual_module_comb_assertion;
+pub mod dual_module_comb_checkpoint;
+pub mod dual_module_comb_context;
+pub mod dual_module_comb_cycles;
 pub mod dual_module_comb_edge;
+pub mod dual_module_comb_fan_in;
+pub mod dual_module_comb_fault_injection;
+pub mod dual_module_comb_mirror;
+pub mod dual_module_comb_multi_beat;
 pub mod dual_module_comb_offloading;
+pub mod dual_module_comb_register_map;
+pub mod dual_module_comb_schedule;
+pub mod dual_module_comb_sequence;
+pub mod dual_module_comb_vcd;
 pub mod dual_module_comb_vertex;
+pub mod dual_module_jitter;
 pub mod dual_module_looper;
 pub mod dual_module_scala;
+pub mod dual_module_trace;
+pub mod edge_heatmap;
 pub mod example_codes;
+pub mod feature_export;
+pub mod firmware_graph;
+pub mod gallery;
+pub mod graph_builder;
+pub mod graph_scaling;
+pub mod graph_symmetry;
+pub mod hardware_config_check;
+pub mod instruction_trace;
+pub mod latency_calibration;
+pub mod layer_assignment;
 pub mod mwpm_solver;
+pub mod node_virtualizer;
+pub mod offloading_regions;
+pub mod offloading_toggle;
+pub mod pinned_matching;
+pub mod prelude;
 pub mod primal_module_embedded_adaptor;
 pub mod resources;
+pub mod result_diff;
+pub mod round_decimation;
+pub mod round_trips;
 pub mod simulation_tcp_client;
+pub mod snapshot_delta;
+pub mod soak;
+pub mod stim_samples;
+pub mod syndrome_source;
+pub mod throughput;
+pub mod tight_paths;
 pub mod transform_syndromes;
 pub mod util;
+pub mod verifier;
+pub mod vertex_reordering;
+pub mod virtual_merging;
+pub mod warm_start;
+pub mod weight_quantum;
 
 use lazy_static::lazy_static;
 use std::sync::Mutex;
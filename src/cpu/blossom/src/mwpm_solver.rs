@@ -1,10 +1,22 @@
+use crate::blossom_budget::*;
+use crate::defect_latency::*;
+use crate::defect_sanitizer::*;
 use crate::dual_module_axi4::*;
 use crate::dual_module_comb::*;
+use crate::dual_module_comb_context::*;
+use crate::dual_module_jitter::*;
 use crate::dual_module_looper::*;
 use crate::dual_module_scala::*;
+use crate::dual_module_trace::*;
+use crate::latency_calibration::*;
+use crate::node_virtualizer::*;
+use crate::offloading_regions::*;
+use crate::pinned_matching::*;
 use crate::primal_module_embedded_adaptor::*;
 use crate::resources::*;
+use crate::round_trips::*;
 use crate::simulation_tcp_client::SimulationConfig;
+use crate::tight_paths::*;
 use crate::util::*;
 use fusion_blossom::dual_module::*;
 use fusion_blossom::dual_module_serial::*;
@@ -18,8 +30,74 @@ use micro_blossom_nostd::dual_driver_tracked::*;
 use micro_blossom_nostd::dual_module_stackless::*;
 use micro_blossom_nostd::interface::*;
 use micro_blossom_nostd::util::*;
+use rand::seq::SliceRandom;
+use rand_xoshiro::rand_core::SeedableRng;
+use rand_xoshiro::Xoroshiro128StarStar;
 use serde::*;
 use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The outcome of a single solve, collected in the same way from every solver of this crate
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SolverResult {
+    pub subgraph: Vec<EdgeIndex>,
+    /// the total weight of the subgraph
+    pub matching_weight: Weight,
+    /// the sum of dual variables, `None` if the solver cannot read them back
+    pub dual_objective: Option<Weight>,
+    /// the dual objective equals the matching weight, which certifies that the matching is minimum-weight
+    pub certified: bool,
+    /// the number of instructions of each type executed by the dual module in this shot, `None` if not tracked
+    pub instruction_counts: Option<BTreeMap<String, usize>>,
+    /// the clock cycles spent by the dual module in this shot, `None` if not tracked
+    pub clock_cycles: Option<u64>,
+    /// the number of defects matched inside the dual module, `None` if the solver does not offload
+    pub offloaded: Option<usize>,
+    /// the explicit correction of every matched pair, `None` if the solver cannot read back the growth of vertices
+    pub correction_paths: Option<Vec<CorrectionPath>>,
+    /// the invalid defects dropped or rejected in this shot, `None` if the solver does not sanitize the defects
+    pub sanitized: Option<SanitizeCounts>,
+    /// a weight computation overflowed in this shot, so the result is a decoding failure; only checked with the
+    /// `checked_weight` feature
+    pub weight_overflow: bool,
+    /// the primal-dual interface desynchronized in this shot and could not be recovered, so the result is a decoding
+    /// failure; the correction only consists of the matches settled before and the greedy fallback
+    pub desynchronized: bool,
+}
+
+impl SolverResult {
+    pub fn new(subgraph: Vec<EdgeIndex>, matching_weight: Weight, dual_objective: Option<Weight>) -> Self {
+        Self {
+            subgraph,
+            matching_weight,
+            dual_objective,
+            certified: dual_objective == Some(matching_weight),
+            instruction_counts: None,
+            clock_cycles: None,
+            offloaded: None,
+            correction_paths: None,
+            sanitized: None,
+            weight_overflow: false,
+            desynchronized: false,
+        }
+    }
+}
+
+/// work around the missing trait upcasting from `dyn MicroBlossomSolver` to `dyn PrimalDualSolver`
+pub trait IntoPrimalDualSolver {
+    fn into_primal_dual_solver(self: Box<Self>) -> Box<dyn PrimalDualSolver>;
+}
+
+impl<T: PrimalDualSolver + 'static> IntoPrimalDualSolver for T {
+    fn into_primal_dual_solver(self: Box<Self>) -> Box<dyn PrimalDualSolver> {
+        self
+    }
+}
+
+pub trait MicroBlossomSolver: PrimalDualSolver + IntoPrimalDualSolver {
+    /// the result of the last solve, which must be called after `solve` and before `clear`
+    fn result(&mut self) -> SolverResult;
+}
 
 pub struct SolverPrimalEmbedded {
     dual_module: DualModuleSerial,
@@ -110,8 +188,16 @@ impl PrimalDualSolver for SolverPrimalEmbedded {
     }
 }
 
+impl MicroBlossomSolver for SolverPrimalEmbedded {
+    fn result(&mut self) -> SolverResult {
+        let subgraph = self.subgraph();
+        let matching_weight = self.subgraph_builder.total_weight();
+        SolverResult::new(subgraph, matching_weight, Some(self.sum_dual_variables()))
+    }
+}
+
 pub struct SolverDualComb {
-    dual_module: Box<DualModuleCombAdaptor>,
+    pub dual_module: Box<DualModuleCombAdaptor>,
     primal_module: PrimalModuleSerialPtr,
     interface_ptr: DualModuleInterfacePtr,
     subgraph_builder: SubGraphBuilder,
@@ -136,6 +222,29 @@ impl SolverDualComb {
         };
         result
     }
+
+    /// the dual module is configured by `{"dual":{...}}`, see [`DualCombConfig`]
+    pub fn new_with_config(graph: MicroBlossomSingle, primal_dual_config: serde_json::Value) -> Self {
+        let config: SolverDualCombConfig = serde_json::from_value(primal_dual_config).unwrap();
+        let dual_config: DualCombConfig = serde_json::from_value(config.dual.unwrap_or(json!({}))).unwrap();
+        let initializer = graph.get_initializer();
+        Self {
+            dual_module: stacker::grow(MAX_NODE_NUM * 256, || {
+                Box::new(DualModuleCombAdaptor::new_with_dual_module(DualModuleStackless::new(
+                    DualDriverTracked::new(DualModuleCombDriver::new(graph, dual_config)),
+                )))
+            }),
+            primal_module: PrimalModuleSerialPtr::new_empty(&initializer),
+            interface_ptr: DualModuleInterfacePtr::new_empty(),
+            subgraph_builder: SubGraphBuilder::new(&initializer),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SolverDualCombConfig {
+    pub dual: Option<serde_json::Value>,
 }
 
 impl PrimalDualSolver for SolverDualComb {
@@ -227,8 +336,94 @@ impl PrimalDualSolver for SolverDualComb {
     }
 }
 
+impl MicroBlossomSolver for SolverDualComb {
+    fn result(&mut self) -> SolverResult {
+        let subgraph = self.subgraph();
+        let matching_weight = self.subgraph_builder.total_weight();
+        SolverResult::new(subgraph, matching_weight, Some(self.sum_dual_variables()))
+    }
+}
+
+/// A snapshot of the matching in the middle of solving.
+/// Only outer (top-level) nodes are reported, using the node indices of the primal module; a blossom node stands for
+/// all the defects inside it. Defects that the primal module has not yet seen in any obstacle, as well as those
+/// offloaded to the dual module, are not reported.
+/// Nodes in alternating trees are matched to their tree neighbors only tentatively: augmenting a tree rewires every
+/// matching inside it, so these nodes are reported as `unsettled` together with the free nodes rather than as matched.
+/// The matched pairs may still change when an alternating tree grows into them; `unsettled` is empty once the
+/// solve finishes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct EmbeddedIntermediateMatching {
+    pub peer_matchings: Vec<(NodeIndex, NodeIndex)>,
+    pub virtual_matchings: Vec<(NodeIndex, VertexIndex)>,
+    /// outer nodes that are free or in an alternating tree
+    pub unsettled: Vec<NodeIndex>,
+}
+
+/// the event counters in the control block of `MicroBlossomBus`, cleared by [`SolverTrackedDual::reset_profiler`].
+/// The error counter counts the rejected bus accesses, e.g., to an unknown address or context, so a flaky run with a
+/// non-zero error counter points to the transport or the driver rather than the algorithm
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct HardwareCounters {
+    pub instruction_counter: u32,
+    pub readout_counter: u32,
+    pub transaction_counter: u32,
+    pub error_counter: u32,
+}
+
+impl HardwareCounters {
+    /// the addresses of the counters in the control block, in the order of the fields
+    pub const ADDRESSES: [usize; 4] = [24, 32, 40, 48];
+
+    pub fn read<E>(mut read_32: impl FnMut(usize) -> Result<u32, E>) -> Result<Self, E> {
+        let [instruction, readout, transaction, error] = Self::ADDRESSES;
+        Ok(Self {
+            instruction_counter: read_32(instruction)?,
+            readout_counter: read_32(readout)?,
+            transaction_counter: read_32(transaction)?,
+            error_counter: read_32(error)?,
+        })
+    }
+}
+
+/// an optional operation of [`SolverTrackedDual`] failed, e.g., the driver does not implement it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DualDriverError {
+    Unsupported {
+        operation: &'static str,
+    },
+    /// the arguments are rejected by the driver without any change to its state
+    InvalidArgument {
+        operation: &'static str,
+        message: String,
+    },
+    /// the configuration of the solver is rejected before any driver is constructed
+    InvalidConfig {
+        message: String,
+    },
+}
+
+impl std::fmt::Display for DualDriverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsupported { operation } => write!(f, "the dual driver does not support {operation}"),
+            Self::InvalidArgument { operation, message } => write!(f, "invalid argument of {operation}: {message}"),
+            Self::InvalidConfig { message } => write!(f, "invalid configuration: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for DualDriverError {}
+
 pub trait SolverTrackedDual: DualStacklessDriver + DualTrackedDriver + FusionVisualizer {
     fn new_from_graph_config(graph: MicroBlossomSingle, config: serde_json::Value) -> Self;
+    /// like [`SolverTrackedDual::new_from_graph_config`] but rejects an invalid configuration instead of panicking
+    fn try_new_from_graph_config(graph: MicroBlossomSingle, config: serde_json::Value) -> Result<Self, DualDriverError>
+    where
+        Self: Sized,
+    {
+        Ok(Self::new_from_graph_config(graph, config))
+    }
     fn reset_profiler(&mut self) {}
     fn generate_profiler_report(&self) -> serde_json::Value {
         json!({})
@@ -241,7 +436,171 @@ pub trait SolverTrackedDual: DualStacklessDriver + DualTrackedDriver + FusionVis
     fn get_pre_matchings(&self, _belonging: DualModuleInterfaceWeak) -> PerfectMatching {
         Default::default()
     }
+    /// the number of instructions of each type executed since the last reset, see [`SolverResult`]
+    fn instruction_counts(&self) -> Option<BTreeMap<String, usize>> {
+        None
+    }
+    /// the clock cycles spent since the last reset, see [`SolverResult`]
+    fn clock_cycles(&self) -> Option<u64> {
+        None
+    }
+    /// the event counters of the hardware since the last reset of the profiler, only available when the driver talks
+    /// to the bus of the real hardware or its simulation
+    fn hardware_counters(&self) -> Option<HardwareCounters> {
+        None
+    }
+    /// whether `read_vertex_grown` reads back the grown value of every vertex, from which the dual variables of the
+    /// nodes are derived, see [`SolverEmbeddedBoxed::read_node_dual`]
+    fn supports_dual_readback(&self) -> bool {
+        false
+    }
+    /// whether a weight computation of the current shot overflowed, only checked with the `checked_weight` feature
+    fn weight_overflowed(&self) -> bool {
+        false
+    }
+    /// whether the primal-dual interface desynchronized in the current shot and could not be recovered, so that the
+    /// responses of the shot are no longer trustworthy
+    fn desynchronized(&self) -> bool {
+        false
+    }
+    /// freeze the vertices in `begin..end` of a committed region in streaming mode: their speed is forced to `Stay`
+    /// and they are excluded from the obstacle scan until the next reset
+    fn freeze_vertex_range(&mut self, _begin: VertexIndex, _end: VertexIndex) -> Result<(), DualDriverError> {
+        Err(DualDriverError::Unsupported {
+            operation: "freeze_vertex_range",
+        })
+    }
+    /// set the weight of an edge until the next reset, e.g., 0 for an erasure or an adaptive reweighting between
+    /// decoding rounds; a negative weight or one smaller than the growth already covering the edge is rejected
+    fn set_edge_weight(&mut self, _edge_index: EdgeIndex, _weight: Weight) -> Result<(), DualDriverError> {
+        Err(DualDriverError::Unsupported {
+            operation: "set_edge_weight",
+        })
+    }
+    /// retract a defect, e.g., after a heralded reset: the defect vertex and the whole region of its node are cleared;
+    /// the node must not be in a blossom. The primal module should forget it as well, see
+    /// [`SolverEmbeddedBoxed::remove_defect`]
+    fn remove_defect(&mut self, _vertex_index: VertexIndex) -> Result<(), DualDriverError> {
+        Err(DualDriverError::Unsupported {
+            operation: "remove_defect",
+        })
+    }
+    /// release the indices of finished nodes so that new defects can reuse them, see [`NodeVirtualizer`]: the vertices
+    /// keep their growth but no longer belong to any node. A node can only be released once its whole region is
+    /// frozen (see [`Self::freeze_vertex_range`]); returns false without any change if any of the nodes still occupies
+    /// a vertex that is not frozen
+    fn release_nodes(&mut self, _nodes: &[NodeIndex]) -> Result<bool, DualDriverError> {
+        Err(DualDriverError::Unsupported {
+            operation: "release_nodes",
+        })
+    }
+    /// called once the solver finishes a shot, before it is cleared, e.g., to record the statistics of the shot
+    fn finish_shot(&mut self) {}
+}
+
+/// implement the listed methods of [`SolverTrackedDual`] by forwarding them to an inner driver, so that a wrapper
+/// driver only writes the methods it changes; `$inner` is the inner driver of the `&self` methods and `$inner_mut` of
+/// the `&mut self` methods, e.g., `forward_solver_tracked_dual!(self, self.driver, self.driver; clock_cycles);`
+#[macro_export]
+macro_rules! forward_solver_tracked_dual {
+    ($self:ident, $inner:expr, $inner_mut:expr; $($method:ident),* $(,)?) => {
+        $($crate::forward_solver_tracked_dual!(@$method $self, $inner, $inner_mut);)*
+    };
+    (@reset_profiler $self:ident, $inner:expr, $inner_mut:expr) => {
+        fn reset_profiler(&mut $self) {
+            $inner_mut.reset_profiler();
+        }
+    };
+    (@generate_profiler_report $self:ident, $inner:expr, $inner_mut:expr) => {
+        fn generate_profiler_report(&$self) -> serde_json::Value {
+            $inner.generate_profiler_report()
+        }
+    };
+    (@fuse_layer $self:ident, $inner:expr, $inner_mut:expr) => {
+        fn fuse_layer(&mut $self, layer_id: usize) {
+            $inner_mut.fuse_layer(layer_id);
+        }
+    };
+    (@get_pre_matchings $self:ident, $inner:expr, $inner_mut:expr) => {
+        fn get_pre_matchings(
+            &$self,
+            belonging: fusion_blossom::dual_module::DualModuleInterfaceWeak,
+        ) -> fusion_blossom::primal_module::PerfectMatching {
+            $inner.get_pre_matchings(belonging)
+        }
+    };
+    (@instruction_counts $self:ident, $inner:expr, $inner_mut:expr) => {
+        fn instruction_counts(&$self) -> Option<std::collections::BTreeMap<String, usize>> {
+            $inner.instruction_counts()
+        }
+    };
+    (@clock_cycles $self:ident, $inner:expr, $inner_mut:expr) => {
+        fn clock_cycles(&$self) -> Option<u64> {
+            $inner.clock_cycles()
+        }
+    };
+    (@hardware_counters $self:ident, $inner:expr, $inner_mut:expr) => {
+        fn hardware_counters(&$self) -> Option<$crate::mwpm_solver::HardwareCounters> {
+            $inner.hardware_counters()
+        }
+    };
+    (@supports_dual_readback $self:ident, $inner:expr, $inner_mut:expr) => {
+        fn supports_dual_readback(&$self) -> bool {
+            $inner.supports_dual_readback()
+        }
+    };
+    (@weight_overflowed $self:ident, $inner:expr, $inner_mut:expr) => {
+        fn weight_overflowed(&$self) -> bool {
+            $inner.weight_overflowed()
+        }
+    };
+    (@desynchronized $self:ident, $inner:expr, $inner_mut:expr) => {
+        fn desynchronized(&$self) -> bool {
+            $inner.desynchronized()
+        }
+    };
+    (@freeze_vertex_range $self:ident, $inner:expr, $inner_mut:expr) => {
+        fn freeze_vertex_range(
+            &mut $self,
+            begin: fusion_blossom::util::VertexIndex,
+            end: fusion_blossom::util::VertexIndex,
+        ) -> std::result::Result<(), $crate::mwpm_solver::DualDriverError> {
+            $inner_mut.freeze_vertex_range(begin, end)
+        }
+    };
+    (@set_edge_weight $self:ident, $inner:expr, $inner_mut:expr) => {
+        fn set_edge_weight(
+            &mut $self,
+            edge_index: fusion_blossom::util::EdgeIndex,
+            weight: fusion_blossom::util::Weight,
+        ) -> std::result::Result<(), $crate::mwpm_solver::DualDriverError> {
+            $inner_mut.set_edge_weight(edge_index, weight)
+        }
+    };
+    (@remove_defect $self:ident, $inner:expr, $inner_mut:expr) => {
+        fn remove_defect(
+            &mut $self,
+            vertex_index: fusion_blossom::util::VertexIndex,
+        ) -> std::result::Result<(), $crate::mwpm_solver::DualDriverError> {
+            $inner_mut.remove_defect(vertex_index)
+        }
+    };
+    (@release_nodes $self:ident, $inner:expr, $inner_mut:expr) => {
+        fn release_nodes(
+            &mut $self,
+            nodes: &[fusion_blossom::util::NodeIndex],
+        ) -> std::result::Result<bool, $crate::mwpm_solver::DualDriverError> {
+            $inner_mut.release_nodes(nodes)
+        }
+    };
+    (@finish_shot $self:ident, $inner:expr, $inner_mut:expr) => {
+        fn finish_shot(&mut $self) {
+            $inner_mut.finish_shot();
+        }
+    };
 }
+#[allow(unused_imports)]
+pub use forward_solver_tracked_dual;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -251,22 +610,105 @@ pub struct SolverEmbeddedBoxedConfig {
     /// to debug the infinite loop bugs: terminate and save the waveform in the middle
     #[serde(default = "solver_embedded_boxed_config_default::max_iterations")]
     pub max_iterations: usize,
+    /// load defects using a single bitmap transaction when at least this many defects fall into a 64-vertex window;
+    /// sparse defects are still loaded one by one because a bitmap transaction is wider than a single instruction
+    #[serde(default = "solver_embedded_boxed_config_default::bitmap_loading_threshold")]
+    pub bitmap_loading_threshold: usize,
+    /// the number of hardware node indices available for defects, by default the number of vertices;
+    /// a smaller pool requires recycling the indices of finished nodes, see [`NodeVirtualizer`]
+    #[serde(default = "Default::default")]
+    pub hardware_node_capacity: Option<usize>,
+    /// the time between two measurement rounds in seconds; when set, decode in streaming mode and report the
+    /// latency of every defect in the profiler, see [`DefectLatencyTracker`]
+    #[serde(default = "Default::default")]
+    pub defect_latency_round_interval: Option<f64>,
+    /// emulate a hardware with at most this many blossoms per shot, matching the rest greedily once it runs out,
+    /// see [`BlossomBudget`]
+    #[serde(default = "Default::default")]
+    pub blossom_budget: Option<usize>,
+    /// emulate a hardware tracking at most this many children per blossom, matching the rest greedily once a larger
+    /// blossom is created, see [`BlossomSizeCap`]
+    #[serde(default = "Default::default")]
+    pub max_blossom_children: Option<usize>,
+    /// shuffle the order of loading the defects with this seed, which emulates hardware reporting the defects in
+    /// arrival or scan order instead of index order; the result should not depend on the order. The shuffle of
+    /// each shot only depends on the seed and the defects of the shot, not on the shots decoded before, so that
+    /// splitting the shots across threads gives exactly the same decisions
+    #[serde(default = "Default::default")]
+    pub defect_order_seed: Option<u64>,
+    /// record the conflict queries of every shot and optionally cap them, see [`RoundTripTracker`]
+    #[serde(default = "Default::default")]
+    pub round_trips: Option<RoundTripConfig>,
+    /// check the defects of every shot for duplicated or invalid vertices before loading, see [`DefectSanitizer`]
+    #[serde(default = "Default::default")]
+    pub sanitize: Option<SanitizePolicy>,
+    /// predict the hardware latency of every shot with a calibrated model, see [`LatencyModel`]
+    #[serde(default = "Default::default")]
+    pub latency_model: Option<LatencyModel>,
+    /// the speed magnitude of the defects until the primal module encounters them, limited by the dual module,
+    /// see [`PrimalModuleEmbedded::lone_defect_magnitude`]
+    #[serde(default = "solver_embedded_boxed_config_default::lone_defect_magnitude")]
+    pub lone_defect_magnitude: CompactSpeed,
 }
 
 pub mod solver_embedded_boxed_config_default {
     pub fn max_iterations() -> usize {
         usize::MAX
     }
+    pub fn bitmap_loading_threshold() -> usize {
+        4
+    }
+    pub fn lone_defect_magnitude() -> micro_blossom_nostd::util::CompactSpeed {
+        1
+    }
+}
+
+/// mix the defects of a shot into the seed (splitmix64), so that the same shot always gets the same random stream
+fn shot_seed(seed: u64, defect_vertices: &[VertexIndex]) -> u64 {
+    let mix = |mut value: u64| {
+        value = value.wrapping_add(0x9e3779b97f4a7c15);
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+        value ^ (value >> 31)
+    };
+    defect_vertices
+        .iter()
+        .fold(mix(seed), |state, &vertex_index| mix(state ^ vertex_index as u64))
 }
 
 pub struct SolverEmbeddedBoxed<Dual: SolverTrackedDual> {
     pub dual_module: Box<DualModuleStackless<DualDriverTracked<Dual, MAX_NODE_NUM>>>,
     pub primal_module: Box<PrimalModuleEmbedded<MAX_NODE_NUM>>,
     subgraph_builder: SubGraphBuilder,
+    /// the vertex of each defect node, indexed by the hardware node index
     defect_nodes: Vec<VertexIndex>,
+    pub node_virtualizer: NodeVirtualizer,
     pub offloaded: usize,
-    layer_id: usize,
-    graph: MicroBlossomSingle,
+    pub defect_latency: Option<DefectLatencyTracker>,
+    pub blossom_budget: Option<BlossomBudget>,
+    pub blossom_size_cap: Option<BlossomSizeCap>,
+    pub round_trips: Option<RoundTripTracker>,
+    /// the offload rate of the physical regions, see [`crate::offloading_regions`]
+    pub offloading_regions: Option<OffloadingRegionTracker>,
+    pub sanitizer: Option<DefectSanitizer>,
+    pub latency_predictor: Option<LatencyPredictor>,
+    /// the number of shots with an overflowed weight computation, see [`SolverResult::weight_overflow`]
+    pub weight_overflow_shots: usize,
+    /// the number of failed shots due to an unrecoverable desynchronization, see [`SolverResult::desynchronized`]
+    pub desynchronized_shots: usize,
+    /// the greedy matching of the defects left by a truncated solve, see [`greedy_matching`]
+    fallback: PinnedMatching,
+    /// the matches of the finished nodes whose hardware indices are recycled, see [`Self::recycle_finished_nodes`]
+    recycled: PinnedMatching,
+    /// the matches decided before solving, see [`crate::pinned_matching`]
+    pub(crate) pinned: PinnedMatching,
+    /// the defects loaded in this shot, in the order of loading, see [`crate::checkpoint`]
+    pub(crate) loaded_defects: Vec<VertexIndex>,
+    /// the loaded defects retracted by [`Self::remove_defect`], which no longer need a match
+    pub(crate) removed_defects: Vec<VertexIndex>,
+    pub(crate) layer_id: usize,
+    pub(crate) iteration: usize,
+    pub(crate) graph: MicroBlossomSingle,
     sim_config: SimulationConfig,
     config: SolverEmbeddedBoxedConfig,
 }
@@ -282,22 +724,49 @@ impl<Dual: SolverTrackedDual> FusionVisualizer for SolverEmbeddedBoxed<Dual> {
 
 impl<Dual: SolverTrackedDual> SolverEmbeddedBoxed<Dual> {
     pub fn new(graph: MicroBlossomSingle, primal_dual_config: serde_json::Value) -> Self {
+        Self::try_new(graph, primal_dual_config).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// construct the solver, rejecting an invalid configuration of the solver or of the dual driver
+    pub fn try_new(graph: MicroBlossomSingle, primal_dual_config: serde_json::Value) -> Result<Self, DualDriverError> {
         assert!(graph.vertex_num <= MAX_NODE_NUM, "potential overflow");
-        let config: SolverEmbeddedBoxedConfig = serde_json::from_value(primal_dual_config).unwrap();
+        let invalid = |message: String| DualDriverError::InvalidConfig { message };
+        let config: SolverEmbeddedBoxedConfig =
+            serde_json::from_value(primal_dual_config).map_err(|error| invalid(error.to_string()))?;
         let dual_config = config.dual.clone().unwrap_or(json!({}));
-        let sim_config: SimulationConfig = dual_config
-            .get("sim_config")
-            .map(|sim_config| serde_json::from_value(sim_config.clone()).unwrap())
-            .unwrap_or_default();
+        let sim_config: SimulationConfig = match dual_config.get("sim_config") {
+            Some(sim_config) => serde_json::from_value(sim_config.clone()).map_err(|error| invalid(error.to_string()))?,
+            None => SimulationConfig::default(),
+        };
+        let node_capacity = config.hardware_node_capacity.unwrap_or(graph.vertex_num);
+        if node_capacity > graph.vertex_num {
+            return Err(invalid("more node indices than vertices is not useful".to_string()));
+        }
+        if config.hardware_node_capacity.is_some() && sim_config.support_offloading {
+            return Err(invalid(
+                "the pre-matched nodes cannot be recycled, disable offloading to limit the hardware node indices"
+                    .to_string(),
+            ));
+        }
+        if !(1..=MAX_SPEED_MAGNITUDE).contains(&config.lone_defect_magnitude) {
+            return Err(invalid(format!(
+                "the speed magnitude of the lone defects must be in [1, {MAX_SPEED_MAGNITUDE}]"
+            )));
+        }
         let initializer = graph.get_initializer();
-        let dual_module = stacker::grow(MAX_NODE_NUM * 256, || {
-            Box::new(DualModuleStackless::new(DualDriverTracked::new(Dual::new_from_graph_config(
-                graph.clone(),
-                dual_config,
-            ))))
-        });
+        let mut dual_module = stacker::grow(MAX_NODE_NUM * 256, || {
+            Dual::try_new_from_graph_config(graph.clone(), dual_config)
+                .map(|driver| Box::new(DualModuleStackless::new(DualDriverTracked::new(driver))))
+        })?;
         let mut primal_module = stacker::grow(MAX_NODE_NUM * 256, || Box::new(PrimalModuleEmbedded::new()));
-        primal_module.nodes.blossom_begin = graph.vertex_num; // make sure the index is not overflow on the dual side
+        if config.hardware_node_capacity.is_some() {
+            // releasing no node has no effect, it only checks whether the driver supports recycling
+            if let Err(error) = dual_module.driver.driver.release_nodes(&[]) {
+                return Err(invalid(format!("{error}, cannot limit the hardware node indices")));
+            }
+        }
+        primal_module.nodes.blossom_begin = node_capacity; // make sure the index is not overflow on the dual side
+        primal_module.lone_defect_magnitude = config.lone_defect_magnitude;
         if let Some(layer_fusion) = graph.layer_fusion.as_ref() {
             // load the layer id to the primal
             for vertex_index in 0..graph.vertex_num {
@@ -310,93 +779,426 @@ impl<Dual: SolverTrackedDual> SolverEmbeddedBoxed<Dual> {
                 }
             }
         }
-        Self {
+        let defect_latency = config
+            .defect_latency_round_interval
+            .map(|round_interval| DefectLatencyTracker::new(&graph, round_interval));
+        let blossom_budget = config.blossom_budget.map(BlossomBudget::new);
+        let blossom_size_cap = config.max_blossom_children.map(BlossomSizeCap::new);
+        let round_trips = config.round_trips.clone().map(RoundTripTracker::new);
+        let offloading_regions = (sim_config.support_offloading && graph.offloading_regions.is_some())
+            .then(|| OffloadingRegionTracker::new(&graph));
+        let sanitizer = config.sanitize.map(|policy| DefectSanitizer::new(&graph, policy));
+        let latency_predictor = config.latency_model.clone().map(LatencyPredictor::new);
+        Ok(Self {
             dual_module,
             primal_module,
             subgraph_builder: SubGraphBuilder::new(&initializer),
             defect_nodes: vec![],
+            node_virtualizer: NodeVirtualizer::new(node_capacity),
             offloaded: 0,
+            defect_latency,
+            blossom_budget,
+            blossom_size_cap,
+            round_trips,
+            offloading_regions,
+            sanitizer,
+            latency_predictor,
+            weight_overflow_shots: 0,
+            desynchronized_shots: 0,
+            fallback: PinnedMatching::new(),
+            recycled: PinnedMatching::new(),
+            pinned: PinnedMatching::new(),
+            loaded_defects: vec![],
+            removed_defects: vec![],
             layer_id: 0,
+            iteration: 0,
             graph,
             sim_config,
             config,
-        }
+        })
     }
 }
 
-impl<Dual: SolverTrackedDual> PrimalDualSolver for SolverEmbeddedBoxed<Dual> {
-    fn clear(&mut self) {
-        self.primal_module.reset();
-        self.dual_module.reset();
-        self.subgraph_builder.clear();
-        self.defect_nodes.clear();
-        self.layer_id = 0;
+impl<Dual: SolverTrackedDual> SolverEmbeddedBoxed<Dual> {
+    /// load the defects into the dual module, choosing between per-defect and bitmap loading based on the density;
+    /// the node indices are always assigned in the order of `defect_vertices`
+    fn load_defects(&mut self, defect_vertices: &[VertexIndex]) {
+        // bitmap loading assigns node indices in increasing vertex order, which requires sorted defects
+        let is_sorted = defect_vertices.windows(2).all(|pair| pair[0] < pair[1]);
+        // the defects of the previous rounds keep their global indices in streaming mode
+        let base_index = self.loaded_defects.len();
+        let mut defect_index = 0;
+        while defect_index < defect_vertices.len() {
+            let base_vertex = defect_vertices[defect_index];
+            let window = if is_sorted {
+                defect_vertices[defect_index..]
+                    .iter()
+                    .take_while(|&&vertex_index| vertex_index < base_vertex + 64)
+                    .count()
+            } else {
+                1
+            };
+            let node_indices: Vec<NodeIndex> = (defect_index..defect_index + window)
+                .map(|local_index| self.allocate_node(base_index + local_index, defect_vertices[local_index]))
+                .collect();
+            // the node indices may not be consecutive after recycling
+            let is_consecutive = node_indices.windows(2).all(|pair| pair[1] == pair[0] + 1);
+            if window >= self.config.bitmap_loading_threshold && is_consecutive {
+                let bitmap = defect_vertices[defect_index..defect_index + window]
+                    .iter()
+                    .fold(0u64, |bitmap, &vertex_index| bitmap | (1 << (vertex_index - base_vertex)));
+                self.dual_module
+                    .add_defects_bitmap(ni!(base_vertex), ni!(node_indices[0]), bitmap);
+            } else {
+                for (offset, &node_index) in node_indices.iter().enumerate() {
+                    self.dual_module
+                        .add_defect(ni!(defect_vertices[defect_index + offset]), ni!(node_index));
+                }
+            }
+            for &node_index in node_indices.iter() {
+                self.primal_module
+                    .speed_up_lone_defect(self.dual_module.as_mut(), ni!(node_index));
+            }
+            defect_index += window;
+        }
     }
-    fn reset_profiler(&mut self) {
-        self.dual_module.driver.driver.reset_profiler();
+
+    /// pin the matches decided before solving, which removes their defects from the syndrome (see
+    /// [`crate::pinned_matching`]); must be called before [`Self::load_syndrome`] and stays until
+    /// [`PrimalDualSolver::clear`]
+    pub fn pin_matching(&mut self, pinned: PinnedMatching) {
+        assert!(
+            self.defect_nodes.is_empty(),
+            "must pin the matches before loading the syndrome"
+        );
+        self.pinned = pinned;
     }
-    fn solve_visualizer(&mut self, syndrome_pattern: &SyndromePattern, mut visualizer: Option<&mut Visualizer>) {
+
+    /// load the syndrome without solving it; call [`Self::step`] repeatedly to advance the solver
+    pub fn load_syndrome(&mut self, syndrome_pattern: &SyndromePattern) {
         assert!(syndrome_pattern.erasures.is_empty());
         assert!(syndrome_pattern.dynamic_weights.is_empty());
+        let defect_vertices = match self.sanitizer.as_mut() {
+            Some(sanitizer) => sanitizer.sanitize(&syndrome_pattern.defect_vertices),
+            None => syndrome_pattern.defect_vertices.clone(),
+        };
+        // the pinned defects are never loaded into the dual module
+        let mut defect_vertices = self.pinned.remaining_defects(&self.graph, &defect_vertices);
+        if let Some(seed) = self.config.defect_order_seed {
+            let mut rng = Xoroshiro128StarStar::seed_from_u64(shot_seed(seed, &syndrome_pattern.defect_vertices));
+            defect_vertices.shuffle(&mut rng);
+        }
+        self.load_ordered_defects(defect_vertices);
+    }
+
+    /// load the defects exactly in the given order, without shuffling
+    pub(crate) fn load_ordered_defects(&mut self, defect_vertices: Vec<VertexIndex>) {
         assert!(self.defect_nodes.is_empty(), "must call `clear` between different runs");
-        for (node_index, &defect_index) in syndrome_pattern.defect_vertices.iter().enumerate() {
-            self.dual_module.add_defect(ni!(defect_index), ni!(node_index));
-            self.defect_nodes.push(defect_index);
+        self.load_defects(&defect_vertices);
+        self.loaded_defects = defect_vertices;
+    }
+
+    /// load the defects of the next measurement round as it arrives and decode it in streaming mode, i.e., fuse the
+    /// layer and resolve all the obstacles, without knowing the later rounds; requires `support_layer_fusion`.
+    /// Like [`Self::load_syndrome`], the defects of every round are sanitized and shuffled if configured.
+    /// Call [`Self::stream_finish`] after the last round; returns false if there is no pending layer
+    pub fn stream_round(&mut self, defect_vertices: &[VertexIndex]) -> bool {
+        assert!(
+            self.sim_config.support_layer_fusion,
+            "streaming rounds requires `support_layer_fusion`"
+        );
+        let raw_defect_vertices = defect_vertices;
+        let mut defect_vertices = match self.sanitizer.as_mut() {
+            Some(sanitizer) => sanitizer.sanitize_round(raw_defect_vertices),
+            None => raw_defect_vertices.to_vec(),
+        };
+        if let Some(seed) = self.config.defect_order_seed {
+            let mut rng = Xoroshiro128StarStar::seed_from_u64(shot_seed(seed, raw_defect_vertices));
+            defect_vertices.shuffle(&mut rng);
+        }
+        let layer_fusion = self.graph.layer_fusion.as_ref().unwrap();
+        if self.layer_id >= layer_fusion.num_layers {
+            assert!(defect_vertices.is_empty(), "defects after the last round");
+            return false;
+        }
+        for vertex_index in defect_vertices.iter() {
+            // a truncated solve stops fusing, so the defects may belong to a later pending round
+            assert!(
+                layer_fusion
+                    .vertex_layer_id
+                    .get(vertex_index)
+                    .map_or(true, |&layer_id| layer_id >= self.layer_id),
+                "defect {vertex_index} belongs to a round that is already fused"
+            );
+        }
+        assert!(self.pinned.is_empty(), "pinned matches are not supported in streaming mode");
+        self.load_defects(&defect_vertices);
+        self.loaded_defects.extend_from_slice(&defect_vertices);
+        self.step_round()
+    }
+
+    /// fuse the remaining rounds, if any, and finish the shot streamed by [`Self::stream_round`]
+    pub fn stream_finish(&mut self) {
+        while self.step() {}
+        self.finish();
+    }
+
+    /// resolve a single obstacle, or fuse the next layer if there is no obstacle;
+    /// returns false when the solver has finished (or reached `max_iterations`)
+    pub fn step(&mut self) -> bool {
+        self.step_visualizer(None)
+    }
+
+    pub fn step_visualizer(&mut self, mut visualizer: Option<&mut Visualizer>) -> bool {
+        if self.iteration >= self.config.max_iterations {
+            return false;
+        }
+        self.resolve_obstacle(visualizer.as_deref_mut()) || self.fuse_next_layer(visualizer)
+    }
+
+    /// decode a single measurement round in streaming mode: fuse the next layer and resolve all the obstacles;
+    /// returns false if there is no pending layer (or reached `max_iterations`)
+    pub fn step_round(&mut self) -> bool {
+        while self.resolve_obstacle(None) {}
+        if self.iteration >= self.config.max_iterations || !self.fuse_next_layer(None) {
+            return false;
+        }
+        while self.resolve_obstacle(None) {}
+        true
+    }
+
+    /// returns whether an obstacle is resolved
+    fn resolve_obstacle(&mut self, mut visualizer: Option<&mut Visualizer>) -> bool {
+        if let Some(round_trips) = self.round_trips.as_mut() {
+            round_trips.check(self.dual_module.driver.round_trips);
+        }
+        if self.iteration >= self.config.max_iterations || self.is_truncated() {
+            return false;
+        }
+        let (obstacle, _) = self.dual_module.find_obstacle();
+        if obstacle.is_none() {
+            return false;
         }
+        self.iteration += 1;
+        debug_assert!(
+            obstacle.is_obstacle(),
+            "dual module should spontaneously process all finite growth"
+        );
         if let Some(visualizer) = visualizer.as_mut() {
-            visualizer.snapshot("syndrome".to_string(), self).unwrap();
+            visualizer.snapshot(format!("{obstacle:?}"), self).unwrap();
         }
-        let mut iteration = 0;
-        loop {
-            let (mut obstacle, _) = self.dual_module.find_obstacle();
-            while !obstacle.is_none() && iteration < self.config.max_iterations {
-                iteration += 1;
-                // println!("obstacle: {obstacle:?}");
-                debug_assert!(
-                    obstacle.is_obstacle(),
-                    "dual module should spontaneously process all finite growth"
-                );
-                if let Some(visualizer) = visualizer.as_mut() {
-                    visualizer.snapshot(format!("{obstacle:?}"), self).unwrap();
-                }
-                self.primal_module.resolve(self.dual_module.as_mut(), obstacle);
-                (obstacle, _) = self.dual_module.find_obstacle();
+        self.primal_module.resolve(self.dual_module.as_mut(), obstacle);
+        if let Some(blossom_budget) = self.blossom_budget.as_mut() {
+            blossom_budget.check(self.primal_module.nodes.count_blossoms);
+        }
+        if let Some(blossom_size_cap) = self.blossom_size_cap.as_mut() {
+            blossom_size_cap.check(&self.primal_module.nodes);
+        }
+        true
+    }
+
+    /// whether the solve stops early and leaves the remaining defects to the greedy fallback, either because the
+    /// blossom budget or the blossom size cap overflows, because the round trips reach the cap or because the
+    /// primal-dual interface desynchronizes
+    fn is_truncated(&self) -> bool {
+        self.dual_module.driver.driver.desynchronized()
+            || self
+                .blossom_budget
+                .as_ref()
+                .is_some_and(|blossom_budget| blossom_budget.overflowed)
+            || self
+                .blossom_size_cap
+                .as_ref()
+                .is_some_and(|blossom_size_cap| blossom_size_cap.overflowed)
+            || self.round_trips.as_ref().is_some_and(|round_trips| round_trips.capped)
+    }
+
+    /// returns whether a pending layer is fused
+    fn fuse_next_layer(&mut self, mut visualizer: Option<&mut Visualizer>) -> bool {
+        if !self.sim_config.support_layer_fusion || self.is_truncated() {
+            return false;
+        }
+        let num_layers = self.graph.layer_fusion.as_ref().unwrap().num_layers;
+        if self.layer_id >= num_layers {
+            return false;
+        }
+        self.dual_module.driver.driver.fuse_layer(self.layer_id);
+        self.primal_module.fuse_layer(
+            self.dual_module.as_mut(),
+            CompactLayerId::new(self.layer_id as CompactLayerNum).unwrap(),
+        );
+        if let Some(visualizer) = visualizer.as_mut() {
+            visualizer.snapshot(format!("fusion {}", self.layer_id), self).unwrap();
+        }
+        self.layer_id += 1;
+        true
+    }
+
+    /// build the subgraph after all the obstacles are resolved
+    pub(crate) fn finish(&mut self) {
+        let mut fallback_defects = 0;
+        if self.is_truncated() {
+            // the defects not covered by the settled matchings, including those in the layers not yet fused
+            let (settled_matching, _) = self.settled_perfect_matching();
+            let defect_vertex_of = |node_ptr: &DualNodePtr| match node_ptr.read_recursive().class {
+                DualNodeClass::DefectVertex { defect_index } => defect_index,
+                DualNodeClass::Blossom { .. } => unreachable!("perfect matching only contains defect vertices"),
+            };
+            let mut remaining: BTreeSet<VertexIndex> = self.loaded_defects.iter().cloned().collect();
+            for vertex_index in self.recycled.defect_vertices().chain(self.removed_defects.iter().cloned()) {
+                remaining.remove(&vertex_index);
             }
-            if iteration >= self.config.max_iterations {
-                break;
+            for (node_1, node_2) in settled_matching.peer_matchings.iter() {
+                remaining.remove(&defect_vertex_of(node_1));
+                remaining.remove(&defect_vertex_of(node_2));
             }
-            // if there are pending fusion layers, execute them
-            if self.sim_config.support_layer_fusion {
-                let num_layers = self.graph.layer_fusion.as_ref().unwrap().num_layers;
-                if self.layer_id < num_layers {
-                    self.dual_module.driver.driver.fuse_layer(self.layer_id);
-                    self.primal_module.fuse_layer(
-                        self.dual_module.as_mut(),
-                        CompactLayerId::new(self.layer_id as CompactLayerNum).unwrap(),
-                    );
-                    if let Some(visualizer) = visualizer.as_mut() {
-                        visualizer.snapshot(format!("fusion {}", self.layer_id), self).unwrap();
-                    }
-                    self.layer_id += 1;
-                    continue;
-                }
+            for (node, _) in settled_matching.virtual_matchings.iter() {
+                remaining.remove(&defect_vertex_of(node));
             }
-            break;
+            let remaining: Vec<VertexIndex> = remaining.into_iter().collect();
+            self.fallback = greedy_matching(&self.graph, &remaining);
+            fallback_defects = remaining.len();
         }
-        if let Some(visualizer) = visualizer.as_mut() {
-            visualizer.snapshot("solved".to_string(), self).unwrap();
+        if let Some(blossom_budget) = self.blossom_budget.as_mut() {
+            blossom_budget.finish(self.primal_module.nodes.count_blossoms, fallback_defects);
+        }
+        if let Some(blossom_size_cap) = self.blossom_size_cap.as_mut() {
+            blossom_size_cap.finish(fallback_defects);
+        }
+        if let Some(round_trips) = self.round_trips.as_mut() {
+            round_trips.finish(
+                self.loaded_defects.len(),
+                self.dual_module.driver.round_trips,
+                fallback_defects,
+            );
         }
         let perfect_matching = self.perfect_matching();
         self.subgraph_builder.load_perfect_matching(&perfect_matching);
         // check how many defect vertices are offloaded (not maintained by the primal module at all)
         self.offloaded = 0;
         for node_index in 0..self.defect_nodes.len() {
+            if self.node_virtualizer.to_global(node_index as NodeIndex).is_none() {
+                continue; // recycled and not reused yet
+            }
             if !self.primal_module.nodes.maintains_defect_node(ni!(node_index)) {
                 self.offloaded += 1;
             }
         }
+        if let Some(offloading_regions) = self.offloading_regions.as_mut() {
+            let interface_ptr = DualModuleInterfacePtr::new_empty();
+            let pre_matchings = self.dual_module.driver.driver.get_pre_matchings(interface_ptr.downgrade());
+            offloading_regions.record(&pre_matchings);
+        }
+        if let Some(latency_predictor) = self.latency_predictor.as_mut() {
+            let latency = latency_predictor.model.predict(&self.latency_features());
+            latency_predictor.latencies.push(latency);
+        }
+        if self.weight_overflowed() {
+            self.weight_overflow_shots += 1;
+        }
+        if self.dual_module.driver.driver.desynchronized() {
+            self.desynchronized_shots += 1;
+        }
+        self.dual_module.driver.driver.finish_shot();
     }
-    fn perfect_matching_visualizer(&mut self, visualizer: Option<&mut Visualizer>) -> PerfectMatching {
+
+    /// whether a weight computation of the current shot overflowed, either in the driver or in the dual module
+    pub fn weight_overflowed(&self) -> bool {
+        self.dual_module.driver.weight_overflowed() || self.dual_module.driver.driver.weight_overflowed()
+    }
+
+    /// the features of the current shot that the hardware latency depends on, see [`LatencyModel`]
+    pub fn latency_features(&self) -> LatencyFeatures {
+        LatencyFeatures {
+            round_trips: self.dual_module.driver.round_trips,
+            instruction_counts: self.dual_module.driver.driver.instruction_counts().unwrap_or_default(),
+        }
+    }
+
+    /// the number of obstacles resolved in the current shot
+    pub fn iterations(&self) -> usize {
+        self.iteration
+    }
+
+    /// the matching maintained by the primal module at this moment, without finishing the solve, see
+    /// [`EmbeddedIntermediateMatching`]; pre-matchings inside the dual module are not included
+    pub fn intermediate_matching(&self) -> EmbeddedIntermediateMatching {
+        let nodes = &self.primal_module.nodes;
+        let mut intermediate_matching = EmbeddedIntermediateMatching::default();
+        nodes.iterate_intermediate_matching(|node_index, match_target, _| match match_target {
+            CompactMatchTarget::Peer(peer_index) => intermediate_matching
+                .peer_matchings
+                .push((node_index.get() as NodeIndex, peer_index.get() as NodeIndex)),
+            CompactMatchTarget::VirtualVertex(vertex_index) => intermediate_matching
+                .virtual_matchings
+                .push((node_index.get() as NodeIndex, vertex_index.get() as VertexIndex)),
+        });
+        for index in nodes.index_iter() {
+            let node_index = ni!(index);
+            if !nodes.has_node(node_index) {
+                continue; // not loaded yet, or a disposed blossom
+            }
+            let node = nodes.get_node(node_index);
+            if node.is_outer_blossom() && !node.is_matched() {
+                intermediate_matching.unsettled.push(index as NodeIndex);
+            }
+        }
+        intermediate_matching
+    }
+
+    /// the dual variable of a node; the dual module only stores the grown value of every vertex, which is the sum
+    /// of the dual variables of the defect node at this vertex and all the blossoms containing it, while the dual
+    /// variables of the blossoms are tracked in software by [`DualDriverTracked`]; `None` if the driver does not
+    /// support reading back the vertices
+    pub fn read_node_dual(&mut self, node_index: NodeIndex) -> Option<Weight> {
+        let nodes = &self.primal_module.nodes;
+        let node = ni!(node_index);
+        if nodes.is_blossom(node) {
+            return self.dual_module.read_node_dual(node).map(|dual| dual as Weight);
+        }
+        // an offloaded defect is never loaded into the primal module, thus not in any blossom
+        let mut blossoms = vec![];
+        if nodes.has_node(node) {
+            let mut inner = node;
+            while !nodes.get_node(inner).is_outer_blossom() {
+                inner = nodes.get_node(inner).parent.unwrap();
+                blossoms.push(inner);
+            }
+        }
+        let mut dual = self.dual_module.read_vertex_grown(ni!(self.defect_nodes[node_index]))? as Weight;
+        for blossom in blossoms {
+            dual -= self.dual_module.read_node_dual(blossom)? as Weight;
+        }
+        Some(dual)
+    }
+
+    /// the dual objective read back from the dual module, which should equal the weight of the minimum-weight
+    /// perfect matching once solved; `None` if the driver does not support reading back dual variables
+    pub fn read_dual_objective(&mut self) -> Option<Weight> {
+        let blossom_begin = self.primal_module.nodes.blossom_begin;
+        let blossoms = blossom_begin..blossom_begin + self.primal_module.nodes.count_blossoms;
+        (0..self.defect_nodes.len())
+            .chain(blossoms)
+            .map(|node_index| self.read_node_dual(node_index))
+            .sum()
+    }
+
+    /// the path of tight edges between every matched pair, read back from the growth of every vertex once solved
+    pub fn correction_paths(&mut self) -> Option<Vec<CorrectionPath>> {
+        let grown: Vec<Weight> = (0..self.graph.vertex_num)
+            .map(|vertex_index| {
+                self.dual_module
+                    .read_vertex_grown(ni!(vertex_index))
+                    .map(|grown| grown as Weight)
+            })
+            .collect::<Option<_>>()?;
+        let perfect_matching = self.perfect_matching();
+        Some(TightPathFinder::new(&self.graph, &grown).correction_paths(&perfect_matching))
+    }
+
+    /// the matchings of the primal module and the pre-matchings inside the dual module; after a truncated solve,
+    /// the matchings to the layers not yet fused are excluded because they are not valid corrections
+    fn settled_perfect_matching(&mut self) -> (PerfectMatching, DualModuleInterfaceWeak) {
         // this perfect matching is not necessarily complete when some of the matchings are inside the dual module
         let (mut perfect_matching, belonging) =
             perfect_matching_from_embedded_primal(&mut self.primal_module, &self.defect_nodes);
@@ -407,6 +1209,216 @@ impl<Dual: SolverTrackedDual> PrimalDualSolver for SolverEmbeddedBoxed<Dual> {
         perfect_matching
             .virtual_matchings
             .append(&mut pre_matchings.virtual_matchings);
+        if self.is_truncated() {
+            let virtual_vertices = &self.graph.virtual_vertices;
+            perfect_matching
+                .virtual_matchings
+                .retain(|(_, virtual_vertex)| virtual_vertices.contains(virtual_vertex));
+        }
+        (perfect_matching, belonging)
+    }
+
+    /// allocate a hardware node index for a defect, recycling the finished nodes once the pool is exhausted,
+    /// see [`NodeVirtualizer`]
+    fn allocate_node(&mut self, global_index: usize, vertex_index: VertexIndex) -> NodeIndex {
+        let node_index = match self.node_virtualizer.allocate(global_index) {
+            Some(node_index) => node_index,
+            None => {
+                self.recycle_finished_nodes();
+                self.node_virtualizer
+                    .allocate(global_index)
+                    .expect("hardware node index pool exhausted and no finished node can be recycled")
+            }
+        };
+        if node_index as usize == self.defect_nodes.len() {
+            self.defect_nodes.push(vertex_index);
+        } else {
+            self.defect_nodes[node_index as usize] = vertex_index;
+        }
+        node_index
+    }
+
+    /// recycle the hardware indices of the finished nodes, i.e., the defect nodes matched to each other or to a
+    /// boundary whose regions are entirely frozen in the dual module, so that they never change again (see
+    /// [`crate::correction_stream`]); the matches are recorded before the primal module, the dual module and the
+    /// virtualizer forget the nodes. Returns the number of recycled nodes
+    pub fn recycle_finished_nodes(&mut self) -> usize {
+        let nodes = &self.primal_module.nodes;
+        let mut finished: Vec<(NodeIndex, CompactMatchTarget)> = vec![];
+        for index in 0..nodes.count_defects {
+            let node_index = ni!(index);
+            if !nodes.has_node(node_index) {
+                continue;
+            }
+            let node = nodes.get_node(node_index);
+            if !node.is_outer_blossom() || !node.is_matched() {
+                continue;
+            }
+            match node.get_matched() {
+                CompactMatchTarget::Peer(peer_index) => {
+                    if peer_index.get() > node_index.get() && !nodes.is_blossom(peer_index) {
+                        finished.push((index as NodeIndex, CompactMatchTarget::Peer(peer_index)));
+                    }
+                }
+                CompactMatchTarget::VirtualVertex(virtual_vertex) => {
+                    // the vertices of a pending layer are a temporary boundary, which is broken when fused
+                    if self.graph.virtual_vertices.contains(&(virtual_vertex.get() as VertexIndex)) {
+                        finished.push((index as NodeIndex, CompactMatchTarget::VirtualVertex(virtual_vertex)));
+                    }
+                }
+            }
+        }
+        let mut recycled = 0;
+        for (node_index, match_target) in finished {
+            let released = match match_target {
+                CompactMatchTarget::Peer(peer_index) => vec![node_index, peer_index.get() as NodeIndex],
+                CompactMatchTarget::VirtualVertex(_) => vec![node_index],
+            };
+            match self.dual_module.driver.driver.release_nodes(&released) {
+                Ok(true) => {}
+                Ok(false) => continue, // still growing in the region that is not frozen
+                // the support is checked in `Self::new` if the hardware node indices are limited
+                Err(error) => panic!("{error}, cannot recycle the finished nodes"),
+            }
+            match match_target {
+                CompactMatchTarget::Peer(peer_index) => self.recycled.pin_peer(
+                    self.defect_nodes[node_index as usize],
+                    self.defect_nodes[peer_index.get() as usize],
+                ),
+                CompactMatchTarget::VirtualVertex(virtual_vertex) => self
+                    .recycled
+                    .pin_virtual(self.defect_nodes[node_index as usize], virtual_vertex.get() as VertexIndex),
+            }
+            for node_index in released {
+                self.primal_module.recycle_defect(ni!(node_index));
+                let global_index = self.node_virtualizer.to_global(node_index).unwrap();
+                self.node_virtualizer.recycle(global_index);
+                recycled += 1;
+            }
+        }
+        recycled
+    }
+
+    /// retract a loaded defect, e.g., after a heralded reset, from both the dual module and the primal module, see
+    /// [`SolverTrackedDual::remove_defect`]; the node must be free, i.e., not matched, not in any alternating tree
+    /// and not in any blossom. Its hardware index is recycled for the later defects
+    pub fn remove_defect(&mut self, vertex_index: VertexIndex) -> Result<(), DualDriverError> {
+        let invalid = |message: String| DualDriverError::InvalidArgument {
+            operation: "remove_defect",
+            message,
+        };
+        let node_index = (0..self.defect_nodes.len())
+            .find(|&node_index| {
+                self.defect_nodes[node_index] == vertex_index
+                    && self.node_virtualizer.to_global(node_index as NodeIndex).is_some()
+            })
+            .ok_or_else(|| invalid(format!("vertex {vertex_index} is not a loaded defect")))?;
+        let nodes = &self.primal_module.nodes;
+        // a node that the primal module never encountered is only known to the dual module
+        let in_primal = nodes.has_node(ni!(node_index));
+        if in_primal {
+            let node = nodes.get_node(ni!(node_index));
+            if !node.is_outer_blossom() || !node.is_free() {
+                return Err(invalid(format!("defect {vertex_index} is not free in the primal module")));
+            }
+        }
+        self.dual_module.driver.driver.remove_defect(vertex_index)?;
+        if in_primal {
+            self.primal_module.recycle_defect(ni!(node_index));
+        }
+        let global_index = self.node_virtualizer.to_global(node_index as NodeIndex).unwrap();
+        self.node_virtualizer.recycle(global_index);
+        self.removed_defects.push(vertex_index);
+        Ok(())
+    }
+}
+
+impl<Dual: SolverTrackedDual> PrimalDualSolver for SolverEmbeddedBoxed<Dual> {
+    fn clear(&mut self) {
+        self.primal_module.reset();
+        self.dual_module.reset();
+        self.subgraph_builder.clear();
+        self.defect_nodes.clear();
+        self.node_virtualizer.clear();
+        self.loaded_defects.clear();
+        self.removed_defects.clear();
+        self.pinned.clear();
+        self.fallback.clear();
+        self.recycled.clear();
+        if let Some(sanitizer) = self.sanitizer.as_mut() {
+            sanitizer.clear_shot();
+        }
+        if let Some(blossom_budget) = self.blossom_budget.as_mut() {
+            blossom_budget.clear();
+        }
+        if let Some(blossom_size_cap) = self.blossom_size_cap.as_mut() {
+            blossom_size_cap.clear();
+        }
+        if let Some(round_trips) = self.round_trips.as_mut() {
+            round_trips.clear();
+        }
+        self.layer_id = 0;
+        self.iteration = 0;
+    }
+    fn reset_profiler(&mut self) {
+        self.dual_module.driver.driver.reset_profiler();
+        self.node_virtualizer.statistics = NodeVirtualizerStatistics::default();
+        if let Some(tracker) = self.defect_latency.as_mut() {
+            tracker.clear();
+        }
+        if let Some(blossom_budget) = self.blossom_budget.as_mut() {
+            blossom_budget.statistics = BlossomBudgetStatistics::default();
+        }
+        if let Some(blossom_size_cap) = self.blossom_size_cap.as_mut() {
+            blossom_size_cap.statistics = BlossomSizeCapStatistics::default();
+        }
+        if let Some(round_trips) = self.round_trips.as_mut() {
+            round_trips.reset_statistics();
+        }
+        if let Some(offloading_regions) = self.offloading_regions.as_mut() {
+            offloading_regions.reset_statistics();
+        }
+        if let Some(sanitizer) = self.sanitizer.as_mut() {
+            sanitizer.reset_statistics();
+        }
+        if let Some(latency_predictor) = self.latency_predictor.as_mut() {
+            latency_predictor.latencies.clear();
+        }
+        self.weight_overflow_shots = 0;
+        self.desynchronized_shots = 0;
+    }
+    fn solve_visualizer(&mut self, syndrome_pattern: &SyndromePattern, mut visualizer: Option<&mut Visualizer>) {
+        if visualizer.is_none() {
+            if let Some(mut tracker) = self.defect_latency.take() {
+                tracker.solve(self, syndrome_pattern);
+                self.defect_latency = Some(tracker);
+                return;
+            }
+        }
+        self.load_syndrome(syndrome_pattern);
+        if let Some(visualizer) = visualizer.as_mut() {
+            visualizer.snapshot("syndrome".to_string(), self).unwrap();
+        }
+        while self.step_visualizer(visualizer.as_deref_mut()) {}
+        if let Some(visualizer) = visualizer.as_mut() {
+            visualizer.snapshot("solved".to_string(), self).unwrap();
+        }
+        self.finish();
+    }
+    fn perfect_matching_visualizer(&mut self, visualizer: Option<&mut Visualizer>) -> PerfectMatching {
+        let (mut perfect_matching, belonging) = self.settled_perfect_matching();
+        let loaded_defects = &self.loaded_defects;
+        let global_index = |vertex_index: VertexIndex| {
+            loaded_defects.iter().position(|&defect| defect == vertex_index).unwrap() as NodeIndex
+        };
+        self.fallback.append_to(&mut perfect_matching, &belonging, global_index);
+        self.recycled.append_to(&mut perfect_matching, &belonging, global_index);
+        // the pinned defects have no hardware node, so they are numbered after the loaded defects
+        let pinned = &self.pinned;
+        let loaded_num = self.loaded_defects.len();
+        pinned.append_to(&mut perfect_matching, &belonging, |vertex_index| {
+            (loaded_num + pinned.defect_vertices().position(|defect| defect == vertex_index).unwrap()) as NodeIndex
+        });
         if let Some(visualizer) = visualizer {
             visualizer
                 .snapshot_combined("perfect matching".to_string(), vec![self, &perfect_matching])
@@ -437,12 +1449,67 @@ impl<Dual: SolverTrackedDual> PrimalDualSolver for SolverEmbeddedBoxed<Dual> {
         self.subgraph_builder.total_weight()
     }
     fn generate_profiler_report(&self) -> serde_json::Value {
-        json!({
+        let mut report = json!({
             "dual": self.dual_module.driver.driver.generate_profiler_report(),
             "primal": {
                 "offloaded": self.offloaded,
+                "node_virtualizer": self.node_virtualizer.statistics,
             },
-        })
+        });
+        if let Some(tracker) = self.defect_latency.as_ref() {
+            report["defect_latency"] = tracker.generate_report();
+        }
+        if let Some(blossom_budget) = self.blossom_budget.as_ref() {
+            report["blossom_budget"] = blossom_budget.generate_report();
+        }
+        if let Some(blossom_size_cap) = self.blossom_size_cap.as_ref() {
+            report["blossom_size_cap"] = blossom_size_cap.generate_report();
+        }
+        if let Some(round_trips) = self.round_trips.as_ref() {
+            report["round_trips"] = round_trips.generate_report();
+        }
+        if let Some(offloading_regions) = self.offloading_regions.as_ref() {
+            report["offloading_regions"] = offloading_regions.generate_report();
+        }
+        if let Some(sanitizer) = self.sanitizer.as_ref() {
+            report["sanitized"] = json!(sanitizer.total);
+        }
+        if let Some(latency_predictor) = self.latency_predictor.as_ref() {
+            report["predicted_latency"] = latency_predictor.generate_report();
+        }
+        if let Some(hardware_counters) = self.dual_module.driver.driver.hardware_counters() {
+            report["hardware_counters"] = json!(hardware_counters);
+        }
+        if cfg!(feature = "checked_weight") {
+            report["weight_overflow_shots"] = json!(self.weight_overflow_shots);
+        }
+        report["desynchronized_shots"] = json!(self.desynchronized_shots);
+        report
+    }
+}
+
+impl<Dual: SolverTrackedDual + 'static> MicroBlossomSolver for SolverEmbeddedBoxed<Dual> {
+    fn result(&mut self) -> SolverResult {
+        let subgraph = self.subgraph();
+        let matching_weight = self.subgraph_builder.total_weight();
+        // recycled node indices lose the dual variables of the finished nodes, and the dual variables do not cover
+        // the pinned matches
+        let readback = self.dual_module.driver.driver.supports_dual_readback()
+            && self.config.hardware_node_capacity.is_none()
+            && self.pinned.is_empty();
+        let dual_objective = readback.then(|| self.read_dual_objective()).flatten();
+        let mut result = SolverResult::new(subgraph, matching_weight, dual_objective);
+        result.instruction_counts = self.dual_module.driver.driver.instruction_counts();
+        result.clock_cycles = self.dual_module.driver.driver.clock_cycles();
+        result.offloaded = self.sim_config.support_offloading.then_some(self.offloaded);
+        // the greedy fallback of a truncated solve does not follow the tight edges
+        result.correction_paths = (readback && !self.is_truncated()).then(|| self.correction_paths()).flatten();
+        result.sanitized = self.sanitizer.as_ref().map(|sanitizer| sanitizer.shot.clone());
+        result.weight_overflow = self.weight_overflowed();
+        result.desynchronized = self.dual_module.driver.driver.desynchronized();
+        // the saturated weights or the untrustworthy responses no longer certify the optimality
+        result.certified &= !result.weight_overflow && !result.desynchronized;
+        result
     }
 }
 
@@ -450,3 +1517,204 @@ pub type SolverEmbeddedComb = SolverEmbeddedBoxed<DualModuleCombDriver>;
 pub type SolverEmbeddedScala = SolverEmbeddedBoxed<DualModuleScalaDriver>;
 pub type SolverEmbeddedLooper = SolverEmbeddedBoxed<DualModuleLooperDriver>;
 pub type SolverEmbeddedAxi4 = SolverEmbeddedBoxed<DualModuleAxi4Driver>;
+pub type SolverEmbeddedCombJitter = SolverEmbeddedBoxed<DualModuleJitterDriver<DualModuleCombDriver>>;
+pub type SolverEmbeddedCombTrace = SolverEmbeddedBoxed<DualModuleTraceDriver<DualModuleCombDriver>>;
+
+/// Multiple decoding graphs served by a single dual core in round-robin, e.g., the X-basis and Z-basis decoding
+/// graphs of one surface code patch. Each graph is loaded as a separate context of the same comb dual module (see
+/// [`crate::dual_module_comb_context`]), and every measurement round is decoded for all the contexts before moving on
+/// to the next round, so that the latency of a round is the combined decoding time of all the contexts.
+/// The contexts share the hardware, and thus the topology of the decoding graph; the weights of a graph that differ
+/// from the first one are written into the edge registers of its context at the beginning of every shot.
+pub struct SolverInterleaved {
+    pub contexts: Vec<SolverEmbeddedBoxed<ContextDriver>>,
+    /// `round_latencies[round][context]` in seconds, where the last round includes building the correction
+    pub round_latencies: Vec<Vec<f64>>,
+    /// `context_weights[context]`: the edges whose weight differs from the graph of the dual module
+    context_weights: Vec<Vec<(EdgeIndex, Weight)>>,
+    num_layers: usize,
+}
+
+impl SolverInterleaved {
+    /// every context shares the same `primal_dual_config`, which must enable layer fusion; the graphs must have the
+    /// same vertices, edges and layers, only the weights of the edges may differ
+    pub fn new(graphs: Vec<MicroBlossomSingle>, mut primal_dual_config: serde_json::Value) -> Self {
+        assert!(!graphs.is_empty(), "at least one decoding graph is required");
+        let base = graphs[0].clone();
+        let num_layers = base.layer_fusion.as_ref().map_or(0, |layer_fusion| layer_fusion.num_layers);
+        let mut context_weights = vec![];
+        for graph in graphs.iter() {
+            assert!(
+                graph.vertex_num == base.vertex_num
+                    && graph.virtual_vertices == base.virtual_vertices
+                    && graph.weighted_edges.len() == base.weighted_edges.len()
+                    && (graph.weighted_edges.iter().zip(base.weighted_edges.iter())).all(|(edge, base_edge)| (
+                        edge.l, edge.r
+                    ) == (
+                        base_edge.l,
+                        base_edge.r
+                    ))
+                    && graph.layer_fusion == base.layer_fusion,
+                "the contexts of a dual module must share the topology of the decoding graph"
+            );
+            let weights = (graph.weighted_edges.iter().zip(base.weighted_edges.iter()).enumerate())
+                .filter(|(_, (edge, base_edge))| edge.w != base_edge.w)
+                .map(|(edge_index, (edge, _))| (edge_index as EdgeIndex, edge.w as Weight))
+                .collect();
+            context_weights.push(weights);
+        }
+        primal_dual_config["dual"]["context_depth"] = json!(graphs.len());
+        let mut contexts: Vec<SolverEmbeddedBoxed<ContextDriver>> = vec![];
+        for (context_id, graph) in graphs.into_iter().enumerate() {
+            let mut context = SolverEmbeddedBoxed::<ContextDriver>::new(graph, primal_dual_config.clone());
+            assert!(
+                context.sim_config.support_layer_fusion,
+                "interleaving requires layer fusion to decode round by round"
+            );
+            if let Some(first) = contexts.first() {
+                context.dual_module.driver.driver = first.dual_module.driver.driver.share(context_id);
+            }
+            contexts.push(context);
+        }
+        Self {
+            contexts,
+            round_latencies: vec![],
+            context_weights,
+            num_layers,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for context in self.contexts.iter_mut() {
+            context.clear();
+        }
+        self.round_latencies.clear();
+    }
+
+    /// one syndrome pattern for each context
+    pub fn solve(&mut self, syndrome_patterns: &[SyndromePattern]) {
+        assert_eq!(syndrome_patterns.len(), self.contexts.len());
+        for ((context, weights), syndrome_pattern) in
+            (self.contexts.iter_mut().zip(self.context_weights.iter())).zip(syndrome_patterns.iter())
+        {
+            for &(edge_index, weight) in weights.iter() {
+                context.dual_module.driver.driver.set_edge_weight(edge_index, weight).unwrap();
+            }
+            context.load_syndrome(syndrome_pattern);
+        }
+        for round in 0..self.num_layers {
+            let mut latencies = vec![0.; self.contexts.len()];
+            for (context_id, context) in self.contexts.iter_mut().enumerate() {
+                let begin = std::time::Instant::now();
+                context.step_round();
+                if round + 1 == self.num_layers {
+                    // the correction is only available after the last round
+                    context.finish();
+                }
+                latencies[context_id] = begin.elapsed().as_secs_f64();
+            }
+            self.round_latencies.push(latencies);
+        }
+    }
+
+    pub fn subgraphs(&mut self) -> Vec<Vec<EdgeIndex>> {
+        self.contexts.iter_mut().map(|context| context.subgraph()).collect()
+    }
+
+    pub fn generate_profiler_report(&self) -> serde_json::Value {
+        let round_latency: Vec<f64> = self.round_latencies.iter().map(|latencies| latencies.iter().sum()).collect();
+        json!({
+            "round_latency": round_latency,
+            "context_round_latency": self.round_latencies,
+            "contexts": self.contexts.iter().map(|context| context.generate_profiler_report()).collect::<Vec<_>>(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    /// feeding the rounds one by one to the same solver gives the same result as solving the whole syndrome
+    #[test]
+    fn mwpm_solver_stream_rounds() {
+        // cargo test mwpm_solver_stream_rounds -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 6, 0.03, 500);
+        let initializer = code.get_initializer();
+        let graph = MicroBlossomSingle::new_code(&code);
+        let layer_fusion = graph.layer_fusion.clone().unwrap();
+        let config = json!({ "dual": { "sim_config": { "support_offloading": true, "support_layer_fusion": true } } });
+        let mut solver = SolverEmbeddedComb::new(graph, config);
+        let mut serial = SolverSerial::new(&initializer);
+        for seed in 0..50 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            let mut rounds = vec![vec![]; layer_fusion.num_layers];
+            for &vertex_index in syndrome_pattern.defect_vertices.iter() {
+                rounds[layer_fusion.vertex_layer_id[&vertex_index]].push(vertex_index);
+            }
+            for defect_vertices in rounds.iter() {
+                assert!(solver.stream_round(defect_vertices));
+            }
+            assert!(!solver.stream_round(&[]));
+            solver.stream_finish();
+            serial.solve(&syndrome_pattern);
+            assert_eq!(solver.sum_dual_variables(), serial.sum_dual_variables());
+            assert_eq!(
+                solver
+                    .subgraph()
+                    .iter()
+                    .map(|&edge_index| initializer.weighted_edges[edge_index].2)
+                    .sum::<Weight>(),
+                serial
+                    .subgraph()
+                    .iter()
+                    .map(|&edge_index| initializer.weighted_edges[edge_index].2)
+                    .sum::<Weight>()
+            );
+            solver.clear();
+            serial.clear();
+        }
+    }
+
+    /// a retracted defect is forgotten by both modules, as if it were never loaded; a matched defect is rejected
+    #[test]
+    fn mwpm_solver_remove_defect() {
+        // cargo test mwpm_solver_remove_defect -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let initializer = code.get_initializer();
+        let graph = MicroBlossomSingle::new_code(&code);
+        let mut solver = SolverEmbeddedComb::new(graph, json!({}));
+        let mut serial = SolverSerial::new(&initializer);
+        let weight_of = |subgraph: Vec<EdgeIndex>| -> Weight {
+            subgraph
+                .iter()
+                .map(|&edge_index| initializer.weighted_edges[edge_index].2)
+                .sum()
+        };
+        for seed in 0..20 {
+            let mut syndrome_pattern = code.generate_random_errors(seed);
+            if syndrome_pattern.defect_vertices.len() < 2 {
+                continue;
+            }
+            solver.load_syndrome(&syndrome_pattern);
+            let removed = syndrome_pattern.defect_vertices.pop().unwrap();
+            solver.remove_defect(removed).unwrap();
+            assert!(matches!(
+                solver.remove_defect(removed),
+                Err(DualDriverError::InvalidArgument { .. })
+            ));
+            while solver.step() {}
+            solver.finish();
+            serial.solve(&syndrome_pattern);
+            assert_eq!(weight_of(solver.subgraph()), weight_of(serial.subgraph()));
+            let matched = syndrome_pattern.defect_vertices[0];
+            assert!(matches!(
+                solver.remove_defect(matched),
+                Err(DualDriverError::InvalidArgument { .. })
+            ));
+            solver.clear();
+            serial.clear();
+        }
+    }
+}
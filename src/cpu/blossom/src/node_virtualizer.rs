@@ -0,0 +1,124 @@
+//! Node Index Virtualization
+//!
+//! The hardware only supports a limited width of node index (e.g., 15 bits in `Instruction32`), while a long
+//! memory experiment may produce more defects than that. This module maps global defect indices to a small pool of
+//! hardware node indices, so that the indices of finished trees can be recycled and reused by new defects.
+//!
+//! Recycling is only safe when neither the primal nor the dual module will ever refer to the node again:
+//! the primal module must have recorded its matching and forgotten the node
+//! (see `PrimalModuleEmbedded::recycle_defect`), and no vertex in the dual module should still be occupied by the node.
+//! The virtualizer itself only does the bookkeeping; it never decides when a node is finished.
+//!
+
+use fusion_blossom::util::*;
+use serde::*;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone)]
+pub struct NodeVirtualizer {
+    /// the number of hardware node indices available for defects
+    pub capacity: usize,
+    /// `hardware_to_global[hardware_index]` is the global defect index currently using it
+    hardware_to_global: Vec<Option<usize>>,
+    global_to_hardware: BTreeMap<usize, NodeIndex>,
+    /// recycled hardware indices, always reused before allocating a brand new index
+    free_list: Vec<NodeIndex>,
+    pub statistics: NodeVirtualizerStatistics,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NodeVirtualizerStatistics {
+    pub allocated: usize,
+    pub recycled: usize,
+    pub max_active: usize,
+}
+
+impl NodeVirtualizer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            hardware_to_global: vec![],
+            global_to_hardware: BTreeMap::new(),
+            free_list: vec![],
+            statistics: NodeVirtualizerStatistics::default(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.hardware_to_global.clear();
+        self.global_to_hardware.clear();
+        self.free_list.clear();
+    }
+
+    pub fn active_count(&self) -> usize {
+        self.global_to_hardware.len()
+    }
+
+    /// allocate a hardware index for a global defect index; returns None if the pool is exhausted
+    pub fn allocate(&mut self, global_index: usize) -> Option<NodeIndex> {
+        assert!(
+            !self.global_to_hardware.contains_key(&global_index),
+            "global index {global_index} already allocated"
+        );
+        let hardware_index = if let Some(hardware_index) = self.free_list.pop() {
+            hardware_index
+        } else if self.hardware_to_global.len() < self.capacity {
+            self.hardware_to_global.push(None);
+            (self.hardware_to_global.len() - 1) as NodeIndex
+        } else {
+            return None;
+        };
+        self.hardware_to_global[hardware_index as usize] = Some(global_index);
+        self.global_to_hardware.insert(global_index, hardware_index);
+        self.statistics.allocated += 1;
+        self.statistics.max_active = std::cmp::max(self.statistics.max_active, self.active_count());
+        Some(hardware_index)
+    }
+
+    /// release the hardware index of a finished node so that it can be reused
+    pub fn recycle(&mut self, global_index: usize) -> NodeIndex {
+        let hardware_index = self
+            .global_to_hardware
+            .remove(&global_index)
+            .unwrap_or_else(|| panic!("global index {global_index} is not allocated"));
+        self.hardware_to_global[hardware_index as usize] = None;
+        self.free_list.push(hardware_index);
+        self.statistics.recycled += 1;
+        hardware_index
+    }
+
+    pub fn to_hardware(&self, global_index: usize) -> Option<NodeIndex> {
+        self.global_to_hardware.get(&global_index).cloned()
+    }
+
+    pub fn to_global(&self, hardware_index: NodeIndex) -> Option<usize> {
+        self.hardware_to_global
+            .get(hardware_index as usize)
+            .and_then(|global_index| *global_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_virtualizer_basic() {
+        // cargo test node_virtualizer_basic -- --nocapture
+        let mut virtualizer = NodeVirtualizer::new(3);
+        assert_eq!(virtualizer.allocate(100), Some(0));
+        assert_eq!(virtualizer.allocate(101), Some(1));
+        assert_eq!(virtualizer.allocate(102), Some(2));
+        assert_eq!(virtualizer.allocate(103), None, "pool exhausted");
+        assert_eq!(virtualizer.recycle(101), 1);
+        assert_eq!(virtualizer.to_global(1), None);
+        assert_eq!(virtualizer.allocate(103), Some(1), "should reuse the recycled index");
+        assert_eq!(virtualizer.to_global(1), Some(103));
+        assert_eq!(virtualizer.to_hardware(102), Some(2));
+        assert_eq!(virtualizer.to_hardware(101), None);
+        assert_eq!(virtualizer.statistics.max_active, 3);
+        assert_eq!(virtualizer.statistics.recycled, 1);
+        virtualizer.clear();
+        assert_eq!(virtualizer.allocate(0), Some(0));
+    }
+}
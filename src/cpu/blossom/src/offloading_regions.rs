@@ -0,0 +1,249 @@
+//! Offloading Regions
+//!
+//! The offloading units are placed next to the vertices they watch, so on a large device they are spread over the
+//! physical tiles of the floorplan. This module partitions the units into regions by the positions of their vertices:
+//! a grid of `rows` x `columns` tiles over the (i, j) plane, optionally split further by the layer (measurement
+//! round) of the vertices. The per-tile unit lists are emitted in the hardware configuration, and the solver reports
+//! the offload rate of every region, e.g., to find out whether the tiles near the boundary offload more than the
+//! bulk and deserve more resources.
+//!
+
+use crate::resources::*;
+use fusion_blossom::dual_module::*;
+use fusion_blossom::primal_module::*;
+use fusion_blossom::util::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OffloadingRegion {
+    pub row: usize,
+    pub column: usize,
+    /// the layer of the units when split by layers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layer: Option<usize>,
+    /// the indices of the units in [`MicroBlossomSingle::offloading`]
+    pub units: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OffloadingRegions {
+    pub rows: usize,
+    pub columns: usize,
+    /// the non-empty regions, in the order of (row, column, layer)
+    pub regions: Vec<OffloadingRegion>,
+}
+
+/// the edge that an offloading unit matches along
+fn unit_edge(offloading_type: &OffloadingType) -> usize {
+    match offloading_type {
+        OffloadingType::DefectMatch { edge_index }
+        | OffloadingType::VirtualMatch { edge_index, .. }
+        | OffloadingType::FusionMatch { edge_index, .. }
+        | OffloadingType::ChainMatch { edge_index, .. } => *edge_index,
+    }
+}
+
+impl OffloadingRegions {
+    /// a unit is placed at the center of the regular vertices of its edge; the grid spans the bounding box of the
+    /// regular vertices, and the layer of a unit is the earliest layer of its regular vertices
+    pub fn new(graph: &MicroBlossomSingle, rows: usize, columns: usize, split_layers: bool) -> Self {
+        assert!(rows > 0 && columns > 0, "at least one tile is required");
+        let is_virtual = |vertex_index: usize| graph.virtual_vertices.contains(&vertex_index);
+        let regular_positions: Vec<&Position> = (0..graph.vertex_num)
+            .filter(|&vertex_index| !is_virtual(vertex_index))
+            .map(|vertex_index| &graph.positions[vertex_index])
+            .collect();
+        let range = |values: Vec<f64>| {
+            let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+            let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            (min, max)
+        };
+        let range_i = range(regular_positions.iter().map(|position| position.i).collect());
+        let range_j = range(regular_positions.iter().map(|position| position.j).collect());
+        let bin = |value: f64, (min, max): (f64, f64), count: usize| {
+            if max <= min {
+                return 0;
+            }
+            (((value - min) / (max - min) * count as f64) as usize).min(count - 1)
+        };
+        let layer_of = |vertex_index: usize| -> usize {
+            if let Some(layer_assignment) = graph.layer_assignment.as_ref() {
+                layer_assignment.vertex_layer_id[vertex_index].unwrap()
+            } else {
+                graph.layer_fusion.as_ref().unwrap().vertex_layer_id[&vertex_index]
+            }
+        };
+        let mut regions = BTreeMap::<(usize, usize, Option<usize>), Vec<usize>>::new();
+        for (unit_index, offloading_type) in graph.offloading.0.iter().enumerate() {
+            let edge = &graph.weighted_edges[unit_edge(offloading_type)];
+            let vertices: Vec<usize> = [edge.l, edge.r].into_iter().filter(|&index| !is_virtual(index)).collect();
+            let center = |coordinate: fn(&Position) -> f64| {
+                vertices
+                    .iter()
+                    .map(|&vertex_index| coordinate(&graph.positions[vertex_index]))
+                    .sum::<f64>()
+                    / vertices.len() as f64
+            };
+            let row = bin(center(|position| position.i), range_i, rows);
+            let column = bin(center(|position| position.j), range_j, columns);
+            let layer = split_layers.then(|| vertices.iter().map(|&vertex_index| layer_of(vertex_index)).min().unwrap());
+            regions.entry((row, column, layer)).or_default().push(unit_index);
+        }
+        Self {
+            rows,
+            columns,
+            regions: regions
+                .into_iter()
+                .map(|((row, column, layer), units)| OffloadingRegion {
+                    row,
+                    column,
+                    layer,
+                    units,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl MicroBlossomSingle {
+    pub fn assign_offloading_regions(&mut self, rows: usize, columns: usize, split_layers: bool) {
+        self.offloading_regions = Some(OffloadingRegions::new(self, rows, columns, split_layers));
+    }
+}
+
+/// count the defects offloaded in every region from the pre-matchings of each shot
+#[derive(Debug, Clone)]
+pub struct OffloadingRegionTracker {
+    pub regions: OffloadingRegions,
+    /// the region of the unit along the edge between the two vertices, in increasing order
+    region_of_pair: BTreeMap<(VertexIndex, VertexIndex), usize>,
+    pub shots: usize,
+    /// the number of offloaded defects in every region
+    pub offloaded_defects: Vec<usize>,
+    /// the defects pre-matched along an edge without any unit in the regions, which indicates stale regions
+    pub unassigned_defects: usize,
+}
+
+impl OffloadingRegionTracker {
+    pub fn new(graph: &MicroBlossomSingle) -> Self {
+        let regions = graph.offloading_regions.clone().expect("offloading regions are not assigned");
+        let mut region_of_pair = BTreeMap::new();
+        for (region_index, region) in regions.regions.iter().enumerate() {
+            for &unit_index in region.units.iter() {
+                let edge = &graph.weighted_edges[unit_edge(&graph.offloading.0[unit_index])];
+                region_of_pair.insert((edge.l.min(edge.r), edge.l.max(edge.r)), region_index);
+            }
+        }
+        Self {
+            offloaded_defects: vec![0; regions.regions.len()],
+            regions,
+            region_of_pair,
+            shots: 0,
+            unassigned_defects: 0,
+        }
+    }
+
+    pub fn reset_statistics(&mut self) {
+        self.shots = 0;
+        self.offloaded_defects.fill(0);
+        self.unassigned_defects = 0;
+    }
+
+    /// record the pre-matchings of a finished shot
+    pub fn record(&mut self, pre_matchings: &PerfectMatching) {
+        let defect_vertex = |node_ptr: &DualNodePtr| match node_ptr.read_recursive().class {
+            DualNodeClass::DefectVertex { defect_index } => defect_index,
+            DualNodeClass::Blossom { .. } => unreachable!("pre-matchings only contain defect vertices"),
+        };
+        let peer_pairs = pre_matchings
+            .peer_matchings
+            .iter()
+            .map(|(node_1, node_2)| (defect_vertex(node_1), defect_vertex(node_2), 2));
+        let virtual_pairs = pre_matchings
+            .virtual_matchings
+            .iter()
+            .map(|(node, virtual_vertex)| (defect_vertex(node), *virtual_vertex, 1));
+        for (vertex_1, vertex_2, defects) in peer_pairs.chain(virtual_pairs) {
+            match self.region_of_pair.get(&(vertex_1.min(vertex_2), vertex_1.max(vertex_2))) {
+                Some(&region_index) => self.offloaded_defects[region_index] += defects,
+                None => self.unassigned_defects += defects,
+            }
+        }
+        self.shots += 1;
+    }
+
+    pub fn generate_report(&self) -> serde_json::Value {
+        let shots = self.shots.max(1) as f64;
+        let regions: Vec<serde_json::Value> = self
+            .regions
+            .regions
+            .iter()
+            .zip(self.offloaded_defects.iter())
+            .map(|(region, &offloaded_defects)| {
+                json!({
+                    "row": region.row,
+                    "column": region.column,
+                    "layer": region.layer,
+                    "units": region.units.len(),
+                    "offloaded_defects": offloaded_defects,
+                    "offload_rate": offloaded_defects as f64 / shots,
+                })
+            })
+            .collect();
+        json!({
+            "rows": self.regions.rows,
+            "columns": self.regions.columns,
+            "shots": self.shots,
+            "unassigned_defects": self.unassigned_defects,
+            "regions": regions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwpm_solver::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::*;
+
+    #[test]
+    fn offloading_regions_partition() {
+        // cargo test offloading_regions_partition -- --nocapture
+        let code = PhenomenologicalRotatedCode::new(5, 4, 0.1, 500);
+        let mut graph = MicroBlossomSingle::new_code(&code);
+        graph.assign_offloading_regions(2, 2, true);
+        let regions = graph.offloading_regions.as_ref().unwrap();
+        // every unit belongs to exactly one region
+        let mut units: Vec<usize> = regions.regions.iter().flat_map(|region| region.units.clone()).collect();
+        units.sort();
+        assert_eq!(units, (0..graph.offloading.0.len()).collect::<Vec<_>>());
+        let num_layers = graph.layer_fusion.as_ref().unwrap().num_layers;
+        assert!(regions.regions.len() > 4 && regions.regions.len() <= 4 * num_layers);
+        // emitted in the hardware configuration
+        let parsed: MicroBlossomSingle = serde_json::from_str(&serde_json::to_string(&graph).unwrap()).unwrap();
+        assert_eq!(parsed, graph);
+    }
+
+    #[test]
+    fn offloading_regions_offload_rate() {
+        // cargo test offloading_regions_offload_rate -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(9, 0.02, 500);
+        let mut graph = MicroBlossomSingle::new_code(&code);
+        graph.assign_offloading_regions(3, 3, false);
+        let mut solver = SolverEmbeddedComb::new(graph, json!({ "dual": { "sim_config": { "support_offloading": true } } }));
+        let mut offloaded = 0;
+        for seed in 0..100 {
+            solver.solve(&code.generate_random_errors(seed));
+            offloaded += solver.offloaded;
+            solver.clear();
+        }
+        let tracker = solver.offloading_regions.as_ref().unwrap();
+        println!("{}", tracker.generate_report());
+        assert_eq!(tracker.shots, 100);
+        assert_eq!(tracker.unassigned_defects, 0);
+        assert_eq!(tracker.offloaded_defects.iter().sum::<usize>(), offloaded);
+    }
+}
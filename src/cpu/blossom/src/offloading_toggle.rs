@@ -0,0 +1,286 @@
+//! Offloading Toggle
+//!
+//! Pre-matching (offloading) resolves the isolated defect pairs inside the dual module, which is exact under the
+//! expected noise but may lose accuracy under unexpected noise, e.g., a burst of correlated errors. This solver
+//! protects the accuracy at runtime: in the self-check mode, every `check_interval`-th shot is decoded both with and
+//! without pre-matching, and a mismatch is a larger matching weight with pre-matching. When the mismatch rate of the
+//! recent window exceeds `disable_threshold`, pre-matching is disabled; it is re-enabled once the mismatch rate of
+//! the shadow decoding falls back to `enable_threshold`. The window restarts after every toggle, so a toggle is
+//! decided on a full window of the new state.
+//!
+//! Every toggle is logged as an [`OffloadingToggleEvent`] and reported in the profile.
+//!
+
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::primal_module::*;
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OffloadingToggleConfig {
+    /// the number of recent checked shots to compute the mismatch rate
+    #[serde(default = "offloading_toggle_config_default::window")]
+    pub window: usize,
+    /// disable pre-matching when the mismatch rate exceeds this threshold
+    #[serde(default = "offloading_toggle_config_default::disable_threshold")]
+    pub disable_threshold: f64,
+    /// re-enable pre-matching when the mismatch rate is no more than this threshold
+    #[serde(default = "offloading_toggle_config_default::enable_threshold")]
+    pub enable_threshold: f64,
+    /// check one shot out of every `check_interval` shots
+    #[serde(default = "offloading_toggle_config_default::check_interval")]
+    pub check_interval: usize,
+}
+
+impl Default for OffloadingToggleConfig {
+    fn default() -> Self {
+        serde_json::from_value(json!({})).unwrap()
+    }
+}
+
+pub mod offloading_toggle_config_default {
+    pub fn window() -> usize {
+        200
+    }
+    pub fn disable_threshold() -> f64 {
+        0.02
+    }
+    pub fn enable_threshold() -> f64 {
+        0.005
+    }
+    pub fn check_interval() -> usize {
+        1
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OffloadingToggleEvent {
+    /// the index of the shot that triggers the toggle
+    pub shot: usize,
+    /// whether pre-matching is enabled from the next shot
+    pub enabled: bool,
+    pub mismatch_rate: f64,
+    /// the average number of defects per checked shot in the window, a proxy of the noise level
+    pub average_defects: f64,
+}
+
+/// the hysteresis state machine, independent of the solvers
+#[derive(Debug, Clone)]
+pub struct OffloadingPolicy {
+    pub config: OffloadingToggleConfig,
+    pub enabled: bool,
+    /// the mismatch and the number of defects of the recent checked shots
+    window: VecDeque<(bool, usize)>,
+    shot: usize,
+    pub events: Vec<OffloadingToggleEvent>,
+}
+
+impl OffloadingPolicy {
+    pub fn new(config: OffloadingToggleConfig) -> Self {
+        assert!(config.window > 0 && config.check_interval > 0);
+        assert!(
+            config.enable_threshold <= config.disable_threshold,
+            "the hysteresis requires `enable_threshold <= disable_threshold`"
+        );
+        Self {
+            config,
+            enabled: true,
+            window: VecDeque::new(),
+            shot: 0,
+            events: vec![],
+        }
+    }
+
+    /// whether the next shot is decoded both with and without pre-matching
+    pub fn is_checking(&self) -> bool {
+        self.shot % self.config.check_interval == 0
+    }
+
+    pub fn mismatch_rate(&self) -> f64 {
+        let mismatches = self.window.iter().filter(|(mismatch, _)| *mismatch).count();
+        mismatches as f64 / self.window.len().max(1) as f64
+    }
+
+    /// record a shot, with its mismatch if checked; returns the toggle event if any
+    pub fn record(&mut self, mismatch: Option<bool>, defects: usize) -> Option<&OffloadingToggleEvent> {
+        let shot = self.shot;
+        self.shot += 1;
+        self.window.push_back((mismatch?, defects));
+        if self.window.len() > self.config.window {
+            self.window.pop_front();
+        }
+        if self.window.len() < self.config.window {
+            return None;
+        }
+        let mismatch_rate = self.mismatch_rate();
+        let toggle = if self.enabled {
+            mismatch_rate > self.config.disable_threshold
+        } else {
+            mismatch_rate <= self.config.enable_threshold
+        };
+        if !toggle {
+            return None;
+        }
+        self.enabled = !self.enabled;
+        let total_defects: usize = self.window.iter().map(|(_, defects)| defects).sum();
+        self.events.push(OffloadingToggleEvent {
+            shot,
+            enabled: self.enabled,
+            mismatch_rate,
+            average_defects: total_defects as f64 / self.window.len() as f64,
+        });
+        self.window.clear();
+        self.events.last()
+    }
+}
+
+pub struct SolverOffloadingToggle {
+    pub pre_matching: Box<dyn MicroBlossomSolver>,
+    pub exact: Box<dyn MicroBlossomSolver>,
+    pub policy: OffloadingPolicy,
+    /// the policy state when the last shot was solved, i.e., the solver that holds the result
+    active_enabled: bool,
+}
+
+impl SolverOffloadingToggle {
+    /// the pre-matching is configured by `dual.sim_config.support_offloading` on top of `primal_dual_config`
+    pub fn new(graph: MicroBlossomSingle, primal_dual_config: serde_json::Value, config: OffloadingToggleConfig) -> Self {
+        let with_offloading = |support_offloading: bool| {
+            let mut config = primal_dual_config.clone();
+            config["dual"]["sim_config"]["support_offloading"] = json!(support_offloading);
+            config
+        };
+        Self {
+            pre_matching: Box::new(SolverEmbeddedComb::new(graph.clone(), with_offloading(true))),
+            exact: Box::new(SolverEmbeddedComb::new(graph, with_offloading(false))),
+            policy: OffloadingPolicy::new(config),
+            active_enabled: true,
+        }
+    }
+
+    fn active(&mut self) -> &mut Box<dyn MicroBlossomSolver> {
+        if self.active_enabled {
+            &mut self.pre_matching
+        } else {
+            &mut self.exact
+        }
+    }
+}
+
+impl PrimalDualSolver for SolverOffloadingToggle {
+    fn clear(&mut self) {
+        self.active().clear();
+    }
+    fn reset_profiler(&mut self) {
+        self.pre_matching.reset_profiler();
+        self.exact.reset_profiler();
+    }
+    fn solve_visualizer(&mut self, syndrome_pattern: &SyndromePattern, visualizer: Option<&mut Visualizer>) {
+        self.active_enabled = self.policy.enabled;
+        let is_checking = self.policy.is_checking();
+        self.active().solve_visualizer(syndrome_pattern, visualizer);
+        let mismatch = is_checking.then(|| {
+            let active_weight = self.active().result().matching_weight;
+            let shadow = if self.active_enabled {
+                &mut self.exact
+            } else {
+                &mut self.pre_matching
+            };
+            shadow.solve(syndrome_pattern);
+            let shadow_weight = shadow.result().matching_weight;
+            shadow.clear();
+            active_weight != shadow_weight
+        });
+        self.policy.record(mismatch, syndrome_pattern.defect_vertices.len());
+    }
+    fn perfect_matching_visualizer(&mut self, visualizer: Option<&mut Visualizer>) -> PerfectMatching {
+        self.active().perfect_matching_visualizer(visualizer)
+    }
+    fn subgraph_visualizer(&mut self, visualizer: Option<&mut Visualizer>) -> Vec<EdgeIndex> {
+        self.active().subgraph_visualizer(visualizer)
+    }
+    fn sum_dual_variables(&self) -> Weight {
+        if self.active_enabled {
+            self.pre_matching.sum_dual_variables()
+        } else {
+            self.exact.sum_dual_variables()
+        }
+    }
+    fn generate_profiler_report(&self) -> serde_json::Value {
+        json!({
+            "pre_matching": self.pre_matching.generate_profiler_report(),
+            "exact": self.exact.generate_profiler_report(),
+            "enabled": self.policy.enabled,
+            "mismatch_rate": self.policy.mismatch_rate(),
+            "events": self.policy.events,
+        })
+    }
+}
+
+impl MicroBlossomSolver for SolverOffloadingToggle {
+    fn result(&mut self) -> SolverResult {
+        self.active().result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn offloading_toggle_hysteresis() {
+        // cargo test offloading_toggle_hysteresis -- --nocapture
+        let config: OffloadingToggleConfig = serde_json::from_value(json!({
+            "window": 10, "disable_threshold": 0.2, "enable_threshold": 0.0, "check_interval": 2
+        }))
+        .unwrap();
+        let mut policy = OffloadingPolicy::new(config);
+        let record = |policy: &mut OffloadingPolicy, mismatch: bool| {
+            let checked = policy.is_checking().then_some(mismatch);
+            policy.record(checked, 4).cloned()
+        };
+        // a burst of mismatches: 3 out of 10 checked shots disable pre-matching on the 20th shot
+        for shot in 0..20 {
+            let event = record(&mut policy, shot < 6);
+            assert_eq!(event.is_some(), shot == 18, "shot {shot}");
+        }
+        assert!(!policy.enabled);
+        assert_eq!(policy.events[0].mismatch_rate, 0.3);
+        // a single mismatch keeps it disabled until it slides out of a full window
+        for shot in 20..60 {
+            record(&mut policy, shot == 24);
+            assert_eq!(policy.enabled, shot >= 44, "shot {shot}");
+        }
+        assert_eq!(policy.events.len(), 2);
+        assert!(policy.events[1].enabled && policy.events[1].average_defects == 4.);
+    }
+
+    /// pre-matching is exact under the expected noise, so it is never disabled
+    #[test]
+    fn offloading_toggle_solver() {
+        // cargo test offloading_toggle_solver -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.05, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let mut solver = SolverOffloadingToggle::new(graph.clone(), json!({}), OffloadingToggleConfig::default());
+        let mut reference = SolverEmbeddedComb::new(graph, json!({}));
+        for seed in 0..50 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            solver.solve(&syndrome_pattern);
+            reference.solve(&syndrome_pattern);
+            let result = solver.result();
+            assert!(result.offloaded.is_some());
+            assert_eq!(result.matching_weight, reference.result().matching_weight);
+            solver.clear();
+            reference.clear();
+        }
+        assert!(solver.policy.enabled && solver.policy.events.is_empty());
+        assert_eq!(solver.policy.mismatch_rate(), 0.);
+    }
+}
@@ -0,0 +1,170 @@
+//! Pinned Matching (Syndrome Pre-Filtering)
+//!
+//! Matches decided before the solve starts, e.g., by an external pre-decoder or from heralded information, so that
+//! a hybrid decoding pipeline can hand a partial solution to Micro Blossom and only decode the remainder.
+//! This is a pre-filtering of the syndrome, not a warm start: the hardware has no instruction to load a pair as
+//! already matched, so the two defects of a pinned match are simply not loaded into the dual module, which is the
+//! same as removing them from the syndrome, and the match is added back to the perfect matching after the solve.
+//! A pinned match is thus never revisited, even if the remaining defects would prefer to match with them.
+//! The dual variables therefore only cover the remaining defects, so the dual objective and the tight paths are not
+//! reported for a shot with pinned matches.
+//!
+
+use crate::primal_module_embedded_adaptor::*;
+use crate::resources::*;
+use fusion_blossom::dual_module::*;
+use fusion_blossom::primal_module::*;
+use fusion_blossom::util::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PinnedMatching {
+    pub peer_matchings: Vec<(VertexIndex, VertexIndex)>,
+    /// `(defect_vertex, virtual_vertex)`
+    pub virtual_matchings: Vec<(VertexIndex, VertexIndex)>,
+}
+
+impl PinnedMatching {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peer_matchings.is_empty() && self.virtual_matchings.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.peer_matchings.clear();
+        self.virtual_matchings.clear();
+    }
+
+    pub fn pin_peer(&mut self, vertex_1: VertexIndex, vertex_2: VertexIndex) {
+        self.peer_matchings.push((vertex_1, vertex_2));
+    }
+
+    pub fn pin_virtual(&mut self, vertex_index: VertexIndex, virtual_vertex: VertexIndex) {
+        self.virtual_matchings.push((vertex_index, virtual_vertex));
+    }
+
+    /// the defect vertices of all the pinned matches
+    pub fn defect_vertices(&self) -> impl Iterator<Item = VertexIndex> + '_ {
+        self.peer_matchings
+            .iter()
+            .flat_map(|&(vertex_1, vertex_2)| [vertex_1, vertex_2])
+            .chain(self.virtual_matchings.iter().map(|&(vertex_index, _)| vertex_index))
+    }
+
+    /// the defects left for the solver, panics if a pinned match is not consistent with the graph and the defects
+    pub fn remaining_defects(&self, graph: &MicroBlossomSingle, defect_vertices: &[VertexIndex]) -> Vec<VertexIndex> {
+        if self.is_empty() {
+            return defect_vertices.to_vec();
+        }
+        let defects: BTreeSet<VertexIndex> = defect_vertices.iter().cloned().collect();
+        let mut pinned = BTreeSet::new();
+        for vertex_index in self.defect_vertices() {
+            assert!(
+                defects.contains(&vertex_index),
+                "pinned vertex {vertex_index} is not a defect"
+            );
+            assert!(pinned.insert(vertex_index), "vertex {vertex_index} is pinned more than once");
+        }
+        for (_, virtual_vertex) in self.virtual_matchings.iter() {
+            assert!(
+                graph.virtual_vertices.contains(virtual_vertex),
+                "pinned target {virtual_vertex} is not a virtual vertex"
+            );
+        }
+        defect_vertices
+            .iter()
+            .filter(|vertex_index| !pinned.contains(vertex_index))
+            .cloned()
+            .collect()
+    }
+
+    /// append the pinned matches, where `node_of` gives the node index of a defect vertex
+    pub fn append_to(
+        &self,
+        perfect_matching: &mut PerfectMatching,
+        belonging: &DualModuleInterfaceWeak,
+        node_of: impl Fn(VertexIndex) -> NodeIndex,
+    ) {
+        let defect_node = |vertex_index: VertexIndex| defect_dual_node(node_of(vertex_index), vertex_index, belonging);
+        for &(vertex_1, vertex_2) in self.peer_matchings.iter() {
+            perfect_matching
+                .peer_matchings
+                .push((defect_node(vertex_1), defect_node(vertex_2)));
+        }
+        for &(vertex_index, virtual_vertex) in self.virtual_matchings.iter() {
+            perfect_matching
+                .virtual_matchings
+                .push((defect_node(vertex_index), virtual_vertex));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwpm_solver::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::*;
+    use serde_json::json;
+
+    /// pinning a match of the optimal solution keeps the optimal weight, and any pinned match is honored
+    #[test]
+    fn pinned_matching_refine_remainder() {
+        // cargo test pinned_matching_refine_remainder -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(11, 0.05, 500);
+        let initializer = code.get_initializer();
+        let mut standard_solver = SolverSerial::new(&initializer);
+        let mut solver = SolverEmbeddedComb::new(MicroBlossomSingle::new_code(&code), json!({}));
+        let defect_vertex = |node_ptr: &DualNodePtr| match node_ptr.read_recursive().class {
+            DualNodeClass::DefectVertex { defect_index } => defect_index,
+            DualNodeClass::Blossom { .. } => unreachable!(),
+        };
+        let mut tested = 0;
+        for seed in 0..50 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            standard_solver.solve(&syndrome_pattern);
+            let standard_matching = standard_solver.perfect_matching();
+            let optimal_weight = standard_solver.sum_dual_variables();
+            standard_solver.clear();
+            let Some((node_1, node_2)) = standard_matching.peer_matchings.first() else {
+                continue;
+            };
+            let (vertex_1, vertex_2) = (defect_vertex(node_1), defect_vertex(node_2));
+            let mut pinned = PinnedMatching::new();
+            pinned.pin_peer(vertex_1, vertex_2);
+            solver.pin_matching(pinned.clone());
+            solver.solve(&syndrome_pattern);
+            let subgraph = solver.subgraph();
+            let defects: BTreeSet<VertexIndex> = syndrome_pattern.defect_vertices.iter().cloned().collect();
+            assert_eq!(initializer.syndrome_of(&subgraph), defects);
+            assert_eq!(solver.sum_dual_variables(), optimal_weight);
+            let perfect_matching = solver.perfect_matching();
+            assert!(perfect_matching
+                .peer_matchings
+                .iter()
+                .any(|(node_1, node_2)| (defect_vertex(node_1), defect_vertex(node_2)) == (vertex_1, vertex_2)));
+            let result = solver.result();
+            assert_eq!(result.dual_objective, None);
+            assert_eq!(result.correction_paths, None);
+            solver.clear();
+            assert!(solver.checkpoint().pinned.is_empty(), "the pins are cleared with the shot");
+            tested += 1;
+        }
+        assert!(tested > 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "pinned vertex 2 is not a defect")]
+    fn pinned_matching_not_defect() {
+        // cargo test pinned_matching_not_defect -- --nocapture
+        let code = CodeCapacityRepetitionCode::new(7, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let mut pinned = PinnedMatching::new();
+        pinned.pin_peer(1, 2);
+        pinned.remaining_defects(&graph, &[1, 3]);
+    }
+}
@@ -0,0 +1,335 @@
+//! Prelude
+//!
+//! A small facade for decoding with Micro Blossom from another crate. Only plain standard types cross this interface:
+//! the decoding graph is a list of weighted edges, a shot is a list of defect vertices and the result is a list of
+//! edges together with the matched pairs. The drivers, the primal module and the types of fusion-blossom used by the
+//! rest of this crate are free to change between versions, while the items in this module keep their signatures; new
+//! fields and variants are only added to the `#[non_exhaustive]` types.
+//!
+//! ```ignore
+//! use micro_blossom::prelude::*;
+//! // a repetition code of 3 data qubits with a virtual vertex at each end
+//! let mut decoder = DecoderBuilder::new(4)
+//!     .edges([(0, 1, 2), (1, 2, 2), (2, 3, 2)])
+//!     .virtual_vertices([0, 3])
+//!     .build()?;
+//! let decoding = decoder.decode(&[1, 2])?;
+//! assert_eq!(decoding.edges, vec![1]);
+//! ```
+//!
+
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use fusion_blossom::mwpm_solver::PrimalDualSolver;
+use fusion_blossom::util::*;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeSet;
+
+/// the implementation of the dual module behind the decoder
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum Backend {
+    /// the cycle-accurate model of the accelerator driven by the embedded primal module
+    #[default]
+    Accelerator,
+    /// a software dual module driven by the embedded primal module, faster to set up on large graphs
+    Software,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecoderError {
+    /// an edge or a defect refers to a vertex not in the graph
+    VertexOutOfRange { vertex: usize, vertex_num: usize },
+    /// the weights must be non-negative even numbers so that the dual variables stay integers
+    InvalidWeight { edge: usize, weight: i64 },
+    /// an edge connects a vertex to itself
+    SelfLoop { edge: usize },
+    /// a defect is reported on a virtual vertex
+    VirtualDefect { vertex: usize },
+    /// the same defect is reported more than once in a shot
+    DuplicateDefect { vertex: usize },
+}
+
+impl std::fmt::Display for DecoderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::VertexOutOfRange { vertex, vertex_num } => {
+                write!(f, "vertex {vertex} out of range, the graph has {vertex_num} vertices")
+            }
+            Self::InvalidWeight { edge, weight } => {
+                write!(f, "edge {edge} has weight {weight}, which is not a non-negative even number")
+            }
+            Self::SelfLoop { edge } => write!(f, "edge {edge} connects a vertex to itself"),
+            Self::VirtualDefect { vertex } => write!(f, "defect on virtual vertex {vertex}"),
+            Self::DuplicateDefect { vertex } => write!(f, "defect on vertex {vertex} reported more than once"),
+        }
+    }
+}
+
+impl std::error::Error for DecoderError {}
+
+#[derive(Debug, Clone)]
+pub struct DecoderBuilder {
+    vertex_num: usize,
+    edges: Vec<(usize, usize, i64)>,
+    virtual_vertices: Vec<usize>,
+    backend: Backend,
+}
+
+impl DecoderBuilder {
+    pub fn new(vertex_num: usize) -> Self {
+        Self {
+            vertex_num,
+            edges: vec![],
+            virtual_vertices: vec![],
+            backend: Backend::default(),
+        }
+    }
+
+    /// add an edge between `left` and `right`; the edges are indexed in the order they are added
+    pub fn edge(mut self, left: usize, right: usize, weight: i64) -> Self {
+        self.edges.push((left, right, weight));
+        self
+    }
+
+    pub fn edges(mut self, edges: impl IntoIterator<Item = (usize, usize, i64)>) -> Self {
+        self.edges.extend(edges);
+        self
+    }
+
+    /// a virtual vertex is a boundary that any number of defects can match to
+    pub fn virtual_vertices(mut self, vertices: impl IntoIterator<Item = usize>) -> Self {
+        self.virtual_vertices.extend(vertices);
+        self
+    }
+
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn build(self) -> Result<Decoder, DecoderError> {
+        let vertex_num = self.vertex_num;
+        let check_vertex = |vertex: usize| match vertex < vertex_num {
+            true => Ok(()),
+            false => Err(DecoderError::VertexOutOfRange { vertex, vertex_num }),
+        };
+        for (edge, &(left, right, weight)) in self.edges.iter().enumerate() {
+            check_vertex(left)?;
+            check_vertex(right)?;
+            if left == right {
+                return Err(DecoderError::SelfLoop { edge });
+            }
+            if weight < 0 || weight % 2 != 0 {
+                return Err(DecoderError::InvalidWeight { edge, weight });
+            }
+        }
+        for &vertex in self.virtual_vertices.iter() {
+            check_vertex(vertex)?;
+        }
+        let virtual_vertices: BTreeSet<usize> = self.virtual_vertices.into_iter().collect();
+        let initializer = SolverInitializer::new(
+            vertex_num,
+            (self.edges.iter())
+                .map(|&(left, right, weight)| (left, right, weight as Weight))
+                .collect(),
+            virtual_vertices.iter().cloned().collect(),
+        );
+        let solver: Box<dyn MicroBlossomSolver> = match self.backend {
+            Backend::Accelerator => {
+                // the positions only matter to the placement on the hardware, not to the simulation
+                let graph = MicroBlossomSingle::new_initializer_only(&initializer);
+                Box::new(SolverEmbeddedComb::new(graph, json!({})))
+            }
+            Backend::Software => Box::new(SolverPrimalEmbedded::new(&initializer)),
+        };
+        Ok(Decoder {
+            vertex_num,
+            virtual_vertices,
+            solver,
+            stats: DecoderStats::default(),
+        })
+    }
+}
+
+/// a defect matched to another defect or to a virtual vertex
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MatchedPair {
+    pub defect: usize,
+    /// the peer defect, or the virtual vertex if `is_boundary`
+    pub target: usize,
+    pub is_boundary: bool,
+    /// the edges along the path from `defect` to `target`
+    pub edges: Vec<usize>,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Decoding {
+    /// the edges of the correction, i.e., the minimum-weight set of edges whose boundary is the defects
+    pub edges: Vec<usize>,
+    /// the total weight of the correction
+    pub weight: i64,
+    /// the matched pairs, `None` if the backend cannot trace the paths back
+    pub matching: Option<Vec<MatchedPair>>,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DecoderStats {
+    pub shots: usize,
+    pub defects: usize,
+    /// the instructions executed by the dual module, only counted by [`Backend::Accelerator`]
+    pub instructions: usize,
+    /// the shots whose optimality is certified by the dual objective
+    pub certified: usize,
+}
+
+pub struct Decoder {
+    vertex_num: usize,
+    virtual_vertices: BTreeSet<usize>,
+    solver: Box<dyn MicroBlossomSolver>,
+    stats: DecoderStats,
+}
+
+impl Decoder {
+    pub fn vertex_num(&self) -> usize {
+        self.vertex_num
+    }
+
+    /// decode the defects of a single shot; an invalid shot is rejected as a whole
+    pub fn decode(&mut self, defects: &[usize]) -> Result<Decoding, DecoderError> {
+        let mut seen = BTreeSet::new();
+        for &vertex in defects.iter() {
+            if vertex >= self.vertex_num {
+                let vertex_num = self.vertex_num;
+                return Err(DecoderError::VertexOutOfRange { vertex, vertex_num });
+            }
+            if self.virtual_vertices.contains(&vertex) {
+                return Err(DecoderError::VirtualDefect { vertex });
+            }
+            if !seen.insert(vertex) {
+                return Err(DecoderError::DuplicateDefect { vertex });
+            }
+        }
+        self.solver.solve(&SyndromePattern::new_vertices(defects.to_vec()));
+        let result = self.solver.result();
+        self.solver.clear();
+        self.stats.shots += 1;
+        self.stats.defects += defects.len();
+        self.stats.instructions += result
+            .instruction_counts
+            .iter()
+            .flat_map(|counts| counts.values())
+            .sum::<usize>();
+        self.stats.certified += result.certified as usize;
+        let matching = result.correction_paths.map(|paths| {
+            paths
+                .into_iter()
+                .map(|path| MatchedPair {
+                    defect: path.vertex,
+                    target: path.target,
+                    is_boundary: self.virtual_vertices.contains(&path.target),
+                    edges: path.edges,
+                })
+                .collect()
+        });
+        Ok(Decoding {
+            edges: result.subgraph,
+            weight: result.matching_weight as i64,
+            matching,
+        })
+    }
+
+    /// the statistics accumulated over all the decoded shots
+    pub fn stats(&self) -> &DecoderStats {
+        &self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = DecoderStats::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn prelude_repetition_code() {
+        // cargo test prelude_repetition_code -- --nocapture
+        for backend in [Backend::Accelerator, Backend::Software] {
+            let mut decoder = DecoderBuilder::new(11)
+                .edges((0..10).map(|left| (left, left + 1, 2)))
+                .virtual_vertices([0, 10])
+                .backend(backend)
+                .build()
+                .unwrap();
+            let decoding = decoder.decode(&[4, 5]).unwrap();
+            assert_eq!((decoding.edges, decoding.weight), (vec![4], 2));
+            let decoding = decoder.decode(&[1, 2, 7]).unwrap();
+            assert_eq!(decoding.weight, 8);
+            if let Some(matching) = decoding.matching {
+                println!("{backend:?}: {matching:?}");
+                assert_eq!(matching.iter().filter(|pair| pair.is_boundary).count(), 1);
+            }
+            assert_eq!(decoder.decode(&[]).unwrap().edges, Vec::<usize>::new());
+            assert_eq!(decoder.stats().shots, 3);
+            assert_eq!(decoder.stats().defects, 5);
+        }
+    }
+
+    #[test]
+    fn prelude_invalid_input() {
+        // cargo test prelude_invalid_input -- --nocapture
+        let build = |edges: Vec<(usize, usize, i64)>| DecoderBuilder::new(3).edges(edges).build().err();
+        assert_eq!(
+            build(vec![(0, 1, 3)]),
+            Some(DecoderError::InvalidWeight { edge: 0, weight: 3 })
+        );
+        assert_eq!(build(vec![(0, 1, 2), (1, 1, 2)]), Some(DecoderError::SelfLoop { edge: 1 }));
+        assert_eq!(
+            build(vec![(0, 3, 2)]),
+            Some(DecoderError::VertexOutOfRange {
+                vertex: 3,
+                vertex_num: 3
+            })
+        );
+        let mut decoder = DecoderBuilder::new(3)
+            .edges([(0, 1, 2), (1, 2, 2)])
+            .virtual_vertices([2])
+            .build()
+            .unwrap();
+        assert_eq!(decoder.decode(&[2]), Err(DecoderError::VirtualDefect { vertex: 2 }));
+        assert_eq!(decoder.decode(&[1, 1]), Err(DecoderError::DuplicateDefect { vertex: 1 }));
+        assert_eq!(decoder.stats().shots, 0);
+        println!("{}", decoder.decode(&[5]).unwrap_err());
+    }
+
+    /// the facade gives the same matching weight as the solver it wraps
+    #[test]
+    fn prelude_matches_solver() {
+        // cargo test prelude_matches_solver -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let initializer = code.get_initializer();
+        let mut decoder = DecoderBuilder::new(initializer.vertex_num)
+            .edges((initializer.weighted_edges.iter()).map(|&(left, right, weight)| (left, right, weight as i64)))
+            .virtual_vertices(initializer.virtual_vertices.iter().cloned())
+            .build()
+            .unwrap();
+        let mut solver = SolverEmbeddedComb::new(MicroBlossomSingle::new_code(&code), json!({}));
+        for seed in 0..50 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            let decoding = decoder.decode(&syndrome_pattern.defect_vertices).unwrap();
+            solver.solve(&syndrome_pattern);
+            assert_eq!(decoding.weight, solver.result().matching_weight as i64, "seed {seed}");
+            solver.clear();
+        }
+        println!("{}", json!(decoder.stats()));
+        assert_eq!(decoder.stats().certified, 50);
+    }
+}
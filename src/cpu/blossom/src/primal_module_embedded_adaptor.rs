@@ -116,6 +116,10 @@ impl<'a, D: DualModuleImpl> DualInterface for MockDualInterface<'a, D> {
     fn add_defect(&mut self, _vertex: CompactVertexIndex, _node: CompactNodeIndex) {
         unimplemented_or_loop!()
     }
+    fn read_node_dual(&mut self, node_index: CompactNodeIndex) -> Option<CompactWeight> {
+        let interface = self.interface_ptr.read_recursive();
+        Some(self.index_to_ptr.get(&node_index).unwrap().get_dual_variable(&interface) as CompactWeight)
+    }
 }
 
 impl PrimalModuleImpl for PrimalModuleEmbeddedAdaptor {
@@ -382,6 +386,21 @@ impl<'a, const N: usize> DualNodesOf<'a, N> {
     }
 }
 
+/// a single defect node that is not tracked by any dual module, used to report a matching
+pub fn defect_dual_node(index: NodeIndex, vertex_index: VertexIndex, belonging: &DualModuleInterfaceWeak) -> DualNodePtr {
+    DualNodePtr::new_value(DualNode {
+        index,
+        class: DualNodeClass::DefectVertex {
+            defect_index: vertex_index,
+        },
+        defect_size: nonzero::nonzero!(1usize),
+        grow_state: DualNodeGrowState::Stay,
+        parent_blossom: None,
+        dual_variable_cache: (0, 0),
+        belonging: belonging.clone(),
+    })
+}
+
 pub fn perfect_matching_from_embedded_primal<const N: usize>(
     primal_module: &mut PrimalModuleEmbedded<N>,
     defect_nodes: &[VertexIndex],
@@ -390,30 +409,12 @@ pub fn perfect_matching_from_embedded_primal<const N: usize>(
     let interface_ptr = DualModuleInterfacePtr::new_empty();
     let belonging = interface_ptr.downgrade();
     primal_module.iterate_perfect_matching(|_, node_index, match_target, _link| {
-        let node = DualNodePtr::new_value(DualNode {
-            index: node_index.get() as NodeIndex,
-            class: DualNodeClass::DefectVertex {
-                defect_index: defect_nodes[node_index.get() as usize],
-            },
-            defect_size: nonzero::nonzero!(1usize),
-            grow_state: DualNodeGrowState::Stay,
-            parent_blossom: None,
-            dual_variable_cache: (0, 0),
-            belonging: belonging.clone(),
-        });
+        let node_index = node_index.get() as NodeIndex;
+        let node = defect_dual_node(node_index, defect_nodes[node_index as usize], &belonging);
         match match_target {
             CompactMatchTarget::Peer(peer_index) => {
-                let peer = DualNodePtr::new_value(DualNode {
-                    index: peer_index.get() as NodeIndex,
-                    class: DualNodeClass::DefectVertex {
-                        defect_index: defect_nodes[peer_index.get() as usize],
-                    },
-                    defect_size: nonzero::nonzero!(1usize),
-                    grow_state: DualNodeGrowState::Stay,
-                    parent_blossom: None,
-                    dual_variable_cache: (0, 0),
-                    belonging: belonging.clone(),
-                });
+                let peer_index = peer_index.get() as NodeIndex;
+                let peer = defect_dual_node(peer_index, defect_nodes[peer_index as usize], &belonging);
                 perfect_matching.peer_matchings.push((node, peer));
             }
             CompactMatchTarget::VirtualVertex(virtual_index) => {
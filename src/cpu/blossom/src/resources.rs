@@ -1,5 +1,8 @@
 // see micro-blossom/resources/graphs/README.md
 
+use crate::graph_symmetry::*;
+use crate::layer_assignment::*;
+use crate::offloading_regions::*;
 use fusion_blossom::example_codes::*;
 use fusion_blossom::util::*;
 use fusion_blossom::visualize::*;
@@ -26,6 +29,18 @@ pub struct MicroBlossomSingle {
     pub layer_fusion: Option<LayerFusion>,
     /// parity tracker allows the hardware to report the pre-matched result
     pub parity_reporters: Option<ParityReporters>,
+    /// the measurement round of every vertex, see [`crate::layer_assignment`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layer_assignment: Option<LayerAssignment>,
+    /// hints of sharing hardware resources between repeated unit cells, see [`crate::graph_symmetry`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sharing_hints: Option<SharingHints>,
+    /// the physical regions of the offloading units, see [`crate::offloading_regions`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offloading_regions: Option<OffloadingRegions>,
+    /// every weight is a multiple of this unit of the original graph, see [`crate::weight_quantum`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight_quantum: Option<isize>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -87,6 +102,19 @@ pub enum OffloadingType {
         #[serde(rename = "c")]
         conditioned_vertex: usize,
     },
+    /// a defect match with virtual vertex through a non-defect vertex in the middle, i.e., along a chain of two edges;
+    /// only implemented in the combinatorial dual module, see [`OffloadingFinder::find_chain_match`]
+    #[serde(rename = "cm")]
+    ChainMatch {
+        /// the edge between the defect and the vertex in the middle
+        #[serde(rename = "e")]
+        edge_index: usize,
+        /// the edge between the vertex in the middle and the virtual vertex
+        #[serde(rename = "b")]
+        boundary_edge: usize,
+        #[serde(rename = "v")]
+        virtual_vertex: usize,
+    },
 }
 
 impl MicroBlossomSingle {
@@ -131,6 +159,10 @@ impl MicroBlossomSingle {
             offloading,
             layer_fusion: None,
             parity_reporters: None,
+            layer_assignment: None,
+            sharing_hints: None,
+            offloading_regions: None,
+            weight_quantum: None,
         };
         result.layer_fusion = Some(LayerFusion::new(&result));
         result
@@ -160,6 +192,31 @@ impl MicroBlossomSingle {
         )
     }
 
+    /// set the weight of the boundary edges of each regular vertex in `weights`, e.g., asymmetric boundaries during
+    /// lattice surgery, and update the fields that depend on the weights
+    pub fn set_boundary_weights(&mut self, weights: &BTreeMap<usize, isize>) {
+        let virtual_vertices: BTreeSet<usize> = self.virtual_vertices.iter().cloned().collect();
+        for edge in self.weighted_edges.iter_mut() {
+            let regular = match (virtual_vertices.contains(&edge.l), virtual_vertices.contains(&edge.r)) {
+                (false, true) => edge.l,
+                (true, false) => edge.r,
+                _ => continue,
+            };
+            if let Some(&weight) = weights.get(&regular) {
+                assert!(weight >= 0 && weight % 2 == 0, "weight must be non-negative even number");
+                edge.w = weight;
+            }
+        }
+        let initializer = self.get_initializer();
+        self.vertex_max_growth = infer_vertex_max_growth(&initializer);
+        self.offloading
+            .0
+            .retain(|offloading| !matches!(offloading, OffloadingType::VirtualMatch { .. }));
+        self.offloading.find_virtual_match(&initializer);
+        // the unit indices have changed
+        self.offloading_regions = None;
+    }
+
     pub fn get_positions(&self) -> Vec<VisualizePosition> {
         self.positions
             .iter()
@@ -192,11 +249,20 @@ impl OffloadingFinder {
         }
     }
 
+    /// boundary weights may differ between the boundary edges of the same vertex; only the cheapest ones are
+    /// offloaded, because a more expensive boundary edge never becomes tight before the cheapest one does
     pub fn find_virtual_match(&mut self, initializer: &SolverInitializer) {
         let virtual_vertices: BTreeSet<_> = initializer.virtual_vertices.iter().cloned().collect();
-        for (edge_index, (l, r, _weight)) in initializer.weighted_edges.iter().enumerate() {
+        let min_boundary_weight = Self::min_boundary_weight(initializer, &virtual_vertices);
+        for (edge_index, (l, r, weight)) in initializer.weighted_edges.iter().enumerate() {
             let is_virtual_left = virtual_vertices.contains(l);
             let is_virtual_right = virtual_vertices.contains(r);
+            if is_virtual_left != is_virtual_right {
+                let regular = if is_virtual_left { r } else { l };
+                if *weight > min_boundary_weight[regular] {
+                    continue;
+                }
+            }
             if is_virtual_left {
                 self.0.push(OffloadingType::VirtualMatch {
                     edge_index,
@@ -211,6 +277,62 @@ impl OffloadingFinder {
             }
         }
     }
+
+    /// a defect next to a vertex on the boundary reaches the virtual vertex through a chain of two edges, which
+    /// happens more often at higher physical error rates; only the cheapest boundary edge of the vertex in the middle
+    /// is used, and a defect that has a boundary edge no heavier than the chain reaches the boundary directly instead
+    pub fn find_chain_match(&mut self, initializer: &SolverInitializer) {
+        let virtual_vertices: BTreeSet<_> = initializer.virtual_vertices.iter().cloned().collect();
+        let min_boundary_weight = Self::min_boundary_weight(initializer, &virtual_vertices);
+        for (boundary_edge, (l, r, boundary_weight)) in initializer.weighted_edges.iter().enumerate() {
+            let (middle, virtual_vertex) = match (virtual_vertices.contains(l), virtual_vertices.contains(r)) {
+                (false, true) => (*l, *r),
+                (true, false) => (*r, *l),
+                _ => continue,
+            };
+            if *boundary_weight > min_boundary_weight[&middle] {
+                continue;
+            }
+            for (edge_index, (l, r, weight)) in initializer.weighted_edges.iter().enumerate() {
+                let defect = match (*l == middle, *r == middle) {
+                    (true, false) => *r,
+                    (false, true) => *l,
+                    _ => continue,
+                };
+                if virtual_vertices.contains(&defect) {
+                    continue;
+                }
+                if let Some(&direct_weight) = min_boundary_weight.get(&defect) {
+                    if direct_weight <= weight + boundary_weight {
+                        continue;
+                    }
+                }
+                self.0.push(OffloadingType::ChainMatch {
+                    edge_index,
+                    boundary_edge,
+                    virtual_vertex,
+                })
+            }
+        }
+    }
+
+    /// the weight of the cheapest boundary edge of every regular vertex that has one
+    fn min_boundary_weight(
+        initializer: &SolverInitializer,
+        virtual_vertices: &BTreeSet<VertexIndex>,
+    ) -> BTreeMap<VertexIndex, Weight> {
+        let mut min_boundary_weight = BTreeMap::<VertexIndex, Weight>::new();
+        for (l, r, weight) in initializer.weighted_edges.iter() {
+            let regular = match (virtual_vertices.contains(l), virtual_vertices.contains(r)) {
+                (false, true) => l,
+                (true, false) => r,
+                _ => continue,
+            };
+            let min_weight = min_boundary_weight.entry(*regular).or_insert(*weight);
+            *min_weight = std::cmp::min(*min_weight, *weight);
+        }
+        min_boundary_weight
+    }
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
@@ -496,6 +618,9 @@ fn infer_vertex_max_growth(initializer: &SolverInitializer) -> Vec<isize> {
         let mut farthest_non_virtual = 0;
         for j in 0..initializer.vertex_num {
             let dij = *distance.get(&(node_indices[i], node_indices[j])).unwrap();
+            if dij == isize::MAX {
+                continue; // unreachable: an isolated vertex or a disconnected region
+            }
             if is_virtual[j] {
                 nearest_virtual = std::cmp::min(nearest_virtual, dij);
             } else {
@@ -566,4 +691,43 @@ mod tests {
         println!("{:?}", micro_blossom.layer_fusion);
         visualize_code(&mut code, visualize_filename);
     }
+
+    /// a vertex with two boundary edges of different weights only offloads the cheaper one
+    #[test]
+    fn resources_asymmetric_boundary_offloading() {
+        // cargo test resources_asymmetric_boundary_offloading -- --nocapture
+        // 0(virtual) - 1 - 2 - 3(virtual), and 1 also connects to 3
+        let initializer = SolverInitializer::new(4, vec![(0, 1, 100), (1, 2, 100), (2, 3, 100), (1, 3, 100)], vec![0, 3]);
+        let mut micro_blossom = MicroBlossomSingle::new_initializer_only(&initializer);
+        let virtual_matches = |micro_blossom: &MicroBlossomSingle| -> Vec<usize> {
+            micro_blossom
+                .offloading
+                .0
+                .iter()
+                .filter_map(|offloading| match offloading {
+                    OffloadingType::VirtualMatch { edge_index, .. } => Some(*edge_index),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(virtual_matches(&micro_blossom), vec![0, 2, 3]);
+        micro_blossom.set_boundary_weights(&BTreeMap::from([(1, 60)]));
+        assert_eq!(virtual_matches(&micro_blossom), vec![0, 2, 3]);
+        assert_eq!(micro_blossom.vertex_max_growth[1], 60);
+        micro_blossom.weighted_edges[3].w = 200;
+        micro_blossom.set_boundary_weights(&BTreeMap::from([(2, 40)]));
+        assert_eq!(micro_blossom.weighted_edges[0].w, 60);
+        assert_eq!(micro_blossom.weighted_edges[3].w, 200);
+        assert_eq!(virtual_matches(&micro_blossom), vec![0, 2]);
+    }
+
+    #[test]
+    fn resources_isolated_vertices() {
+        // cargo test resources_isolated_vertices -- --nocapture
+        // 0(virtual) - 1 - 2, 3 - 4 is a disconnected region, 5(virtual) and 6 are isolated
+        let initializer = SolverInitializer::new(7, vec![(0, 1, 100), (1, 2, 40), (3, 4, 20)], vec![0, 5]);
+        let positions: Vec<_> = (0..7).map(|i| VisualizePosition::new(0., i as f64, 0.)).collect();
+        let micro_blossom = MicroBlossomSingle::new(&initializer, &positions);
+        assert_eq!(micro_blossom.vertex_max_growth, vec![0, 100, 140, 20, 20, 0, 0]);
+    }
 }
@@ -0,0 +1,442 @@
+//! Result Diff
+//!
+//! Compare two benchmark profiles, i.e., the files written by `micro-blossom benchmark --benchmark-profiler-output`,
+//! and report the changes that are statistically significant rather than run-to-run noise. The first two lines of a
+//! profile are the partition and the benchmark configuration, which must hash to the same value up to the fields that
+//! do not change the experiment, e.g., the output paths or the number of shots; every other line is a shot.
+//!
+//! - the latency percentiles (p50, p90, p99) of the `decoded` event, with a bootstrap confidence interval
+//! - the logical error rate, with a normal-approximation interval; only when every shot records a `logical_error`
+//!   flag, because the benchmark itself only verifies the minimum weight
+//! - the mean count of every instruction per shot, taken from the `instruction_counts` of the comb dual module or
+//!   counted from its `history` when `log_instructions` is enabled
+//!
+//! A change is significant when the 95% confidence interval of the difference `b - a` excludes 0.
+//!
+//! ```bash
+//! micro-blossom diff-results a.profile b.profile
+//! ```
+//!
+
+use crate::dual_module_comb::*;
+use clap::Parser;
+use rand::Rng;
+use rand_xoshiro::rand_core::SeedableRng;
+use rand_xoshiro::Xoroshiro128StarStar;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// the two-sided 95% quantile of the standard normal distribution
+const Z_95: f64 = 1.959963984540054;
+
+/// the fields of the benchmark configuration that do not change the experiment
+pub const IGNORED_CONFIG_KEYS: &[&str] = &[
+    "benchmark_profiler_output",
+    "enable_visualizer",
+    "pb_message",
+    "print_syndrome_pattern",
+    "starting_iteration",
+    "total_rounds",
+    "visualizer_filename",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResultDiffError {
+    /// the profile cannot be read
+    Read { filename: String, message: String },
+    /// a line of the profile is not as written by the benchmark, where `line` starts from 1
+    Format { line: usize, message: String },
+    /// the two profiles are not from the same configuration
+    ConfigMismatch { hash_a: u64, hash_b: u64 },
+}
+
+impl std::fmt::Display for ResultDiffError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read { filename, message } => write!(f, "cannot read {filename}: {message}"),
+            Self::Format { line, message } => write!(f, "invalid profile at line {line}: {message}"),
+            Self::ConfigMismatch { hash_a, hash_b } => {
+                write!(f, "configuration hash {hash_a:016x} differs from {hash_b:016x}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResultDiffError {}
+
+#[derive(Debug, Clone, Default)]
+pub struct BenchmarkResults {
+    /// the partition and the benchmark configuration
+    pub config: Vec<serde_json::Value>,
+    /// the latency of every shot in seconds
+    pub latencies: Vec<f64>,
+    /// whether every shot has a logical error, `None` if any shot does not record it
+    pub logical_errors: Option<Vec<bool>>,
+    /// the number of every instruction in every shot
+    pub instruction_counts: Vec<BTreeMap<String, usize>>,
+}
+
+impl BenchmarkResults {
+    pub fn load(filename: &str) -> Result<Self, ResultDiffError> {
+        let content = std::fs::read_to_string(filename).map_err(|error| ResultDiffError::Read {
+            filename: filename.to_string(),
+            message: error.to_string(),
+        })?;
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Self, ResultDiffError> {
+        let mut results = Self {
+            logical_errors: Some(vec![]),
+            ..Default::default()
+        };
+        for (line_index, line) in content.lines().enumerate() {
+            let format_error = |message: String| ResultDiffError::Format {
+                line: line_index + 1,
+                message,
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+            let value: serde_json::Value = serde_json::from_str(line).map_err(|error| format_error(error.to_string()))?;
+            if line_index < 2 {
+                results.config.push(value);
+                continue;
+            }
+            let latency = value["events"]["decoded"]
+                .as_f64()
+                .ok_or_else(|| format_error("missing the `decoded` event".to_string()))?;
+            results.latencies.push(latency);
+            let logical_error = value.get("logical_error").and_then(serde_json::Value::as_bool);
+            results.logical_errors = results.logical_errors.take().and_then(|mut logical_errors| {
+                logical_errors.push(logical_error?);
+                Some(logical_errors)
+            });
+            let dual_profile = &value["solver_profile"]["dual"];
+            let mut instruction_counts = BTreeMap::<String, usize>::new();
+            if let Some(counts) = dual_profile["instruction_counts"].as_object() {
+                for (name, count) in counts.iter() {
+                    let count = count
+                        .as_u64()
+                        .ok_or_else(|| format_error(format!("invalid count of instruction {name}")))?;
+                    instruction_counts.insert(name.clone(), count as usize);
+                }
+            } else if let Some(history) = dual_profile["history"].as_array() {
+                for instruction in history.iter() {
+                    let instruction: Instruction =
+                        serde_json::from_value(instruction.clone()).map_err(|error| format_error(error.to_string()))?;
+                    *instruction_counts.entry(instruction.name().to_string()).or_default() += 1;
+                }
+            }
+            results.instruction_counts.push(instruction_counts);
+        }
+        if results.config.len() < 2 {
+            return Err(ResultDiffError::Format {
+                line: results.config.len() + 1,
+                message: "missing the configuration".to_string(),
+            });
+        }
+        if results.latencies.is_empty() {
+            results.logical_errors = None;
+        }
+        Ok(results)
+    }
+
+    /// the hash of the configuration without the [`IGNORED_CONFIG_KEYS`], independent of the order of the keys
+    pub fn config_hash(&self) -> u64 {
+        let mut config = self.config.clone();
+        if let Some(benchmark_config) = config.get_mut(1).and_then(serde_json::Value::as_object_mut) {
+            for key in IGNORED_CONFIG_KEYS.iter() {
+                benchmark_config.remove(*key);
+            }
+        }
+        // FNV-1a, which is stable across runs and toolchains unlike the default hasher
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in canonical_json(&json!(config)).to_string().bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        hash
+    }
+}
+
+/// the same value with the keys of every object sorted
+fn canonical_json(value: &serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(object) => {
+            let sorted: BTreeMap<&String, serde_json::Value> =
+                object.iter().map(|(key, value)| (key, canonical_json(value))).collect();
+            json!(sorted)
+        }
+        serde_json::Value::Array(array) => serde_json::Value::Array(array.iter().map(canonical_json).collect()),
+        _ => value.clone(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricChange {
+    pub a: f64,
+    pub b: f64,
+    /// `(b - a) / a`, `None` if `a` is 0
+    pub relative: Option<f64>,
+    /// the 95% confidence interval of `b - a`
+    pub interval: (f64, f64),
+    pub significant: bool,
+}
+
+impl MetricChange {
+    pub fn new(a: f64, b: f64, interval: (f64, f64)) -> Self {
+        Self {
+            a,
+            b,
+            relative: (a != 0.).then(|| (b - a) / a),
+            interval,
+            significant: interval.0 > 0. || interval.1 < 0.,
+        }
+    }
+
+    /// the difference of the means of two samples, with the normal approximation of its standard error
+    pub fn of_means(a: &[f64], b: &[f64]) -> Self {
+        let (mean_a, variance_a) = mean_variance(a);
+        let (mean_b, variance_b) = mean_variance(b);
+        let error = (variance_a / a.len() as f64 + variance_b / b.len() as f64).sqrt();
+        let difference = mean_b - mean_a;
+        Self::new(mean_a, mean_b, (difference - Z_95 * error, difference + Z_95 * error))
+    }
+}
+
+fn mean_variance(samples: &[f64]) -> (f64, f64) {
+    let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+    if samples.len() < 2 {
+        return (mean, 0.);
+    }
+    let variance = samples.iter().map(|sample| (sample - mean).powi(2)).sum::<f64>() / (samples.len() - 1) as f64;
+    (mean, variance)
+}
+
+/// the nearest-rank percentile of sorted samples
+fn percentile(sorted: &[f64], ratio: f64) -> f64 {
+    sorted[((sorted.len() - 1) as f64 * ratio).round() as usize]
+}
+
+fn sorted(mut samples: Vec<f64>) -> Vec<f64> {
+    samples.sort_by(f64::total_cmp);
+    samples
+}
+
+/// the difference of a percentile, with the percentile bootstrap interval over `resamples` resamples
+pub fn percentile_change(a: &[f64], b: &[f64], ratio: f64, resamples: usize, seed: u64) -> MetricChange {
+    let mut rng = Xoroshiro128StarStar::seed_from_u64(seed);
+    let mut resample = |samples: &[f64]| -> f64 {
+        let resampled = (0..samples.len()).map(|_| samples[rng.gen_range(0..samples.len())]).collect();
+        percentile(&sorted(resampled), ratio)
+    };
+    let differences = sorted((0..resamples).map(|_| resample(b) - resample(a)).collect());
+    let interval = if differences.is_empty() {
+        (f64::NEG_INFINITY, f64::INFINITY)
+    } else {
+        (percentile(&differences, 0.025), percentile(&differences, 0.975))
+    };
+    MetricChange::new(
+        percentile(&sorted(a.to_vec()), ratio),
+        percentile(&sorted(b.to_vec()), ratio),
+        interval,
+    )
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResultDiff {
+    pub config_hash: String,
+    pub shots: (usize, usize),
+    /// the change of the latency percentiles, e.g., `p99`
+    pub latency: BTreeMap<String, MetricChange>,
+    /// `None` if either profile does not record the logical errors
+    pub logical_error_rate: Option<MetricChange>,
+    /// the change of the mean count of every instruction per shot
+    pub instructions: BTreeMap<String, MetricChange>,
+}
+
+impl ResultDiff {
+    pub fn compare(a: &BenchmarkResults, b: &BenchmarkResults, resamples: usize) -> Result<Self, ResultDiffError> {
+        let (hash_a, hash_b) = (a.config_hash(), b.config_hash());
+        if hash_a != hash_b {
+            return Err(ResultDiffError::ConfigMismatch { hash_a, hash_b });
+        }
+        let mut diff = Self {
+            config_hash: format!("{hash_a:016x}"),
+            shots: (a.latencies.len(), b.latencies.len()),
+            latency: BTreeMap::new(),
+            logical_error_rate: None,
+            instructions: BTreeMap::new(),
+        };
+        if a.latencies.is_empty() || b.latencies.is_empty() {
+            return Ok(diff);
+        }
+        for (index, (name, ratio)) in [("p50", 0.5), ("p90", 0.9), ("p99", 0.99)].into_iter().enumerate() {
+            let change = percentile_change(&a.latencies, &b.latencies, ratio, resamples, index as u64);
+            diff.latency.insert(name.to_string(), change);
+        }
+        if let (Some(errors_a), Some(errors_b)) = (a.logical_errors.as_ref(), b.logical_errors.as_ref()) {
+            let indicators = |errors: &[bool]| -> Vec<f64> { errors.iter().map(|&error| error as usize as f64).collect() };
+            diff.logical_error_rate = Some(MetricChange::of_means(&indicators(errors_a), &indicators(errors_b)));
+        }
+        let names: BTreeSet<&String> = (a.instruction_counts.iter())
+            .chain(b.instruction_counts.iter())
+            .flat_map(|counts| counts.keys())
+            .collect();
+        for name in names {
+            let counts_of = |results: &BenchmarkResults| -> Vec<f64> {
+                (results.instruction_counts.iter())
+                    .map(|counts| counts.get(name).copied().unwrap_or(0) as f64)
+                    .collect()
+            };
+            diff.instructions
+                .insert(name.clone(), MetricChange::of_means(&counts_of(a), &counts_of(b)));
+        }
+        Ok(diff)
+    }
+
+    /// the name of every significant change, e.g., `latency.p99` or `instructions.grow`
+    pub fn significant_changes(&self) -> Vec<(String, &MetricChange)> {
+        let latency = (self.latency.iter()).map(|(name, change)| (format!("latency.{name}"), change));
+        let logical_error_rate = (self.logical_error_rate.iter()).map(|change| ("logical_error_rate".to_string(), change));
+        let instructions = (self.instructions.iter()).map(|(name, change)| (format!("instructions.{name}"), change));
+        latency
+            .chain(logical_error_rate)
+            .chain(instructions)
+            .filter(|(_, change)| change.significant)
+            .collect()
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct DiffResultsParameters {
+    /// the benchmark profile of the baseline run
+    #[clap(value_parser)]
+    a: String,
+    /// the benchmark profile of the run to compare with the baseline
+    #[clap(value_parser)]
+    b: String,
+    /// the number of bootstrap resamples for the confidence intervals of the latency percentiles
+    #[clap(long, default_value_t = 1000)]
+    resamples: usize,
+    /// the report output file path
+    #[clap(long)]
+    output: Option<String>,
+}
+
+impl DiffResultsParameters {
+    pub fn run(&self) -> ResultDiff {
+        let load = |filename: &str| BenchmarkResults::load(filename).unwrap_or_else(|error| panic!("{error}"));
+        let (a, b) = (load(&self.a), load(&self.b));
+        let diff = ResultDiff::compare(&a, &b, self.resamples).unwrap_or_else(|error| panic!("{error}"));
+        let significant_changes = diff.significant_changes();
+        eprintln!(
+            "{} significant changes over {} vs {} shots",
+            significant_changes.len(),
+            diff.shots.0,
+            diff.shots.1
+        );
+        for (name, change) in significant_changes.iter() {
+            let relative = change.relative.map(|relative| format!(" ({:+.2}%)", relative * 100.));
+            eprintln!("    {name}: {} -> {}{}", change.a, change.b, relative.unwrap_or_default());
+        }
+        let report = json!(diff);
+        println!("{report}");
+        if let Some(output) = self.output.as_ref() {
+            std::fs::write(output, serde_json::to_string_pretty(&report).unwrap()).unwrap();
+        }
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile_of(config: serde_json::Value, latencies: &[f64], grows: &[usize]) -> String {
+        let mut lines = vec![json!({ "vertex_num": 10 }).to_string(), config.to_string()];
+        for (latency, grow) in latencies.iter().zip(grows.iter()) {
+            let entry = json!({
+                "events": { "decoded": latency },
+                "solver_profile": { "dual": { "instruction_counts": { "grow": grow, "find_obstacle": 3 } } },
+            });
+            lines.push(entry.to_string());
+        }
+        lines.join("\n")
+    }
+
+    #[test]
+    fn result_diff_significance() {
+        // cargo test result_diff_significance -- --nocapture
+        let config = json!({ "d": 5, "p": 0.01, "total_rounds": 200, "pb_message": "a" });
+        let latencies: Vec<f64> = (0..200).map(|index| 1e-6 * (1. + (index * 37 % 200) as f64 / 200.)).collect();
+        let grows: Vec<usize> = (0..200).map(|index| 10 + index % 3).collect();
+        let a = BenchmarkResults::parse(&profile_of(config, &latencies, &grows)).unwrap();
+        // another run of the same experiment with shuffled shots, and a run that is 20% slower
+        let config = json!({ "total_rounds": 100, "p": 0.01, "d": 5, "pb_message": "b" });
+        let mut shuffled = latencies.clone();
+        shuffled.rotate_left(51);
+        let same = BenchmarkResults::parse(&profile_of(config.clone(), &shuffled, &grows)).unwrap();
+        let slower: Vec<f64> = latencies.iter().map(|latency| latency * 1.2).collect();
+        let more_grows: Vec<usize> = grows.iter().map(|grow| grow + 1).collect();
+        let b = BenchmarkResults::parse(&profile_of(config, &slower, &more_grows)).unwrap();
+        assert_eq!(a.config_hash(), b.config_hash());
+        assert!(ResultDiff::compare(&a, &same, 200).unwrap().significant_changes().is_empty());
+        let diff = ResultDiff::compare(&a, &b, 200).unwrap();
+        println!("{}", json!(diff));
+        let names: Vec<String> = diff.significant_changes().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(
+            names,
+            ["latency.p50", "latency.p90", "latency.p99", "instructions.grow"].map(String::from)
+        );
+        assert!(diff.logical_error_rate.is_none());
+    }
+
+    #[test]
+    fn result_diff_reject() {
+        // cargo test result_diff_reject -- --nocapture
+        let a = BenchmarkResults::parse(&profile_of(json!({ "d": 5 }), &[1.], &[1])).unwrap();
+        let b = BenchmarkResults::parse(&profile_of(json!({ "d": 7 }), &[1.], &[1])).unwrap();
+        let error = ResultDiff::compare(&a, &b, 10).unwrap_err();
+        println!("{error}");
+        assert!(matches!(error, ResultDiffError::ConfigMismatch { .. }));
+        let error = BenchmarkResults::parse("{}\n{}\n{\"events\":{}}").unwrap_err();
+        assert_eq!(
+            error,
+            ResultDiffError::Format {
+                line: 3,
+                message: "missing the `decoded` event".to_string()
+            }
+        );
+    }
+
+    /// the logical errors and the instruction history are read when recorded
+    #[test]
+    fn result_diff_logical_errors() {
+        // cargo test result_diff_logical_errors -- --nocapture
+        let profile_of = |failures: usize| -> String {
+            let mut lines = vec!["{}".to_string(), "{}".to_string()];
+            for index in 0..1000 {
+                let entry = json!({
+                    "events": { "decoded": 1. },
+                    "logical_error": index < failures,
+                    "solver_profile": { "dual": { "history": [ { "FindObstacle": { "region_preference": null } } ] } },
+                });
+                lines.push(entry.to_string());
+            }
+            lines.join("\n")
+        };
+        let a = BenchmarkResults::parse(&profile_of(10)).unwrap();
+        assert_eq!(a.instruction_counts[0]["find_obstacle"], 1);
+        let close = ResultDiff::compare(&a, &BenchmarkResults::parse(&profile_of(12)).unwrap(), 10).unwrap();
+        assert!(!close.logical_error_rate.unwrap().significant);
+        let worse = ResultDiff::compare(&a, &BenchmarkResults::parse(&profile_of(40)).unwrap(), 10).unwrap();
+        let change = worse.logical_error_rate.unwrap();
+        assert_eq!((change.a, change.b), (0.01, 0.04));
+        assert!(change.significant);
+        assert!(worse.instructions["find_obstacle"].interval == (0., 0.));
+    }
+}
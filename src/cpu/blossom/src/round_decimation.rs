@@ -0,0 +1,363 @@
+//! Round Decimation
+//!
+//! A threshold scan over many distances and error rates is slow on a graph with `d` measurement rounds, although a
+//! rough estimate is often enough while iterating on a design. [`decimate_rounds`] collapses every `k` consecutive
+//! measurement rounds into a single round: the vertices at the same spatial position in the same group of rounds are
+//! merged, and a defect of the merged vertex is the parity of the original defects. The timelike edges inside a
+//! group are dropped, because an error on such an edge flips two vertices that are merged into one. The parallel
+//! edges that remain, i.e., the same spacelike error in different rounds of a group, are merged by
+//! [`merge_parallel_edges`], which adjusts the weights to the combined error probability.
+//!
+//! For independent error mechanisms, the errors sampled on the decimated code follow the same distribution as the
+//! decimated syndromes of the full code, so a scan can run on the decimated code directly. The decoder, however,
+//! loses the information of when a defect happened within a group, so the result is only an APPROXIMATION of the
+//! full decoding and every report is labeled with `"approximate": true`. With `--compare`, every shot is sampled on
+//! the full code and decoded both exactly on the full graph and approximately on the decimated graph, and
+//! [`DecimationComparison`] quantifies the difference between the two corrections on the decimated graph.
+//!
+//! ```bash
+//! micro-blossom decimate 7 0.01 -n 7 -c phenomenological-planar-code -k 2 --compare
+//! ```
+//!
+
+use crate::cli::CodeParameters;
+use crate::example_codes::*;
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use clap::{Parser, ValueEnum};
+use fusion_blossom::example_codes::*;
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::util::*;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Instant;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoundDecimation {
+    /// always true: decoding the decimated graph only approximates decoding the full graph
+    pub approximate: bool,
+    /// the number of consecutive measurement rounds collapsed into one
+    pub k: usize,
+    pub original_rounds: usize,
+    pub decimated_rounds: usize,
+    pub original_vertex_num: usize,
+    pub decimated_vertex_num: usize,
+    /// the timelike edges dropped because both ends are merged into the same vertex
+    pub collapsed_edges: usize,
+    /// the spacelike edges merged into a parallel edge of another round in the same group
+    pub merged_edges: usize,
+    /// `merged[original_index]` is the vertex index in the decimated graph
+    #[serde(skip)]
+    pub merged: Vec<VertexIndex>,
+    /// the decimated edge of every original edge, `None` if the edge is collapsed
+    #[serde(skip)]
+    pub edge_mapping: Vec<Option<EdgeIndex>>,
+}
+
+/// collapse every `k` consecutive measurement rounds of the code into a single round, where the rounds are told
+/// apart by the time coordinate of the vertices; the merged vertex keeps the position of its earliest round
+pub fn decimate_rounds(code: &mut dyn ExampleCode, k: usize) -> RoundDecimation {
+    assert!(k >= 1, "should decimate at least one round into each group");
+    let (vertices, edges) = code.vertices_edges();
+    let mut times: Vec<f64> = vertices.iter().map(|vertex| vertex.position.t).collect();
+    times.sort_by(f64::total_cmp);
+    times.dedup();
+    let mut decimation = RoundDecimation {
+        approximate: true,
+        k,
+        original_rounds: times.len(),
+        decimated_rounds: times.len().div_ceil(k),
+        original_vertex_num: vertices.len(),
+        decimated_vertex_num: 0,
+        collapsed_edges: 0,
+        merged_edges: 0,
+        merged: Vec::with_capacity(vertices.len()),
+        edge_mapping: Vec::with_capacity(edges.len()),
+    };
+    let mut keys = BTreeMap::<(usize, u64, u64, bool), VertexIndex>::new();
+    let mut decimated_vertices: Vec<CodeVertex> = vec![];
+    for vertex in vertices.iter() {
+        let group = times.partition_point(|&t| t < vertex.position.t) / k;
+        let key = (
+            group,
+            vertex.position.i.to_bits(),
+            vertex.position.j.to_bits(),
+            vertex.is_virtual,
+        );
+        let merged_index = *keys.entry(key).or_insert_with(|| {
+            let mut decimated_vertex = vertex.clone();
+            decimated_vertex.neighbor_edges.clear();
+            decimated_vertex.is_defect = false;
+            decimated_vertices.push(decimated_vertex);
+            decimated_vertices.len() - 1
+        });
+        decimation.merged.push(merged_index);
+    }
+    let mut decimated_edges: Vec<CodeEdge> = vec![];
+    for edge in edges.iter() {
+        let (left, right) = edge.vertices;
+        let (left, right) = (decimation.merged[left], decimation.merged[right]);
+        if left == right {
+            decimation.collapsed_edges += 1;
+            decimation.edge_mapping.push(None);
+            continue;
+        }
+        let mut decimated_edge = edge.clone();
+        decimated_edge.vertices = (left, right);
+        decimation.edge_mapping.push(Some(decimated_edges.len()));
+        decimated_edges.push(decimated_edge);
+    }
+    decimation.decimated_vertex_num = decimated_vertices.len();
+    *vertices = decimated_vertices;
+    *edges = decimated_edges;
+    let merge = merge_parallel_edges(code);
+    decimation.merged_edges = merge.merged_edges;
+    for edge_index in decimation.edge_mapping.iter_mut().flatten() {
+        *edge_index = merge.edge_mapping[*edge_index];
+    }
+    decimation
+}
+
+impl RoundDecimation {
+    /// the defects of the decimated graph, each being the parity of the original defects merged into it
+    pub fn syndrome_pattern(&self, syndrome_pattern: &SyndromePattern) -> SyndromePattern {
+        assert!(syndrome_pattern.erasures.is_empty(), "erasures are not decimated");
+        let mut defect_vertices = BTreeSet::new();
+        for &defect_vertex in syndrome_pattern.defect_vertices.iter() {
+            let merged_index = self.merged[defect_vertex];
+            if !defect_vertices.remove(&merged_index) {
+                defect_vertices.insert(merged_index);
+            }
+        }
+        SyndromePattern::new_vertices(defect_vertices.into_iter().collect())
+    }
+
+    /// project a subgraph of the full graph onto the decimated graph, which explains the decimated defects
+    pub fn decimated_subgraph(&self, subgraph: &[EdgeIndex]) -> Vec<EdgeIndex> {
+        let mut decimated = BTreeSet::new();
+        for &edge_index in subgraph.iter() {
+            if let Some(merged_index) = self.edge_mapping[edge_index] {
+                if !decimated.remove(&merged_index) {
+                    decimated.insert(merged_index);
+                }
+            }
+        }
+        decimated.into_iter().collect()
+    }
+}
+
+/// the approximation error of decoding the decimated graph, measured against the exact corrections projected onto
+/// the decimated graph
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DecimationComparison {
+    pub shots: usize,
+    /// the shots whose approximate correction differs from the projected exact correction
+    pub mismatched_shots: usize,
+    /// the total weight of the projected exact corrections in the decimated graph
+    pub exact_weight: Weight,
+    /// the total weight of the approximate corrections, never more than `exact_weight`
+    pub approximate_weight: Weight,
+}
+
+impl DecimationComparison {
+    pub fn record(
+        &mut self,
+        decimation: &RoundDecimation,
+        decimated_initializer: &SolverInitializer,
+        exact_subgraph: &[EdgeIndex],
+        approximate_subgraph: &[EdgeIndex],
+    ) {
+        let projected = decimation.decimated_subgraph(exact_subgraph);
+        let mut approximate = approximate_subgraph.to_vec();
+        approximate.sort();
+        let weight_of = |subgraph: &[EdgeIndex]| -> Weight {
+            (subgraph.iter())
+                .map(|&edge_index| decimated_initializer.weighted_edges[edge_index].2)
+                .sum()
+        };
+        self.shots += 1;
+        if projected != approximate {
+            self.mismatched_shots += 1;
+        }
+        self.exact_weight += weight_of(&projected);
+        self.approximate_weight += weight_of(&approximate);
+    }
+
+    pub fn mismatch_rate(&self) -> f64 {
+        self.mismatched_shots as f64 / self.shots as f64
+    }
+
+    /// the relative weight that the approximate decoder finds below the projected exact corrections, because it
+    /// ignores when a defect happens within a group of rounds
+    pub fn weight_gap(&self) -> f64 {
+        if self.exact_weight == 0 {
+            return 0.;
+        }
+        1. - self.approximate_weight as f64 / self.exact_weight as f64
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct DecimateParameters {
+    #[clap(flatten)]
+    code: CodeParameters,
+    /// decode only every k-th measurement round, collapsing the rounds in between
+    #[clap(short = 'k', long, default_value_t = 2)]
+    k: usize,
+    /// the number of shots to run; the seed of each shot is its index
+    #[clap(short = 'r', long, default_value_t = 1000)]
+    total_rounds: usize,
+    /// the configuration of primal and dual module
+    #[clap(long, default_value_t = ("{}").to_string())]
+    primal_dual_config: String,
+    /// sample on the full code and also decode the full graph exactly, to quantify the approximation error
+    #[clap(long, action)]
+    compare: bool,
+    /// the report output file path
+    #[clap(long)]
+    profiler_output: Option<String>,
+}
+
+impl DecimateParameters {
+    pub fn run(&self) -> serde_json::Value {
+        let primal_dual_config: serde_json::Value = serde_json::from_str(&self.primal_dual_config).unwrap();
+        let mut full_code = self.code.build();
+        let mut decimated_code = self.code.build();
+        let decimation = decimate_rounds(decimated_code.as_mut(), self.k);
+        let decimated_initializer = decimated_code.get_initializer();
+        let graph = MicroBlossomSingle::new_code(decimated_code.as_ref());
+        let mut solver = SolverEmbeddedComb::new(graph, primal_dual_config);
+        let mut exact_solver = self.compare.then(|| SolverSerial::new(&full_code.get_initializer()));
+        let mut comparison = DecimationComparison::default();
+        let mut elapsed = 0.;
+        for seed in 0..self.total_rounds as u64 {
+            let syndrome_pattern = match exact_solver.as_mut() {
+                Some(exact_solver) => {
+                    let syndrome_pattern = full_code.generate_random_errors(seed);
+                    exact_solver.solve(&syndrome_pattern);
+                    let exact_subgraph = exact_solver.subgraph();
+                    exact_solver.clear();
+                    let syndrome_pattern = decimation.syndrome_pattern(&syndrome_pattern);
+                    let begin = Instant::now();
+                    solver.solve(&syndrome_pattern);
+                    elapsed += begin.elapsed().as_secs_f64();
+                    comparison.record(&decimation, &decimated_initializer, &exact_subgraph, &solver.subgraph());
+                    syndrome_pattern
+                }
+                None => {
+                    let syndrome_pattern = decimated_code.generate_random_errors(seed);
+                    let begin = Instant::now();
+                    solver.solve(&syndrome_pattern);
+                    elapsed += begin.elapsed().as_secs_f64();
+                    syndrome_pattern
+                }
+            };
+            assert_eq!(
+                decimated_initializer.syndrome_of(&solver.subgraph()),
+                syndrome_pattern.defect_vertices.iter().cloned().collect(),
+                "invalid correction of shot {seed}"
+            );
+            solver.clear();
+        }
+        let mut report = json!({
+            "approximate": true,
+            "decimation": decimation,
+            "shots": self.total_rounds,
+            "elapsed": elapsed,
+        });
+        if self.compare {
+            report["comparison"] = json!(comparison);
+            report["comparison"]["mismatch_rate"] = json!(comparison.mismatch_rate());
+            report["comparison"]["weight_gap"] = json!(comparison.weight_gap());
+        }
+        report["code_type"] = json!(self.code.code_type.to_possible_value().unwrap().get_name());
+        report["d"] = json!(self.code.d);
+        report["p"] = json!(self.code.p);
+        report["noisy_measurements"] = json!(self.code.noisy_measurements);
+        report["primal_dual_config"] = json!(self.primal_dual_config);
+        eprintln!(
+            "[approximate] decoded every {}-th round: {} -> {} rounds",
+            self.k, decimation.original_rounds, decimation.decimated_rounds
+        );
+        println!("{report}");
+        if let Some(profiler_output) = self.profiler_output.as_ref() {
+            std::fs::write(profiler_output, serde_json::to_string_pretty(&report).unwrap()).unwrap();
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_decimation_graph() {
+        // cargo test round_decimation_graph -- --nocapture
+        let mut code = PhenomenologicalPlanarCode::new(5, 4, 0.03, 500);
+        let original_edges = code.immutable_vertices_edges().1.clone();
+        let decimation = decimate_rounds(&mut code, 2);
+        println!("{}", json!(decimation));
+        assert!(decimation.approximate);
+        assert_eq!(decimation.original_rounds, 5);
+        assert_eq!(decimation.decimated_rounds, 3);
+        let (vertices, edges) = code.immutable_vertices_edges();
+        assert_eq!(vertices.len(), decimation.decimated_vertex_num);
+        assert!(decimation.decimated_vertex_num < decimation.original_vertex_num);
+        assert!(decimation.collapsed_edges > 0 && decimation.merged_edges > 0);
+        for (edge, edge_mapping) in original_edges.iter().zip(decimation.edge_mapping.iter()) {
+            let (left, right) = edge.vertices;
+            let (left, right) = (decimation.merged[left], decimation.merged[right]);
+            match edge_mapping {
+                None => assert_eq!(left, right),
+                Some(edge_index) => {
+                    let (decimated_left, decimated_right) = edges[*edge_index].vertices;
+                    assert_eq!(
+                        (left.min(right), left.max(right)),
+                        (decimated_left.min(decimated_right), decimated_left.max(decimated_right))
+                    );
+                }
+            }
+        }
+        // a single round per group merges nothing
+        let mut code = PhenomenologicalPlanarCode::new(5, 4, 0.03, 500);
+        let decimation = decimate_rounds(&mut code, 1);
+        assert_eq!(decimation.decimated_vertex_num, decimation.original_vertex_num);
+        assert_eq!((decimation.collapsed_edges, decimation.merged_edges), (0, 0));
+    }
+
+    /// the projected exact correction explains the decimated defects, but never weighs less than the approximate one
+    #[test]
+    fn round_decimation_compare() {
+        // cargo test round_decimation_compare -- --nocapture
+        let mut full_code = PhenomenologicalPlanarCode::new(5, 4, 0.03, 500);
+        let mut decimated_code = PhenomenologicalPlanarCode::new(5, 4, 0.03, 500);
+        let decimation = decimate_rounds(&mut decimated_code, 2);
+        let decimated_initializer = decimated_code.get_initializer();
+        let mut exact_solver = SolverSerial::new(&full_code.get_initializer());
+        let graph = MicroBlossomSingle::new_code(&decimated_code);
+        let mut solver = SolverEmbeddedComb::new(graph, json!({}));
+        let mut comparison = DecimationComparison::default();
+        for seed in 0..50 {
+            let syndrome_pattern = full_code.generate_random_errors(seed);
+            exact_solver.solve(&syndrome_pattern);
+            let exact_subgraph = exact_solver.subgraph();
+            let decimated_syndrome = decimation.syndrome_pattern(&syndrome_pattern);
+            let decimated_defects: BTreeSet<_> = decimated_syndrome.defect_vertices.iter().cloned().collect();
+            assert_eq!(
+                decimated_initializer.syndrome_of(&decimation.decimated_subgraph(&exact_subgraph)),
+                decimated_defects
+            );
+            solver.solve(&decimated_syndrome);
+            assert_eq!(decimated_initializer.syndrome_of(&solver.subgraph()), decimated_defects);
+            comparison.record(&decimation, &decimated_initializer, &exact_subgraph, &solver.subgraph());
+            exact_solver.clear();
+            solver.clear();
+        }
+        println!("{comparison:?}");
+        assert_eq!(comparison.shots, 50);
+        assert!(comparison.approximate_weight <= comparison.exact_weight);
+        assert!((0. ..1.).contains(&comparison.weight_gap()));
+    }
+}
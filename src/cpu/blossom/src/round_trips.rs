@@ -0,0 +1,209 @@
+//! Round Trips
+//!
+//! Every conflict query is a round trip between the CPU and the hardware, which dominates the latency of a shot in
+//! the latency model: the number of round trips grows with the number of defects, and hence with the code distance
+//! and the physical error rate. This module records the round trips of every shot together with the number of
+//! defects, and fits them linearly to provide the empirical scaling data of the latency model.
+//!
+//! Optionally, a hard cap protects a real-time deadline: once a shot reaches the cap, the solve stops and the
+//! remaining defects are matched greedily, the same fallback as the blossom budget (see [`crate::blossom_budget`]).
+//! The cap is best-effort because a single obstacle may take a few round trips to find.
+//!
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoundTripConfig {
+    /// the maximum number of round trips of a shot before falling back to the greedy matching
+    #[serde(default = "Default::default")]
+    pub cap: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RoundTripSample {
+    pub defects: usize,
+    pub round_trips: usize,
+}
+
+/// the least-squares fit of `y = slope * x + intercept`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LinearFit {
+    pub slope: f64,
+    pub intercept: f64,
+    /// the coefficient of determination, 1 means a perfect fit
+    pub r_squared: f64,
+}
+
+impl LinearFit {
+    /// `None` if there are fewer than two distinct `x` values
+    pub fn new(points: &[(f64, f64)]) -> Option<Self> {
+        let count = points.len() as f64;
+        let mean_x = points.iter().map(|&(x, _)| x).sum::<f64>() / count;
+        let mean_y = points.iter().map(|&(_, y)| y).sum::<f64>() / count;
+        let variance_x: f64 = points.iter().map(|&(x, _)| (x - mean_x).powi(2)).sum();
+        let variance_y: f64 = points.iter().map(|&(_, y)| (y - mean_y).powi(2)).sum();
+        let covariance: f64 = points.iter().map(|&(x, y)| (x - mean_x) * (y - mean_y)).sum();
+        if points.len() < 2 || variance_x == 0. {
+            return None;
+        }
+        let slope = covariance / variance_x;
+        let r_squared = if variance_y == 0. {
+            1.
+        } else {
+            covariance * covariance / (variance_x * variance_y)
+        };
+        Some(Self {
+            slope,
+            intercept: mean_y - slope * mean_x,
+            r_squared,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RoundTripTracker {
+    pub config: RoundTripConfig,
+    /// whether the current shot has reached the cap
+    pub capped: bool,
+    pub samples: Vec<RoundTripSample>,
+    /// the number of shots that reach the cap
+    pub capped_shots: usize,
+    /// the number of defects matched by the greedy fallback
+    pub fallback_defects: usize,
+}
+
+impl RoundTripTracker {
+    pub fn new(config: RoundTripConfig) -> Self {
+        Self {
+            config,
+            capped: false,
+            samples: vec![],
+            capped_shots: 0,
+            fallback_defects: 0,
+        }
+    }
+
+    /// prepare for the next shot
+    pub fn clear(&mut self) {
+        self.capped = false;
+    }
+
+    pub fn reset_statistics(&mut self) {
+        self.samples.clear();
+        self.capped_shots = 0;
+        self.fallback_defects = 0;
+    }
+
+    /// called before every conflict query; returns whether the shot has reached the cap
+    pub fn check(&mut self, round_trips: usize) -> bool {
+        self.capped |= self.config.cap.is_some_and(|cap| round_trips >= cap);
+        self.capped
+    }
+
+    /// record the statistics of the shot, where `fallback_defects` are matched greedily after reaching the cap
+    pub fn finish(&mut self, defects: usize, round_trips: usize, fallback_defects: usize) {
+        if self.capped {
+            self.capped_shots += 1;
+            self.fallback_defects += fallback_defects;
+        }
+        self.samples.push(RoundTripSample { defects, round_trips });
+    }
+
+    /// the fit of the round trips against the number of defects
+    pub fn fit(&self) -> Option<LinearFit> {
+        let points: Vec<(f64, f64)> = self
+            .samples
+            .iter()
+            .map(|sample| (sample.defects as f64, sample.round_trips as f64))
+            .collect();
+        LinearFit::new(&points)
+    }
+
+    pub fn generate_report(&self) -> serde_json::Value {
+        // `defects -> (shots, average round trips, maximum round trips)`
+        let mut by_defects = BTreeMap::<usize, (usize, f64, usize)>::new();
+        for sample in self.samples.iter() {
+            let entry = by_defects.entry(sample.defects).or_default();
+            entry.0 += 1;
+            entry.1 += sample.round_trips as f64;
+            entry.2 = entry.2.max(sample.round_trips);
+        }
+        let by_defects: BTreeMap<usize, serde_json::Value> = by_defects
+            .into_iter()
+            .map(|(defects, (shots, total, max))| {
+                (
+                    defects,
+                    json!({ "shots": shots, "average": total / shots as f64, "max": max }),
+                )
+            })
+            .collect();
+        let shots = self.samples.len();
+        json!({
+            "cap": self.config.cap,
+            "shots": shots,
+            "capped_shots": self.capped_shots,
+            "fallback_defects": self.fallback_defects,
+            "average": self.samples.iter().map(|sample| sample.round_trips).sum::<usize>() as f64 / shots.max(1) as f64,
+            "max": self.samples.iter().map(|sample| sample.round_trips).max(),
+            "by_defects": by_defects,
+            "fit": self.fit(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwpm_solver::*;
+    use crate::resources::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::*;
+    use fusion_blossom::util::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn round_trips_linear_fit() {
+        // cargo test round_trips_linear_fit -- --nocapture
+        let fit = LinearFit::new(&[(0., 1.), (1., 3.), (2., 5.)]).unwrap();
+        assert!((fit.slope - 2.).abs() < 1e-9 && (fit.intercept - 1.).abs() < 1e-9);
+        assert!((fit.r_squared - 1.).abs() < 1e-9);
+        assert_eq!(LinearFit::new(&[(1., 1.), (1., 2.)]), None);
+        assert_eq!(LinearFit::new(&[]), None);
+    }
+
+    /// the round trips grow with the defects, and a cap still gives a valid correction
+    #[test]
+    fn round_trips_cap() {
+        // cargo test round_trips_cap -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(11, 0.05, 500);
+        let initializer = code.get_initializer();
+        let graph = MicroBlossomSingle::new_code(&code);
+        let mut solver = SolverEmbeddedComb::new(graph.clone(), json!({ "round_trips": {} }));
+        let mut capped_solver = SolverEmbeddedComb::new(graph, json!({ "round_trips": { "cap": 4 } }));
+        for seed in 0..100 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            solver.solve(&syndrome_pattern);
+            capped_solver.solve(&syndrome_pattern);
+            let subgraph = capped_solver.subgraph();
+            let defects: BTreeSet<VertexIndex> = syndrome_pattern.defect_vertices.iter().cloned().collect();
+            assert_eq!(initializer.syndrome_of(&subgraph), defects);
+            assert!(capped_solver.sum_dual_variables() >= solver.sum_dual_variables());
+            solver.clear();
+            capped_solver.clear();
+        }
+        let tracker = solver.round_trips.as_ref().unwrap();
+        println!("{}", tracker.generate_report());
+        assert_eq!(tracker.samples.len(), 100);
+        assert_eq!(tracker.capped_shots, 0);
+        assert!(tracker.fit().unwrap().slope > 0.);
+        let capped_tracker = capped_solver.round_trips.as_ref().unwrap();
+        assert!(capped_tracker.capped_shots > 0);
+        // the cap is only exceeded by the round trips of the last obstacle
+        for (sample, capped_sample) in tracker.samples.iter().zip(capped_tracker.samples.iter()) {
+            assert!(capped_sample.round_trips <= sample.round_trips);
+        }
+    }
+}
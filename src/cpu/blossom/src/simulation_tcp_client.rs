@@ -161,6 +161,12 @@ impl SimulationTcpClient {
     pub fn link_wall_time(&self) -> Duration {
         self.link.lock().unwrap().wall_time
     }
+
+    /// a handle to the connection after the handshake, e.g., to pipeline the requests asynchronously; the lines sent
+    /// through it are not counted in the link wall time
+    pub fn try_clone_stream(&self) -> std::io::Result<TcpStream> {
+        self.link.lock().unwrap().writer.get_ref().try_clone()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
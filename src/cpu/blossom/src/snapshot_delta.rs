@@ -0,0 +1,148 @@
+//! Delta Snapshots
+//!
+//! A snapshot of the combinatorial dual module serializes every vertex and edge, which adds up to huge visualizer
+//! files for large codes (d >= 11), although an instruction usually changes only a few of them. In the delta mode,
+//! only the first snapshot after a reset is complete; every following snapshot has `"delta": true` and replaces the
+//! unchanged entries of the arrays in [`DELTA_ARRAYS`] by `null`, which is also how the visualizer skips the entries
+//! that a snapshot does not provide. The complete snapshot is recovered by [`apply_snapshot_delta`] on the previous
+//! complete one.
+//!
+//! Enable it with `{ "dual": { "delta_snapshot": true } }`.
+//!
+
+use crate::dual_module_comb::*;
+use serde_json::json;
+use std::cell::RefCell;
+
+/// the arrays of a snapshot that are compressed in the delta mode
+pub const DELTA_ARRAYS: &[&str] = &["vertices", "edges", "vertices_comb", "edges_comb"];
+
+/// the delta from the complete snapshot `last` to the complete snapshot `current`
+pub fn snapshot_delta(last: &serde_json::Value, current: &serde_json::Value) -> serde_json::Value {
+    let mut delta = current.clone();
+    for &key in DELTA_ARRAYS.iter() {
+        let (Some(last), Some(entries)) = (last[key].as_array(), delta[key].as_array_mut()) else {
+            continue;
+        };
+        if last.len() != entries.len() {
+            continue; // the complete array is kept when the graph changes
+        }
+        for (entry, last) in entries.iter_mut().zip(last.iter()) {
+            if entry == last {
+                *entry = serde_json::Value::Null;
+            }
+        }
+    }
+    delta["delta"] = json!(true);
+    delta
+}
+
+/// recover the complete snapshot in place from the previous complete snapshot `base`
+pub fn apply_snapshot_delta(base: &mut serde_json::Value, delta: &serde_json::Value) {
+    if !delta["delta"].as_bool().unwrap_or(false) {
+        *base = delta.clone();
+        return;
+    }
+    let mut complete = delta.clone();
+    complete.as_object_mut().unwrap().remove("delta");
+    for &key in DELTA_ARRAYS.iter() {
+        let (Some(base), Some(entries)) = (base[key].as_array(), complete[key].as_array_mut()) else {
+            continue;
+        };
+        for (entry, base) in entries.iter_mut().zip(base.iter()) {
+            if entry.is_null() {
+                *entry = base.clone();
+            }
+        }
+    }
+    *base = complete;
+}
+
+/// remembers the last complete snapshot, which is taken by `&self` in [`fusion_blossom::visualize::FusionVisualizer`]
+#[derive(Debug, Default)]
+pub struct DeltaSnapshotter {
+    /// whether the last snapshot is abbreviated, and its complete value
+    last: RefCell<Option<(bool, serde_json::Value)>>,
+}
+
+impl DeltaSnapshotter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the next snapshot to emit given the complete one; the first snapshot after a reset is complete
+    pub fn emit(&self, abbrev: bool, complete: serde_json::Value) -> serde_json::Value {
+        let mut last = self.last.borrow_mut();
+        let value = match last.as_ref() {
+            Some((last_abbrev, last_value)) if *last_abbrev == abbrev => snapshot_delta(last_value, &complete),
+            _ => complete.clone(),
+        };
+        *last = Some((abbrev, complete));
+        value
+    }
+
+    pub fn clear(&mut self) {
+        *self.last.get_mut() = None;
+    }
+}
+
+impl CombObserver for DeltaSnapshotter {
+    fn name(&self) -> &'static str {
+        "delta_snapshot"
+    }
+    fn emit_snapshot(&self, abbrev: bool, snapshot: serde_json::Value) -> serde_json::Value {
+        self.emit(abbrev, snapshot)
+    }
+    fn clear(&mut self) {
+        DeltaSnapshotter::clear(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::visualize::*;
+    use micro_blossom_nostd::dual_driver_tracked::*;
+    use micro_blossom_nostd::dual_module_stackless::*;
+    use micro_blossom_nostd::util::*;
+
+    /// the deltas reconstruct the complete snapshots of the same sequence of instructions
+    #[test]
+    fn snapshot_delta_reconstruct() {
+        // cargo test snapshot_delta_reconstruct -- --nocapture
+        let code = CodeCapacityPlanarCode::new(11, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let config: DualCombConfig = serde_json::from_value(json!({ "delta_snapshot": true })).unwrap();
+        let mut delta_driver = DualModuleCombDriver::new(graph.clone(), config);
+        let mut driver = DualModuleCombDriver::new(graph, DualCombConfig::default());
+        let mut base = serde_json::Value::Null;
+        let (mut complete_size, mut delta_size) = (0, 0);
+        for step in 0..4 {
+            for driver in [&mut delta_driver, &mut driver] {
+                match step {
+                    0 => {}
+                    1 => driver.add_defect(ni!(30), ni!(0)),
+                    2 => driver.add_defect(ni!(31), ni!(1)),
+                    _ => {
+                        driver.find_conflict(CompactWeight::MAX);
+                    }
+                }
+            }
+            let delta = delta_driver.snapshot(true);
+            assert_eq!(delta["delta"].as_bool().unwrap_or(false), step > 0);
+            apply_snapshot_delta(&mut base, &delta);
+            let complete = driver.snapshot(true);
+            assert_eq!(base, complete);
+            if step > 0 {
+                complete_size += complete.to_string().len();
+                delta_size += delta.to_string().len();
+            }
+        }
+        assert!(delta_size * 2 < complete_size, "{delta_size} vs {complete_size}");
+        // the first snapshot after a reset is complete again
+        delta_driver.reset();
+        assert!(delta_driver.snapshot(true).get("delta").is_none());
+    }
+}
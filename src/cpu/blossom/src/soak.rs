@@ -0,0 +1,533 @@
+//! Soak
+//!
+//! Before the decoder is deployed in a live experiment, it must decode continuously for hours without slowing down,
+//! losing accuracy or leaking memory. `micro-blossom soak` decodes the shots of a [`SyndromeSource`] on the chosen
+//! backend for `--duration` seconds, split into windows of `window` seconds. Every `verify_every`-th shot is verified
+//! against the serial solver (see [`BoundedVerifier`]), and every window records the latency distribution, the
+//! verification failures and the resident memory of the process at its end.
+//!
+//! The first `baseline_windows` windows are the baseline, which also absorbs the warm-up allocations. The endurance
+//! report raises an alert for every later window whose median or tail latency drifts above the baseline by more than
+//! `latency_drift`, for every window with verification failures, and when the resident memory after the baseline
+//! grows faster than `memory_growth` bytes per hour (a least-squares fit over the windows). The report is rewritten
+//! at the end of every window, so a run that is killed or crashes still leaves the report up to its last window.
+//!
+//! ```bash
+//! micro-blossom soak 9 0.001 -n 9 -c phenomenological-planar-code --duration 14400 --report soak.json
+//! ```
+//!
+
+use crate::cli::{CodeParameters, PrimalDualType};
+use crate::mwpm_solver::*;
+use crate::syndrome_source::*;
+use crate::verifier::*;
+use clap::{Parser, ValueEnum};
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::util::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SoakConfig {
+    /// the length of a window in seconds
+    #[serde(default = "soak_config_default::window")]
+    pub window: f64,
+    /// verify one shot out of this many against the serial solver
+    #[serde(default = "soak_config_default::verify_every")]
+    pub verify_every: usize,
+    /// the number of windows at the beginning that form the baseline
+    #[serde(default = "soak_config_default::baseline_windows")]
+    pub baseline_windows: usize,
+    /// the tolerated relative increase of the p50 and p99 latency over the baseline
+    #[serde(default = "soak_config_default::latency_drift")]
+    pub latency_drift: f64,
+    /// the tolerated growth of the resident memory in bytes per hour
+    #[serde(default = "soak_config_default::memory_growth")]
+    pub memory_growth: f64,
+}
+
+impl Default for SoakConfig {
+    fn default() -> Self {
+        serde_json::from_value(json!({})).unwrap()
+    }
+}
+
+pub mod soak_config_default {
+    pub fn window() -> f64 {
+        60.
+    }
+    pub fn verify_every() -> usize {
+        100
+    }
+    pub fn baseline_windows() -> usize {
+        3
+    }
+    pub fn latency_drift() -> f64 {
+        0.2
+    }
+    pub fn memory_growth() -> f64 {
+        16. * 1024. * 1024.
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SoakWindow {
+    /// the time in seconds since the beginning of the soak
+    pub begin: f64,
+    pub end: f64,
+    pub shots: usize,
+    /// the number of shots verified against the serial solver
+    pub verified: usize,
+    /// the indices of the verified shots that are not accepted
+    pub failed_shots: Vec<usize>,
+    /// the sum of the excess weight among the accepted suboptimal shots
+    pub accepted_excess: Weight,
+    /// decoding latency in seconds, `None` if the window has no shot
+    pub latency_mean: Option<f64>,
+    pub latency_p50: Option<f64>,
+    pub latency_p99: Option<f64>,
+    pub latency_max: Option<f64>,
+    /// the resident memory of the process in bytes at the end of the window
+    pub resident_memory: Option<usize>,
+}
+
+impl SoakWindow {
+    /// the fraction of the verified shots that are not accepted
+    pub fn failure_rate(&self) -> f64 {
+        if self.verified == 0 {
+            return 0.;
+        }
+        self.failed_shots.len() as f64 / self.verified as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SoakBaseline {
+    /// the mean of the per-window latency percentiles over the baseline windows
+    pub latency_p50: f64,
+    pub latency_p99: f64,
+    pub resident_memory: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "alert", rename_all = "snake_case")]
+pub enum SoakAlert {
+    /// the latency percentile of a window is above the baseline by more than `latency_drift`
+    LatencyDrift {
+        window: usize,
+        percentile: String,
+        /// the latency of the window divided by the baseline
+        ratio: f64,
+    },
+    VerificationFailure {
+        window: usize,
+        failed_shots: Vec<usize>,
+    },
+    /// the resident memory after the baseline grows faster than `memory_growth`
+    MemoryGrowth {
+        /// bytes per hour
+        rate: f64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SoakReport {
+    pub config: SoakConfig,
+    /// the parameters of the run, e.g., the code and the backend
+    pub parameters: serde_json::Value,
+    pub shots: usize,
+    pub elapsed: f64,
+    pub baseline: Option<SoakBaseline>,
+    /// the least-squares growth of the resident memory after the baseline in bytes per hour
+    pub memory_growth_rate: Option<f64>,
+    pub alerts: Vec<SoakAlert>,
+    pub windows: Vec<SoakWindow>,
+}
+
+impl SoakReport {
+    pub fn passed(&self) -> bool {
+        self.alerts.is_empty()
+    }
+
+    pub fn print(&self) {
+        let window_num = self.windows.len();
+        println!(
+            "soak of {} shots in {:.1}s over {window_num} windows: {}",
+            self.shots,
+            self.elapsed,
+            if self.passed() { "passed" } else { "failed" }
+        );
+        if let Some(baseline) = self.baseline.as_ref() {
+            println!(
+                "baseline latency p50 {:.3e}s p99 {:.3e}s",
+                baseline.latency_p50, baseline.latency_p99
+            );
+        }
+        if let Some(rate) = self.memory_growth_rate {
+            println!("resident memory growth {rate:.0} bytes per hour");
+        }
+        for alert in self.alerts.iter() {
+            println!("[alert] {}", serde_json::to_string(alert).unwrap());
+        }
+    }
+}
+
+/// the resident memory of this process in bytes, only available on Linux
+pub fn resident_memory() -> Option<usize> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: usize = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    (page_size > 0).then(|| pages * page_size as usize)
+}
+
+/// accumulates the shots into windows and detects the drift against the baseline windows
+pub struct SoakMonitor {
+    pub config: SoakConfig,
+    pub windows: Vec<SoakWindow>,
+    /// the window being accumulated
+    current: SoakWindow,
+    latencies: Vec<f64>,
+}
+
+impl SoakMonitor {
+    pub fn new(config: SoakConfig) -> Self {
+        assert!(config.window > 0., "the window must be positive");
+        assert!(config.verify_every > 0, "verify_every must be positive");
+        Self {
+            config,
+            windows: vec![],
+            current: SoakWindow::default(),
+            latencies: vec![],
+        }
+    }
+
+    /// whether the shot should be verified against the serial solver
+    pub fn should_verify(&self, shot: usize) -> bool {
+        shot % self.config.verify_every == 0
+    }
+
+    pub fn record(&mut self, shot: usize, latency: f64, verdict: Option<Verdict>) {
+        self.current.shots += 1;
+        self.latencies.push(latency);
+        let Some(verdict) = verdict else {
+            return;
+        };
+        self.current.verified += 1;
+        match verdict {
+            Verdict::Optimal => {}
+            Verdict::Suboptimal { excess } => self.current.accepted_excess += excess,
+            _ => self.current.failed_shots.push(shot),
+        }
+    }
+
+    /// whether the current window has any shot
+    pub fn has_pending_shots(&self) -> bool {
+        self.current.shots > 0
+    }
+
+    /// close the current window at `time` seconds since the beginning and start the next one
+    pub fn close_window(&mut self, time: f64, resident_memory: Option<usize>) -> &SoakWindow {
+        let mut latencies = std::mem::take(&mut self.latencies);
+        latencies.sort_by(f64::total_cmp);
+        let percentile = |ratio: f64| -> Option<f64> {
+            if latencies.is_empty() {
+                return None;
+            }
+            Some(latencies[((latencies.len() - 1) as f64 * ratio).round() as usize])
+        };
+        let mut window = std::mem::take(&mut self.current);
+        window.end = time;
+        window.latency_mean = (!latencies.is_empty()).then(|| latencies.iter().sum::<f64>() / latencies.len() as f64);
+        window.latency_p50 = percentile(0.5);
+        window.latency_p99 = percentile(0.99);
+        window.latency_max = latencies.last().cloned();
+        window.resident_memory = resident_memory;
+        self.current.begin = time;
+        self.windows.push(window);
+        self.windows.last().unwrap()
+    }
+
+    /// `None` until all the baseline windows are closed, or if none of them has a shot
+    pub fn baseline(&self) -> Option<SoakBaseline> {
+        if self.windows.len() < self.config.baseline_windows.max(1) {
+            return None;
+        }
+        let windows = &self.windows[..self.config.baseline_windows.max(1)];
+        let mean = |latency: fn(&SoakWindow) -> Option<f64>| -> Option<f64> {
+            let latencies: Vec<f64> = windows.iter().filter_map(latency).collect();
+            (!latencies.is_empty()).then(|| latencies.iter().sum::<f64>() / latencies.len() as f64)
+        };
+        Some(SoakBaseline {
+            latency_p50: mean(|window| window.latency_p50)?,
+            latency_p99: mean(|window| window.latency_p99)?,
+            resident_memory: windows.last().unwrap().resident_memory,
+        })
+    }
+
+    /// the slope of the resident memory over the windows after the baseline, in bytes per hour
+    pub fn memory_growth_rate(&self) -> Option<f64> {
+        let points: Vec<(f64, f64)> = (self.windows.iter().skip(self.config.baseline_windows))
+            .filter_map(|window| Some((window.end / 3600., window.resident_memory? as f64)))
+            .collect();
+        if points.len() < 2 {
+            return None;
+        }
+        let count = points.len() as f64;
+        let mean_time = points.iter().map(|(time, _)| time).sum::<f64>() / count;
+        let mean_memory = points.iter().map(|(_, memory)| memory).sum::<f64>() / count;
+        let covariance: f64 = (points.iter())
+            .map(|(time, memory)| (time - mean_time) * (memory - mean_memory))
+            .sum();
+        let variance: f64 = points.iter().map(|(time, _)| (time - mean_time).powi(2)).sum();
+        (variance > 0.).then(|| covariance / variance)
+    }
+
+    pub fn alerts(&self) -> Vec<SoakAlert> {
+        let mut alerts = vec![];
+        let baseline = self.baseline();
+        for (index, window) in self.windows.iter().enumerate() {
+            if !window.failed_shots.is_empty() {
+                alerts.push(SoakAlert::VerificationFailure {
+                    window: index,
+                    failed_shots: window.failed_shots.clone(),
+                });
+            }
+            let Some(baseline) = baseline.filter(|_| index >= self.config.baseline_windows) else {
+                continue;
+            };
+            for (percentile, latency, baseline_latency) in [
+                ("p50", window.latency_p50, baseline.latency_p50),
+                ("p99", window.latency_p99, baseline.latency_p99),
+            ] {
+                let Some(latency) = latency else {
+                    continue;
+                };
+                let ratio = latency / baseline_latency;
+                if ratio > 1. + self.config.latency_drift {
+                    alerts.push(SoakAlert::LatencyDrift {
+                        window: index,
+                        percentile: percentile.to_string(),
+                        ratio,
+                    });
+                }
+            }
+        }
+        if let Some(rate) = self.memory_growth_rate() {
+            if rate > self.config.memory_growth {
+                alerts.push(SoakAlert::MemoryGrowth { rate });
+            }
+        }
+        alerts
+    }
+
+    pub fn generate_report(&self, parameters: serde_json::Value) -> SoakReport {
+        SoakReport {
+            config: self.config.clone(),
+            parameters,
+            shots: self.windows.iter().map(|window| window.shots).sum(),
+            elapsed: self.windows.last().map_or(0., |window| window.end),
+            baseline: self.baseline(),
+            memory_growth_rate: self.memory_growth_rate(),
+            alerts: self.alerts(),
+            windows: self.windows.clone(),
+        }
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct SoakParameters {
+    #[clap(flatten)]
+    code: CodeParameters,
+    /// select the combination of primal and dual module
+    #[clap(short = 'p', long, value_enum, default_value_t = PrimalDualType::EmbeddedComb)]
+    primal_dual_type: PrimalDualType,
+    /// the configuration of primal and dual module
+    #[clap(long, default_value_t = ("{}").to_string())]
+    primal_dual_config: String,
+    /// the tolerated suboptimality, see [`VerifierConfig`]; by default the correction must be minimum-weight
+    #[clap(long, default_value_t = ("{}").to_string())]
+    verifier_config: String,
+    /// the duration of the soak in seconds
+    #[clap(long, default_value_t = 3600.)]
+    duration: f64,
+    /// stop after this many shots even if the duration is not reached
+    #[clap(long)]
+    max_shots: Option<usize>,
+    /// the windows, the verification sampling and the drift thresholds, see [`SoakConfig`]
+    #[clap(long, default_value_t = ("{}").to_string())]
+    soak_config: String,
+    /// the endurance report output file path, rewritten at the end of every window
+    #[clap(long)]
+    report: Option<String>,
+    #[clap(flatten)]
+    source: SyndromeSourceParameters,
+}
+
+impl SoakParameters {
+    pub fn run(&self) -> SoakReport {
+        assert!(
+            !matches!(
+                self.primal_dual_type,
+                PrimalDualType::Serial | PrimalDualType::ErrorPatternLogger
+            ),
+            "the soak is for the solvers in this crate"
+        );
+        let primal_dual_config: serde_json::Value = serde_json::from_str(&self.primal_dual_config).unwrap();
+        let verifier_config: VerifierConfig = serde_json::from_str(&self.verifier_config).unwrap();
+        let soak_config: SoakConfig = serde_json::from_str(&self.soak_config).unwrap();
+        let code = self.code.build();
+        let initializer = code.get_initializer();
+        let positions = code.get_positions();
+        let mut solver = self.primal_dual_type.build(&initializer, &positions, primal_dual_config);
+        let mut verifier = BoundedVerifier::new(&initializer, verifier_config);
+        let mut source = self.source.build(code);
+        let parameters = json!({
+            "code_type": self.code.code_type.to_possible_value().unwrap().get_name(),
+            "d": self.code.d,
+            "p": self.code.p,
+            "noisy_measurements": self.code.noisy_measurements,
+            "primal_dual_type": self.primal_dual_type,
+            "primal_dual_config": self.primal_dual_config,
+            "verifier_config": verifier.config,
+            "duration": self.duration,
+        });
+        let mut monitor = SoakMonitor::new(soak_config);
+        let begin = Instant::now();
+        let mut window_end = monitor.config.window;
+        let mut shot = 0;
+        loop {
+            let elapsed = begin.elapsed().as_secs_f64();
+            if elapsed >= window_end {
+                self.close_window(&mut monitor, elapsed, &parameters);
+                // a shot longer than a window does not leave empty windows behind
+                while window_end <= elapsed {
+                    window_end += monitor.config.window;
+                }
+            }
+            if elapsed >= self.duration || self.max_shots.is_some_and(|max_shots| shot >= max_shots) {
+                break;
+            }
+            let Some(syndrome_pattern) = source.next_syndrome_pattern() else {
+                break;
+            };
+            let shot_begin = Instant::now();
+            solver.solve(&syndrome_pattern);
+            solver.subgraph();
+            let latency = shot_begin.elapsed().as_secs_f64();
+            // reading back the dual variables and verifying is not part of the latency
+            let verdict = (monitor.should_verify(shot)).then(|| verifier.verify(&syndrome_pattern, &solver.result()));
+            monitor.record(shot, latency, verdict);
+            solver.clear();
+            shot += 1;
+        }
+        if monitor.has_pending_shots() {
+            self.close_window(&mut monitor, begin.elapsed().as_secs_f64(), &parameters);
+        }
+        let report = monitor.generate_report(parameters);
+        report.print();
+        report
+    }
+
+    fn close_window(&self, monitor: &mut SoakMonitor, elapsed: f64, parameters: &serde_json::Value) {
+        let window = monitor.close_window(elapsed, resident_memory());
+        println!("{}", serde_json::to_string(window).unwrap());
+        if let Some(report) = self.report.as_ref() {
+            let report_value = monitor.generate_report(parameters.clone());
+            std::fs::write(report, serde_json::to_string_pretty(&report_value).unwrap()).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor_of(latencies: &[f64], memories: &[usize]) -> SoakMonitor {
+        let mut monitor = SoakMonitor::new(SoakConfig::default());
+        for (index, (&latency, &memory)) in latencies.iter().zip(memories.iter()).enumerate() {
+            for shot in 0..100 {
+                monitor.record(index * 100 + shot, latency, Some(Verdict::Optimal));
+            }
+            monitor.close_window((index + 1) as f64 * 60., Some(memory));
+        }
+        monitor
+    }
+
+    #[test]
+    fn soak_monitor_drift() {
+        // cargo test soak_monitor_drift -- --nocapture
+        let monitor = monitor_of(&[1e-6, 1e-6, 1e-6, 1.1e-6, 1e-6], &[1 << 20; 5]);
+        assert_eq!(monitor.windows.len(), 5);
+        assert_eq!(monitor.windows[0].shots, 100);
+        assert_eq!(monitor.windows[0].verified, 100);
+        assert_eq!(monitor.windows[1].begin, 60.);
+        assert_eq!(monitor.baseline().unwrap().latency_p50, 1e-6);
+        assert_eq!(monitor.memory_growth_rate(), Some(0.));
+        assert!(monitor.alerts().is_empty());
+        // the latency drifts in the last window
+        let monitor = monitor_of(&[1e-6, 1e-6, 1e-6, 1e-6, 1.5e-6], &[1 << 20; 5]);
+        let alerts = monitor.alerts();
+        println!("{}", serde_json::to_string(&alerts).unwrap());
+        assert_eq!(alerts.len(), 2);
+        assert!(matches!(alerts[0], SoakAlert::LatencyDrift { window: 4, .. }));
+        // the memory grows by 1MB per window after the baseline, i.e., 60MB per hour
+        let memories: Vec<usize> = (0..6).map(|index| (1 << 20) * (10 + index)).collect();
+        let monitor = monitor_of(&[1e-6; 6], &memories);
+        let rate = monitor.memory_growth_rate().unwrap();
+        assert!((rate - 60. * (1 << 20) as f64).abs() < 1.);
+        assert_eq!(monitor.alerts(), vec![SoakAlert::MemoryGrowth { rate }]);
+    }
+
+    #[test]
+    fn soak_monitor_failure() {
+        // cargo test soak_monitor_failure -- --nocapture
+        let mut monitor = SoakMonitor::new(SoakConfig::default());
+        assert!(monitor.should_verify(0) && !monitor.should_verify(1) && monitor.should_verify(100));
+        monitor.record(0, 1e-6, Some(Verdict::Suboptimal { excess: 2 }));
+        monitor.record(1, 1e-6, None);
+        monitor.record(2, 1e-6, Some(Verdict::InvalidCorrection));
+        assert!(monitor.baseline().is_none());
+        let window = monitor.close_window(1., None);
+        assert_eq!((window.shots, window.verified, window.accepted_excess), (3, 2, 2));
+        assert_eq!(window.failure_rate(), 0.5);
+        assert!(!monitor.has_pending_shots());
+        let report = monitor.generate_report(json!({}));
+        assert!(!report.passed());
+        assert_eq!(
+            report.alerts,
+            vec![SoakAlert::VerificationFailure {
+                window: 0,
+                failed_shots: vec![2]
+            }]
+        );
+    }
+
+    #[test]
+    fn soak_short_run() {
+        // cargo test soak_short_run -- --nocapture
+        let parameters = SoakParameters::parse_from([
+            "soak",
+            "5",
+            "0.03",
+            "-n",
+            "3",
+            "-c",
+            "phenomenological-planar-code",
+            "--duration",
+            "60",
+            "--max-shots",
+            "200",
+            "--soak-config",
+            r#"{"verify_every":10}"#,
+        ]);
+        let report = parameters.run();
+        assert_eq!(report.shots, 200);
+        assert_eq!(report.windows.iter().map(|window| window.verified).sum::<usize>(), 20);
+        assert!(report.passed());
+        if cfg!(target_os = "linux") {
+            assert!(report.windows.iter().all(|window| window.resident_memory.is_some()));
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Stim Samples
+//!
+//! Read the detection events sampled by Stim (`stim sample_dets` or `stim detect`) in the `b8` and `dets` formats, so
+//! that the shots of a circuit simulated at scale are decoded directly, e.g.,
+//! `micro-blossom decode-stim samples.b8 graph.json --format b8 --num-detectors 120`.
+//!
+//! - `b8`: every shot is packed into `ceil(bits / 8)` bytes, the bit `k` being the bit `k % 8` (LSB first) of the
+//!   byte `k / 8`; the observables follow the detectors when sampled with `--append_observables`
+//! - `dets`: every shot is a line starting with `shot`, followed by the fired detectors `D<k>` and the flipped
+//!   observables `L<k>`
+//!
+//! The detector `k` is vertex `k` of the decoding graph unless a detector mapping is given: a JSON array whose entry
+//! `k` is the vertex of detector `k`, or `null` for a detector that is not in the graph (e.g., the detectors of the
+//! other basis).
+//!
+
+use crate::cli::PrimalDualType;
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use crate::syndrome_source::*;
+use clap::{Parser, ValueEnum};
+use fusion_blossom::mwpm_solver::PrimalDualSolver;
+use fusion_blossom::util::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum StimSampleFormat {
+    B8,
+    Dets,
+}
+
+/// the detection events of a single shot
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StimShot {
+    pub detectors: Vec<usize>,
+    pub observables: Vec<usize>,
+}
+
+fn invalid_sample(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// read the `b8` samples of `num_detectors` detectors followed by `num_observables` observables
+pub fn read_b8(mut reader: impl Read, num_detectors: usize, num_observables: usize) -> Result<Vec<StimShot>> {
+    let bits = num_detectors + num_observables;
+    assert!(bits > 0, "a shot has at least one bit");
+    let shot_bytes = bits.div_ceil(8);
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    if bytes.len() % shot_bytes != 0 {
+        let message = format!("{} bytes is not a multiple of the shot size {shot_bytes}", bytes.len());
+        return Err(invalid_sample(message));
+    }
+    let shots = bytes.chunks(shot_bytes).map(|shot| {
+        let mut stim_shot = StimShot::default();
+        for bit in (0..bits).filter(|bit| (shot[bit / 8] >> (bit % 8)) & 1 == 1) {
+            match bit.checked_sub(num_detectors) {
+                None => stim_shot.detectors.push(bit),
+                Some(observable) => stim_shot.observables.push(observable),
+            }
+        }
+        stim_shot
+    });
+    Ok(shots.collect())
+}
+
+/// read the `dets` samples, one shot per line
+pub fn read_dets(reader: impl BufRead) -> Result<Vec<StimShot>> {
+    let mut shots = vec![];
+    for (line_index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            None => continue,
+            Some("shot") => {}
+            Some(token) => {
+                return Err(invalid_sample(format!(
+                    "line {}: expect `shot`, found {token}",
+                    line_index + 1
+                )))
+            }
+        }
+        let mut shot = StimShot::default();
+        for token in tokens {
+            let index = |prefix| token.strip_prefix(prefix).and_then(|index: &str| index.parse::<usize>().ok());
+            match (index('D'), index('L')) {
+                (Some(detector), _) => shot.detectors.push(detector),
+                (_, Some(observable)) => shot.observables.push(observable),
+                _ => return Err(invalid_sample(format!("line {}: invalid token {token}", line_index + 1))),
+            }
+        }
+        shots.push(shot);
+    }
+    Ok(shots)
+}
+
+pub fn read_samples(
+    filename: &str,
+    format: StimSampleFormat,
+    num_detectors: usize,
+    num_observables: usize,
+) -> Result<Vec<StimShot>> {
+    let reader = BufReader::new(File::open(filename)?);
+    match format {
+        StimSampleFormat::B8 => read_b8(reader, num_detectors, num_observables),
+        StimSampleFormat::Dets => read_dets(reader),
+    }
+}
+
+/// the vertex of every detector, `None` for a detector not in the decoding graph
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectorMapping(pub Vec<Option<VertexIndex>>);
+
+impl DetectorMapping {
+    pub fn identity(num_detectors: usize) -> Self {
+        Self((0..num_detectors).map(Some).collect())
+    }
+
+    pub fn load(filename: &str) -> Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(filename)?))?)
+    }
+
+    /// the defect vertices of a shot, sorted
+    pub fn syndrome_pattern(&self, shot: &StimShot) -> Result<SyndromePattern> {
+        let mut defect_vertices = vec![];
+        for &detector in shot.detectors.iter() {
+            match self.0.get(detector) {
+                Some(vertex) => defect_vertices.extend(vertex),
+                None => return Err(invalid_sample(format!("detector {detector} is not in the mapping"))),
+            }
+        }
+        defect_vertices.sort_unstable();
+        Ok(SyndromePattern::new_vertices(defect_vertices))
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct DecodeStimParameters {
+    /// the samples generated by Stim
+    #[clap(value_parser)]
+    samples_file: String,
+    /// the decoding graph, e.g., generated by `micro-blossom parser --graph-file`
+    #[clap(value_parser)]
+    graph_file: String,
+    #[clap(long, value_enum, default_value_t = StimSampleFormat::B8)]
+    format: StimSampleFormat,
+    /// the number of detectors of a `b8` shot, by default the number of vertices in the mapping or the graph
+    #[clap(long)]
+    num_detectors: Option<usize>,
+    /// the number of observables appended to every `b8` shot
+    #[clap(long, default_value_t = 0)]
+    num_observables: usize,
+    /// the JSON array of the vertex of every detector, see [`crate::stim_samples`]
+    #[clap(long)]
+    detector_mapping: Option<String>,
+    /// select the combination of primal and dual module
+    #[clap(short = 'p', long, value_enum, default_value_t = PrimalDualType::EmbeddedComb)]
+    primal_dual_type: PrimalDualType,
+    /// the configuration of primal and dual module
+    #[clap(long, default_value_t = ("{}").to_string())]
+    primal_dual_config: String,
+}
+
+impl DecodeStimParameters {
+    pub fn run(&self) {
+        let graph: MicroBlossomSingle = serde_json::from_str(&std::fs::read_to_string(&self.graph_file).unwrap()).unwrap();
+        let mapping = match self.detector_mapping.as_ref() {
+            Some(filename) => DetectorMapping::load(filename).unwrap(),
+            None => DetectorMapping::identity(graph.vertex_num),
+        };
+        let num_detectors = self.num_detectors.unwrap_or(mapping.0.len());
+        let shots = read_samples(&self.samples_file, self.format, num_detectors, self.num_observables).unwrap();
+        let shot_num = shots.len();
+        let positions = graph.get_positions();
+        let mut solver = self.primal_dual_type.build(
+            &graph.get_initializer(),
+            &positions,
+            serde_json::from_str(&self.primal_dual_config).unwrap(),
+        );
+        let mut source = StimSource::new(shots, mapping, &positions);
+        let (mut defects, mut matching_weight, mut certified, mut observable_flips) = (0, 0, 0, 0);
+        while let Some(syndrome_pattern) = source.next_syndrome_pattern() {
+            defects += syndrome_pattern.defect_vertices.len();
+            solver.solve(&syndrome_pattern);
+            let result = solver.result();
+            matching_weight += result.matching_weight;
+            certified += result.certified as usize;
+            observable_flips += !source.observables.is_empty() as usize;
+            solver.clear();
+        }
+        let shot_count = shot_num.max(1) as f64;
+        println!(
+            "{}",
+            json!({
+                "shots": shot_num,
+                "average_defects": defects as f64 / shot_count,
+                "average_matching_weight": matching_weight as f64 / shot_count,
+                "certified": certified,
+                "observable_flips": observable_flips,
+            })
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn stim_samples_formats() {
+        // cargo test stim_samples_formats -- --nocapture
+        // 10 detectors and 1 observable in 2 bytes per shot
+        let b8 = [0b0000_0101, 0b0000_0110, 0, 0];
+        let shots = read_b8(&b8[..], 10, 1).unwrap();
+        assert_eq!(shots.len(), 2);
+        assert_eq!(
+            (shots[0].detectors.clone(), shots[0].observables.clone()),
+            (vec![0, 2, 9], vec![0])
+        );
+        assert_eq!(shots[1], StimShot::default());
+        assert!(read_b8(&b8[..3], 10, 1).is_err());
+        let dets = "shot D0 D2 D9 L0\nshot\n\n";
+        assert_eq!(read_dets(dets.as_bytes()).unwrap(), shots);
+        assert!(read_dets("shot D1 X3\n".as_bytes()).is_err());
+        assert!(read_dets("D1\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn stim_samples_mapping() {
+        // cargo test stim_samples_mapping -- --nocapture
+        let mapping: DetectorMapping = serde_json::from_value(json!([3, null, 1])).unwrap();
+        let shot = StimShot {
+            detectors: vec![0, 1, 2],
+            observables: vec![],
+        };
+        assert_eq!(mapping.syndrome_pattern(&shot).unwrap().defect_vertices, vec![1, 3]);
+        let shot = StimShot {
+            detectors: vec![3],
+            observables: vec![],
+        };
+        assert!(mapping.syndrome_pattern(&shot).is_err());
+    }
+
+    /// the shots written in `b8` decode to the same matching as the original syndrome patterns
+    #[test]
+    fn stim_samples_decode() {
+        // cargo test stim_samples_decode -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(5, 0.1, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let num_detectors = graph.vertex_num;
+        let mut solver = SolverEmbeddedComb::new(graph, json!({}));
+        let syndrome_patterns: Vec<SyndromePattern> = (0..20).map(|seed| code.generate_random_errors(seed)).collect();
+        let mut b8 = vec![];
+        for syndrome_pattern in syndrome_patterns.iter() {
+            let mut shot = vec![0u8; num_detectors.div_ceil(8)];
+            for &vertex in syndrome_pattern.defect_vertices.iter() {
+                shot[vertex / 8] |= 1 << (vertex % 8);
+            }
+            b8.extend(shot);
+        }
+        let shots = read_b8(b8.as_slice(), num_detectors, 0).unwrap();
+        let mapping = DetectorMapping::identity(num_detectors);
+        for (shot, syndrome_pattern) in shots.iter().zip(syndrome_patterns.iter()) {
+            let parsed = mapping.syndrome_pattern(shot).unwrap();
+            assert_eq!(parsed.defect_vertices, syndrome_pattern.defect_vertices);
+            solver.solve(&parsed);
+            assert!(solver.result().certified);
+            solver.clear();
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! Syndrome Sources
+//!
+//! The decoding subcommands take their shots from a [`SyndromeSource`], which gives the defect vertices of every shot
+//! grouped by measurement round, so that the same decoding loop runs on any of
+//!
+//! - [`RandomSource`]: random errors sampled on an example code, the seed of each shot being its index
+//! - [`ReplaySource`]: the shots of a syndrome file, e.g., generated by `--primal-dual-type error-pattern-logger`
+//! - [`StimSource`]: the detection events sampled by Stim, see [`crate::stim_samples`]
+//! - [`CaptureSource`]: the shots streamed by the hardware capture interface in the layout of the defects memory, i.e.,
+//!   every defect vertex as a little-endian `u32` followed by `u32::MAX` at the end of the shot; the `.defects` file
+//!   written by `micro-blossom parser` has the same layout and is replayed by opening it as the stream
+//!
+//! The measurement round of a vertex is the rank of its time coordinate among all the vertices of the graph.
+//!
+//! ```bash
+//! micro-blossom throughput 5 0.001 -n 5 -c phenomenological-planar-code --replay syndromes.txt
+//! micro-blossom gallery 5 0.001 -n 5 -c phenomenological-planar-code --capture 192.168.0.2:5000
+//! ```
+//!
+
+use crate::device_lock::*;
+use crate::stim_samples::*;
+use byteorder::{LittleEndian, ReadBytesExt};
+use clap::Args;
+use fusion_blossom::example_codes::{ErrorPatternReader, ExampleCode};
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::io::{BufReader, ErrorKind, Read};
+use std::net::TcpStream;
+
+pub trait SyndromeSource {
+    /// the defect vertices of the next shot grouped by measurement round, `None` once the source is exhausted
+    fn next_shot(&mut self) -> Option<Vec<Vec<VertexIndex>>>;
+
+    /// the defect vertices of the next shot regardless of the rounds, sorted
+    fn next_syndrome_pattern(&mut self) -> Option<SyndromePattern> {
+        let mut defect_vertices: Vec<VertexIndex> = self.next_shot()?.into_iter().flatten().collect();
+        defect_vertices.sort_unstable();
+        Some(SyndromePattern::new_vertices(defect_vertices))
+    }
+}
+
+/// the measurement round of every vertex, i.e., the rank of its time coordinate
+pub fn vertex_rounds(positions: &[VisualizePosition]) -> Vec<usize> {
+    let mut times: Vec<f64> = positions.iter().map(|position| position.t).collect();
+    times.sort_by(f64::total_cmp);
+    times.dedup();
+    (positions.iter())
+        .map(|position| times.partition_point(|&t| t < position.t))
+        .collect()
+}
+
+/// group the defect vertices by their measurement round, each round being sorted
+pub fn group_by_round(defect_vertices: &[VertexIndex], vertex_rounds: &[usize]) -> Vec<Vec<VertexIndex>> {
+    let round_num = vertex_rounds.iter().max().map_or(0, |&round| round + 1);
+    let mut rounds = vec![vec![]; round_num];
+    for &vertex in defect_vertices.iter() {
+        assert!(
+            vertex < vertex_rounds.len(),
+            "defect vertex {vertex} out of the {} vertices",
+            vertex_rounds.len()
+        );
+        rounds[vertex_rounds[vertex]].push(vertex);
+    }
+    for round in rounds.iter_mut() {
+        round.sort_unstable();
+    }
+    rounds
+}
+
+pub struct RandomSource {
+    pub code: Box<dyn ExampleCode>,
+    /// the seed of the next shot, which is also its index
+    pub seed: u64,
+    vertex_rounds: Vec<usize>,
+}
+
+impl RandomSource {
+    pub fn new(code: Box<dyn ExampleCode>) -> Self {
+        let vertex_rounds = vertex_rounds(&code.get_positions());
+        Self {
+            code,
+            seed: 0,
+            vertex_rounds,
+        }
+    }
+}
+
+impl SyndromeSource for RandomSource {
+    fn next_shot(&mut self) -> Option<Vec<Vec<VertexIndex>>> {
+        let syndrome_pattern = self.code.generate_random_errors(self.seed);
+        self.seed += 1;
+        Some(group_by_round(&syndrome_pattern.defect_vertices, &self.vertex_rounds))
+    }
+}
+
+pub struct ReplaySource {
+    /// the decoding graph recorded in the syndrome file
+    pub initializer: SolverInitializer,
+    pub positions: Vec<VisualizePosition>,
+    syndrome_patterns: VecDeque<SyndromePattern>,
+    vertex_rounds: Vec<usize>,
+}
+
+impl ReplaySource {
+    pub fn open(filename: &str) -> Self {
+        let reader = ErrorPatternReader::new(json!({ "filename": filename }));
+        let positions = reader.get_positions();
+        Self {
+            initializer: reader.get_initializer(),
+            vertex_rounds: vertex_rounds(&positions),
+            positions,
+            syndrome_patterns: reader.syndrome_patterns.into(),
+        }
+    }
+}
+
+impl SyndromeSource for ReplaySource {
+    fn next_shot(&mut self) -> Option<Vec<Vec<VertexIndex>>> {
+        let syndrome_pattern = self.syndrome_patterns.pop_front()?;
+        assert!(syndrome_pattern.erasures.is_empty(), "erasures are not replayed");
+        Some(group_by_round(&syndrome_pattern.defect_vertices, &self.vertex_rounds))
+    }
+}
+
+pub struct StimSource {
+    shots: VecDeque<StimShot>,
+    mapping: DetectorMapping,
+    vertex_rounds: Vec<usize>,
+    /// the observables flipped in the last shot
+    pub observables: Vec<usize>,
+}
+
+impl StimSource {
+    pub fn new(shots: Vec<StimShot>, mapping: DetectorMapping, positions: &[VisualizePosition]) -> Self {
+        Self {
+            shots: shots.into(),
+            mapping,
+            vertex_rounds: vertex_rounds(positions),
+            observables: vec![],
+        }
+    }
+}
+
+impl SyndromeSource for StimSource {
+    fn next_shot(&mut self) -> Option<Vec<Vec<VertexIndex>>> {
+        let shot = self.shots.pop_front()?;
+        let syndrome_pattern = (self.mapping.syndrome_pattern(&shot)).unwrap_or_else(|error| panic!("{error}"));
+        self.observables = shot.observables;
+        Some(group_by_round(&syndrome_pattern.defect_vertices, &self.vertex_rounds))
+    }
+}
+
+pub struct CaptureSource<R: Read> {
+    reader: R,
+    vertex_rounds: Vec<usize>,
+    /// the capture interface streams to a single process at a time, see [`DeviceLock`]
+    pub device_lock: Option<DeviceLock>,
+}
+
+impl CaptureSource<BufReader<TcpStream>> {
+    /// connect to the capture interface of the hardware, e.g., `192.168.0.2:5000`, after locking it
+    pub fn connect(address: &str, positions: &[VisualizePosition], force_take: bool) -> std::io::Result<Self> {
+        let device_lock = DeviceLock::acquire(&format!("capture-{address}"), vec![0], force_take)
+            .map_err(|error| std::io::Error::new(ErrorKind::Other, error))?;
+        let mut source = Self::new(BufReader::new(TcpStream::connect(address)?), positions);
+        source.device_lock = Some(device_lock);
+        Ok(source)
+    }
+}
+
+impl<R: Read> CaptureSource<R> {
+    pub fn new(reader: R, positions: &[VisualizePosition]) -> Self {
+        Self {
+            reader,
+            vertex_rounds: vertex_rounds(positions),
+            device_lock: None,
+        }
+    }
+
+    /// the defect vertices of the next shot, `None` if the stream ends before the shot
+    pub fn read_shot(&mut self) -> std::io::Result<Option<Vec<VertexIndex>>> {
+        let mut defect_vertices = vec![];
+        loop {
+            let value = match self.reader.read_u32::<LittleEndian>() {
+                Ok(value) => value,
+                Err(error) if error.kind() == ErrorKind::UnexpectedEof && defect_vertices.is_empty() => {
+                    return Ok(None);
+                }
+                Err(error) => return Err(error),
+            };
+            if value == u32::MAX {
+                return Ok(Some(defect_vertices));
+            }
+            defect_vertices.push(value as VertexIndex);
+        }
+    }
+}
+
+impl<R: Read> SyndromeSource for CaptureSource<R> {
+    fn next_shot(&mut self) -> Option<Vec<Vec<VertexIndex>>> {
+        let defect_vertices = (self.read_shot()).unwrap_or_else(|error| panic!("capture interrupted: {error}"))?;
+        Some(group_by_round(&defect_vertices, &self.vertex_rounds))
+    }
+}
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct SyndromeSourceParameters {
+    /// replay the shots of a syndrome file instead of sampling random errors, see [`crate::syndrome_source`]
+    #[clap(long)]
+    replay: Option<String>,
+    /// decode the shots streamed by the hardware capture interface at this address instead of sampling random errors
+    #[clap(long, conflicts_with = "replay")]
+    capture: Option<String>,
+    /// take the capture interface even if another process holds it; the stream restarts from a new shot
+    #[clap(long, requires = "capture")]
+    force_take: bool,
+}
+
+impl SyndromeSourceParameters {
+    /// sample random errors on the code unless another source is selected, which must be on the same graph
+    pub fn build(&self, code: Box<dyn ExampleCode>) -> Box<dyn SyndromeSource> {
+        if let Some(replay) = self.replay.as_ref() {
+            let source = ReplaySource::open(replay);
+            let initializer = code.get_initializer();
+            assert!(
+                source.initializer.vertex_num == initializer.vertex_num
+                    && source.initializer.weighted_edges == initializer.weighted_edges,
+                "the syndrome file {replay} is recorded on a different graph"
+            );
+            return Box::new(source);
+        }
+        if let Some(capture) = self.capture.as_ref() {
+            let source = CaptureSource::connect(capture, &code.get_positions(), self.force_take)
+                .unwrap_or_else(|error| panic!("cannot connect to {capture}: {error}"));
+            return Box::new(source);
+        }
+        Box::new(RandomSource::new(code))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn syndrome_source_rounds() {
+        // cargo test syndrome_source_rounds -- --nocapture
+        let code = PhenomenologicalPlanarCode::new(5, 4, 0.05, 500);
+        let positions = code.get_positions();
+        let rounds = vertex_rounds(&positions);
+        assert_eq!(rounds.iter().max(), Some(&4));
+        // the random source gives the same shots as sampling the code directly
+        let mut reference = PhenomenologicalPlanarCode::new(5, 4, 0.05, 500);
+        let mut source = RandomSource::new(Box::new(code));
+        for seed in 0..20 {
+            let shot = source.next_shot().unwrap();
+            assert_eq!(shot.len(), 5);
+            for (round, defect_vertices) in shot.iter().enumerate() {
+                assert!(defect_vertices.iter().all(|&vertex| rounds[vertex] == round));
+            }
+            let mut defect_vertices: Vec<VertexIndex> = shot.into_iter().flatten().collect();
+            defect_vertices.sort_unstable();
+            assert_eq!(defect_vertices, reference.generate_random_errors(seed).defect_vertices);
+        }
+    }
+
+    /// the capture stream has the same layout as the defects file
+    #[test]
+    fn syndrome_source_capture() {
+        // cargo test syndrome_source_capture -- --nocapture
+        let code = PhenomenologicalPlanarCode::new(3, 2, 0.05, 500);
+        let shots: Vec<Vec<VertexIndex>> = vec![vec![1, 9], vec![], vec![4]];
+        let mut stream: Vec<u8> = vec![];
+        for shot in shots.iter() {
+            for &vertex in shot.iter() {
+                stream.write_u32::<LittleEndian>(vertex as u32).unwrap();
+            }
+            stream.write_u32::<LittleEndian>(u32::MAX).unwrap();
+        }
+        let mut source = CaptureSource::new(stream.as_slice(), &code.get_positions());
+        for shot in shots.iter() {
+            assert_eq!(&source.next_syndrome_pattern().unwrap().defect_vertices, shot);
+        }
+        assert!(source.next_shot().is_none());
+        // a shot cut off by the end of the stream is an error
+        let mut source = CaptureSource::new(&stream[..6], &code.get_positions());
+        assert!(source.read_shot().is_err());
+    }
+}
@@ -0,0 +1,294 @@
+//! Throughput
+//!
+//! Offline reprocessing of recorded syndromes cares about the aggregate throughput (shots per second) rather than
+//! the latency of a single shot. A single shot leaves the dual core idle while the primal module decides on the next
+//! instruction, so independent shots are interleaved through the contexts of the same comb driver: every context
+//! holds one shot in flight, and the instructions are issued in a pipelined round-robin, one step per context, so
+//! that the primal work of one context overlaps with the dual work of the others. Once a context finishes its shot,
+//! it is cleared and immediately takes the next pending shot.
+//!
+//! Unlike [`crate::mwpm_solver::SolverInterleaved`], the contexts share the same edge weights and the shots do not
+//! need to proceed round by round, so layer fusion is not required.
+//!
+//! On a multi-core machine, the shots can further be split into contiguous chunks decoded by independent pipelined
+//! solvers on different threads. The decisions of every shot must be bit-identical regardless of the number of
+//! threads and contexts, otherwise a mismatch against the hardware cannot be reproduced: every shot is decoded from a
+//! cleared solver, the randomness of a shot only depends on the shot itself (see `defect_order_seed`), and the
+//! results are collected in the order of the shots rather than the order of completion.
+//!
+
+use crate::cli::CodeParameters;
+use crate::dual_module_comb::*;
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use crate::syndrome_source::*;
+use clap::{Parser, ValueEnum};
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::util::*;
+use rayon::prelude::*;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ThroughputStatistics {
+    pub shots: usize,
+    /// the total number of steps issued to all the contexts
+    pub steps: usize,
+    /// the wall-clock time in seconds, from loading the first shot to finishing the last one
+    pub elapsed: f64,
+}
+
+impl ThroughputStatistics {
+    /// shots per second
+    pub fn throughput(&self) -> f64 {
+        self.shots as f64 / self.elapsed
+    }
+}
+
+pub struct SolverPipelined<Dual: SolverTrackedDual> {
+    pub contexts: Vec<SolverEmbeddedBoxed<Dual>>,
+    pub statistics: ThroughputStatistics,
+}
+
+impl<Dual: SolverTrackedDual> SolverPipelined<Dual> {
+    /// every context decodes the same graph with the same `primal_dual_config`
+    pub fn new(graph: MicroBlossomSingle, context_num: usize, primal_dual_config: serde_json::Value) -> Self {
+        assert!(context_num > 0, "at least one context is required");
+        Self {
+            contexts: (0..context_num)
+                .map(|_| SolverEmbeddedBoxed::<Dual>::new(graph.clone(), primal_dual_config.clone()))
+                .collect(),
+            statistics: ThroughputStatistics::default(),
+        }
+    }
+
+    pub fn reset_profiler(&mut self) {
+        for context in self.contexts.iter_mut() {
+            context.reset_profiler();
+        }
+        self.statistics = ThroughputStatistics::default();
+    }
+
+    /// decode all the shots and return the subgraphs in the order of the shots
+    pub fn solve_batch(&mut self, syndrome_patterns: &[SyndromePattern]) -> Vec<Vec<EdgeIndex>> {
+        let mut subgraphs = vec![vec![]; syndrome_patterns.len()];
+        let mut pending: VecDeque<usize> = (0..syndrome_patterns.len()).collect();
+        // the shot in flight of each context
+        let mut in_flight: Vec<Option<usize>> = vec![None; self.contexts.len()];
+        let begin = Instant::now();
+        loop {
+            let mut busy = false;
+            for (context, shot) in self.contexts.iter_mut().zip(in_flight.iter_mut()) {
+                if shot.is_none() {
+                    *shot = pending.pop_front();
+                    if let Some(shot_index) = *shot {
+                        context.load_syndrome(&syndrome_patterns[shot_index]);
+                    }
+                }
+                let Some(shot_index) = *shot else {
+                    continue;
+                };
+                busy = true;
+                self.statistics.steps += 1;
+                if !context.step() {
+                    context.finish();
+                    subgraphs[shot_index] = context.subgraph();
+                    context.clear();
+                    self.statistics.shots += 1;
+                    *shot = None;
+                }
+            }
+            if !busy {
+                break;
+            }
+        }
+        self.statistics.elapsed += begin.elapsed().as_secs_f64();
+        subgraphs
+    }
+
+    pub fn generate_profiler_report(&self) -> serde_json::Value {
+        json!({
+            "contexts": self.contexts.len(),
+            "shots": self.statistics.shots,
+            "steps": self.statistics.steps,
+            "elapsed": self.statistics.elapsed,
+            "throughput": self.statistics.throughput(),
+        })
+    }
+}
+
+/// decode the shots on `threads` threads, each running a [`SolverPipelined`] of `context_num` contexts on a
+/// contiguous chunk of the shots; the subgraphs are in the order of the shots and the statistics are summed in the
+/// order of the chunks, so the result is identical to a single thread
+pub fn solve_batch_threaded<Dual: SolverTrackedDual>(
+    graph: &MicroBlossomSingle,
+    context_num: usize,
+    primal_dual_config: &serde_json::Value,
+    syndrome_patterns: &[SyndromePattern],
+    threads: usize,
+) -> (Vec<Vec<EdgeIndex>>, ThroughputStatistics) {
+    assert!(threads > 0, "at least one thread is required");
+    let chunk_size = syndrome_patterns.len().div_ceil(threads).max(1);
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+    let begin = Instant::now();
+    let chunks: Vec<(Vec<Vec<EdgeIndex>>, ThroughputStatistics)> = pool.install(|| {
+        syndrome_patterns
+            .par_chunks(chunk_size)
+            .map(|chunk| {
+                let mut solver = SolverPipelined::<Dual>::new(graph.clone(), context_num, primal_dual_config.clone());
+                let subgraphs = solver.solve_batch(chunk);
+                (subgraphs, solver.statistics)
+            })
+            .collect()
+    });
+    let mut subgraphs = Vec::with_capacity(syndrome_patterns.len());
+    let mut statistics = ThroughputStatistics::default();
+    for (mut chunk_subgraphs, chunk_statistics) in chunks {
+        subgraphs.append(&mut chunk_subgraphs);
+        statistics.shots += chunk_statistics.shots;
+        statistics.steps += chunk_statistics.steps;
+    }
+    statistics.elapsed = begin.elapsed().as_secs_f64();
+    (subgraphs, statistics)
+}
+
+pub type SolverPipelinedComb = SolverPipelined<DualModuleCombDriver>;
+
+#[derive(Parser, Clone)]
+pub struct ThroughputParameters {
+    #[clap(flatten)]
+    code: CodeParameters,
+    /// the number of shots to run; the seed of each shot is its index
+    #[clap(short = 'r', long, default_value_t = 1000)]
+    total_rounds: usize,
+    /// the number of shots in flight at the same time
+    #[clap(long, default_value_t = 4)]
+    contexts: usize,
+    /// the number of threads, each decoding a contiguous chunk of the shots with its own contexts
+    #[clap(long, default_value_t = 1)]
+    threads: usize,
+    /// the configuration of primal and dual module
+    #[clap(long, default_value_t = ("{}").to_string())]
+    primal_dual_config: String,
+    /// check that every correction explains the defects
+    #[clap(long, action)]
+    verify: bool,
+    /// the throughput report output file path
+    #[clap(long)]
+    profiler_output: Option<String>,
+    #[clap(flatten)]
+    source: SyndromeSourceParameters,
+}
+
+impl ThroughputParameters {
+    pub fn run(&self) -> ThroughputStatistics {
+        let primal_dual_config: serde_json::Value = serde_json::from_str(&self.primal_dual_config).unwrap();
+        let code = self.code.build();
+        let initializer = code.get_initializer();
+        let graph = MicroBlossomSingle::new_code(code.as_ref());
+        // take all the shots beforehand so that the syndrome source is not part of the throughput
+        let mut source = self.source.build(code);
+        let syndrome_patterns: Vec<SyndromePattern> =
+            (0..self.total_rounds).map_while(|_| source.next_syndrome_pattern()).collect();
+        let (subgraphs, statistics) = solve_batch_threaded::<DualModuleCombDriver>(
+            &graph,
+            self.contexts,
+            &primal_dual_config,
+            &syndrome_patterns,
+            self.threads,
+        );
+        if self.verify {
+            for (seed, (syndrome_pattern, subgraph)) in syndrome_patterns.iter().zip(subgraphs.iter()).enumerate() {
+                assert_eq!(
+                    initializer.syndrome_of(subgraph),
+                    syndrome_pattern.defect_vertices.iter().cloned().collect(),
+                    "invalid correction of shot {seed}"
+                );
+            }
+        }
+        let mut report = json!({
+            "contexts": self.contexts,
+            "threads": self.threads,
+            "shots": statistics.shots,
+            "steps": statistics.steps,
+            "elapsed": statistics.elapsed,
+            "throughput": statistics.throughput(),
+        });
+        report["code_type"] = json!(self.code.code_type.to_possible_value().unwrap().get_name());
+        report["d"] = json!(self.code.d);
+        report["p"] = json!(self.code.p);
+        report["noisy_measurements"] = json!(self.code.noisy_measurements);
+        report["primal_dual_config"] = json!(self.primal_dual_config);
+        println!("{report}");
+        if let Some(profiler_output) = self.profiler_output.as_ref() {
+            std::fs::write(profiler_output, serde_json::to_string_pretty(&report).unwrap()).unwrap();
+        }
+        statistics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fusion_blossom::example_codes::*;
+
+    /// pipelining only changes the order of the instructions, not the result of any shot
+    #[test]
+    fn throughput_pipelined_same_result() {
+        // cargo test throughput_pipelined_same_result -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 4, 0.03, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let syndrome_patterns: Vec<SyndromePattern> = (0..50).map(|seed| code.generate_random_errors(seed)).collect();
+        for primal_dual_config in [
+            json!({}),
+            json!({ "dual": { "sim_config": { "support_offloading": true, "support_layer_fusion": true } } }),
+        ] {
+            let mut solver = SolverEmbeddedComb::new(graph.clone(), primal_dual_config.clone());
+            let expected: Vec<Vec<EdgeIndex>> = syndrome_patterns
+                .iter()
+                .map(|syndrome_pattern| {
+                    solver.solve(syndrome_pattern);
+                    let subgraph = solver.subgraph();
+                    solver.clear();
+                    subgraph
+                })
+                .collect();
+            for context_num in [1, 3, 8] {
+                let mut pipelined = SolverPipelinedComb::new(graph.clone(), context_num, primal_dual_config.clone());
+                assert_eq!(pipelined.solve_batch(&syndrome_patterns), expected);
+                assert_eq!(pipelined.statistics.shots, syndrome_patterns.len());
+                println!("{}", pipelined.generate_profiler_report());
+            }
+        }
+    }
+
+    /// the decisions do not depend on the number of threads, even with a shuffled defect order
+    #[test]
+    fn throughput_thread_count_determinism() {
+        // cargo test throughput_thread_count_determinism -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 4, 0.05, 500);
+        let graph = MicroBlossomSingle::new_code(&code);
+        let syndrome_patterns: Vec<SyndromePattern> = (0..60).map(|seed| code.generate_random_errors(seed)).collect();
+        for primal_dual_config in [
+            json!({ "defect_order_seed": 3 }),
+            json!({ "dual": { "sim_config": { "support_offloading": true } }, "defect_order_seed": 7 }),
+        ] {
+            let (expected, expected_statistics) =
+                solve_batch_threaded::<DualModuleCombDriver>(&graph, 1, &primal_dual_config, &syndrome_patterns, 1);
+            for (context_num, threads) in [(1, 2), (3, 4), (2, 7), (1, 100)] {
+                let (subgraphs, statistics) = solve_batch_threaded::<DualModuleCombDriver>(
+                    &graph,
+                    context_num,
+                    &primal_dual_config,
+                    &syndrome_patterns,
+                    threads,
+                );
+                assert_eq!(subgraphs, expected);
+                assert_eq!(statistics.shots, expected_statistics.shots);
+                assert_eq!(statistics.steps, expected_statistics.steps);
+            }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Tight Paths
+//!
+//! The correction of a matched pair is a path between the two defects (or between a defect and the virtual vertex
+//! it matches to), which a Pauli-frame tracker applies to the physical qubits. Once solved, every matched pair is
+//! connected by edges that are fully covered by the dual variables, i.e., tight edges, so a Dijkstra restricted to
+//! the tight edges recovers the path from the dual state alone, without the all-pair shortest paths of
+//! [`fusion_blossom::primal_module::SubGraphBuilder`].
+//!
+
+use crate::resources::*;
+use fusion_blossom::dual_module::*;
+use fusion_blossom::primal_module::*;
+use fusion_blossom::util::*;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CorrectionPath {
+    pub vertex: VertexIndex,
+    /// the peer defect vertex, or the virtual vertex of a boundary matching
+    pub target: VertexIndex,
+    /// the edges along the path from `vertex` to `target`
+    pub edges: Vec<EdgeIndex>,
+}
+
+pub struct TightPathFinder {
+    /// `(peer, edge_index, weight)` of every tight edge incident to each vertex
+    tight_neighbors: Vec<Vec<(VertexIndex, EdgeIndex, Weight)>>,
+}
+
+impl TightPathFinder {
+    /// an edge is tight when the growth on both of its vertices covers the weight
+    pub fn new(graph: &MicroBlossomSingle, grown: &[Weight]) -> Self {
+        assert_eq!(grown.len(), graph.vertex_num, "the grown value of every vertex is required");
+        let mut tight_neighbors = vec![vec![]; graph.vertex_num];
+        for (edge_index, edge) in graph.weighted_edges.iter().enumerate() {
+            let (left, right) = (edge.l as VertexIndex, edge.r as VertexIndex);
+            if grown[left] + grown[right] >= edge.w {
+                tight_neighbors[left].push((right, edge_index, edge.w));
+                tight_neighbors[right].push((left, edge_index, edge.w));
+            }
+        }
+        Self { tight_neighbors }
+    }
+
+    /// the minimum-weight path from `source` to `target` using only the tight edges, `None` if disconnected
+    pub fn find(&self, source: VertexIndex, target: VertexIndex) -> Option<Vec<EdgeIndex>> {
+        let mut distance = vec![Weight::MAX; self.tight_neighbors.len()];
+        let mut previous: Vec<Option<(VertexIndex, EdgeIndex)>> = vec![None; self.tight_neighbors.len()];
+        let mut queue = BinaryHeap::new();
+        distance[source] = 0;
+        queue.push(Reverse((0, source)));
+        while let Some(Reverse((vertex_distance, vertex))) = queue.pop() {
+            if vertex == target {
+                break;
+            }
+            if vertex_distance > distance[vertex] {
+                continue; // outdated entry
+            }
+            for &(peer, edge_index, weight) in self.tight_neighbors[vertex].iter() {
+                let peer_distance = vertex_distance + weight;
+                if peer_distance < distance[peer] {
+                    distance[peer] = peer_distance;
+                    previous[peer] = Some((vertex, edge_index));
+                    queue.push(Reverse((peer_distance, peer)));
+                }
+            }
+        }
+        if distance[target] == Weight::MAX {
+            return None;
+        }
+        let mut edges = vec![];
+        let mut vertex = target;
+        while let Some((parent, edge_index)) = previous[vertex] {
+            edges.push(edge_index);
+            vertex = parent;
+        }
+        edges.reverse();
+        Some(edges)
+    }
+
+    /// the correction path of every pair in the perfect matching
+    pub fn correction_paths(&self, perfect_matching: &PerfectMatching) -> Vec<CorrectionPath> {
+        let defect_vertex = |node_ptr: &DualNodePtr| match node_ptr.read_recursive().class {
+            DualNodeClass::DefectVertex { defect_index } => defect_index,
+            DualNodeClass::Blossom { .. } => unreachable!("the perfect matching should be expanded to defect vertices"),
+        };
+        let peer_pairs = perfect_matching
+            .peer_matchings
+            .iter()
+            .map(|(node_1, node_2)| (defect_vertex(node_1), defect_vertex(node_2)));
+        let virtual_pairs = perfect_matching
+            .virtual_matchings
+            .iter()
+            .map(|(node, virtual_vertex)| (defect_vertex(node), *virtual_vertex));
+        peer_pairs
+            .chain(virtual_pairs)
+            .map(|(vertex, target)| CorrectionPath {
+                vertex,
+                target,
+                edges: self
+                    .find(vertex, target)
+                    .unwrap_or_else(|| panic!("no tight path between matched vertices {vertex} and {target}")),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mwpm_solver::*;
+    use crate::resources::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::mwpm_solver::*;
+    use fusion_blossom::util::*;
+    use serde_json::json;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn tight_paths_explain_defects() {
+        // cargo test tight_paths_explain_defects -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 4, 0.03, 500);
+        let initializer = code.get_initializer();
+        let mut solver = SolverEmbeddedComb::new(MicroBlossomSingle::new_code(&code), json!({}));
+        for seed in 0..50 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            solver.solve(&syndrome_pattern);
+            let result = solver.result();
+            let correction_paths = result.correction_paths.unwrap();
+            let mut matched = BTreeSet::new();
+            let mut total_weight = 0;
+            for path in correction_paths.iter() {
+                let expected: BTreeSet<VertexIndex> = [path.vertex, path.target]
+                    .into_iter()
+                    .filter(|vertex_index| !initializer.virtual_vertices.contains(vertex_index))
+                    .collect();
+                assert_eq!(initializer.syndrome_of(&path.edges), expected);
+                matched.extend(expected);
+                total_weight += path
+                    .edges
+                    .iter()
+                    .map(|&edge_index| initializer.weighted_edges[edge_index].2)
+                    .sum::<Weight>();
+            }
+            assert_eq!(matched, syndrome_pattern.defect_vertices.iter().cloned().collect());
+            // every tight path is at least as long as the shortest path, which sums to the matching weight
+            assert!(total_weight >= result.matching_weight);
+            solver.clear();
+        }
+        // offloaded matchings are not reflected in the growth of the vertices
+        let mut solver = SolverEmbeddedComb::new(
+            MicroBlossomSingle::new_code(&code),
+            json!({ "dual": { "sim_config": { "support_offloading": true } } }),
+        );
+        solver.solve(&code.generate_random_errors(0));
+        assert_eq!(solver.result().correction_paths, None);
+    }
+}
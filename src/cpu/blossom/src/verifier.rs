@@ -0,0 +1,254 @@
+//! Verifier
+//!
+//! Check the result of a solver against the minimum-weight correction computed by the serial solver of
+//! fusion-blossom. Some configurations are suboptimal by design, e.g., a pre-matching that commits to a local match
+//! without seeing the whole syndrome, so an exact comparison would report every such shot as a failure and hide the
+//! real bugs among them. The verifier accepts a correction whose weight is within
+//! `optimal * multiplicative_bound + additive_bound`, and tells apart the suboptimal corrections within the bound
+//! from the incorrect ones: an invalid correction, a weight below the optimal (which indicates a bug in the weight
+//! bookkeeping) or a weight beyond the bound.
+//!
+
+use crate::mwpm_solver::*;
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::primal_module::*;
+use fusion_blossom::util::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VerifierConfig {
+    /// the tolerated excess weight on top of the multiplicative bound
+    #[serde(default = "verifier_config_default::additive_bound")]
+    pub additive_bound: Weight,
+    /// the tolerated ratio to the optimal weight, at least 1
+    #[serde(default = "verifier_config_default::multiplicative_bound")]
+    pub multiplicative_bound: f64,
+}
+
+pub mod verifier_config_default {
+    use fusion_blossom::util::*;
+    pub fn additive_bound() -> Weight {
+        0
+    }
+    pub fn multiplicative_bound() -> f64 {
+        1.
+    }
+}
+
+impl Default for VerifierConfig {
+    fn default() -> Self {
+        serde_json::from_value(json!({})).unwrap()
+    }
+}
+
+impl VerifierConfig {
+    /// the maximum accepted weight given the optimal weight
+    pub fn weight_bound(&self, optimal_weight: Weight) -> Weight {
+        (optimal_weight as f64 * self.multiplicative_bound).floor() as Weight + self.additive_bound
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "verdict", rename_all = "snake_case")]
+pub enum Verdict {
+    Optimal,
+    /// a valid correction heavier than the optimal but within the bound
+    Suboptimal {
+        excess: Weight,
+    },
+    /// the correction does not explain the defects
+    InvalidCorrection,
+    /// the reported weight is below the optimal, which is impossible for a valid correction
+    BelowOptimal {
+        deficit: Weight,
+    },
+    /// a valid correction heavier than the bound
+    ExceedsBound {
+        excess: Weight,
+    },
+}
+
+impl Verdict {
+    pub fn is_accepted(&self) -> bool {
+        matches!(self, Self::Optimal | Self::Suboptimal { .. })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Optimal => "optimal",
+            Self::Suboptimal { .. } => "suboptimal",
+            Self::InvalidCorrection => "invalid_correction",
+            Self::BelowOptimal { .. } => "below_optimal",
+            Self::ExceedsBound { .. } => "exceeds_bound",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifierStatistics {
+    /// the number of shots of every verdict
+    pub verdicts: BTreeMap<String, usize>,
+    /// the largest excess weight among the accepted shots
+    pub max_accepted_excess: Weight,
+    /// the sum of the excess weight among the accepted shots
+    pub total_accepted_excess: Weight,
+}
+
+pub struct BoundedVerifier {
+    pub config: VerifierConfig,
+    initializer: SolverInitializer,
+    standard_solver: SolverSerial,
+    subgraph_builder: SubGraphBuilder,
+    pub statistics: VerifierStatistics,
+}
+
+impl BoundedVerifier {
+    pub fn new(initializer: &SolverInitializer, config: VerifierConfig) -> Self {
+        assert!(
+            config.multiplicative_bound >= 1.,
+            "the bound cannot be tighter than the optimal"
+        );
+        assert!(config.additive_bound >= 0, "the bound cannot be tighter than the optimal");
+        Self {
+            config,
+            initializer: initializer.clone(),
+            standard_solver: SolverSerial::new(initializer),
+            subgraph_builder: SubGraphBuilder::new(initializer),
+            statistics: VerifierStatistics::default(),
+        }
+    }
+
+    /// the weight of the minimum-weight correction
+    pub fn optimal_weight(&mut self, syndrome_pattern: &SyndromePattern) -> Weight {
+        self.standard_solver.solve(syndrome_pattern);
+        self.subgraph_builder.load_subgraph(&self.standard_solver.subgraph());
+        self.standard_solver.clear();
+        self.subgraph_builder.total_weight()
+    }
+
+    pub fn verify(&mut self, syndrome_pattern: &SyndromePattern, result: &SolverResult) -> Verdict {
+        let verdict = if self.initializer.syndrome_of(&result.subgraph)
+            != syndrome_pattern.defect_vertices.iter().cloned().collect()
+        {
+            Verdict::InvalidCorrection
+        } else {
+            let optimal_weight = self.optimal_weight(syndrome_pattern);
+            let excess = result.matching_weight - optimal_weight;
+            if excess == 0 {
+                Verdict::Optimal
+            } else if excess < 0 {
+                Verdict::BelowOptimal { deficit: -excess }
+            } else if result.matching_weight <= self.config.weight_bound(optimal_weight) {
+                Verdict::Suboptimal { excess }
+            } else {
+                Verdict::ExceedsBound { excess }
+            }
+        };
+        *self.statistics.verdicts.entry(verdict.name().to_string()).or_default() += 1;
+        if let Verdict::Suboptimal { excess } = verdict {
+            self.statistics.max_accepted_excess = self.statistics.max_accepted_excess.max(excess);
+            self.statistics.total_accepted_excess += excess;
+        }
+        verdict
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn verifier_weight_bound() {
+        // cargo test verifier_weight_bound -- --nocapture
+        assert_eq!(VerifierConfig::default().weight_bound(1000), 1000);
+        let config: VerifierConfig =
+            serde_json::from_value(json!({ "additive_bound": 100, "multiplicative_bound": 1.5 })).unwrap();
+        assert_eq!(config.weight_bound(1000), 1600);
+    }
+
+    #[test]
+    fn verifier_verdicts() {
+        // cargo test verifier_verdicts -- --nocapture
+        let mut code = CodeCapacityRepetitionCode::new(7, 0.1, 500);
+        let initializer = code.get_initializer();
+        let is_virtual = |vertex_index: VertexIndex| initializer.virtual_vertices.contains(&vertex_index);
+        let regular_edges: Vec<EdgeIndex> = (0..initializer.weighted_edges.len())
+            .filter(|&edge_index| {
+                let (left, right, _) = initializer.weighted_edges[edge_index];
+                !is_virtual(left) && !is_virtual(right)
+            })
+            .collect();
+        let (edge, other_edge) = (regular_edges[0], regular_edges[1]);
+        let (left, right, _) = initializer.weighted_edges[edge];
+        code.set_defect_vertices(&[left, right]);
+        let syndrome_pattern = code.get_syndrome();
+        let other_weight = initializer.weighted_edges[other_edge].2;
+        let config = VerifierConfig {
+            additive_bound: 2 * other_weight,
+            multiplicative_bound: 1.,
+        };
+        let mut verifier = BoundedVerifier::new(&initializer, config);
+        let result_of = |subgraph: Vec<EdgeIndex>| {
+            let matching_weight = subgraph
+                .iter()
+                .map(|&edge_index| initializer.weighted_edges[edge_index].2)
+                .sum();
+            SolverResult::new(subgraph, matching_weight, None)
+        };
+        let optimal = result_of(vec![edge]);
+        assert_eq!(verifier.verify(&syndrome_pattern, &optimal), Verdict::Optimal);
+        // an edge used twice cancels out, giving a valid but heavier correction
+        let suboptimal = result_of(vec![edge, other_edge, other_edge]);
+        assert_eq!(
+            verifier.verify(&syndrome_pattern, &suboptimal),
+            Verdict::Suboptimal {
+                excess: 2 * other_weight
+            }
+        );
+        let too_heavy = result_of(vec![edge, other_edge, other_edge, other_edge, other_edge]);
+        assert_eq!(
+            verifier.verify(&syndrome_pattern, &too_heavy),
+            Verdict::ExceedsBound {
+                excess: 4 * other_weight
+            }
+        );
+        let invalid = result_of(vec![other_edge]);
+        assert_eq!(verifier.verify(&syndrome_pattern, &invalid), Verdict::InvalidCorrection);
+        let mut below_optimal = optimal.clone();
+        below_optimal.matching_weight -= 1;
+        assert_eq!(
+            verifier.verify(&syndrome_pattern, &below_optimal),
+            Verdict::BelowOptimal { deficit: 1 }
+        );
+        assert_eq!(verifier.statistics.verdicts.values().sum::<usize>(), 5);
+        assert_eq!(verifier.statistics.max_accepted_excess, 2 * other_weight);
+    }
+
+    /// the pre-matching configurations are regression-tested against a bound instead of the exact optimal
+    #[test]
+    fn verifier_pre_matching() {
+        // cargo test verifier_pre_matching -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 4, 0.03, 500);
+        let initializer = code.get_initializer();
+        let config = serde_json::from_value(json!({ "multiplicative_bound": 2, "additive_bound": 1000 })).unwrap();
+        let mut verifier = BoundedVerifier::new(&initializer, config);
+        let mut solver = SolverEmbeddedComb::new(
+            MicroBlossomSingle::new_code(&code),
+            json!({ "dual": { "sim_config": { "support_offloading": true } } }),
+        );
+        for seed in 0..100 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            solver.solve(&syndrome_pattern);
+            let result = solver.result();
+            let verdict = verifier.verify(&syndrome_pattern, &result);
+            assert!(verdict.is_accepted(), "seed {seed}: {verdict:?}");
+            solver.clear();
+        }
+        println!("{:?}", verifier.statistics);
+    }
+}
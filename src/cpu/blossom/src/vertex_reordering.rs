@@ -0,0 +1,284 @@
+//! Vertex Reordering
+//!
+//! Renumber the vertices of a decoding graph so that vertices close to each other in the graph also have close
+//! indices. The software simulators scan the vertices by index, so a local ordering improves their cache locality,
+//! and the hardware places the vertices following the same binary tree of positions.
+//!
+//! The edges keep their indices and only their endpoints are renumbered, so the erasures, the dynamic weights and
+//! the subgraph of a solver are the same before and after reordering. Only the defect vertices and the perfect
+//! matching need to be translated, which [`SolverReordered`] does transparently around any solver.
+//!
+
+use crate::mwpm_solver::*;
+use fusion_blossom::dual_module::*;
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::pointers::*;
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, VecDeque};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VertexOrder {
+    /// breadth-first search from the smallest unvisited vertex of each connected component
+    Bfs,
+    /// Z-order (Morton) curve over the `(t, i, j)` positions
+    Morton,
+}
+
+#[derive(Debug, Clone)]
+pub struct VertexReordering {
+    /// `order[new_index]` is the original index of the vertex
+    pub order: Vec<VertexIndex>,
+    /// `inverse[original_index]` is the new index of the vertex
+    pub inverse: Vec<VertexIndex>,
+}
+
+impl VertexReordering {
+    pub fn new(vertex_order: VertexOrder, initializer: &SolverInitializer, positions: &[VisualizePosition]) -> Self {
+        let order = match vertex_order {
+            VertexOrder::Bfs => bfs_order(initializer),
+            VertexOrder::Morton => morton_order(positions),
+        };
+        Self::from_order(order)
+    }
+
+    pub fn from_order(order: Vec<VertexIndex>) -> Self {
+        let mut inverse = vec![VertexIndex::MAX; order.len()];
+        for (new_index, &original_index) in order.iter().enumerate() {
+            assert_eq!(inverse[original_index], VertexIndex::MAX, "not a permutation");
+            inverse[original_index] = new_index;
+        }
+        Self { order, inverse }
+    }
+
+    pub fn vertex_num(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn initializer(&self, initializer: &SolverInitializer) -> SolverInitializer {
+        assert_eq!(initializer.vertex_num, self.vertex_num());
+        SolverInitializer::new(
+            initializer.vertex_num,
+            initializer
+                .weighted_edges
+                .iter()
+                .map(|&(left, right, weight)| (self.inverse[left], self.inverse[right], weight))
+                .collect(),
+            initializer
+                .virtual_vertices
+                .iter()
+                .map(|&vertex_index| self.inverse[vertex_index])
+                .collect(),
+        )
+    }
+
+    pub fn positions(&self, positions: &[VisualizePosition]) -> Vec<VisualizePosition> {
+        assert_eq!(positions.len(), self.vertex_num());
+        self.order
+            .iter()
+            .map(|&original_index| positions[original_index].clone())
+            .collect()
+    }
+
+    pub fn syndrome_pattern(&self, syndrome_pattern: &SyndromePattern) -> SyndromePattern {
+        let mut reordered = syndrome_pattern.clone();
+        for defect_vertex in reordered.defect_vertices.iter_mut() {
+            *defect_vertex = self.inverse[*defect_vertex];
+        }
+        reordered
+    }
+
+    /// translate a perfect matching of the reordered graph back to the original vertex indices
+    pub fn original_perfect_matching(&self, perfect_matching: &PerfectMatching) -> PerfectMatching {
+        let original_node = |node_ptr: &DualNodePtr| {
+            let node = node_ptr.read_recursive();
+            let DualNodeClass::DefectVertex { defect_index } = node.class else {
+                unreachable!("perfect matching only contains defect vertices")
+            };
+            DualNodePtr::new_value(DualNode {
+                index: node.index,
+                class: DualNodeClass::DefectVertex {
+                    defect_index: self.order[defect_index],
+                },
+                defect_size: nonzero::nonzero!(1usize),
+                grow_state: DualNodeGrowState::Stay,
+                parent_blossom: None,
+                dual_variable_cache: (0, 0),
+                belonging: node.belonging.clone(),
+            })
+        };
+        let mut original = PerfectMatching::new();
+        for (node_1, node_2) in perfect_matching.peer_matchings.iter() {
+            original.peer_matchings.push((original_node(node_1), original_node(node_2)));
+        }
+        for (node, virtual_vertex) in perfect_matching.virtual_matchings.iter() {
+            original
+                .virtual_matchings
+                .push((original_node(node), self.order[*virtual_vertex]));
+        }
+        original
+    }
+}
+
+fn bfs_order(initializer: &SolverInitializer) -> Vec<VertexIndex> {
+    let vertex_num = initializer.vertex_num;
+    let mut neighbors: Vec<BTreeSet<VertexIndex>> = vec![BTreeSet::new(); vertex_num];
+    for &(left, right, _) in initializer.weighted_edges.iter() {
+        neighbors[left].insert(right);
+        neighbors[right].insert(left);
+    }
+    let mut visited = vec![false; vertex_num];
+    let mut order = Vec::with_capacity(vertex_num);
+    for start in 0..vertex_num {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut queue = VecDeque::from([start]);
+        while let Some(vertex_index) = queue.pop_front() {
+            order.push(vertex_index);
+            for &neighbor in neighbors[vertex_index].iter() {
+                if !visited[neighbor] {
+                    visited[neighbor] = true;
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+    order
+}
+
+fn morton_order(positions: &[VisualizePosition]) -> Vec<VertexIndex> {
+    // the coordinates are not necessarily integers, so use the rank of the value along each axis
+    let axes: [fn(&VisualizePosition) -> f64; 3] = [|position| position.t, |position| position.i, |position| position.j];
+    let ranks: Vec<Vec<u64>> = axes
+        .iter()
+        .map(|axis| {
+            let values: BTreeSet<OrderedFloat<f64>> = positions.iter().map(|position| axis(position).into()).collect();
+            let values: Vec<OrderedFloat<f64>> = values.into_iter().collect();
+            positions
+                .iter()
+                .map(|position| values.binary_search(&axis(position).into()).unwrap() as u64)
+                .collect()
+        })
+        .collect();
+    let mut codes: Vec<(u128, VertexIndex)> = (0..positions.len())
+        .map(|vertex_index| {
+            let mut code = 0u128;
+            for bit in (0..32).rev() {
+                for rank in ranks.iter() {
+                    code = (code << 1) | ((rank[vertex_index] >> bit) & 1) as u128;
+                }
+            }
+            (code, vertex_index)
+        })
+        .collect();
+    codes.sort();
+    codes.into_iter().map(|(_, vertex_index)| vertex_index).collect()
+}
+
+/// run any solver on the reordered graph while taking and reporting the original vertex indices
+pub struct SolverReordered {
+    pub solver: Box<dyn MicroBlossomSolver>,
+    pub reordering: VertexReordering,
+}
+
+impl SolverReordered {
+    pub fn new(solver: Box<dyn MicroBlossomSolver>, reordering: VertexReordering) -> Self {
+        Self { solver, reordering }
+    }
+}
+
+impl PrimalDualSolver for SolverReordered {
+    fn clear(&mut self) {
+        self.solver.clear();
+    }
+    fn reset_profiler(&mut self) {
+        self.solver.reset_profiler();
+    }
+    /// the visualizer shows the reordered graph
+    fn solve_visualizer(&mut self, syndrome_pattern: &SyndromePattern, visualizer: Option<&mut Visualizer>) {
+        let syndrome_pattern = self.reordering.syndrome_pattern(syndrome_pattern);
+        self.solver.solve_visualizer(&syndrome_pattern, visualizer);
+    }
+    fn perfect_matching_visualizer(&mut self, visualizer: Option<&mut Visualizer>) -> PerfectMatching {
+        let perfect_matching = self.solver.perfect_matching_visualizer(visualizer);
+        self.reordering.original_perfect_matching(&perfect_matching)
+    }
+    fn subgraph_visualizer(&mut self, visualizer: Option<&mut Visualizer>) -> Vec<EdgeIndex> {
+        // the edge indices are not changed by reordering
+        self.solver.subgraph_visualizer(visualizer)
+    }
+    fn sum_dual_variables(&self) -> Weight {
+        self.solver.sum_dual_variables()
+    }
+    fn generate_profiler_report(&self) -> serde_json::Value {
+        self.solver.generate_profiler_report()
+    }
+}
+
+impl MicroBlossomSolver for SolverReordered {
+    fn result(&mut self) -> SolverResult {
+        let mut result = self.solver.result();
+        for path in result.correction_paths.iter_mut().flatten() {
+            path.vertex = self.reordering.order[path.vertex];
+            path.target = self.reordering.order[path.target];
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwpm_solver::*;
+    use crate::resources::*;
+    use fusion_blossom::example_codes::*;
+
+    #[test]
+    fn vertex_reordering_solve() {
+        // cargo test vertex_reordering_solve -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 4, 0.03, 500);
+        let initializer = code.get_initializer();
+        let positions = code.get_positions();
+        let mut standard_solver = SolverSerial::new(&initializer);
+        for vertex_order in [VertexOrder::Bfs, VertexOrder::Morton] {
+            let reordering = VertexReordering::new(vertex_order, &initializer, &positions);
+            let mut sorted = reordering.order.clone();
+            sorted.sort();
+            assert_eq!(sorted, (0..initializer.vertex_num).collect::<Vec<_>>());
+            let graph = MicroBlossomSingle::new(&reordering.initializer(&initializer), &reordering.positions(&positions));
+            let mut solver = SolverReordered::new(Box::new(SolverEmbeddedComb::new(graph, json!({}))), reordering);
+            for seed in 0..50 {
+                let syndrome_pattern = code.generate_random_errors(seed);
+                solver.solve(&syndrome_pattern);
+                // the subgraph is in the original edge indices and explains the original defects
+                let subgraph = solver.subgraph();
+                let original_defects = syndrome_pattern.defect_vertices.iter().cloned().collect();
+                assert_eq!(initializer.syndrome_of(&subgraph), original_defects);
+                standard_solver.solve(&syndrome_pattern);
+                assert_eq!(solver.sum_dual_variables(), standard_solver.sum_dual_variables());
+                let perfect_matching = solver.perfect_matching();
+                let mut matched = BTreeSet::new();
+                for (node_1, node_2) in perfect_matching.peer_matchings.iter() {
+                    for node in [node_1, node_2] {
+                        if let DualNodeClass::DefectVertex { defect_index } = node.read_recursive().class {
+                            matched.insert(defect_index);
+                        }
+                    }
+                }
+                for (node, _) in perfect_matching.virtual_matchings.iter() {
+                    if let DualNodeClass::DefectVertex { defect_index } = node.read_recursive().class {
+                        matched.insert(defect_index);
+                    }
+                }
+                assert_eq!(matched, syndrome_pattern.defect_vertices.iter().cloned().collect());
+                solver.clear();
+                standard_solver.clear();
+            }
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Virtual Merging
+//!
+//! Many example codes create one virtual vertex per boundary edge, although a matching to the boundary only cares
+//! about the distance to the nearest virtual vertex: a minimum-weight path never goes through a virtual vertex,
+//! because matching both ends to that vertex costs the same. Merging the virtual vertices of a group into a single
+//! one thus keeps every minimum-weight matching, while saving the vertices and their registers in the hardware.
+//!
+//! Each regular vertex keeps a single edge to a merged virtual vertex, the lightest of its edges into the group; the
+//! weights are not changed, so the pass commutes with any scaling of the weights. The edges between two virtual
+//! vertices are dropped. [`SolverVirtualMerged`] runs any solver on the merged graph and translates the defects, the
+//! subgraph and the correction paths back, where a path to the boundary ends at the original virtual vertex of its
+//! last edge. Only the virtual vertex of a perfect matching is ambiguous, and reported as the first original vertex
+//! of the group.
+//!
+//! Enable it with `--primal-dual-config '{"merge_virtual":"side"}'`.
+//!
+
+use crate::mwpm_solver::*;
+use fusion_blossom::dual_module::*;
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::pointers::*;
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VirtualMergePolicy {
+    /// a single virtual vertex for the whole graph
+    All,
+    /// one virtual vertex per side of the bounding box of the regular vertices, i.e., per boundary of the code
+    Side,
+}
+
+#[derive(Debug, Clone)]
+pub struct VirtualMerging {
+    /// `merged[original_index]` is the vertex index in the merged graph
+    pub merged: Vec<VertexIndex>,
+    /// `representative[merged_index]` is the first original vertex merged into it
+    pub representative: Vec<VertexIndex>,
+    /// `edges[merged_edge_index]` is the original edge, the lightest of the parallel ones
+    pub edges: Vec<EdgeIndex>,
+    is_virtual: Vec<bool>,
+}
+
+impl VirtualMerging {
+    pub fn new(policy: VirtualMergePolicy, initializer: &SolverInitializer, positions: &[VisualizePosition]) -> Self {
+        let mut is_virtual = vec![false; initializer.vertex_num];
+        for &vertex_index in initializer.virtual_vertices.iter() {
+            is_virtual[vertex_index] = true;
+        }
+        let group_of = |vertex_index: VertexIndex| match policy {
+            VirtualMergePolicy::All => 0,
+            VirtualMergePolicy::Side => side_of(&positions[vertex_index], &is_virtual, positions),
+        };
+        let mut merged = Vec::with_capacity(initializer.vertex_num);
+        let mut representative = vec![];
+        let mut groups = BTreeMap::<usize, VertexIndex>::new();
+        for vertex_index in 0..initializer.vertex_num {
+            let merged_index = if is_virtual[vertex_index] {
+                *groups.entry(group_of(vertex_index)).or_insert(representative.len())
+            } else {
+                representative.len()
+            };
+            if merged_index == representative.len() {
+                representative.push(vertex_index);
+            }
+            merged.push(merged_index);
+        }
+        let mut edges: Vec<EdgeIndex> = vec![];
+        let mut pairs = BTreeMap::<(VertexIndex, VertexIndex), usize>::new();
+        for (edge_index, &(left, right, weight)) in initializer.weighted_edges.iter().enumerate() {
+            if is_virtual[left] && is_virtual[right] {
+                continue;
+            }
+            let (left, right) = (merged[left], merged[right]);
+            match pairs.get(&(left.min(right), left.max(right))) {
+                Some(&slot) => {
+                    if weight < initializer.weighted_edges[edges[slot]].2 {
+                        edges[slot] = edge_index;
+                    }
+                }
+                None => {
+                    pairs.insert((left.min(right), left.max(right)), edges.len());
+                    edges.push(edge_index);
+                }
+            }
+        }
+        Self {
+            merged,
+            representative,
+            edges,
+            is_virtual,
+        }
+    }
+
+    pub fn vertex_num(&self) -> usize {
+        self.representative.len()
+    }
+
+    pub fn initializer(&self, initializer: &SolverInitializer) -> SolverInitializer {
+        assert_eq!(initializer.vertex_num, self.merged.len());
+        SolverInitializer::new(
+            self.vertex_num(),
+            (self.edges.iter())
+                .map(|&edge_index| {
+                    let (left, right, weight) = initializer.weighted_edges[edge_index];
+                    (self.merged[left], self.merged[right], weight)
+                })
+                .collect(),
+            (0..self.vertex_num())
+                .filter(|&merged_index| self.is_virtual[self.representative[merged_index]])
+                .collect(),
+        )
+    }
+
+    /// a merged virtual vertex is placed at the center of its group
+    pub fn positions(&self, positions: &[VisualizePosition]) -> Vec<VisualizePosition> {
+        assert_eq!(positions.len(), self.merged.len());
+        let mut sums = vec![(0., 0., 0., 0usize); self.vertex_num()];
+        for (original_index, position) in positions.iter().enumerate() {
+            let sum = &mut sums[self.merged[original_index]];
+            *sum = (sum.0 + position.i, sum.1 + position.j, sum.2 + position.t, sum.3 + 1);
+        }
+        (sums.into_iter())
+            .map(|(i, j, t, count)| VisualizePosition::new(i / count as f64, j / count as f64, t / count as f64))
+            .collect()
+    }
+
+    pub fn syndrome_pattern(&self, syndrome_pattern: &SyndromePattern) -> SyndromePattern {
+        let mut merged = syndrome_pattern.clone();
+        for defect_vertex in merged.defect_vertices.iter_mut() {
+            assert!(!self.is_virtual[*defect_vertex], "defect on virtual vertex {defect_vertex}");
+            *defect_vertex = self.merged[*defect_vertex];
+        }
+        merged
+    }
+
+    pub fn original_subgraph(&self, subgraph: &[EdgeIndex]) -> Vec<EdgeIndex> {
+        subgraph.iter().map(|&edge_index| self.edges[edge_index]).collect()
+    }
+
+    /// translate a perfect matching of the merged graph back to the original vertex indices
+    pub fn original_perfect_matching(&self, perfect_matching: &PerfectMatching) -> PerfectMatching {
+        let original_node = |node_ptr: &DualNodePtr| {
+            let node = node_ptr.read_recursive();
+            let DualNodeClass::DefectVertex { defect_index } = node.class else {
+                unreachable!("perfect matching only contains defect vertices")
+            };
+            DualNodePtr::new_value(DualNode {
+                index: node.index,
+                class: DualNodeClass::DefectVertex {
+                    defect_index: self.representative[defect_index],
+                },
+                defect_size: nonzero::nonzero!(1usize),
+                grow_state: DualNodeGrowState::Stay,
+                parent_blossom: None,
+                dual_variable_cache: (0, 0),
+                belonging: node.belonging.clone(),
+            })
+        };
+        let mut original = PerfectMatching::new();
+        for (node_1, node_2) in perfect_matching.peer_matchings.iter() {
+            original.peer_matchings.push((original_node(node_1), original_node(node_2)));
+        }
+        for (node, virtual_vertex) in perfect_matching.virtual_matchings.iter() {
+            original
+                .virtual_matchings
+                .push((original_node(node), self.representative[*virtual_vertex]));
+        }
+        original
+    }
+}
+
+/// the side of the bounding box of the regular vertices that a virtual vertex is the closest to
+fn side_of(position: &VisualizePosition, is_virtual: &[bool], positions: &[VisualizePosition]) -> usize {
+    let regular = || (0..positions.len()).filter(|&vertex_index| !is_virtual[vertex_index]);
+    let bound = |axis: fn(&VisualizePosition) -> f64, fold: fn(f64, f64) -> f64, init: f64| {
+        regular().map(|vertex_index| axis(&positions[vertex_index])).fold(init, fold)
+    };
+    let axes: [fn(&VisualizePosition) -> f64; 2] = [|position| position.i, |position| position.j];
+    let mut distances = vec![];
+    for axis in axes {
+        distances.push(bound(axis, f64::min, f64::INFINITY) - axis(position));
+        distances.push(axis(position) - bound(axis, f64::max, f64::NEG_INFINITY));
+    }
+    (0..distances.len())
+        .max_by(|&a, &b| distances[a].total_cmp(&distances[b]).then(b.cmp(&a)))
+        .unwrap()
+}
+
+/// run any solver on the merged graph while taking and reporting the original vertex and edge indices
+pub struct SolverVirtualMerged {
+    pub solver: Box<dyn MicroBlossomSolver>,
+    pub merging: VirtualMerging,
+    /// the original edges, to find the virtual vertex at the end of a correction path
+    weighted_edges: Vec<(VertexIndex, VertexIndex, Weight)>,
+}
+
+impl SolverVirtualMerged {
+    pub fn new(solver: Box<dyn MicroBlossomSolver>, merging: VirtualMerging, initializer: &SolverInitializer) -> Self {
+        Self {
+            solver,
+            merging,
+            weighted_edges: initializer.weighted_edges.clone(),
+        }
+    }
+}
+
+impl PrimalDualSolver for SolverVirtualMerged {
+    fn clear(&mut self) {
+        self.solver.clear();
+    }
+    fn reset_profiler(&mut self) {
+        self.solver.reset_profiler();
+    }
+    /// the visualizer shows the merged graph
+    fn solve_visualizer(&mut self, syndrome_pattern: &SyndromePattern, visualizer: Option<&mut Visualizer>) {
+        let syndrome_pattern = self.merging.syndrome_pattern(syndrome_pattern);
+        self.solver.solve_visualizer(&syndrome_pattern, visualizer);
+    }
+    fn perfect_matching_visualizer(&mut self, visualizer: Option<&mut Visualizer>) -> PerfectMatching {
+        let perfect_matching = self.solver.perfect_matching_visualizer(visualizer);
+        self.merging.original_perfect_matching(&perfect_matching)
+    }
+    fn subgraph_visualizer(&mut self, visualizer: Option<&mut Visualizer>) -> Vec<EdgeIndex> {
+        let subgraph = self.solver.subgraph_visualizer(visualizer);
+        self.merging.original_subgraph(&subgraph)
+    }
+    fn sum_dual_variables(&self) -> Weight {
+        self.solver.sum_dual_variables()
+    }
+    fn generate_profiler_report(&self) -> serde_json::Value {
+        self.solver.generate_profiler_report()
+    }
+}
+
+impl MicroBlossomSolver for SolverVirtualMerged {
+    fn result(&mut self) -> SolverResult {
+        let mut result = self.solver.result();
+        for path in result.correction_paths.iter_mut().flatten() {
+            path.edges = self.merging.original_subgraph(&path.edges);
+            path.vertex = self.merging.representative[path.vertex];
+            path.target = match path.edges.last() {
+                Some(&edge_index) if self.merging.is_virtual[self.merging.representative[path.target]] => {
+                    let (left, right, _) = self.weighted_edges[edge_index];
+                    if self.merging.is_virtual[left] {
+                        left
+                    } else {
+                        right
+                    }
+                }
+                _ => self.merging.representative[path.target],
+            };
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::*;
+    use fusion_blossom::example_codes::*;
+    use fusion_blossom::primal_module::SubGraphBuilder;
+    use serde_json::json;
+
+    #[test]
+    fn virtual_merging_vertex_num() {
+        // cargo test virtual_merging_vertex_num -- --nocapture
+        let code = CodeCapacityPlanarCode::new(7, 0.1, 500);
+        let (initializer, positions) = (code.get_initializer(), code.get_positions());
+        let regular_num = initializer.vertex_num - initializer.virtual_vertices.len();
+        for (policy, virtual_num) in [(VirtualMergePolicy::All, 1), (VirtualMergePolicy::Side, 2)] {
+            let merging = VirtualMerging::new(policy, &initializer, &positions);
+            let merged = merging.initializer(&initializer);
+            assert_eq!(merged.vertex_num, regular_num + virtual_num);
+            assert_eq!(merged.virtual_vertices.len(), virtual_num);
+            // every boundary edge survives, because each regular vertex touches a single virtual vertex
+            assert_eq!(merged.weighted_edges.len(), initializer.weighted_edges.len());
+        }
+    }
+
+    /// the merged graph gives a correction of the same weight that explains the original defects
+    #[test]
+    fn virtual_merging_solve() {
+        // cargo test virtual_merging_solve -- --nocapture
+        let codes: Vec<Box<dyn ExampleCode>> = vec![
+            Box::new(CodeCapacityPlanarCode::new(7, 0.1, 500)),
+            Box::new(PhenomenologicalRotatedCode::new(5, 4, 0.03, 500)),
+        ];
+        for mut code in codes {
+            let (initializer, positions) = (code.get_initializer(), code.get_positions());
+            let mut standard_solver = SolverSerial::new(&initializer);
+            let mut subgraph_builder = SubGraphBuilder::new(&initializer);
+            for policy in [VirtualMergePolicy::All, VirtualMergePolicy::Side] {
+                let merging = VirtualMerging::new(policy, &initializer, &positions);
+                let graph = MicroBlossomSingle::new(&merging.initializer(&initializer), &merging.positions(&positions));
+                println!("{policy:?}: {} -> {} vertices", initializer.vertex_num, graph.vertex_num);
+                let inner = Box::new(SolverEmbeddedComb::new(graph, json!({})));
+                let mut solver = SolverVirtualMerged::new(inner, merging, &initializer);
+                for seed in 0..30 {
+                    let syndrome_pattern = code.generate_random_errors(seed);
+                    solver.solve(&syndrome_pattern);
+                    standard_solver.solve(&syndrome_pattern);
+                    let subgraph = solver.subgraph();
+                    let original_defects = syndrome_pattern.defect_vertices.iter().cloned().collect();
+                    assert_eq!(initializer.syndrome_of(&subgraph), original_defects);
+                    subgraph_builder.load_subgraph(&standard_solver.subgraph());
+                    let standard_weight = subgraph_builder.total_weight();
+                    subgraph_builder.load_subgraph(&subgraph);
+                    assert_eq!(subgraph_builder.total_weight(), standard_weight);
+                    solver.clear();
+                    standard_solver.clear();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! Warm Start
+//!
+//! Consecutive windows of a streaming decoder share most of their defects, and re-solving the unchanged parts from
+//! scratch is redundant. This solver splits the defects into independent clusters and only solves the clusters that
+//! differ from the previous window, reusing the matching of the others.
+//!
+//! Two defects `u` and `v` with `d(u, v) >= b(u) + b(v)`, where `b` is the distance to the nearest virtual vertex,
+//! never need to be matched together: matching both to the boundary is at least as good. Thus the connected
+//! components of the "interacting" relation `d(u, v) < b(u) + b(v)` are independent sub-problems, and a component
+//! with exactly the same defects as in the previous window has exactly the same minimum-weight matching. The reuse
+//! is therefore exact; the optional validation compares every result against a full solve as a safety net.
+//!
+
+use crate::mwpm_solver::*;
+use fusion_blossom::dual_module::*;
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::pointers::*;
+use fusion_blossom::primal_module::*;
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MatchTarget {
+    Peer(VertexIndex),
+    VirtualVertex(VertexIndex),
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WarmStartStatistics {
+    pub reused_components: usize,
+    pub solved_components: usize,
+    pub reused_defects: usize,
+    pub solved_defects: usize,
+}
+
+pub struct SolverWarmStart {
+    pub solver: Box<dyn MicroBlossomSolver>,
+    /// compare every result against a full solve of this reference solver
+    pub validator: Option<SolverSerial>,
+    validated_dual_objective: Option<Weight>,
+    initializer: SolverInitializer,
+    neighbors: Vec<Vec<(VertexIndex, Weight)>>,
+    /// the distance from each vertex to the nearest virtual vertex, `Weight::MAX` if unreachable
+    boundary_distance: Vec<Weight>,
+    /// the matching of every component of the previous window, keyed by its sorted defect vertices
+    previous_components: BTreeMap<Vec<VertexIndex>, Vec<(VertexIndex, MatchTarget)>>,
+    matching: Vec<(VertexIndex, MatchTarget)>,
+    subgraph_builder: SubGraphBuilder,
+    pub statistics: WarmStartStatistics,
+}
+
+impl SolverWarmStart {
+    pub fn new(initializer: &SolverInitializer, solver: Box<dyn MicroBlossomSolver>, validate: bool) -> Self {
+        let mut neighbors = vec![vec![]; initializer.vertex_num];
+        for &(left, right, weight) in initializer.weighted_edges.iter() {
+            neighbors[left].push((right, weight));
+            neighbors[right].push((left, weight));
+        }
+        let mut solver = Self {
+            solver,
+            validator: validate.then(|| SolverSerial::new(initializer)),
+            validated_dual_objective: None,
+            initializer: initializer.clone(),
+            neighbors,
+            boundary_distance: vec![],
+            previous_components: BTreeMap::new(),
+            matching: vec![],
+            subgraph_builder: SubGraphBuilder::new(initializer),
+            statistics: WarmStartStatistics::default(),
+        };
+        solver.boundary_distance = solver.dijkstra(&initializer.virtual_vertices, Weight::MAX);
+        solver
+    }
+
+    /// forget the previous window, so that the next shot is solved from scratch
+    pub fn reset_warm_start(&mut self) {
+        self.previous_components.clear();
+    }
+
+    /// the distances from the nearest source within `radius`, `Weight::MAX` for the other vertices
+    fn dijkstra(&self, sources: &[VertexIndex], radius: Weight) -> Vec<Weight> {
+        let mut distance = vec![Weight::MAX; self.initializer.vertex_num];
+        let mut heap = BinaryHeap::new();
+        for &source in sources.iter() {
+            distance[source] = 0;
+            heap.push(Reverse((0, source)));
+        }
+        while let Some(Reverse((current, vertex_index))) = heap.pop() {
+            if current > distance[vertex_index] {
+                continue;
+            }
+            for &(neighbor, weight) in self.neighbors[vertex_index].iter() {
+                let next = current + weight;
+                if next < distance[neighbor] && next <= radius {
+                    distance[neighbor] = next;
+                    heap.push(Reverse((next, neighbor)));
+                }
+            }
+        }
+        distance
+    }
+
+    /// group the defects into independent components, each sorted by vertex index
+    fn interacting_components(&self, defect_vertices: &[VertexIndex]) -> Vec<Vec<VertexIndex>> {
+        let mut parent: Vec<usize> = (0..defect_vertices.len()).collect();
+        fn find(parent: &mut [usize], mut index: usize) -> usize {
+            while parent[index] != index {
+                parent[index] = parent[parent[index]];
+                index = parent[index];
+            }
+            index
+        }
+        let boundary = |vertex_index: VertexIndex| self.boundary_distance[vertex_index];
+        let max_boundary = defect_vertices.iter().map(|&vertex_index| boundary(vertex_index)).max();
+        for (index, &vertex_index) in defect_vertices.iter().enumerate() {
+            let radius = boundary(vertex_index).saturating_add(max_boundary.unwrap());
+            let distance = self.dijkstra(&[vertex_index], radius);
+            for (peer_index, &peer_vertex) in defect_vertices.iter().enumerate().skip(index + 1) {
+                let interaction = boundary(vertex_index).saturating_add(boundary(peer_vertex));
+                if distance[peer_vertex] < interaction {
+                    let (root, peer_root) = (find(&mut parent, index), find(&mut parent, peer_index));
+                    parent[root] = peer_root;
+                }
+            }
+        }
+        let mut components = BTreeMap::<usize, Vec<VertexIndex>>::new();
+        for (index, &vertex_index) in defect_vertices.iter().enumerate() {
+            let root = find(&mut parent, index);
+            components.entry(root).or_default().push(vertex_index);
+        }
+        components
+            .into_values()
+            .map(|mut component| {
+                component.sort();
+                component
+            })
+            .collect()
+    }
+
+    fn perfect_matching_of(&self, matching: &[(VertexIndex, MatchTarget)]) -> PerfectMatching {
+        let interface_ptr = DualModuleInterfacePtr::new_empty();
+        let belonging = interface_ptr.downgrade();
+        let node = |index: NodeIndex, defect_index: VertexIndex| {
+            DualNodePtr::new_value(DualNode {
+                index,
+                class: DualNodeClass::DefectVertex { defect_index },
+                defect_size: nonzero::nonzero!(1usize),
+                grow_state: DualNodeGrowState::Stay,
+                parent_blossom: None,
+                dual_variable_cache: (0, 0),
+                belonging: belonging.clone(),
+            })
+        };
+        let mut perfect_matching = PerfectMatching::new();
+        for (index, &(vertex_index, target)) in matching.iter().enumerate() {
+            match target {
+                MatchTarget::Peer(peer_vertex) => perfect_matching
+                    .peer_matchings
+                    .push((node(2 * index, vertex_index), node(2 * index + 1, peer_vertex))),
+                MatchTarget::VirtualVertex(virtual_vertex) => perfect_matching
+                    .virtual_matchings
+                    .push((node(2 * index, vertex_index), virtual_vertex)),
+            }
+        }
+        perfect_matching
+    }
+
+    /// solve the defects with the inner solver, reporting each pair once
+    fn solve_defects(&mut self, defect_vertices: Vec<VertexIndex>) -> Vec<(VertexIndex, MatchTarget)> {
+        if defect_vertices.is_empty() {
+            return vec![];
+        }
+        let defect_vertex = |node_ptr: &DualNodePtr| match node_ptr.read_recursive().class {
+            DualNodeClass::DefectVertex { defect_index } => defect_index,
+            DualNodeClass::Blossom { .. } => unreachable!("perfect matching only contains defect vertices"),
+        };
+        self.solver.solve(&SyndromePattern::new_vertices(defect_vertices));
+        let perfect_matching = self.solver.perfect_matching();
+        let mut matching = vec![];
+        for (node_1, node_2) in perfect_matching.peer_matchings.iter() {
+            matching.push((defect_vertex(node_1), MatchTarget::Peer(defect_vertex(node_2))));
+        }
+        for (node, virtual_vertex) in perfect_matching.virtual_matchings.iter() {
+            matching.push((defect_vertex(node), MatchTarget::VirtualVertex(*virtual_vertex)));
+        }
+        self.solver.clear();
+        matching
+    }
+}
+
+impl PrimalDualSolver for SolverWarmStart {
+    /// the components of the previous window are kept, see [`SolverWarmStart::reset_warm_start`]
+    fn clear(&mut self) {
+        self.matching.clear();
+        self.subgraph_builder.clear();
+        self.validated_dual_objective = None;
+    }
+    fn reset_profiler(&mut self) {
+        self.solver.reset_profiler();
+        self.statistics = WarmStartStatistics::default();
+    }
+    fn solve_visualizer(&mut self, syndrome_pattern: &SyndromePattern, _visualizer: Option<&mut Visualizer>) {
+        assert!(syndrome_pattern.erasures.is_empty(), "erasures change the distances");
+        assert!(
+            syndrome_pattern.dynamic_weights.is_empty(),
+            "dynamic weights change the distances"
+        );
+        let components = self.interacting_components(&syndrome_pattern.defect_vertices);
+        let mut previous_components = std::mem::take(&mut self.previous_components);
+        let mut changed = vec![];
+        for component in components.iter() {
+            if let Some(matching) = previous_components.remove(component) {
+                self.statistics.reused_components += 1;
+                self.statistics.reused_defects += component.len();
+                self.matching.extend(matching);
+            } else {
+                self.statistics.solved_components += 1;
+                self.statistics.solved_defects += component.len();
+                changed.extend(component.iter().cloned());
+            }
+        }
+        // the changed components are independent of the reused ones, so solving them together is still optimal
+        let solved = self.solve_defects(changed);
+        self.matching.extend(solved);
+        // remember the matching of every component for the next window
+        let mut component_of = BTreeMap::new();
+        for (component_index, component) in components.iter().enumerate() {
+            for &vertex_index in component.iter() {
+                component_of.insert(vertex_index, component_index);
+            }
+        }
+        let mut component_matchings = vec![vec![]; components.len()];
+        for &(vertex_index, target) in self.matching.iter() {
+            component_matchings[component_of[&vertex_index]].push((vertex_index, target));
+        }
+        self.previous_components = components.into_iter().zip(component_matchings).collect();
+        self.subgraph_builder
+            .load_perfect_matching(&self.perfect_matching_of(&self.matching));
+        if let Some(validator) = self.validator.as_mut() {
+            validator.solve(syndrome_pattern);
+            let dual_objective = validator.sum_dual_variables();
+            assert_eq!(
+                self.subgraph_builder.total_weight(),
+                dual_objective,
+                "warm start result is not a minimum-weight matching"
+            );
+            self.validated_dual_objective = Some(dual_objective);
+            validator.clear();
+        }
+    }
+    fn perfect_matching_visualizer(&mut self, visualizer: Option<&mut Visualizer>) -> PerfectMatching {
+        let perfect_matching = self.perfect_matching_of(&self.matching);
+        if let Some(visualizer) = visualizer {
+            visualizer
+                .snapshot_combined("perfect matching".to_string(), vec![&perfect_matching])
+                .unwrap();
+        }
+        perfect_matching
+    }
+    fn subgraph_visualizer(&mut self, visualizer: Option<&mut Visualizer>) -> Vec<EdgeIndex> {
+        let subgraph = self.subgraph_builder.get_subgraph();
+        if let Some(visualizer) = visualizer {
+            visualizer
+                .snapshot_combined("subgraph".to_string(), vec![&VisualizeSubgraph::new(&subgraph)])
+                .unwrap();
+        }
+        subgraph
+    }
+    fn sum_dual_variables(&self) -> Weight {
+        self.subgraph_builder.total_weight()
+    }
+    fn generate_profiler_report(&self) -> serde_json::Value {
+        json!({
+            "solver": self.solver.generate_profiler_report(),
+            "warm_start": self.statistics,
+        })
+    }
+}
+
+impl MicroBlossomSolver for SolverWarmStart {
+    /// the dual variables of the reused components are not kept, so the dual objective is only known when validating
+    fn result(&mut self) -> SolverResult {
+        let subgraph = self.subgraph();
+        SolverResult::new(subgraph, self.subgraph_builder.total_weight(), self.validated_dual_objective)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwpm_solver::*;
+    use crate::resources::*;
+    use fusion_blossom::example_codes::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn warm_start_sliding_windows() {
+        // cargo test warm_start_sliding_windows -- --nocapture
+        let mut code = CodeCapacityPlanarCode::new(11, 0.03, 500);
+        let initializer = code.get_initializer();
+        let inner = Box::new(SolverEmbeddedComb::new(MicroBlossomSingle::new_code(&code), json!({})));
+        let mut solver = SolverWarmStart::new(&initializer, inner, true);
+        // each window shares the defects of the previous shot and adds the defects of a new one
+        let mut previous = BTreeSet::new();
+        for seed in 0..100 {
+            let current: BTreeSet<VertexIndex> = code.generate_random_errors(seed).defect_vertices.into_iter().collect();
+            // an error in both shots cancels out
+            let defect_vertices: Vec<VertexIndex> = previous.symmetric_difference(&current).cloned().collect();
+            // validated against the full solve inside
+            solver.solve(&SyndromePattern::new_vertices(defect_vertices.clone()));
+            let subgraph = solver.subgraph();
+            let original_defects = defect_vertices.iter().cloned().collect();
+            assert_eq!(initializer.syndrome_of(&subgraph), original_defects);
+            solver.clear();
+            previous = current;
+        }
+        println!("{:?}", solver.statistics);
+        assert!(solver.statistics.reused_components > 0);
+        assert!(solver.statistics.solved_components > 0);
+    }
+}
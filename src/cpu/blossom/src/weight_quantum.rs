@@ -0,0 +1,222 @@
+//! Weight Quantum
+//!
+//! The weights of a decoding graph are often much finer than needed, e.g., every weight of a code with uniform
+//! error rates is a multiple of a large number. The weight quantum is the finest unit of growth: every weight is an
+//! even multiple of the quantum, so that all the dual variables stay multiples of the quantum. The hardware then only
+//! sees the weights divided by the quantum, and the growth registers are `log2(quantum)` bits narrower, see
+//! [`crate::hardware_config_check::generated_bits`]. The minimum-weight matching does not change under the scaling.
+//!
+//! [`MicroBlossomSingle::quantize_weights`] validates and divides the weights of a graph and records the quantum in
+//! the graph configuration, e.g., `micro-blossom parser --weight-quantum 50`. [`SolverQuantized`] runs any solver on
+//! the quantized graph and reports the weights in the original unit, e.g., `micro-blossom benchmark
+//! --weight-quantum 50`.
+//!
+
+use crate::mwpm_solver::*;
+use crate::resources::*;
+use fusion_blossom::mwpm_solver::*;
+use fusion_blossom::util::*;
+use fusion_blossom::visualize::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WeightQuantumError {
+    /// the quantum must be positive
+    InvalidQuantum { quantum: Weight },
+    /// a quantized weight must be an even number, so that the dual variables stay integers
+    NotMultiple {
+        edge: EdgeIndex,
+        weight: Weight,
+        quantum: Weight,
+    },
+}
+
+impl std::fmt::Display for WeightQuantumError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidQuantum { quantum } => write!(f, "weight quantum {quantum} is not positive"),
+            Self::NotMultiple { edge, weight, quantum } => {
+                write!(
+                    f,
+                    "edge {edge} has weight {weight}, which is not an even multiple of {quantum}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for WeightQuantumError {}
+
+/// check that every weight is an even multiple of the quantum
+pub fn check_weight_quantum(weights: impl IntoIterator<Item = Weight>, quantum: Weight) -> Result<(), WeightQuantumError> {
+    if quantum <= 0 {
+        return Err(WeightQuantumError::InvalidQuantum { quantum });
+    }
+    for (edge, weight) in weights.into_iter().enumerate() {
+        if weight % (2 * quantum) != 0 {
+            return Err(WeightQuantumError::NotMultiple { edge, weight, quantum });
+        }
+    }
+    Ok(())
+}
+
+/// the largest valid quantum of the weights, i.e., half of their greatest common divisor
+pub fn finest_weight_quantum(weights: impl IntoIterator<Item = Weight>) -> Weight {
+    let gcd = |mut a: Weight, mut b: Weight| {
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a
+    };
+    (weights.into_iter().fold(0, gcd) / 2).max(1)
+}
+
+/// the initializer with every weight divided by the quantum
+pub fn quantize_initializer(
+    initializer: &SolverInitializer,
+    quantum: Weight,
+) -> Result<SolverInitializer, WeightQuantumError> {
+    check_weight_quantum(initializer.weighted_edges.iter().map(|&(_, _, weight)| weight), quantum)?;
+    Ok(SolverInitializer::new(
+        initializer.vertex_num,
+        (initializer.weighted_edges.iter())
+            .map(|&(left, right, weight)| (left, right, weight / quantum))
+            .collect(),
+        initializer.virtual_vertices.clone(),
+    ))
+}
+
+impl MicroBlossomSingle {
+    /// divide every weight by the quantum, which multiplies the quantum already recorded in the graph
+    pub fn quantize_weights(&mut self, quantum: Weight) -> Result<(), WeightQuantumError> {
+        check_weight_quantum(self.weighted_edges.iter().map(|edge| edge.w), quantum)?;
+        for edge in self.weighted_edges.iter_mut() {
+            edge.w /= quantum;
+        }
+        // the maximum growth is a distance in the graph, thus also a multiple of the quantum
+        for max_growth in self.vertex_max_growth.iter_mut() {
+            *max_growth /= quantum;
+        }
+        self.weight_quantum = Some(self.weight_quantum() * quantum);
+        Ok(())
+    }
+
+    /// the unit of the weights in the graph configuration, 1 if the weights are not quantized
+    pub fn weight_quantum(&self) -> Weight {
+        self.weight_quantum.unwrap_or(1)
+    }
+}
+
+/// run any solver on the quantized graph while reporting the weights in the original unit
+pub struct SolverQuantized {
+    pub solver: Box<dyn MicroBlossomSolver>,
+    pub quantum: Weight,
+}
+
+impl SolverQuantized {
+    pub fn new(solver: Box<dyn MicroBlossomSolver>, quantum: Weight) -> Self {
+        Self { solver, quantum }
+    }
+}
+
+impl PrimalDualSolver for SolverQuantized {
+    fn clear(&mut self) {
+        self.solver.clear();
+    }
+    fn reset_profiler(&mut self) {
+        self.solver.reset_profiler();
+    }
+    fn solve_visualizer(&mut self, syndrome_pattern: &SyndromePattern, visualizer: Option<&mut Visualizer>) {
+        self.solver.solve_visualizer(syndrome_pattern, visualizer);
+    }
+    fn perfect_matching_visualizer(&mut self, visualizer: Option<&mut Visualizer>) -> PerfectMatching {
+        self.solver.perfect_matching_visualizer(visualizer)
+    }
+    fn subgraph_visualizer(&mut self, visualizer: Option<&mut Visualizer>) -> Vec<EdgeIndex> {
+        self.solver.subgraph_visualizer(visualizer)
+    }
+    fn sum_dual_variables(&self) -> Weight {
+        self.solver.sum_dual_variables() * self.quantum
+    }
+    fn generate_profiler_report(&self) -> serde_json::Value {
+        self.solver.generate_profiler_report()
+    }
+}
+
+impl MicroBlossomSolver for SolverQuantized {
+    fn result(&mut self) -> SolverResult {
+        let mut result = self.solver.result();
+        result.matching_weight *= self.quantum;
+        if let Some(dual_objective) = result.dual_objective.as_mut() {
+            *dual_objective *= self.quantum;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware_config_check::*;
+    use fusion_blossom::example_codes::*;
+    use serde_json::json;
+
+    #[test]
+    fn weight_quantum_validation() {
+        // cargo test weight_quantum_validation -- --nocapture
+        assert_eq!(finest_weight_quantum([200, 600, 1000]), 100);
+        assert_eq!(finest_weight_quantum([2, 3]), 1);
+        assert_eq!(check_weight_quantum([200, 600], 100), Ok(()));
+        assert_eq!(
+            check_weight_quantum([200, 300], 100),
+            Err(WeightQuantumError::NotMultiple {
+                edge: 1,
+                weight: 300,
+                quantum: 100
+            })
+        );
+        assert_eq!(
+            check_weight_quantum([200], 0),
+            Err(WeightQuantumError::InvalidQuantum { quantum: 0 })
+        );
+        // the quantum is recorded in the graph configuration and narrows the weight registers
+        let code = CodeCapacityPlanarCode::new(5, 0.1, 500);
+        let mut graph = MicroBlossomSingle::new_code(&code);
+        let quantum = finest_weight_quantum(graph.weighted_edges.iter().map(|edge| edge.w));
+        assert!(quantum > 1);
+        let (_, weight_bits) = generated_bits(&graph);
+        graph.quantize_weights(quantum).unwrap();
+        assert_eq!(graph.weight_quantum(), quantum);
+        assert!(generated_bits(&graph).1 < weight_bits);
+        let mut graph: MicroBlossomSingle = serde_json::from_str(&serde_json::to_string(&graph).unwrap()).unwrap();
+        assert_eq!(graph.weight_quantum, Some(quantum));
+        assert!(
+            graph.quantize_weights(2).is_err(),
+            "the quantized weights are already the finest"
+        );
+    }
+
+    /// the quantized graph gives the same correction with the same weight in the original unit
+    #[test]
+    fn weight_quantum_solve() {
+        // cargo test weight_quantum_solve -- --nocapture
+        let mut code = PhenomenologicalRotatedCode::new(5, 4, 0.03, 500);
+        let initializer = code.get_initializer();
+        let quantum = finest_weight_quantum(initializer.weighted_edges.iter().map(|&(_, _, weight)| weight));
+        println!("quantum: {quantum}");
+        let mut graph = MicroBlossomSingle::new_code(&code);
+        graph.quantize_weights(quantum).unwrap();
+        let mut solver = SolverQuantized::new(Box::new(SolverEmbeddedComb::new(graph, json!({}))), quantum);
+        let mut reference = SolverEmbeddedComb::new(MicroBlossomSingle::new_code(&code), json!({}));
+        for seed in 0..30 {
+            let syndrome_pattern = code.generate_random_errors(seed);
+            solver.solve(&syndrome_pattern);
+            reference.solve(&syndrome_pattern);
+            let (result, expected) = (solver.result(), reference.result());
+            assert_eq!(result.matching_weight, expected.matching_weight);
+            assert_eq!(result.dual_objective, expected.dual_objective);
+            assert_eq!(solver.sum_dual_variables(), reference.sum_dual_variables());
+            solver.clear();
+            reference.clear();
+        }
+    }
+}
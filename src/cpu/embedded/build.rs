@@ -65,6 +65,17 @@ fn main() {
         println!("cargo:rerun-if-changed=riscv-memory.x");
     }
 
+    if target_arch == Ok("arm".to_string()) && env::var("CARGO_FEATURE_CORTEX_M").is_ok() {
+        // cortex-m-rt looks for `memory.x` in the linker search path
+        let mut f = File::create(&dest_path.join("memory.x")).expect("Could not create file");
+
+        f.write_all(include_bytes!("cortex-m-memory.x")).expect("Could not write file");
+
+        println!("cargo:rustc-link-search={}", dest_path.display());
+
+        println!("cargo:rerun-if-changed=cortex-m-memory.x");
+    }
+
     // create empty embedded.defects if it doesn't exist
     let defects_file = Path::new("./embedded.defects");
     if !defects_file.exists() {
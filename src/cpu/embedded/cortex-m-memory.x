@@ -0,0 +1,6 @@
+/* adjust to the memory map of your MCU; the default fits most Cortex-M4F/M7F parts, e.g., STM32F4 */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08000000, LENGTH = 256K
+  RAM : ORIGIN = 0x20000000, LENGTH = 64K
+}
@@ -0,0 +1,31 @@
+//! Cortex-M Driver
+//!
+//! The bindings of a bare Cortex-M MCU without the Micro Blossom accelerator, so only the self-contained examples
+//! (e.g., `example_repetition_code`) are able to run. The output is written to the stimulus port 0 of the ITM, which
+//! can be read from the SWO pin by most debug probes (e.g., `probe-rs` or `openocd`).
+//!
+
+use core::ptr::{read_volatile, write_volatile};
+
+const ITM_STIMULUS_PORT_0: usize = 0xE0000000;
+const ITM_TRACE_ENABLE: usize = 0xE0000E00;
+const ITM_TRACE_CONTROL: usize = 0xE0000E80;
+
+#[no_mangle]
+extern "C" fn print_char(c: cty::c_char) {
+    unsafe {
+        // drop the output if the debugger has not enabled the ITM
+        let enabled = read_volatile(ITM_TRACE_CONTROL as *const u32) & 1 != 0
+            && read_volatile(ITM_TRACE_ENABLE as *const u32) & 1 != 0;
+        if !enabled {
+            return;
+        }
+        while read_volatile(ITM_STIMULUS_PORT_0 as *const u32) == 0 {} // wait for the FIFO
+        write_volatile(ITM_STIMULUS_PORT_0 as *mut u8, c as u8);
+    }
+}
+
+#[no_mangle]
+extern "C" fn set_leds(_mask: cty::uint32_t) {
+    // TODO: board specific
+}
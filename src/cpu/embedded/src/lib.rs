@@ -1,6 +1,9 @@
 #![no_std]
 
 pub mod binding;
+#[cfg(feature = "cortex_m")]
+pub mod cortex_m_driver;
+pub mod memory_dual;
 pub mod util;
 pub mod mains {
     automod::dir!(pub "src/mains");
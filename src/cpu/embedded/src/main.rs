@@ -1,9 +1,10 @@
 #![no_std]
 #![no_main]
 
-// riscv
-#[cfg(feature = "riscv")]
+#[cfg(any(feature = "riscv", feature = "cortex_m"))]
 use embedded_blossom::*;
+
+// riscv
 #[cfg(feature = "riscv")]
 use riscv_rt::entry;
 #[cfg(feature = "riscv")]
@@ -11,3 +12,12 @@ use riscv_rt::entry;
 fn main() -> ! {
     rust_main();
 }
+
+// cortex-m
+#[cfg(feature = "cortex_m")]
+use cortex_m_rt::entry;
+#[cfg(feature = "cortex_m")]
+#[entry]
+fn main() -> ! {
+    rust_main();
+}
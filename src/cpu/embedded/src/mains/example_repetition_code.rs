@@ -0,0 +1,57 @@
+use crate::binding::*;
+use crate::memory_dual::*;
+use core::cell::UnsafeCell;
+use micro_blossom_nostd::dual_driver_tracked::*;
+use micro_blossom_nostd::dual_module_stackless::*;
+use micro_blossom_nostd::interface::*;
+use micro_blossom_nostd::primal_module_embedded::*;
+
+/*
+ * a self-contained example that needs no accelerator: the embedded primal module with the in-memory dual module
+EMBEDDED_BLOSSOM_MAIN=example_repetition_code make cortex-m
+ * then flash `target/thumbv7em-none-eabihf/release/embedded_blossom` and read the output from the ITM port, e.g.,
+probe-rs run --chip <your chip> target/thumbv7em-none-eabihf/release/embedded_blossom
+*/
+
+/// a repetition code of 10 data qubits, i.e., 9 stabilizers and a virtual vertex at each end
+pub const VERTEX_NUM: usize = 11;
+/// the primal module reserves half of the node indices for blossoms
+pub const MAX_NODE_NUM: usize = 2 * VERTEX_NUM;
+pub const EDGE_WEIGHT: CompactWeight = 2;
+
+static mut PRIMAL_MODULE: UnsafeCell<PrimalModuleEmbedded<MAX_NODE_NUM>> = UnsafeCell::new(PrimalModuleEmbedded::new());
+static mut DUAL_MODULE: UnsafeCell<DualModuleMemory<VERTEX_NUM, MAX_NODE_NUM>> = UnsafeCell::new(DualModuleStackless::new(
+    DualDriverTracked::new(MemoryDualDriver::new(EDGE_WEIGHT)),
+));
+
+/// the defect vertices of a few shots
+const SHOTS: [&[usize]; 4] = [&[4, 5], &[1, 9], &[2, 3, 7], &[1, 2, 5, 6, 8]];
+
+pub fn main() {
+    println!("Example: repetition code with in-memory dual module");
+    let primal_module = unsafe { PRIMAL_MODULE.get().as_mut().unwrap() };
+    let dual_module = unsafe { DUAL_MODULE.get().as_mut().unwrap() };
+    for (shot, defects) in SHOTS.iter().enumerate() {
+        primal_module.reset();
+        dual_module.reset();
+        for (node, &vertex) in defects.iter().enumerate() {
+            dual_module.add_defect(ni!(vertex), ni!(node));
+        }
+        loop {
+            let (obstacle, _) = dual_module.find_obstacle();
+            if obstacle.is_none() {
+                break;
+            }
+            primal_module.resolve(dual_module, obstacle);
+        }
+        println!("\nshot {shot}: defects {defects:?}");
+        primal_module.iterate_perfect_matching(|_, node, target, _| match target {
+            CompactMatchTarget::Peer(peer) => {
+                println!("    {} <-> {}", defects[node.get() as usize], defects[peer.get() as usize])
+            }
+            CompactMatchTarget::VirtualVertex(vertex) => {
+                println!("    {} <-> boundary {}", defects[node.get() as usize], vertex)
+            }
+        });
+    }
+}
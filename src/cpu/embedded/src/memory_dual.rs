@@ -0,0 +1,245 @@
+//! Memory Dual
+//!
+//! A tiny dual module that runs entirely in memory, for trying out the embedded primal module on a bare MCU without
+//! any accelerator. The decoding graph is a repetition code: a chain of `V` vertices where the two ends are virtual
+//! and every edge has the same (even) weight. Each vertex follows the same logic as a vertex of the hardware (see
+//! `dual_module_comb_vertex.rs` in the `micro-blossom` crate): it grows at the speed of its node, propagates the node
+//! to an idle neighbor over a tight edge and reports a conflict when two different nodes meet. The blossoms hitting
+//! zero are handled in software by wrapping the driver in [`DualDriverTracked`].
+//!
+
+use micro_blossom_nostd::dual_driver_tracked::*;
+use micro_blossom_nostd::dual_module_stackless::*;
+use micro_blossom_nostd::interface::*;
+use micro_blossom_nostd::util::*;
+
+pub type DualModuleMemory<const V: usize, const N: usize> = DualModuleStackless<DualDriverTracked<MemoryDualDriver<V>, N>>;
+
+pub struct MemoryDualDriver<const V: usize> {
+    /// the weight of every edge
+    pub weight: CompactWeight,
+    grown: [CompactWeight; V],
+    speed: [CompactGrowState; V],
+    /// the outer node of the vertex
+    node: [OptionCompactNodeIndex; V],
+    /// the defect node that the vertex is propagated from
+    root: [OptionCompactNodeIndex; V],
+    is_defect: [bool; V],
+}
+
+/// the node as seen by the edges, i.e., a shrinking vertex at zero growth is taken over by a propagating peer
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct ShadowNode {
+    node: OptionCompactNodeIndex,
+    root: OptionCompactNodeIndex,
+    speed: CompactGrowState,
+}
+
+fn signed_speed(speed: CompactGrowState) -> CompactWeight {
+    match speed {
+        CompactGrowState::Grow => 1,
+        CompactGrowState::Stay => 0,
+        CompactGrowState::Shrink => -1,
+    }
+}
+
+impl<const V: usize> MemoryDualDriver<V> {
+    pub const fn new(weight: CompactWeight) -> Self {
+        Self {
+            weight,
+            grown: [0; V],
+            speed: [CompactGrowState::Stay; V],
+            node: [OptionCompactNodeIndex::NONE; V],
+            root: [OptionCompactNodeIndex::NONE; V],
+            is_defect: [false; V],
+        }
+    }
+
+    pub fn is_virtual(&self, vertex: usize) -> bool {
+        vertex == 0 || vertex == V - 1
+    }
+
+    /// the neighbors of the vertex on the chain
+    fn neighbors(vertex: usize) -> impl Iterator<Item = usize> {
+        vertex.checked_sub(1).into_iter().chain((vertex + 1 < V).then(|| vertex + 1))
+    }
+
+    fn is_propagating(&self, vertex: usize) -> bool {
+        !self.is_virtual(vertex) && self.node[vertex].is_some() && self.speed[vertex] == CompactGrowState::Grow
+    }
+
+    /// a growing neighbor that fully covers the edge to this vertex
+    fn propagating_peer(&self, vertex: usize) -> Option<usize> {
+        if self.grown[vertex] != 0 {
+            return None;
+        }
+        Self::neighbors(vertex).find(|&peer| self.is_propagating(peer) && self.grown[peer] >= self.weight)
+    }
+
+    /// update the idle vertices; since the weight is positive, a vertex updated here never propagates further in the
+    /// same pass, so the in-place update is equivalent to the simultaneous update of the hardware
+    fn propagate(&mut self) {
+        for vertex in 0..V {
+            if self.is_defect[vertex] || self.is_virtual(vertex) || self.grown[vertex] != 0 {
+                continue;
+            }
+            match self.propagating_peer(vertex) {
+                Some(peer) => {
+                    self.node[vertex] = self.node[peer];
+                    self.root[vertex] = self.root[peer];
+                    self.speed[vertex] = CompactGrowState::Grow;
+                }
+                None => {
+                    self.node[vertex] = None.into();
+                    self.root[vertex] = None.into();
+                    self.speed[vertex] = CompactGrowState::Stay;
+                }
+            }
+        }
+    }
+
+    fn shadow(&self, vertex: usize) -> ShadowNode {
+        if self.is_virtual(vertex) {
+            return ShadowNode {
+                node: None.into(),
+                root: None.into(),
+                speed: CompactGrowState::Stay,
+            };
+        }
+        if self.speed[vertex] == CompactGrowState::Shrink && self.grown[vertex] == 0 {
+            if let Some(peer) = self.propagating_peer(vertex) {
+                return ShadowNode {
+                    node: self.node[peer],
+                    root: self.root[peer],
+                    speed: CompactGrowState::Grow,
+                };
+            }
+        }
+        ShadowNode {
+            node: self.node[vertex],
+            root: self.root[vertex],
+            speed: self.speed[vertex],
+        }
+    }
+
+    /// the response of the edge between `left` and `left + 1`
+    fn edge_response(&self, left: usize) -> CompactObstacle {
+        let right = left + 1;
+        let (left_shadow, right_shadow) = (self.shadow(left), self.shadow(right));
+        let joint_speed = signed_speed(left_shadow.speed) + signed_speed(right_shadow.speed);
+        if left_shadow.node == right_shadow.node || joint_speed <= 0 {
+            return CompactObstacle::GrowLength {
+                length: CompactWeight::MAX,
+            };
+        }
+        let remaining = self.weight - self.grown[left] - self.grown[right];
+        let is_available = |vertex: usize, shadow: &ShadowNode| shadow.node.is_none() && !self.is_virtual(vertex);
+        if remaining == 0 && !is_available(left, &left_shadow) && !is_available(right, &right_shadow) {
+            // the primal module expects the first node to be a regular node
+            let ((vertex_1, shadow_1), (vertex_2, shadow_2)) = if left_shadow.node.is_some() {
+                ((left, left_shadow), (right, right_shadow))
+            } else {
+                ((right, right_shadow), (left, left_shadow))
+            };
+            return CompactObstacle::Conflict {
+                node_1: shadow_1.node,
+                touch_1: shadow_1.root,
+                vertex_1: ni!(vertex_1),
+                node_2: shadow_2.node,
+                touch_2: shadow_2.root,
+                vertex_2: ni!(vertex_2),
+            };
+        }
+        CompactObstacle::GrowLength {
+            length: remaining / joint_speed,
+        }
+    }
+
+    /// find a conflict or the maximum length to grow, after propagating the nodes
+    fn response(&mut self) -> CompactObstacle {
+        self.propagate();
+        let mut length = CompactWeight::MAX;
+        for left in 0..V - 1 {
+            match self.edge_response(left) {
+                CompactObstacle::GrowLength { length: edge_length } => length = length.min(edge_length),
+                conflict => return conflict,
+            }
+        }
+        for vertex in 0..V {
+            if self.speed[vertex] == CompactGrowState::Shrink {
+                length = length.min(self.grown[vertex]);
+            }
+        }
+        CompactObstacle::GrowLength { length }
+    }
+
+    fn grow(&mut self, length: CompactWeight) {
+        for vertex in 0..V {
+            if !self.is_virtual(vertex) {
+                self.grown[vertex] += signed_speed(self.speed[vertex]) * length;
+            }
+        }
+    }
+}
+
+impl<const V: usize> DualStacklessDriver for MemoryDualDriver<V> {
+    fn reset(&mut self) {
+        self.grown = [0; V];
+        self.speed = [CompactGrowState::Stay; V];
+        self.node = [OptionCompactNodeIndex::NONE; V];
+        self.root = [OptionCompactNodeIndex::NONE; V];
+        self.is_defect = [false; V];
+    }
+    fn set_speed(&mut self, _is_blossom: bool, node: CompactNodeIndex, speed: CompactGrowState) {
+        for vertex in 0..V {
+            if self.node[vertex] == node.option() {
+                self.speed[vertex] = speed;
+            }
+        }
+    }
+    fn set_blossom(&mut self, node: CompactNodeIndex, blossom: CompactNodeIndex) {
+        for vertex in 0..V {
+            if self.node[vertex] == node.option() || self.root[vertex] == node.option() {
+                self.node[vertex] = blossom.option();
+                self.speed[vertex] = CompactGrowState::Grow;
+            }
+        }
+    }
+    fn find_obstacle(&mut self) -> (CompactObstacle, CompactWeight) {
+        self.find_conflict(CompactWeight::MAX)
+    }
+    fn add_defect(&mut self, vertex: CompactVertexIndex, node: CompactNodeIndex) {
+        let vertex = vertex.get() as usize;
+        debug_assert!(!self.is_virtual(vertex), "virtual vertex {} cannot be a defect", vertex);
+        self.is_defect[vertex] = true;
+        self.node[vertex] = node.option();
+        self.root[vertex] = node.option();
+        self.speed[vertex] = CompactGrowState::Grow;
+    }
+}
+
+impl<const V: usize> DualTrackedDriver for MemoryDualDriver<V> {
+    /// grow until a conflict, or until reaching the maximum growth like the primal offloading unit of the hardware
+    fn find_conflict(&mut self, maximum_growth: CompactWeight) -> (CompactObstacle, CompactWeight) {
+        debug_assert!(
+            self.weight > 0 && self.weight % 2 == 0,
+            "the weight must be positive and even"
+        );
+        let mut grown = 0;
+        loop {
+            let length = match self.response() {
+                CompactObstacle::GrowLength { length } => length,
+                conflict => return (conflict, grown),
+            };
+            if length == CompactWeight::MAX {
+                return (CompactObstacle::None, grown);
+            }
+            let length = length.min(maximum_growth - grown);
+            if length == 0 {
+                return (CompactObstacle::GrowLength { length: 0 }, grown);
+            }
+            self.grow(length);
+            grown += length;
+        }
+    }
+}
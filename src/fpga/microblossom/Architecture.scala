@@ -19,9 +19,13 @@ import spinal.core._
  * |                                         0                                   | 3'b100 | 3'b100 | Reset
  * |                  Time[14:0]                |           Channel[10:0]        | 3'b101 | 3'b100 | LoadDefectsExternal/LayerFusion
  * |                                      Length[25:0]                           | 3'b110 | 3'b100 | Grow
- * |                 Vertex[14:0]               | v|e | t|e |                    | 3'b111 | 3'b100 | SetAttribute(debug)
+ * |                Vertex[14:0]                |                             | 1| 3'b111 | 3'b100 | ReadVertexGrown
+ * |               BaseNode[14:0]               | 0|     BaseVertex[14:6]     | 0| 3'b111 | 3'b100 | LoadDefectsBitmap(header)
  * -------------------------------------------------------------------------------------------------
  *
+ * LoadDefectsBitmap is followed by two data words with the low and high 32 bits of the bitmap of the vertices
+ * [BaseVertex, BaseVertex + 64), whose defects take consecutive node indices from BaseNode; the host expands it into
+ * AddDefectVertex instructions (only the looper host for now)
  *
  * The return value is also 32-bits wide, but some messages are splitted into two
  * -------------------------------------------------------------------------------------------------
@@ -75,7 +79,7 @@ object ExtendedOpCode {
   def Reset = Integer.parseInt("100", 2)
   def LoadDefectsExternal = Integer.parseInt("101", 2)
   def Grow = Integer.parseInt("110", 2)
-  def Reserved2 = Integer.parseInt("111", 2)
+  def ReadDual = Integer.parseInt("111", 2)
 }
 
 case class Speed() extends Bits {
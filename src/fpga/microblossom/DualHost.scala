@@ -161,6 +161,17 @@ object DualHost extends App {
               val vertex = parameters(0).toInt
               val node = parameters(1).toInt
               dut.simExecute(ioConfig.instructionSpec.generateAddDefect(vertex, node))
+            } else if (command.startsWith("read_vertex_grown(")) {
+              val parameters = command.substring("read_vertex_grown(".length, command.length - 1).split(", ")
+              assert(parameters.length == 1)
+              val vertex = parameters(0).toInt
+              val (maxGrowable, _) = dut.simExecute(ioConfig.instructionSpec.generateReadVertexGrown(vertex))
+              outStream.println(
+                "Grown(%d)".format(
+                  if (maxGrowable.length == ioConfig.LengthNone) { Int.MaxValue }
+                  else { maxGrowable.length }
+                )
+              )
             } else if (command.startsWith("snapshot(")) {
               val parameters = command.substring("snapshot(".length, command.length - 1).split(", ")
               assert(parameters.length == 1)
@@ -36,9 +36,21 @@ case class Instruction(config: DualConfig = DualConfig()) extends Bits {
           field1 := source.field1.resized
           speed := source.speed
           setSpeedZero.clearAll()
+          if (spec.hasSpeedMagnitude) {
+            if (source.spec.hasSpeedMagnitude) {
+              speedMagnitude := source.speedMagnitude
+            } else {
+              speedMagnitude := B(1, speedMagnitude.getWidth bits)
+            }
+          }
           when(source.field1.asUInt > field1.asUInt.maxValue) {
             hasError := True
           }
+          if (source.spec.hasSpeedMagnitude && !spec.hasSpeedMagnitude) {
+            when(source.speedMagnitude.asUInt > 1) {
+              hasError := True
+            }
+          }
         } otherwise {
           extendedOpCode := source.extendedOpCode
           extensionIndicator := source.extensionIndicator
@@ -77,7 +89,9 @@ case class Instruction(config: DualConfig = DualConfig()) extends Bits {
   def extendedPayload = sliceOf(spec.extendedPayloadRange)
   def extendedField2 = sliceOf(spec.extendedField2Range)
   def speed = sliceOf(spec.speedRange)
+  def speedMagnitude = sliceOf(spec.speedMagnitudeRange)
   def setSpeedZero = sliceOf(spec.setSpeedZeroRange)
+  def readDualVertexFlag = sliceOf(spec.readDualVertexFlagRange)
 
   def sliceOf(range: BitRange): Bits = {
     this(range.msb downto range.lsb)
@@ -91,6 +105,8 @@ case class Instruction(config: DualConfig = DualConfig()) extends Bits {
   def isFindObstacle(): Bool = isExtended && (extendedOpCode === ExtendedOpCode.FindObstacle)
   def isReset(): Bool = isExtended && (extendedOpCode === ExtendedOpCode.Reset)
   def isLoadDefectsExternal(): Bool = isExtended && (extendedOpCode === ExtendedOpCode.LoadDefectsExternal)
+  def isReadDual(): Bool = isExtended && (extendedOpCode === ExtendedOpCode.ReadDual)
+  def isReadVertex(): Bool = isReadDual && readDualVertexFlag.asBool
 
   def isChangingSyndrome(): Bool = isAddDefect || isReset || isLoadDefectsExternal
 
@@ -151,11 +167,33 @@ case class InstructionSpec(config: DualConfig) {
   def extendedField2Range = BitRange(numBits - config.vertexBits - 1, 6)
   def speedRange =
     BitRange(numBits - config.vertexBits - 1, numBits - config.vertexBits - 2)
-  def setSpeedZeroRange = BitRange(numBits - config.vertexBits - 3, 2)
+  // the speed magnitude of SetSpeed follows the speed, i.e., bits [12, 15) of the 32 bit instruction; 0 is decoded
+  // as 1 for compatibility. A narrow instruction without enough bits only supports unit speed
+  def hasSpeedMagnitude = numBits - config.vertexBits - 5 >= 3
+  def speedMagnitudeRange = BitRange(numBits - config.vertexBits - 3, numBits - config.vertexBits - 5)
+  // ReadDual reads back the grown value of the vertex in field1 when this flag is set; the dual variables of the nodes
+  // are not stored in the hardware and are derived by the host from the vertices, so the flag must always be set
+  def readDualVertexFlagRange = BitRange(6, 6)
+  // LoadDefectsBitmap shares the extended op code of ReadDual with the vertex flag cleared, only in the 32 bit client
+  // format: the header carries the base node in field1 and the base vertex divided by 64 in bits [15, 7], followed by
+  // two data words with the low and high 32 bits of the bitmap; the host expands it into AddDefectVertex instructions
+  def bitmapBlockRange = BitRange(15, 7)
+  def setSpeedZeroRange = if (hasSpeedMagnitude) {
+    BitRange(numBits - config.vertexBits - 6, 2)
+  } else {
+    BitRange(numBits - config.vertexBits - 3, 2)
+  }
 
   def generateSetSpeed(node: Long, speed: Long): Long = {
     opCodeRange.masked(OpCode.SetSpeed) | field1Range.masked(node) | speedRange.masked(speed)
   }
+  def generateSetSpeedWithMagnitude(node: Long, speed: Long, magnitude: Long): Long = {
+    if (magnitude <= 1 && !hasSpeedMagnitude) {
+      return generateSetSpeed(node, speed)
+    }
+    assert(hasSpeedMagnitude, "the instruction is too narrow for the speed magnitude")
+    generateSetSpeed(node, speed) | speedMagnitudeRange.masked(magnitude)
+  }
   def generateSetBlossom(node: Long, blossom: Long): Long = {
     opCodeRange.masked(OpCode.SetBlossom) | field1Range.masked(node) | field2Range.masked(blossom)
   }
@@ -177,6 +215,14 @@ case class InstructionSpec(config: DualConfig) {
   def generateLoadDefectsExternal(time: Long): Long = {
     generateExtendedSuffix(ExtendedOpCode.LoadDefectsExternal) | field1Range.masked(time)
   }
+  def generateReadVertexGrown(vertex: Long): Long = {
+    generateExtendedSuffix(ExtendedOpCode.ReadDual) | readDualVertexFlagRange.masked(1) | field1Range.masked(vertex)
+  }
+  def generateLoadDefectsBitmapHeader(baseVertex: Long, baseNode: Long): Long = {
+    assert(baseVertex % 64 == 0, "the base vertex of a bitmap must be aligned to 64")
+    val header = generateExtendedSuffix(ExtendedOpCode.ReadDual) | bitmapBlockRange.masked(baseVertex / 64)
+    header | field1Range.masked(baseNode)
+  }
 
   def sanityCheck() = {
     assert(config.weightBits + 2 <= numBits)
@@ -193,6 +239,13 @@ case class InstructionSpec(config: DualConfig) {
   def extendedPayload(value: Long) = extendedPayloadRange.of(value)
   def extendedField2(value: Long) = extendedField2Range.of(value)
   def speed(value: Long) = speedRange.of(value)
+  def speedMagnitude(value: Long): Long = {
+    if (!hasSpeedMagnitude) {
+      return 1
+    }
+    val magnitude = speedMagnitudeRange.of(value)
+    if (magnitude == 0) 1 else magnitude
+  }
   def setSpeedZero(value: Long) = setSpeedZeroRange.of(value)
 
   def isSetSpeed(value: Long) = (opCode(value) == OpCode.SetSpeed) && !extensionIndicator(value)
@@ -204,6 +257,10 @@ case class InstructionSpec(config: DualConfig) {
   def isReset(value: Long) = isExtended(value) && (extendedOpCode(value) == ExtendedOpCode.Reset)
   def isLoadDefectsExternal(value: Long) =
     isExtended(value) && (extendedOpCode(value) == ExtendedOpCode.LoadDefectsExternal)
+  def isReadDual(value: Long) = isExtended(value) && (extendedOpCode(value) == ExtendedOpCode.ReadDual)
+  def isReadVertex(value: Long) = isReadDual(value) && (readDualVertexFlagRange.of(value) != 0)
+  def isLoadDefectsBitmap(value: Long) = isReadDual(value) && (readDualVertexFlagRange.of(value) == 0)
+  def bitmapBaseVertex(value: Long) = bitmapBlockRange.of(value) * 64
 
   def isValid(value: Long): Boolean = {
     value < (1L << numBits)
@@ -216,6 +273,9 @@ case class InstructionSpec(config: DualConfig) {
   def format(value: Long): String = {
     assert(isValid(value))
     if (isSetSpeed(value)) {
+      if (speedMagnitude(value) != 1) {
+        return s"SetSpeed(node=${field1(value)}, speed=${Speed.format(speed(value))}, magnitude=${speedMagnitude(value)})"
+      }
       return s"SetSpeed(node=${field1(value)}, speed=${Speed.format(speed(value))})"
     } else if (isSetBlossom(value)) {
       return s"SetBlossom(node=${field1(value)}, blossom=${field2(value)})"
@@ -229,6 +289,10 @@ case class InstructionSpec(config: DualConfig) {
       return s"Reset()"
     } else if (isLoadDefectsExternal(value)) {
       return s"LoadDefectsExternal(time=${field1(value)})"
+    } else if (isReadVertex(value)) {
+      return s"ReadVertexGrown(vertex=${field1(value)})"
+    } else if (isLoadDefectsBitmap(value)) {
+      return s"LoadDefectsBitmap(base_vertex=${bitmapBaseVertex(value)}, base_node=${field1(value)})"
     } else {
       return s"Unknown(value=${value}=0b${binaryOf(value)})"
     }
@@ -242,9 +306,10 @@ case class InstructionSpec(config: DualConfig) {
   def toSpec(value: Long, spec: InstructionSpec): Long = {
     assert(isValid(value))
     if (isSetSpeed(value)) {
-      val result = spec.generateSetSpeed(field1(value), speed(value))
+      val result = spec.generateSetSpeedWithMagnitude(field1(value), speed(value), speedMagnitude(value))
       assert(spec.field1(result) == field1(value))
       assert(spec.speed(result) == speed(value))
+      assert(spec.speedMagnitude(result) == speedMagnitude(value))
       return result
     } else if (isSetBlossom(value)) {
       val result = spec.generateSetBlossom(field1(value), field2(value))
@@ -268,6 +333,10 @@ case class InstructionSpec(config: DualConfig) {
       val result = spec.generateLoadDefectsExternal(field1(value))
       assert(spec.field1(result) == field1(value))
       return result
+    } else if (isReadVertex(value)) {
+      val result = spec.generateReadVertexGrown(field1(value))
+      assert(spec.field1(result) == field1(value))
+      return result
     } else {
       throw new Exception(s"Unknown(value=${value}=0b${binaryOf(value)})")
     }
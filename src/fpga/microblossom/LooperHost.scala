@@ -46,6 +46,26 @@ object LooperHost extends SimulationTcpHost("LooperHost") {
         dut.clockDomain.forkStimulus(period = 10)
         for (idx <- 0 to 10) { dut.clockDomain.waitSampling() }
 
+        def execute(inputData: LooperInputData): LooperOutputData = {
+          // adapt instruction width
+          // println(clientSpec.format(inputData.instruction))
+          val instruction = config.instructionSpec.from(inputData.instruction, clientSpec)
+          val adaptedInput = inputData.copy(instruction = instruction)
+          val outputData = dut.simExecute(adaptedInput)
+          // adapt output
+          val adaptedOutput = outputData.copy()
+          if (outputData.maxGrowable == config.LengthNone) { adaptedOutput.maxGrowable = 65535 }
+          // sanity checks
+          if (config.contextBits > 0) {
+            assert(adaptedOutput.contextId == inputData.contextId)
+          }
+          adaptedOutput
+        }
+
+        // the words of a pending LoadDefectsBitmap: the header followed by the low and high 32 bits of the bitmap
+        var bitmapWords = List[LooperInputData]()
+        var lastOutput: Option[LooperOutputData] = None
+
         // start hosting the commands
         breakable {
           while (true) {
@@ -60,20 +80,35 @@ object LooperHost extends SimulationTcpHost("LooperHost") {
                 case Right(inputData) => inputData
                 case Left(ex)         => throw ex
               }
-              // adapt instruction width
-              // println(clientSpec.format(inputData.instruction))
-              val instruction = config.instructionSpec.from(inputData.instruction, clientSpec)
-              val adaptedInput = inputData.copy(instruction = instruction)
-              val outputData = dut.simExecute(adaptedInput)
-              // adapt output
-              val adaptedOutput = outputData.copy()
-              if (outputData.maxGrowable == config.LengthNone) { adaptedOutput.maxGrowable = 65535 }
-              // sanity checks
-              if (config.contextBits > 0) {
-                assert(adaptedOutput.contextId == inputData.contextId)
+              if (bitmapWords.nonEmpty || clientSpec.isLoadDefectsBitmap(inputData.instruction)) {
+                bitmapWords = bitmapWords :+ inputData
+                if (bitmapWords.length == 3) {
+                  // expand the bitmap into AddDefectVertex instructions with consecutive node indices
+                  val header = bitmapWords(0).instruction
+                  val bitmap = (bitmapWords(2).instruction << 32) | bitmapWords(1).instruction
+                  var node = clientSpec.field1(header)
+                  for (offset <- 0 until 64) {
+                    if (((bitmap >> offset) & 1) == 1) {
+                      val vertex = clientSpec.bitmapBaseVertex(header) + offset
+                      val addDefect = clientSpec.generateAddDefect(vertex, node)
+                      lastOutput = Some(execute(inputData.copy(instruction = addDefect)))
+                      node += 1
+                    }
+                  }
+                  bitmapWords = List()
+                }
+                // the header and the data words acknowledge the last readout, or the readout of a FindObstacle
+                // without growth if nothing has been executed yet
+                val findObstacle = inputData.copy(instruction = clientSpec.generateFindObstacle())
+                val output = lastOutput.getOrElse(execute(findObstacle))
+                lastOutput = Some(output)
+                outStream.println(output.asJson.noSpacesSortKeys)
+              } else {
+                val adaptedOutput = execute(inputData)
+                lastOutput = Some(adaptedOutput)
+                // println(adaptedOutput)
+                outStream.println(adaptedOutput.asJson.noSpacesSortKeys)
               }
-              // println(adaptedOutput)
-              outStream.println(adaptedOutput.asJson.noSpacesSortKeys)
             } else if (command.startsWith("snapshot(")) {
               val parameters = command.substring("snapshot(".length, command.length - 1).split(", ")
               assert(parameters.length == 1)
@@ -28,6 +28,14 @@ object VertexPostExecuteStateCommon {
         when(before.node === instruction.field1) {
           after.speed := instruction.speed
         }
+        // the vertex grows by the length of the instruction, only unit speed is supported for now
+        if (instruction.spec.hasSpeedMagnitude) {
+          assert(
+            assertion = instruction.speedMagnitude.asUInt <= 1,
+            message = "Speed magnitude larger than 1 is not supported by the hardware",
+            severity = ERROR
+          )
+        }
       }
       when(instruction.isSetBlossom) {
         when(before.node === instruction.field1 || before.root === instruction.field1) {
@@ -87,16 +87,35 @@ case class DistributedDual(config: DualConfig, ioConfig: DualConfig) extends Com
     offloader.io.edgeInputOffloadGet3 := edges(edgeIndex).io.stageOutputs.offloadGet3
   }
 
+  // the ReadDual instruction reads back the grown value of a single vertex through the maxGrowable tree: the selected
+  // vertex reports its grown value and all the other leaves report infinity; a grown value too large for the weight
+  // bits is also reported as infinity so that the host never sees a truncated value
+  val responseMessage = Delay(broadcastRegInserted, config.executeLatency)
+  val isReadVertex = responseMessage.valid && responseMessage.instruction.isReadVertex
+  val readVertexIndex = responseMessage.instruction.field1.asUInt
+  val outDelay = (config.contextDepth != 1).toInt
+
   // build convergecast tree for maxGrowable
   val maxGrowableConvergcastTree =
     Vec.fill(config.graph.vertex_edge_binary_tree.nodes.length)(ConvergecastMaxGrowable(config.weightBits))
   for ((treeNode, index) <- config.graph.vertex_edge_binary_tree.nodes.zipWithIndex) {
     if (index < config.vertexNum) {
       val vertexIndex = index
-      maxGrowableConvergcastTree(index) := vertices(vertexIndex).io.maxGrowable
+      val leaf = maxGrowableConvergcastTree(index)
+      val grown = Delay(vertices(vertexIndex).io.stageOutputs.updateGet3.state.grown, outDelay)
+      leaf := vertices(vertexIndex).io.maxGrowable
+      when(isReadVertex) {
+        leaf.length := leaf.length.maxValue
+        when(readVertexIndex === vertexIndex && grown < leaf.length.maxValue) {
+          leaf.length := grown.resized
+        }
+      }
     } else if (index < config.vertexNum + config.edgeNum) {
       val edgeIndex = index - config.vertexNum
       maxGrowableConvergcastTree(index) := edges(edgeIndex).io.maxGrowable
+      when(isReadVertex) {
+        maxGrowableConvergcastTree(index).length := maxGrowableConvergcastTree(index).length.maxValue
+      }
     } else {
       val left = maxGrowableConvergcastTree(treeNode.l.get.toInt)
       val right = maxGrowableConvergcastTree(treeNode.r.get.toInt)
@@ -122,7 +141,8 @@ case class DistributedDual(config: DualConfig, ioConfig: DualConfig) extends Com
     } else {
       val left = conflictConvergecastTree(treeNode.l.get.toInt)
       val right = conflictConvergecastTree(treeNode.r.get.toInt)
-      when(left.valid) {
+      // follow the arbitration order of the CPU reference instead of the position in the tree
+      when(left.valid && (!right.valid || left.arbitrationKey <= right.arbitrationKey)) {
         conflictConvergecastTree(index) := left
       } otherwise {
         conflictConvergecastTree(index) := right
@@ -56,6 +56,17 @@ case class ConvergecastConflict(vertexBits: Int) extends Bundle {
     this
   }
 
+  // the arbitration order of `CompactObstacle::reduce` in the CPU driver (smaller wins): a conflict between two nodes
+  // wins over a conflict with a virtual vertex, and the ties are broken by the smaller and then the larger vertex
+  def arbitrationKey: UInt = {
+    val IndexNone = (1 << vertexBits) - 1
+    val isVirtual = node1 === IndexNone || node2 === IndexNone
+    val isOrdered = vertex1.asUInt < vertex2.asUInt
+    val low = Mux(isOrdered, vertex1.asUInt, vertex2.asUInt)
+    val high = Mux(isOrdered, vertex2.asUInt, vertex1.asUInt)
+    isVirtual.asUInt @@ low @@ high
+  }
+
   def assignReordered(source: ConvergecastConflict) = {
     val IndexNone = (1 << vertexBits) - 1
     valid := source.valid